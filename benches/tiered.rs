@@ -0,0 +1,152 @@
+//! Measures the overhead of tiered compilation compared to compiling modules
+//! eagerly with Cranelift.
+
+use criterion::*;
+use wasmtime::*;
+
+criterion_main!(benches);
+criterion_group!(benches, bench_tiered);
+
+/// The number of functions in the benchmarked module.
+const FUNCS: usize = 100;
+
+fn bench_tiered(c: &mut Criterion) {
+    let Some(tiered) = engine(Strategy::Tiered, 1000) else {
+        eprintln!("skipping tiered compilation benchmarks: not supported on this host");
+        return;
+    };
+    let engines = [
+        ("cranelift", engine(Strategy::Cranelift, 1000).unwrap()),
+        ("tiered", tiered),
+    ];
+    let wasm = module_wasm();
+
+    let mut group = c.benchmark_group("tiered-compile-module");
+    for (name, engine) in &engines {
+        group.bench_function(*name, |b| {
+            b.iter(|| Module::new(engine, &wasm).unwrap());
+        });
+    }
+    group.finish();
+
+    // The first call of each function, which compiles it with tiered
+    // compilation.
+    let mut group = c.benchmark_group("tiered-first-calls");
+    group.throughput(Throughput::Elements(FUNCS as u64));
+    for (name, engine) in &engines {
+        group.bench_function(*name, |b| {
+            b.iter_batched(
+                || Module::new(engine, &wasm).unwrap(),
+                |module| {
+                    let mut store = Store::new(engine, ());
+                    let instance = Instance::new(&mut store, &module, &[]).unwrap();
+                    let run = instance
+                        .get_typed_func::<(), i32>(&mut store, "run")
+                        .unwrap();
+                    run.call(&mut store, ()).unwrap();
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+
+    // Recompiling all functions with Cranelift, which happens together since
+    // they're called equally often.
+    let mut group = c.benchmark_group("tiered-tier-up");
+    group.throughput(Throughput::Elements(FUNCS as u64));
+    let tier_up = engine(Strategy::Tiered, 2).unwrap();
+    group.bench_function("tiered", |b| {
+        b.iter_batched(
+            || Module::new(&tier_up, &wasm).unwrap(),
+            |module| {
+                let mut store = Store::new(&tier_up, ());
+                let instance = Instance::new(&mut store, &module, &[]).unwrap();
+                let run = instance
+                    .get_typed_func::<(), i32>(&mut store, "run")
+                    .unwrap();
+                for _ in 0..3 {
+                    run.call(&mut store, ()).unwrap();
+                }
+            },
+            BatchSize::PerIteration,
+        );
+    });
+    group.finish();
+
+    // Calls to functions after they've been recompiled, both directly and
+    // through another instance's import of the function which was made before
+    // the function was compiled.
+    let mut group = c.benchmark_group("tiered-steady-state-calls");
+    let engines = [
+        ("cranelift", engine(Strategy::Cranelift, 1000).unwrap()),
+        ("tiered", tier_up),
+    ];
+    for (name, engine) in &engines {
+        let mut store = Store::new(engine, ());
+        let module = Module::new(engine, &wasm).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let f0 = instance.get_func(&mut store, "f0").unwrap();
+        let importer = Module::new(
+            engine,
+            r#"
+                (module
+                    (import "" "f0" (func $f0 (result i32)))
+                    (func (export "run") (result i32)
+                        (local $i i32) (local $sum i32)
+                        (loop $l
+                            (local.set $sum (i32.add (local.get $sum) (call $f0)))
+                            (br_if $l (i32.ne
+                                (local.tee $i (i32.add (local.get $i) (i32.const 1)))
+                                (i32.const 1000))))
+                        (local.get $sum))
+                )
+            "#,
+        )
+        .unwrap();
+        let importer = Instance::new(&mut store, &importer, &[f0.into()]).unwrap();
+        let run = instance
+            .get_typed_func::<(), i32>(&mut store, "run")
+            .unwrap();
+        let run_import = importer
+            .get_typed_func::<(), i32>(&mut store, "run")
+            .unwrap();
+        for _ in 0..3 {
+            run.call(&mut store, ()).unwrap();
+            run_import.call(&mut store, ()).unwrap();
+        }
+
+        group.bench_function(BenchmarkId::new("call-each", name), |b| {
+            b.iter(|| run.call(&mut store, ()).unwrap());
+        });
+        group.bench_function(BenchmarkId::new("import-loop", name), |b| {
+            b.iter(|| run_import.call(&mut store, ()).unwrap());
+        });
+    }
+    group.finish();
+}
+
+fn engine(strategy: Strategy, tier_up_threshold: u32) -> Option<Engine> {
+    let mut config = Config::new();
+    config.strategy(strategy);
+    config.tier_up_threshold(tier_up_threshold);
+    Engine::new(&config).ok()
+}
+
+/// Returns a module with `FUNCS` small functions and a `run` function which
+/// calls each of them once.
+fn module_wasm() -> String {
+    let mut wat = String::from("(module\n");
+    for i in 0..FUNCS {
+        wat.push_str(&format!(
+            "(func $f{i} (export \"f{i}\") (result i32)
+                (i32.mul (i32.add (i32.const {i}) (i32.const 1)) (i32.const 3)))\n"
+        ));
+    }
+    wat.push_str("(func (export \"run\") (result i32) (i32.const 0)\n");
+    for i in 0..FUNCS {
+        wat.push_str(&format!("(i32.add (call $f{i}))\n"));
+    }
+    wat.push_str("))");
+    wat
+}
@@ -65,6 +65,21 @@ wasmtime_option_group! {
         /// Bytes to reserve at the end of linear memory for growth into.
         pub memory_reservation_for_growth: Option<u64>,
 
+        /// Align linear memories to, and back them with, transparent huge
+        /// pages on Linux.
+        pub memory_huge_pages: Option<bool>,
+
+        /// Enable memory protection keys for linear memories when the pooling
+        /// allocator is not used; this can shrink their guard regions.
+        #[serde(default)]
+        #[serde(deserialize_with = "crate::opt::cli_parse_wrapper")]
+        pub memory_protection_keys: Option<wasmtime::MpkEnabled>,
+
+        /// The maximum size, in bytes, that linear memories tagged with memory
+        /// protection keys may grow to when the pooling allocator is not used.
+        /// (default: 1GiB)
+        pub max_protected_memory_size: Option<usize>,
+
         /// Size, in bytes, of guard pages for linear memories.
         pub memory_guard_size: Option<u64>,
 
@@ -135,6 +150,14 @@ wasmtime_option_group! {
         /// allocator, in bytes.
         pub pooling_max_memory_size: Option<usize>,
 
+        /// The number of slots in the pooling allocator dedicated to 64-bit
+        /// linear memories. (default: 0, shared with 32-bit memories)
+        pub pooling_total_memory64s: Option<u32>,
+
+        /// The maximum runtime size of each 64-bit linear memory in the
+        /// pooling allocator's dedicated slots, in bytes.
+        pub pooling_max_memory64_size: Option<usize>,
+
         /// The maximum table elements for any table defined in a module when
         /// using the pooling allocator.
         pub pooling_table_elements: Option<usize>,
@@ -147,6 +170,15 @@ wasmtime_option_group! {
         /// pooling allocator. (default: 100)
         pub pooling_max_unused_warm_slots: Option<u32>,
 
+        /// Reserve the pooling allocator's address space in chunks of this
+        /// many slots, on demand, instead of all up front. (default: 0,
+        /// disabled)
+        pub pooling_elastic_chunk_slots: Option<u32>,
+
+        /// Initialize linear memories in the pooling allocator lazily with a
+        /// userfaultfd, on Linux. (default: false)
+        pub pooling_memory_init_uffd: Option<bool>,
+
         /// How much memory, in bytes, to keep resident for async stacks allocated
         /// with the pooling allocator. (default: 0)
         pub pooling_async_stack_keep_resident: Option<usize>,
@@ -204,25 +236,35 @@ wasmtime_option_group! {
     #[derive(PartialEq, Clone, Deserialize)]
     #[serde(rename_all = "kebab-case", deny_unknown_fields)]
     pub struct CodegenOptions {
-        /// Either `cranelift` or `winch`.
+        /// Either `cranelift`, `winch`, or `tiered`.
         ///
-        /// Currently only `cranelift` and `winch` are supported, but not all
-        /// builds of Wasmtime have both built in.
+        /// `tiered` compiles functions with `winch` on their first call and
+        /// then recompiles them with `cranelift` once they've been called
+        /// enough times. Not all builds of Wasmtime have every compiler built
+        /// in.
         #[serde(default)]
         #[serde(deserialize_with = "crate::opt::cli_parse_wrapper")]
         pub compiler: Option<wasmtime::Strategy>,
-        /// Which garbage collector to use: `drc` or `null`.
+        /// The number of calls after which a function is recompiled with
+        /// `cranelift` when using the `tiered` compiler.
+        pub tier_up_threshold: Option<u32>,
+        /// Which garbage collector to use: `drc`, `null`, or `copying`.
         ///
         /// `drc` is the deferred reference-counting collector.
         ///
         /// `null` is the null garbage collector, which does not collect any
         /// garbage.
         ///
+        /// `copying` is the semi-space copying collector.
+        ///
         /// Note that not all builds of Wasmtime will have support for garbage
         /// collection included.
         #[serde(default)]
         #[serde(deserialize_with = "crate::opt::cli_parse_wrapper")]
         pub collector: Option<wasmtime::Collector>,
+        /// Collect garbage incrementally, processing at most this many
+        /// objects per collection step.
+        pub incremental_gc_budget: Option<usize>,
         /// Enable Cranelift's internal debug verifier (expensive)
         pub cranelift_debug_verifier: Option<bool>,
         /// Whether or not to enable caching of compiled modules.
@@ -354,6 +396,13 @@ wasmtime_option_group! {
         /// the specification. Note that enabling this option may come at a
         /// performance cost.
         pub relaxed_simd_deterministic: Option<bool>,
+        /// Configure deterministic execution of WebAssembly.
+        ///
+        /// This canonicalizes NaNs, forces deterministic relaxed-simd
+        /// instructions, disables threads, traps when the host fails to grow a
+        /// memory, and gives WASI a fixed wall clock, a monotonic clock that
+        /// advances on each read, and random numbers with a fixed seed.
+        pub deterministic: Option<bool>,
         /// Configure support for the tail-call proposal.
         pub tail_call: Option<bool>,
         /// Configure support for the threads proposal.
@@ -428,6 +477,9 @@ wasmtime_option_group! {
         pub config: Option<bool>,
         /// Enable support for WASI key-value imports (experimental)
         pub keyvalue: Option<bool>,
+        /// Store WASI key-value data in files in the given directory, so that
+        /// it persists across runs, instead of in memory.
+        pub keyvalue_dir: Option<String>,
         /// Inherit environment variables and file descriptors following the
         /// systemd listen fd specification (UNIX only) (legacy wasip1
         /// implementation only)
@@ -682,9 +734,19 @@ impl CommonOptions {
             collector => config.collector(collector),
             _ => err,
         }
+        match_feature! {
+            ["gc" : self.codegen.incremental_gc_budget]
+            budget => config.incremental_gc_budget(std::num::NonZeroUsize::new(budget)),
+            _ => err,
+        }
         if let Some(target) = &self.target {
             config.target(target)?;
         }
+        match_feature! {
+            ["cranelift" : self.codegen.tier_up_threshold]
+            calls => config.tier_up_threshold(calls),
+            _ => err,
+        }
         match_feature! {
             ["cranelift" : self.codegen.cranelift_debug_verifier]
             enable => config.cranelift_debug_verifier(enable),
@@ -792,6 +854,19 @@ impl CommonOptions {
         if let Some(size) = mem_for_growth {
             config.memory_reservation_for_growth(size);
         }
+        if let Some(enable) = self.opts.memory_huge_pages {
+            config.memory_huge_pages(enable);
+        }
+        match_feature! {
+            ["memory-protection-keys" : self.opts.memory_protection_keys]
+            enable => config.memory_protection_keys(enable),
+            _ => err,
+        }
+        match_feature! {
+            ["memory-protection-keys" : self.opts.max_protected_memory_size]
+            size => config.max_protected_memory_size(size),
+            _ => err,
+        }
         if let Some(enable) = self.opts.guard_before_linear_memory {
             config.guard_before_linear_memory(enable);
         }
@@ -881,12 +956,24 @@ impl CommonOptions {
                     {
                         cfg.max_memory_size(max);
                     }
+                    if let Some(limit) = self.opts.pooling_total_memory64s {
+                        cfg.total_memory64s(limit);
+                    }
+                    if let Some(max) = self.opts.pooling_max_memory64_size {
+                        cfg.max_memory64_size(max);
+                    }
                     if let Some(size) = self.opts.pooling_decommit_batch_size {
                         cfg.decommit_batch_size(size);
                     }
                     if let Some(max) = self.opts.pooling_max_unused_warm_slots {
                         cfg.max_unused_warm_slots(max);
                     }
+                    if let Some(slots) = self.opts.pooling_elastic_chunk_slots {
+                        cfg.elastic_chunk_slots(slots);
+                    }
+                    if let Some(enable) = self.opts.pooling_memory_init_uffd {
+                        cfg.memory_init_uffd(enable);
+                    }
                     match_feature! {
                         ["async" : self.opts.pooling_async_stack_keep_resident]
                         size => cfg.async_stack_keep_resident(size),
@@ -967,6 +1054,9 @@ impl CommonOptions {
         if let Some(enable) = self.wasm.relaxed_simd_deterministic {
             config.relaxed_simd_deterministic(enable);
         }
+        if let Some(enable) = self.wasm.deterministic {
+            config.deterministic(enable);
+        }
         match_feature! {
             ["cranelift" : self.wasm.wmemcheck]
             enable => config.wmemcheck(enable),
@@ -1142,6 +1232,7 @@ mod tests {
         for (strategy_value, expected) in [
             ("\"cranelift\"", Some(wasmtime::Strategy::Cranelift)),
             ("\"winch\"", Some(wasmtime::Strategy::Winch)),
+            ("\"tiered\"", Some(wasmtime::Strategy::Tiered)),
             ("\"hello\"", None), // should fail
             ("5", None),         // should fail
             ("true", None),      // should fail
@@ -1168,6 +1259,7 @@ mod tests {
                 Some(wasmtime::Collector::DeferredReferenceCounting),
             ),
             ("\"null\"", Some(wasmtime::Collector::Null)),
+            ("\"copying\"", Some(wasmtime::Collector::Copying)),
             ("\"hello\"", None), // should fail
             ("5", None),         // should fail
             ("true", None),      // should fail
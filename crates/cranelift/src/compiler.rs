@@ -191,6 +191,7 @@ impl wasmtime_environ::Compiler for Compiler {
     ) -> Result<CompiledFunctionBody, CompileError> {
         let isa = &*self.isa;
         let module = &translation.module;
+        let def_func_index = func_index;
         let func_index = module.func_index(func_index);
         let sig = translation.module.functions[func_index]
             .signature
@@ -211,6 +212,9 @@ impl wasmtime_environ::Compiler for Compiler {
         }
 
         let mut func_env = FuncEnvironment::new(self, translation, types, wasm_func_ty);
+        if module.num_fuel_counters > 0 {
+            func_env.fuel_counter = Some(func_env.offsets.vmctx_fuel_counter(def_func_index));
+        }
 
         // The `stack_limit` global value below is the implementation of stack
         // overflow checks in Wasmtime.
@@ -441,6 +445,103 @@ impl wasmtime_environ::Compiler for Compiler {
         })
     }
 
+    fn compile_wasm_to_lazy_trampoline(
+        &self,
+        wasm_func_ty: &WasmFuncType,
+        symbol: &str,
+    ) -> Result<CompiledFunctionBody, CompileError> {
+        let isa = &*self.isa;
+        let pointer_type = isa.pointer_type();
+        let ptr = isa.pointer_bytes();
+        let wasm_call_sig = wasm_call_signature(isa, wasm_func_ty, &self.tunables);
+        let sigs = BuiltinFunctionSignatures::new(self);
+        let lazy_compile = BuiltinFunctionIndex::lazy_compile();
+        let host_sig = sigs.host_signature(lazy_compile);
+
+        let mut compiler = self.function_compiler();
+        let func = ir::Function::with_name_signature(Default::default(), wasm_call_sig.clone());
+        let (mut builder, block0) = compiler.builder(func);
+
+        let args = builder.func.dfg.block_params(block0).to_vec();
+        let lazy_func = args[0];
+        let caller_vmctx = args[1];
+
+        // The callee "vmctx" is the function's `VMLazyFunc`, which records
+        // the function's real vmctx and index.
+        debug_assert_vmctx_kind(
+            isa,
+            &mut builder,
+            lazy_func,
+            wasmtime_environ::VM_LAZY_FUNC_MAGIC,
+        );
+        let vmctx = builder.ins().load(
+            pointer_type,
+            MemFlags::trusted(),
+            lazy_func,
+            i32::from(ptr.vm_lazy_func_vmctx()),
+        );
+
+        // If the function has already been compiled, which is the case when
+        // this is called through a copy of the function's original
+        // `VMFuncRef` such as another instance's import, then call its code
+        // directly.
+        let compiled = builder.ins().load(
+            pointer_type,
+            MemFlags::trusted(),
+            lazy_func,
+            i32::from(ptr.vm_lazy_func_wasm_call()),
+        );
+        let compile_block = builder.create_block();
+        let call_block = builder.create_block();
+        builder.append_block_param(call_block, pointer_type);
+        builder
+            .ins()
+            .brif(compiled, call_block, &[compiled.into()], compile_block, &[]);
+        builder.seal_block(compile_block);
+        builder.switch_to_block(compile_block);
+
+        let index = builder.ins().load(
+            ir::types::I32,
+            MemFlags::trusted(),
+            lazy_func,
+            i32::from(ptr.vm_lazy_func_index()),
+        );
+
+        // We are exiting Wasm to compile the function, so save our PC and FP.
+        let vm_store_context = builder.ins().load(
+            pointer_type,
+            MemFlags::trusted(),
+            vmctx,
+            i32::from(ptr.vmcontext_store_context()),
+        );
+        save_last_wasm_exit_fp_and_pc(&mut builder, pointer_type, &ptr, vm_store_context);
+
+        // Compile the function, or fetch its code if some other caller has
+        // already compiled it, raising a trap if compilation failed.
+        let call = self.call_builtin(&mut builder, vmctx, &[vmctx, index], lazy_compile, host_sig);
+        let code = builder.func.dfg.inst_results(call)[0];
+        self.raise_if_host_trapped(&mut builder, vmctx, code);
+        builder.ins().jump(call_block, &[code.into()]);
+        builder.seal_block(call_block);
+        builder.switch_to_block(call_block);
+        let code = builder.block_params(call_block)[0];
+
+        // And finally forward our arguments to the function's code and return
+        // its results.
+        let mut call_args = vec![vmctx, caller_vmctx];
+        call_args.extend_from_slice(&args[2..]);
+        let sig_ref = builder.func.import_signature(wasm_call_sig);
+        let call = builder.ins().call_indirect(sig_ref, code, &call_args);
+        let results = builder.func.dfg.inst_results(call).to_vec();
+        builder.ins().return_(&results);
+        builder.finalize();
+
+        Ok(CompiledFunctionBody {
+            code: Box::new(compiler.finish(&symbol)?),
+            needs_gc_heap: false,
+        })
+    }
+
     fn append_code(
         &self,
         obj: &mut Object<'static>,
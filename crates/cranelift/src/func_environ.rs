@@ -23,10 +23,10 @@ use std::mem;
 use wasmparser::{Operator, WasmFeatures};
 use wasmtime_environ::{
     BuiltinFunctionIndex, DataIndex, ElemIndex, EngineOrModuleTypeIndex, FuncIndex, GlobalIndex,
-    IndexType, Memory, MemoryIndex, Module, ModuleInternedTypeIndex, ModuleTranslation,
-    ModuleTypesBuilder, PtrSize, Table, TableIndex, TripleExt, Tunables, TypeConvert, TypeIndex,
-    VMOffsets, WasmCompositeInnerType, WasmFuncType, WasmHeapTopType, WasmHeapType, WasmRefType,
-    WasmResult, WasmValType,
+    IndexType, Memory, MemoryAccessKind, MemoryIndex, Module, ModuleInternedTypeIndex,
+    ModuleTranslation, ModuleTypesBuilder, PtrSize, Table, TableIndex, TripleExt, Tunables,
+    TypeConvert, TypeIndex, VMOffsets, WasmCompositeInnerType, WasmFuncType, WasmHeapTopType,
+    WasmHeapType, WasmRefType, WasmResult, WasmValType,
 };
 use wasmtime_environ::{FUNCREF_INIT_BIT, FUNCREF_MASK};
 use wasmtime_math::f64_cvt_to_int_bounds;
@@ -83,6 +83,7 @@ macro_rules! declare_function_signatures {
     )*) => {
         $(impl BuiltinFunctions {
             $( #[$attr] )*
+            #[allow(dead_code, reason = "some builtins are only called by trampolines or Winch")]
             pub(crate) fn $name(&mut self, func: &mut Function) -> ir::FuncRef {
                 self.load_builtin(func, BuiltinFunctionIndex::$name())
             }
@@ -166,6 +167,11 @@ pub struct FuncEnvironment<'module_environment> {
 
     fuel_consumed: i64,
 
+    /// The offset within the `VMContext` of this function's fuel counter,
+    /// when fuel profiling is enabled. Fuel added to `fuel_var` is also added
+    /// to this counter.
+    pub(crate) fuel_counter: Option<u32>,
+
     /// A `GlobalValue` in CLIF which represents the stack limit.
     ///
     /// Typically this resides in the `stack_limit` value of `ir::Function` but
@@ -223,6 +229,7 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             // Start with at least one fuel being consumed because even empty
             // functions should consume at least some fuel.
             fuel_consumed: 1,
+            fuel_counter: None,
 
             #[cfg(feature = "wmemcheck")]
             translation,
@@ -490,6 +497,17 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         let fuel = builder.use_var(self.fuel_var);
         let fuel = builder.ins().iadd_imm(fuel, consumption);
         builder.def_var(self.fuel_var, fuel);
+
+        // When profiling, also attribute the consumption to this function's
+        // counter in the `VMContext`.
+        if let Some(offset) = self.fuel_counter {
+            let vmctx = self.vmctx_val(&mut builder.cursor());
+            let offset = i32::try_from(offset).unwrap();
+            let flags = ir::MemFlags::trusted();
+            let counter = builder.ins().load(ir::types::I64, flags, vmctx, offset);
+            let counter = builder.ins().iadd_imm(counter, consumption);
+            builder.ins().store(flags, counter, vmctx, offset);
+        }
     }
 
     /// Loads the fuel consumption value from `VMStoreContext` into `self.fuel_var`
@@ -980,14 +998,18 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         builder.ins().call(free_start, &[vmctx]);
     }
 
-    #[cfg(feature = "wmemcheck")]
-    fn current_func_name(&self, builder: &mut FunctionBuilder) -> Option<&str> {
-        let func_index = match &builder.func.name {
+    fn current_func_index(&self, builder: &mut FunctionBuilder) -> FuncIndex {
+        match &builder.func.name {
             ir::UserFuncName::User(user) => FuncIndex::from_u32(user.index),
             _ => {
                 panic!("function name not a UserFuncName::User as expected")
             }
-        };
+        }
+    }
+
+    #[cfg(feature = "wmemcheck")]
+    fn current_func_name(&self, builder: &mut FunctionBuilder) -> Option<&str> {
+        let func_index = self.current_func_index(builder);
         self.translation
             .debuginfo
             .name_section
@@ -1301,7 +1323,8 @@ impl<'a, 'func, 'module_env> Call<'a, 'func, 'module_env> {
             .unwrap();
 
         // Handle direct calls to locally-defined functions.
-        if !self.env.module.is_imported_function(callee_index) {
+        let is_imported = self.env.module.is_imported_function(callee_index);
+        if !is_imported && !self.env.tunables.tiered_compilation {
             // First append the callee vmctx address, which is the same as the caller vmctx in
             // this case.
             real_call_args.push(caller_vmctx);
@@ -1318,28 +1341,43 @@ impl<'a, 'func, 'module_env> Call<'a, 'func, 'module_env> {
 
         // Handle direct calls to imported functions. We use an indirect call
         // so that we don't have to patch the code at runtime.
+        //
+        // With tiered compilation calls to locally-defined functions are
+        // similarly made through their `VMFuncRef`, which is updated whenever
+        // the callee is compiled or recompiled, so it can't be read-only.
         let pointer_type = self.env.pointer_type();
         let sig_ref = self.builder.func.dfg.ext_funcs[callee].signature;
         let vmctx = self.env.vmctx(self.builder.func);
         let base = self.builder.ins().global_value(pointer_type, vmctx);
 
-        let mem_flags = ir::MemFlags::trusted().with_readonly().with_can_move();
+        let (body_offset, vmctx_offset, mem_flags) = if is_imported {
+            (
+                self.env
+                    .offsets
+                    .vmctx_vmfunction_import_wasm_call(callee_index),
+                self.env.offsets.vmctx_vmfunction_import_vmctx(callee_index),
+                ir::MemFlags::trusted().with_readonly().with_can_move(),
+            )
+        } else {
+            let func_ref = self.env.module.functions[callee_index].func_ref;
+            let func_ref = self.env.offsets.vmctx_func_ref(func_ref);
+            let ptr = self.env.offsets.ptr;
+            (
+                func_ref + u32::from(ptr.vm_func_ref_wasm_call()),
+                func_ref + u32::from(ptr.vm_func_ref_vmctx()),
+                ir::MemFlags::trusted(),
+            )
+        };
 
         // Load the callee address.
-        let body_offset = i32::try_from(
-            self.env
-                .offsets
-                .vmctx_vmfunction_import_wasm_call(callee_index),
-        )
-        .unwrap();
+        let body_offset = i32::try_from(body_offset).unwrap();
         let func_addr = self
             .builder
             .ins()
             .load(pointer_type, mem_flags, base, body_offset);
 
         // First append the callee vmctx address.
-        let vmctx_offset =
-            i32::try_from(self.env.offsets.vmctx_vmfunction_import_vmctx(callee_index)).unwrap();
+        let vmctx_offset = i32::try_from(vmctx_offset).unwrap();
         let vmctx = self
             .builder
             .ins()
@@ -3287,6 +3325,45 @@ impl FuncEnvironment<'_> {
         let _ = (builder, val_size, addr, offset);
     }
 
+    /// Emits a call to the `trace_memory_access` builtin, if memory access
+    /// tracing is enabled, before an access of `size` bytes at `index +
+    /// offset` within `memory`.
+    pub fn trace_memory_access(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        kind: MemoryAccessKind,
+        memory: MemoryIndex,
+        size: u8,
+        index: ir::Value,
+        offset: u64,
+    ) {
+        if !self.tunables.memory_access_tracing {
+            return;
+        }
+        let trace_memory_access = self.builtin_functions.trace_memory_access(builder.func);
+        let vmctx = self.vmctx_val(&mut builder.cursor());
+        let memory = builder.ins().iconst(I32, i64::from(memory.as_u32()));
+        let kind = builder.ins().iconst(I8, i64::from(kind as u8));
+        let size = builder.ins().iconst(I8, i64::from(size));
+        let index = match builder.func.dfg.value_type(index) {
+            I64 => index,
+            _ => builder.ins().uextend(I64, index),
+        };
+        let addr = builder.ins().iadd_imm(index, offset as i64);
+        let func_index = self.current_func_index(builder);
+        let func_index = builder.ins().iconst(I32, i64::from(func_index.as_u32()));
+        // Instructions inserted here are given the source location of the
+        // Wasm instruction which is being translated.
+        let srcloc = builder
+            .func
+            .srcloc(builder.func.dfg.value_def(memory).unwrap_inst());
+        let srcloc = builder.ins().iconst(I32, i64::from(srcloc.bits()));
+        builder.ins().call(
+            trace_memory_access,
+            &[vmctx, memory, kind, size, addr, func_index, srcloc],
+        );
+    }
+
     pub fn update_global(
         &mut self,
         builder: &mut FunctionBuilder,
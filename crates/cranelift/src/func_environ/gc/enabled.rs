@@ -17,6 +17,8 @@ use wasmtime_environ::{
     WasmStorageType, WasmValType, wasm_unsupported,
 };
 
+#[cfg(feature = "gc-copying")]
+mod copying;
 #[cfg(feature = "gc-drc")]
 mod drc;
 #[cfg(feature = "gc-null")]
@@ -45,20 +47,28 @@ pub fn gc_compiler(func_env: &mut FuncEnvironment<'_>) -> WasmResult<Box<dyn GcC
              was disabled at compile time",
         )),
 
-        #[cfg(any(feature = "gc-drc", feature = "gc-null"))]
+        #[cfg(feature = "gc-copying")]
+        Some(Collector::Copying) => Ok(Box::new(copying::CopyingCompiler::default())),
+        #[cfg(not(feature = "gc-copying"))]
+        Some(Collector::Copying) => Err(wasm_unsupported!(
+            "the copying collector is unavailable because the `gc-copying` \
+             feature was disabled at compile time",
+        )),
+
+        #[cfg(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying"))]
         None => Err(wasm_unsupported!(
             "support for GC types disabled at configuration time"
         )),
-        #[cfg(not(any(feature = "gc-drc", feature = "gc-null")))]
+        #[cfg(not(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying")))]
         None => Err(wasm_unsupported!(
             "support for GC types disabled because no collector implementation \
-             was selected at compile time; enable one of the `gc-drc` or \
-             `gc-null` features",
+             was selected at compile time; enable one of the `gc-drc`, \
+             `gc-null`, or `gc-copying` features",
         )),
     }
 }
 
-#[cfg_attr(not(feature = "gc-drc"), allow(dead_code))]
+#[cfg_attr(not(any(feature = "gc-drc", feature = "gc-copying")), allow(dead_code))]
 fn unbarriered_load_gc_ref(
     builder: &mut FunctionBuilder,
     ty: WasmHeapType,
@@ -73,7 +83,10 @@ fn unbarriered_load_gc_ref(
     Ok(gc_ref)
 }
 
-#[cfg_attr(not(any(feature = "gc-drc", feature = "gc-null")), allow(dead_code))]
+#[cfg_attr(
+    not(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying")),
+    allow(dead_code)
+)]
 fn unbarriered_store_gc_ref(
     builder: &mut FunctionBuilder,
     ty: WasmHeapType,
@@ -459,7 +472,10 @@ pub fn translate_array_new_fixed(
 
 impl ArrayInit<'_> {
     /// Get the length (as an `i32`-typed `ir::Value`) of these array elements.
-    #[cfg_attr(not(any(feature = "gc-drc", feature = "gc-null")), allow(dead_code))]
+    #[cfg_attr(
+        not(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying")),
+        allow(dead_code)
+    )]
     fn len(self, pos: &mut FuncCursor) -> ir::Value {
         match self {
             ArrayInit::Fill { len, .. } => len,
@@ -471,7 +487,10 @@ impl ArrayInit<'_> {
     }
 
     /// Initialize a newly-allocated array's elements.
-    #[cfg_attr(not(any(feature = "gc-drc", feature = "gc-null")), allow(dead_code))]
+    #[cfg_attr(
+        not(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying")),
+        allow(dead_code)
+    )]
     fn initialize(
         self,
         func_env: &mut FuncEnvironment<'_>,
@@ -1134,7 +1153,10 @@ fn uextend_i32_to_pointer_type(
 /// in its initialization.
 ///
 /// Traps if the size overflows.
-#[cfg_attr(not(any(feature = "gc-drc", feature = "gc-null")), allow(dead_code))]
+#[cfg_attr(
+    not(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying")),
+    allow(dead_code)
+)]
 fn emit_array_size(
     func_env: &mut FuncEnvironment<'_>,
     builder: &mut FunctionBuilder<'_>,
@@ -1179,7 +1201,10 @@ fn emit_array_size(
 
 /// Common helper for struct-field initialization that can be reused across
 /// collectors.
-#[cfg_attr(not(any(feature = "gc-drc", feature = "gc-null")), allow(dead_code))]
+#[cfg_attr(
+    not(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying")),
+    allow(dead_code)
+)]
 fn initialize_struct_fields(
     func_env: &mut FuncEnvironment<'_>,
     builder: &mut FunctionBuilder<'_>,
@@ -1274,7 +1299,7 @@ impl FuncEnvironment<'_> {
     }
 
     /// Get the GC heap's base.
-    #[cfg(any(feature = "gc-null", feature = "gc-drc"))]
+    #[cfg(any(feature = "gc-null", feature = "gc-drc", feature = "gc-copying"))]
     fn get_gc_heap_base(&mut self, builder: &mut FunctionBuilder) -> ir::Value {
         let global = self.get_gc_heap_base_global(&mut builder.func);
         builder.ins().global_value(self.pointer_type(), global)
@@ -0,0 +1,336 @@
+//! Compiler for the copying collector.
+//!
+//! The copying collector moves objects when it collects garbage, so every GC
+//! reference that is live across a safepoint must be included in stack maps,
+//! and must be reloaded from its stack slot after the safepoint, which the
+//! `cranelift-frontend` takes care of for us. On the other hand, the collector
+//! doesn't require any read or write barriers.
+
+use super::*;
+use crate::func_environ::FuncEnvironment;
+use cranelift_codegen::ir::{self, InstBuilder};
+use cranelift_frontend::FunctionBuilder;
+use wasmtime_environ::{
+    GcTypeLayouts, ModuleInternedTypeIndex, PtrSize, TypeIndex, VMGcKind, WasmRefType, WasmResult,
+    copying::{ALLOC_AREA_END_OFFSET, ALLOC_AREA_NEXT_OFFSET, CopyingTypeLayouts},
+};
+
+#[derive(Default)]
+pub struct CopyingCompiler {
+    layouts: CopyingTypeLayouts,
+}
+
+impl CopyingCompiler {
+    /// Emit code to allocate a new GC object.
+    ///
+    /// Objects are bump allocated inline out of the current semi-space when
+    /// there is room for them, falling back to the `gc_alloc_raw` libcall,
+    /// which may collect garbage or grow the GC heap, otherwise.
+    ///
+    /// `size` must be greater than or equal to `size_of(VMGcHeader)`.
+    ///
+    /// `align` must be greater than or equal to `align_of(VMGcHeader)` and a
+    /// power of two.
+    ///
+    /// The resulting values are
+    ///
+    /// 1. The `VMGcRef` indexing into the GC heap.
+    ///
+    /// 2. The raw pointer to the start of the object inside the GC heap. This
+    ///    may be used to access up to `size` bytes.
+    fn emit_alloc(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+        kind: VMGcKind,
+        ty: ModuleInternedTypeIndex,
+        size: ir::Value,
+        align: u32,
+    ) -> (ir::Value, ir::Value) {
+        log::trace!("emit_alloc(kind={kind:?}, ty={ty:?}, size={size}, align={align})");
+
+        assert_eq!(builder.func.dfg.value_type(size), ir::types::I32);
+        assert!(align.is_power_of_two());
+
+        let current_block = builder.current_block().unwrap();
+        let bump_block = builder.create_block();
+        let slow_block = builder.create_block();
+        let continue_block = builder.create_block();
+        let gc_ref = builder.append_block_param(continue_block, ir::types::I32);
+        builder.declare_value_needs_stack_map(gc_ref);
+
+        builder.ensure_inserted_block();
+        builder.insert_block_after(bump_block, current_block);
+        builder.insert_block_after(continue_block, bump_block);
+        builder.insert_block_after(slow_block, continue_block);
+
+        // Check that the size fits in the unused bits of a `VMGcKind`, since
+        // the copying collector stores the object's size there.
+        let mask = builder
+            .ins()
+            .iconst(ir::types::I32, i64::from(VMGcKind::MASK));
+        let masked = builder.ins().band(size, mask);
+        func_env.trapnz(builder, masked, crate::TRAP_ALLOCATION_TOO_LARGE);
+
+        // Load the bump "pointer" and the end of the current semi-space (both
+        // are actually indices into the GC heap, not raw pointers).
+        let pointer_type = func_env.pointer_type();
+        let vmctx = func_env.vmctx_val(&mut builder.cursor());
+        let ptr_to_alloc_area = builder.ins().load(
+            pointer_type,
+            ir::MemFlags::trusted().with_readonly(),
+            vmctx,
+            i32::from(func_env.offsets.ptr.vmctx_gc_heap_data()),
+        );
+        let next = builder.ins().load(
+            ir::types::I32,
+            ir::MemFlags::trusted(),
+            ptr_to_alloc_area,
+            i32::try_from(ALLOC_AREA_NEXT_OFFSET).unwrap(),
+        );
+        let end = builder.ins().load(
+            ir::types::I32,
+            ir::MemFlags::trusted(),
+            ptr_to_alloc_area,
+            i32::try_from(ALLOC_AREA_END_OFFSET).unwrap(),
+        );
+
+        // Increment the bump "pointer" to the requested alignment:
+        //
+        //     next + (align - 1) & !(align - 1)
+        //
+        // Overflow means that the alignment is too large to satisfy, so trap
+        // accordingly.
+        let align_minus_one = builder.ins().iconst(ir::types::I32, i64::from(align - 1));
+        let next_plus_align_minus_one = func_env.uadd_overflow_trap(
+            builder,
+            next,
+            align_minus_one,
+            crate::TRAP_ALLOCATION_TOO_LARGE,
+        );
+        let aligned = builder
+            .ins()
+            .band_imm(next_plus_align_minus_one, i64::from(!(align - 1)));
+
+        // Check whether the allocation fits in the current semi-space.
+        let end_of_object =
+            func_env.uadd_overflow_trap(builder, aligned, size, crate::TRAP_ALLOCATION_TOO_LARGE);
+        let fits = builder.ins().icmp(
+            ir::condcodes::IntCC::UnsignedLessThanOrEqual,
+            end_of_object,
+            end,
+        );
+        builder.ins().brif(fits, bump_block, &[], slow_block, &[]);
+
+        // Update the bump "pointer" and write the object's header.
+        //
+        // TODO: Ideally we would use a single `i64` store to write both the
+        // header and the type index, but that requires generating different
+        // code for big-endian architectures, and I haven't bothered doing that
+        // yet.
+        log::trace!("emit_alloc: bump_block");
+        builder.switch_to_block(bump_block);
+        builder.seal_block(bump_block);
+        builder.ins().store(
+            ir::MemFlags::trusted(),
+            end_of_object,
+            ptr_to_alloc_area,
+            i32::try_from(ALLOC_AREA_NEXT_OFFSET).unwrap(),
+        );
+        let base = func_env.get_gc_heap_base(builder);
+        let uext_aligned = uextend_i32_to_pointer_type(builder, pointer_type, aligned);
+        let ptr_to_object = builder.ins().iadd(base, uext_aligned);
+        let kind_val = builder
+            .ins()
+            .iconst(ir::types::I32, i64::from(kind.as_u32()));
+        let kind_and_size = builder.ins().bor(kind_val, size);
+        let shared_ty = func_env.module_interned_to_shared_ty(&mut builder.cursor(), ty);
+        builder.ins().store(
+            ir::MemFlags::trusted(),
+            kind_and_size,
+            ptr_to_object,
+            i32::try_from(wasmtime_environ::VM_GC_HEADER_KIND_OFFSET).unwrap(),
+        );
+        builder.ins().store(
+            ir::MemFlags::trusted(),
+            shared_ty,
+            ptr_to_object,
+            i32::try_from(wasmtime_environ::VM_GC_HEADER_TYPE_INDEX_OFFSET).unwrap(),
+        );
+        builder.ins().jump(continue_block, &[aligned.into()]);
+
+        // The current semi-space is full, so call out to the runtime, which
+        // will collect garbage and/or grow the GC heap as necessary.
+        log::trace!("emit_alloc: slow_block");
+        builder.switch_to_block(slow_block);
+        builder.seal_block(slow_block);
+        builder.set_cold_block(slow_block);
+        let gc_alloc_raw_builtin = func_env.builtin_functions.gc_alloc_raw(builder.func);
+        let vmctx = func_env.vmctx_val(&mut builder.cursor());
+        let kind_val = builder
+            .ins()
+            .iconst(ir::types::I32, i64::from(kind.as_u32()));
+        let ty_val = builder.ins().iconst(ir::types::I32, i64::from(ty.as_u32()));
+        let align_val = builder.ins().iconst(ir::types::I32, i64::from(align));
+        let call_inst = builder.ins().call(
+            gc_alloc_raw_builtin,
+            &[vmctx, kind_val, ty_val, size, align_val],
+        );
+        let new_gc_ref = builder.func.dfg.first_result(call_inst);
+        builder.ins().jump(continue_block, &[new_gc_ref.into()]);
+
+        // Return the newly allocated object.
+        log::trace!("emit_alloc: continue_block");
+        builder.switch_to_block(continue_block);
+        builder.seal_block(continue_block);
+
+        // NB: The GC heap's base may have changed if the slow path grew it, so
+        // reload it here, rather than reusing the bump path's value.
+        let base = func_env.get_gc_heap_base(builder);
+        let uext_gc_ref = uextend_i32_to_pointer_type(builder, pointer_type, gc_ref);
+        let ptr_to_object = builder.ins().iadd(base, uext_gc_ref);
+
+        log::trace!("emit_alloc(..) -> ({gc_ref}, {ptr_to_object})");
+        (gc_ref, ptr_to_object)
+    }
+}
+
+impl GcCompiler for CopyingCompiler {
+    fn layouts(&self) -> &dyn GcTypeLayouts {
+        &self.layouts
+    }
+
+    fn alloc_array(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder<'_>,
+        array_type_index: TypeIndex,
+        init: super::ArrayInit<'_>,
+    ) -> WasmResult<ir::Value> {
+        let interned_type_index =
+            func_env.module.types[array_type_index].unwrap_module_type_index();
+        let ptr_ty = func_env.pointer_type();
+
+        let len_offset = gc_compiler(func_env)?.layouts().array_length_field_offset();
+        let array_layout = func_env.array_layout(interned_type_index).clone();
+        let base_size = array_layout.base_size;
+        let align = array_layout.align;
+        let len_to_elems_delta = base_size.checked_sub(len_offset).unwrap();
+
+        // First, compute the array's total size from its base size, element
+        // size, and length.
+        let len = init.len(&mut builder.cursor());
+        let size = emit_array_size(func_env, builder, &array_layout, len);
+
+        // Next, allocate the array.
+        let (gc_ref, ptr_to_object) = self.emit_alloc(
+            func_env,
+            builder,
+            VMGcKind::ArrayRef,
+            interned_type_index,
+            size,
+            align,
+        );
+
+        // Write the array's length into its field.
+        //
+        // Note: we don't need to bounds-check the GC ref access here, because
+        // the result of the allocation is trusted and we aren't reading any
+        // pointers or offsets out from the (untrusted) GC heap.
+        let len_addr = builder.ins().iadd_imm(ptr_to_object, i64::from(len_offset));
+        let len = init.len(&mut builder.cursor());
+        builder
+            .ins()
+            .store(ir::MemFlags::trusted(), len, len_addr, 0);
+
+        // Finally, initialize the elements.
+        let len_to_elems_delta = builder.ins().iconst(ptr_ty, i64::from(len_to_elems_delta));
+        let elems_addr = builder.ins().iadd(len_addr, len_to_elems_delta);
+        init.initialize(
+            func_env,
+            builder,
+            interned_type_index,
+            base_size,
+            size,
+            elems_addr,
+            |func_env, builder, elem_ty, elem_addr, val| {
+                write_field_at_addr(func_env, builder, elem_ty, elem_addr, val)
+            },
+        )?;
+
+        Ok(gc_ref)
+    }
+
+    fn alloc_struct(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder<'_>,
+        struct_type_index: TypeIndex,
+        field_vals: &[ir::Value],
+    ) -> WasmResult<ir::Value> {
+        let interned_type_index =
+            func_env.module.types[struct_type_index].unwrap_module_type_index();
+        let struct_layout = func_env.struct_layout(interned_type_index);
+
+        // Copy some stuff out of the struct layout to avoid borrowing issues.
+        let struct_size = struct_layout.size;
+        let struct_align = struct_layout.align;
+
+        assert_eq!(VMGcKind::MASK & struct_size, 0);
+        assert_eq!(VMGcKind::UNUSED_MASK & struct_size, struct_size);
+        let struct_size_val = builder.ins().iconst(ir::types::I32, i64::from(struct_size));
+
+        let (struct_ref, raw_struct_pointer) = self.emit_alloc(
+            func_env,
+            builder,
+            VMGcKind::StructRef,
+            interned_type_index,
+            struct_size_val,
+            struct_align,
+        );
+
+        // Initialize the struct's fields.
+        //
+        // Note: we don't need to bounds-check the GC ref access here, because
+        // the result of the allocation is trusted and we aren't reading any
+        // pointers or offsets out from the (untrusted) GC heap.
+        initialize_struct_fields(
+            func_env,
+            builder,
+            interned_type_index,
+            raw_struct_pointer,
+            field_vals,
+            |func_env, builder, ty, field_addr, val| {
+                write_field_at_addr(func_env, builder, ty, field_addr, val)
+            },
+        )?;
+
+        Ok(struct_ref)
+    }
+
+    fn translate_read_gc_reference(
+        &mut self,
+        _func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+        ty: WasmRefType,
+        src: ir::Value,
+        flags: ir::MemFlags,
+    ) -> WasmResult<ir::Value> {
+        // Unlike the null collector, we must mark the loaded value as requiring
+        // inclusion in stack maps, so that the collector can both find it and
+        // update it when the object it references is moved.
+        unbarriered_load_gc_ref(builder, ty.heap_type, src, flags)
+    }
+
+    fn translate_write_gc_reference(
+        &mut self,
+        _func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+        ty: WasmRefType,
+        dst: ir::Value,
+        new_val: ir::Value,
+        flags: ir::MemFlags,
+    ) -> WasmResult<()> {
+        unbarriered_store_gc_ref(builder, ty.heap_type, dst, new_val, flags)
+    }
+}
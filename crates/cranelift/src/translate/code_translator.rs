@@ -93,8 +93,9 @@ use std::collections::{HashMap, hash_map};
 use std::vec::Vec;
 use wasmparser::{FuncValidator, MemArg, Operator, WasmModuleResources};
 use wasmtime_environ::{
-    DataIndex, ElemIndex, FuncIndex, GlobalIndex, MemoryIndex, Signed, TableIndex, TypeConvert,
-    TypeIndex, Unsigned, WasmRefType, WasmResult, WasmValType, wasm_unsupported,
+    DataIndex, ElemIndex, FuncIndex, GlobalIndex, MemoryAccessKind, MemoryIndex, Signed,
+    TableIndex, TypeConvert, TypeIndex, Unsigned, WasmRefType, WasmResult, WasmValType,
+    wasm_unsupported,
 };
 
 /// Given a `Reachability<T>`, unwrap the inner `T` or, when unreachable, set
@@ -878,7 +879,7 @@ pub fn translate_operator(
             //TODO(#6829): add before_load() and before_store() hooks for SIMD loads and stores.
             let (flags, _, base) = unwrap_or_return_unreachable_state!(
                 state,
-                prepare_addr(memarg, 8, builder, state, environ)?
+                prepare_addr(memarg, 8, MemoryAccessKind::Load, builder, state, environ)?
             );
             let loaded = builder.ins().sload8x8(flags, base, 0);
             state.push1(loaded);
@@ -886,7 +887,7 @@ pub fn translate_operator(
         Operator::V128Load8x8U { memarg } => {
             let (flags, _, base) = unwrap_or_return_unreachable_state!(
                 state,
-                prepare_addr(memarg, 8, builder, state, environ)?
+                prepare_addr(memarg, 8, MemoryAccessKind::Load, builder, state, environ)?
             );
             let loaded = builder.ins().uload8x8(flags, base, 0);
             state.push1(loaded);
@@ -894,7 +895,7 @@ pub fn translate_operator(
         Operator::V128Load16x4S { memarg } => {
             let (flags, _, base) = unwrap_or_return_unreachable_state!(
                 state,
-                prepare_addr(memarg, 8, builder, state, environ)?
+                prepare_addr(memarg, 8, MemoryAccessKind::Load, builder, state, environ)?
             );
             let loaded = builder.ins().sload16x4(flags, base, 0);
             state.push1(loaded);
@@ -902,7 +903,7 @@ pub fn translate_operator(
         Operator::V128Load16x4U { memarg } => {
             let (flags, _, base) = unwrap_or_return_unreachable_state!(
                 state,
-                prepare_addr(memarg, 8, builder, state, environ)?
+                prepare_addr(memarg, 8, MemoryAccessKind::Load, builder, state, environ)?
             );
             let loaded = builder.ins().uload16x4(flags, base, 0);
             state.push1(loaded);
@@ -910,7 +911,7 @@ pub fn translate_operator(
         Operator::V128Load32x2S { memarg } => {
             let (flags, _, base) = unwrap_or_return_unreachable_state!(
                 state,
-                prepare_addr(memarg, 8, builder, state, environ)?
+                prepare_addr(memarg, 8, MemoryAccessKind::Load, builder, state, environ)?
             );
             let loaded = builder.ins().sload32x2(flags, base, 0);
             state.push1(loaded);
@@ -918,7 +919,7 @@ pub fn translate_operator(
         Operator::V128Load32x2U { memarg } => {
             let (flags, _, base) = unwrap_or_return_unreachable_state!(
                 state,
-                prepare_addr(memarg, 8, builder, state, environ)?
+                prepare_addr(memarg, 8, MemoryAccessKind::Load, builder, state, environ)?
             );
             let loaded = builder.ins().uload32x2(flags, base, 0);
             state.push1(loaded);
@@ -3213,12 +3214,15 @@ fn translate_unreachable_operator(
 /// in-bounds, and various parameters are returned describing the valid *native*
 /// heap address if execution reaches that point.
 ///
-/// Returns `None` when the Wasm access will unconditionally trap.
+/// Returns `None` when the Wasm access will unconditionally trap. Otherwise
+/// the access, of the given `kind`, is also reported to memory access tracing
+/// if it's enabled.
 ///
 /// Returns `(flags, wasm_addr, native_addr)`.
 fn prepare_addr(
     memarg: &MemArg,
     access_size: u8,
+    kind: MemoryAccessKind,
     builder: &mut FunctionBuilder,
     state: &mut FuncTranslationState,
     environ: &mut FuncEnvironment<'_>,
@@ -3226,6 +3230,18 @@ fn prepare_addr(
     let index = state.pop1();
     let heap = state.get_heap(builder.func, memarg.memory, environ)?;
 
+    // The tracing hook may grow the memory and move it, so it's called before
+    // the bounds check and the computation of the access's address. This means
+    // that out-of-bounds accesses are traced too, before they trap.
+    environ.trace_memory_access(
+        builder,
+        kind,
+        MemoryIndex::from_u32(memarg.memory),
+        access_size,
+        index,
+        memarg.offset,
+    );
+
     // How exactly the bounds check is performed here and what it's performed
     // on is a bit tricky. Generally we want to rely on access violations (e.g.
     // segfaults) to generate traps since that means we don't have to bounds
@@ -3427,12 +3443,13 @@ fn align_atomic_addr(
 fn prepare_atomic_addr(
     memarg: &MemArg,
     loaded_bytes: u8,
+    kind: MemoryAccessKind,
     builder: &mut FunctionBuilder,
     state: &mut FuncTranslationState,
     environ: &mut FuncEnvironment<'_>,
 ) -> WasmResult<Reachability<(MemFlags, Value, Value)>> {
     align_atomic_addr(memarg, loaded_bytes, builder, state, environ);
-    prepare_addr(memarg, loaded_bytes, builder, state, environ)
+    prepare_addr(memarg, loaded_bytes, kind, builder, state, environ)
 }
 
 /// Translate a load instruction.
@@ -3447,11 +3464,17 @@ fn translate_load(
     environ: &mut FuncEnvironment<'_>,
 ) -> WasmResult<Reachability<()>> {
     let mem_op_size = mem_op_size(opcode, result_ty);
-    let (flags, wasm_index, base) =
-        match prepare_addr(memarg, mem_op_size, builder, state, environ)? {
-            Reachability::Unreachable => return Ok(Reachability::Unreachable),
-            Reachability::Reachable((f, i, b)) => (f, i, b),
-        };
+    let (flags, wasm_index, base) = match prepare_addr(
+        memarg,
+        mem_op_size,
+        MemoryAccessKind::Load,
+        builder,
+        state,
+        environ,
+    )? {
+        Reachability::Unreachable => return Ok(Reachability::Unreachable),
+        Reachability::Reachable((f, i, b)) => (f, i, b),
+    };
 
     environ.before_load(builder, mem_op_size, wasm_index, memarg.offset);
 
@@ -3476,7 +3499,14 @@ fn translate_store(
 
     let (flags, wasm_index, base) = unwrap_or_return_unreachable_state!(
         state,
-        prepare_addr(memarg, mem_op_size, builder, state, environ)?
+        prepare_addr(
+            memarg,
+            mem_op_size,
+            MemoryAccessKind::Store,
+            builder,
+            state,
+            environ
+        )?
     );
 
     environ.before_store(builder, mem_op_size, wasm_index, memarg.offset);
@@ -3542,6 +3572,7 @@ fn translate_atomic_rmw(
         prepare_atomic_addr(
             memarg,
             u8::try_from(access_ty.bytes()).unwrap(),
+            MemoryAccessKind::ReadModifyWrite,
             builder,
             state,
             environ,
@@ -3599,6 +3630,7 @@ fn translate_atomic_cas(
         prepare_atomic_addr(
             memarg,
             u8::try_from(access_ty.bytes()).unwrap(),
+            MemoryAccessKind::ReadModifyWrite,
             builder,
             state,
             environ,
@@ -3642,6 +3674,7 @@ fn translate_atomic_load(
         prepare_atomic_addr(
             memarg,
             u8::try_from(access_ty.bytes()).unwrap(),
+            MemoryAccessKind::Load,
             builder,
             state,
             environ,
@@ -3691,6 +3724,7 @@ fn translate_atomic_store(
         prepare_atomic_addr(
             memarg,
             u8::try_from(access_ty.bytes()).unwrap(),
+            MemoryAccessKind::Store,
             builder,
             state,
             environ,
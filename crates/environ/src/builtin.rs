@@ -42,6 +42,15 @@ macro_rules! foreach_builtin_function {
             // Invoked when we reach a new epoch.
             #[cfg(target_has_atomic = "64")]
             new_epoch(vmctx: vmctx) -> u64;
+            // Invoked on the first call of a function with tiered compilation,
+            // returning the function's code after compiling it.
+            lazy_compile(vmctx: vmctx, func: u32) -> pointer;
+            // Invoked once a function has been called enough times with tiered
+            // compilation, to recompile it with the optimizing compiler.
+            tier_up(vmctx: vmctx, func: u32);
+            // Invoked before each access to linear memory when memory access
+            // tracing is enabled.
+            trace_memory_access(vmctx: vmctx, memory: u32, kind: u8, size: u8, addr: u64, func: u32, offset: u32) -> bool;
             // Invoked before malloc returns.
             #[cfg(feature = "wmemcheck")]
             check_malloc(vmctx: vmctx, addr: u32, len: u32) -> bool;
@@ -88,7 +97,7 @@ macro_rules! foreach_builtin_function {
 
             // Allocate a new, uninitialized GC object and return a reference to
             // it.
-            #[cfg(feature = "gc-drc")]
+            #[cfg(any(feature = "gc-drc", feature = "gc-copying"))]
             gc_alloc_raw(
                 vmctx: vmctx,
                 kind: u32,
@@ -428,6 +437,9 @@ impl BuiltinFunctionIndex {
 
             (@get cont_new pointer) => (TrapSentinel::Negative);
 
+            // Compiling a function returns null to indicate a trap.
+            (@get lazy_compile pointer) => (TrapSentinel::Falsy);
+
             // Bool-returning functions use `false` as an indicator of a trap.
             (@get $name:ident bool) => (TrapSentinel::Falsy);
 
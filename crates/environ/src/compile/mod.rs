@@ -227,6 +227,20 @@ pub trait Compiler: Send + Sync {
         symbol: &str,
     ) -> Result<CompiledFunctionBody, CompileError>;
 
+    /// Compile a trampoline for a Wasm caller calling a function with the
+    /// given signature which, with tiered compilation, hasn't been compiled
+    /// yet.
+    ///
+    /// The trampoline is called with the function's `VMLazyFunc` in place of
+    /// the callee's vmctx. It should save the necessary state to record the
+    /// Wasm-to-host transition, invoke the `lazy_compile` builtin to get the
+    /// function's code, and then call that code with the original arguments.
+    fn compile_wasm_to_lazy_trampoline(
+        &self,
+        wasm_func_ty: &WasmFuncType,
+        symbol: &str,
+    ) -> Result<CompiledFunctionBody, CompileError>;
+
     /// Creates a trampoline that can be used to call Wasmtime's implementation
     /// of the builtin function specified by `index`.
     ///
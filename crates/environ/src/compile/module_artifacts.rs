@@ -110,6 +110,10 @@ impl<'a> ObjectBuilder<'a> {
     ///   Wasm callers calling array callees (e.g. `Func::wrap`). One for each
     ///   function signature in the module. Must be sorted by `SignatureIndex`.
     ///
+    /// * `lazy_trampolines` - list of all trampolines necessary for Wasm
+    ///   callers calling functions which haven't been compiled yet with tiered
+    ///   compilation. Must be sorted by `SignatureIndex`.
+    ///
     /// Returns the `CompiledModuleInfo` corresponding to this core Wasm module
     /// as a result of this append operation. This is then serialized into the
     /// final artifact by the caller.
@@ -118,6 +122,7 @@ impl<'a> ObjectBuilder<'a> {
         translation: ModuleTranslation<'_>,
         funcs: PrimaryMap<DefinedFuncIndex, CompiledFunctionInfo>,
         wasm_to_array_trampolines: Vec<(ModuleInternedTypeIndex, FunctionLoc)>,
+        lazy_trampolines: Vec<(ModuleInternedTypeIndex, FunctionLoc)>,
     ) -> Result<CompiledModuleInfo> {
         let ModuleTranslation {
             mut module,
@@ -221,6 +226,7 @@ impl<'a> ObjectBuilder<'a> {
             module,
             funcs,
             wasm_to_array_trampolines,
+            lazy_trampolines,
             func_names,
             meta: Metadata {
                 has_unparsed_debuginfo,
@@ -250,6 +256,17 @@ impl<'a> ObjectBuilder<'a> {
         dwarf.push((T::id() as u8, offset..offset + data.len() as u64));
     }
 
+    /// Creates the `ELF_WASMTIME_WASM` section with the original `wasm` binary
+    /// of a module compiled with tiered compilation.
+    pub fn append_wasm(&mut self, wasm: &[u8]) {
+        let section = self.obj.add_section(
+            self.obj.segment_name(StandardSegment::Data).to_vec(),
+            obj::ELF_WASMTIME_WASM.as_bytes().to_vec(),
+            SectionKind::ReadOnlyData,
+        );
+        self.obj.append_section_data(section, wasm, 1);
+    }
+
     /// Creates the `ELF_WASMTIME_INFO` section from the given serializable data
     /// structure.
     pub fn serialize_info<T>(&mut self, info: &T)
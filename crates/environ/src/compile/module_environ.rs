@@ -107,6 +107,20 @@ impl<'data> ModuleTranslation<'data> {
             .as_ref()
             .expect("module type information to be available")
     }
+
+    /// Returns the parts of this translation which don't borrow from the
+    /// original wasm, leaving out the function bodies, debug information and
+    /// data segments.
+    pub fn into_owned(self) -> ModuleTranslation<'static> {
+        ModuleTranslation {
+            module: self.module,
+            exported_signatures: self.exported_signatures,
+            has_unparsed_debuginfo: self.has_unparsed_debuginfo,
+            data_align: self.data_align,
+            types: self.types,
+            ..Default::default()
+        }
+    }
 }
 
 /// Contains function data: byte code and its offset in the module.
@@ -216,6 +230,17 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
             Payload::End(offset) => {
                 self.result.types = Some(self.validator.end(offset)?);
 
+                // With tiered compilation every call to a defined function goes
+                // through its `VMFuncRef`, so that the function can be compiled
+                // on its first call and have its code replaced later on.
+                if self.tunables.tiered_compilation {
+                    for index in self.result.module.defined_func_indices() {
+                        let index = self.result.module.func_index(index);
+                        self.flag_func_escaped(index);
+                    }
+                    self.result.module.num_lazy_funcs = self.result.module.num_defined_funcs();
+                }
+
                 // With the `escaped_funcs` set of functions finished
                 // we can calculate the set of signatures that are exported as
                 // the set of exported functions' signatures.
@@ -234,6 +259,12 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
                     .collect();
                 self.result.exported_signatures.sort_unstable();
                 self.result.exported_signatures.dedup();
+
+                // Fuel profiling attributes the fuel consumed to each defined
+                // function with a counter per function.
+                if self.tunables.consume_fuel && self.tunables.fuel_profiling {
+                    self.result.module.num_fuel_counters = self.result.module.num_defined_funcs();
+                }
             }
 
             Payload::TypeSection(types) => {
@@ -9,6 +9,9 @@
 //! on our various `gc` cargo features is the actual garbage collection
 //! functions and their associated impact on binary size anyways.
 
+#[cfg(feature = "gc-copying")]
+pub mod copying;
+
 #[cfg(feature = "gc-drc")]
 pub mod drc;
 
@@ -58,7 +61,7 @@ pub fn byte_size_of_wasm_ty_in_gc_heap(ty: &WasmStorageType) -> u32 {
 
 /// Align `offset` up to `bytes`, updating `max_align` if `align` is the
 /// new maximum alignment, and returning the aligned offset.
-#[cfg(any(feature = "gc-drc", feature = "gc-null"))]
+#[cfg(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying"))]
 fn align_up(offset: &mut u32, max_align: &mut u32, align: u32) -> u32 {
     debug_assert!(max_align.is_power_of_two());
     debug_assert!(align.is_power_of_two());
@@ -70,7 +73,7 @@ fn align_up(offset: &mut u32, max_align: &mut u32, align: u32) -> u32 {
 /// Define a new field of size and alignment `bytes`, updating the object's
 /// total `size` and `align` as necessary. The offset of the new field is
 /// returned.
-#[cfg(any(feature = "gc-drc", feature = "gc-null"))]
+#[cfg(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying"))]
 fn field(size: &mut u32, align: &mut u32, bytes: u32) -> u32 {
     let offset = align_up(size, align, bytes);
     *size += bytes;
@@ -79,7 +82,7 @@ fn field(size: &mut u32, align: &mut u32, bytes: u32) -> u32 {
 
 /// Common code to define a GC array's layout, given the size and alignment of
 /// the collector's GC header and its expected offset of the array length field.
-#[cfg(any(feature = "gc-drc", feature = "gc-null"))]
+#[cfg(any(feature = "gc-drc", feature = "gc-null", feature = "gc-copying"))]
 fn common_array_layout(
     ty: &WasmArrayType,
     header_size: u32,
@@ -121,7 +124,7 @@ fn common_array_layout(
 
 /// Common code to define a GC struct's layout, given the size and alignment of
 /// the collector's GC header and its expected offset of the array length field.
-#[cfg(any(feature = "gc-null", feature = "gc-drc"))]
+#[cfg(any(feature = "gc-null", feature = "gc-drc", feature = "gc-copying"))]
 fn common_struct_layout(
     ty: &WasmStructType,
     header_size: u32,
@@ -0,0 +1,38 @@
+//! Layout of Wasm GC objects in the copying garbage collector.
+
+use super::*;
+
+/// The size of the `VMCopyingHeader` header for GC objects.
+pub const HEADER_SIZE: u32 = 8;
+
+/// The align of the `VMCopyingHeader` header for GC objects.
+pub const HEADER_ALIGN: u32 = 8;
+
+/// The offset of the length field in a `VMCopyingArrayHeader`.
+pub const ARRAY_LENGTH_OFFSET: u32 = HEADER_SIZE;
+
+/// The offset of the bump-allocation finger in the copying collector's
+/// allocation area, which is what `VMContext::gc_heap_data` points to.
+pub const ALLOC_AREA_NEXT_OFFSET: u32 = 0;
+
+/// The offset of the end of the current semi-space in the copying collector's
+/// allocation area.
+pub const ALLOC_AREA_END_OFFSET: u32 = 4;
+
+/// The layout of Wasm GC objects in the copying collector.
+#[derive(Default)]
+pub struct CopyingTypeLayouts;
+
+impl GcTypeLayouts for CopyingTypeLayouts {
+    fn array_length_field_offset(&self) -> u32 {
+        ARRAY_LENGTH_OFFSET
+    }
+
+    fn array_layout(&self, ty: &WasmArrayType) -> GcArrayLayout {
+        common_array_layout(ty, HEADER_SIZE, HEADER_ALIGN, ARRAY_LENGTH_OFFSET)
+    }
+
+    fn struct_layout(&self, ty: &WasmStructType) -> GcStructLayout {
+        common_struct_layout(ty, HEADER_SIZE, HEADER_ALIGN)
+    }
+}
@@ -26,6 +26,7 @@ mod error;
 mod ext;
 mod gc;
 mod hostcall;
+mod memory_access;
 mod module;
 mod module_artifacts;
 mod module_types;
@@ -46,6 +47,7 @@ pub use crate::demangling::*;
 pub use crate::error::*;
 pub use crate::gc::*;
 pub use crate::hostcall::*;
+pub use crate::memory_access::*;
 pub use crate::module::*;
 pub use crate::module_artifacts::*;
 pub use crate::module_types::*;
@@ -0,0 +1,28 @@
+/// The kind of an access to linear memory, as reported by memory access
+/// tracing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[repr(u8)]
+pub enum MemoryAccessKind {
+    /// The access reads from memory, for example with `i32.load`.
+    Load,
+
+    /// The access writes to memory, for example with `i32.store`.
+    Store,
+
+    /// The access atomically reads and then writes to memory, for example
+    /// with `i32.atomic.rmw.add` or `i32.atomic.rmw.cmpxchg`.
+    ReadModifyWrite,
+}
+
+impl MemoryAccessKind {
+    /// Converts a kind which was encoded with `kind as u8` back into a
+    /// `MemoryAccessKind`, returning `None` if it's not valid.
+    pub fn from_u8(kind: u8) -> Option<MemoryAccessKind> {
+        match kind {
+            0 => Some(MemoryAccessKind::Load),
+            1 => Some(MemoryAccessKind::Store),
+            2 => Some(MemoryAccessKind::ReadModifyWrite),
+            _ => None,
+        }
+    }
+}
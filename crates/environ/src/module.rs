@@ -346,6 +346,18 @@ pub struct Module {
     /// an `func_ref` index (and is the maximum func_ref index).
     pub num_escaped_funcs: usize,
 
+    /// Number of per-function fuel counters in this module's `VMContext`.
+    ///
+    /// This is the number of defined functions when fuel profiling is enabled
+    /// and zero otherwise.
+    pub num_fuel_counters: usize,
+
+    /// Number of `VMLazyFunc`s in this module's `VMContext`.
+    ///
+    /// This is the number of defined functions when tiered compilation is
+    /// enabled and zero otherwise.
+    pub num_lazy_funcs: usize,
+
     /// Types of functions, imported and local.
     pub functions: PrimaryMap<FuncIndex, FunctionType>,
 
@@ -576,6 +588,12 @@ impl Module {
         (0..self.functions.len() - self.num_imported_funcs).map(|i| DefinedFuncIndex::new(i))
     }
 
+    /// Returns the number of functions defined by this module itself: all
+    /// functions minus imported functions.
+    pub fn num_defined_funcs(&self) -> usize {
+        self.functions.len() - self.num_imported_funcs
+    }
+
     /// Returns the number of tables defined by this module itself: all tables
     /// minus imported tables.
     pub fn num_defined_tables(&self) -> usize {
@@ -625,6 +643,8 @@ impl TypeTrace for Module {
             num_imported_globals: _,
             num_imported_tags: _,
             num_escaped_funcs: _,
+            num_fuel_counters: _,
+            num_lazy_funcs: _,
             needs_gc_heap: _,
             functions,
             tables,
@@ -675,6 +695,8 @@ impl TypeTrace for Module {
             num_imported_globals: _,
             num_imported_tags: _,
             num_escaped_funcs: _,
+            num_fuel_counters: _,
+            num_lazy_funcs: _,
             needs_gc_heap: _,
             functions,
             tables,
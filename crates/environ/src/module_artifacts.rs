@@ -50,6 +50,11 @@ pub struct CompiledModuleInfo {
     /// callee (e.g. `Func::wrap`) to a Wasm caller. Sorted by signature index.
     pub wasm_to_array_trampolines: Vec<(ModuleInternedTypeIndex, FunctionLoc)>,
 
+    /// Metadata about wasm-to-lazy trampolines, which are used with tiered
+    /// compilation to call functions that haven't been compiled yet. Sorted by
+    /// signature index, and empty unless tiered compilation is enabled.
+    pub lazy_trampolines: Vec<(ModuleInternedTypeIndex, FunctionLoc)>,
+
     /// General compilation metadata.
     pub meta: Metadata,
 }
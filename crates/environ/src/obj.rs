@@ -156,6 +156,13 @@ pub const ELF_NAME_DATA: &'static str = ".name.wasm";
 /// metadata.
 pub const ELF_WASMTIME_DWARF: &str = ".wasmtime.dwarf";
 
+/// This is the name of the section in the final ELF image which contains the
+/// original WebAssembly binary of a module compiled with tiered compilation.
+///
+/// With tiered compilation functions aren't compiled until they're first
+/// called, so their bodies are retrieved from this section at that time.
+pub const ELF_WASMTIME_WASM: &str = ".wasmtime.wasm";
+
 /// Workaround to implement `core::error::Error` until
 /// gimli-rs/object#747 is settled.
 pub struct ObjectCrateErrorWrapper(pub object::Error);
@@ -73,6 +73,10 @@ define_tunables! {
         /// memory for growth.
         pub memory_reservation_for_growth: u64,
 
+        /// Whether linear memories are aligned to, and backed by, huge pages
+        /// where the host supports it.
+        pub memory_huge_pages: bool,
+
         /// Whether or not to generate native DWARF debug information.
         pub generate_native_debuginfo: bool,
 
@@ -83,6 +87,14 @@ define_tunables! {
         /// will be consumed every time a wasm instruction is executed.
         pub consume_fuel: bool,
 
+        /// Whether or not the fuel consumed by each defined function is also
+        /// counted separately, for fuel profiling.
+        pub fuel_profiling: bool,
+
+        /// Whether or not compiled code reports each access to linear memory
+        /// to the store's memory access hook.
+        pub memory_access_tracing: bool,
+
         /// Whether or not we use epoch-based interruption.
         pub epoch_interruption: bool,
 
@@ -115,6 +127,11 @@ define_tunables! {
         /// Whether or not Wasm functions target the winch abi.
         pub winch_callable: bool,
 
+        /// Whether or not Wasm functions are compiled lazily on their first
+        /// call, and then recompiled with an optimizing compiler once they've
+        /// been called enough times.
+        pub tiered_compilation: bool,
+
         /// Whether or not the host will be using native signals (e.g. SIGILL,
         /// SIGSEGV, etc) to implement traps.
         pub signals_based_traps: bool,
@@ -175,12 +192,15 @@ impl Tunables {
             memory_reservation: 1 << 20,
             memory_guard_size: 0,
             memory_reservation_for_growth: 0,
+            memory_huge_pages: false,
 
             // General options which have the same defaults regardless of
             // architecture.
             generate_native_debuginfo: false,
             parse_wasm_debuginfo: true,
             consume_fuel: false,
+            fuel_profiling: false,
+            memory_access_tracing: false,
             epoch_interruption: false,
             memory_may_move: true,
             guard_before_linear_memory: true,
@@ -189,6 +209,7 @@ impl Tunables {
             debug_adapter_modules: false,
             relaxed_simd_deterministic: false,
             winch_callable: false,
+            tiered_compilation: false,
             signals_based_traps: false,
             memory_init_cow: true,
         }
@@ -257,6 +278,8 @@ pub enum Collector {
     DeferredReferenceCounting,
     /// The null collector.
     Null,
+    /// The semi-space copying collector.
+    Copying,
 }
 
 impl fmt::Display for Collector {
@@ -264,6 +287,7 @@ impl fmt::Display for Collector {
         match self {
             Collector::DeferredReferenceCounting => write!(f, "deferred reference-counting"),
             Collector::Null => write!(f, "null"),
+            Collector::Copying => write!(f, "copying"),
         }
     }
 }
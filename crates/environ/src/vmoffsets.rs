@@ -31,11 +31,14 @@
 //      globals: [VMGlobalDefinition; module.num_defined_globals],
 //      tags: [VMTagDefinition; module.num_defined_tags],
 //      func_refs: [VMFuncRef; module.num_escaped_funcs],
+//      fuel_counters: [u64; module.num_fuel_counters],
+//      lazy_funcs: [VMLazyFunc; module.num_lazy_funcs],
 // }
 
 use crate::{
-    DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex, DefinedTagIndex, FuncIndex,
-    FuncRefIndex, GlobalIndex, MemoryIndex, Module, OwnedMemoryIndex, TableIndex, TagIndex,
+    DefinedFuncIndex, DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex, DefinedTagIndex,
+    FuncIndex, FuncRefIndex, GlobalIndex, MemoryIndex, Module, OwnedMemoryIndex, TableIndex,
+    TagIndex,
 };
 use cranelift_entity::packed_option::ReservedValue;
 
@@ -83,6 +86,10 @@ pub struct VMOffsets<P> {
     /// The number of escaped functions in the module, the size of the func_refs
     /// array.
     pub num_escaped_funcs: u32,
+    /// The number of per-function fuel counters in the module.
+    pub num_fuel_counters: u32,
+    /// The number of `VMLazyFunc`s in the module.
+    pub num_lazy_funcs: u32,
 
     // precalculated offsets of various member fields
     imported_functions: u32,
@@ -96,6 +103,8 @@ pub struct VMOffsets<P> {
     defined_globals: u32,
     defined_tags: u32,
     defined_func_refs: u32,
+    fuel_counters: u32,
+    lazy_funcs: u32,
     size: u32,
 }
 
@@ -148,6 +157,39 @@ pub trait PtrSize {
         4 * self.size()
     }
 
+    // Offsets within `VMLazyFunc`
+
+    /// The offset of the `index` field.
+    #[inline]
+    fn vm_lazy_func_index(&self) -> u8 {
+        4
+    }
+
+    /// The offset of the `vmctx` field.
+    #[inline]
+    fn vm_lazy_func_vmctx(&self) -> u8 {
+        8
+    }
+
+    /// The offset of the `wasm_call` field.
+    #[inline]
+    fn vm_lazy_func_wasm_call(&self) -> u8 {
+        self.vm_lazy_func_vmctx() + self.size()
+    }
+
+    /// The offset of the `calls_left` field.
+    #[inline]
+    fn vm_lazy_func_calls_left(&self) -> u8 {
+        self.vm_lazy_func_wasm_call() + self.size()
+    }
+
+    /// Return the size of `VMLazyFunc`.
+    #[inline]
+    fn size_of_vm_lazy_func(&self) -> u8 {
+        let end = u32::from(self.vm_lazy_func_calls_left()) + 4;
+        u8::try_from(align(end, u32::from(self.size()))).unwrap()
+    }
+
     /// Return the size of `VMGlobalDefinition`; this is the size of the largest value type (i.e. a
     /// V128).
     #[inline]
@@ -472,6 +514,10 @@ pub struct VMOffsetsFields<P> {
     /// The number of escaped functions in the module, the size of the function
     /// references array.
     pub num_escaped_funcs: u32,
+    /// The number of per-function fuel counters in the module.
+    pub num_fuel_counters: u32,
+    /// The number of `VMLazyFunc`s in the module.
+    pub num_lazy_funcs: u32,
 }
 
 impl<P: PtrSize> VMOffsets<P> {
@@ -498,6 +544,8 @@ impl<P: PtrSize> VMOffsets<P> {
             num_defined_globals: cast_to_u32(module.globals.len() - module.num_imported_globals),
             num_defined_tags: cast_to_u32(module.tags.len() - module.num_imported_tags),
             num_escaped_funcs: cast_to_u32(module.num_escaped_funcs),
+            num_fuel_counters: cast_to_u32(module.num_fuel_counters),
+            num_lazy_funcs: cast_to_u32(module.num_lazy_funcs),
         })
     }
 
@@ -529,6 +577,8 @@ impl<P: PtrSize> VMOffsets<P> {
                     num_defined_tags: _,
                     num_owned_memories: _,
                     num_escaped_funcs: _,
+                    num_fuel_counters: _,
+                    num_lazy_funcs: _,
 
                     // used as the initial size below
                     size,
@@ -557,6 +607,8 @@ impl<P: PtrSize> VMOffsets<P> {
         }
 
         calculate_sizes! {
+            lazy_funcs: "lazy functions",
+            fuel_counters: "fuel counters",
             defined_func_refs: "module functions",
             defined_tags: "defined tags",
             defined_globals: "defined globals",
@@ -587,6 +639,8 @@ impl<P: PtrSize> From<VMOffsetsFields<P>> for VMOffsets<P> {
             num_defined_globals: fields.num_defined_globals,
             num_defined_tags: fields.num_defined_tags,
             num_escaped_funcs: fields.num_escaped_funcs,
+            num_fuel_counters: fields.num_fuel_counters,
+            num_lazy_funcs: fields.num_lazy_funcs,
             imported_functions: 0,
             imported_tables: 0,
             imported_memories: 0,
@@ -598,6 +652,8 @@ impl<P: PtrSize> From<VMOffsetsFields<P>> for VMOffsets<P> {
             defined_globals: 0,
             defined_tags: 0,
             defined_func_refs: 0,
+            fuel_counters: 0,
+            lazy_funcs: 0,
             size: 0,
         };
 
@@ -656,6 +712,13 @@ impl<P: PtrSize> From<VMOffsetsFields<P>> for VMOffsets<P> {
                 ret.num_escaped_funcs,
                 ret.ptr.size_of_vm_func_ref(),
             ),
+            align(8),
+            size(fuel_counters) = cmul(ret.num_fuel_counters, 8),
+            align(8),
+            size(lazy_funcs) = cmul(
+                ret.num_lazy_funcs,
+                ret.ptr.size_of_vm_lazy_func(),
+            ),
         }
 
         ret.size = next_field_offset;
@@ -862,6 +925,18 @@ impl<P: PtrSize> VMOffsets<P> {
         self.defined_func_refs
     }
 
+    /// The offset of the `fuel_counters` array.
+    #[inline]
+    pub fn vmctx_fuel_counters_begin(&self) -> u32 {
+        self.fuel_counters
+    }
+
+    /// The offset of the `lazy_funcs` array.
+    #[inline]
+    pub fn vmctx_lazy_funcs_begin(&self) -> u32 {
+        self.lazy_funcs
+    }
+
     /// Return the size of the `VMContext` allocation.
     #[inline]
     pub fn size_of_vmctx(&self) -> u32 {
@@ -954,6 +1029,27 @@ impl<P: PtrSize> VMOffsets<P> {
         self.vmctx_func_refs_begin() + index.as_u32() * u32::from(self.ptr.size_of_vm_func_ref())
     }
 
+    /// Return the offset to the fuel counter of the defined function `index`.
+    #[inline]
+    pub fn vmctx_fuel_counter(&self, index: DefinedFuncIndex) -> u32 {
+        assert!(index.as_u32() < self.num_fuel_counters);
+        self.vmctx_fuel_counters_begin() + index.as_u32() * 8
+    }
+
+    /// Return the offset to the `VMLazyFunc` of the defined function `index`.
+    #[inline]
+    pub fn vmctx_lazy_func(&self, index: DefinedFuncIndex) -> u32 {
+        assert!(index.as_u32() < self.num_lazy_funcs);
+        self.vmctx_lazy_funcs_begin() + index.as_u32() * u32::from(self.ptr.size_of_vm_lazy_func())
+    }
+
+    /// Return the offset to the `calls_left` field of the `VMLazyFunc` of the
+    /// defined function `index`.
+    #[inline]
+    pub fn vmctx_lazy_func_calls_left(&self, index: DefinedFuncIndex) -> u32 {
+        self.vmctx_lazy_func(index) + u32::from(self.ptr.vm_lazy_func_calls_left())
+    }
+
     /// Return the offset to the `wasm_call` field in `*const VMFunctionBody` index `index`.
     #[inline]
     pub fn vmctx_vmfunction_import_wasm_call(&self, index: FuncIndex) -> u32 {
@@ -1062,6 +1158,12 @@ pub const VMCONTEXT_MAGIC: u32 = u32::from_le_bytes(*b"core");
 /// and double-checked on `VMArrayCallHostFuncContext::from_opaque`.
 pub const VM_ARRAY_CALL_HOST_FUNC_MAGIC: u32 = u32::from_le_bytes(*b"ACHF");
 
+/// Equivalent of `VMCONTEXT_MAGIC` except for the contexts of functions that
+/// haven't been compiled yet with tiered compilation.
+///
+/// This is stored at the start of all `VMLazyFunc` structures.
+pub const VM_LAZY_FUNC_MAGIC: u32 = u32::from_le_bytes(*b"lazy");
+
 #[cfg(test)]
 mod tests {
     use crate::vmoffsets::align;
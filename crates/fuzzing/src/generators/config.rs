@@ -237,6 +237,7 @@ impl Config {
         WastConfig {
             collector: match self.wasmtime.collector {
                 Collector::Null => wasmtime_test_util::wast::Collector::Null,
+                Collector::Copying => wasmtime_test_util::wast::Collector::Copying,
                 Collector::DeferredReferenceCounting => {
                     wasmtime_test_util::wast::Collector::DeferredReferenceCounting
                 }
@@ -868,6 +869,7 @@ impl Arbitrary<'_> for CompilerStrategy {
 pub enum Collector {
     DeferredReferenceCounting,
     Null,
+    Copying,
 }
 
 impl Collector {
@@ -875,6 +877,7 @@ impl Collector {
         match self {
             Collector::DeferredReferenceCounting => wasmtime::Collector::DeferredReferenceCounting,
             Collector::Null => wasmtime::Collector::Null,
+            Collector::Copying => wasmtime::Collector::Copying,
         }
     }
 }
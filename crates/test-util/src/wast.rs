@@ -397,6 +397,7 @@ pub enum Collector {
     Auto,
     Null,
     DeferredReferenceCounting,
+    Copying,
 }
 
 impl WastTest {
@@ -0,0 +1,584 @@
+use crate::{Change, ChangeNotifier, Error, KeyValueBackend, Subscription, increment_value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, DirEntry, File, Metadata, OpenOptions};
+use std::io::{self, ErrorKind, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+/// A [`KeyValueBackend`] which stores data in a directory, so that it persists
+/// across processes.
+///
+/// Only the default bucket, the one with the empty identifier, exists in a new
+/// directory. Opening any other bucket fails with [`Error::NoSuchStore`] until
+/// it's created with [`FileBackend::create_bucket`], or by setting a key in it
+/// directly through the backend.
+///
+/// Each bucket is a subdirectory, and each key a file holding its value. File
+/// names are escaped so that any bucket identifier or key can be stored,
+/// including on case-insensitive file systems. Names which would be too long
+/// for the file system once escaped are shortened and suffixed with a hash of
+/// the name; files of such keys start with the key itself, before the value.
+///
+/// Writes are crash-safe: a new value is written to a temporary file which is
+/// synced to disk and then renamed over the key's file, so that a crash leaves
+/// either the old or the new value but never part of one.
+///
+/// Operations are atomic with respect to each other within a process. Other
+/// processes sharing the directory observe each write atomically, but
+/// increments racing with another process may be lost.
+///
+/// Operations block the calling thread on file I/O, so
+/// [`KeyValueBackend::is_blocking`] is true and guests' operations run on a
+/// thread for blocking work.
+///
+/// Subscriptions report the changes made by other processes too. While there
+/// are subscriptions, the directory is scanned for changes after every write
+/// made through this backend, and otherwise every 100ms. Scanning takes time
+/// proportional to the number of keys, and several changes made to a key
+/// between two scans are reported as one.
+pub struct FileBackend {
+    dir: PathBuf,
+    /// Held while writing, so that increments are atomic.
+    write_lock: Mutex<()>,
+    next_tmp: AtomicU64,
+    watch: Arc<Watch>,
+}
+
+impl FileBackend {
+    /// Creates a backend storing data in `dir`, creating the directory and
+    /// its default bucket if they don't exist yet.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let backend = Self {
+            watch: Arc::new(Watch {
+                dir: dir.clone(),
+                notifier: ChangeNotifier::new(),
+                snapshot: Mutex::new(None),
+                poll_now: Mutex::new(false),
+                poll_now_changed: Condvar::new(),
+            }),
+            dir,
+            write_lock: Mutex::new(()),
+            next_tmp: AtomicU64::new(0),
+        };
+        backend.create_bucket("")?;
+        Ok(backend)
+    }
+
+    /// Create the bucket `identifier`, so that it can be opened, if it doesn't
+    /// exist yet.
+    pub fn create_bucket(&self, identifier: &str) -> io::Result<()> {
+        self.create_bucket_dir(identifier)?;
+        Ok(())
+    }
+
+    fn bucket_dir(&self, bucket: &str) -> PathBuf {
+        self.dir.join(file_name("b-", bucket).0)
+    }
+
+    /// The path of the file of `key`, and whether the key is stored in the
+    /// file because its name is hashed.
+    fn key_path(&self, bucket: &str, key: &str) -> (PathBuf, bool) {
+        let (name, hashed) = file_name("k-", key);
+        (self.bucket_dir(bucket).join(name), hashed)
+    }
+
+    /// Create the directory of `bucket` if it doesn't exist yet.
+    ///
+    /// The identifier of a bucket whose name is hashed is stored in the
+    /// directory, for subscriptions to report.
+    fn create_bucket_dir(&self, bucket: &str) -> io::Result<PathBuf> {
+        let (name, hashed) = file_name("b-", bucket);
+        let dir = self.dir.join(name);
+        if !dir.is_dir() {
+            fs::create_dir_all(&dir)?;
+            if hashed {
+                fs::write(dir.join(BUCKET_NAME_FILE), bucket)?;
+                sync_dir(&dir)?;
+            }
+            sync_dir(&self.dir)?;
+        }
+        Ok(dir)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.write_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read(&self, bucket: &str, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let (path, hashed) = self.key_path(bucket, key);
+        let Some(contents) = read_if_exists(&path)? else {
+            return Ok(None);
+        };
+        if !hashed {
+            return Ok(Some(contents));
+        }
+        let (stored, value) = split_key(&contents).ok_or_else(|| corrupt(&path))?;
+        Ok((stored == key).then(|| value.to_vec()))
+    }
+
+    fn write(&self, bucket: &str, key: &str, value: &[u8]) -> io::Result<()> {
+        let (path, hashed) = self.key_path(bucket, key);
+        let dir = self.create_bucket_dir(bucket)?;
+        let tmp = dir.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            self.next_tmp.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = (|| {
+            let mut file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+            if hashed {
+                write!(file, "{}\n{key}", key.len())?;
+            }
+            file.write_all(value)?;
+            file.sync_all()?;
+            fs::rename(&tmp, path)?;
+            sync_dir(&dir)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        self.watch.poll_soon();
+        result
+    }
+}
+
+impl KeyValueBackend for FileBackend {
+    fn open(&self, bucket: &str) -> Result<(), Error> {
+        if self.bucket_dir(bucket).is_dir() {
+            Ok(())
+        } else {
+            Err(Error::NoSuchStore)
+        }
+    }
+
+    fn get(&self, bucket: &str, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.read(bucket, key)?)
+    }
+
+    fn set(&self, bucket: &str, key: &str, value: Vec<u8>) -> Result<(), Error> {
+        let _guard = self.lock();
+        Ok(self.write(bucket, key, &value)?)
+    }
+
+    fn delete(&self, bucket: &str, key: &str) -> Result<(), Error> {
+        let _guard = self.lock();
+        match fs::remove_file(self.key_path(bucket, key).0) {
+            Ok(()) => {
+                self.watch.poll_soon();
+                Ok(sync_dir(&self.bucket_dir(bucket))?)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn exists(&self, bucket: &str, key: &str) -> Result<bool, Error> {
+        Ok(self.key_path(bucket, key).0.is_file())
+    }
+
+    fn list_keys(&self, bucket: &str) -> Result<Vec<String>, Error> {
+        let entries = match fs::read_dir(self.bucket_dir(bucket)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut keys = Vec::new();
+        for entry in entries {
+            if let Some((key, _)) = key_of(&entry?)? {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn increment(&self, bucket: &str, key: &str, delta: u64) -> Result<u64, Error> {
+        let _guard = self.lock();
+        let current = self.read(bucket, key)?;
+        let (new, value) = increment_value(current.as_deref(), delta)?;
+        self.write(bucket, key, &value)?;
+        Ok(new)
+    }
+
+    fn is_blocking(&self) -> bool {
+        true
+    }
+
+    fn subscribe(&self, capacity: usize) -> Result<Subscription, Error> {
+        let mut snapshot = self.watch.snapshot();
+        match &mut *snapshot {
+            // Report the changes made so far to the existing subscriptions,
+            // so that the new one only gets later changes.
+            Some(snapshot) => self.watch.poll(snapshot)?,
+            None => {
+                *snapshot = Some(scan(&self.dir)?);
+                Watch::spawn_poller(&self.watch);
+            }
+        }
+        Ok(self.watch.notifier.subscribe(capacity))
+    }
+}
+
+/// How often the directory of a [`FileBackend`] with subscriptions is scanned
+/// for changes made by other processes.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The name of the file holding the identifier of a bucket whose directory
+/// name is hashed.
+const BUCKET_NAME_FILE: &str = ".bucket";
+
+/// The subscriptions to a [`FileBackend`], and what's needed to find changes.
+struct Watch {
+    dir: PathBuf,
+    notifier: ChangeNotifier,
+    /// The keys as of the last scan, or `None` when there are no
+    /// subscriptions and so no background thread polling for changes.
+    snapshot: Mutex<Option<Snapshot>>,
+    /// Set to scan right away, rather than after `POLL_INTERVAL`.
+    poll_now: Mutex<bool>,
+    poll_now_changed: Condvar,
+}
+
+/// The key files of every bucket, by bucket identifier and key.
+type Snapshot = HashMap<(String, String), KeyFile>;
+
+struct KeyFile {
+    path: PathBuf,
+    hashed: bool,
+    stamp: Stamp,
+}
+
+/// Identifies a version of a key's file. Every write renames a new file over
+/// the key's file, which changes its inode on Unix, and most likely its
+/// modification time and length elsewhere.
+#[derive(PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    inode: u64,
+}
+
+impl Stamp {
+    fn new(metadata: &Metadata) -> Self {
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            inode,
+        }
+    }
+}
+
+impl Watch {
+    fn snapshot(&self) -> MutexGuard<'_, Option<Snapshot>> {
+        self.snapshot.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Report the changes made since `snapshot`, and update it.
+    fn poll(&self, snapshot: &mut Snapshot) -> io::Result<()> {
+        let current = scan(&self.dir)?;
+        for ((bucket, key), file) in &current {
+            if snapshot
+                .get(&(bucket.clone(), key.clone()))
+                .map(|f| &f.stamp)
+                == Some(&file.stamp)
+            {
+                continue;
+            }
+            // A key deleted since the scan is reported at the next one.
+            let Some(contents) = read_if_exists(&file.path)? else {
+                continue;
+            };
+            let value = if file.hashed {
+                split_key(&contents).ok_or_else(|| corrupt(&file.path))?.1
+            } else {
+                &contents
+            };
+            self.notifier.notify(Change::Set {
+                bucket: bucket.clone(),
+                key: key.clone(),
+                value: value.to_vec(),
+            });
+        }
+        for (bucket, key) in snapshot.keys() {
+            if !current.contains_key(&(bucket.clone(), key.clone())) {
+                self.notifier.notify(Change::Delete {
+                    bucket: bucket.clone(),
+                    key: key.clone(),
+                });
+            }
+        }
+        *snapshot = current;
+        Ok(())
+    }
+
+    /// Poll for changes in a background thread until there are no more
+    /// subscriptions or the backend is dropped.
+    fn spawn_poller(watch: &Arc<Self>) {
+        let watch = Arc::downgrade(watch);
+        std::thread::spawn(move || {
+            while let Some(watch) = watch.upgrade() {
+                watch.wait_for_poll();
+                let mut snapshot = watch.snapshot();
+                if !watch.notifier.is_watched() {
+                    *snapshot = None;
+                    return;
+                }
+                if let Some(snapshot) = &mut *snapshot {
+                    // Errors, such as a file being replaced while it's read,
+                    // are retried at the next poll.
+                    let _ = watch.poll(snapshot);
+                }
+            }
+        });
+    }
+
+    fn wait_for_poll(&self) {
+        let poll_now = self.poll_now.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut poll_now, _) = self
+            .poll_now_changed
+            .wait_timeout_while(poll_now, POLL_INTERVAL, |poll_now| !*poll_now)
+            .unwrap_or_else(|e| e.into_inner());
+        *poll_now = false;
+    }
+
+    /// Have the background thread, if any, scan for changes right away.
+    fn poll_soon(&self) {
+        *self.poll_now.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.poll_now_changed.notify_all();
+    }
+}
+
+/// Find the key files of every bucket in `dir`.
+fn scan(dir: &Path) -> io::Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let bucket = match unescape("b-", &name) {
+            Some(bucket) => bucket,
+            None if is_hashed("b-", &name) => {
+                match read_if_exists(&entry.path().join(BUCKET_NAME_FILE))? {
+                    Some(bucket) => {
+                        String::from_utf8(bucket).map_err(|_| corrupt(&entry.path()))?
+                    }
+                    None => continue,
+                }
+            }
+            None => continue,
+        };
+        let entries = match fs::read_dir(entry.path()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let Some((key, hashed)) = key_of(&entry)? else {
+                continue;
+            };
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            snapshot.insert(
+                (bucket.clone(), key),
+                KeyFile {
+                    path: entry.path(),
+                    hashed,
+                    stamp: Stamp::new(&metadata),
+                },
+            );
+        }
+    }
+    Ok(snapshot)
+}
+
+/// The key whose file is `entry`, if it's the file of a key, and whether the
+/// file's name is hashed.
+fn key_of(entry: &DirEntry) -> io::Result<Option<(String, bool)>> {
+    let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+        return Ok(None);
+    };
+    if let Some(key) = unescape("k-", &name) {
+        return Ok(Some((key, false)));
+    }
+    if !is_hashed("k-", &name) {
+        return Ok(None);
+    }
+    let Some(contents) = read_if_exists(&entry.path())? else {
+        return Ok(None);
+    };
+    let (key, _) = split_key(&contents).ok_or_else(|| corrupt(&entry.path()))?;
+    Ok(Some((key.to_string(), true)))
+}
+
+/// Read the file at `path`, or return `None` if it doesn't exist.
+fn read_if_exists(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn corrupt(path: &Path) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("`{}` is corrupt", path.display()),
+    )
+}
+
+/// Make the creation, removal or renaming of files in `dir` durable.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    // Directories can't be opened as files on Windows, where metadata updates
+    // are durable once the operation returns.
+    if cfg!(unix) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// The longest file name used for a bucket or key, well below the limit of
+/// 255 bytes common to most file systems.
+const MAX_FILE_NAME_LEN: usize = 200;
+
+/// Separates the shortened name from the hash in file names of long names.
+/// This character is never part of an escaped name.
+const HASH_SEPARATOR: char = '~';
+
+/// Whether `name` is a hashed file name starting with `prefix`.
+fn is_hashed(prefix: &str, name: &str) -> bool {
+    name.starts_with(prefix) && name.contains(HASH_SEPARATOR)
+}
+
+/// The file name used for `name`, and whether it had to be hashed to keep it
+/// below `MAX_FILE_NAME_LEN`.
+///
+/// Hashed names keep the start of the escaped name for readability, followed
+/// by the SHA-256 of the full name. They can't be unescaped, so the name must
+/// be stored elsewhere if it's needed.
+fn file_name(prefix: &str, name: &str) -> (String, bool) {
+    let mut escaped = escape(prefix, name);
+    if escaped.len() <= MAX_FILE_NAME_LEN {
+        return (escaped, false);
+    }
+    // 64 hex digits for the hash, plus the separator.
+    escaped.truncate(MAX_FILE_NAME_LEN - 65);
+    escaped.push(HASH_SEPARATOR);
+    for b in Sha256::digest(name.as_bytes()) {
+        write!(escaped, "{b:02x}").unwrap();
+    }
+    (escaped, true)
+}
+
+/// Split the contents of the file of a key with a hashed name into the key
+/// and its value.
+fn split_key(contents: &[u8]) -> Option<(&str, &[u8])> {
+    let newline = contents.iter().position(|b| *b == b'\n')?;
+    let len = std::str::from_utf8(&contents[..newline])
+        .ok()?
+        .parse::<usize>()
+        .ok()?;
+    let rest = &contents[newline + 1..];
+    let key = std::str::from_utf8(rest.get(..len)?).ok()?;
+    Some((key, &rest[len..]))
+}
+
+/// Escape `name` as a file name starting with `prefix`.
+///
+/// Only lowercase ASCII letters, digits and `-` are kept as they are, so that
+/// escaped names are distinct on case-insensitive file systems and free of
+/// characters that some file systems treat specially. Every other byte is
+/// escaped as `%XX`.
+fn escape(prefix: &str, name: &str) -> String {
+    let mut escaped = String::from(prefix);
+    for b in name.bytes() {
+        match b {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' => escaped.push(char::from(b)),
+            _ => write!(escaped, "%{b:02x}").unwrap(),
+        }
+    }
+    escaped
+}
+
+/// The inverse of `escape`, or `None` if `escaped` is not the escaped form of
+/// a name with `prefix`.
+fn unescape(prefix: &str, escaped: &str) -> Option<String> {
+    let mut bytes = escaped.strip_prefix(prefix)?.bytes();
+    let mut name = Vec::new();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                name.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b'a'..=b'z' | b'0'..=b'9' | b'-' => name.push(b),
+            _ => return None,
+        }
+    }
+    String::from_utf8(name).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_FILE_NAME_LEN, escape, file_name, split_key, unescape};
+
+    #[test]
+    fn escape_roundtrip() {
+        for name in [
+            "",
+            "a",
+            "Hello",
+            "a/b",
+            "..",
+            "%41",
+            "ünïcödé",
+            "CON",
+            "a.b ",
+        ] {
+            let escaped = escape("k-", name);
+            assert!(
+                escaped[2..]
+                    .bytes()
+                    .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'-' | b'%')),
+                "{escaped}"
+            );
+            assert_eq!(unescape("k-", &escaped).as_deref(), Some(name));
+        }
+        assert_ne!(escape("k-", "A"), escape("k-", "a"));
+        assert_eq!(unescape("k-", ".tmp-1-2"), None);
+        assert_eq!(unescape("k-", "k-%4"), None);
+    }
+
+    #[test]
+    fn long_names_are_hashed() {
+        let short = "a".repeat(100);
+        assert_eq!(file_name("k-", &short), (escape("k-", &short), false));
+
+        let long = "a".repeat(300);
+        let (name, hashed) = file_name("k-", &long);
+        assert!(hashed);
+        assert_eq!(name.len(), MAX_FILE_NAME_LEN);
+        assert_eq!(unescape("k-", &name), None);
+        assert_ne!(file_name("k-", &"a".repeat(301)).0, name);
+        // Escaping can make short names too long too.
+        assert!(file_name("k-", &"/".repeat(100)).1);
+
+        assert_eq!(split_key(b"3\nkeyvalue"), Some(("key", &b"value"[..])));
+        assert_eq!(split_key(b"4\nkey"), None);
+        assert_eq!(split_key(b"key"), None);
+    }
+}
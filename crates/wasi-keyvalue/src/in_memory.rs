@@ -0,0 +1,139 @@
+use crate::{Change, ChangeNotifier, Error, KeyValueBackend, Subscription, increment_value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+
+/// A [`KeyValueBackend`] which keeps data in memory for as long as it's alive.
+///
+/// Only the default bucket, the one with the empty identifier, exists
+/// initially. Opening any other bucket fails with [`Error::NoSuchStore`] until
+/// it's created with [`InMemoryBackend::create_bucket`], or by setting a key in
+/// it directly through the backend.
+///
+/// Every change to the data is reported to the backend's subscriptions.
+pub struct InMemoryBackend {
+    buckets: Mutex<HashMap<String, BTreeMap<String, Vec<u8>>>>,
+    notifier: ChangeNotifier,
+}
+
+impl Default for InMemoryBackend {
+    fn default() -> Self {
+        Self::with_data::<_, String, Vec<u8>>([])
+    }
+}
+
+impl InMemoryBackend {
+    /// Creates a new backend with an empty default bucket.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new backend whose default bucket, the one with the empty
+    /// identifier, holds `data`.
+    pub fn with_data<I, K, V>(data: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<Vec<u8>>,
+    {
+        let bucket = data
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        Self {
+            buckets: Mutex::new(HashMap::from([(String::new(), bucket)])),
+            notifier: ChangeNotifier::new(),
+        }
+    }
+
+    /// Create the bucket `identifier`, so that it can be opened, if it doesn't
+    /// exist yet.
+    pub fn create_bucket(&self, identifier: impl Into<String>) {
+        self.buckets().entry(identifier.into()).or_default();
+    }
+
+    fn buckets(&self) -> MutexGuard<'_, HashMap<String, BTreeMap<String, Vec<u8>>>> {
+        // A panic while the lock is held can't leave a bucket half-updated, so
+        // ignore poisoning.
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl KeyValueBackend for InMemoryBackend {
+    fn open(&self, bucket: &str) -> Result<(), Error> {
+        if self.buckets().contains_key(bucket) {
+            Ok(())
+        } else {
+            Err(Error::NoSuchStore)
+        }
+    }
+
+    fn get(&self, bucket: &str, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .buckets()
+            .get(bucket)
+            .and_then(|bucket| bucket.get(key))
+            .cloned())
+    }
+
+    fn set(&self, bucket: &str, key: &str, value: Vec<u8>) -> Result<(), Error> {
+        // Changes are reported while the lock is held, so that they're
+        // reported in the order they're made.
+        let mut buckets = self.buckets();
+        if self.notifier.is_watched() {
+            self.notifier.notify(Change::Set {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                value: value.clone(),
+            });
+        }
+        buckets
+            .entry(bucket.to_string())
+            .or_default()
+            .insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn delete(&self, bucket_name: &str, key: &str) -> Result<(), Error> {
+        let mut buckets = self.buckets();
+        let Some(bucket) = buckets.get_mut(bucket_name) else {
+            return Ok(());
+        };
+        if bucket.remove(key).is_some() {
+            self.notifier.notify(Change::Delete {
+                bucket: bucket_name.to_string(),
+                key: key.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn list_keys(&self, bucket: &str) -> Result<Vec<String>, Error> {
+        Ok(self
+            .buckets()
+            .get(bucket)
+            .map(|bucket| bucket.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn increment(&self, bucket_name: &str, key: &str, delta: u64) -> Result<u64, Error> {
+        let mut buckets = self.buckets();
+        let bucket = buckets.entry(bucket_name.to_string()).or_default();
+        let (new, value) = increment_value(bucket.get(key).map(|v| &v[..]), delta)?;
+        if self.notifier.is_watched() {
+            self.notifier.notify(Change::Set {
+                bucket: bucket_name.to_string(),
+                key: key.to_string(),
+                value: value.clone(),
+            });
+        }
+        bucket.insert(key.to_string(), value);
+        Ok(new)
+    }
+
+    fn subscribe(&self, capacity: usize) -> Result<Subscription, Error> {
+        // Changes are reported with the lock held too, so exactly the changes
+        // made after this are reported.
+        let _buckets = self.buckets();
+        Ok(self.notifier.subscribe(capacity))
+    }
+}
@@ -4,8 +4,19 @@
 //! API. With this crate, the runtime can run components that call APIs in
 //! [wasi-keyvalue] and provide components with access to key-value storages.
 //!
-//! Currently supported storage backends:
-//! * In-Memory (empty identifier)
+//! Storage is provided by a [`KeyValueBackend`], which is shared by every
+//! bucket opened through a [`WasiKeyValueCtx`] and by every clone of it. This
+//! crate provides two backends:
+//! * [`InMemoryBackend`], which keeps data in memory for the lifetime of the
+//!   process (the default)
+//! * [`FileBackend`], which persists data in a directory on disk
+//!
+//! Buckets are identified by the string passed to `wasi:keyvalue/store.open`,
+//! where the empty string names the default bucket.
+//!
+//! Changes to the data of a backend, whoever makes them, can be observed with
+//! [`KeyValueBackend::subscribe`], and delivered to components exporting
+//! `wasi:keyvalue/watcher` with [`WatchService::watch`].
 //!
 //! # Examples
 //!
@@ -71,6 +82,22 @@ mod generated {
     wasmtime::component::bindgen!({
         path: "wit",
         world: "wasi:keyvalue/imports",
+        // Every function which calls into the backend is `async`, so that
+        // backends which block can be run on a thread for blocking work.
+        async: {
+            only_imports: [
+                "open",
+                "[method]bucket.get",
+                "[method]bucket.set",
+                "[method]bucket.delete",
+                "[method]bucket.exists",
+                "[method]bucket.list-keys",
+                "increment",
+                "get-many",
+                "set-many",
+                "delete-many",
+            ],
+        },
         trappable_imports: true,
         with: {
             "wasi:keyvalue/store/bucket": crate::Bucket,
@@ -81,33 +108,182 @@ mod generated {
     });
 }
 
+mod file;
+mod in_memory;
+mod watch;
+
+pub use self::file::FileBackend;
+pub use self::in_memory::InMemoryBackend;
+pub use self::watch::{
+    Change, ChangeNotifier, Lagged, Subscription, WatchService, WatchServicePre,
+};
+
 use self::generated::wasi::keyvalue;
 use anyhow::Result;
 use std::collections::HashMap;
+use std::sync::Arc;
 use wasmtime::component::{HasData, Resource, ResourceTable, ResourceTableError};
 
-#[doc(hidden)]
+/// An error returned by a [`KeyValueBackend`], reported to the guest as a
+/// `wasi:keyvalue/store.error`.
+#[derive(Debug)]
 pub enum Error {
+    /// The requested bucket does not exist.
     NoSuchStore,
+    /// The guest is not allowed to access the requested bucket.
     AccessDenied,
+    /// Some other error, described by the message.
     Other(String),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSuchStore => f.write_str("no such store"),
+            Self::AccessDenied => f.write_str("access denied"),
+            Self::Other(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<ResourceTableError> for Error {
     fn from(err: ResourceTableError) -> Self {
         Self::Other(err.to_string())
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => Self::AccessDenied,
+            _ => Self::Other(err.to_string()),
+        }
+    }
+}
+
+/// A storage backend for the buckets of `wasi-keyvalue`.
+///
+/// Every operation is given the identifier of the bucket it applies to, as
+/// passed to `wasi:keyvalue/store.open`. Backends are shared between all the
+/// stores using a [`WasiKeyValueCtx`], so writes through one bucket are
+/// visible to every other bucket with the same identifier.
+pub trait KeyValueBackend: Send + Sync {
+    /// Check that the bucket `bucket` exists, or can be created, and may be
+    /// accessed.
+    fn open(&self, bucket: &str) -> Result<(), Error>;
+
+    /// Get the value of `key`, if any.
+    fn get(&self, bucket: &str, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Set the value of `key`, replacing any existing value.
+    fn set(&self, bucket: &str, key: &str, value: Vec<u8>) -> Result<(), Error>;
+
+    /// Delete `key` and its value, if any.
+    fn delete(&self, bucket: &str, key: &str) -> Result<(), Error>;
+
+    /// Check whether `key` has a value.
+    fn exists(&self, bucket: &str, key: &str) -> Result<bool, Error> {
+        Ok(self.get(bucket, key)?.is_some())
+    }
+
+    /// List all keys with a value, in a stable order.
+    fn list_keys(&self, bucket: &str) -> Result<Vec<String>, Error>;
+
+    /// Atomically add `delta` to the value of `key`, treating a missing value
+    /// as zero, and return the new value.
+    ///
+    /// Values are stored as decimal strings; see [`increment_value`].
+    fn increment(&self, bucket: &str, key: &str, delta: u64) -> Result<u64, Error>;
+
+    /// Get the values of `keys`, in the same order.
+    fn get_many(
+        &self,
+        bucket: &str,
+        keys: Vec<String>,
+    ) -> Result<Vec<Option<(String, Vec<u8>)>>, Error> {
+        keys.into_iter()
+            .map(|key| Ok(self.get(bucket, &key)?.map(|value| (key, value))))
+            .collect()
+    }
+
+    /// Set the values of multiple keys.
+    ///
+    /// This need not be atomic: on error, some of the values may have been
+    /// set.
+    fn set_many(&self, bucket: &str, key_values: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+        for (key, value) in key_values {
+            self.set(bucket, &key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Delete multiple keys.
+    ///
+    /// This need not be atomic: on error, some of the keys may have been
+    /// deleted.
+    fn delete_many(&self, bucket: &str, keys: Vec<String>) -> Result<(), Error> {
+        for key in keys {
+            self.delete(bucket, &key)?;
+        }
+        Ok(())
+    }
+
+    /// Whether operations block the calling thread, for example on file I/O.
+    ///
+    /// Operations of blocking backends are run on a thread for blocking work,
+    /// rather than on the async executor running the guest. By default this
+    /// is false.
+    fn is_blocking(&self) -> bool {
+        false
+    }
+
+    /// Subscribe to the changes made to the keys of every bucket from now on,
+    /// buffering up to `capacity` changes which haven't been received yet.
+    ///
+    /// Every change to the data is reported, whether it's made by a guest,
+    /// through the backend directly, or by anything else sharing the backend's
+    /// storage. [`ChangeNotifier`] helps implement this.
+    ///
+    /// By default this fails, for backends which can't observe changes.
+    fn subscribe(&self, capacity: usize) -> Result<Subscription, Error> {
+        let _ = capacity;
+        Err(Error::Other(
+            "this key-value backend does not support subscriptions".to_string(),
+        ))
+    }
+}
+
+/// Add `delta` to `value`, the current value of a key used as a counter, and
+/// return the new count along with its encoding as a value.
+///
+/// Counters are stored as decimal strings, and a missing value counts as zero.
+/// This is a helper for implementations of [`KeyValueBackend::increment`].
+pub fn increment_value(value: Option<&[u8]>, delta: u64) -> Result<(u64, Vec<u8>), Error> {
+    let current = match value {
+        Some(value) => std::str::from_utf8(value)
+            .map_err(|e| Error::Other(e.to_string()))?
+            .parse::<u64>()
+            .map_err(|e| Error::Other(e.to_string()))?,
+        None => 0,
+    };
+    let new = current
+        .checked_add(delta)
+        .ok_or_else(|| Error::Other("increment overflows a u64".to_string()))?;
+    Ok((new, new.to_string().into_bytes()))
+}
+
 #[doc(hidden)]
 pub struct Bucket {
-    in_memory_data: HashMap<String, Vec<u8>>,
+    name: String,
 }
 
 /// Builder-style structure used to create a [`WasiKeyValueCtx`].
 #[derive(Default)]
 pub struct WasiKeyValueCtxBuilder {
     in_memory_data: HashMap<String, Vec<u8>>,
+    backend: Option<Arc<dyn KeyValueBackend>>,
 }
 
 impl WasiKeyValueCtxBuilder {
@@ -116,7 +292,9 @@ impl WasiKeyValueCtxBuilder {
         Default::default()
     }
 
-    /// Preset data for the In-Memory provider.
+    /// Preset data for the default bucket of the [`InMemoryBackend`].
+    ///
+    /// This has no effect if a [`backend`](Self::backend) is configured.
     pub fn in_memory_data<I, K, V>(mut self, data: I) -> Self
     where
         I: IntoIterator<Item = (K, V)>,
@@ -130,17 +308,30 @@ impl WasiKeyValueCtxBuilder {
         self
     }
 
+    /// Use `backend` to store data, instead of a new [`InMemoryBackend`].
+    ///
+    /// The same backend may be shared by multiple contexts, for example to
+    /// share state between instances.
+    pub fn backend(mut self, backend: Arc<dyn KeyValueBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
     /// Uses the configured context so far to construct the final [`WasiKeyValueCtx`].
     pub fn build(self) -> WasiKeyValueCtx {
-        WasiKeyValueCtx {
-            in_memory_data: self.in_memory_data,
-        }
+        let backend = self
+            .backend
+            .unwrap_or_else(|| Arc::new(InMemoryBackend::with_data(self.in_memory_data)));
+        WasiKeyValueCtx { backend }
     }
 }
 
 /// Capture the state necessary for use in the `wasi-keyvalue` API implementation.
+///
+/// Clones of a context share its backend.
+#[derive(Clone)]
 pub struct WasiKeyValueCtx {
-    in_memory_data: HashMap<String, Vec<u8>>,
+    backend: Arc<dyn KeyValueBackend>,
 }
 
 impl WasiKeyValueCtx {
@@ -148,6 +339,32 @@ impl WasiKeyValueCtx {
     pub fn builder() -> WasiKeyValueCtxBuilder {
         WasiKeyValueCtxBuilder::new()
     }
+
+    /// Subscribe to the changes made to the data of this context's backend,
+    /// buffering up to `capacity` changes; see
+    /// [`KeyValueBackend::subscribe`].
+    ///
+    /// Use [`WatchService::watch`] to deliver the changes to a component
+    /// exporting `wasi:keyvalue/watcher`.
+    pub fn subscribe(&self, capacity: usize) -> Result<Subscription, Error> {
+        self.backend.subscribe(capacity)
+    }
+
+    /// Run `f` with the backend and the identifier of a bucket.
+    ///
+    /// Operations of backends which block run on a thread for blocking work,
+    /// so that they don't stall the async executor.
+    async fn run<R, F>(&self, bucket: String, f: F) -> Result<R, Error>
+    where
+        R: Send + 'static,
+        F: FnOnce(&dyn KeyValueBackend, &str) -> Result<R, Error> + Send + 'static,
+    {
+        if !self.backend.is_blocking() {
+            return f(&*self.backend, &bucket);
+        }
+        let backend = self.backend.clone();
+        wasmtime_wasi::runtime::spawn_blocking(move || f(&*backend, &bucket)).await
+    }
 }
 
 /// A wrapper capturing the needed internal `wasi-keyvalue` state.
@@ -164,13 +381,12 @@ impl<'a> WasiKeyValue<'a> {
 }
 
 impl keyvalue::store::Host for WasiKeyValue<'_> {
-    fn open(&mut self, identifier: String) -> Result<Resource<Bucket>, Error> {
-        match identifier.as_str() {
-            "" => Ok(self.table.push(Bucket {
-                in_memory_data: self.ctx.in_memory_data.clone(),
-            })?),
-            _ => Err(Error::NoSuchStore),
-        }
+    async fn open(&mut self, identifier: String) -> Result<Resource<Bucket>, Error> {
+        let name = identifier.clone();
+        self.ctx
+            .run(name, |backend, bucket| backend.open(bucket))
+            .await?;
+        Ok(self.table.push(Bucket { name: identifier })?)
     }
 
     fn convert_error(&mut self, err: Error) -> Result<keyvalue::store::Error> {
@@ -182,40 +398,66 @@ impl keyvalue::store::Host for WasiKeyValue<'_> {
     }
 }
 
+impl WasiKeyValue<'_> {
+    /// Get the identifier of `bucket`.
+    fn bucket(&self, bucket: &Resource<Bucket>) -> Result<String, Error> {
+        Ok(self.table.get(bucket)?.name.clone())
+    }
+}
+
 impl keyvalue::store::HostBucket for WasiKeyValue<'_> {
-    fn get(&mut self, bucket: Resource<Bucket>, key: String) -> Result<Option<Vec<u8>>, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        Ok(bucket.in_memory_data.get(&key).cloned())
+    async fn get(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let bucket = self.bucket(&bucket)?;
+        self.ctx
+            .run(bucket, move |backend, bucket| backend.get(bucket, &key))
+            .await
     }
 
-    fn set(&mut self, bucket: Resource<Bucket>, key: String, value: Vec<u8>) -> Result<(), Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        bucket.in_memory_data.insert(key, value);
-        Ok(())
+    async fn set(
+        &mut self,
+        bucket: Resource<Bucket>,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<(), Error> {
+        let bucket = self.bucket(&bucket)?;
+        self.ctx
+            .run(bucket, move |backend, bucket| {
+                backend.set(bucket, &key, value)
+            })
+            .await
     }
 
-    fn delete(&mut self, bucket: Resource<Bucket>, key: String) -> Result<(), Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        bucket.in_memory_data.remove(&key);
-        Ok(())
+    async fn delete(&mut self, bucket: Resource<Bucket>, key: String) -> Result<(), Error> {
+        let bucket = self.bucket(&bucket)?;
+        self.ctx
+            .run(bucket, move |backend, bucket| backend.delete(bucket, &key))
+            .await
     }
 
-    fn exists(&mut self, bucket: Resource<Bucket>, key: String) -> Result<bool, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        Ok(bucket.in_memory_data.contains_key(&key))
+    async fn exists(&mut self, bucket: Resource<Bucket>, key: String) -> Result<bool, Error> {
+        let bucket = self.bucket(&bucket)?;
+        self.ctx
+            .run(bucket, move |backend, bucket| backend.exists(bucket, &key))
+            .await
     }
 
-    fn list_keys(
+    async fn list_keys(
         &mut self,
         bucket: Resource<Bucket>,
         cursor: Option<u64>,
     ) -> Result<keyvalue::store::KeyResponse, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        let keys: Vec<String> = bucket.in_memory_data.keys().cloned().collect();
-        let cursor = cursor.unwrap_or(0) as usize;
-        let keys_slice = &keys[cursor..];
+        let bucket = self.bucket(&bucket)?;
+        let keys = self
+            .ctx
+            .run(bucket, |backend, bucket| backend.list_keys(bucket))
+            .await?;
+        let cursor = usize::try_from(cursor.unwrap_or(0)).unwrap_or(usize::MAX);
         Ok(keyvalue::store::KeyResponse {
-            keys: keys_slice.to_vec(),
+            keys: keys.get(cursor..).unwrap_or_default().to_vec(),
             cursor: None,
         })
     }
@@ -227,67 +469,66 @@ impl keyvalue::store::HostBucket for WasiKeyValue<'_> {
 }
 
 impl keyvalue::atomics::Host for WasiKeyValue<'_> {
-    fn increment(
+    async fn increment(
         &mut self,
         bucket: Resource<Bucket>,
         key: String,
         delta: u64,
     ) -> Result<u64, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        let value = bucket
-            .in_memory_data
-            .entry(key.clone())
-            .or_insert("0".to_string().into_bytes());
-        let current_value = String::from_utf8(value.clone())
-            .map_err(|e| Error::Other(e.to_string()))?
-            .parse::<u64>()
-            .map_err(|e| Error::Other(e.to_string()))?;
-        let new_value = current_value + delta;
-        *value = new_value.to_string().into_bytes();
-        Ok(new_value)
+        let bucket = self.bucket(&bucket)?;
+        self.ctx
+            .run(bucket, move |backend, bucket| {
+                backend.increment(bucket, &key, delta)
+            })
+            .await
     }
 }
 
 impl keyvalue::batch::Host for WasiKeyValue<'_> {
-    fn get_many(
+    async fn get_many(
         &mut self,
         bucket: Resource<Bucket>,
         keys: Vec<String>,
     ) -> Result<Vec<Option<(String, Vec<u8>)>>, Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        Ok(keys
-            .into_iter()
-            .map(|key| {
-                bucket
-                    .in_memory_data
-                    .get(&key)
-                    .map(|value| (key.clone(), value.clone()))
+        let bucket = self.bucket(&bucket)?;
+        self.ctx
+            .run(bucket, move |backend, bucket| {
+                backend.get_many(bucket, keys)
             })
-            .collect())
+            .await
     }
 
-    fn set_many(
+    async fn set_many(
         &mut self,
         bucket: Resource<Bucket>,
         key_values: Vec<(String, Vec<u8>)>,
     ) -> Result<(), Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        for (key, value) in key_values {
-            bucket.in_memory_data.insert(key, value);
-        }
-        Ok(())
+        let bucket = self.bucket(&bucket)?;
+        self.ctx
+            .run(bucket, move |backend, bucket| {
+                backend.set_many(bucket, key_values)
+            })
+            .await
     }
 
-    fn delete_many(&mut self, bucket: Resource<Bucket>, keys: Vec<String>) -> Result<(), Error> {
-        let bucket = self.table.get_mut(&bucket)?;
-        for key in keys {
-            bucket.in_memory_data.remove(&key);
-        }
-        Ok(())
+    async fn delete_many(
+        &mut self,
+        bucket: Resource<Bucket>,
+        keys: Vec<String>,
+    ) -> Result<(), Error> {
+        let bucket = self.bucket(&bucket)?;
+        self.ctx
+            .run(bucket, move |backend, bucket| {
+                backend.delete_many(bucket, keys)
+            })
+            .await
     }
 }
 
 /// Add all the `wasi-keyvalue` world's interfaces to a [`wasmtime::component::Linker`].
+///
+/// The functions are `async`, so the linker's [`wasmtime::Config`] must have
+/// [`async_support`](wasmtime::Config::async_support) enabled.
 pub fn add_to_linker<T: Send + 'static>(
     l: &mut wasmtime::component::Linker<T>,
     f: fn(&mut T) -> WasiKeyValue<'_>,
@@ -0,0 +1,285 @@
+//! Host support for components exporting `wasi:keyvalue/watcher`.
+
+use crate::{Bucket, WasiKeyValue};
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::future;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Poll, Waker};
+use wasmtime::AsContextMut;
+
+#[expect(missing_docs, reason = "bindgen-generated code")]
+mod generated {
+    wasmtime::component::bindgen!({
+        path: "wit",
+        world: "wasi:keyvalue/watch-service",
+        // Flag this as "possibly async" which will cause the exports to be
+        // generated as async, but none of the imports here are async.
+        async: {
+            only_imports: ["nonexistent"],
+        },
+        trappable_imports: true,
+        with: {
+            "wasi:keyvalue/store": crate::generated::wasi::keyvalue::store,
+            "wasi:keyvalue/atomics": crate::generated::wasi::keyvalue::atomics,
+            "wasi:keyvalue/batch": crate::generated::wasi::keyvalue::batch,
+        },
+    });
+}
+
+/// Bindings to the `wasi:keyvalue/watch-service` world.
+pub use self::generated::{WatchService, WatchServicePre};
+
+/// A change made to a key of a bucket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// `key` was set to `value`.
+    Set {
+        /// The identifier of the bucket.
+        bucket: String,
+        /// The key which was set.
+        key: String,
+        /// The new value of the key.
+        value: Vec<u8>,
+    },
+    /// `key` was deleted.
+    Delete {
+        /// The identifier of the bucket.
+        bucket: String,
+        /// The key which was deleted.
+        key: String,
+    },
+}
+
+impl Change {
+    /// The identifier of the bucket which changed.
+    pub fn bucket(&self) -> &str {
+        match self {
+            Self::Set { bucket, .. } | Self::Delete { bucket, .. } => bucket,
+        }
+    }
+
+    /// The key which changed.
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Set { key, .. } | Self::Delete { key, .. } => key,
+        }
+    }
+}
+
+/// Delivers the changes made to a [`KeyValueBackend`] to its subscriptions.
+///
+/// This is a helper for implementations of [`KeyValueBackend::subscribe`]:
+/// backends hand out subscriptions with [`ChangeNotifier::subscribe`], and
+/// call [`ChangeNotifier::notify`] for every change, in the order the changes
+/// are made. Subscriptions end once the notifier is dropped.
+///
+/// [`KeyValueBackend`]: crate::KeyValueBackend
+/// [`KeyValueBackend::subscribe`]: crate::KeyValueBackend::subscribe
+#[derive(Default)]
+pub struct ChangeNotifier {
+    queues: Mutex<Vec<Weak<Queue>>>,
+}
+
+impl ChangeNotifier {
+    /// Creates a notifier without any subscriptions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn queues(&self) -> MutexGuard<'_, Vec<Weak<Queue>>> {
+        self.queues.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Create a subscription to the changes reported from now on, buffering
+    /// up to `capacity` changes which haven't been received yet.
+    pub fn subscribe(&self, capacity: usize) -> Subscription {
+        let queue = Arc::new(Queue {
+            state: Mutex::new(QueueState {
+                changes: VecDeque::new(),
+                capacity: capacity.max(1),
+                missed: 0,
+                waker: None,
+                closed: false,
+            }),
+        });
+        let mut queues = self.queues();
+        queues.retain(|queue| queue.strong_count() > 0);
+        queues.push(Arc::downgrade(&queue));
+        Subscription { queue }
+    }
+
+    /// Whether there are any subscriptions, so that changes need not be built
+    /// when nobody is watching.
+    pub fn is_watched(&self) -> bool {
+        self.queues().iter().any(|queue| queue.strong_count() > 0)
+    }
+
+    /// Report `change` to every subscription.
+    ///
+    /// If a subscription's buffer is full, its oldest change is dropped to
+    /// make room, and it's reported as [`Lagged`] instead.
+    pub fn notify(&self, change: Change) {
+        for queue in self.queues().iter().filter_map(Weak::upgrade) {
+            let mut state = queue.state();
+            if state.changes.len() == state.capacity {
+                state.changes.pop_front();
+                state.missed += 1;
+            }
+            state.changes.push_back(change.clone());
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Drop for ChangeNotifier {
+    fn drop(&mut self) {
+        for queue in self.queues().iter().filter_map(Weak::upgrade) {
+            let mut state = queue.state();
+            state.closed = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+struct Queue {
+    state: Mutex<QueueState>,
+}
+
+struct QueueState {
+    changes: VecDeque<Change>,
+    capacity: usize,
+    /// The number of changes dropped since the subscription last reported
+    /// [`Lagged`].
+    missed: u64,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+impl Queue {
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl QueueState {
+    fn pop(&mut self) -> Option<Result<Change, Lagged>> {
+        if self.missed > 0 {
+            return Some(Err(Lagged(std::mem::take(&mut self.missed))));
+        }
+        self.changes.pop_front().map(Ok)
+    }
+}
+
+/// Returned by a [`Subscription`] which missed changes because they were made
+/// faster than they were received, filling its buffer.
+///
+/// The number of changes missed is given, all of which were made before the
+/// changes received next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+impl std::fmt::Display for Lagged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "missed {} key-value changes because the subscription's buffer was full",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for Lagged {}
+
+/// A stream of the changes made to a [`KeyValueBackend`], created with
+/// [`KeyValueBackend::subscribe`] or [`WasiKeyValueCtx::subscribe`].
+///
+/// Changes are buffered until they are received, in the order they were made,
+/// up to the capacity given when subscribing.
+///
+/// [`KeyValueBackend`]: crate::KeyValueBackend
+/// [`KeyValueBackend::subscribe`]: crate::KeyValueBackend::subscribe
+/// [`WasiKeyValueCtx::subscribe`]: crate::WasiKeyValueCtx::subscribe
+pub struct Subscription {
+    queue: Arc<Queue>,
+}
+
+impl Subscription {
+    /// Receive the next change, waiting for one to be made.
+    ///
+    /// Returns [`Lagged`] if changes were dropped since the last call because
+    /// the buffer was full, and `None` once all buffered changes have been
+    /// received and the backend has been dropped.
+    pub async fn next(&mut self) -> Option<Result<Change, Lagged>> {
+        future::poll_fn(|cx| {
+            let mut state = self.queue.state();
+            match state.pop() {
+                Some(change) => Poll::Ready(Some(change)),
+                None if state.closed => Poll::Ready(None),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Receive the next change if one has already been made.
+    pub fn try_next(&mut self) -> Option<Result<Change, Lagged>> {
+        self.queue.state().pop()
+    }
+}
+
+impl WatchService {
+    /// Call the `on-set` or `on-delete` export for `change`.
+    ///
+    /// The bucket passed to the export is created in the resource table of
+    /// the store's [`WasiKeyValue`], so `view` should be the same function
+    /// given to [`add_to_linker`](crate::add_to_linker).
+    pub async fn call_on_change<T: Send + 'static>(
+        &self,
+        mut store: impl AsContextMut<Data = T>,
+        view: fn(&mut T) -> WasiKeyValue<'_>,
+        change: &Change,
+    ) -> Result<()> {
+        let mut store = store.as_context_mut();
+        let bucket = view(store.data_mut()).table.push(Bucket {
+            name: change.bucket().to_string(),
+        })?;
+        let watcher = self.wasi_keyvalue_watcher();
+        match change {
+            Change::Set { key, value, .. } => {
+                watcher.call_on_set(&mut store, bucket, key, value).await
+            }
+            Change::Delete { key, .. } => watcher.call_on_delete(&mut store, bucket, key).await,
+        }
+    }
+
+    /// Deliver every change received from `subscription` to the component,
+    /// until the subscription ends, an export traps, or changes are missed.
+    ///
+    /// Changes are delivered one at a time, in order. Changes made by the
+    /// watcher itself are delivered to it too, so it must take care not to
+    /// react to them with more changes indefinitely.
+    ///
+    /// If the component falls behind so that the subscription's buffer fills
+    /// up, this returns the [`Lagged`] error rather than skip changes
+    /// silently. The caller may then resynchronize the component's state and
+    /// watch again.
+    pub async fn watch<T: Send + 'static>(
+        &self,
+        mut store: impl AsContextMut<Data = T>,
+        view: fn(&mut T) -> WasiKeyValue<'_>,
+        subscription: &mut Subscription,
+    ) -> Result<()> {
+        while let Some(change) = subscription.next().await {
+            self.call_on_change(&mut store, view, &change?).await?;
+        }
+        Ok(())
+    }
+}
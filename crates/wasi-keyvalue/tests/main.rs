@@ -1,11 +1,18 @@
 use anyhow::{Result, anyhow};
-use test_programs_artifacts::{KEYVALUE_MAIN_COMPONENT, foreach_keyvalue};
+use std::sync::Arc;
+use std::time::Duration;
+use test_programs_artifacts::{
+    KEYVALUE_MAIN_COMPONENT, KEYVALUE_WATCHER_COMPONENT, foreach_keyvalue,
+};
 use wasmtime::{
     Store,
     component::{Component, Linker, ResourceTable},
 };
 use wasmtime_wasi::p2::{IoView, WasiCtx, WasiCtxBuilder, WasiView, bindings::Command};
-use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx, WasiKeyValueCtxBuilder};
+use wasmtime_wasi_keyvalue::{
+    Change, Error, FileBackend, InMemoryBackend, KeyValueBackend, Lagged, Subscription,
+    WasiKeyValue, WasiKeyValueCtx, WasiKeyValueCtxBuilder, WatchService,
+};
 
 struct Ctx {
     table: ResourceTable,
@@ -24,19 +31,35 @@ impl WasiView for Ctx {
     }
 }
 
-async fn run_wasi(path: &str, ctx: Ctx) -> Result<()> {
+impl Ctx {
+    fn new(wasi_keyvalue_ctx: WasiKeyValueCtx) -> Self {
+        Self {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().inherit_stderr().build(),
+            wasi_keyvalue_ctx,
+        }
+    }
+
+    fn keyvalue(&mut self) -> WasiKeyValue<'_> {
+        WasiKeyValue::new(&self.wasi_keyvalue_ctx, &mut self.table)
+    }
+}
+
+fn setup(path: &str, ctx: Ctx) -> Result<(Store<Ctx>, Component, Linker<Ctx>)> {
     let engine = test_programs_artifacts::engine(|config| {
         config.async_support(true);
     });
-    let mut store = Store::new(&engine, ctx);
+    let store = Store::new(&engine, ctx);
     let component = Component::from_file(&engine, path)?;
 
     let mut linker = Linker::new(&engine);
     wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
-    wasmtime_wasi_keyvalue::add_to_linker(&mut linker, |h: &mut Ctx| {
-        WasiKeyValue::new(&h.wasi_keyvalue_ctx, &mut h.table)
-    })?;
+    wasmtime_wasi_keyvalue::add_to_linker(&mut linker, Ctx::keyvalue)?;
+    Ok((store, component, linker))
+}
 
+async fn run_wasi(path: &str, ctx: Ctx) -> Result<()> {
+    let (mut store, component, linker) = setup(path, ctx)?;
     let command = Command::instantiate_async(&mut store, &component, &linker).await?;
     command
         .wasi_cli_run()
@@ -68,3 +91,209 @@ async fn keyvalue_main() -> Result<()> {
     )
     .await
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keyvalue_main_file_backend() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let backend = Arc::new(FileBackend::new(dir.path())?);
+    backend.set("", "atomics_key", b"5".to_vec())?;
+    run_wasi(
+        KEYVALUE_MAIN_COMPONENT,
+        Ctx {
+            table: ResourceTable::new(),
+            wasi_ctx: WasiCtxBuilder::new().inherit_stderr().build(),
+            wasi_keyvalue_ctx: WasiKeyValueCtxBuilder::new().backend(backend).build(),
+        },
+    )
+    .await?;
+
+    // The data written by the guest is still there after reopening the
+    // directory.
+    let backend = FileBackend::new(dir.path())?;
+    assert_eq!(backend.get("", "atomics_key")?, Some(b"6".to_vec()));
+    assert_eq!(backend.list_keys("")?, ["atomics_key", "b1"]);
+    Ok(())
+}
+
+#[test]
+fn backends_share_state() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let backends: [Arc<dyn KeyValueBackend>; 2] = [
+        Arc::new(InMemoryBackend::new()),
+        Arc::new(FileBackend::new(dir.path())?),
+    ];
+    for backend in backends {
+        let a = WasiKeyValueCtxBuilder::new()
+            .backend(backend.clone())
+            .build();
+        let b = a.clone();
+        drop(a);
+
+        backend.set("bucket/with:odd names", "Key", b"upper".to_vec())?;
+        backend.set("bucket/with:odd names", "key", b"lower".to_vec())?;
+        backend.open("bucket/with:odd names")?;
+        assert_eq!(backend.increment("", "n", 2)?, 2);
+        assert_eq!(backend.increment("", "n", 3)?, 5);
+        assert!(backend.increment("", "n", u64::MAX).is_err());
+        assert_eq!(backend.list_keys("")?, ["n"]);
+        assert_eq!(backend.list_keys("bucket/with:odd names")?, ["Key", "key"]);
+        assert_eq!(
+            backend.get("bucket/with:odd names", "Key")?,
+            Some(b"upper".to_vec())
+        );
+        backend.delete("bucket/with:odd names", "Key")?;
+        backend.delete("bucket/with:odd names", "missing")?;
+        assert!(!backend.exists("bucket/with:odd names", "Key")?);
+        assert!(backend.exists("bucket/with:odd names", "key")?);
+
+        // Keys too long for a file name are stored all the same.
+        let long = "Long/".repeat(1000);
+        backend.set("", &long, b"long".to_vec())?;
+        assert_eq!(backend.get("", &long)?, Some(b"long".to_vec()));
+        assert!(backend.exists("", &long)?);
+        assert_eq!(backend.list_keys("")?, [&long[..], "n"]);
+        backend.delete("", &long)?;
+        assert_eq!(backend.get("", &long)?, None);
+        drop(b);
+    }
+    Ok(())
+}
+
+#[test]
+fn in_memory_buckets_must_exist() -> Result<()> {
+    let backend = InMemoryBackend::new();
+    backend.open("")?;
+    assert!(matches!(backend.open("other"), Err(Error::NoSuchStore)));
+    backend.create_bucket("other");
+    backend.open("other")?;
+    Ok(())
+}
+
+#[test]
+fn file_buckets_must_exist() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let backend = FileBackend::new(dir.path())?;
+    backend.open("")?;
+    assert!(matches!(backend.open("other"), Err(Error::NoSuchStore)));
+    backend.create_bucket("other")?;
+    backend.open("other")?;
+    assert!(FileBackend::new(dir.path())?.open("other").is_ok());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keyvalue_watcher() -> Result<()> {
+    let backend = Arc::new(InMemoryBackend::with_data([("atomics_key", "5")]));
+    let ctx = WasiKeyValueCtxBuilder::new()
+        .backend(backend.clone())
+        .build();
+    let mut subscription = ctx.subscribe(64)?;
+
+    run_wasi(KEYVALUE_MAIN_COMPONENT, Ctx::new(ctx.clone())).await?;
+    assert_eq!(
+        subscription.try_next(),
+        Some(Ok(Change::Set {
+            bucket: String::new(),
+            key: "atomics_key".to_string(),
+            value: b"6".to_vec(),
+        }))
+    );
+
+    let (mut store, component, linker) = setup(KEYVALUE_WATCHER_COMPONENT, Ctx::new(ctx))?;
+    let watcher = WatchService::instantiate_async(&mut store, &component, &linker).await?;
+    // The watcher records each of the remaining seven changes, and its own
+    // changes are delivered too, and ignored by it.
+    let mut delivered = 0;
+    while let Some(change) = subscription.try_next() {
+        watcher
+            .call_on_change(&mut store, Ctx::keyvalue, &change?)
+            .await?;
+        delivered += 1;
+    }
+    assert_eq!(delivered, 14);
+
+    let get = |key: &str| backend.get("", key).unwrap();
+    assert_eq!(get("seen:hello"), Some(b"<deleted>".to_vec()));
+    assert_eq!(get("seen:a1"), Some(b"<deleted>".to_vec()));
+    assert_eq!(get("seen:b1"), Some(b"v1".to_vec()));
+    assert_eq!(get("seen:atomics_key"), None);
+
+    // The subscription ends once the backend is gone.
+    drop(store);
+    drop(backend);
+    assert_eq!(subscription.next().await, None);
+    Ok(())
+}
+
+#[test]
+fn subscriptions_see_every_change() -> Result<()> {
+    let backend = Arc::new(InMemoryBackend::new());
+    let ctx = WasiKeyValueCtxBuilder::new()
+        .backend(backend.clone())
+        .build();
+    let mut subscription = ctx.subscribe(2)?;
+
+    // Changes made directly through the backend are reported too, and the
+    // oldest changes are dropped once the buffer is full.
+    backend.set("", "a", b"1".to_vec())?;
+    assert_eq!(backend.increment("", "a", 1)?, 2);
+    backend.delete("", "a")?;
+    backend.delete("", "a")?;
+    assert_eq!(subscription.try_next(), Some(Err(Lagged(1))));
+    assert_eq!(subscription.try_next(), Some(Ok(set("", "a", "2"))));
+    assert_eq!(subscription.try_next(), Some(Ok(delete("", "a"))));
+    assert_eq!(subscription.try_next(), None);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_subscriptions_see_other_processes() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let backend = Arc::new(FileBackend::new(dir.path())?);
+    // Another backend using the same directory stands in for another process.
+    let other = FileBackend::new(dir.path())?;
+    other.set("", "before", b"0".to_vec())?;
+    let mut subscription = backend.subscribe(16)?;
+
+    other.set("", "a", b"1".to_vec())?;
+    assert_eq!(next(&mut subscription).await, Some(Ok(set("", "a", "1"))));
+    other.delete("", "before")?;
+    assert_eq!(
+        next(&mut subscription).await,
+        Some(Ok(delete("", "before")))
+    );
+    let long = "long ".repeat(100);
+    other.set(&long, &long, b"2".to_vec())?;
+    assert_eq!(
+        next(&mut subscription).await,
+        Some(Ok(set(&long, &long, "2")))
+    );
+    backend.set("b", "c", b"3".to_vec())?;
+    assert_eq!(next(&mut subscription).await, Some(Ok(set("b", "c", "3"))));
+
+    drop(backend);
+    assert_eq!(next(&mut subscription).await, None);
+    Ok(())
+}
+
+/// Wait for the next change, failing the test if it takes too long.
+async fn next(subscription: &mut Subscription) -> Option<Result<Change, Lagged>> {
+    tokio::time::timeout(Duration::from_secs(10), subscription.next())
+        .await
+        .expect("timed out waiting for a change")
+}
+
+fn set(bucket: &str, key: &str, value: &str) -> Change {
+    Change::Set {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        value: value.as_bytes().to_vec(),
+    }
+}
+
+fn delete(bucket: &str, key: &str) -> Change {
+    Change::Delete {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+    }
+}
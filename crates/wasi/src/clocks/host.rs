@@ -2,6 +2,7 @@ use super::{HostMonotonicClock, HostWallClock};
 use cap_std::time::{Duration, Instant, SystemClock};
 use cap_std::{AmbientAuthority, ambient_authority};
 use cap_time_ext::{MonotonicClockExt, SystemClockExt};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 pub struct WallClock {
     /// The underlying system clock.
@@ -64,6 +65,35 @@ impl HostMonotonicClock for MonotonicClock {
     }
 }
 
+/// A wall clock which always reads as the Unix epoch.
+pub struct FixedWallClock;
+
+impl HostWallClock for FixedWallClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// A monotonic clock which advances by one microsecond each time it's read.
+#[derive(Default)]
+pub struct SteppingMonotonicClock {
+    now: AtomicU64,
+}
+
+impl HostMonotonicClock for SteppingMonotonicClock {
+    fn resolution(&self) -> u64 {
+        1_000
+    }
+
+    fn now(&self) -> u64 {
+        self.now.fetch_add(1_000, Ordering::Relaxed)
+    }
+}
+
 pub fn monotonic_clock() -> Box<dyn HostMonotonicClock + Send> {
     Box::new(MonotonicClock::new(ambient_authority()))
 }
@@ -1,7 +1,13 @@
 bitflags::bitflags! {
+    /// Permission bits for operating on files within a directory.
     #[derive(Copy, Clone, Debug, PartialEq, Eq)]
     pub struct FilePerms: usize {
+        /// Files can be read.
         const READ = 0b1;
+        /// Files can be written. In a directory preopened with
+        /// [`WasiCtxBuilder::preopened_dir_overlay`](crate::p2::WasiCtxBuilder::preopened_dir_overlay)
+        /// a file is copied to the overlay's upper layer before it's first
+        /// written, leaving the host's file unchanged.
         const WRITE = 0b10;
     }
 }
@@ -27,6 +33,11 @@ bitflags::bitflags! {
 
         /// This directory can be mutated, for example by creating new files
         /// within it.
+        ///
+        /// For a directory preopened with
+        /// [`WasiCtxBuilder::preopened_dir_overlay`](crate::p2::WasiCtxBuilder::preopened_dir_overlay)
+        /// mutations are made to a copy-on-write layer on top of it, so the
+        /// guest can change it while the host's directory stays as it is.
         const MUTATE = 0b10;
     }
 }
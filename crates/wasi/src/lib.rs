@@ -20,6 +20,8 @@ pub mod preview0;
 #[cfg(feature = "preview1")]
 pub mod preview1;
 mod random;
+#[cfg(feature = "record-replay")]
+mod record_replay;
 pub mod runtime;
 
 pub use self::clocks::{HostMonotonicClock, HostWallClock};
@@ -1,17 +1,16 @@
 use crate::clocks::{
     HostMonotonicClock, HostWallClock,
-    host::{monotonic_clock, wall_clock},
+    host::{FixedWallClock, SteppingMonotonicClock, monotonic_clock, wall_clock},
 };
 use crate::net::{SocketAddrCheck, SocketAddrUse};
 use crate::p2::{
-    filesystem::Dir,
+    filesystem::{CapStdFilesystem, Dir, InMemoryFilesystem, OverlayFilesystem, WasiFilesystem},
     pipe, stdio,
     stdio::{StdinStream, StdoutStream},
 };
 use crate::{DirPerms, FilePerms, OpenMode, random};
 use anyhow::Result;
 use cap_rand::{Rng, RngCore, SeedableRng};
-use cap_std::ambient_authority;
 use std::path::Path;
 use std::sync::Arc;
 use std::{future::Future, pin::Pin};
@@ -52,6 +51,10 @@ pub struct WasiCtxBuilder {
     monotonic_clock: Box<dyn HostMonotonicClock + Send>,
     allowed_network_uses: AllowedNetworkUses,
     allow_blocking_current_thread: bool,
+    #[cfg(feature = "record-replay")]
+    host_call_trace: Option<wasmtime::HostCallTrace>,
+    seeded: Seeded,
+    deterministic: bool,
     built: bool,
 }
 
@@ -101,6 +104,10 @@ impl WasiCtxBuilder {
             monotonic_clock: monotonic_clock(),
             allowed_network_uses: AllowedNetworkUses::default(),
             allow_blocking_current_thread: false,
+            #[cfg(feature = "record-replay")]
+            host_call_trace: None,
+            seeded: Seeded::default(),
+            deterministic: false,
             built: false,
         }
     }
@@ -331,7 +338,118 @@ impl WasiCtxBuilder {
         dir_perms: DirPerms,
         file_perms: FilePerms,
     ) -> Result<&mut Self> {
-        let dir = cap_std::fs::Dir::open_ambient_dir(host_path.as_ref(), ambient_authority())?;
+        let dir = CapStdFilesystem::open_ambient(host_path)?;
+        Ok(self.preopened_filesystem(dir, guest_path, dir_perms, file_perms))
+    }
+
+    /// Provides a host directory to be accessible by WebAssembly as a
+    /// preopened directory whose changes are kept in memory.
+    ///
+    /// This is like [`Self::preopened_dir`], except the directory at
+    /// `host_path` is never modified. When `dir_perms` and `file_perms` allow
+    /// changes to be made, they're made to an in-memory copy-on-write layer on
+    /// top of the directory instead, which is private to the `WasiCtx` being
+    /// built and discarded along with it. See
+    /// [`OverlayFilesystem`](crate::p2::OverlayFilesystem) for how the layers
+    /// are combined, and for keeping changes in a scratch directory instead.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if `host_path` cannot be opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::p2::WasiCtxBuilder;
+    /// use wasmtime_wasi::{DirPerms, FilePerms};
+    ///
+    /// # fn main() {}
+    /// # fn foo() -> wasmtime::Result<()> {
+    /// let mut wasi = WasiCtxBuilder::new();
+    ///
+    /// // Let the guest build in `./src` without changing it on the host
+    /// wasi.preopened_dir_overlay("./src", "/src", DirPerms::all(), FilePerms::all())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preopened_dir_overlay(
+        &mut self,
+        host_path: impl AsRef<Path>,
+        guest_path: impl AsRef<str>,
+        dir_perms: DirPerms,
+        file_perms: FilePerms,
+    ) -> Result<&mut Self> {
+        let dir = CapStdFilesystem::open_ambient(host_path)?;
+        let overlay = OverlayFilesystem::new(dir, InMemoryFilesystem::new());
+        Ok(self.preopened_filesystem(overlay, guest_path, dir_perms, file_perms))
+    }
+
+    /// Provides the contents of a tar or zip archive to be accessible by
+    /// WebAssembly as a read-only preopened directory.
+    ///
+    /// The archive at `host_path` isn't extracted: see
+    /// [`ArchiveFilesystem`](crate::p2::ArchiveFilesystem) for how it's read.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if `host_path` cannot be opened or
+    /// isn't a tar or zip archive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::p2::WasiCtxBuilder;
+    ///
+    /// # fn main() {}
+    /// # fn foo() -> wasmtime::Result<()> {
+    /// let mut wasi = WasiCtxBuilder::new();
+    ///
+    /// // Make the files of `./python-stdlib.zip` available in the guest as
+    /// // `/usr/lib/python3.13`
+    /// wasi.preopened_archive("./python-stdlib.zip", "/usr/lib/python3.13")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "archive")]
+    pub fn preopened_archive(
+        &mut self,
+        host_path: impl AsRef<Path>,
+        guest_path: impl AsRef<str>,
+    ) -> Result<&mut Self> {
+        let archive = crate::p2::ArchiveFilesystem::open(host_path)?;
+        Ok(self.preopened_filesystem(archive, guest_path, DirPerms::READ, FilePerms::READ))
+    }
+
+    /// Provides a [`WasiFilesystem`] to be accessible by WebAssembly as a
+    /// preopened directory.
+    ///
+    /// This is like [`Self::preopened_dir`], except the files are provided by
+    /// `fs` rather than a directory of the host. For example an
+    /// [`InMemoryFilesystem`](crate::p2::InMemoryFilesystem) can serve files
+    /// without touching the host's filesystem at all.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::p2::{InMemoryFilesystem, WasiCtxBuilder};
+    /// use wasmtime_wasi::{DirPerms, FilePerms};
+    ///
+    /// # fn main() -> wasmtime::Result<()> {
+    /// let fs = InMemoryFilesystem::new();
+    /// fs.insert_file("config.toml", "verbose = true")?;
+    ///
+    /// let mut wasi = WasiCtxBuilder::new();
+    /// wasi.preopened_filesystem(fs, "/etc/app", DirPerms::READ, FilePerms::READ);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preopened_filesystem(
+        &mut self,
+        fs: impl WasiFilesystem,
+        guest_path: impl AsRef<str>,
+        dir_perms: DirPerms,
+        file_perms: FilePerms,
+    ) -> &mut Self {
         let mut open_mode = OpenMode::empty();
         if dir_perms.contains(DirPerms::READ) {
             open_mode |= OpenMode::READ;
@@ -341,7 +459,7 @@ impl WasiCtxBuilder {
         }
         self.preopens.push((
             Dir::new(
-                dir,
+                Arc::new(fs),
                 dir_perms,
                 file_perms,
                 open_mode,
@@ -349,7 +467,7 @@ impl WasiCtxBuilder {
             ),
             guest_path.as_ref().to_owned(),
         ));
-        Ok(self)
+        self
     }
 
     /// Set the generator for the `wasi:random/random` number generator to the
@@ -364,6 +482,7 @@ impl WasiCtxBuilder {
     /// prerecorded or otherwise predictable data may compromise security.
     pub fn secure_random(&mut self, random: impl RngCore + Send + 'static) -> &mut Self {
         self.random = Box::new(random);
+        self.seeded.random = true;
         self
     }
 
@@ -373,6 +492,7 @@ impl WasiCtxBuilder {
     /// requested by the `wasi:random/insecure` interface.
     pub fn insecure_random(&mut self, insecure_random: impl RngCore + Send + 'static) -> &mut Self {
         self.insecure_random = Box::new(insecure_random);
+        self.seeded.insecure_random = true;
         self
     }
 
@@ -382,6 +502,7 @@ impl WasiCtxBuilder {
     /// By default this number is randomly generated when a builder is created.
     pub fn insecure_random_seed(&mut self, insecure_random_seed: u128) -> &mut Self {
         self.insecure_random_seed = insecure_random_seed;
+        self.seeded.insecure_random_seed = true;
         self
     }
 
@@ -390,6 +511,7 @@ impl WasiCtxBuilder {
     /// By default the host's wall clock is used.
     pub fn wall_clock(&mut self, clock: impl HostWallClock + 'static) -> &mut Self {
         self.wall_clock = Box::new(clock);
+        self.seeded.wall_clock = true;
         self
     }
 
@@ -398,6 +520,53 @@ impl WasiCtxBuilder {
     /// By default the host's monotonic clock is used.
     pub fn monotonic_clock(&mut self, clock: impl HostMonotonicClock + 'static) -> &mut Self {
         self.monotonic_clock = Box::new(clock);
+        self.seeded.monotonic_clock = true;
+        self
+    }
+
+    /// Configures all clocks and random number generators to be
+    /// deterministic, with the random number generators seeded by `seed`.
+    ///
+    /// The wall clock always reads as the Unix epoch, and the monotonic clock
+    /// advances by one microsecond each time it's read.
+    ///
+    /// This also enables [`deterministic`](WasiCtxBuilder::deterministic).
+    pub fn deterministic_sources(&mut self, seed: u64) -> &mut Self {
+        self.deterministic = true;
+        self.secure_random(cap_rand::rngs::StdRng::seed_from_u64(seed))
+            .insecure_random(cap_rand::rngs::SmallRng::seed_from_u64(seed))
+            .insecure_random_seed(seed.into())
+            .wall_clock(FixedWallClock)
+            .monotonic_clock(SteppingMonotonicClock::default())
+    }
+
+    /// Configures whether the context refuses to use the host's clocks and
+    /// random number generators, allowing only the ones configured explicitly.
+    ///
+    /// This should be enabled for contexts used with an engine configured
+    /// with [`Config::deterministic`](wasmtime::Config::deterministic), where
+    /// reading the host's clocks or random numbers makes the guest's execution
+    /// nondeterministic. Such reads then trap.
+    ///
+    /// This is disabled by default, and enabled by
+    /// [`deterministic_sources`](WasiCtxBuilder::deterministic_sources).
+    pub fn deterministic(&mut self, enable: bool) -> &mut Self {
+        self.deterministic = enable;
+        self
+    }
+
+    /// Records the readings of this context's clocks and random number
+    /// generators into `trace`, or replays them from it.
+    ///
+    /// This applies to whichever clocks and generators are configured when
+    /// the context is built, as well as the `wasi:random/insecure-seed` seed.
+    /// The same trace should also be configured for the store with
+    /// [`Store::host_call_trace`](wasmtime::Store::host_call_trace), so that a
+    /// guest re-executed with a replaying trace observes exactly the same
+    /// values it did when recorded.
+    #[cfg(feature = "record-replay")]
+    pub fn host_call_trace(&mut self, trace: wasmtime::HostCallTrace) -> &mut Self {
+        self.host_call_trace = Some(trace);
         self
     }
 
@@ -481,10 +650,58 @@ impl WasiCtxBuilder {
             monotonic_clock,
             allowed_network_uses,
             allow_blocking_current_thread,
+            #[cfg(feature = "record-replay")]
+            host_call_trace,
+            seeded,
+            deterministic,
             built: _,
         } = mem::replace(self, Self::new());
         self.built = true;
 
+        // Replayed readings are the same every time, regardless of the sources
+        // that are configured.
+        #[cfg(feature = "record-replay")]
+        let seeded = match &host_call_trace {
+            Some(trace) if trace.is_replaying() => Seeded::all(),
+            _ => seeded,
+        };
+
+        #[cfg(feature = "record-replay")]
+        let (random, insecure_random, insecure_random_seed, wall_clock, monotonic_clock) =
+            match host_call_trace {
+                Some(trace) => {
+                    use crate::record_replay::*;
+                    let seed_hi = trace.value(|| (insecure_random_seed >> 64) as u64);
+                    let seed_lo = trace.value(|| insecure_random_seed as u64);
+                    (
+                        Box::new(TracedRng {
+                            rng: random,
+                            trace: trace.clone(),
+                        }) as Box<dyn RngCore + Send>,
+                        Box::new(TracedRng {
+                            rng: insecure_random,
+                            trace: trace.clone(),
+                        }) as Box<dyn RngCore + Send>,
+                        (u128::from(seed_hi) << 64) | u128::from(seed_lo),
+                        Box::new(TracedWallClock {
+                            clock: wall_clock,
+                            trace: trace.clone(),
+                        }) as Box<dyn HostWallClock + Send>,
+                        Box::new(TracedMonotonicClock {
+                            clock: monotonic_clock,
+                            trace,
+                        }) as Box<dyn HostMonotonicClock + Send>,
+                    )
+                }
+                None => (
+                    random,
+                    insecure_random,
+                    insecure_random_seed,
+                    wall_clock,
+                    monotonic_clock,
+                ),
+            };
+
         WasiCtx {
             stdin,
             stdout,
@@ -500,6 +717,8 @@ impl WasiCtxBuilder {
             monotonic_clock,
             allowed_network_uses,
             allow_blocking_current_thread,
+            seeded,
+            deterministic,
         }
     }
 
@@ -585,6 +804,10 @@ pub struct WasiCtx {
     pub(crate) socket_addr_check: SocketAddrCheck,
     pub(crate) allowed_network_uses: AllowedNetworkUses,
     pub(crate) allow_blocking_current_thread: bool,
+    pub(crate) seeded: Seeded,
+    /// Whether the clocks and random number generators that weren't configured
+    /// explicitly may not be used, see [`WasiCtxBuilder::deterministic`].
+    pub(crate) deterministic: bool,
 }
 
 impl WasiCtx {
@@ -592,6 +815,42 @@ impl WasiCtx {
     pub fn builder() -> WasiCtxBuilder {
         WasiCtxBuilder::new()
     }
+
+    /// Returns an error if this context is deterministic and the source of
+    /// nondeterminism named `source` wasn't configured explicitly.
+    pub(crate) fn ensure_seeded(&self, seeded: bool, source: &str) -> Result<()> {
+        if self.deterministic && !seeded {
+            anyhow::bail!(
+                "the host's {source} cannot be used in deterministic mode; \
+                 configure one with `WasiCtxBuilder`"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Which of a [`WasiCtx`]'s clocks and random number generators were
+/// configured explicitly, rather than left as the host's defaults.
+#[derive(Default, Clone, Copy)]
+pub(crate) struct Seeded {
+    pub(crate) random: bool,
+    pub(crate) insecure_random: bool,
+    pub(crate) insecure_random_seed: bool,
+    pub(crate) wall_clock: bool,
+    pub(crate) monotonic_clock: bool,
+}
+
+impl Seeded {
+    #[cfg(feature = "record-replay")]
+    fn all() -> Seeded {
+        Seeded {
+            random: true,
+            insecure_random: true,
+            insecure_random_seed: true,
+            wall_clock: true,
+            monotonic_clock: true,
+        }
+    }
 }
 
 pub struct AllowedNetworkUses {
@@ -4,10 +4,23 @@ use crate::runtime::{AbortOnDropJoinHandle, spawn_blocking};
 use crate::{DirPerms, FilePerms, OpenMode, TrappableError};
 use anyhow::anyhow;
 use bytes::{Bytes, BytesMut};
+use std::any::Any;
 use std::io;
 use std::mem;
 use std::sync::Arc;
 
+#[cfg(feature = "archive")]
+mod archive;
+mod cap_std_fs;
+mod in_memory;
+mod overlay;
+
+#[cfg(feature = "archive")]
+pub use self::archive::ArchiveFilesystem;
+pub use self::cap_std_fs::{CapStdFile, CapStdFilesystem};
+pub use self::in_memory::InMemoryFilesystem;
+pub use self::overlay::OverlayFilesystem;
+
 pub type FsResult<T> = Result<T, FsError>;
 
 pub type FsError = TrappableError<types::ErrorCode>;
@@ -24,6 +37,204 @@ impl From<io::Error> for FsError {
     }
 }
 
+/// A directory, and the tree of files and directories below it, which can be
+/// exposed to guests as a `wasi:filesystem` descriptor.
+///
+/// This is the interface between the `wasi:filesystem` implementation and the
+/// storage of files, which may be the host's filesystem through
+/// [`CapStdFilesystem`] or something else entirely, such as
+/// [`InMemoryFilesystem`]. Implementations are registered with
+/// [`WasiCtxBuilder::preopened_filesystem`](crate::p2::WasiCtxBuilder::preopened_filesystem).
+///
+/// Paths are relative to this directory, and implementations must not let
+/// them resolve to anything outside of it, for example through `..` or
+/// symbolic links. The [`DirPerms`] and [`FilePerms`] of the preopen are
+/// checked before any method is called, so implementations need only enforce
+/// restrictions of their own.
+///
+/// Methods may block. They're run on a thread where blocking is fine, unless
+/// [`WasiCtxBuilder::allow_blocking_current_thread`](crate::p2::WasiCtxBuilder::allow_blocking_current_thread)
+/// is enabled.
+pub trait WasiFilesystem: Send + Sync + 'static {
+    /// Open the file or directory at `path`.
+    ///
+    /// `open_mode` tells whether a file is opened for reading, writing or
+    /// both. If `oflags` contains [`types::OpenFlags::DIRECTORY`] then flags
+    /// which only apply to files have been rejected already, but this must
+    /// still fail with [`types::ErrorCode::NotDirectory`] if `path` isn't a
+    /// directory.
+    fn open_at(
+        &self,
+        path_flags: types::PathFlags,
+        path: &str,
+        oflags: types::OpenFlags,
+        open_mode: OpenMode,
+    ) -> FsResult<Opened>;
+
+    /// Create a directory at `path`.
+    fn create_directory_at(&self, path: &str) -> FsResult<()>;
+
+    /// Return the metadata of this directory.
+    fn stat(&self) -> FsResult<Metadata>;
+
+    /// Return the metadata of the file or directory at `path`.
+    fn stat_at(&self, path_flags: types::PathFlags, path: &str) -> FsResult<Metadata>;
+
+    /// Adjust the timestamps of this directory.
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()>;
+
+    /// Adjust the timestamps of the file or directory at `path`.
+    fn set_times_at(
+        &self,
+        path_flags: types::PathFlags,
+        path: &str,
+        atim: types::NewTimestamp,
+        mtim: types::NewTimestamp,
+    ) -> FsResult<()>;
+
+    /// Return the entries of this directory, other than `.` and `..`.
+    ///
+    /// Entries which can't be read are reported as errors in their place, so
+    /// that the guest still sees the entries before them.
+    fn read_directory(&self) -> FsResult<Vec<FsResult<types::DirectoryEntry>>>;
+
+    /// Create a hard link at `new_path` in `new_dir` to the file at
+    /// `old_path`.
+    ///
+    /// `new_dir` may be of another type, in which case this should fail with
+    /// [`types::ErrorCode::CrossDevice`].
+    fn link_at(&self, old_path: &str, new_dir: &dyn WasiFilesystem, new_path: &str)
+    -> FsResult<()>;
+
+    /// Return the contents of the symbolic link at `path`.
+    fn readlink_at(&self, path: &str) -> FsResult<String>;
+
+    /// Remove the empty directory at `path`.
+    fn remove_directory_at(&self, path: &str) -> FsResult<()>;
+
+    /// Rename the file or directory at `old_path` to `new_path` in `new_dir`.
+    ///
+    /// `new_dir` may be of another type, in which case this should fail with
+    /// [`types::ErrorCode::CrossDevice`].
+    fn rename_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiFilesystem,
+        new_path: &str,
+    ) -> FsResult<()>;
+
+    /// Create a symbolic link at `dest_path` pointing to `src_path`.
+    fn symlink_at(&self, src_path: &str, dest_path: &str) -> FsResult<()>;
+
+    /// Remove the file or symbolic link at `path`.
+    fn unlink_file_at(&self, path: &str) -> FsResult<()>;
+
+    /// Synchronize the data and metadata of this directory to storage.
+    fn sync(&self) -> FsResult<()> {
+        Ok(())
+    }
+
+    /// Synchronize the data of this directory to storage.
+    fn sync_data(&self) -> FsResult<()> {
+        Ok(())
+    }
+
+    /// Return the synchronization flags of this directory.
+    ///
+    /// The read and write flags are added by the caller.
+    fn get_flags(&self) -> FsResult<types::DescriptorFlags> {
+        Ok(types::DescriptorFlags::empty())
+    }
+
+    /// Return `self`, so that [`Self::link_at`] and [`Self::rename_at`] can
+    /// downcast the other directory to their own type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A file opened through a [`WasiFilesystem`].
+///
+/// Reads and writes return [`io::Error`]s, as they're also used to implement
+/// `wasi:io` streams.
+pub trait WasiFile: Send + Sync + 'static {
+    /// Read into `buf` from `offset`, returning the number of bytes read,
+    /// which is 0 at the end of the file.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Write `buf` at `offset`, returning the number of bytes written.
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize>;
+
+    /// Write `buf` at the end of the file, returning the number of bytes
+    /// written.
+    fn append(&self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Return the metadata of this file.
+    fn stat(&self) -> FsResult<Metadata>;
+
+    /// Truncate or extend this file to `size` bytes.
+    fn set_size(&self, size: types::Filesize) -> FsResult<()>;
+
+    /// Adjust the timestamps of this file.
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()>;
+
+    /// Provide advice on how the data in the given range will be used.
+    fn advise(
+        &self,
+        offset: types::Filesize,
+        len: types::Filesize,
+        advice: types::Advice,
+    ) -> FsResult<()> {
+        let _ = (offset, len, advice);
+        Ok(())
+    }
+
+    /// Synchronize the data and metadata of this file to storage.
+    fn sync(&self) -> FsResult<()> {
+        Ok(())
+    }
+
+    /// Synchronize the data of this file to storage.
+    fn sync_data(&self) -> FsResult<()> {
+        Ok(())
+    }
+
+    /// Return the synchronization flags of this file.
+    ///
+    /// The read and write flags are added by the caller.
+    fn get_flags(&self) -> FsResult<types::DescriptorFlags> {
+        Ok(types::DescriptorFlags::empty())
+    }
+}
+
+/// The result of [`WasiFilesystem::open_at`].
+pub enum Opened {
+    /// A directory was opened.
+    Dir(Box<dyn WasiFilesystem>),
+    /// A file, or any other object which isn't a directory, was opened.
+    File(Box<dyn WasiFile>),
+}
+
+/// Return a device number for a filesystem which isn't backed by a device, so
+/// that its files can't be mistaken for others with the same inode numbers.
+fn unique_dev() -> u64 {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // Start far away from the device numbers of the host's filesystems.
+    static NEXT_DEV: AtomicU64 = AtomicU64::new(1 << 63);
+    NEXT_DEV.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The metadata of a file or directory in a [`WasiFilesystem`].
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    /// The metadata reported to the guest.
+    pub stat: types::DescriptorStat,
+    /// Along with `ino`, identifies the file or directory, to implement
+    /// `is-same-object` and `metadata-hash`.
+    pub dev: u64,
+    /// Along with `dev`, identifies the file or directory.
+    pub ino: u64,
+}
+
 pub enum Descriptor {
     File(File),
     Dir(Dir),
@@ -61,14 +272,14 @@ impl Descriptor {
 
 #[derive(Clone)]
 pub struct File {
-    /// The operating system File this struct is mediating access to.
+    /// The file this struct is mediating access to.
     ///
     /// Wrapped in an Arc because the same underlying file is used for
     /// implementing the stream types. A copy is also needed for
     /// [`spawn_blocking`].
     ///
     /// [`spawn_blocking`]: Self::spawn_blocking
-    pub file: Arc<cap_std::fs::File>,
+    pub file: Arc<dyn WasiFile>,
     /// Permissions to enforce on access to the file. These permissions are
     /// specified by a user of the `crate::p2::WasiCtxBuilder`, and are
     /// enforced prior to any enforced by the underlying operating system.
@@ -84,13 +295,13 @@ pub struct File {
 
 impl File {
     pub fn new(
-        file: cap_std::fs::File,
+        file: Arc<dyn WasiFile>,
         perms: FilePerms,
         open_mode: OpenMode,
         allow_blocking_current_thread: bool,
     ) -> Self {
         Self {
-            file: Arc::new(file),
+            file,
             perms,
             open_mode,
             allow_blocking_current_thread,
@@ -113,7 +324,7 @@ impl File {
     /// - [Implement opt-in for enabling WASI to block the current thread](https://github.com/bytecodealliance/wasmtime/pull/8190)
     pub(crate) async fn run_blocking<F, R>(&self, body: F) -> R
     where
-        F: FnOnce(&cap_std::fs::File) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiFile) -> R + Send + 'static,
         R: Send + 'static,
     {
         match self.as_blocking_file() {
@@ -124,19 +335,19 @@ impl File {
 
     pub(crate) fn spawn_blocking<F, R>(&self, body: F) -> AbortOnDropJoinHandle<R>
     where
-        F: FnOnce(&cap_std::fs::File) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiFile) -> R + Send + 'static,
         R: Send + 'static,
     {
         let f = self.file.clone();
-        spawn_blocking(move || body(&f))
+        spawn_blocking(move || body(&*f))
     }
 
     /// Returns `Some` when the current thread is allowed to block in filesystem
     /// operations, and otherwise returns `None` to indicate that
     /// `spawn_blocking` must be used.
-    pub(crate) fn as_blocking_file(&self) -> Option<&cap_std::fs::File> {
+    pub(crate) fn as_blocking_file(&self) -> Option<&dyn WasiFile> {
         if self.allow_blocking_current_thread {
-            Some(&self.file)
+            Some(&*self.file)
         } else {
             None
         }
@@ -145,13 +356,12 @@ impl File {
 
 #[derive(Clone)]
 pub struct Dir {
-    /// The operating system file descriptor this struct is mediating access
-    /// to.
+    /// The directory this struct is mediating access to.
     ///
     /// Wrapped in an Arc because a copy is needed for [`spawn_blocking`].
     ///
     /// [`spawn_blocking`]: Self::spawn_blocking
-    pub dir: Arc<cap_std::fs::Dir>,
+    pub dir: Arc<dyn WasiFilesystem>,
     /// Permissions to enforce on access to this directory. These permissions
     /// are specified by a user of the `crate::p2::WasiCtxBuilder`, and
     /// are enforced prior to any enforced by the underlying operating system.
@@ -172,14 +382,14 @@ pub struct Dir {
 
 impl Dir {
     pub fn new(
-        dir: cap_std::fs::Dir,
+        dir: Arc<dyn WasiFilesystem>,
         perms: DirPerms,
         file_perms: FilePerms,
         open_mode: OpenMode,
         allow_blocking_current_thread: bool,
     ) -> Self {
         Dir {
-            dir: Arc::new(dir),
+            dir,
             perms,
             file_perms,
             open_mode,
@@ -203,14 +413,14 @@ impl Dir {
     /// - [Implement opt-in for enabling WASI to block the current thread](https://github.com/bytecodealliance/wasmtime/pull/8190)
     pub(crate) async fn run_blocking<F, R>(&self, body: F) -> R
     where
-        F: FnOnce(&cap_std::fs::Dir) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiFilesystem) -> R + Send + 'static,
         R: Send + 'static,
     {
         if self.allow_blocking_current_thread {
-            body(&self.dir)
+            body(&*self.dir)
         } else {
             let d = self.dir.clone();
-            spawn_blocking(move || body(&d)).await
+            spawn_blocking(move || body(&*d)).await
         }
     }
 }
@@ -236,9 +446,7 @@ impl FileInputStream {
         }
     }
 
-    fn blocking_read(file: &cap_std::fs::File, offset: u64, size: usize) -> ReadState {
-        use system_interface::fs::FileIoExt;
-
+    fn blocking_read(file: &dyn WasiFile, offset: u64, size: usize) -> ReadState {
         let mut buf = BytesMut::zeroed(size);
         loop {
             match file.read_at(&mut buf, offset) {
@@ -392,12 +600,10 @@ impl FileOutputStream {
     }
 
     fn blocking_write(
-        file: &cap_std::fs::File,
+        file: &dyn WasiFile,
         mut buf: Bytes,
         mode: FileOutputMode,
     ) -> io::Result<usize> {
-        use system_interface::fs::FileIoExt;
-
         match mode {
             FileOutputMode::Position(mut p) => {
                 let mut total = 0;
@@ -0,0 +1,927 @@
+use super::cap_std_fs::datetime_from;
+use super::{FsResult, Metadata, Opened, WasiFile, WasiFilesystem};
+use crate::OpenMode;
+use crate::p2::bindings::filesystem::types::{self, DescriptorType, ErrorCode};
+use anyhow::{Context, bail};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+use system_interface::fs::FileIoExt;
+
+/// The number of symbolic links followed when resolving a path before giving
+/// up with [`ErrorCode::Loop`], as on Linux.
+const MAX_SYMLINKS: u32 = 40;
+
+/// A read-only [`WasiFilesystem`] serving the contents of a tar or zip
+/// archive.
+///
+/// The archive is indexed when it's opened, and the contents of its files are
+/// read from it as guests open them, so nothing is extracted to disk. Files
+/// stored uncompressed, which includes all files of tar archives, are read in
+/// place. Compressed files of zip archives are decompressed into memory when
+/// they're opened, and the decompressed contents are shared by all the handles
+/// to the file that are open at the same time. Once they're all closed the
+/// memory is freed, and opening the file again decompresses it again, one file
+/// at a time for the whole archive. Compressed tar archives such as `.tar.gz`
+/// aren't supported, as they can't be read in place.
+///
+/// Symbolic links and hard links within the archive are supported. Symbolic
+/// links which lead outside of the archive can't be followed.
+///
+/// Files can only be read, so the archive should be preopened with
+/// [`DirPerms::READ`](crate::DirPerms::READ) and
+/// [`FilePerms::READ`](crate::FilePerms::READ), which is what
+/// [`WasiCtxBuilder::preopened_archive`](crate::p2::WasiCtxBuilder::preopened_archive)
+/// does. Any attempt to modify it fails with [`ErrorCode::ReadOnly`].
+#[derive(Clone)]
+pub struct ArchiveFilesystem {
+    archive: Arc<Archive>,
+    /// The directory of `archive` which this handle refers to.
+    dir: usize,
+}
+
+struct Archive {
+    /// Identifies this archive among all filesystems, to report as the device
+    /// of its files.
+    dev: u64,
+    file: cap_std::fs::File,
+    zip: Option<Mutex<Zip>>,
+    /// The files, directories and symbolic links of the archive, indexed by
+    /// their inode number. The root directory is the first.
+    nodes: Vec<Node>,
+}
+
+struct Node {
+    kind: Kind,
+    modified: SystemTime,
+}
+
+enum Kind {
+    Dir(BTreeMap<String, usize>),
+    File {
+        size: u64,
+        data: Data,
+        link_count: u64,
+    },
+    Symlink(String),
+}
+
+struct Zip {
+    archive: zip::ZipArchive<fs::File>,
+    /// The contents of the compressed files which are currently open, by
+    /// inode number.
+    decompressed: HashMap<usize, Weak<[u8]>>,
+}
+
+#[derive(Clone, Copy)]
+enum Data {
+    /// The file is stored uncompressed at this offset of the archive.
+    Stored(u64),
+    /// The file is compressed, and is this file of the zip archive.
+    Zip(usize),
+}
+
+impl ArchiveFilesystem {
+    /// Open the tar or zip archive at `path`.
+    ///
+    /// The format is detected from the contents of the file rather than its
+    /// name.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if `path` can't be opened or if it isn't a valid
+    /// tar or zip archive.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        Self::from_file(fs::File::open(path).with_context(|| format!("failed to open {path:?}"))?)
+            .with_context(|| format!("failed to read archive {path:?}"))
+    }
+
+    /// Read a tar or zip archive from `file`.
+    pub fn from_file(mut file: fs::File) -> anyhow::Result<Self> {
+        let mut magic = [0; 4];
+        file.rewind()?;
+        let n = file.read(&mut magic)?;
+        file.rewind()?;
+
+        let modified = file
+            .metadata()?
+            .modified()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut builder = Builder::new(modified);
+        let zip = match &magic[..n] {
+            // A local file header, or the end of central directory record of
+            // an empty archive.
+            b"PK\x03\x04" | b"PK\x05\x06" => {
+                let mut archive = zip::ZipArchive::new(file.try_clone()?)?;
+                builder.read_zip(&mut archive)?;
+                Some(Mutex::new(Zip {
+                    archive,
+                    decompressed: HashMap::new(),
+                }))
+            }
+            [0x1f, 0x8b, ..] => {
+                bail!("compressed tar archives aren't supported, decompress the archive first")
+            }
+            _ => {
+                builder.read_tar(&file)?;
+                None
+            }
+        };
+
+        Ok(Self {
+            archive: Arc::new(Archive {
+                dev: super::unique_dev(),
+                file: cap_std::fs::File::from_std(file),
+                zip,
+                nodes: builder.nodes,
+            }),
+            dir: 0,
+        })
+    }
+
+    fn node(&self, ino: usize) -> &Node {
+        &self.archive.nodes[ino]
+    }
+
+    fn entries(&self) -> &BTreeMap<String, usize> {
+        match &self.node(self.dir).kind {
+            Kind::Dir(entries) => entries,
+            _ => unreachable!("handles always refer to directories"),
+        }
+    }
+
+    /// Resolve `path` from this directory, without leaving it, returning the
+    /// inode it leads to.
+    ///
+    /// Symbolic links are followed, except for the last component of `path`
+    /// if `follow` is false.
+    fn lookup(&self, path: &str, follow: bool) -> FsResult<usize> {
+        if path.is_empty() {
+            return Err(ErrorCode::NoEntry.into());
+        }
+        if path.starts_with('/') {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let mut stack = vec![self.dir];
+        let mut components = path
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .collect::<VecDeque<_>>();
+        let mut links = 0;
+        while let Some(component) = components.pop_front() {
+            let dir = *stack.last().unwrap();
+            match component {
+                "." => {}
+                ".." => {
+                    stack.pop();
+                    if stack.is_empty() {
+                        return Err(ErrorCode::NotPermitted.into());
+                    }
+                }
+                name => {
+                    let Kind::Dir(entries) = &self.node(dir).kind else {
+                        unreachable!("only directories are pushed");
+                    };
+                    let ino = *entries.get(name).ok_or(ErrorCode::NoEntry)?;
+                    let last = components.is_empty();
+                    match &self.node(ino).kind {
+                        Kind::Dir(_) => stack.push(ino),
+                        Kind::File { .. } if last => return Ok(ino),
+                        Kind::File { .. } => return Err(ErrorCode::NotDirectory.into()),
+                        Kind::Symlink(_) if last && !follow => return Ok(ino),
+                        Kind::Symlink(target) => {
+                            links += 1;
+                            if links > MAX_SYMLINKS {
+                                return Err(ErrorCode::Loop.into());
+                            }
+                            if target.starts_with('/') {
+                                return Err(ErrorCode::NotPermitted.into());
+                            }
+                            for c in target.rsplit('/').filter(|c| !c.is_empty()) {
+                                components.push_front(c);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(*stack.last().unwrap())
+    }
+
+    fn metadata(&self, ino: usize) -> Metadata {
+        let node = self.node(ino);
+        let (type_, size, link_count) = match &node.kind {
+            Kind::Dir(_) => (DescriptorType::Directory, 0, 1),
+            Kind::File {
+                size, link_count, ..
+            } => (DescriptorType::RegularFile, *size, *link_count),
+            Kind::Symlink(target) => (DescriptorType::SymbolicLink, target.len() as u64, 1),
+        };
+        let time = Some(datetime_from(node.modified));
+        Metadata {
+            stat: types::DescriptorStat {
+                type_,
+                link_count,
+                size,
+                data_access_timestamp: time,
+                data_modification_timestamp: time,
+                status_change_timestamp: time,
+            },
+            dev: self.archive.dev,
+            ino: ino as u64,
+        }
+    }
+
+    fn open_file(&self, ino: usize) -> FsResult<ArchiveFile> {
+        let Kind::File { size, data, .. } = self.node(ino).kind else {
+            unreachable!("not a file");
+        };
+        let contents = match data {
+            Data::Stored(offset) => Contents::Stored { offset, size },
+            Data::Zip(index) => {
+                let mut zip = self.archive.zip.as_ref().unwrap().lock().unwrap();
+                if let Some(contents) = zip.decompressed.get(&ino).and_then(Weak::upgrade) {
+                    Contents::Decompressed(contents)
+                } else {
+                    // The size comes from the archive, so don't trust it with
+                    // more than a small allocation up front, nor read more.
+                    let file = zip.archive.by_index(index).map_err(io::Error::from)?;
+                    let capacity = size.min(1 << 20);
+                    let mut contents = Vec::with_capacity(capacity.try_into().unwrap());
+                    file.take(size).read_to_end(&mut contents)?;
+                    let contents = Arc::<[u8]>::from(contents);
+                    zip.decompressed.retain(|_, c| c.strong_count() > 0);
+                    zip.decompressed.insert(ino, Arc::downgrade(&contents));
+                    Contents::Decompressed(contents)
+                }
+            }
+        };
+        Ok(ArchiveFile {
+            fs: self.clone(),
+            ino,
+            contents,
+        })
+    }
+}
+
+impl WasiFilesystem for ArchiveFilesystem {
+    fn open_at(
+        &self,
+        path_flags: types::PathFlags,
+        path: &str,
+        oflags: types::OpenFlags,
+        open_mode: OpenMode,
+    ) -> FsResult<Opened> {
+        use types::OpenFlags;
+
+        let follow = path_flags.contains(types::PathFlags::SYMLINK_FOLLOW);
+        let ino = match self.lookup(path, follow) {
+            Ok(ino) => ino,
+            Err(e) if oflags.contains(OpenFlags::CREATE) => {
+                return match e.downcast_ref() {
+                    Some(ErrorCode::NoEntry) => Err(ErrorCode::ReadOnly.into()),
+                    _ => Err(e),
+                };
+            }
+            Err(e) => return Err(e),
+        };
+        if oflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) {
+            return Err(ErrorCode::Exist.into());
+        }
+        let writes = open_mode.contains(OpenMode::WRITE) || oflags.contains(OpenFlags::TRUNCATE);
+        match &self.node(ino).kind {
+            Kind::Dir(_) if writes => Err(ErrorCode::IsDirectory.into()),
+            Kind::Dir(_) => Ok(Opened::Dir(Box::new(Self {
+                archive: self.archive.clone(),
+                dir: ino,
+            }))),
+            Kind::File { .. } if oflags.contains(OpenFlags::DIRECTORY) => {
+                Err(ErrorCode::NotDirectory.into())
+            }
+            Kind::File { .. } if writes => Err(ErrorCode::ReadOnly.into()),
+            Kind::File { .. } => Ok(Opened::File(Box::new(self.open_file(ino)?))),
+            // Opening a symbolic link itself fails, as with `O_NOFOLLOW`.
+            Kind::Symlink(_) => Err(ErrorCode::Loop.into()),
+        }
+    }
+
+    fn create_directory_at(&self, _path: &str) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        Ok(self.metadata(self.dir))
+    }
+
+    fn stat_at(&self, path_flags: types::PathFlags, path: &str) -> FsResult<Metadata> {
+        let follow = path_flags.contains(types::PathFlags::SYMLINK_FOLLOW);
+        Ok(self.metadata(self.lookup(path, follow)?))
+    }
+
+    fn set_times(&self, _atim: types::NewTimestamp, _mtim: types::NewTimestamp) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn set_times_at(
+        &self,
+        _path_flags: types::PathFlags,
+        _path: &str,
+        _atim: types::NewTimestamp,
+        _mtim: types::NewTimestamp,
+    ) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn read_directory(&self) -> FsResult<Vec<FsResult<types::DirectoryEntry>>> {
+        Ok(self
+            .entries()
+            .iter()
+            .map(|(name, ino)| {
+                let type_ = match self.node(*ino).kind {
+                    Kind::Dir(_) => DescriptorType::Directory,
+                    Kind::File { .. } => DescriptorType::RegularFile,
+                    Kind::Symlink(_) => DescriptorType::SymbolicLink,
+                };
+                Ok(types::DirectoryEntry {
+                    type_,
+                    name: name.clone(),
+                })
+            })
+            .collect())
+    }
+
+    fn link_at(
+        &self,
+        _old_path: &str,
+        _new_dir: &dyn WasiFilesystem,
+        _new_path: &str,
+    ) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn readlink_at(&self, path: &str) -> FsResult<String> {
+        match &self.node(self.lookup(path, false)?).kind {
+            Kind::Symlink(target) => Ok(target.clone()),
+            _ => Err(ErrorCode::Invalid.into()),
+        }
+    }
+
+    fn remove_directory_at(&self, _path: &str) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn rename_at(
+        &self,
+        _old_path: &str,
+        _new_dir: &dyn WasiFilesystem,
+        _new_path: &str,
+    ) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn symlink_at(&self, _src_path: &str, _dest_path: &str) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn unlink_file_at(&self, _path: &str) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A file opened through an [`ArchiveFilesystem`].
+struct ArchiveFile {
+    fs: ArchiveFilesystem,
+    ino: usize,
+    contents: Contents,
+}
+
+enum Contents {
+    Stored { offset: u64, size: u64 },
+    Decompressed(Arc<[u8]>),
+}
+
+impl WasiFile for ArchiveFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        match &self.contents {
+            Contents::Stored {
+                offset: start,
+                size,
+            } => {
+                let len = size.saturating_sub(offset);
+                let len = buf.len().min(usize::try_from(len).unwrap_or(usize::MAX));
+                if len == 0 {
+                    return Ok(0);
+                }
+                self.fs
+                    .archive
+                    .file
+                    .read_at(&mut buf[..len], start + offset)
+            }
+            Contents::Decompressed(contents) => {
+                let start = usize::try_from(offset)
+                    .unwrap_or(usize::MAX)
+                    .min(contents.len());
+                let n = buf.len().min(contents.len() - start);
+                buf[..n].copy_from_slice(&contents[start..][..n]);
+                Ok(n)
+            }
+        }
+    }
+
+    fn write_at(&self, _buf: &[u8], _offset: u64) -> io::Result<usize> {
+        Err(io::ErrorKind::ReadOnlyFilesystem.into())
+    }
+
+    fn append(&self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::ReadOnlyFilesystem.into())
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        Ok(self.fs.metadata(self.ino))
+    }
+
+    fn set_size(&self, _size: types::Filesize) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn set_times(&self, _atim: types::NewTimestamp, _mtim: types::NewTimestamp) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+}
+
+/// Builds the tree of an archive from its entries.
+///
+/// Entries which can't be represented, such as those with paths leading
+/// outside of the archive or of special file types, are skipped. When several
+/// entries have the same path the last one wins, as when extracting.
+struct Builder {
+    nodes: Vec<Node>,
+    /// The modification time of the archive itself, for directories which
+    /// have no entry of their own.
+    modified: SystemTime,
+}
+
+impl Builder {
+    fn new(modified: SystemTime) -> Self {
+        Self {
+            nodes: vec![Node {
+                kind: Kind::Dir(BTreeMap::new()),
+                modified,
+            }],
+            modified,
+        }
+    }
+
+    fn read_tar(&mut self, file: &fs::File) -> anyhow::Result<()> {
+        use tar::EntryType;
+
+        let mut archive = tar::Archive::new(file);
+        for entry in archive.entries_with_seek()? {
+            let entry = entry?;
+            let Ok(path) = std::str::from_utf8(&entry.path_bytes()).map(str::to_owned) else {
+                continue;
+            };
+            let header = entry.header();
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(header.mtime()?);
+            let link_name = || {
+                entry
+                    .link_name_bytes()
+                    .and_then(|name| String::from_utf8(name.into_owned()).ok())
+            };
+            let kind = match header.entry_type() {
+                EntryType::Regular | EntryType::Continuous => Kind::File {
+                    size: entry.size(),
+                    data: Data::Stored(entry.raw_file_position()),
+                    link_count: 1,
+                },
+                EntryType::Directory => Kind::Dir(BTreeMap::new()),
+                EntryType::Symlink => match link_name() {
+                    Some(target) => Kind::Symlink(target),
+                    None => continue,
+                },
+                EntryType::Link => {
+                    if let Some(target) = link_name() {
+                        self.link(&path, &target);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            self.insert(&path, Node { kind, modified });
+        }
+        Ok(())
+    }
+
+    fn read_zip(&mut self, zip: &mut zip::ZipArchive<fs::File>) -> anyhow::Result<()> {
+        const S_IFMT: u32 = 0o170000;
+        const S_IFLNK: u32 = 0o120000;
+        // The longest target of a symbolic link, as `PATH_MAX` on Linux.
+        const MAX_LINK_TARGET: u64 = 4096;
+
+        for index in 0..zip.len() {
+            let Ok(file) = zip.by_index_raw(index) else {
+                continue;
+            };
+            let Some(path) = file.enclosed_name().and_then(|p| p.to_str()) else {
+                continue;
+            };
+            let path = path.replace('\\', "/");
+            let modified = zip_time(file.last_modified()).unwrap_or(self.modified);
+            let kind = if file.is_dir() {
+                Kind::Dir(BTreeMap::new())
+            } else if file.unix_mode().map(|m| m & S_IFMT) == Some(S_IFLNK) {
+                // The target of a symbolic link is its contents, which are
+                // read decompressed here.
+                drop(file);
+                let Ok(link) = zip.by_index(index) else {
+                    continue;
+                };
+                let mut target = String::new();
+                if link
+                    .take(MAX_LINK_TARGET + 1)
+                    .read_to_string(&mut target)
+                    .is_err()
+                    || target.len() as u64 > MAX_LINK_TARGET
+                {
+                    continue;
+                }
+                Kind::Symlink(target)
+            } else {
+                let data = match file.compression() {
+                    // Encrypted files have a header before their data, so
+                    // they're larger than their contents.
+                    zip::CompressionMethod::Stored if file.compressed_size() == file.size() => {
+                        Data::Stored(file.data_start())
+                    }
+                    _ => Data::Zip(index),
+                };
+                Kind::File {
+                    size: file.size(),
+                    data,
+                    link_count: 1,
+                }
+            };
+            self.insert(&path, Node { kind, modified });
+        }
+        Ok(())
+    }
+
+    /// Split `path` into the components of a path within the archive, or
+    /// return `None` if it leads outside of it.
+    fn components(path: &str) -> Option<Vec<&str>> {
+        let components = path
+            .split('/')
+            .filter(|c| !c.is_empty() && *c != ".")
+            .collect::<Vec<_>>();
+        if components.contains(&"..") {
+            return None;
+        }
+        Some(components)
+    }
+
+    /// Return the directory at `components`, creating it and its parents if
+    /// needed, or `None` if something else is in the way.
+    fn dir(&mut self, components: &[&str]) -> Option<usize> {
+        let mut dir = 0;
+        for name in components {
+            let Kind::Dir(entries) = &self.nodes[dir].kind else {
+                return None;
+            };
+            dir = match entries.get(*name) {
+                Some(&ino) => match self.nodes[ino].kind {
+                    Kind::Dir(_) => ino,
+                    _ => return None,
+                },
+                None => self.push(
+                    dir,
+                    name,
+                    Node {
+                        kind: Kind::Dir(BTreeMap::new()),
+                        modified: self.modified,
+                    },
+                ),
+            };
+        }
+        Some(dir)
+    }
+
+    /// Return the node at `components`, without creating anything, or `None`
+    /// if there's nothing there.
+    fn lookup(&self, components: &[&str]) -> Option<usize> {
+        let mut ino = 0;
+        for name in components {
+            let Kind::Dir(entries) = &self.nodes[ino].kind else {
+                return None;
+            };
+            ino = *entries.get(*name)?;
+        }
+        Some(ino)
+    }
+
+    fn push(&mut self, dir: usize, name: &str, node: Node) -> usize {
+        let ino = self.nodes.len();
+        self.nodes.push(node);
+        self.entries(dir).insert(name.to_string(), ino);
+        ino
+    }
+
+    fn entries(&mut self, dir: usize) -> &mut BTreeMap<String, usize> {
+        match &mut self.nodes[dir].kind {
+            Kind::Dir(entries) => entries,
+            _ => unreachable!("not a directory"),
+        }
+    }
+
+    fn insert(&mut self, path: &str, node: Node) {
+        let Some(components) = Self::components(path) else {
+            return;
+        };
+        let Some((name, parent)) = components.split_last() else {
+            // An entry for the root directory itself.
+            if let Kind::Dir(_) = node.kind {
+                self.nodes[0].modified = node.modified;
+            }
+            return;
+        };
+        let Some(parent) = self.dir(parent) else {
+            return;
+        };
+        if let Some(&existing) = self.entries(parent).get(*name) {
+            // Directories are merged rather than replaced, so that the
+            // entries already added to them are kept.
+            if let (Kind::Dir(_), Kind::Dir(_)) = (&node.kind, &self.nodes[existing].kind) {
+                self.nodes[existing].modified = node.modified;
+                return;
+            }
+        }
+        self.push(parent, name, node);
+    }
+
+    /// Add a hard link at `path` to the file at `target`, which must have been
+    /// added already.
+    fn link(&mut self, path: &str, target: &str) {
+        let (Some(path), Some(target)) = (Self::components(path), Self::components(target)) else {
+            return;
+        };
+        let Some((name, parent)) = path.split_last() else {
+            return;
+        };
+        let Some(ino) = self.lookup(&target) else {
+            return;
+        };
+        let Kind::File { link_count, .. } = &mut self.nodes[ino].kind else {
+            return;
+        };
+        *link_count += 1;
+        if let Some(parent) = self.dir(parent) {
+            self.entries(parent).insert(name.to_string(), ino);
+        }
+    }
+}
+
+/// Convert the modification time of a zip entry, which has no time zone and
+/// is taken to be UTC.
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    // Days since the epoch of a date of the proleptic Gregorian calendar, from
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let (year, month, day) = (
+        i64::from(time.year()),
+        i64::from(time.month()),
+        i64::from(time.day()),
+    );
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let secs = days * 86400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use types::{OpenFlags, PathFlags};
+
+    fn tar() -> ArchiveFilesystem {
+        let mut builder = tar::Builder::new(tempfile::tempfile().unwrap());
+        let mut append = |path: &str, kind: tar::EntryType, link: &str, contents: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_000_000_000);
+            if !link.is_empty() {
+                header.set_link_name(link).unwrap();
+            }
+            builder.append_data(&mut header, path, contents).unwrap();
+        };
+        append("./lib/", tar::EntryType::Directory, "", b"");
+        append("./lib/os.py", tar::EntryType::Regular, "", b"import sys\n");
+        append("./bin/python", tar::EntryType::Regular, "", b"#!python");
+        append("bin/python3", tar::EntryType::Symlink, "python", b"");
+        append("bin/py", tar::EntryType::Link, "bin/python", b"");
+        append("bin/gone", tar::EntryType::Link, "missing/python", b"");
+        append("escape", tar::EntryType::Symlink, "../etc/passwd", b"");
+        append("loop", tar::EntryType::Symlink, "loop", b"");
+        let file = builder.into_inner().unwrap();
+        ArchiveFilesystem::from_file(file).unwrap()
+    }
+
+    fn read(fs: &dyn WasiFilesystem, path: &str) -> FsResult<Vec<u8>> {
+        let Opened::File(file) = fs.open_at(
+            PathFlags::SYMLINK_FOLLOW,
+            path,
+            OpenFlags::empty(),
+            OpenMode::READ,
+        )?
+        else {
+            return Err(ErrorCode::IsDirectory.into());
+        };
+        let mut contents = vec![0; 64];
+        let n = file.read_at(&mut contents, 0)?;
+        contents.truncate(n);
+        Ok(contents)
+    }
+
+    fn error(result: FsResult<impl Sized>) -> ErrorCode {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.downcast().unwrap(),
+        }
+    }
+
+    #[test]
+    fn tar_contents() {
+        let fs = tar();
+        assert_eq!(read(&fs, "lib/os.py").unwrap(), b"import sys\n");
+        assert_eq!(read(&fs, "bin/python3").unwrap(), b"#!python");
+        assert_eq!(read(&fs, "bin/../bin/py").unwrap(), b"#!python");
+        assert_eq!(fs.readlink_at("bin/python3").unwrap(), "python");
+
+        let python = fs.stat_at(PathFlags::empty(), "bin/python").unwrap();
+        let py = fs.stat_at(PathFlags::empty(), "bin/py").unwrap();
+        assert_eq!(python.ino, py.ino);
+        assert_eq!(python.stat.link_count, 2);
+        assert_eq!(python.stat.size, 8);
+        assert_eq!(
+            python.stat.data_modification_timestamp.unwrap().seconds,
+            1_000_000_000
+        );
+        assert_eq!(
+            fs.stat_at(PathFlags::empty(), "bin/python3")
+                .unwrap()
+                .stat
+                .type_,
+            DescriptorType::SymbolicLink
+        );
+
+        let names = fs
+            .read_directory()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.unwrap().name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["bin", "escape", "lib", "loop"]);
+
+        assert_eq!(error(read(&fs, "escape")), ErrorCode::NotPermitted);
+        assert_eq!(error(read(&fs, "loop")), ErrorCode::Loop);
+        assert_eq!(error(read(&fs, "lib/missing")), ErrorCode::NoEntry);
+        // Hard links to nothing are skipped, without creating the directories
+        // of their targets.
+        assert_eq!(error(read(&fs, "bin/gone")), ErrorCode::NoEntry);
+    }
+
+    #[test]
+    fn tar_is_read_only() {
+        let fs = tar();
+        assert_eq!(
+            error(fs.open_at(
+                PathFlags::empty(),
+                "lib/os.py",
+                OpenFlags::empty(),
+                OpenMode::WRITE
+            )),
+            ErrorCode::ReadOnly
+        );
+        assert_eq!(
+            error(fs.open_at(
+                PathFlags::empty(),
+                "new.txt",
+                OpenFlags::CREATE,
+                OpenMode::WRITE
+            )),
+            ErrorCode::ReadOnly
+        );
+        assert_eq!(error(fs.create_directory_at("new")), ErrorCode::ReadOnly);
+        assert_eq!(error(fs.unlink_file_at("lib/os.py")), ErrorCode::ReadOnly);
+    }
+
+    #[test]
+    fn zip_contents() {
+        use zip::write::FileOptions;
+
+        let mut writer = zip::ZipWriter::new(tempfile::tempfile().unwrap());
+        let stored = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        writer.add_directory("empty/", stored).unwrap();
+        writer.start_file("a/stored.txt", stored).unwrap();
+        writer.write_all(b"stored contents").unwrap();
+        writer.start_file("a/deflated.txt", deflated).unwrap();
+        writer.write_all(&b"deflated contents ".repeat(3)).unwrap();
+        writer.add_symlink("link", "a/stored.txt", stored).unwrap();
+        writer
+            .add_symlink("long", "a/".repeat(4096), stored)
+            .unwrap();
+        let file = writer.finish().unwrap();
+
+        let fs = ArchiveFilesystem::from_file(file).unwrap();
+        assert_eq!(read(&fs, "a/stored.txt").unwrap(), b"stored contents");
+        assert_eq!(
+            read(&fs, "a/deflated.txt").unwrap(),
+            b"deflated contents ".repeat(3)
+        );
+        assert_eq!(read(&fs, "link").unwrap(), b"stored contents");
+        assert_eq!(
+            error(fs.stat_at(PathFlags::empty(), "long")),
+            ErrorCode::NoEntry
+        );
+        assert_eq!(
+            fs.stat_at(PathFlags::empty(), "empty").unwrap().stat.type_,
+            DescriptorType::Directory
+        );
+    }
+
+    #[test]
+    fn zip_decompressed_contents() {
+        use std::io::{Seek, SeekFrom};
+        use zip::write::FileOptions;
+
+        let mut writer = zip::ZipWriter::new(tempfile::tempfile().unwrap());
+        let deflated = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        writer.start_file("deflated.txt", deflated).unwrap();
+        writer.write_all(&b"deflated contents ".repeat(3)).unwrap();
+        let mut file = writer.finish().unwrap();
+
+        // Claim in the central directory that the file is much larger than it
+        // is, which must not be trusted.
+        let mut bytes = Vec::new();
+        file.rewind().unwrap();
+        file.read_to_end(&mut bytes).unwrap();
+        let central = bytes.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        file.seek(SeekFrom::Start(central as u64 + 24)).unwrap();
+        file.write_all(&0xffff_fff0u32.to_le_bytes()).unwrap();
+
+        let fs = ArchiveFilesystem::from_file(file).unwrap();
+        let stat = fs.stat_at(PathFlags::empty(), "deflated.txt").unwrap();
+        assert_eq!(stat.stat.size, 0xffff_fff0);
+        let cached = || {
+            let zip = fs.archive.zip.as_ref().unwrap().lock().unwrap();
+            zip.decompressed.get(&1).and_then(Weak::upgrade)
+        };
+        let open = || match fs
+            .open_at(
+                PathFlags::empty(),
+                "deflated.txt",
+                OpenFlags::empty(),
+                OpenMode::READ,
+            )
+            .unwrap()
+        {
+            Opened::File(file) => file,
+            Opened::Dir(_) => unreachable!(),
+        };
+
+        // Handles open at the same time share the decompressed contents,
+        // which are freed once they're all closed.
+        let a = open();
+        let contents = cached().unwrap();
+        assert_eq!(&contents[..], b"deflated contents ".repeat(3));
+        let b = open();
+        assert!(Arc::ptr_eq(&contents, &cached().unwrap()));
+        let mut buf = [0; 8];
+        assert_eq!(b.read_at(&mut buf, 9).unwrap(), 8);
+        assert_eq!(&buf, b"contents");
+        drop((a, b, contents));
+        assert!(cached().is_none());
+        assert_eq!(
+            read(&fs, "deflated.txt").unwrap(),
+            b"deflated contents ".repeat(3)
+        );
+    }
+}
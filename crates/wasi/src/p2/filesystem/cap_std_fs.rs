@@ -0,0 +1,409 @@
+use super::{FsResult, Metadata, Opened, WasiFile, WasiFilesystem};
+use crate::OpenMode;
+use crate::p2::bindings::clocks::wall_clock;
+use crate::p2::bindings::filesystem::types::{self, ErrorCode};
+use cap_fs_ext::{DirExt, MetadataExt};
+use fs_set_times::SetTimes;
+use std::any::Any;
+use std::io;
+use std::path::Path;
+use system_interface::fs::{FdFlags, FileIoExt, GetSetFdFlags};
+
+/// A [`WasiFilesystem`] backed by a directory of the host's filesystem.
+///
+/// This is what [`WasiCtxBuilder::preopened_dir`] uses. Paths are resolved
+/// with [`cap_std`], which prevents them from escaping the directory.
+///
+/// [`WasiCtxBuilder::preopened_dir`]: crate::p2::WasiCtxBuilder::preopened_dir
+pub struct CapStdFilesystem(cap_std::fs::Dir);
+
+impl CapStdFilesystem {
+    /// Open the host directory at `path`.
+    pub fn open_ambient(path: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = cap_std::fs::Dir::open_ambient_dir(path.as_ref(), cap_std::ambient_authority())?;
+        Ok(Self(dir))
+    }
+
+    /// Return the underlying directory.
+    pub fn dir(&self) -> &cap_std::fs::Dir {
+        &self.0
+    }
+}
+
+impl From<cap_std::fs::Dir> for CapStdFilesystem {
+    fn from(dir: cap_std::fs::Dir) -> Self {
+        Self(dir)
+    }
+}
+
+/// A [`WasiFile`] opened through a [`CapStdFilesystem`].
+pub struct CapStdFile(cap_std::fs::File);
+
+impl CapStdFile {
+    /// Return the underlying file.
+    pub fn file(&self) -> &cap_std::fs::File {
+        &self.0
+    }
+}
+
+impl From<cap_std::fs::File> for CapStdFile {
+    fn from(file: cap_std::fs::File) -> Self {
+        Self(file)
+    }
+}
+
+impl WasiFilesystem for CapStdFilesystem {
+    fn open_at(
+        &self,
+        path_flags: types::PathFlags,
+        path: &str,
+        oflags: types::OpenFlags,
+        open_mode: OpenMode,
+    ) -> FsResult<Opened> {
+        use cap_fs_ext::{FollowSymlinks, OpenOptionsFollowExt, OpenOptionsMaybeDirExt};
+        use types::OpenFlags;
+
+        // Construct the OpenOptions to give the OS:
+        let mut opts = cap_std::fs::OpenOptions::new();
+        opts.maybe_dir(true);
+
+        if oflags.contains(OpenFlags::CREATE) {
+            if oflags.contains(OpenFlags::EXCLUSIVE) {
+                opts.create_new(true);
+            } else {
+                opts.create(true);
+            }
+        }
+        if oflags.contains(OpenFlags::TRUNCATE) {
+            opts.truncate(true).write(true);
+        }
+        if open_mode.contains(OpenMode::READ) {
+            opts.read(true);
+        }
+        if open_mode.contains(OpenMode::WRITE) {
+            opts.write(true);
+        }
+        if symlink_follow(path_flags) {
+            opts.follow(FollowSymlinks::Yes);
+        } else {
+            opts.follow(FollowSymlinks::No);
+        }
+
+        let mut opened = self.0.open_with(path, &opts)?;
+        if opened.metadata()?.is_dir() {
+            Ok(Opened::Dir(Box::new(CapStdFilesystem(
+                cap_std::fs::Dir::from_std_file(opened.into_std()),
+            ))))
+        } else if oflags.contains(OpenFlags::DIRECTORY) {
+            Err(ErrorCode::NotDirectory.into())
+        } else {
+            // FIXME cap-std needs a nonblocking open option so that files reads and writes
+            // are nonblocking. Instead we set it after opening here:
+            let set_fd_flags = opened.new_set_fd_flags(FdFlags::NONBLOCK)?;
+            opened.set_fd_flags(set_fd_flags)?;
+            Ok(Opened::File(Box::new(CapStdFile(opened))))
+        }
+    }
+
+    fn create_directory_at(&self, path: &str) -> FsResult<()> {
+        Ok(self.0.create_dir(path)?)
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        Ok(metadata_from(self.0.dir_metadata()?))
+    }
+
+    fn stat_at(&self, path_flags: types::PathFlags, path: &str) -> FsResult<Metadata> {
+        let meta = if symlink_follow(path_flags) {
+            self.0.metadata(path)?
+        } else {
+            self.0.symlink_metadata(path)?
+        };
+        Ok(metadata_from(meta))
+    }
+
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()> {
+        let atim = systemtimespec_from(atim)?;
+        let mtim = systemtimespec_from(mtim)?;
+        Ok(SetTimes::set_times(&self.0, atim, mtim)?)
+    }
+
+    fn set_times_at(
+        &self,
+        path_flags: types::PathFlags,
+        path: &str,
+        atim: types::NewTimestamp,
+        mtim: types::NewTimestamp,
+    ) -> FsResult<()> {
+        let atim = systemtimespec_from(atim)?.map(cap_fs_ext::SystemTimeSpec::from_std);
+        let mtim = systemtimespec_from(mtim)?.map(cap_fs_ext::SystemTimeSpec::from_std);
+        if symlink_follow(path_flags) {
+            DirExt::set_times(&self.0, path, atim, mtim)?;
+        } else {
+            self.0.set_symlink_times(path, atim, mtim)?;
+        }
+        Ok(())
+    }
+
+    fn read_directory(&self) -> FsResult<Vec<FsResult<types::DirectoryEntry>>> {
+        // Both `entries` and `metadata` perform syscalls, which is why they are
+        // done here, rather than delay calculating the metadata for entries
+        // when they're demanded later.
+        let entries = self
+            .0
+            .entries()?
+            .map(|entry| {
+                let entry = entry?;
+                let meta = entry.metadata()?;
+                Ok((descriptortype_from(meta.file_type()), entry.file_name()))
+            })
+            .collect::<Vec<io::Result<_>>>();
+
+        // On windows, filter out files like `C:\DumpStack.log.tmp` which we
+        // can't get full metadata for.
+        #[cfg(windows)]
+        let entries = entries.into_iter().filter(|entry| {
+            use windows_sys::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION};
+            if let Err(err) = entry {
+                if err.raw_os_error() == Some(ERROR_SHARING_VIOLATION as i32)
+                    || err.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32)
+                {
+                    return false;
+                }
+            }
+            true
+        });
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                let (type_, name) = entry?;
+                let name = name
+                    .into_string()
+                    .map_err(|_| ErrorCode::IllegalByteSequence)?;
+                Ok(types::DirectoryEntry { type_, name })
+            })
+            .collect())
+    }
+
+    fn link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiFilesystem,
+        new_path: &str,
+    ) -> FsResult<()> {
+        let new_dir = downcast(new_dir)?;
+        Ok(self.0.hard_link(old_path, &new_dir.0, new_path)?)
+    }
+
+    fn readlink_at(&self, path: &str) -> FsResult<String> {
+        let link = self.0.read_link(path)?;
+        Ok(link
+            .into_os_string()
+            .into_string()
+            .map_err(|_| ErrorCode::IllegalByteSequence)?)
+    }
+
+    fn remove_directory_at(&self, path: &str) -> FsResult<()> {
+        Ok(self.0.remove_dir(path)?)
+    }
+
+    fn rename_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiFilesystem,
+        new_path: &str,
+    ) -> FsResult<()> {
+        let new_dir = downcast(new_dir)?;
+        Ok(self.0.rename(old_path, &new_dir.0, new_path)?)
+    }
+
+    fn symlink_at(&self, src_path: &str, dest_path: &str) -> FsResult<()> {
+        Ok(self.0.symlink(src_path, dest_path)?)
+    }
+
+    fn unlink_file_at(&self, path: &str) -> FsResult<()> {
+        Ok(self.0.remove_file_or_symlink(path)?)
+    }
+
+    fn sync(&self) -> FsResult<()> {
+        Ok(self.0.open(std::path::Component::CurDir)?.sync_all()?)
+    }
+
+    fn sync_data(&self) -> FsResult<()> {
+        Ok(self.0.open(std::path::Component::CurDir)?.sync_data()?)
+    }
+
+    fn get_flags(&self) -> FsResult<types::DescriptorFlags> {
+        Ok(descriptorflags_from(self.0.get_fd_flags()?))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl WasiFile for CapStdFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.0.read_at(buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.0.write_at(buf, offset)
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        self.0.append(buf)
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        Ok(metadata_from(self.0.metadata()?))
+    }
+
+    fn set_size(&self, size: types::Filesize) -> FsResult<()> {
+        Ok(self.0.set_len(size)?)
+    }
+
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()> {
+        let atim = systemtimespec_from(atim)?;
+        let mtim = systemtimespec_from(mtim)?;
+        Ok(SetTimes::set_times(&self.0, atim, mtim)?)
+    }
+
+    fn advise(
+        &self,
+        offset: types::Filesize,
+        len: types::Filesize,
+        advice: types::Advice,
+    ) -> FsResult<()> {
+        use system_interface::fs::Advice as A;
+        use types::Advice;
+
+        let advice = match advice {
+            Advice::Normal => A::Normal,
+            Advice::Sequential => A::Sequential,
+            Advice::Random => A::Random,
+            Advice::WillNeed => A::WillNeed,
+            Advice::DontNeed => A::DontNeed,
+            Advice::NoReuse => A::NoReuse,
+        };
+        Ok(self.0.advise(offset, len, advice)?)
+    }
+
+    fn sync(&self) -> FsResult<()> {
+        match self.0.sync_all() {
+            Ok(()) => Ok(()),
+            // On windows, `sync_data` uses `FileFlushBuffers` which fails with
+            // `ERROR_ACCESS_DENIED` if the file is not upen for writing. Ignore
+            // this error, for POSIX compatibility.
+            #[cfg(windows)]
+            Err(e)
+                if e.raw_os_error()
+                    == Some(windows_sys::Win32::Foundation::ERROR_ACCESS_DENIED as _) =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn sync_data(&self) -> FsResult<()> {
+        match self.0.sync_data() {
+            Ok(()) => Ok(()),
+            // See `sync` above.
+            #[cfg(windows)]
+            Err(e)
+                if e.raw_os_error()
+                    == Some(windows_sys::Win32::Foundation::ERROR_ACCESS_DENIED as _) =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn get_flags(&self) -> FsResult<types::DescriptorFlags> {
+        Ok(descriptorflags_from(self.0.get_fd_flags()?))
+    }
+}
+
+fn downcast(dir: &dyn WasiFilesystem) -> FsResult<&CapStdFilesystem> {
+    dir.as_any()
+        .downcast_ref()
+        .ok_or_else(|| ErrorCode::CrossDevice.into())
+}
+
+fn symlink_follow(path_flags: types::PathFlags) -> bool {
+    path_flags.contains(types::PathFlags::SYMLINK_FOLLOW)
+}
+
+fn descriptorflags_from(flags: FdFlags) -> types::DescriptorFlags {
+    use types::DescriptorFlags;
+
+    let mut out = DescriptorFlags::empty();
+    if flags.contains(FdFlags::DSYNC) {
+        out |= DescriptorFlags::REQUESTED_WRITE_SYNC;
+    }
+    if flags.contains(FdFlags::RSYNC) {
+        out |= DescriptorFlags::DATA_INTEGRITY_SYNC;
+    }
+    if flags.contains(FdFlags::SYNC) {
+        out |= DescriptorFlags::FILE_INTEGRITY_SYNC;
+    }
+    out
+}
+
+fn descriptortype_from(ft: cap_std::fs::FileType) -> types::DescriptorType {
+    use cap_fs_ext::FileTypeExt;
+    use types::DescriptorType;
+    if ft.is_dir() {
+        DescriptorType::Directory
+    } else if ft.is_symlink() {
+        DescriptorType::SymbolicLink
+    } else if ft.is_block_device() {
+        DescriptorType::BlockDevice
+    } else if ft.is_char_device() {
+        DescriptorType::CharacterDevice
+    } else if ft.is_file() {
+        DescriptorType::RegularFile
+    } else {
+        DescriptorType::Unknown
+    }
+}
+
+fn systemtimespec_from(t: types::NewTimestamp) -> FsResult<Option<fs_set_times::SystemTimeSpec>> {
+    use fs_set_times::SystemTimeSpec;
+    use types::NewTimestamp;
+    match t {
+        NewTimestamp::NoChange => Ok(None),
+        NewTimestamp::Now => Ok(Some(SystemTimeSpec::SymbolicNow)),
+        NewTimestamp::Timestamp(st) => Ok(Some(SystemTimeSpec::Absolute(systemtime_from(st)?))),
+    }
+}
+
+pub(super) fn systemtime_from(t: wall_clock::Datetime) -> FsResult<std::time::SystemTime> {
+    use std::time::{Duration, SystemTime};
+    SystemTime::UNIX_EPOCH
+        .checked_add(Duration::new(t.seconds, t.nanoseconds))
+        .ok_or_else(|| ErrorCode::Overflow.into())
+}
+
+pub(super) fn datetime_from(t: std::time::SystemTime) -> wall_clock::Datetime {
+    // FIXME make this infallible or handle errors properly
+    wall_clock::Datetime::try_from(cap_std::time::SystemTime::from_std(t)).unwrap()
+}
+
+fn metadata_from(meta: cap_std::fs::Metadata) -> Metadata {
+    Metadata {
+        stat: types::DescriptorStat {
+            type_: descriptortype_from(meta.file_type()),
+            link_count: meta.nlink(),
+            size: meta.len(),
+            data_access_timestamp: meta.accessed().map(|t| datetime_from(t.into_std())).ok(),
+            data_modification_timestamp: meta.modified().map(|t| datetime_from(t.into_std())).ok(),
+            status_change_timestamp: meta.created().map(|t| datetime_from(t.into_std())).ok(),
+        },
+        dev: meta.dev(),
+        ino: meta.ino(),
+    }
+}
@@ -0,0 +1,864 @@
+use super::cap_std_fs::{datetime_from, systemtime_from};
+use super::{FsResult, Metadata, Opened, WasiFile, WasiFilesystem};
+use crate::OpenMode;
+use crate::p2::bindings::filesystem::types::{self, DescriptorType, ErrorCode};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::SystemTime;
+
+/// A [`WasiFilesystem`] which keeps its files in memory.
+///
+/// This can be used to give guests files without a directory on the host, for
+/// example read-only assets shared by many stores. Clones share the same
+/// files, as do the directories opened within them, so files written by a
+/// guest are visible to every store the filesystem was preopened in.
+///
+/// Hard links are supported but symbolic links aren't.
+///
+/// # Examples
+///
+/// ```
+/// use wasmtime_wasi::p2::{InMemoryFilesystem, WasiCtxBuilder};
+/// use wasmtime_wasi::{DirPerms, FilePerms};
+///
+/// # fn main() -> wasmtime::Result<()> {
+/// let assets = InMemoryFilesystem::new();
+/// assets.insert_file("index.html", "<h1>Hello</h1>")?;
+/// assets.insert_file("css/site.css", "h1 { color: red }")?;
+///
+/// let mut wasi = WasiCtxBuilder::new();
+/// wasi.preopened_filesystem(assets.clone(), "/assets", DirPerms::READ, FilePerms::READ);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct InMemoryFilesystem {
+    tree: Arc<Tree>,
+    /// The directory of `tree` which this handle refers to.
+    ino: u64,
+}
+
+struct Tree {
+    /// Identifies this tree among all in-memory filesystems, to report as the
+    /// device of its files.
+    dev: u64,
+    nodes: Mutex<Nodes>,
+}
+
+struct Nodes {
+    next_ino: u64,
+    nodes: HashMap<u64, Node>,
+}
+
+enum Node {
+    Dir(DirNode),
+    File(Arc<FileNode>),
+}
+
+struct DirNode {
+    /// The directory containing this one, or itself for the root.
+    parent: u64,
+    entries: BTreeMap<String, u64>,
+    accessed: SystemTime,
+    modified: SystemTime,
+}
+
+struct FileNode {
+    ino: u64,
+    dev: u64,
+    data: RwLock<FileData>,
+}
+
+struct FileData {
+    contents: Vec<u8>,
+    link_count: u64,
+    accessed: SystemTime,
+    modified: SystemTime,
+}
+
+/// Where a path leads: the directory containing its last component along with
+/// the name of that component, or just a directory for paths like `.`.
+enum Walked<'a> {
+    Entry(u64, &'a str),
+    Dir(u64),
+}
+
+impl InMemoryFilesystem {
+    /// Create a new filesystem with an empty root directory.
+    pub fn new() -> Self {
+        let now = SystemTime::now();
+        let root = Node::Dir(DirNode {
+            parent: 0,
+            entries: BTreeMap::new(),
+            accessed: now,
+            modified: now,
+        });
+        Self {
+            tree: Arc::new(Tree {
+                dev: super::unique_dev(),
+                nodes: Mutex::new(Nodes {
+                    next_ino: 1,
+                    nodes: HashMap::from([(0, root)]),
+                }),
+            }),
+            ino: 0,
+        }
+    }
+
+    /// Create or replace the file at `path` with `contents`, creating its
+    /// parent directories as needed.
+    pub fn insert_file(&self, path: &str, contents: impl Into<Vec<u8>>) -> anyhow::Result<()> {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let dir = self.insert_dir_all(parent)?;
+        let mut nodes = self.nodes();
+        if let Walked::Entry(parent, name) = nodes.walk(dir, name)? {
+            match nodes.lookup(parent, name) {
+                Ok(ino) => {
+                    let file = nodes.file(ino)?;
+                    let mut data = file.write();
+                    data.contents = contents.into();
+                    data.modified = SystemTime::now();
+                }
+                Err(_) => {
+                    nodes.create_file(self.tree.dev, parent, name, contents.into())?;
+                }
+            }
+            return Ok(());
+        }
+        Err(ErrorCode::IsDirectory.into())
+    }
+
+    /// Create the directory at `path` and its parents, if they don't exist
+    /// yet.
+    pub fn insert_dir(&self, path: &str) -> anyhow::Result<()> {
+        self.insert_dir_all(path)?;
+        Ok(())
+    }
+
+    /// Return the contents of the file at `path`, if there's one.
+    pub fn read_file(&self, path: &str) -> Option<Vec<u8>> {
+        let nodes = self.nodes();
+        let ino = nodes.lookup_path(self.ino, path).ok()?;
+        let file = nodes.file(ino).ok()?;
+        Some(file.read().contents.clone())
+    }
+
+    fn insert_dir_all(&self, path: &str) -> FsResult<u64> {
+        let mut nodes = self.nodes();
+        let mut dir = self.ino;
+        for name in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            dir = match nodes.walk(dir, name)? {
+                Walked::Dir(dir) => dir,
+                Walked::Entry(parent, name) => match nodes.lookup(parent, name) {
+                    Ok(ino) => {
+                        nodes.dir(ino)?;
+                        ino
+                    }
+                    Err(_) => nodes.create_dir(parent, name)?,
+                },
+            };
+        }
+        Ok(dir)
+    }
+
+    fn nodes(&self) -> MutexGuard<'_, Nodes> {
+        // Updates to the tree are done in a way that a panic can't leave it
+        // inconsistent, so ignore poisoning.
+        self.tree.nodes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn handle(&self, ino: u64) -> Self {
+        Self {
+            tree: self.tree.clone(),
+            ino,
+        }
+    }
+
+    /// Downcast `dir` to an `InMemoryFilesystem` sharing the same tree.
+    fn same_tree<'a>(&self, dir: &'a dyn WasiFilesystem) -> FsResult<&'a Self> {
+        match dir.as_any().downcast_ref::<Self>() {
+            Some(dir) if Arc::ptr_eq(&self.tree, &dir.tree) => Ok(dir),
+            _ => Err(ErrorCode::CrossDevice.into()),
+        }
+    }
+}
+
+impl Default for InMemoryFilesystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Nodes {
+    fn node(&self, ino: u64) -> FsResult<&Node> {
+        // A directory may have been removed while a descriptor for it is
+        // still open.
+        self.nodes
+            .get(&ino)
+            .ok_or_else(|| ErrorCode::NoEntry.into())
+    }
+
+    fn dir(&self, ino: u64) -> FsResult<&DirNode> {
+        match self.node(ino)? {
+            Node::Dir(dir) => Ok(dir),
+            Node::File(_) => Err(ErrorCode::NotDirectory.into()),
+        }
+    }
+
+    fn dir_mut(&mut self, ino: u64) -> FsResult<&mut DirNode> {
+        match self.nodes.get_mut(&ino) {
+            Some(Node::Dir(dir)) => Ok(dir),
+            Some(Node::File(_)) => Err(ErrorCode::NotDirectory.into()),
+            None => Err(ErrorCode::NoEntry.into()),
+        }
+    }
+
+    fn file(&self, ino: u64) -> FsResult<&Arc<FileNode>> {
+        match self.node(ino)? {
+            Node::File(file) => Ok(file),
+            Node::Dir(_) => Err(ErrorCode::IsDirectory.into()),
+        }
+    }
+
+    /// Follow `path` from the directory `start`, without leaving it.
+    fn walk<'a>(&self, start: u64, path: &'a str) -> FsResult<Walked<'a>> {
+        if path.is_empty() {
+            return Err(ErrorCode::NoEntry.into());
+        }
+        if path.starts_with('/') {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let mut stack = vec![start];
+        let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+        while let Some(component) = components.next() {
+            let dir = *stack.last().unwrap();
+            self.dir(dir)?;
+            match component {
+                "." => {}
+                ".." => {
+                    stack.pop();
+                    if stack.is_empty() {
+                        return Err(ErrorCode::NotPermitted.into());
+                    }
+                }
+                name if components.peek().is_none() => return Ok(Walked::Entry(dir, name)),
+                name => stack.push(self.lookup(dir, name)?),
+            }
+        }
+        let dir = *stack.last().unwrap();
+        self.dir(dir)?;
+        Ok(Walked::Dir(dir))
+    }
+
+    fn lookup(&self, dir: u64, name: &str) -> FsResult<u64> {
+        self.dir(dir)?
+            .entries
+            .get(name)
+            .copied()
+            .ok_or_else(|| ErrorCode::NoEntry.into())
+    }
+
+    fn lookup_path(&self, start: u64, path: &str) -> FsResult<u64> {
+        match self.walk(start, path)? {
+            Walked::Entry(dir, name) => self.lookup(dir, name),
+            Walked::Dir(dir) => Ok(dir),
+        }
+    }
+
+    fn insert(&mut self, dir: u64, name: &str, node: Node) -> FsResult<u64> {
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.nodes.insert(ino, node);
+        self.link(dir, name, ino)?;
+        Ok(ino)
+    }
+
+    fn link(&mut self, dir: u64, name: &str, ino: u64) -> FsResult<()> {
+        let dir = self.dir_mut(dir)?;
+        dir.entries.insert(name.to_string(), ino);
+        dir.modified = SystemTime::now();
+        Ok(())
+    }
+
+    fn unlink(&mut self, dir: u64, name: &str) -> FsResult<()> {
+        let dir = self.dir_mut(dir)?;
+        let ino = dir.entries.remove(name).ok_or(ErrorCode::NoEntry)?;
+        dir.modified = SystemTime::now();
+        match self.node(ino)? {
+            Node::Dir(_) => {
+                self.nodes.remove(&ino);
+            }
+            Node::File(file) => {
+                let mut data = file.write();
+                data.link_count -= 1;
+                if data.link_count == 0 {
+                    drop(data);
+                    // Open descriptors keep the file's data alive.
+                    self.nodes.remove(&ino);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn create_dir(&mut self, parent: u64, name: &str) -> FsResult<u64> {
+        let now = SystemTime::now();
+        self.insert(
+            parent,
+            name,
+            Node::Dir(DirNode {
+                parent,
+                entries: BTreeMap::new(),
+                accessed: now,
+                modified: now,
+            }),
+        )
+    }
+
+    fn create_file(
+        &mut self,
+        dev: u64,
+        parent: u64,
+        name: &str,
+        contents: Vec<u8>,
+    ) -> FsResult<Arc<FileNode>> {
+        let now = SystemTime::now();
+        let file = Arc::new(FileNode {
+            ino: self.next_ino,
+            dev,
+            data: RwLock::new(FileData {
+                contents,
+                link_count: 1,
+                accessed: now,
+                modified: now,
+            }),
+        });
+        self.insert(parent, name, Node::File(file.clone()))?;
+        Ok(file)
+    }
+
+    /// Whether `ino` is `dir` or one of its ancestors.
+    fn is_ancestor(&self, ino: u64, mut dir: u64) -> FsResult<bool> {
+        loop {
+            if dir == ino {
+                return Ok(true);
+            }
+            let parent = self.dir(dir)?.parent;
+            if parent == dir {
+                return Ok(false);
+            }
+            dir = parent;
+        }
+    }
+
+    fn metadata(&self, dev: u64, ino: u64) -> FsResult<Metadata> {
+        match self.node(ino)? {
+            Node::Dir(dir) => Ok(Metadata {
+                stat: types::DescriptorStat {
+                    type_: DescriptorType::Directory,
+                    link_count: 1,
+                    size: 0,
+                    data_access_timestamp: Some(datetime_from(dir.accessed)),
+                    data_modification_timestamp: Some(datetime_from(dir.modified)),
+                    status_change_timestamp: Some(datetime_from(dir.modified)),
+                },
+                dev,
+                ino,
+            }),
+            Node::File(file) => file.stat(),
+        }
+    }
+
+    fn set_times(
+        &mut self,
+        ino: u64,
+        atim: types::NewTimestamp,
+        mtim: types::NewTimestamp,
+    ) -> FsResult<()> {
+        match self.nodes.get_mut(&ino).ok_or(ErrorCode::NoEntry)? {
+            Node::Dir(dir) => {
+                set_time(&mut dir.accessed, atim)?;
+                set_time(&mut dir.modified, mtim)
+            }
+            Node::File(file) => file.set_times(atim, mtim),
+        }
+    }
+}
+
+impl WasiFilesystem for InMemoryFilesystem {
+    fn open_at(
+        &self,
+        _path_flags: types::PathFlags,
+        path: &str,
+        oflags: types::OpenFlags,
+        open_mode: OpenMode,
+    ) -> FsResult<Opened> {
+        use types::OpenFlags;
+
+        let mut nodes = self.nodes();
+        let existing = match nodes.walk(self.ino, path)? {
+            Walked::Entry(dir, name) => match nodes.lookup(dir, name) {
+                Ok(ino) => Some(ino),
+                Err(_) if oflags.contains(OpenFlags::CREATE) => {
+                    let file = nodes.create_file(self.tree.dev, dir, name, Vec::new())?;
+                    return Ok(Opened::File(Box::new(InMemoryFile::new(file, open_mode))));
+                }
+                Err(e) => return Err(e),
+            },
+            Walked::Dir(dir) => Some(dir),
+        };
+        let ino = existing.unwrap();
+        if oflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) {
+            return Err(ErrorCode::Exist.into());
+        }
+        match nodes.node(ino)? {
+            Node::Dir(_) => {
+                if open_mode.contains(OpenMode::WRITE) || oflags.contains(OpenFlags::TRUNCATE) {
+                    return Err(ErrorCode::IsDirectory.into());
+                }
+                Ok(Opened::Dir(Box::new(self.handle(ino))))
+            }
+            Node::File(file) => {
+                if oflags.contains(OpenFlags::DIRECTORY) {
+                    return Err(ErrorCode::NotDirectory.into());
+                }
+                if oflags.contains(OpenFlags::TRUNCATE) {
+                    let mut data = file.write();
+                    data.contents.clear();
+                    data.modified = SystemTime::now();
+                }
+                Ok(Opened::File(Box::new(InMemoryFile::new(
+                    file.clone(),
+                    open_mode,
+                ))))
+            }
+        }
+    }
+
+    fn create_directory_at(&self, path: &str) -> FsResult<()> {
+        let mut nodes = self.nodes();
+        match nodes.walk(self.ino, path)? {
+            Walked::Entry(dir, name) if nodes.lookup(dir, name).is_err() => {
+                nodes.create_dir(dir, name)?;
+                Ok(())
+            }
+            _ => Err(ErrorCode::Exist.into()),
+        }
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        self.nodes().metadata(self.tree.dev, self.ino)
+    }
+
+    fn stat_at(&self, _path_flags: types::PathFlags, path: &str) -> FsResult<Metadata> {
+        let nodes = self.nodes();
+        let ino = nodes.lookup_path(self.ino, path)?;
+        nodes.metadata(self.tree.dev, ino)
+    }
+
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()> {
+        self.nodes().set_times(self.ino, atim, mtim)
+    }
+
+    fn set_times_at(
+        &self,
+        _path_flags: types::PathFlags,
+        path: &str,
+        atim: types::NewTimestamp,
+        mtim: types::NewTimestamp,
+    ) -> FsResult<()> {
+        let mut nodes = self.nodes();
+        let ino = nodes.lookup_path(self.ino, path)?;
+        nodes.set_times(ino, atim, mtim)
+    }
+
+    fn read_directory(&self) -> FsResult<Vec<FsResult<types::DirectoryEntry>>> {
+        let nodes = self.nodes();
+        let dir = nodes.dir(self.ino)?;
+        Ok(dir
+            .entries
+            .iter()
+            .map(|(name, ino)| {
+                let type_ = match nodes.node(*ino)? {
+                    Node::Dir(_) => DescriptorType::Directory,
+                    Node::File(_) => DescriptorType::RegularFile,
+                };
+                Ok(types::DirectoryEntry {
+                    type_,
+                    name: name.clone(),
+                })
+            })
+            .collect())
+    }
+
+    fn link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiFilesystem,
+        new_path: &str,
+    ) -> FsResult<()> {
+        let new_dir = self.same_tree(new_dir)?;
+        let mut nodes = self.nodes();
+        let ino = nodes.lookup_path(self.ino, old_path)?;
+        let file = match nodes.node(ino)? {
+            Node::File(file) => file.clone(),
+            Node::Dir(_) => return Err(ErrorCode::NotPermitted.into()),
+        };
+        match nodes.walk(new_dir.ino, new_path)? {
+            Walked::Entry(dir, name) if nodes.lookup(dir, name).is_err() => {
+                nodes.link(dir, name, ino)?;
+                file.write().link_count += 1;
+                Ok(())
+            }
+            _ => Err(ErrorCode::Exist.into()),
+        }
+    }
+
+    fn readlink_at(&self, path: &str) -> FsResult<String> {
+        self.nodes().lookup_path(self.ino, path)?;
+        Err(ErrorCode::Invalid.into())
+    }
+
+    fn remove_directory_at(&self, path: &str) -> FsResult<()> {
+        let mut nodes = self.nodes();
+        let Walked::Entry(dir, name) = nodes.walk(self.ino, path)? else {
+            return Err(ErrorCode::Invalid.into());
+        };
+        let ino = nodes.lookup(dir, name)?;
+        if !nodes.dir(ino)?.entries.is_empty() {
+            return Err(ErrorCode::NotEmpty.into());
+        }
+        nodes.unlink(dir, name)
+    }
+
+    fn rename_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiFilesystem,
+        new_path: &str,
+    ) -> FsResult<()> {
+        let new_dir = self.same_tree(new_dir)?;
+        let mut nodes = self.nodes();
+        let (Walked::Entry(old_parent, old_name), Walked::Entry(new_parent, new_name)) = (
+            nodes.walk(self.ino, old_path)?,
+            nodes.walk(new_dir.ino, new_path)?,
+        ) else {
+            return Err(ErrorCode::Invalid.into());
+        };
+        let ino = nodes.lookup(old_parent, old_name)?;
+        let is_dir = matches!(nodes.node(ino)?, Node::Dir(_));
+        if is_dir && nodes.is_ancestor(ino, new_parent)? {
+            return Err(ErrorCode::Invalid.into());
+        }
+        if let Ok(existing) = nodes.lookup(new_parent, new_name) {
+            if existing == ino {
+                return Ok(());
+            }
+            match (is_dir, nodes.node(existing)?) {
+                (true, Node::Dir(dir)) if !dir.entries.is_empty() => {
+                    return Err(ErrorCode::NotEmpty.into());
+                }
+                (true, Node::File(_)) => return Err(ErrorCode::NotDirectory.into()),
+                (false, Node::Dir(_)) => return Err(ErrorCode::IsDirectory.into()),
+                _ => {}
+            }
+            nodes.unlink(new_parent, new_name)?;
+        }
+        let old = nodes.dir_mut(old_parent)?;
+        old.entries.remove(old_name);
+        old.modified = SystemTime::now();
+        nodes.link(new_parent, new_name, ino)?;
+        if is_dir {
+            nodes.dir_mut(ino)?.parent = new_parent;
+        }
+        Ok(())
+    }
+
+    fn symlink_at(&self, _src_path: &str, _dest_path: &str) -> FsResult<()> {
+        Err(ErrorCode::Unsupported.into())
+    }
+
+    fn unlink_file_at(&self, path: &str) -> FsResult<()> {
+        let mut nodes = self.nodes();
+        let Walked::Entry(dir, name) = nodes.walk(self.ino, path)? else {
+            return Err(ErrorCode::IsDirectory.into());
+        };
+        let ino = nodes.lookup(dir, name)?;
+        nodes.file(ino)?;
+        nodes.unlink(dir, name)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A [`WasiFile`] opened through an [`InMemoryFilesystem`].
+struct InMemoryFile {
+    node: Arc<FileNode>,
+    /// Whether this handle may modify the file. Other handles to the same
+    /// node may still be writable.
+    writable: bool,
+}
+
+impl InMemoryFile {
+    fn new(node: Arc<FileNode>, open_mode: OpenMode) -> Self {
+        Self {
+            node,
+            writable: open_mode.contains(OpenMode::WRITE),
+        }
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if self.writable {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::PermissionDenied.into())
+        }
+    }
+}
+
+impl FileNode {
+    fn read(&self) -> RwLockReadGuard<'_, FileData> {
+        self.data.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, FileData> {
+        self.data.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        let data = self.read();
+        Ok(Metadata {
+            stat: types::DescriptorStat {
+                type_: DescriptorType::RegularFile,
+                link_count: data.link_count,
+                size: data.contents.len().try_into().map_err(ErrorCode::from)?,
+                data_access_timestamp: Some(datetime_from(data.accessed)),
+                data_modification_timestamp: Some(datetime_from(data.modified)),
+                status_change_timestamp: Some(datetime_from(data.modified)),
+            },
+            dev: self.dev,
+            ino: self.ino,
+        })
+    }
+
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()> {
+        let mut data = self.write();
+        set_time(&mut data.accessed, atim)?;
+        set_time(&mut data.modified, mtim)
+    }
+}
+
+impl WasiFile for InMemoryFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let data = self.node.read();
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(data.contents.len());
+        let n = buf.len().min(data.contents.len() - start);
+        buf[..n].copy_from_slice(&data.contents[start..][..n]);
+        Ok(n)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        // As on Unix, writing nothing past the end doesn't extend the file.
+        self.check_writable()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut data = self.node.write();
+        let start = usize::try_from(offset).map_err(|_| io::ErrorKind::FileTooLarge)?;
+        let end = start
+            .checked_add(buf.len())
+            .ok_or(io::ErrorKind::FileTooLarge)?;
+        if data.contents.len() < end {
+            data.contents.resize(end, 0);
+        }
+        data.contents[start..end].copy_from_slice(buf);
+        data.modified = SystemTime::now();
+        Ok(buf.len())
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        self.check_writable()?;
+        let mut data = self.node.write();
+        data.contents.extend_from_slice(buf);
+        data.modified = SystemTime::now();
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        self.node.stat()
+    }
+
+    fn set_size(&self, size: types::Filesize) -> FsResult<()> {
+        self.check_writable()?;
+        let mut data = self.node.write();
+        data.contents
+            .resize(size.try_into().map_err(ErrorCode::from)?, 0);
+        data.modified = SystemTime::now();
+        Ok(())
+    }
+
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()> {
+        self.check_writable()?;
+        self.node.set_times(atim, mtim)
+    }
+}
+
+fn set_time(time: &mut SystemTime, new: types::NewTimestamp) -> FsResult<()> {
+    match new {
+        types::NewTimestamp::NoChange => {}
+        types::NewTimestamp::Now => *time = SystemTime::now(),
+        types::NewTimestamp::Timestamp(t) => *time = systemtime_from(t)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use types::{OpenFlags, PathFlags};
+
+    fn open_file(dir: &dyn WasiFilesystem, path: &str, oflags: OpenFlags) -> Box<dyn WasiFile> {
+        match dir
+            .open_at(PathFlags::empty(), path, oflags, OpenMode::all())
+            .unwrap()
+        {
+            Opened::File(file) => file,
+            Opened::Dir(_) => panic!("{path} is a directory"),
+        }
+    }
+
+    fn error(result: FsResult<impl Sized>) -> ErrorCode {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.downcast().unwrap(),
+        }
+    }
+
+    #[test]
+    fn write_and_rename() {
+        let fs = InMemoryFilesystem::new();
+        fs.create_directory_at("a").unwrap();
+        let file = open_file(&fs, "a/f.txt", OpenFlags::CREATE);
+        assert_eq!(file.write_at(b"world", 6).unwrap(), 5);
+        assert_eq!(file.write_at(b"hello", 0).unwrap(), 5);
+        assert_eq!(fs.read_file("a/f.txt").unwrap(), b"hello\0world");
+        assert_eq!(file.write_at(b"", 100).unwrap(), 0);
+        assert_eq!(fs.read_file("a/f.txt").unwrap(), b"hello\0world");
+
+        fs.rename_at("a", &fs, "b").unwrap();
+        assert!(fs.read_file("a/f.txt").is_none());
+        assert_eq!(fs.read_file("b/f.txt").unwrap(), b"hello\0world");
+
+        // The open file still refers to the renamed file.
+        file.append(b"!").unwrap();
+        assert_eq!(fs.read_file("b/f.txt").unwrap(), b"hello\0world!");
+
+        fs.create_directory_at("b/c").unwrap();
+        assert_eq!(error(fs.rename_at("b", &fs, "b/c/d")), ErrorCode::Invalid);
+        assert_eq!(error(fs.remove_directory_at("b")), ErrorCode::NotEmpty);
+    }
+
+    #[test]
+    fn read_only_handles() {
+        let fs = InMemoryFilesystem::new();
+        fs.insert_file("f", "data").unwrap();
+        let Opened::File(file) = fs
+            .open_at(PathFlags::empty(), "f", OpenFlags::empty(), OpenMode::READ)
+            .unwrap()
+        else {
+            panic!("f is a file");
+        };
+        let modified = |file: &dyn WasiFile| {
+            let stat = file.stat().unwrap().stat;
+            stat.data_modification_timestamp.unwrap().seconds
+        };
+        let before = modified(&*file);
+
+        assert_eq!(error(file.set_size(0)), ErrorCode::NotPermitted);
+        assert_eq!(
+            error(file.set_times(
+                types::NewTimestamp::Now,
+                types::NewTimestamp::Timestamp(types::Datetime {
+                    seconds: 0,
+                    nanoseconds: 0,
+                }),
+            )),
+            ErrorCode::NotPermitted
+        );
+        assert!(file.write_at(b"x", 0).is_err());
+        assert!(file.append(b"x").is_err());
+
+        assert_eq!(fs.read_file("f").unwrap(), b"data");
+        assert_eq!(modified(&*file), before);
+    }
+
+    #[test]
+    fn links() {
+        let fs = InMemoryFilesystem::new();
+        fs.insert_file("f", "data").unwrap();
+        fs.link_at("f", &fs, "g").unwrap();
+        assert_eq!(
+            fs.stat_at(PathFlags::empty(), "g").unwrap().stat.link_count,
+            2
+        );
+
+        let file = open_file(&fs, "g", OpenFlags::empty());
+        fs.unlink_file_at("f").unwrap();
+        fs.unlink_file_at("g").unwrap();
+        assert_eq!(
+            error(fs.stat_at(PathFlags::empty(), "g")),
+            ErrorCode::NoEntry
+        );
+
+        // Unlinked files stay readable while they're open.
+        let mut buf = [0; 8];
+        assert_eq!(file.read_at(&mut buf, 0).unwrap(), 4);
+        assert_eq!(&buf[..4], b"data");
+
+        let other = InMemoryFilesystem::new();
+        fs.insert_file("f", "data").unwrap();
+        assert_eq!(error(fs.link_at("f", &other, "f")), ErrorCode::CrossDevice);
+    }
+
+    #[test]
+    fn paths_stay_inside() {
+        let fs = InMemoryFilesystem::new();
+        fs.insert_file("a/b/f", "").unwrap();
+        let Opened::Dir(a) = fs
+            .open_at(
+                PathFlags::empty(),
+                "a",
+                OpenFlags::DIRECTORY,
+                OpenMode::READ,
+            )
+            .unwrap()
+        else {
+            panic!("a is a directory");
+        };
+
+        assert!(a.stat_at(PathFlags::empty(), "b/../b/./f").is_ok());
+        assert_eq!(
+            error(a.stat_at(PathFlags::empty(), "../a")),
+            ErrorCode::NotPermitted
+        );
+        assert_eq!(
+            error(a.stat_at(PathFlags::empty(), "/a")),
+            ErrorCode::NotPermitted
+        );
+        assert_eq!(
+            error(fs.open_at(
+                PathFlags::empty(),
+                "a/b/f",
+                OpenFlags::DIRECTORY,
+                OpenMode::READ
+            )),
+            ErrorCode::NotDirectory
+        );
+    }
+}
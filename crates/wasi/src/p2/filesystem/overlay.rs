@@ -0,0 +1,838 @@
+use super::{FsResult, Metadata, Opened, WasiFile, WasiFilesystem};
+use crate::OpenMode;
+use crate::p2::bindings::filesystem::types::{
+    self, DescriptorType, ErrorCode, NewTimestamp, OpenFlags, PathFlags,
+};
+use std::any::Any;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The number of symbolic links followed when resolving a path before giving
+/// up with [`ErrorCode::Loop`], as on Linux.
+const MAX_SYMLINKS: u32 = 40;
+
+/// A [`WasiFilesystem`] which presents one filesystem on top of another,
+/// keeping all changes in the upper one.
+///
+/// Guests see the files of both layers, with those of the upper layer taking
+/// precedence. A file or directory of the lower layer is copied to the upper
+/// layer before it's changed, and removing one from the lower layer only hides
+/// it, so the lower layer is never modified. This lets a guest which expects a
+/// writable directory work in a directory of the host without copying it
+/// first, and without the host seeing any of its changes.
+///
+/// The upper layer is usually an empty [`InMemoryFilesystem`], which is what
+/// [`WasiCtxBuilder::preopened_dir_overlay`] uses, or an empty scratch
+/// directory opened as a [`CapStdFilesystem`].
+///
+/// Directories of the lower layer can't be renamed, as that would mean copying
+/// everything in them, so this fails with [`ErrorCode::CrossDevice`] like on
+/// Linux's overlayfs. Files opened before being copied to the upper layer keep
+/// reading the lower one.
+///
+/// [`InMemoryFilesystem`]: super::InMemoryFilesystem
+/// [`CapStdFilesystem`]: super::CapStdFilesystem
+/// [`WasiCtxBuilder::preopened_dir_overlay`]: crate::p2::WasiCtxBuilder::preopened_dir_overlay
+///
+/// # Examples
+///
+/// ```
+/// use wasmtime_wasi::p2::{CapStdFilesystem, OverlayFilesystem, WasiCtxBuilder};
+/// use wasmtime_wasi::{DirPerms, FilePerms};
+///
+/// # fn main() {}
+/// # fn foo() -> wasmtime::Result<()> {
+/// // Let the guest change `./src` while keeping its changes in `./scratch`.
+/// let overlay = OverlayFilesystem::new(
+///     CapStdFilesystem::open_ambient("./src")?,
+///     CapStdFilesystem::open_ambient("./scratch")?,
+/// );
+///
+/// let mut wasi = WasiCtxBuilder::new();
+/// wasi.preopened_filesystem(overlay, "/src", DirPerms::all(), FilePerms::all());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct OverlayFilesystem {
+    overlay: Arc<Overlay>,
+    /// The directory which this handle refers to, relative to the root of the
+    /// overlay and without any symbolic links, `.` or `..`. This is empty for
+    /// the root.
+    path: String,
+}
+
+struct Overlay {
+    lower: Box<dyn WasiFilesystem>,
+    upper: Box<dyn WasiFilesystem>,
+    /// Paths at and below which the contents of `lower` are hidden, because
+    /// they were removed or replaced.
+    hidden: Mutex<BTreeSet<String>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Layer {
+    Lower,
+    Upper,
+}
+
+/// The overlay, locked for the duration of an operation so that the layers
+/// and the hidden paths are kept consistent with each other.
+struct Locked<'a> {
+    lower: &'a dyn WasiFilesystem,
+    upper: &'a dyn WasiFilesystem,
+    hidden: MutexGuard<'a, BTreeSet<String>>,
+}
+
+impl OverlayFilesystem {
+    /// Create an overlay presenting `upper` on top of `lower`.
+    ///
+    /// `lower` is only ever read from. `upper` is usually empty to begin with,
+    /// but any files it has already take precedence over those of `lower`.
+    pub fn new(lower: impl WasiFilesystem, upper: impl WasiFilesystem) -> Self {
+        Self {
+            overlay: Arc::new(Overlay {
+                lower: Box::new(lower),
+                upper: Box::new(upper),
+                hidden: Mutex::new(BTreeSet::new()),
+            }),
+            path: String::new(),
+        }
+    }
+
+    fn lock(&self) -> Locked<'_> {
+        Locked {
+            lower: &*self.overlay.lower,
+            upper: &*self.overlay.upper,
+            // The set of hidden paths is only updated once the layers have
+            // been, so it's consistent even if an update panicked.
+            hidden: self
+                .overlay
+                .hidden
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+
+    fn handle(&self, path: String) -> Self {
+        Self {
+            overlay: self.overlay.clone(),
+            path,
+        }
+    }
+
+    /// Downcast `dir` to an `OverlayFilesystem` sharing the same layers.
+    fn same_overlay<'a>(&self, dir: &'a dyn WasiFilesystem) -> FsResult<&'a Self> {
+        match dir.as_any().downcast_ref::<Self>() {
+            Some(dir) if Arc::ptr_eq(&self.overlay, &dir.overlay) => Ok(dir),
+            _ => Err(ErrorCode::CrossDevice.into()),
+        }
+    }
+}
+
+impl Locked<'_> {
+    fn layer(&self, layer: Layer) -> &dyn WasiFilesystem {
+        match layer {
+            Layer::Lower => self.lower,
+            Layer::Upper => self.upper,
+        }
+    }
+
+    fn is_hidden(&self, path: &str) -> bool {
+        path.match_indices('/')
+            .map(|(i, _)| &path[..i])
+            .chain([path])
+            .any(|prefix| self.hidden.contains(prefix))
+    }
+
+    /// Return the metadata of `path`, without following symbolic links, along
+    /// with the layer it's from, or `None` if there's nothing at `path`.
+    fn stat(&self, path: &str) -> FsResult<Option<(Layer, Metadata)>> {
+        match self.upper.stat_at(PathFlags::empty(), at(path)) {
+            Ok(meta) => return Ok(Some((Layer::Upper, meta))),
+            Err(e) if !is_missing(&e) => return Err(e),
+            Err(_) => {}
+        }
+        if self.is_hidden(path) {
+            return Ok(None);
+        }
+        match self.lower.stat_at(PathFlags::empty(), at(path)) {
+            Ok(meta) => Ok(Some((Layer::Lower, meta))),
+            Err(e) if !is_missing(&e) => Err(e),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Like [`Self::stat`], but failing if there's nothing at `path`.
+    fn entry(&self, path: &str) -> FsResult<(Layer, Metadata)> {
+        self.stat(path)?.ok_or_else(|| ErrorCode::NoEntry.into())
+    }
+
+    /// Whether the lower layer has something visible at `path`.
+    fn in_lower(&self, path: &str) -> FsResult<bool> {
+        if self.is_hidden(path) {
+            return Ok(false);
+        }
+        match self.lower.stat_at(PathFlags::empty(), at(path)) {
+            Ok(_) => Ok(true),
+            Err(e) if is_missing(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Resolve `path` from the directory `base`, without leaving it, into a
+    /// path from the root of the overlay without symbolic links, `.` or `..`.
+    ///
+    /// Symbolic links are followed, except for the last component of `path`
+    /// if `follow` is false. The last component needn't exist.
+    fn resolve(&self, base: &str, path: &str, follow: bool) -> FsResult<String> {
+        if path.is_empty() {
+            return Err(ErrorCode::NoEntry.into());
+        }
+        if path.starts_with('/') {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        let mut resolved = Vec::new();
+        let mut components = path
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .map(str::to_owned)
+            .collect::<VecDeque<_>>();
+        let mut links = 0;
+        while let Some(component) = components.pop_front() {
+            match component.as_str() {
+                "." => {}
+                ".." => {
+                    if resolved.pop().is_none() {
+                        return Err(ErrorCode::NotPermitted.into());
+                    }
+                }
+                _ => {
+                    resolved.push(component);
+                    let last = components.is_empty();
+                    let full = join(base, &resolved);
+                    let Some((layer, meta)) = self.stat(&full)? else {
+                        if last {
+                            break;
+                        }
+                        return Err(ErrorCode::NoEntry.into());
+                    };
+                    match meta.stat.type_ {
+                        DescriptorType::Directory => {}
+                        DescriptorType::SymbolicLink if !last || follow => {
+                            links += 1;
+                            if links > MAX_SYMLINKS {
+                                return Err(ErrorCode::Loop.into());
+                            }
+                            let target = self.layer(layer).readlink_at(&full)?;
+                            if target.starts_with('/') {
+                                return Err(ErrorCode::NotPermitted.into());
+                            }
+                            resolved.pop();
+                            for c in target.rsplit('/').filter(|c| !c.is_empty()) {
+                                components.push_front(c.to_owned());
+                            }
+                        }
+                        _ if last => {}
+                        _ => return Err(ErrorCode::NotDirectory.into()),
+                    }
+                }
+            }
+        }
+        Ok(join(base, &resolved))
+    }
+
+    /// Return the entries of the directory at `path`, merging those of both
+    /// layers.
+    fn read_dir(&self, path: &str) -> FsResult<Vec<FsResult<types::DirectoryEntry>>> {
+        let mut entries = Vec::new();
+        let mut names = HashSet::new();
+        if let Some(dir) = open_dir(self.upper, path)? {
+            for entry in dir.read_directory()? {
+                if let Ok(entry) = &entry {
+                    names.insert(entry.name.clone());
+                }
+                entries.push(entry);
+            }
+        }
+        if !self.is_hidden(path) {
+            if let Some(dir) = open_dir(self.lower, path)? {
+                for entry in dir.read_directory()? {
+                    if let Ok(entry) = &entry {
+                        if names.contains(&entry.name)
+                            || self.is_hidden(&join(path, &[&entry.name]))
+                        {
+                            continue;
+                        }
+                    }
+                    entries.push(entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Make sure that the upper layer has the directory at `path`, and all of
+    /// its parents, copying them from the lower layer as needed.
+    fn copy_up_dirs(&self, path: &str) -> FsResult<()> {
+        if path.is_empty() {
+            return Ok(());
+        }
+        for prefix in path
+            .match_indices('/')
+            .map(|(i, _)| &path[..i])
+            .chain([path])
+        {
+            match self.upper.stat_at(PathFlags::empty(), prefix) {
+                Ok(meta) if meta.stat.type_ == DescriptorType::Directory => continue,
+                Ok(_) => return Err(ErrorCode::NotDirectory.into()),
+                Err(e) if !is_missing(&e) => return Err(e),
+                Err(_) => {}
+            }
+            self.upper.create_directory_at(prefix)?;
+            if let Ok(meta) = self.lower.stat_at(PathFlags::empty(), prefix) {
+                self.copy_times(prefix, &meta)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Make sure that the file, directory or symbolic link at `path` is in
+    /// the upper layer, copying it from the lower layer if needed.
+    fn copy_up(&self, path: &str) -> FsResult<()> {
+        let (layer, meta) = self.entry(path)?;
+        if layer == Layer::Upper {
+            return Ok(());
+        }
+        self.copy_up_dirs(parent(path))?;
+        match meta.stat.type_ {
+            DescriptorType::Directory => self.copy_up_dirs(path),
+            DescriptorType::SymbolicLink => {
+                let target = self.lower.readlink_at(path)?;
+                self.upper.symlink_at(&target, path)
+            }
+            DescriptorType::RegularFile => {
+                let src = open_file(self.lower, path, OpenFlags::empty(), OpenMode::READ)?;
+                let dst = open_file(
+                    self.upper,
+                    path,
+                    OpenFlags::CREATE | OpenFlags::TRUNCATE,
+                    OpenMode::WRITE,
+                )?;
+                let mut buf = vec![0; 64 * 1024];
+                let mut offset = 0;
+                loop {
+                    let n = src.read_at(&mut buf, offset)?;
+                    if n == 0 {
+                        break;
+                    }
+                    let mut written = 0;
+                    while written < n {
+                        match dst.write_at(&buf[written..n], offset + written as u64)? {
+                            0 => return Err(ErrorCode::Io.into()),
+                            m => written += m,
+                        }
+                    }
+                    offset += n as u64;
+                }
+                self.copy_times(path, &meta)
+            }
+            // Devices, sockets and the like can't be copied.
+            _ => Err(ErrorCode::NotPermitted.into()),
+        }
+    }
+
+    fn copy_times(&self, path: &str, meta: &Metadata) -> FsResult<()> {
+        let time =
+            |t: Option<types::Datetime>| t.map_or(NewTimestamp::NoChange, NewTimestamp::Timestamp);
+        self.upper.set_times_at(
+            PathFlags::empty(),
+            path,
+            time(meta.stat.data_access_timestamp),
+            time(meta.stat.data_modification_timestamp),
+        )
+    }
+}
+
+impl WasiFilesystem for OverlayFilesystem {
+    fn open_at(
+        &self,
+        path_flags: types::PathFlags,
+        path: &str,
+        oflags: types::OpenFlags,
+        open_mode: OpenMode,
+    ) -> FsResult<Opened> {
+        let mut fs = self.lock();
+        let path = fs.resolve(
+            &self.path,
+            path,
+            path_flags.contains(PathFlags::SYMLINK_FOLLOW),
+        )?;
+        let writes = open_mode.contains(OpenMode::WRITE) || oflags.contains(OpenFlags::TRUNCATE);
+        let mut created = false;
+        let layer = match fs.stat(&path)? {
+            None if oflags.contains(OpenFlags::CREATE) => {
+                fs.copy_up_dirs(parent(&path))?;
+                created = true;
+                Layer::Upper
+            }
+            None => return Err(ErrorCode::NoEntry.into()),
+            Some(_) if oflags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) => {
+                return Err(ErrorCode::Exist.into());
+            }
+            Some((_, meta)) if meta.stat.type_ == DescriptorType::Directory => {
+                if writes {
+                    return Err(ErrorCode::IsDirectory.into());
+                }
+                return Ok(Opened::Dir(Box::new(self.handle(path))));
+            }
+            Some(_) if oflags.contains(OpenFlags::DIRECTORY) => {
+                return Err(ErrorCode::NotDirectory.into());
+            }
+            Some((Layer::Lower, _)) if writes => {
+                fs.copy_up(&path)?;
+                Layer::Upper
+            }
+            Some((layer, _)) => layer,
+        };
+        let opened = match fs
+            .layer(layer)
+            .open_at(path_flags, at(&path), oflags, open_mode)?
+        {
+            Opened::File(file) if layer == Layer::Lower => Opened::File(Box::new(LowerFile(file))),
+            Opened::File(file) => Opened::File(file),
+            Opened::Dir(_) => Opened::Dir(Box::new(self.handle(path.clone()))),
+        };
+        if created {
+            fs.hidden.insert(path);
+        }
+        Ok(opened)
+    }
+
+    fn create_directory_at(&self, path: &str) -> FsResult<()> {
+        let mut fs = self.lock();
+        let path = fs.resolve(&self.path, path, false)?;
+        if fs.stat(&path)?.is_some() {
+            return Err(ErrorCode::Exist.into());
+        }
+        fs.copy_up_dirs(parent(&path))?;
+        fs.upper.create_directory_at(&path)?;
+        fs.hidden.insert(path);
+        Ok(())
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        Ok(self.lock().entry(&self.path)?.1)
+    }
+
+    fn stat_at(&self, path_flags: types::PathFlags, path: &str) -> FsResult<Metadata> {
+        let fs = self.lock();
+        let path = fs.resolve(
+            &self.path,
+            path,
+            path_flags.contains(PathFlags::SYMLINK_FOLLOW),
+        )?;
+        Ok(fs.entry(&path)?.1)
+    }
+
+    fn set_times(&self, atim: types::NewTimestamp, mtim: types::NewTimestamp) -> FsResult<()> {
+        let fs = self.lock();
+        fs.copy_up(&self.path)?;
+        fs.upper
+            .set_times_at(PathFlags::empty(), at(&self.path), atim, mtim)
+    }
+
+    fn set_times_at(
+        &self,
+        path_flags: types::PathFlags,
+        path: &str,
+        atim: types::NewTimestamp,
+        mtim: types::NewTimestamp,
+    ) -> FsResult<()> {
+        let fs = self.lock();
+        let path = fs.resolve(
+            &self.path,
+            path,
+            path_flags.contains(PathFlags::SYMLINK_FOLLOW),
+        )?;
+        fs.copy_up(&path)?;
+        fs.upper.set_times_at(path_flags, at(&path), atim, mtim)
+    }
+
+    fn read_directory(&self) -> FsResult<Vec<FsResult<types::DirectoryEntry>>> {
+        let fs = self.lock();
+        fs.entry(&self.path)?;
+        fs.read_dir(&self.path)
+    }
+
+    fn link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiFilesystem,
+        new_path: &str,
+    ) -> FsResult<()> {
+        let new_dir = self.same_overlay(new_dir)?;
+        let mut fs = self.lock();
+        let old = fs.resolve(&self.path, old_path, false)?;
+        let new = fs.resolve(&new_dir.path, new_path, false)?;
+        if fs.entry(&old)?.1.stat.type_ == DescriptorType::Directory {
+            return Err(ErrorCode::NotPermitted.into());
+        }
+        if fs.stat(&new)?.is_some() {
+            return Err(ErrorCode::Exist.into());
+        }
+        fs.copy_up(&old)?;
+        fs.copy_up_dirs(parent(&new))?;
+        fs.upper.link_at(&old, fs.upper, &new)?;
+        fs.hidden.insert(new);
+        Ok(())
+    }
+
+    fn readlink_at(&self, path: &str) -> FsResult<String> {
+        let fs = self.lock();
+        let path = fs.resolve(&self.path, path, false)?;
+        let (layer, _) = fs.entry(&path)?;
+        fs.layer(layer).readlink_at(at(&path))
+    }
+
+    fn remove_directory_at(&self, path: &str) -> FsResult<()> {
+        let mut fs = self.lock();
+        let path = fs.resolve(&self.path, path, false)?;
+        let (layer, meta) = fs.entry(&path)?;
+        if meta.stat.type_ != DescriptorType::Directory {
+            return Err(ErrorCode::NotDirectory.into());
+        }
+        if path == self.path {
+            return Err(ErrorCode::Invalid.into());
+        }
+        if !fs.read_dir(&path)?.is_empty() {
+            return Err(ErrorCode::NotEmpty.into());
+        }
+        if layer == Layer::Upper {
+            fs.upper.remove_directory_at(&path)?;
+        }
+        fs.hidden.insert(path);
+        Ok(())
+    }
+
+    fn rename_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiFilesystem,
+        new_path: &str,
+    ) -> FsResult<()> {
+        let new_dir = self.same_overlay(new_dir)?;
+        let mut fs = self.lock();
+        let old = fs.resolve(&self.path, old_path, false)?;
+        let new = fs.resolve(&new_dir.path, new_path, false)?;
+        let is_dir = fs.entry(&old)?.1.stat.type_ == DescriptorType::Directory;
+        if old == new {
+            return Ok(());
+        }
+        if is_dir && (old.is_empty() || new.starts_with(&format!("{old}/"))) {
+            return Err(ErrorCode::Invalid.into());
+        }
+        if let Some((_, meta)) = fs.stat(&new)? {
+            match (is_dir, meta.stat.type_ == DescriptorType::Directory) {
+                (true, true) => {
+                    if !fs.read_dir(&new)?.is_empty() {
+                        return Err(ErrorCode::NotEmpty.into());
+                    }
+                }
+                (true, false) => return Err(ErrorCode::NotDirectory.into()),
+                (false, true) => return Err(ErrorCode::IsDirectory.into()),
+                (false, false) => {}
+            }
+        }
+        if is_dir && fs.in_lower(&old)? {
+            return Err(ErrorCode::CrossDevice.into());
+        }
+        fs.copy_up(&old)?;
+        fs.copy_up_dirs(parent(&new))?;
+        fs.upper.rename_at(&old, fs.upper, &new)?;
+        fs.hidden.insert(old);
+        fs.hidden.insert(new);
+        Ok(())
+    }
+
+    fn symlink_at(&self, src_path: &str, dest_path: &str) -> FsResult<()> {
+        let mut fs = self.lock();
+        let path = fs.resolve(&self.path, dest_path, false)?;
+        if fs.stat(&path)?.is_some() {
+            return Err(ErrorCode::Exist.into());
+        }
+        fs.copy_up_dirs(parent(&path))?;
+        fs.upper.symlink_at(src_path, &path)?;
+        fs.hidden.insert(path);
+        Ok(())
+    }
+
+    fn unlink_file_at(&self, path: &str) -> FsResult<()> {
+        let mut fs = self.lock();
+        let path = fs.resolve(&self.path, path, false)?;
+        let (layer, meta) = fs.entry(&path)?;
+        if meta.stat.type_ == DescriptorType::Directory {
+            return Err(ErrorCode::IsDirectory.into());
+        }
+        if layer == Layer::Upper {
+            fs.upper.unlink_file_at(&path)?;
+        }
+        fs.hidden.insert(path);
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A file of the lower layer, opened without copying it up because it was
+/// only opened for reading.
+///
+/// The lower layer may still allow changes through such a file, such as
+/// adjusting the timestamps of a file of the host, so they're refused here.
+struct LowerFile(Box<dyn WasiFile>);
+
+impl WasiFile for LowerFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.0.read_at(buf, offset)
+    }
+
+    fn write_at(&self, _buf: &[u8], _offset: u64) -> io::Result<usize> {
+        Err(io::ErrorKind::ReadOnlyFilesystem.into())
+    }
+
+    fn append(&self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::ReadOnlyFilesystem.into())
+    }
+
+    fn stat(&self) -> FsResult<Metadata> {
+        self.0.stat()
+    }
+
+    fn set_size(&self, _size: types::Filesize) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn set_times(&self, _atim: NewTimestamp, _mtim: NewTimestamp) -> FsResult<()> {
+        Err(ErrorCode::ReadOnly.into())
+    }
+
+    fn advise(
+        &self,
+        offset: types::Filesize,
+        len: types::Filesize,
+        advice: types::Advice,
+    ) -> FsResult<()> {
+        self.0.advise(offset, len, advice)
+    }
+
+    fn get_flags(&self) -> FsResult<types::DescriptorFlags> {
+        self.0.get_flags()
+    }
+}
+
+/// Whether `error` means that there's nothing at a path.
+fn is_missing(error: &super::FsError) -> bool {
+    matches!(
+        error.downcast_ref(),
+        Some(ErrorCode::NoEntry | ErrorCode::NotDirectory)
+    )
+}
+
+/// Return `path` as a path for a layer, in which the root is `.`.
+fn at(path: &str) -> &str {
+    if path.is_empty() { "." } else { path }
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn join(base: &str, components: &[impl AsRef<str>]) -> String {
+    let mut path = base.to_owned();
+    for c in components {
+        if !path.is_empty() {
+            path.push('/');
+        }
+        path.push_str(c.as_ref());
+    }
+    path
+}
+
+fn open_dir(fs: &dyn WasiFilesystem, path: &str) -> FsResult<Option<Box<dyn WasiFilesystem>>> {
+    match fs.open_at(
+        PathFlags::empty(),
+        at(path),
+        OpenFlags::DIRECTORY,
+        OpenMode::READ,
+    ) {
+        Ok(Opened::Dir(dir)) => Ok(Some(dir)),
+        Ok(Opened::File(_)) => Ok(None),
+        Err(e) if is_missing(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn open_file(
+    fs: &dyn WasiFilesystem,
+    path: &str,
+    oflags: OpenFlags,
+    open_mode: OpenMode,
+) -> FsResult<Box<dyn WasiFile>> {
+    match fs.open_at(PathFlags::empty(), path, oflags, open_mode)? {
+        Opened::File(file) => Ok(file),
+        Opened::Dir(_) => Err(ErrorCode::IsDirectory.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::p2::InMemoryFilesystem;
+
+    fn error(result: FsResult<impl Sized>) -> ErrorCode {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.downcast().unwrap(),
+        }
+    }
+
+    fn names(dir: &dyn WasiFilesystem) -> Vec<String> {
+        let mut names = dir
+            .read_directory()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.unwrap().name)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    fn layers() -> (InMemoryFilesystem, InMemoryFilesystem, OverlayFilesystem) {
+        let lower = InMemoryFilesystem::new();
+        lower.insert_file("src/main.c", "int main() {}").unwrap();
+        lower.insert_file("src/util.c", "void util() {}").unwrap();
+        lower.insert_file("README", "hello").unwrap();
+        let upper = InMemoryFilesystem::new();
+        let overlay = OverlayFilesystem::new(lower.clone(), upper.clone());
+        (lower, upper, overlay)
+    }
+
+    #[test]
+    fn writes_go_to_upper() {
+        let (lower, upper, overlay) = layers();
+
+        let file = open_file(&overlay, "src/main.c", OpenFlags::empty(), OpenMode::all()).unwrap();
+        file.append(b" // edited").unwrap();
+        assert_eq!(lower.read_file("src/main.c").unwrap(), b"int main() {}");
+        assert_eq!(
+            upper.read_file("src/main.c").unwrap(),
+            b"int main() {} // edited"
+        );
+
+        let file = open_file(&overlay, "build/out.o", OpenFlags::CREATE, OpenMode::WRITE);
+        assert_eq!(error(file), ErrorCode::NoEntry);
+        overlay.create_directory_at("build").unwrap();
+        open_file(&overlay, "build/out.o", OpenFlags::CREATE, OpenMode::WRITE).unwrap();
+        assert!(lower.read_file("build/out.o").is_none());
+        assert_eq!(upper.read_file("build/out.o").unwrap(), b"");
+
+        assert_eq!(names(&overlay), ["README", "build", "src"]);
+        let Opened::Dir(src) = overlay
+            .open_at(
+                PathFlags::empty(),
+                "src",
+                OpenFlags::DIRECTORY,
+                OpenMode::READ,
+            )
+            .unwrap()
+        else {
+            panic!("src is a file");
+        };
+        assert_eq!(names(&*src), ["main.c", "util.c"]);
+        assert_eq!(
+            src.stat_at(PathFlags::empty(), "main.c").unwrap().stat.size,
+            23
+        );
+    }
+
+    #[test]
+    fn lower_files_stay_unchanged() {
+        let (lower, upper, overlay) = layers();
+        let modified = |fs: &dyn WasiFilesystem| {
+            let meta = fs.stat_at(PathFlags::empty(), "README").unwrap();
+            meta.stat.data_modification_timestamp.unwrap()
+        };
+        let before = modified(&lower);
+
+        let file = open_file(&overlay, "README", OpenFlags::empty(), OpenMode::READ).unwrap();
+        let mut buf = [0; 8];
+        assert_eq!(file.read_at(&mut buf, 0).unwrap(), 5);
+        assert_eq!(error(file.set_size(0)), ErrorCode::ReadOnly);
+        let epoch = types::Datetime {
+            seconds: 0,
+            nanoseconds: 0,
+        };
+        assert_eq!(
+            error(file.set_times(
+                NewTimestamp::Timestamp(epoch),
+                NewTimestamp::Timestamp(epoch)
+            )),
+            ErrorCode::ReadOnly
+        );
+        assert!(file.write_at(b"x", 0).is_err());
+
+        assert_eq!(lower.read_file("README").unwrap(), b"hello");
+        assert_eq!(modified(&lower).seconds, before.seconds);
+        assert!(upper.read_file("README").is_none());
+        assert_eq!(modified(&overlay).seconds, before.seconds);
+    }
+
+    #[test]
+    fn removals_are_hidden() {
+        let (lower, _upper, overlay) = layers();
+
+        overlay.unlink_file_at("src/util.c").unwrap();
+        assert_eq!(
+            error(overlay.stat_at(PathFlags::empty(), "src/util.c")),
+            ErrorCode::NoEntry
+        );
+        assert!(lower.read_file("src/util.c").is_some());
+
+        assert_eq!(
+            error(overlay.remove_directory_at("src")),
+            ErrorCode::NotEmpty
+        );
+        overlay.unlink_file_at("src/main.c").unwrap();
+        overlay.remove_directory_at("src").unwrap();
+        assert_eq!(names(&overlay), ["README"]);
+
+        // A directory created in place of a removed one doesn't show what the
+        // lower layer has there.
+        overlay.create_directory_at("src").unwrap();
+        assert!(names(&overlay.handle("src".to_owned())).is_empty());
+
+        overlay.rename_at("README", &overlay, "src/README").unwrap();
+        assert_eq!(names(&overlay), ["src"]);
+        assert_eq!(lower.read_file("README").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn lower_directories_cant_be_renamed() {
+        let (_lower, _upper, overlay) = layers();
+        assert_eq!(
+            error(overlay.rename_at("src", &overlay, "lib")),
+            ErrorCode::CrossDevice
+        );
+
+        overlay.create_directory_at("tmp").unwrap();
+        overlay.rename_at("tmp", &overlay, "out").unwrap();
+        assert_eq!(names(&overlay), ["README", "out", "src"]);
+        assert_eq!(
+            error(overlay.stat_at(PathFlags::empty(), "../src")),
+            ErrorCode::NotPermitted
+        );
+    }
+}
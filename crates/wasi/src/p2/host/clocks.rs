@@ -27,7 +27,9 @@ where
     T: WasiView,
 {
     fn now(&mut self) -> anyhow::Result<Datetime> {
-        let now = self.ctx().wall_clock.now();
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.wall_clock, "wall clock")?;
+        let now = ctx.wall_clock.now();
         Ok(Datetime {
             seconds: now.as_secs(),
             nanoseconds: now.subsec_nanos(),
@@ -35,7 +37,9 @@ where
     }
 
     fn resolution(&mut self) -> anyhow::Result<Datetime> {
-        let res = self.ctx().wall_clock.resolution();
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.wall_clock, "wall clock")?;
+        let res = ctx.wall_clock.resolution();
         Ok(Datetime {
             seconds: res.as_secs(),
             nanoseconds: res.subsec_nanos(),
@@ -67,15 +71,21 @@ where
     T: WasiView,
 {
     fn now(&mut self) -> anyhow::Result<Instant> {
-        Ok(self.ctx().monotonic_clock.now())
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.monotonic_clock, "monotonic clock")?;
+        Ok(ctx.monotonic_clock.now())
     }
 
     fn resolution(&mut self) -> anyhow::Result<Instant> {
-        Ok(self.ctx().monotonic_clock.resolution())
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.monotonic_clock, "monotonic clock")?;
+        Ok(ctx.monotonic_clock.resolution())
     }
 
     fn subscribe_instant(&mut self, when: Instant) -> anyhow::Result<Resource<DynPollable>> {
-        let clock_now = self.ctx().monotonic_clock.now();
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.monotonic_clock, "monotonic clock")?;
+        let clock_now = ctx.monotonic_clock.now();
         let duration = if when > clock_now {
             Duration::from_nanos(when - clock_now)
         } else {
@@ -88,6 +98,8 @@ where
         &mut self,
         duration: WasiDuration,
     ) -> anyhow::Result<Resource<DynPollable>> {
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.monotonic_clock, "monotonic clock")?;
         subscribe_to_duration(&mut self.table(), Duration::from_nanos(duration))
     }
 }
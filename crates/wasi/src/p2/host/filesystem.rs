@@ -1,14 +1,14 @@
-use crate::p2::bindings::clocks::wall_clock;
 use crate::p2::bindings::filesystem::preopens;
 use crate::p2::bindings::filesystem::types::{
     self, ErrorCode, HostDescriptor, HostDirectoryEntryStream,
 };
 use crate::p2::filesystem::{
-    Descriptor, Dir, File, FileInputStream, FileOutputStream, ReaddirIterator,
+    Descriptor, Dir, File, FileInputStream, FileOutputStream, Metadata, Opened, ReaddirIterator,
 };
 use crate::p2::{FsError, FsResult, IoView, WasiImpl, WasiView};
 use crate::{DirPerms, FilePerms, OpenMode};
 use anyhow::Context;
+use std::sync::Arc;
 use wasmtime::component::Resource;
 use wasmtime_wasi_io::streams::{DynInputStream, DynOutputStream};
 
@@ -68,48 +68,16 @@ where
         len: types::Filesize,
         advice: types::Advice,
     ) -> FsResult<()> {
-        use system_interface::fs::{Advice as A, FileIoExt};
-        use types::Advice;
-
-        let advice = match advice {
-            Advice::Normal => A::Normal,
-            Advice::Sequential => A::Sequential,
-            Advice::Random => A::Random,
-            Advice::WillNeed => A::WillNeed,
-            Advice::DontNeed => A::DontNeed,
-            Advice::NoReuse => A::NoReuse,
-        };
-
         let f = self.table().get(&fd)?.file()?;
-        f.run_blocking(move |f| f.advise(offset, len, advice))
-            .await?;
-        Ok(())
+        f.run_blocking(move |f| f.advise(offset, len, advice)).await
     }
 
     async fn sync_data(&mut self, fd: Resource<types::Descriptor>) -> FsResult<()> {
         let descriptor = self.table().get(&fd)?;
 
         match descriptor {
-            Descriptor::File(f) => {
-                match f.run_blocking(|f| f.sync_data()).await {
-                    Ok(()) => Ok(()),
-                    // On windows, `sync_data` uses `FileFlushBuffers` which fails with
-                    // `ERROR_ACCESS_DENIED` if the file is not upen for writing. Ignore
-                    // this error, for POSIX compatibility.
-                    #[cfg(windows)]
-                    Err(e)
-                        if e.raw_os_error()
-                            == Some(windows_sys::Win32::Foundation::ERROR_ACCESS_DENIED as _) =>
-                    {
-                        Ok(())
-                    }
-                    Err(e) => Err(e.into()),
-                }
-            }
-            Descriptor::Dir(d) => {
-                d.run_blocking(|d| Ok(d.open(std::path::Component::CurDir)?.sync_data()?))
-                    .await
-            }
+            Descriptor::File(f) => f.run_blocking(|f| f.sync_data()).await,
+            Descriptor::Dir(d) => d.run_blocking(|d| d.sync_data()).await,
         }
     }
 
@@ -117,28 +85,12 @@ where
         &mut self,
         fd: Resource<types::Descriptor>,
     ) -> FsResult<types::DescriptorFlags> {
-        use system_interface::fs::{FdFlags, GetSetFdFlags};
         use types::DescriptorFlags;
 
-        fn get_from_fdflags(flags: FdFlags) -> DescriptorFlags {
-            let mut out = DescriptorFlags::empty();
-            if flags.contains(FdFlags::DSYNC) {
-                out |= DescriptorFlags::REQUESTED_WRITE_SYNC;
-            }
-            if flags.contains(FdFlags::RSYNC) {
-                out |= DescriptorFlags::DATA_INTEGRITY_SYNC;
-            }
-            if flags.contains(FdFlags::SYNC) {
-                out |= DescriptorFlags::FILE_INTEGRITY_SYNC;
-            }
-            out
-        }
-
         let descriptor = self.table().get(&fd)?;
         match descriptor {
             Descriptor::File(f) => {
-                let flags = f.run_blocking(|f| f.get_fd_flags()).await?;
-                let mut flags = get_from_fdflags(flags);
+                let mut flags = f.run_blocking(|f| f.get_flags()).await?;
                 if f.open_mode.contains(OpenMode::READ) {
                     flags |= DescriptorFlags::READ;
                 }
@@ -148,8 +100,7 @@ where
                 Ok(flags)
             }
             Descriptor::Dir(d) => {
-                let flags = d.run_blocking(|d| d.get_fd_flags()).await?;
-                let mut flags = get_from_fdflags(flags);
+                let mut flags = d.run_blocking(|d| d.get_flags()).await?;
                 if d.open_mode.contains(OpenMode::READ) {
                     flags |= DescriptorFlags::READ;
                 }
@@ -169,8 +120,8 @@ where
 
         match descriptor {
             Descriptor::File(f) => {
-                let meta = f.run_blocking(|f| f.metadata()).await?;
-                Ok(descriptortype_from(meta.file_type()))
+                let meta = f.run_blocking(|f| f.stat()).await?;
+                Ok(meta.stat.type_)
             }
             Descriptor::Dir(_) => Ok(types::DescriptorType::Directory),
         }
@@ -185,8 +136,15 @@ where
         if !f.perms.contains(FilePerms::WRITE) {
             Err(ErrorCode::NotPermitted)?;
         }
-        f.run_blocking(move |f| f.set_len(size)).await?;
-        Ok(())
+        // Like `ftruncate`, refuse to resize a file that wasn't opened for
+        // writing, whichever filesystem it lives in.
+        if !f.open_mode.contains(OpenMode::WRITE) {
+            if cfg!(windows) {
+                Err(ErrorCode::Access)?;
+            }
+            Err(ErrorCode::Invalid)?;
+        }
+        f.run_blocking(move |f| f.set_size(size)).await
     }
 
     async fn set_times(
@@ -195,27 +153,19 @@ where
         atim: types::NewTimestamp,
         mtim: types::NewTimestamp,
     ) -> FsResult<()> {
-        use fs_set_times::SetTimes;
-
         let descriptor = self.table().get(&fd)?;
         match descriptor {
             Descriptor::File(f) => {
                 if !f.perms.contains(FilePerms::WRITE) {
                     return Err(ErrorCode::NotPermitted.into());
                 }
-                let atim = systemtimespec_from(atim)?;
-                let mtim = systemtimespec_from(mtim)?;
-                f.run_blocking(|f| f.set_times(atim, mtim)).await?;
-                Ok(())
+                f.run_blocking(move |f| f.set_times(atim, mtim)).await
             }
             Descriptor::Dir(d) => {
                 if !d.perms.contains(DirPerms::MUTATE) {
                     return Err(ErrorCode::NotPermitted.into());
                 }
-                let atim = systemtimespec_from(atim)?;
-                let mtim = systemtimespec_from(mtim)?;
-                d.run_blocking(|d| d.set_times(atim, mtim)).await?;
-                Ok(())
+                d.run_blocking(move |d| d.set_times(atim, mtim)).await
             }
         }
     }
@@ -226,9 +176,6 @@ where
         len: types::Filesize,
         offset: types::Filesize,
     ) -> FsResult<(Vec<u8>, bool)> {
-        use std::io::IoSliceMut;
-        use system_interface::fs::FileIoExt;
-
         let table = self.table();
 
         let f = table.get(&fd)?.file()?;
@@ -239,7 +186,7 @@ where
         let (mut buffer, r) = f
             .run_blocking(move |f| {
                 let mut buffer = vec![0; len.try_into().unwrap_or(usize::MAX)];
-                let r = f.read_vectored_at(&mut [IoSliceMut::new(&mut buffer)], offset);
+                let r = f.read_at(&mut buffer, offset);
                 (buffer, r)
             })
             .await;
@@ -260,18 +207,13 @@ where
         buf: Vec<u8>,
         offset: types::Filesize,
     ) -> FsResult<types::Filesize> {
-        use std::io::IoSlice;
-        use system_interface::fs::FileIoExt;
-
         let table = self.table();
         let f = table.get(&fd)?.file()?;
         if !f.perms.contains(FilePerms::WRITE) {
             return Err(ErrorCode::NotPermitted.into());
         }
 
-        let bytes_written = f
-            .run_blocking(move |f| f.write_vectored_at(&[IoSlice::new(&buf)], offset))
-            .await?;
+        let bytes_written = f.run_blocking(move |f| f.write_at(&buf, offset)).await?;
 
         Ok(types::Filesize::try_from(bytes_written).expect("usize fits in Filesize"))
     }
@@ -286,85 +228,16 @@ where
             return Err(ErrorCode::NotPermitted.into());
         }
 
-        enum ReaddirError {
-            Io(std::io::Error),
-            IllegalSequence,
-        }
-        impl From<std::io::Error> for ReaddirError {
-            fn from(e: std::io::Error) -> ReaddirError {
-                ReaddirError::Io(e)
-            }
-        }
-
-        let entries = d
-            .run_blocking(|d| {
-                // Both `entries` and `metadata` perform syscalls, which is why they are done
-                // within this `block` call, rather than delay calculating the metadata
-                // for entries when they're demanded later in the iterator chain.
-                Ok::<_, std::io::Error>(
-                    d.entries()?
-                        .map(|entry| {
-                            let entry = entry?;
-                            let meta = entry.metadata()?;
-                            let type_ = descriptortype_from(meta.file_type());
-                            let name = entry
-                                .file_name()
-                                .into_string()
-                                .map_err(|_| ReaddirError::IllegalSequence)?;
-                            Ok(types::DirectoryEntry { type_, name })
-                        })
-                        .collect::<Vec<Result<types::DirectoryEntry, ReaddirError>>>(),
-                )
-            })
-            .await?
-            .into_iter();
-
-        // On windows, filter out files like `C:\DumpStack.log.tmp` which we
-        // can't get full metadata for.
-        #[cfg(windows)]
-        let entries = entries.filter(|entry| {
-            use windows_sys::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION};
-            if let Err(ReaddirError::Io(err)) = entry {
-                if err.raw_os_error() == Some(ERROR_SHARING_VIOLATION as i32)
-                    || err.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32)
-                {
-                    return false;
-                }
-            }
-            true
-        });
-        let entries = entries.map(|r| match r {
-            Ok(r) => Ok(r),
-            Err(ReaddirError::Io(e)) => Err(e.into()),
-            Err(ReaddirError::IllegalSequence) => Err(ErrorCode::IllegalByteSequence.into()),
-        });
-        Ok(table.push(ReaddirIterator::new(entries))?)
+        let entries = d.run_blocking(|d| d.read_directory()).await?;
+        Ok(table.push(ReaddirIterator::new(entries.into_iter()))?)
     }
 
     async fn sync(&mut self, fd: Resource<types::Descriptor>) -> FsResult<()> {
         let descriptor = self.table().get(&fd)?;
 
         match descriptor {
-            Descriptor::File(f) => {
-                match f.run_blocking(|f| f.sync_all()).await {
-                    Ok(()) => Ok(()),
-                    // On windows, `sync_data` uses `FileFlushBuffers` which fails with
-                    // `ERROR_ACCESS_DENIED` if the file is not upen for writing. Ignore
-                    // this error, for POSIX compatibility.
-                    #[cfg(windows)]
-                    Err(e)
-                        if e.raw_os_error()
-                            == Some(windows_sys::Win32::Foundation::ERROR_ACCESS_DENIED as _) =>
-                    {
-                        Ok(())
-                    }
-                    Err(e) => Err(e.into()),
-                }
-            }
-            Descriptor::Dir(d) => {
-                d.run_blocking(|d| Ok(d.open(std::path::Component::CurDir)?.sync_all()?))
-                    .await
-            }
+            Descriptor::File(f) => f.run_blocking(|f| f.sync()).await,
+            Descriptor::Dir(d) => d.run_blocking(|d| d.sync()).await,
         }
     }
 
@@ -378,24 +251,13 @@ where
         if !d.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        d.run_blocking(move |d| d.create_dir(&path)).await?;
-        Ok(())
+        d.run_blocking(move |d| d.create_directory_at(&path)).await
     }
 
     async fn stat(&mut self, fd: Resource<types::Descriptor>) -> FsResult<types::DescriptorStat> {
-        let descriptor = self.table().get(&fd)?;
-        match descriptor {
-            Descriptor::File(f) => {
-                // No permissions check on stat: if opened, allowed to stat it
-                let meta = f.run_blocking(|f| f.metadata()).await?;
-                Ok(descriptorstat_from(meta))
-            }
-            Descriptor::Dir(d) => {
-                // No permissions check on stat: if opened, allowed to stat it
-                let meta = d.run_blocking(|d| d.dir_metadata()).await?;
-                Ok(descriptorstat_from(meta))
-            }
-        }
+        // No permissions check on stat: if opened, allowed to stat it
+        let meta = get_descriptor_metadata(self.table().get(&fd)?).await?;
+        Ok(meta.stat)
     }
 
     async fn stat_at(
@@ -410,12 +272,10 @@ where
             return Err(ErrorCode::NotPermitted.into());
         }
 
-        let meta = if symlink_follow(path_flags) {
-            d.run_blocking(move |d| d.metadata(&path)).await?
-        } else {
-            d.run_blocking(move |d| d.symlink_metadata(&path)).await?
-        };
-        Ok(descriptorstat_from(meta))
+        let meta = d
+            .run_blocking(move |d| d.stat_at(path_flags, &path))
+            .await?;
+        Ok(meta.stat)
     }
 
     async fn set_times_at(
@@ -426,35 +286,13 @@ where
         atim: types::NewTimestamp,
         mtim: types::NewTimestamp,
     ) -> FsResult<()> {
-        use cap_fs_ext::DirExt;
-
         let table = self.table();
         let d = table.get(&fd)?.dir()?;
         if !d.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        let atim = systemtimespec_from(atim)?;
-        let mtim = systemtimespec_from(mtim)?;
-        if symlink_follow(path_flags) {
-            d.run_blocking(move |d| {
-                d.set_times(
-                    &path,
-                    atim.map(cap_fs_ext::SystemTimeSpec::from_std),
-                    mtim.map(cap_fs_ext::SystemTimeSpec::from_std),
-                )
-            })
-            .await?;
-        } else {
-            d.run_blocking(move |d| {
-                d.set_symlink_times(
-                    &path,
-                    atim.map(cap_fs_ext::SystemTimeSpec::from_std),
-                    mtim.map(cap_fs_ext::SystemTimeSpec::from_std),
-                )
-            })
-            .await?;
-        }
-        Ok(())
+        d.run_blocking(move |d| d.set_times_at(path_flags, &path, atim, mtim))
+            .await
     }
 
     async fn link_at(
@@ -475,14 +313,13 @@ where
         if !new_dir.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        if symlink_follow(old_path_flags) {
+        if old_path_flags.contains(types::PathFlags::SYMLINK_FOLLOW) {
             return Err(ErrorCode::Invalid.into());
         }
-        let new_dir_handle = std::sync::Arc::clone(&new_dir.dir);
+        let new_dir_handle = Arc::clone(&new_dir.dir);
         old_dir
-            .run_blocking(move |d| d.hard_link(&old_path, &new_dir_handle, &new_path))
-            .await?;
-        Ok(())
+            .run_blocking(move |d| d.link_at(&old_path, &*new_dir_handle, &new_path))
+            .await
     }
 
     async fn open_at(
@@ -493,8 +330,6 @@ where
         oflags: types::OpenFlags,
         flags: types::DescriptorFlags,
     ) -> FsResult<Resource<types::Descriptor>> {
-        use cap_fs_ext::{FollowSymlinks, OpenOptionsFollowExt, OpenOptionsMaybeDirExt};
-        use system_interface::fs::{FdFlags, GetSetFdFlags};
         use types::{DescriptorFlags, OpenFlags};
 
         let allow_blocking_current_thread = self.ctx().allow_blocking_current_thread;
@@ -514,47 +349,25 @@ where
         }
 
         // Track whether we are creating file, for permission check:
-        let mut create = false;
+        let create = oflags.contains(OpenFlags::CREATE);
         // Track open mode, for permission check and recording in created descriptor:
         let mut open_mode = OpenMode::empty();
-        // Construct the OpenOptions to give the OS:
-        let mut opts = cap_std::fs::OpenOptions::new();
-        opts.maybe_dir(true);
-
-        if oflags.contains(OpenFlags::CREATE) {
-            if oflags.contains(OpenFlags::EXCLUSIVE) {
-                opts.create_new(true);
-            } else {
-                opts.create(true);
-            }
-            create = true;
-            opts.write(true);
-            open_mode |= OpenMode::WRITE;
-        }
 
-        if oflags.contains(OpenFlags::TRUNCATE) {
-            opts.truncate(true).write(true);
+        if create {
+            open_mode |= OpenMode::WRITE;
         }
         if flags.contains(DescriptorFlags::READ) {
-            opts.read(true);
             open_mode |= OpenMode::READ;
         }
         if flags.contains(DescriptorFlags::WRITE) {
-            opts.write(true);
             open_mode |= OpenMode::WRITE;
         } else {
             // If not opened write, open read. This way the OS lets us open
             // the file, but we can use perms to reject use of the file later.
-            opts.read(true);
             open_mode |= OpenMode::READ;
         }
-        if symlink_follow(path_flags) {
-            opts.follow(FollowSymlinks::Yes);
-        } else {
-            opts.follow(FollowSymlinks::No);
-        }
 
-        // These flags are not yet supported in cap-std:
+        // These flags are not yet supported:
         if flags.contains(DescriptorFlags::FILE_INTEGRITY_SYNC)
             || flags.contains(DescriptorFlags::DATA_INTEGRITY_SYNC)
             || flags.contains(DescriptorFlags::REQUESTED_WRITE_SYNC)
@@ -571,8 +384,8 @@ where
             }
         }
 
-        // Now enforce this WasiCtx's permissions before letting the OS have
-        // its shot:
+        // Now enforce this WasiCtx's permissions before letting the
+        // filesystem have its shot:
         if !d.perms.contains(DirPerms::MUTATE) && create {
             Err(ErrorCode::NotPermitted)?;
         }
@@ -580,51 +393,25 @@ where
             Err(ErrorCode::NotPermitted)?;
         }
 
-        // Represents each possible outcome from the spawn_blocking operation.
-        // This makes sure we don't have to give spawn_blocking any way to
-        // manipulate the table.
-        enum OpenResult {
-            Dir(cap_std::fs::Dir),
-            File(cap_std::fs::File),
-            NotDir,
-        }
-
         let opened = d
-            .run_blocking::<_, std::io::Result<OpenResult>>(move |d| {
-                let mut opened = d.open_with(&path, &opts)?;
-                if opened.metadata()?.is_dir() {
-                    Ok(OpenResult::Dir(cap_std::fs::Dir::from_std_file(
-                        opened.into_std(),
-                    )))
-                } else if oflags.contains(OpenFlags::DIRECTORY) {
-                    Ok(OpenResult::NotDir)
-                } else {
-                    // FIXME cap-std needs a nonblocking open option so that files reads and writes
-                    // are nonblocking. Instead we set it after opening here:
-                    let set_fd_flags = opened.new_set_fd_flags(FdFlags::NONBLOCK)?;
-                    opened.set_fd_flags(set_fd_flags)?;
-                    Ok(OpenResult::File(opened))
-                }
-            })
+            .run_blocking(move |d| d.open_at(path_flags, &path, oflags, open_mode))
             .await?;
 
         match opened {
-            OpenResult::Dir(dir) => Ok(table.push(Descriptor::Dir(Dir::new(
-                dir,
+            Opened::Dir(dir) => Ok(table.push(Descriptor::Dir(Dir::new(
+                Arc::from(dir),
                 d.perms,
                 d.file_perms,
                 open_mode,
                 allow_blocking_current_thread,
             )))?),
 
-            OpenResult::File(file) => Ok(table.push(Descriptor::File(File::new(
-                file,
+            Opened::File(file) => Ok(table.push(Descriptor::File(File::new(
+                Arc::from(file),
                 d.file_perms,
                 open_mode,
                 allow_blocking_current_thread,
             )))?),
-
-            OpenResult::NotDir => Err(ErrorCode::NotDirectory.into()),
         }
     }
 
@@ -651,11 +438,7 @@ where
         if !d.perms.contains(DirPerms::READ) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        let link = d.run_blocking(move |d| d.read_link(&path)).await?;
-        Ok(link
-            .into_os_string()
-            .into_string()
-            .map_err(|_| ErrorCode::IllegalByteSequence)?)
+        d.run_blocking(move |d| d.readlink_at(&path)).await
     }
 
     async fn remove_directory_at(
@@ -668,7 +451,7 @@ where
         if !d.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        Ok(d.run_blocking(move |d| d.remove_dir(&path)).await?)
+        d.run_blocking(move |d| d.remove_directory_at(&path)).await
     }
 
     async fn rename_at(
@@ -687,10 +470,10 @@ where
         if !new_dir.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        let new_dir_handle = std::sync::Arc::clone(&new_dir.dir);
-        Ok(old_dir
-            .run_blocking(move |d| d.rename(&old_path, &new_dir_handle, &new_path))
-            .await?)
+        let new_dir_handle = Arc::clone(&new_dir.dir);
+        old_dir
+            .run_blocking(move |d| d.rename_at(&old_path, &*new_dir_handle, &new_path))
+            .await
     }
 
     async fn symlink_at(
@@ -699,17 +482,13 @@ where
         src_path: String,
         dest_path: String,
     ) -> FsResult<()> {
-        // On windows, Dir.symlink is provided by DirExt
-        #[cfg(windows)]
-        use cap_fs_ext::DirExt;
-
         let table = self.table();
         let d = table.get(&fd)?.dir()?;
         if !d.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        Ok(d.run_blocking(move |d| d.symlink(&src_path, &dest_path))
-            .await?)
+        d.run_blocking(move |d| d.symlink_at(&src_path, &dest_path))
+            .await
     }
 
     async fn unlink_file_at(
@@ -717,15 +496,12 @@ where
         fd: Resource<types::Descriptor>,
         path: String,
     ) -> FsResult<()> {
-        use cap_fs_ext::DirExt;
-
         let table = self.table();
         let d = table.get(&fd)?.dir()?;
         if !d.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted.into());
         }
-        Ok(d.run_blocking(move |d| d.remove_file_or_symlink(&path))
-            .await?)
+        d.run_blocking(move |d| d.unlink_file_at(&path)).await
     }
 
     fn read_via_stream(
@@ -797,12 +573,11 @@ where
         a: Resource<types::Descriptor>,
         b: Resource<types::Descriptor>,
     ) -> anyhow::Result<bool> {
-        use cap_fs_ext::MetadataExt;
         let descriptor_a = self.table().get(&a)?;
         let meta_a = get_descriptor_metadata(descriptor_a).await?;
         let descriptor_b = self.table().get(&b)?;
         let meta_b = get_descriptor_metadata(descriptor_b).await?;
-        if meta_a.dev() == meta_b.dev() && meta_a.ino() == meta_b.ino() {
+        if meta_a.dev == meta_b.dev && meta_a.ino == meta_b.ino {
             // MetadataHashValue does not derive eq, so use a pair of
             // comparisons to check equality:
             debug_assert_eq!(
@@ -837,13 +612,7 @@ where
         let d = table.get(&fd)?.dir()?;
         // No permissions check on metadata: if dir opened, allowed to stat it
         let meta = d
-            .run_blocking(move |d| {
-                if symlink_follow(path_flags) {
-                    d.metadata(path)
-                } else {
-                    d.symlink_metadata(path)
-                }
-            })
+            .run_blocking(move |d| d.stat_at(path_flags, &path))
             .await?;
         Ok(calculate_metadata_hash(&meta))
     }
@@ -868,29 +637,28 @@ where
     }
 }
 
-async fn get_descriptor_metadata(fd: &types::Descriptor) -> FsResult<cap_std::fs::Metadata> {
+async fn get_descriptor_metadata(fd: &types::Descriptor) -> FsResult<Metadata> {
     match fd {
         Descriptor::File(f) => {
             // No permissions check on metadata: if opened, allowed to stat it
-            Ok(f.run_blocking(|f| f.metadata()).await?)
+            f.run_blocking(|f| f.stat()).await
         }
         Descriptor::Dir(d) => {
             // No permissions check on metadata: if opened, allowed to stat it
-            Ok(d.run_blocking(|d| d.dir_metadata()).await?)
+            d.run_blocking(|d| d.stat()).await
         }
     }
 }
 
-fn calculate_metadata_hash(meta: &cap_std::fs::Metadata) -> types::MetadataHashValue {
-    use cap_fs_ext::MetadataExt;
+fn calculate_metadata_hash(meta: &Metadata) -> types::MetadataHashValue {
     // Without incurring any deps, std provides us with a 64 bit hash
     // function:
     use std::hash::Hasher;
     // Note that this means that the metadata hash (which becomes a preview1 ino) may
     // change when a different rustc release is used to build this host implementation:
     let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    hasher.write_u64(meta.dev());
-    hasher.write_u64(meta.ino());
+    hasher.write_u64(meta.dev);
+    hasher.write_u64(meta.ino);
     let lower = hasher.finish();
     // MetadataHashValue has a pair of 64-bit members for representing a
     // single 128-bit number. However, we only have 64 bits of entropy. To
@@ -994,6 +762,7 @@ impl<'a> From<&'a std::io::Error> for ErrorCode {
                     std::io::ErrorKind::PermissionDenied => ErrorCode::NotPermitted,
                     std::io::ErrorKind::AlreadyExists => ErrorCode::Exist,
                     std::io::ErrorKind::InvalidInput => ErrorCode::Invalid,
+                    std::io::ErrorKind::ReadOnlyFilesystem => ErrorCode::ReadOnly,
                     _ => ErrorCode::Io,
                 }
             }
@@ -1014,62 +783,6 @@ impl From<std::num::TryFromIntError> for ErrorCode {
     }
 }
 
-fn descriptortype_from(ft: cap_std::fs::FileType) -> types::DescriptorType {
-    use cap_fs_ext::FileTypeExt;
-    use types::DescriptorType;
-    if ft.is_dir() {
-        DescriptorType::Directory
-    } else if ft.is_symlink() {
-        DescriptorType::SymbolicLink
-    } else if ft.is_block_device() {
-        DescriptorType::BlockDevice
-    } else if ft.is_char_device() {
-        DescriptorType::CharacterDevice
-    } else if ft.is_file() {
-        DescriptorType::RegularFile
-    } else {
-        DescriptorType::Unknown
-    }
-}
-
-fn systemtimespec_from(t: types::NewTimestamp) -> FsResult<Option<fs_set_times::SystemTimeSpec>> {
-    use fs_set_times::SystemTimeSpec;
-    use types::NewTimestamp;
-    match t {
-        NewTimestamp::NoChange => Ok(None),
-        NewTimestamp::Now => Ok(Some(SystemTimeSpec::SymbolicNow)),
-        NewTimestamp::Timestamp(st) => Ok(Some(SystemTimeSpec::Absolute(systemtime_from(st)?))),
-    }
-}
-
-fn systemtime_from(t: wall_clock::Datetime) -> FsResult<std::time::SystemTime> {
-    use std::time::{Duration, SystemTime};
-    SystemTime::UNIX_EPOCH
-        .checked_add(Duration::new(t.seconds, t.nanoseconds))
-        .ok_or_else(|| ErrorCode::Overflow.into())
-}
-
-fn datetime_from(t: std::time::SystemTime) -> wall_clock::Datetime {
-    // FIXME make this infallible or handle errors properly
-    wall_clock::Datetime::try_from(cap_std::time::SystemTime::from_std(t)).unwrap()
-}
-
-fn descriptorstat_from(meta: cap_std::fs::Metadata) -> types::DescriptorStat {
-    use cap_fs_ext::MetadataExt;
-    types::DescriptorStat {
-        type_: descriptortype_from(meta.file_type()),
-        link_count: meta.nlink(),
-        size: meta.len(),
-        data_access_timestamp: meta.accessed().map(|t| datetime_from(t.into_std())).ok(),
-        data_modification_timestamp: meta.modified().map(|t| datetime_from(t.into_std())).ok(),
-        status_change_timestamp: meta.created().map(|t| datetime_from(t.into_std())).ok(),
-    }
-}
-
-fn symlink_follow(path_flags: types::PathFlags) -> bool {
-    path_flags.contains(types::PathFlags::SYMLINK_FOLLOW)
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1084,4 +797,55 @@ mod test {
         let _ = table.get(&ix).unwrap();
         table.delete(ix).unwrap();
     }
+
+    #[tokio::test]
+    async fn set_size_needs_write_mode() {
+        use crate::p2::{InMemoryFilesystem, IoImpl, WasiCtxBuilder};
+        use types::{DescriptorFlags, OpenFlags, PathFlags};
+
+        let fs = InMemoryFilesystem::new();
+        fs.insert_file("f", "data").unwrap();
+        let mut ctx = WasiCtxBuilder::new()
+            .preopened_filesystem(fs.clone(), "/", DirPerms::all(), FilePerms::all())
+            .build_p1();
+        let mut wasi = WasiImpl(IoImpl(&mut ctx));
+        let (dir, _) = preopens::Host::get_directories(&mut wasi)
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        let fd = HostDescriptor::open_at(
+            &mut wasi,
+            Resource::new_borrow(dir.rep()),
+            PathFlags::empty(),
+            "f".to_string(),
+            OpenFlags::empty(),
+            DescriptorFlags::READ,
+        )
+        .await
+        .unwrap();
+        let err = HostDescriptor::set_size(&mut wasi, fd, 0)
+            .await
+            .unwrap_err();
+        let expected = if cfg!(windows) {
+            ErrorCode::Access
+        } else {
+            ErrorCode::Invalid
+        };
+        assert_eq!(err.downcast().unwrap(), expected);
+        assert_eq!(fs.read_file("f").unwrap(), b"data");
+
+        let fd = HostDescriptor::open_at(
+            &mut wasi,
+            dir,
+            PathFlags::empty(),
+            "f".to_string(),
+            OpenFlags::empty(),
+            DescriptorFlags::WRITE,
+        )
+        .await
+        .unwrap();
+        HostDescriptor::set_size(&mut wasi, fd, 0).await.unwrap();
+        assert_eq!(fs.read_file("f").unwrap(), b"");
+    }
 }
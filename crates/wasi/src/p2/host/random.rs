@@ -7,14 +7,18 @@ where
     T: WasiView,
 {
     fn get_random_bytes(&mut self, len: u64) -> anyhow::Result<Vec<u8>> {
-        Ok((&mut self.ctx().random)
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.random, "random number generator")?;
+        Ok((&mut ctx.random)
             .sample_iter(Standard)
             .take(len as usize)
             .collect())
     }
 
     fn get_random_u64(&mut self) -> anyhow::Result<u64> {
-        Ok(self.ctx().random.sample(Standard))
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.random, "random number generator")?;
+        Ok(ctx.random.sample(Standard))
     }
 }
 
@@ -23,14 +27,24 @@ where
     T: WasiView,
 {
     fn get_insecure_random_bytes(&mut self, len: u64) -> anyhow::Result<Vec<u8>> {
-        Ok((&mut self.ctx().insecure_random)
+        let ctx = self.ctx();
+        ctx.ensure_seeded(
+            ctx.seeded.insecure_random,
+            "insecure random number generator",
+        )?;
+        Ok((&mut ctx.insecure_random)
             .sample_iter(Standard)
             .take(len as usize)
             .collect())
     }
 
     fn get_insecure_random_u64(&mut self) -> anyhow::Result<u64> {
-        Ok(self.ctx().insecure_random.sample(Standard))
+        let ctx = self.ctx();
+        ctx.ensure_seeded(
+            ctx.seeded.insecure_random,
+            "insecure random number generator",
+        )?;
+        Ok(ctx.insecure_random.sample(Standard))
     }
 }
 
@@ -39,7 +53,9 @@ where
     T: WasiView,
 {
     fn insecure_seed(&mut self) -> anyhow::Result<(u64, u64)> {
-        let seed: u128 = self.ctx().insecure_random_seed;
+        let ctx = self.ctx();
+        ctx.ensure_seeded(ctx.seeded.insecure_random_seed, "insecure random seed")?;
+        let seed: u128 = ctx.insecure_random_seed;
         Ok((seed as u64, (seed >> 64) as u64))
     }
 }
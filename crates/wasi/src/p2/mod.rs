@@ -243,7 +243,12 @@ mod view;
 mod write_stream;
 
 pub use self::ctx::{WasiCtx, WasiCtxBuilder};
-pub use self::filesystem::{FsError, FsResult};
+#[cfg(feature = "archive")]
+pub use self::filesystem::ArchiveFilesystem;
+pub use self::filesystem::{
+    CapStdFile, CapStdFilesystem, FsError, FsResult, InMemoryFilesystem, Metadata, Opened,
+    OverlayFilesystem, WasiFile, WasiFilesystem,
+};
 pub use self::network::{SocketError, SocketResult};
 pub use self::stdio::{
     AsyncStdinStream, AsyncStdoutStream, InputFile, IsATTY, OutputFile, Stderr, Stdin, StdinStream,
@@ -72,7 +72,7 @@ use crate::p2::bindings::{
     clocks::{monotonic_clock, wall_clock},
     filesystem::{preopens::Host as _, types as filesystem},
 };
-use crate::p2::{FsError, IsATTY, WasiCtx, WasiImpl, WasiView};
+use crate::p2::{FsError, IsATTY, WasiCtx, WasiFile, WasiImpl, WasiView};
 use anyhow::{Context, bail};
 use std::collections::{BTreeMap, HashSet};
 use std::mem::{self, size_of, size_of_val};
@@ -80,7 +80,6 @@ use std::ops::{Deref, DerefMut};
 use std::slice;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use system_interface::fs::FileIoExt;
 use wasmtime::component::Resource;
 use wasmtime_wasi_io::{
     IoImpl, IoView,
@@ -604,7 +603,7 @@ impl WasiP1Ctx {
                 let f = self.table().get(&fd)?.file()?;
                 let buf = first_non_empty_ciovec(memory, ciovs)?;
 
-                let do_write = move |f: &cap_std::fs::File, buf: &[u8]| match (append, write) {
+                let do_write = move |f: &dyn WasiFile, buf: &[u8]| match (append, write) {
                     // Note that this is implementing Linux semantics of
                     // `pwrite` where the offset is ignored if the file was
                     // opened in append mode.
@@ -2290,6 +2289,7 @@ impl wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiP1Ctx {
                     .flags
                     .contains(types::Subclockflags::SUBSCRIPTION_CLOCK_ABSTIME)
                     && self.ctx().allow_blocking_current_thread
+                    && !self.ctx().deterministic
                 {
                     std::thread::sleep(std::time::Duration::from_nanos(clocksub.timeout));
                     memory.write(
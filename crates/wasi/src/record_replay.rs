@@ -0,0 +1,68 @@
+//! Clocks and random number generators which record into, or replay from, a
+//! [`HostCallTrace`].
+
+use crate::clocks::{HostMonotonicClock, HostWallClock};
+use cap_rand::RngCore;
+use cap_std::time::Duration;
+use wasmtime::HostCallTrace;
+
+pub(crate) struct TracedWallClock {
+    pub(crate) clock: Box<dyn HostWallClock + Send>,
+    pub(crate) trace: HostCallTrace,
+}
+
+impl HostWallClock for TracedWallClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(self.trace.value(|| nanos(self.clock.resolution())))
+    }
+
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.trace.value(|| nanos(self.clock.now())))
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    // A `u64` is wide enough to hold over 584 years of nanoseconds.
+    duration.as_nanos().try_into().unwrap()
+}
+
+pub(crate) struct TracedMonotonicClock {
+    pub(crate) clock: Box<dyn HostMonotonicClock + Send>,
+    pub(crate) trace: HostCallTrace,
+}
+
+impl HostMonotonicClock for TracedMonotonicClock {
+    fn resolution(&self) -> u64 {
+        self.trace.value(|| self.clock.resolution())
+    }
+
+    fn now(&self) -> u64 {
+        self.trace.value(|| self.clock.now())
+    }
+}
+
+pub(crate) struct TracedRng {
+    pub(crate) rng: Box<dyn RngCore + Send>,
+    pub(crate) trace: HostCallTrace,
+}
+
+impl RngCore for TracedRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.trace.value(|| self.rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, buf: &mut [u8]) {
+        self.trace.bytes(buf, |buf| self.rng.fill_bytes(buf))
+    }
+
+    fn try_fill_bytes(&mut self, buf: &mut [u8]) -> Result<(), cap_rand::Error> {
+        self.fill_bytes(buf);
+        Ok(())
+    }
+}
@@ -6,7 +6,7 @@ use wasmtime::Store;
 use wasmtime::component::{Component, Linker, ResourceTable};
 use wasmtime_wasi::p2::bindings::Command;
 use wasmtime_wasi::p2::{
-    IoView, WasiCtx, WasiCtxBuilder, WasiView, add_to_linker_async,
+    InMemoryFilesystem, IoView, WasiCtx, WasiCtxBuilder, WasiView, add_to_linker_async,
     bindings::{clocks::wall_clock, filesystem::types as filesystem},
 };
 use wasmtime_wasi::{DirPerms, FilePerms, HostMonotonicClock, HostWallClock};
@@ -112,6 +112,69 @@ async fn api_read_only() -> Result<()> {
         .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
 }
 
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn api_read_only_in_memory() -> Result<()> {
+    let fs = InMemoryFilesystem::new();
+    fs.insert_file("bar.txt", "And stood awhile in thought")?;
+    fs.insert_dir("sub")?;
+
+    let table = ResourceTable::new();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_filesystem(fs.clone(), "/", DirPerms::READ, FilePerms::READ)
+        .build();
+
+    let (mut store, command) =
+        instantiate(API_READ_ONLY_COMPONENT, CommandCtx { table, wasi }).await?;
+
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)
+        .await?
+        .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))?;
+
+    assert_eq!(
+        fs.read_file("bar.txt").as_deref(),
+        Some(&b"And stood awhile in thought"[..])
+    );
+    assert!(fs.read_file("new.txt").is_none());
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn api_overlay() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("bar.txt"), "And stood awhile in thought")?;
+
+    for program in [
+        PREVIEW1_FILE_PREAD_PWRITE_COMPONENT,
+        PREVIEW1_PATH_RENAME_COMPONENT,
+    ] {
+        let table = ResourceTable::new();
+        let wasi = WasiCtxBuilder::new()
+            .args(&["program", "."])
+            .preopened_dir_overlay(dir.path(), ".", DirPerms::all(), FilePerms::all())?
+            .build();
+
+        let (mut store, command) = instantiate(program, CommandCtx { table, wasi }).await?;
+
+        command
+            .wasi_cli_run()
+            .call_run(&mut store)
+            .await?
+            .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))?;
+    }
+
+    let entries = std::fs::read_dir(dir.path())?
+        .map(|e| Ok(e?.file_name()))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(entries, ["bar.txt"]);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("bar.txt"))?,
+        "And stood awhile in thought"
+    );
+    Ok(())
+}
+
 #[expect(
     dead_code,
     reason = "tested in the wasi-http crate, satisfying foreach_api! macro"
@@ -48,6 +48,8 @@ pub use self::code_builder::{CodeBuilder, CodeHint, HashedEngineCompileEnv};
 
 #[cfg(feature = "runtime")]
 mod runtime;
+#[cfg(feature = "runtime")]
+pub(crate) use self::runtime::compile_lazy_functions;
 
 /// Converts an input binary-encoded WebAssembly module to compilation
 /// artifacts and type information.
@@ -73,16 +75,23 @@ pub(crate) fn build_artifacts<T: FinishedObject>(
     // about the wasm module. This is where the WebAssembly is parsed and
     // validated. Afterwards `types` will have all the type information for
     // this module.
-    let mut parser = wasmparser::Parser::new(0);
-    let mut validator = wasmparser::Validator::new_with_features(engine.features());
-    parser.set_features(*validator.features());
-    let mut types = ModuleTypesBuilder::new(&validator);
-    let mut translation = ModuleEnvironment::new(tunables, &mut validator, &mut types)
-        .translate(parser, wasm)
-        .context("failed to parse WebAssembly module")?;
+    let (types, mut translation) = translate_module(engine, wasm)?;
     let functions = mem::take(&mut translation.function_body_inputs);
 
-    let compile_inputs = CompileInputs::for_module(&types, &translation, functions);
+    let compile_inputs = if tunables.tiered_compilation {
+        // With tiered compilation functions aren't compiled until they're
+        // first called, but they're all validated here so that invalid
+        // modules are still rejected up front.
+        let functions = functions.into_iter().map(|(_, f)| f).collect();
+        engine.run_maybe_parallel(functions, |f: FunctionBodyData<'_>| {
+            f.validator
+                .into_validator(Default::default())
+                .validate(&f.body)
+        })?;
+        CompileInputs::for_tiered_module(&types, &translation)
+    } else {
+        CompileInputs::for_module(&types, &translation, functions)
+    };
     let unlinked_compile_outputs = compile_inputs.compile(engine)?;
     let PreLinkOutput {
         needs_gc_heap,
@@ -115,6 +124,12 @@ pub(crate) fn build_artifacts<T: FinishedObject>(
         dwarf_package,
     )?;
 
+    // Functions are compiled from the original wasm binary when they're first
+    // called with tiered compilation, so keep it around.
+    if tunables.tiered_compilation {
+        object.append_wasm(wasm);
+    }
+
     let info = compilation_artifacts.unwrap_as_module_info();
     let types = types.finish();
     object.serialize_info(&(&info, &types));
@@ -123,6 +138,103 @@ pub(crate) fn build_artifacts<T: FinishedObject>(
     Ok((result, Some((info, types))))
 }
 
+/// Parses and validates the wasm module `wasm`, except for its function
+/// bodies, returning the types and translation of the module.
+pub(crate) fn translate_module<'a>(
+    engine: &Engine,
+    wasm: &'a [u8],
+) -> Result<(ModuleTypesBuilder, ModuleTranslation<'a>)> {
+    let mut parser = wasmparser::Parser::new(0);
+    let mut validator = wasmparser::Validator::new_with_features(engine.features());
+    parser.set_features(*validator.features());
+    let mut types = ModuleTypesBuilder::new(&validator);
+    let translation = ModuleEnvironment::new(engine.tunables(), &mut validator, &mut types)
+        .translate(parser, wasm)
+        .context("failed to parse WebAssembly module")?;
+    Ok((types, translation))
+}
+
+/// Compiles the functions `funcs` of a module which was compiled with tiered
+/// compilation together, using `compiler`.
+///
+/// The `translation` and `types` are the result of `translate_module` for the
+/// module, and each function's body is its entry in the translation's
+/// `function_body_inputs`. The returned object contains the functions, their
+/// array-to-Wasm trampolines, and any builtin trampolines that they use, and
+/// is returned alongside the locations of the functions within the object's
+/// text section, in the same order as `funcs`.
+pub(crate) fn build_lazy_functions<'a, T: FinishedObject>(
+    engine: &Engine,
+    compiler: &dyn Compiler,
+    types: &'a ModuleTypesBuilder,
+    translation: &'a ModuleTranslation<'a>,
+    funcs: Vec<(DefinedFuncIndex, FunctionBodyData<'a>)>,
+    obj_state: &T::State,
+) -> Result<(T, Vec<CompiledFunctionInfo>)> {
+    let module = StaticModuleIndex::from_u32(0);
+    let mut compile_inputs = CompileInputs { inputs: vec![] };
+    let funcs = funcs
+        .into_iter()
+        .map(|(index, func_body)| {
+            compile_inputs.push_function_inputs(types, module, translation, index, func_body);
+            index
+        })
+        .collect::<Vec<_>>();
+    let PreLinkOutput {
+        needs_gc_heap: _,
+        compiled_funcs,
+        indices,
+    } = compile_inputs.compile_with(engine, compiler)?.pre_link();
+
+    let mut obj = compiler.object(ObjectKind::Module)?;
+    engine.append_bti(&mut obj);
+    let symbol_ids_and_locs = compiler.append_code(
+        &mut obj,
+        &compiled_funcs,
+        &|_caller_index: usize, callee: RelocationTarget| match callee {
+            RelocationTarget::Builtin(builtin) => indices.indices
+                [&CompileKey::WASM_TO_BUILTIN_TRAMPOLINE_KIND]
+                [&CompileKey::wasm_to_builtin_trampoline(builtin)]
+                .unwrap_function(),
+            // Only each function's own array-to-Wasm trampoline calls it
+            // directly; calls between Wasm functions are indirect with
+            // tiered compilation.
+            RelocationTarget::Wasm(callee) => {
+                let callee = translation.module.defined_func_index(callee).unwrap();
+                indices.indices[&CompileKey::WASM_FUNCTION_KIND]
+                    .get(&CompileKey::wasm_function(module, callee))
+                    .expect("calls to other wasm functions are indirect with tiered compilation")
+                    .unwrap_function()
+            }
+            RelocationTarget::PulleyHostcall(_) => {
+                unreachable!("relocation is resolved at runtime, not compile time");
+            }
+        },
+    )?;
+
+    let loc = |kind: u32, key: CompileKey| {
+        let i = indices.indices[&kind][&key].unwrap_function();
+        symbol_ids_and_locs[i].1
+    };
+    let infos = funcs
+        .into_iter()
+        .map(|index| {
+            let key = CompileKey::wasm_function(module, index);
+            CompiledFunctionInfo {
+                start_srcloc: indices.start_srclocs[&key],
+                wasm_func_loc: loc(CompileKey::WASM_FUNCTION_KIND, key),
+                array_to_wasm_trampoline: Some(loc(
+                    CompileKey::ARRAY_TO_WASM_TRAMPOLINE_KIND,
+                    CompileKey::array_to_wasm_trampoline(module, index),
+                )),
+            }
+        })
+        .collect();
+
+    let obj = wasmtime_environ::ObjectBuilder::new(obj, engine.tunables());
+    Ok((T::finish_object(obj, obj_state)?, infos))
+}
+
 /// Performs the compilation phase for a component, translating and
 /// validating the provided wasm binary to machine code.
 ///
@@ -144,6 +256,9 @@ pub(crate) fn build_component_artifacts<T: FinishedObject>(
 
     let tunables = engine.tunables();
     let compiler = engine.compiler();
+    if tunables.tiered_compilation {
+        bail!("tiered compilation is not supported with components");
+    }
 
     let scope = ScopeVec::new();
     let mut validator = wasmparser::Validator::new_with_features(engine.features());
@@ -237,6 +352,7 @@ impl CompileKey {
     const ARRAY_TO_WASM_TRAMPOLINE_KIND: u32 = Self::new_kind(1);
     const WASM_TO_ARRAY_TRAMPOLINE_KIND: u32 = Self::new_kind(2);
     const WASM_TO_BUILTIN_TRAMPOLINE_KIND: u32 = Self::new_kind(3);
+    const LAZY_TRAMPOLINE_KIND: u32 = Self::new_kind(6);
 
     const fn new_kind(kind: u32) -> u32 {
         assert!(kind < (1 << Self::KIND_BITS));
@@ -274,6 +390,13 @@ impl CompileKey {
             index: index.index(),
         }
     }
+
+    fn lazy_trampoline(index: ModuleInternedTypeIndex) -> Self {
+        Self {
+            namespace: Self::LAZY_TRAMPOLINE_KIND,
+            index: index.as_u32(),
+        }
+    }
 }
 
 #[cfg(feature = "component-model")]
@@ -360,6 +483,48 @@ impl<'a> CompileInputs<'a> {
         ret
     }
 
+    /// Create the `CompileInputs` for a core Wasm module compiled with tiered
+    /// compilation.
+    ///
+    /// None of the module's functions are compiled here, only trampolines:
+    /// functions are instead compiled when they're first called, which goes
+    /// through a lazy trampoline for the function's signature.
+    fn for_tiered_module(
+        types: &'a ModuleTypesBuilder,
+        translation: &'a ModuleTranslation<'a>,
+    ) -> Self {
+        let mut ret = CompileInputs { inputs: vec![] };
+
+        let module_index = StaticModuleIndex::from_u32(0);
+        ret.collect_inputs_in_translations(types, [(module_index, translation, PrimaryMap::new())]);
+
+        let mut trampoline_types_seen = HashSet::new();
+        for (_func_type_index, trampoline_type_index) in types.trampoline_types() {
+            let is_new = trampoline_types_seen.insert(trampoline_type_index);
+            if !is_new {
+                continue;
+            }
+            let trampoline_func_ty = types[trampoline_type_index].unwrap_func();
+            ret.push_input(move |compiler| {
+                let symbol = format!(
+                    "signatures[{}]::lazy_trampoline",
+                    trampoline_type_index.as_u32()
+                );
+                let trampoline = compiler
+                    .compile_wasm_to_lazy_trampoline(trampoline_func_ty, &symbol)
+                    .with_context(|| format!("failed to compile: {symbol}"))?;
+                Ok(CompileOutput {
+                    key: CompileKey::lazy_trampoline(trampoline_type_index),
+                    function: CompiledFunction::Function(trampoline),
+                    symbol,
+                    start_srcloc: FilePos::default(),
+                })
+            });
+        }
+
+        ret
+    }
+
     /// Create a `CompileInputs` for a component.
     #[cfg(feature = "component-model")]
     fn for_component(
@@ -451,6 +616,73 @@ impl<'a> CompileInputs<'a> {
         }
     }
 
+    /// Push the inputs to compile the defined function `def_func_index` of
+    /// `translation`, along with its array-to-Wasm trampoline if it escapes.
+    fn push_function_inputs(
+        &mut self,
+        types: &'a ModuleTypesBuilder,
+        module: StaticModuleIndex,
+        translation: &'a ModuleTranslation<'a>,
+        def_func_index: DefinedFuncIndex,
+        func_body: FunctionBodyData<'a>,
+    ) {
+        self.push_input(move |compiler| {
+            let func_index = translation.module.func_index(def_func_index);
+            let symbol = match translation
+                .debuginfo
+                .name_section
+                .func_names
+                .get(&func_index)
+            {
+                Some(name) => format!(
+                    "wasm[{}]::function[{}]::{}",
+                    module.as_u32(),
+                    func_index.as_u32(),
+                    Self::clean_symbol(&name)
+                ),
+                None => format!(
+                    "wasm[{}]::function[{}]",
+                    module.as_u32(),
+                    func_index.as_u32()
+                ),
+            };
+            let data = func_body.body.get_binary_reader();
+            let offset = data.original_position();
+            let start_srcloc = FilePos::new(u32::try_from(offset).unwrap());
+            let function = compiler
+                .compile_function(translation, def_func_index, func_body, types, &symbol)
+                .with_context(|| format!("failed to compile: {symbol}"))?;
+
+            Ok(CompileOutput {
+                key: CompileKey::wasm_function(module, def_func_index),
+                symbol,
+                function: CompiledFunction::Function(function),
+                start_srcloc,
+            })
+        });
+
+        let func_index = translation.module.func_index(def_func_index);
+        if translation.module.functions[func_index].is_escaping() {
+            self.push_input(move |compiler| {
+                let func_index = translation.module.func_index(def_func_index);
+                let symbol = format!(
+                    "wasm[{}]::array_to_wasm_trampoline[{}]",
+                    module.as_u32(),
+                    func_index.as_u32()
+                );
+                let trampoline = compiler
+                    .compile_array_to_wasm_trampoline(translation, types, def_func_index, &symbol)
+                    .with_context(|| format!("failed to compile: {symbol}"))?;
+                Ok(CompileOutput {
+                    key: CompileKey::array_to_wasm_trampoline(module, def_func_index),
+                    symbol,
+                    function: CompiledFunction::Function(trampoline),
+                    start_srcloc: FilePos::default(),
+                })
+            });
+        }
+    }
+
     fn collect_inputs_in_translations(
         &mut self,
         types: &'a ModuleTypesBuilder,
@@ -464,66 +696,7 @@ impl<'a> CompileInputs<'a> {
     ) {
         for (module, translation, functions) in translations {
             for (def_func_index, func_body) in functions {
-                self.push_input(move |compiler| {
-                    let func_index = translation.module.func_index(def_func_index);
-                    let symbol = match translation
-                        .debuginfo
-                        .name_section
-                        .func_names
-                        .get(&func_index)
-                    {
-                        Some(name) => format!(
-                            "wasm[{}]::function[{}]::{}",
-                            module.as_u32(),
-                            func_index.as_u32(),
-                            Self::clean_symbol(&name)
-                        ),
-                        None => format!(
-                            "wasm[{}]::function[{}]",
-                            module.as_u32(),
-                            func_index.as_u32()
-                        ),
-                    };
-                    let data = func_body.body.get_binary_reader();
-                    let offset = data.original_position();
-                    let start_srcloc = FilePos::new(u32::try_from(offset).unwrap());
-                    let function = compiler
-                        .compile_function(translation, def_func_index, func_body, types, &symbol)
-                        .with_context(|| format!("failed to compile: {symbol}"))?;
-
-                    Ok(CompileOutput {
-                        key: CompileKey::wasm_function(module, def_func_index),
-                        symbol,
-                        function: CompiledFunction::Function(function),
-                        start_srcloc,
-                    })
-                });
-
-                let func_index = translation.module.func_index(def_func_index);
-                if translation.module.functions[func_index].is_escaping() {
-                    self.push_input(move |compiler| {
-                        let func_index = translation.module.func_index(def_func_index);
-                        let symbol = format!(
-                            "wasm[{}]::array_to_wasm_trampoline[{}]",
-                            module.as_u32(),
-                            func_index.as_u32()
-                        );
-                        let trampoline = compiler
-                            .compile_array_to_wasm_trampoline(
-                                translation,
-                                types,
-                                def_func_index,
-                                &symbol,
-                            )
-                            .with_context(|| format!("failed to compile: {symbol}"))?;
-                        Ok(CompileOutput {
-                            key: CompileKey::array_to_wasm_trampoline(module, def_func_index),
-                            symbol,
-                            function: CompiledFunction::Function(trampoline),
-                            start_srcloc: FilePos::default(),
-                        })
-                    });
-                }
+                self.push_function_inputs(types, module, translation, def_func_index, func_body);
             }
         }
 
@@ -555,8 +728,16 @@ impl<'a> CompileInputs<'a> {
     /// Compile these `CompileInput`s (maybe in parallel) and return the
     /// resulting `UnlinkedCompileOutput`s.
     fn compile(self, engine: &Engine) -> Result<UnlinkedCompileOutputs> {
-        let compiler = engine.compiler();
+        self.compile_with(engine, engine.compiler())
+    }
 
+    /// Same as `compile`, but using `compiler` rather than the engine's
+    /// compiler.
+    fn compile_with(
+        self,
+        engine: &Engine,
+        compiler: &dyn Compiler,
+    ) -> Result<UnlinkedCompileOutputs> {
         if self.inputs.len() > 0 && cfg!(miri) {
             bail!(
                 "\
@@ -582,7 +763,7 @@ the use case.
         // wasmtime-builtin functions are necessary. If so those need to be
         // collected and then those trampolines additionally need to be
         // compiled.
-        compile_required_builtins(engine, &mut raw_outputs)?;
+        compile_required_builtins(engine, compiler, &mut raw_outputs)?;
 
         // Bucket the outputs by kind.
         let mut outputs: BTreeMap<u32, Vec<CompileOutput>> = BTreeMap::new();
@@ -594,8 +775,11 @@ the use case.
     }
 }
 
-fn compile_required_builtins(engine: &Engine, raw_outputs: &mut Vec<CompileOutput>) -> Result<()> {
-    let compiler = engine.compiler();
+fn compile_required_builtins(
+    engine: &Engine,
+    compiler: &dyn Compiler,
+    raw_outputs: &mut Vec<CompileOutput>,
+) -> Result<()> {
     let mut builtins = HashSet::new();
     let mut new_inputs: Vec<CompileInput<'_>> = Vec::new();
 
@@ -844,6 +1028,10 @@ impl FunctionIndices {
             .indices
             .remove(&CompileKey::WASM_TO_ARRAY_TRAMPOLINE_KIND)
             .unwrap_or_default();
+        let lazy_trampolines = self
+            .indices
+            .remove(&CompileKey::LAZY_TRAMPOLINE_KIND)
+            .unwrap_or_default();
 
         artifacts.modules = translations
             .into_iter()
@@ -904,8 +1092,25 @@ impl FunctionIndices {
                         (*idx, symbol_ids_and_locs[compiled.unwrap_function()].1)
                     })
                     .collect();
-
-                obj.append(translation, funcs, wasm_to_array_trampolines)
+                let lazy_trampolines = if lazy_trampolines.is_empty() {
+                    Vec::new()
+                } else {
+                    unique_and_sorted_trampoline_sigs
+                        .iter()
+                        .map(|idx| {
+                            let key = CompileKey::lazy_trampoline(*idx);
+                            let compiled = lazy_trampolines[&key];
+                            (*idx, symbol_ids_and_locs[compiled.unwrap_function()].1)
+                        })
+                        .collect()
+                };
+
+                obj.append(
+                    translation,
+                    funcs,
+                    wasm_to_array_trampolines,
+                    lazy_trampolines,
+                )
             })
             .collect::<Result<PrimaryMap<_, _>>>()?;
 
@@ -5,7 +5,10 @@ use crate::runtime::vm::MmapVec;
 use crate::{CodeBuilder, CodeMemory, Engine, Module};
 use object::write::WritableBuffer;
 use std::sync::Arc;
-use wasmtime_environ::{FinishedObject, ObjectBuilder};
+use wasmtime_environ::{
+    CompiledFunctionInfo, Compiler, DefinedFuncIndex, FinishedObject, FunctionBodyData,
+    ModuleTranslation, ModuleTypesBuilder, ObjectBuilder,
+};
 
 impl<'a> CodeBuilder<'a> {
     fn compile_cached<T, S>(
@@ -109,16 +112,42 @@ impl<'a> CodeBuilder<'a> {
     }
 
     fn custom_alignment(&self) -> CustomAlignment {
-        CustomAlignment {
-            alignment: self
-                .engine
-                .custom_code_memory()
-                .map(|c| c.required_alignment())
-                .unwrap_or(1),
-        }
+        custom_alignment(self.engine)
+    }
+}
+
+fn custom_alignment(engine: &Engine) -> CustomAlignment {
+    CustomAlignment {
+        alignment: engine
+            .custom_code_memory()
+            .map(|c| c.required_alignment())
+            .unwrap_or(1),
     }
 }
 
+/// Compiles the functions `funcs` of a module compiled with tiered
+/// compilation together using `compiler`, returning their published code and
+/// the locations of the functions within it.
+///
+/// See `build_lazy_functions` for more information.
+pub(crate) fn compile_lazy_functions<'a>(
+    engine: &Engine,
+    compiler: &dyn Compiler,
+    types: &'a ModuleTypesBuilder,
+    translation: &'a ModuleTranslation<'a>,
+    funcs: Vec<(DefinedFuncIndex, FunctionBodyData<'a>)>,
+) -> Result<(Arc<CodeMemory>, Vec<CompiledFunctionInfo>)> {
+    let (mmap, infos) = super::build_lazy_functions::<MmapVecWrapper>(
+        engine,
+        compiler,
+        types,
+        translation,
+        funcs,
+        &custom_alignment(engine),
+    )?;
+    Ok((publish_mmap(engine, mmap.0)?, infos))
+}
+
 fn publish_mmap(engine: &Engine, mmap: MmapVec) -> Result<Arc<CodeMemory>> {
     let mut code = CodeMemory::new(engine, mmap)?;
     code.publish()?;
@@ -124,6 +124,8 @@ pub struct Config {
     target: Option<target_lexicon::Triple>,
     #[cfg(feature = "gc")]
     collector: Collector,
+    #[cfg(feature = "gc")]
+    pub(crate) incremental_gc_budget: Option<core::num::NonZeroUsize>,
     profiling_strategy: ProfilingStrategy,
     tunables: ConfigTunables,
 
@@ -154,10 +156,21 @@ pub struct Config {
     #[cfg(feature = "async")]
     pub(crate) stack_creator: Option<Arc<dyn RuntimeFiberStackCreator>>,
     pub(crate) async_support: bool,
+    pub(crate) deterministic: bool,
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub(crate) tier_up_threshold: u32,
     pub(crate) module_version: ModuleVersionStrategy,
     pub(crate) parallel_compilation: bool,
     pub(crate) memory_guaranteed_dense_image_size: u64,
     pub(crate) force_memory_init_memfd: bool,
+    #[cfg(feature = "memory-image-dir")]
+    pub(crate) memory_image_dir: Option<std::path::PathBuf>,
+    #[cfg(feature = "memory-protection-keys")]
+    pub(crate) memory_protection_keys: MpkEnabled,
+    #[cfg(feature = "memory-protection-keys")]
+    pub(crate) max_memory_protection_keys: usize,
+    #[cfg(feature = "memory-protection-keys")]
+    pub(crate) max_protected_memory_size: usize,
     pub(crate) wmemcheck: bool,
     #[cfg(feature = "coredump")]
     pub(crate) coredump_on_trap: bool,
@@ -230,6 +243,8 @@ impl Config {
             target: None,
             #[cfg(feature = "gc")]
             collector: Collector::default(),
+            #[cfg(feature = "gc")]
+            incremental_gc_budget: None,
             #[cfg(feature = "cache")]
             cache: None,
             profiling_strategy: ProfilingStrategy::None,
@@ -259,10 +274,21 @@ impl Config {
             #[cfg(feature = "async")]
             stack_creator: None,
             async_support: false,
+            deterministic: false,
+            #[cfg(any(feature = "cranelift", feature = "winch"))]
+            tier_up_threshold: 1000,
             module_version: ModuleVersionStrategy::default(),
             parallel_compilation: !cfg!(miri),
             memory_guaranteed_dense_image_size: 16 << 20,
             force_memory_init_memfd: false,
+            #[cfg(feature = "memory-image-dir")]
+            memory_image_dir: None,
+            #[cfg(feature = "memory-protection-keys")]
+            memory_protection_keys: MpkEnabled::Disable,
+            #[cfg(feature = "memory-protection-keys")]
+            max_memory_protection_keys: 16,
+            #[cfg(feature = "memory-protection-keys")]
+            max_protected_memory_size: 1 << 30,
             wmemcheck: false,
             #[cfg(feature = "coredump")]
             coredump_on_trap: false,
@@ -555,6 +581,53 @@ impl Config {
         self
     }
 
+    /// Configures whether the fuel consumed by WebAssembly is also attributed
+    /// to the function that consumed it.
+    ///
+    /// When enabled, compiled code maintains a counter of the fuel consumed by
+    /// each function of each instance, in addition to the store-wide fuel
+    /// counter, which can be read with
+    /// [`Store::fuel_profile`](crate::Store::fuel_profile). Fuel consumed by a
+    /// function does not include the fuel consumed by the functions that it
+    /// calls.
+    ///
+    /// This requires [`Config::consume_fuel`] to be enabled, and makes fuel
+    /// consumption somewhat more expensive.
+    ///
+    /// By default this option is `false`.
+    pub fn fuel_profiling(&mut self, enable: bool) -> &mut Self {
+        self.tunables.fuel_profiling = Some(enable);
+        self
+    }
+
+    /// Configures whether compiled code reports every access to linear
+    /// memory to the host.
+    ///
+    /// When enabled, each load, store, and atomic access performed by a
+    /// WebAssembly function calls the hook configured with
+    /// [`Store::memory_access_hook`](crate::Store::memory_access_hook) before
+    /// the memory is accessed, with a [`MemoryAccess`](crate::MemoryAccess)
+    /// describing the address, size, and kind of the access as well as the
+    /// function and instruction which performed it. This can be used to build
+    /// tools such as data race detectors or cache simulators. Bulk memory
+    /// operations, such as `memory.copy`, and atomic waits and notifications
+    /// aren't reported.
+    ///
+    /// The hook is called before the access is bounds-checked, so accesses
+    /// which go on to trap are reported as well, and the hook may grow the
+    /// accessed memory.
+    ///
+    /// This makes every memory access much more expensive, so it's only
+    /// intended for instrumentation and debugging. Modules compiled with this
+    /// enabled can only be loaded into engines which also enable it. This is
+    /// only supported by Cranelift, not Winch.
+    ///
+    /// By default this option is `false`.
+    pub fn memory_access_tracing(&mut self, enable: bool) -> &mut Self {
+        self.tunables.memory_access_tracing = Some(enable);
+        self
+    }
+
     /// Enables epoch-based interruption.
     ///
     /// When executing code in async mode, we sometimes want to
@@ -1005,6 +1078,43 @@ impl Config {
         self
     }
 
+    /// Configures whether WebAssembly executes deterministically, producing
+    /// the same results on every host given the same inputs.
+    ///
+    /// This enables, and validates, every setting that Wasmtime knows of which
+    /// is required for deterministic execution:
+    ///
+    /// * NaNs produced by floating-point instructions are canonicalized, as
+    ///   with [`Config::cranelift_nan_canonicalization`].
+    /// * Relaxed SIMD instructions have their deterministic behavior, as with
+    ///   [`Config::relaxed_simd_deterministic`].
+    /// * The [threads proposal](Config::wasm_threads) is disabled by default,
+    ///   and it is an error to enable it.
+    /// * A `memory.grow` which fails for any reason other than exceeding the
+    ///   memory's maximum size or being rejected by a
+    ///   [`ResourceLimiter`](crate::ResourceLimiter), such as the host being
+    ///   unable to allocate memory, traps instead of returning -1.
+    ///
+    /// Hosts can check [`Engine::is_deterministic`](crate::Engine::is_deterministic)
+    /// to configure their own sources of nondeterminism. For example a
+    /// `wasmtime-wasi` context built with `WasiCtxBuilder::deterministic`
+    /// traps when WebAssembly reads the host's clocks or random numbers.
+    ///
+    /// It is an error to enable this along with settings that conflict with it,
+    /// such as disabling NaN canonicalization, and it is not supported by the
+    /// Winch compiler.
+    ///
+    /// Note that deterministic execution only covers WebAssembly itself; host
+    /// functions are responsible for their own determinism, and resource
+    /// exhaustion such as stack overflow or running out of fuel depends on the
+    /// configuration of the engine and store.
+    ///
+    /// This is `false` by default.
+    pub fn deterministic(&mut self, enable: bool) -> &mut Self {
+        self.deterministic = enable;
+        self
+    }
+
     /// Configures whether the [WebAssembly bulk memory operations
     /// proposal][proposal] will be enabled for compilation.
     ///
@@ -1214,6 +1324,28 @@ impl Config {
         self
     }
 
+    /// Configures how many times a function must be called, with
+    /// [`Strategy::Tiered`], before it's recompiled with Cranelift.
+    ///
+    /// Functions are first compiled with Winch when they're called for the
+    /// first time. Once a function has been called this many times within an
+    /// instance it's recompiled with Cranelift, and from then on all calls to
+    /// the function use Cranelift's code. Other functions of the instance
+    /// which have been called at least half this many times are recompiled
+    /// along with it.
+    ///
+    /// Functions are only recompiled when they're called, so a call which is
+    /// already running keeps running Winch's code until it returns.
+    ///
+    /// This has no effect with other compilation strategies.
+    ///
+    /// The default value for this is 1000.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub fn tier_up_threshold(&mut self, calls: u32) -> &mut Self {
+        self.tier_up_threshold = calls;
+        self
+    }
+
     /// Configures which garbage collector will be used for Wasm modules.
     ///
     /// This method can be used to configure which garbage collector
@@ -1227,6 +1359,38 @@ impl Config {
         self
     }
 
+    /// Configures the garbage collector to collect incrementally, doing at
+    /// most `budget` units of work per step.
+    ///
+    /// By default each garbage collection runs to completion, which can pause
+    /// the program for time proportional to the amount of garbage being
+    /// reclaimed. When a budget is configured, each collection -- whether
+    /// triggered explicitly with [`Store::gc`](crate::Store::gc), or
+    /// implicitly by an allocation or by Wasm -- only does a bounded amount of
+    /// work, and any garbage it didn't get to is reclaimed by later steps
+    /// instead. Dropping the last reference to a large data structure is
+    /// similarly bounded, rather than freeing the whole structure at once.
+    ///
+    /// For the [`Collector::DeferredReferenceCounting`] collector a unit of
+    /// work is a single reference count decrement, so a budget of `n` frees at
+    /// most `n` objects per step.
+    ///
+    /// Note that pauses are only bounded while the GC heap has room to grow.
+    /// If an allocation fails and the GC heap cannot be grown any further, the
+    /// collector finishes all of its outstanding work before reporting that
+    /// the heap is out of memory.
+    ///
+    /// Passing `None` disables incremental collection. Incremental collection
+    /// is only supported by the [`Collector::DeferredReferenceCounting`]
+    /// collector.
+    ///
+    /// The default value for this is `None`.
+    #[cfg(feature = "gc")]
+    pub fn incremental_gc_budget(&mut self, budget: Option<core::num::NonZeroUsize>) -> &mut Self {
+        self.incremental_gc_budget = budget;
+        self
+    }
+
     /// Creates a default profiler based on the profiling strategy chosen.
     ///
     /// Profiler creation calls the type's default initializer where the purpose is
@@ -1803,6 +1967,112 @@ impl Config {
         self
     }
 
+    /// Configures whether linear memories are backed by huge pages.
+    ///
+    /// Guests with large heaps can spend much of their time on TLB misses when
+    /// their linear memory is backed by regular host pages. With this option
+    /// enabled the start of each linear memory's reservation is aligned to
+    /// 2MiB, and the kernel is asked with `madvise(MADV_HUGEPAGE)` to back the
+    /// memory with transparent huge pages. This applies to memories from both
+    /// the on-demand and the pooling allocator; in the latter every slot in
+    /// the pool is additionally rounded up to a multiple of 2MiB.
+    ///
+    /// Whether huge pages are actually used is still up to the kernel: this
+    /// requires transparent huge pages to be set to `madvise` or `always` in
+    /// `/sys/kernel/mm/transparent_hugepage/enabled`. Note that the first
+    /// access to a huge page zeroes, or copies, 2MiB at once, and that the
+    /// resident memory of an instance is rounded up accordingly.
+    ///
+    /// Explicit huge pages from `hugetlbfs` are not supported, since their
+    /// memory protection granularity is the huge page size which can't
+    /// represent the bounds of a linear memory with 64KiB pages.
+    ///
+    /// This option is only supported on Linux, and is disabled by default.
+    pub fn memory_huge_pages(&mut self, enable: bool) -> &mut Self {
+        self.tunables.memory_huge_pages = Some(enable);
+        self
+    }
+
+    /// Configures whether memory protection keys (MPK) should be used to
+    /// shrink the guard regions of linear memories allocated by the on-demand
+    /// allocator.
+    ///
+    /// This is the on-demand counterpart of
+    /// [`PoolingAllocationConfig::memory_protection_keys`], which has more
+    /// details on how MPK works, and has no effect when
+    /// [`InstanceAllocationStrategy::Pooling`] is used. With MPK enabled the
+    /// on-demand allocator places linear memories defined by modules into
+    /// regions of address space that it reserves in bulk and "colors" with
+    /// different protection keys, so that the memories of other stores act as
+    /// guard regions for each other. Each memory then needs only
+    /// [`Config::max_protected_memory_size`] bytes of address space plus a
+    /// fraction of [`Config::memory_reservation`] and
+    /// [`Config::memory_guard_size`] instead of all of them.
+    ///
+    /// Memories which can't be placed this way are allocated as usual, without
+    /// a protection key: shared memories, memories without a declared maximum
+    /// or whose maximum size exceeds [`Config::max_protected_memory_size`],
+    /// and GC heaps.
+    ///
+    /// This configuration setting can be in three states:
+    ///
+    /// - `auto`: use MPK if supported by the host, and allocate memories as
+    ///   usual otherwise
+    /// - `enable`: use MPK; creating an [`Engine`] fails if MPK is not
+    ///   supported
+    /// - `disable`: never use MPK
+    ///
+    /// MPK is not used when a custom [`Config::with_host_memory`] is
+    /// configured, and creating an [`Engine`] fails if this is `enable` in that
+    /// case.
+    ///
+    /// By default this value is `disable`.
+    ///
+    /// __WARNING__: this configuration options is still experimental--use at
+    /// your own risk! MPK uses kernel and CPU features to protect memory
+    /// regions; you may observe segmentation faults if anything is
+    /// misconfigured.
+    ///
+    /// [`Engine`]: crate::Engine
+    #[cfg(feature = "memory-protection-keys")]
+    pub fn memory_protection_keys(&mut self, enable: MpkEnabled) -> &mut Self {
+        self.memory_protection_keys = enable;
+        self
+    }
+
+    /// Sets an upper limit on how many memory protection keys (MPK) the
+    /// on-demand allocator will use.
+    ///
+    /// This setting is only applicable when [`Config::memory_protection_keys`]
+    /// is set to `enable` or `auto`; see
+    /// [`PoolingAllocationConfig::max_memory_protection_keys`] for how keys
+    /// are shared between engines.
+    ///
+    /// By default this is 16.
+    #[cfg(feature = "memory-protection-keys")]
+    pub fn max_memory_protection_keys(&mut self, max: usize) -> &mut Self {
+        self.max_memory_protection_keys = max;
+        self
+    }
+
+    /// The maximum size, in bytes, that linear memories tagged with a memory
+    /// protection key may grow to.
+    ///
+    /// This setting is only applicable when [`Config::memory_protection_keys`]
+    /// is set to `enable` or `auto`. Memories tagged with a protection key
+    /// can't move, so only memories whose declared maximum size is at most
+    /// this value are tagged; all other memories are allocated as usual and
+    /// can grow up to their own maximum. Smaller values save more address
+    /// space per memory, but fewer memories will benefit from protection
+    /// keys.
+    ///
+    /// By default this is 1 GiB.
+    #[cfg(feature = "memory-protection-keys")]
+    pub fn max_protected_memory_size(&mut self, bytes: usize) -> &mut Self {
+        self.max_protected_memory_size = bytes;
+        self
+    }
+
     /// Indicates whether a guard region is present before allocations of
     /// linear memory.
     ///
@@ -1967,6 +2237,50 @@ impl Config {
         self
     }
 
+    /// Configures a directory of files to back modules' initial memory images,
+    /// so that processes on the same machine can share them.
+    ///
+    /// When [`Config::memory_init_cow`] is enabled, a module that wasn't loaded
+    /// from a precompiled file on disk gets a new anonymous in-memory file
+    /// (`memfd_create` on Linux) for its initial memory image, separately in
+    /// every process. When many worker processes instantiate the same module
+    /// that means many copies of the same image in memory.
+    ///
+    /// With this option set the image is instead written to a file in `dir`
+    /// named after the SHA-256 hash of its contents, or an existing file with
+    /// identical contents is reused, and linear memories are mapped
+    /// copy-on-write from that file. All processes using the same directory
+    /// then share the page cache pages of the image. The contents of an
+    /// existing file are always checked before it is used, and a new anonymous
+    /// file is used instead if they don't match, or if the image can't be
+    /// written to `dir`, for example because it isn't writable.
+    ///
+    /// Files in `dir` are created read-only, are only ever opened for reading
+    /// once created, and are never removed by Wasmtime.
+    /// They must not be modified while in use, since pages of the image that
+    /// haven't been written to by an instance reflect the file's current
+    /// contents. The directory should therefore only be writable by trusted
+    /// processes; a `tmpfs` mount is a good fit.
+    ///
+    /// This takes precedence over [`Config::force_memory_init_memfd`]. Another
+    /// way to share images across processes is to pass around a serialized
+    /// module in a file, see [`Module::serialize_to_memfd`] and
+    /// [`Module::deserialize_open_file`].
+    ///
+    /// This option is only supported on Unix platforms, and is unset by
+    /// default.
+    ///
+    /// This method is only available when the `memory-image-dir` feature of
+    /// this crate is enabled.
+    ///
+    /// [`Module::serialize_to_memfd`]: crate::Module::serialize_to_memfd
+    /// [`Module::deserialize_open_file`]: crate::Module::deserialize_open_file
+    #[cfg(feature = "memory-image-dir")]
+    pub fn memory_image_dir(&mut self, dir: impl Into<std::path::PathBuf>) -> &mut Self {
+        self.memory_image_dir = Some(dir.into());
+        self
+    }
+
     /// Configures whether or not a coredump should be generated and attached to
     /// the anyhow::Error when a trap is raised.
     ///
@@ -2082,7 +2396,7 @@ impl Config {
                 }
                 unsupported
             }
-            Some(Strategy::Winch) => {
+            Some(Strategy::Winch | Strategy::Tiered) => {
                 let mut unsupported = WasmFeatures::GC
                     | WasmFeatures::FUNCTION_REFERENCES
                     | WasmFeatures::RELAXED_SIMD
@@ -2141,7 +2455,10 @@ impl Config {
         // Set some features to their conditionally-enabled defaults depending
         // on crate compile-time features.
         features.set(WasmFeatures::GC_TYPES, cfg!(feature = "gc"));
-        features.set(WasmFeatures::THREADS, cfg!(feature = "threads"));
+        features.set(
+            WasmFeatures::THREADS,
+            cfg!(feature = "threads") && !self.deterministic,
+        );
         features.set(
             WasmFeatures::COMPONENT_MODEL,
             cfg!(feature = "component-model"),
@@ -2200,6 +2517,11 @@ impl Config {
             panic!("should have returned an error by now")
         }
 
+        #[cfg(all(feature = "memory-image-dir", not(unix)))]
+        if self.memory_image_dir.is_some() {
+            bail!("`Config::memory_image_dir` is only supported on Unix platforms");
+        }
+
         #[cfg(any(feature = "async", feature = "stack-switching"))]
         if self.async_support && self.max_wasm_stack > self.async_stack_size {
             bail!("max_wasm_stack size cannot exceed the async_stack_size");
@@ -2241,21 +2563,74 @@ impl Config {
 
         self.tunables.configure(&mut tunables);
 
+        if tunables.fuel_profiling && !tunables.consume_fuel {
+            bail!("fuel profiling requires fuel consumption to be enabled");
+        }
+
+        if tunables.memory_huge_pages && !cfg!(target_os = "linux") {
+            bail!("`Config::memory_huge_pages` is only supported on Linux");
+        }
+
+        #[cfg(any(feature = "cranelift", feature = "winch"))]
+        if tunables.memory_access_tracing
+            && matches!(
+                self.compiler_config.strategy,
+                Some(Strategy::Winch | Strategy::Tiered)
+            )
+        {
+            bail!("memory access tracing is not supported by Winch");
+        }
+
+        if self.deterministic {
+            if features.contains(WasmFeatures::THREADS)
+                || features.contains(WasmFeatures::SHARED_EVERYTHING_THREADS)
+            {
+                bail!("threads cannot be enabled in deterministic mode");
+            }
+            if self.tunables.relaxed_simd_deterministic == Some(false) {
+                bail!("relaxed SIMD must be deterministic in deterministic mode");
+            }
+            tunables.relaxed_simd_deterministic = true;
+            #[cfg(any(feature = "cranelift", feature = "winch"))]
+            if matches!(
+                self.compiler_config.strategy,
+                Some(Strategy::Winch | Strategy::Tiered)
+            ) {
+                bail!("deterministic mode is not supported by Winch");
+            }
+        }
+
         // If we're going to compile with winch, we must use the winch calling convention.
+        // This includes Cranelift's code with tiered compilation, which must be
+        // callable from code compiled by Winch.
         #[cfg(any(feature = "cranelift", feature = "winch"))]
         {
-            tunables.winch_callable = self.compiler_config.strategy == Some(Strategy::Winch);
+            tunables.winch_callable = matches!(
+                self.compiler_config.strategy,
+                Some(Strategy::Winch | Strategy::Tiered)
+            );
+            tunables.tiered_compilation = self.compiler_config.strategy == Some(Strategy::Tiered);
         }
 
         tunables.collector = if features.gc_types() {
             #[cfg(feature = "gc")]
             {
                 use wasmtime_environ::Collector as EnvCollector;
-                Some(match self.collector.try_not_auto()? {
+                let collector = match self.collector.try_not_auto()? {
                     Collector::DeferredReferenceCounting => EnvCollector::DeferredReferenceCounting,
                     Collector::Null => EnvCollector::Null,
+                    Collector::Copying => EnvCollector::Copying,
                     Collector::Auto => unreachable!(),
-                })
+                };
+                if self.incremental_gc_budget.is_some()
+                    && collector != EnvCollector::DeferredReferenceCounting
+                {
+                    bail!(
+                        "incremental garbage collection is only supported by the \
+                         deferred reference-counting collector"
+                    );
+                }
+                Some(collector)
             }
             #[cfg(not(feature = "gc"))]
             bail!("cannot use GC types: the `gc` feature was disabled at compile time")
@@ -2291,6 +2666,24 @@ impl Config {
                 if let Some(stack_creator) = &self.stack_creator {
                     allocator.set_stack_creator(stack_creator.clone());
                 }
+                #[cfg(feature = "memory-protection-keys")]
+                match self.memory_protection_keys {
+                    MpkEnabled::Disable => {}
+                    _ if self.mem_creator.is_some() => {
+                        if self.memory_protection_keys == MpkEnabled::Enable {
+                            bail!(
+                                "memory protection keys cannot be used with a custom \
+                                 memory creator"
+                            );
+                        }
+                    }
+                    enable => allocator.enable_memory_protection_keys(
+                        enable,
+                        self.max_memory_protection_keys,
+                        self.max_protected_memory_size,
+                        tunables,
+                    )?,
+                }
                 Ok(allocator)
             }
             #[cfg(feature = "pooling-allocator")]
@@ -2316,7 +2709,7 @@ impl Config {
 
         #[cfg(feature = "gc")]
         #[cfg_attr(
-            not(any(feature = "gc-null", feature = "gc-drc")),
+            not(any(feature = "gc-null", feature = "gc-drc", feature = "gc-copying")),
             allow(unused_variables, unreachable_code)
         )]
         {
@@ -2335,6 +2728,13 @@ impl Config {
                 #[cfg(not(feature = "gc-null"))]
                 Collector::Null => unreachable!(),
 
+                #[cfg(feature = "gc-copying")]
+                Collector::Copying => {
+                    Arc::new(crate::runtime::vm::CopyingCollector::default()) as Arc<dyn GcRuntime>
+                }
+                #[cfg(not(feature = "gc-copying"))]
+                Collector::Copying => unreachable!(),
+
                 Collector::Auto => unreachable!(),
             }))
         }
@@ -2358,19 +2758,7 @@ impl Config {
         features: WasmFeatures,
     ) -> Result<(Self, Box<dyn wasmtime_environ::Compiler>)> {
         let target = self.compiler_target();
-
-        // The target passed to the builders below is an `Option<Triple>` where
-        // `None` represents the current host with CPU features inferred from
-        // the host's CPU itself. The `target` above is not an `Option`, so
-        // switch it to `None` in the case that a target wasn't explicitly
-        // specified (which indicates no feature inference) and the target
-        // matches the host.
-        let target_for_builder =
-            if self.target.is_none() && target == target_lexicon::Triple::host() {
-                None
-            } else {
-                Some(target.clone())
-            };
+        let target_for_builder = self.target_for_builder();
 
         let mut compiler = match self.compiler_config.strategy {
             #[cfg(feature = "cranelift")]
@@ -2381,14 +2769,18 @@ impl Config {
             Some(Strategy::Winch) => wasmtime_winch::builder(target_for_builder)?,
             #[cfg(not(feature = "winch"))]
             Some(Strategy::Winch) => bail!("winch support not compiled in"),
+            #[cfg(all(feature = "cranelift", feature = "winch"))]
+            Some(Strategy::Tiered) => wasmtime_winch::builder(target_for_builder)?,
+            #[cfg(not(all(feature = "cranelift", feature = "winch")))]
+            Some(Strategy::Tiered) => {
+                bail!(
+                    "tiered compilation requires both cranelift and winch support to be compiled in"
+                )
+            }
 
             None | Some(Strategy::Auto) => unreachable!(),
         };
 
-        if let Some(path) = &self.compiler_config.clif_dir {
-            compiler.clif_dir(path)?;
-        }
-
         // If probestack is enabled for a target, Wasmtime will always use the
         // inline strategy which doesn't require us to define a `__probestack`
         // function or similar.
@@ -2472,6 +2864,14 @@ impl Config {
             }
         }
 
+        if self.deterministic
+            && !self
+                .compiler_config
+                .ensure_setting_unset_or_given("enable_nan_canonicalization", "true")
+        {
+            bail!("NaN canonicalization cannot be disabled in deterministic mode");
+        }
+
         if features.contains(WasmFeatures::RELAXED_SIMD) && !features.contains(WasmFeatures::SIMD) {
             bail!("cannot disable the simd proposal but enable the relaxed simd proposal");
         }
@@ -2497,7 +2897,66 @@ impl Config {
             }
         }
 
-        // Apply compiler settings and flags
+        self.configure_compiler(&mut *compiler, tunables)?;
+        Ok((self, compiler.build()?))
+    }
+
+    /// Builds the compiler which functions are recompiled with once they've
+    /// been called enough times with [`Strategy::Tiered`], or `None` if that
+    /// isn't the configured strategy.
+    ///
+    /// This must be called on the `Config` returned by `build_compiler` so the
+    /// settings that it configures apply to this compiler too.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub(crate) fn build_tier_up_compiler(
+        &self,
+        tunables: &Tunables,
+    ) -> Result<Option<Box<dyn wasmtime_environ::Compiler>>> {
+        if self.compiler_config.strategy != Some(Strategy::Tiered) {
+            return Ok(None);
+        }
+        #[cfg(all(feature = "cranelift", feature = "winch"))]
+        {
+            let mut compiler = wasmtime_cranelift::builder(self.target_for_builder())?;
+            self.configure_compiler(&mut *compiler, tunables)?;
+            Ok(Some(compiler.build()?))
+        }
+        #[cfg(not(all(feature = "cranelift", feature = "winch")))]
+        {
+            let _ = tunables;
+            unreachable!()
+        }
+    }
+
+    /// The target passed to compiler builders.
+    ///
+    /// This is an `Option<Triple>` where `None` represents the current host
+    /// with CPU features inferred from the host's CPU itself. The target from
+    /// `compiler_target` is not an `Option`, so switch it to `None` in the case
+    /// that a target wasn't explicitly specified (which indicates no feature
+    /// inference) and the target matches the host.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    fn target_for_builder(&self) -> Option<target_lexicon::Triple> {
+        let target = self.compiler_target();
+        if self.target.is_none() && target == target_lexicon::Triple::host() {
+            None
+        } else {
+            Some(target)
+        }
+    }
+
+    /// Applies this configuration's compiler settings and flags, along with
+    /// `tunables`, to `compiler`.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    fn configure_compiler(
+        &self,
+        compiler: &mut dyn wasmtime_environ::CompilerBuilder,
+        tunables: &Tunables,
+    ) -> Result<()> {
+        if let Some(path) = &self.compiler_config.clif_dir {
+            compiler.clif_dir(path)?;
+        }
+
         for (k, v) in self.compiler_config.settings.iter() {
             compiler.set(k, v)?;
         }
@@ -2512,8 +2971,7 @@ impl Config {
 
         compiler.set_tunables(tunables.clone())?;
         compiler.wmemcheck(self.compiler_config.wmemcheck);
-
-        Ok((self, compiler.build()?))
+        Ok(())
     }
 
     /// Internal setting for whether adapter modules for components will have
@@ -2665,6 +3123,7 @@ impl fmt::Debug for Config {
         }
 
         f.field("parallel_compilation", &self.parallel_compilation);
+        f.field("deterministic", &self.deterministic);
         #[cfg(any(feature = "cranelift", feature = "winch"))]
         {
             f.field("compiler_config", &self.compiler_config);
@@ -2699,6 +3158,20 @@ pub enum Strategy {
     /// A baseline compiler for WebAssembly, currently under active development and not ready for
     /// production applications.
     Winch,
+
+    /// Tiered compilation, where functions are compiled lazily with Winch
+    /// and then recompiled with Cranelift once they're called frequently.
+    ///
+    /// With this strategy [`Module::new`](crate::Module::new) only validates
+    /// the module and compiles trampolines, which significantly reduces the
+    /// time it takes to create large modules. Each function is instead
+    /// compiled with Winch when it's first called, and recompiled with
+    /// Cranelift once it's been called as many times as configured with
+    /// [`Config::tier_up_threshold`].
+    ///
+    /// This requires both the `cranelift` and `winch` features, supports the
+    /// same WebAssembly proposals as Winch, and doesn't support components.
+    Tiered,
 }
 
 #[cfg(any(feature = "winch", feature = "cranelift"))]
@@ -2730,6 +3203,7 @@ impl Strategy {
 /// |-----------------------------|----------------------|-------------|----------------|----------------------|----------------------|
 /// | `DeferredReferenceCounting` | Yes, but not cycles  | 🙂         | 🙁             | 😐                   | 😐                  |
 /// | `Null`                      | No                   | 🙂         | 🙂             | 🙂                   | 🙂                  |
+/// | `Copying`                   | Yes                  | 😐         | 🙂             | 🙂                   | 🙁                  |
 ///
 /// [^1]: Whether or not the collector is capable of collecting garbage and cyclic garbage.
 ///
@@ -2794,6 +3268,20 @@ pub enum Collector {
     /// collectors, as this collector imposes as close to zero throughput and
     /// latency overhead as possible.
     Null,
+
+    /// The semi-space copying collector.
+    ///
+    /// A tracing collector that splits the GC heap into two halves, bump
+    /// allocates out of one of them, and when that half is full, copies every
+    /// live object into the other half. Allocation is very fast, dead objects
+    /// cost nothing to reclaim, and cycles are collected. The costs are that
+    /// only half of the GC heap can hold objects at any given time, and that
+    /// each collection pauses the Wasm program for time proportional to the
+    /// amount of live data.
+    ///
+    /// This collector is a good fit for allocation-heavy programs that create
+    /// many short-lived objects.
+    Copying,
 }
 
 impl Default for Collector {
@@ -2811,6 +3299,8 @@ impl Collector {
                     Some(Collector::DeferredReferenceCounting)
                 } else if cfg!(feature = "gc-null") {
                     Some(Collector::Null)
+                } else if cfg!(feature = "gc-copying") {
+                    Some(Collector::Copying)
                 } else {
                     None
                 }
@@ -2838,12 +3328,20 @@ impl Collector {
                  the `gc-null` feature was not enabled at compile time",
             ),
 
+            #[cfg(feature = "gc-copying")]
+            Some(c @ Collector::Copying) => Ok(c),
+            #[cfg(not(feature = "gc-copying"))]
+            Some(Collector::Copying) => bail!(
+                "cannot create an engine using the copying collector because \
+                 the `gc-copying` feature was not enabled at compile time",
+            ),
+
             Some(Collector::Auto) => unreachable!(),
 
             None => bail!(
                 "cannot create an engine with GC support when none of the \
                  collectors are available; enable one of the following \
-                 features: `gc-drc`, `gc-null`",
+                 features: `gc-drc`, `gc-null`, `gc-copying`",
             ),
         }
     }
@@ -3087,6 +3585,35 @@ impl PoolingAllocationConfig {
         self
     }
 
+    /// Reserve the virtual address space of the pooling allocator's pools in
+    /// chunks of `slots` slots, on demand, rather than all up front.
+    ///
+    /// By default the pooling allocator reserves address space for every slot
+    /// of every pool when it is created, for example
+    /// [`PoolingAllocationConfig::total_memories`] times the size of each
+    /// linear memory slot. This is cheap in physical memory but can be a large
+    /// amount of virtual memory, which can be a problem in environments that
+    /// limit or account for address space, or when the `total_*` limits are
+    /// sized for peak load that is rarely reached.
+    ///
+    /// When this is set to a nonzero value the pooling allocator is "elastic":
+    /// the linear memory, table, and async stack pools each start out with
+    /// address space for only `slots` slots, and reserve another `slots` slots
+    /// whenever all of their reserved slots are in use. Reserved chunks are
+    /// never released until the engine is dropped. Unused slots in reserved
+    /// chunks are always reused before a new chunk is reserved, and the
+    /// `total_*` limits remain hard caps on the number of slots in each pool.
+    ///
+    /// When memory protection keys are in use the number of linear memory
+    /// slots per chunk is rounded up to a multiple of the number of stripes.
+    /// The GC heap pool is not affected by this setting.
+    ///
+    /// The default value for this option is `0`, which disables elastic pools.
+    pub fn elastic_chunk_slots(&mut self, slots: u32) -> &mut Self {
+        self.config.elastic_chunk_slots = slots;
+        self
+    }
+
     /// The target number of decommits to do per batch.
     ///
     /// This is not precise, as we can queue up decommits at times when we
@@ -3424,6 +3951,45 @@ impl PoolingAllocationConfig {
         self
     }
 
+    /// The number of slots in the memory pool dedicated to 64-bit linear
+    /// memories (default is `0`).
+    ///
+    /// By default 64-bit linear memories are allocated from the same slots as
+    /// 32-bit ones, so they are limited to
+    /// [`max_memory_size`](PoolingAllocationConfig::max_memory_size) which in
+    /// turn cannot exceed [`Config::memory_reservation`]. Slots for 32-bit
+    /// memories are sized to elide bounds checks, but 64-bit memories always
+    /// use explicit bounds checks, so they can instead be given their own,
+    /// possibly much larger, slots.
+    ///
+    /// When `count` is non-zero the pool is split: 64-bit memories are only
+    /// allocated from `count` slots of
+    /// [`max_memory64_size`](PoolingAllocationConfig::max_memory64_size) bytes
+    /// each, and 32-bit memories from the
+    /// [`total_memories`](PoolingAllocationConfig::total_memories) regular
+    /// slots. Both kinds of slots can be used by the same instance.
+    ///
+    /// Slots for 64-bit memories do not use memory protection keys.
+    pub fn total_memory64s(&mut self, count: u32) -> &mut Self {
+        self.config.limits.total_memory64s = count;
+        self
+    }
+
+    /// The maximum byte size that a 64-bit linear memory allocated from the
+    /// slots configured with
+    /// [`total_memory64s`](PoolingAllocationConfig::total_memory64s) may grow
+    /// to.
+    ///
+    /// This option defaults to 4 GiB. Unlike
+    /// [`max_memory_size`](PoolingAllocationConfig::max_memory_size) it may
+    /// exceed [`Config::memory_reservation`]. Each
+    /// slot reserves the larger of this size and the memory reservation, plus
+    /// [`Config::memory_guard_size`], of virtual address space.
+    pub fn max_memory64_size(&mut self, bytes: usize) -> &mut Self {
+        self.config.limits.max_memory64_size = bytes;
+        self
+    }
+
     /// Configures whether memory protection keys (MPK) should be used for more
     /// efficient layout of pool-allocated memories.
     ///
@@ -3463,6 +4029,42 @@ impl PoolingAllocationConfig {
         self
     }
 
+    /// Initialize linear memories lazily, on first access, using Linux's
+    /// `userfaultfd`.
+    ///
+    /// With [`Config::memory_init_cow`] a module's initial memory image is
+    /// `mmap`-ed into a linear memory slot on instantiation. When this option
+    /// is enabled the pooling allocator instead registers its linear memory
+    /// pool with a userfaultfd and leaves the pages of each slot missing. The
+    /// first access to a page faults, and a dedicated handler thread fills the
+    /// page in from the module's memory image, or with zeros outside of it.
+    /// Resetting a slot for reuse is then a single `madvise` of its dirty
+    /// pages, even when the slot is reused for a different module.
+    ///
+    /// This can be faster than copy-on-write initialization for instances
+    /// with large memory images of which only a small part is accessed, and
+    /// for workloads that frequently instantiate many different modules in the
+    /// same slots. It is slower for instances that touch most of their memory,
+    /// since every first access to a page round-trips through the handler
+    /// thread.
+    ///
+    /// Memory images are still only created when [`Config::memory_init_cow`]
+    /// is enabled and a module's data segments are amenable to it; otherwise
+    /// memory is initialized by copying as usual.
+    ///
+    /// This option is only supported on Linux, and requires the privilege to
+    /// handle page faults taken in the kernel with a userfaultfd (see the
+    /// `vm.unprivileged_userfaultfd` sysctl). Creating an [`Engine`] fails
+    /// otherwise.
+    ///
+    /// By default this option is disabled.
+    ///
+    /// [`Engine`]: crate::Engine
+    pub fn memory_init_uffd(&mut self, enable: bool) -> &mut Self {
+        self.config.memory_init_uffd = enable;
+        self
+    }
+
     /// Sets an upper limit on how many memory protection keys (MPK) Wasmtime
     /// will use.
     ///
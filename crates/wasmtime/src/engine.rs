@@ -52,6 +52,10 @@ struct EngineInner {
     tunables: Tunables,
     #[cfg(any(feature = "cranelift", feature = "winch"))]
     compiler: Box<dyn wasmtime_environ::Compiler>,
+    /// The compiler that functions are recompiled with once they're called
+    /// frequently, with tiered compilation.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    tier_up_compiler: Option<Box<dyn wasmtime_environ::Compiler>>,
     #[cfg(feature = "runtime")]
     allocator: Box<dyn crate::runtime::vm::InstanceAllocator + Send + Sync>,
     #[cfg(feature = "runtime")]
@@ -116,11 +120,15 @@ impl Engine {
 
         #[cfg(any(feature = "cranelift", feature = "winch"))]
         let (config, compiler) = config.build_compiler(&tunables, features)?;
+        #[cfg(any(feature = "cranelift", feature = "winch"))]
+        let tier_up_compiler = config.build_tier_up_compiler(&tunables)?;
 
         Ok(Engine {
             inner: Arc::new(EngineInner {
                 #[cfg(any(feature = "cranelift", feature = "winch"))]
                 compiler,
+                #[cfg(any(feature = "cranelift", feature = "winch"))]
+                tier_up_compiler,
                 #[cfg(feature = "runtime")]
                 allocator: {
                     let allocator = config.build_allocator(&tunables)?;
@@ -220,6 +228,13 @@ impl Engine {
         self.config().async_support
     }
 
+    /// Returns whether the engine is configured for deterministic execution
+    /// with [`Config::deterministic`].
+    #[inline]
+    pub fn is_deterministic(&self) -> bool {
+        self.config().deterministic
+    }
+
     /// Detects whether the bytes provided are a precompiled object produced by
     /// Wasmtime.
     ///
@@ -589,6 +604,12 @@ impl Engine {
         &*self.inner.compiler
     }
 
+    /// Returns the compiler that functions are recompiled with once they've
+    /// been called frequently, if tiered compilation is enabled.
+    pub(crate) fn tier_up_compiler(&self) -> Option<&dyn wasmtime_environ::Compiler> {
+        self.inner.tier_up_compiler.as_deref()
+    }
+
     /// Ahead-of-time (AOT) compiles a WebAssembly module.
     ///
     /// The `bytes` provided must be in one of two formats:
@@ -684,6 +705,20 @@ impl Engine {
         crate::runtime::vm::tls_eager_initialize();
     }
 
+    /// Take a snapshot of the pooling allocator's runtime statistics.
+    ///
+    /// This reports how many slots of each of the pooling allocator's pools
+    /// are in use, how many allocations from them have failed and why, and
+    /// similar metrics that are useful for tuning the limits in
+    /// [`PoolingAllocationConfig`](crate::PoolingAllocationConfig).
+    ///
+    /// Returns `None` if this engine is not configured to use the pooling
+    /// allocator.
+    #[cfg(feature = "pooling-allocator")]
+    pub fn pooling_allocation_stats(&self) -> Option<crate::PoolingAllocationStats> {
+        self.allocator().as_pooling().map(|pool| pool.stats())
+    }
+
     pub(crate) fn allocator(&self) -> &dyn crate::runtime::vm::InstanceAllocator {
         self.inner.allocator.as_ref()
     }
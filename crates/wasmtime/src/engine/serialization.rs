@@ -280,6 +280,9 @@ impl Metadata<'_> {
             generate_native_debuginfo,
             parse_wasm_debuginfo,
             consume_fuel,
+            fuel_profiling,
+            memory_access_tracing,
+            tiered_compilation,
             epoch_interruption,
             memory_may_move,
             guard_before_linear_memory,
@@ -288,8 +291,9 @@ impl Metadata<'_> {
             winch_callable,
             signals_based_traps,
             memory_init_cow,
-            // This doesn't affect compilation, it's just a runtime setting.
+            // These don't affect compilation, they're just runtime settings.
             memory_reservation_for_growth: _,
+            memory_huge_pages: _,
 
             // This does technically affect compilation but modules with/without
             // trap information can be loaded into engines with the opposite
@@ -323,6 +327,17 @@ impl Metadata<'_> {
             "WebAssembly backtrace support",
         )?;
         Self::check_bool(consume_fuel, other.consume_fuel, "fuel support")?;
+        Self::check_bool(fuel_profiling, other.fuel_profiling, "fuel profiling")?;
+        Self::check_bool(
+            memory_access_tracing,
+            other.memory_access_tracing,
+            "memory access tracing",
+        )?;
+        Self::check_bool(
+            tiered_compilation,
+            other.tiered_compilation,
+            "tiered compilation",
+        )?;
         Self::check_bool(
             epoch_interruption,
             other.epoch_interruption,
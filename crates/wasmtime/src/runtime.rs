@@ -82,8 +82,11 @@ pub use module::{Module, ModuleExport};
 pub use resources::*;
 #[cfg(all(feature = "async", feature = "call-hook"))]
 pub use store::CallHookHandler;
+#[cfg(feature = "record-replay")]
+pub use store::HostCallTrace;
 pub use store::{
-    AsContext, AsContextMut, CallHook, Store, StoreContext, StoreContextMut, UpdateDeadline,
+    AsContext, AsContextMut, CallHook, FuncFuel, MemoryAccess, MemoryAccessKind, Store,
+    StoreContext, StoreContextMut, StoreSnapshot, UpdateDeadline,
 };
 pub use trap::*;
 pub use types::*;
@@ -93,7 +96,9 @@ pub use values::*;
 pub(crate) use uninhabited::*;
 
 #[cfg(feature = "pooling-allocator")]
-pub use vm::PoolConcurrencyLimitError;
+pub use vm::{
+    PoolAllocationFailures, PoolConcurrencyLimitError, PoolSlotStats, PoolingAllocationStats,
+};
 
 #[cfg(feature = "profiling")]
 mod profiling;
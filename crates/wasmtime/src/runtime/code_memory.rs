@@ -37,6 +37,7 @@ pub struct CodeMemory {
     func_name_data: Range<usize>,
     info_data: Range<usize>,
     wasm_dwarf: Range<usize>,
+    wasm: Range<usize>,
 }
 
 impl Drop for CodeMemory {
@@ -125,6 +126,7 @@ impl CodeMemory {
         let mut func_name_data = 0..0;
         let mut info_data = 0..0;
         let mut wasm_dwarf = 0..0;
+        let mut wasm = 0..0;
         for section in obj.sections() {
             let data = section.data().map_err(obj::ObjectCrateErrorWrapper)?;
             let name = section.name().map_err(obj::ObjectCrateErrorWrapper)?;
@@ -171,6 +173,7 @@ impl CodeMemory {
                 obj::ELF_NAME_DATA => func_name_data = range,
                 obj::ELF_WASMTIME_INFO => info_data = range,
                 obj::ELF_WASMTIME_DWARF => wasm_dwarf = range,
+                obj::ELF_WASMTIME_WASM => wasm = range,
                 #[cfg(feature = "debug-builtins")]
                 ".debug_info" => has_native_debug_info = true,
 
@@ -202,6 +205,7 @@ impl CodeMemory {
             stack_map_data,
             func_name_data,
             wasm_dwarf,
+            wasm,
             info_data,
             wasm_data,
         })
@@ -226,6 +230,14 @@ impl CodeMemory {
         &self.mmap[self.wasm_dwarf.clone()]
     }
 
+    /// Returns the contents of the `ELF_WASMTIME_WASM` section, the original
+    /// wasm binary of a module compiled with tiered compilation, or an empty
+    /// slice if it wasn't found.
+    #[inline]
+    pub fn original_wasm(&self) -> &[u8] {
+        &self.mmap[self.wasm.clone()]
+    }
+
     /// Returns the data in the `ELF_NAME_DATA` section.
     #[inline]
     pub fn func_name_data(&self) -> &[u8] {
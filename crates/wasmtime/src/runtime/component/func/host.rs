@@ -7,6 +7,8 @@ use crate::runtime::vm::component::{
     ComponentInstance, InstanceFlags, VMComponentContext, VMLowering, VMLoweringCallee,
 };
 use crate::runtime::vm::{VMFuncRef, VMGlobalDefinition, VMMemoryDefinition, VMOpaqueContext};
+#[cfg(feature = "record-replay")]
+use crate::store::{HostCallFrame, StoreOpaque};
 use crate::{AsContextMut, CallHook, StoreContextMut, ValRaw};
 use alloc::sync::Arc;
 use core::any::Any;
@@ -184,6 +186,13 @@ where
     let param_tys = InterfaceType::Tuple(ty.params);
     let result_tys = InterfaceType::Tuple(ty.results);
 
+    #[cfg(feature = "record-replay")]
+    let memories = store.0.record_replay_component_checkpoint(memory);
+    #[cfg(feature = "record-replay")]
+    let flat_results = types[ty.results].abi.flat_count(MAX_FLAT_RESULTS);
+    #[cfg(feature = "record-replay")]
+    let raw_storage: *mut [MaybeUninit<ValRaw>] = storage;
+
     let mut storage = Storage::<'_, Params, Return>::new_sync(storage);
     let mut lift = LiftContext::new(store.0, &options, &types, instance);
     lift.enter_call();
@@ -197,6 +206,9 @@ where
     flags.set_may_leave(true);
     lower.exit_call()?;
 
+    #[cfg(feature = "record-replay")]
+    record_replay_results(lower.store.0, memories, &mut *raw_storage, flat_results)?;
+
     return Ok(());
 
     /// Type-level representation of the matrix of possibilities of how
@@ -397,7 +409,7 @@ where
             match self.lower_dst() {
                 Dst::Direct(storage) => ret.linear_lower_to_flat(cx, ty, storage),
                 Dst::Indirect(ptr) => {
-                    let ptr = validate_inbounds::<R>(cx.as_slice_mut(), ptr)?;
+                    let ptr = validate_inbounds::<R>(cx.as_slice(), ptr)?;
                     ret.linear_lower_to_memory(cx, ty, ptr)
                 }
             }
@@ -498,6 +510,9 @@ where
     let func_ty = &types[ty];
     let param_tys = &types[func_ty.params];
     let result_tys = &types[func_ty.results];
+
+    #[cfg(feature = "record-replay")]
+    let memories = store.0.record_replay_component_checkpoint(memory);
     let mut cx = LiftContext::new(store.0, &options, &types, instance);
     cx.enter_call();
     if let Some(param_count) = param_tys.abi.flat_count(MAX_FLAT_PARAMS) {
@@ -543,7 +558,7 @@ where
         assert!(dst.next().is_none());
     } else {
         let ret_ptr = storage[ret_index].assume_init_ref();
-        let mut ptr = validate_inbounds_dynamic(&result_tys.abi, cx.as_slice_mut(), ret_ptr)?;
+        let mut ptr = validate_inbounds_dynamic(&result_tys.abi, cx.as_slice(), ret_ptr)?;
         for (val, ty) in result_vals.iter().zip(result_tys.types.iter()) {
             let offset = types.canonical_abi(ty).next_field32_size(&mut ptr);
             val.store(&mut cx, *ty, offset)?;
@@ -554,9 +569,35 @@ where
 
     cx.exit_call()?;
 
+    #[cfg(feature = "record-replay")]
+    record_replay_results(
+        cx.store.0,
+        memories,
+        storage,
+        result_tys.abi.flat_count(MAX_FLAT_RESULTS),
+    )?;
+
     return Ok(());
 }
 
+/// Record the flat results of a component-model host call, or replace them
+/// with recorded ones, if the store has a host call trace.
+///
+/// The first `flat_results` values in `storage`, if any, must have been
+/// initialized by lowering the host function's results.
+#[cfg(feature = "record-replay")]
+unsafe fn record_replay_results(
+    store: &mut StoreOpaque,
+    frame: Option<HostCallFrame>,
+    storage: &mut [MaybeUninit<ValRaw>],
+    flat_results: Option<usize>,
+) -> Result<()> {
+    let results = &mut storage[..flat_results.unwrap_or(0)];
+    // NB: can use `MaybeUninit::slice_assume_init_mut` when that's stable
+    let results = mem::transmute::<&mut [MaybeUninit<ValRaw>], &mut [ValRaw]>(results);
+    store.record_replay_component_host_call(frame, results)
+}
+
 fn validate_inbounds_dynamic(abi: &CanonicalAbiInfo, memory: &[u8], ptr: &ValRaw) -> Result<usize> {
     // FIXME(#4311): needs memory64 support
     let ptr = usize::try_from(ptr.get_u32())?;
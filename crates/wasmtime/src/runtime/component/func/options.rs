@@ -232,9 +232,47 @@ impl<'a, T: 'static> LowerContext<'a, T> {
     /// This will panic if memory has not been configured for this lowering
     /// (e.g. it wasn't present during the specification of canonical options).
     pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        #[cfg(feature = "record-replay")]
+        self.record_replay_write(0..usize::MAX);
         self.options.memory_mut(self.store.0)
     }
 
+    /// Returns a view into memory as a slice of bytes.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if memory has not been configured for this lowering
+    /// (e.g. it wasn't present during the specification of canonical options).
+    pub fn as_slice(&self) -> &[u8] {
+        self.options.memory(self.store.0)
+    }
+
+    /// Returns a mutable slice of memory `len` bytes large starting at
+    /// `offset`, panicking on out-of-bounds.
+    ///
+    /// Prefer this over [`LowerContext::as_slice_mut`] when only part of
+    /// memory is written, since it lets host call traces record just that
+    /// part.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if memory has not been configured for this lowering
+    /// (e.g. it wasn't present during the specification of canonical options).
+    pub fn slice_mut(&mut self, offset: usize, len: usize) -> &mut [u8] {
+        #[cfg(feature = "record-replay")]
+        self.record_replay_write(offset..offset.saturating_add(len));
+        &mut self.options.memory_mut(self.store.0)[offset..][..len]
+    }
+
+    /// Note that `range` of memory is about to be written, for host call
+    /// traces.
+    #[cfg(feature = "record-replay")]
+    fn record_replay_write(&self, range: core::ops::Range<usize>) {
+        if let Some(memory) = self.options.memory {
+            self.store.0.record_replay_write(memory, range);
+        }
+    }
+
     /// Invokes the memory allocation function (which is style after `realloc`)
     /// with the specified parameters.
     ///
@@ -249,6 +287,9 @@ impl<'a, T: 'static> LowerContext<'a, T> {
         old_align: u32,
         new_size: usize,
     ) -> Result<usize> {
+        // The guest's allocator may write anywhere in its memory.
+        #[cfg(feature = "record-replay")]
+        self.record_replay_write(0..usize::MAX);
         let realloc_func_ty = Arc::clone(self.instance().component().realloc_func_ty());
         self.options
             .realloc(
@@ -283,7 +324,7 @@ impl<'a, T: 'static> LowerContext<'a, T> {
         // For now I figure we can leave in this bounds check and if it becomes
         // an issue we can optimize further later, probably with judicious use
         // of `unsafe`.
-        self.as_slice_mut()[offset..].first_chunk_mut().unwrap()
+        self.slice_mut(offset, N).first_chunk_mut().unwrap()
     }
 
     /// Lowers an `own` resource into the guest, converting the `rep` specified
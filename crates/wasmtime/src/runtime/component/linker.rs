@@ -442,7 +442,7 @@ impl<T: 'static> LinkerInstance<'_, T> {
             "cannot use `func_wrap_async` without enabling async support in the config"
         );
         let ff = move |store: StoreContextMut<'_, T>, params: Params| -> Result<Return> {
-            store.block_on(|store| f(store, params).into())?
+            store.block_on_host_call(|store| f(store, params).into())?
         };
         self.func_wrap(name, ff)
     }
@@ -602,7 +602,9 @@ impl<T: 'static> LinkerInstance<'_, T> {
             "cannot use `func_new_async` without enabling async support in the config"
         );
         let ff = move |store: StoreContextMut<'_, T>, params: &[Val], results: &mut [Val]| {
-            store.with_blocking(|store, cx| cx.block_on(Pin::from(f(store, params, results)))?)
+            store.with_blocking(|store, cx| {
+                cx.block_on_host_call(Pin::from(f(store, params, results)))
+            })?
         };
         return self.func_new(name, ff);
     }
@@ -673,7 +675,7 @@ impl<T: 'static> LinkerInstance<'_, T> {
             &self.engine,
             move |mut cx: crate::Caller<'_, T>, (param,): (u32,)| {
                 cx.as_context_mut()
-                    .block_on(|store| dtor(store, param).into())?
+                    .block_on_host_call(|store| dtor(store, param).into())?
             },
         ));
         self.insert(name, Definition::Resource(ty, dtor))?;
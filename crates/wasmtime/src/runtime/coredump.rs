@@ -1,8 +1,8 @@
 use crate::hash_map::HashMap;
 use crate::prelude::*;
 use crate::{
-    AsContextMut, FrameInfo, Global, HeapType, Instance, Memory, Module, StoreContextMut, Val,
-    ValType, WasmBacktrace, store::StoreOpaque,
+    AsContextMut, ExternType, FrameInfo, Func, Global, HeapType, Instance, Memory, Module,
+    Mutability, Ref, StoreContextMut, Table, Val, ValType, WasmBacktrace, store::StoreOpaque,
 };
 use std::fmt;
 
@@ -299,6 +299,392 @@ impl WasmCoreDump {
 
         core_dump.finish()
     }
+
+    /// Reconstruct the state recorded in a serialized core dump within
+    /// `store`, so that it may be inspected post-mortem.
+    ///
+    /// `core_dump` must have been produced by [`WasmCoreDump::serialize`], and
+    /// `modules` must contain the modules that were instantiated when the core
+    /// dump was created. Modules are matched up with those in the core dump by
+    /// name, so at most one of `modules` may be unnamed, in which case it is
+    /// used for all of the core dump's anonymous modules.
+    ///
+    /// Every instance recorded in the core dump is re-instantiated within
+    /// `store`, without running its start function, and the contents of its
+    /// memories and the values of its mutable globals are then overwritten
+    /// with those recorded in the core dump. The resulting instances are
+    /// returned in the same order as [`WasmCoreDump::instances`], and their
+    /// exports may be used to read memories and globals or to call exported
+    /// functions which only read state.
+    ///
+    /// Since the host is not available post-mortem, every imported function is
+    /// replaced with one that traps when called. Imported memories, globals,
+    /// and tables are recreated from the core dump, or shared with the
+    /// restored instance that defines them, which is instantiated first even
+    /// if it comes later in the core dump.
+    ///
+    /// Note that core dumps do not record the contents of tables or the values
+    /// of references, so tables and reference-typed globals are left in the
+    /// state that instantiation put them in.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `core_dump` is not a valid core dump, if `modules`
+    /// doesn't match the modules recorded in it, or if instantiation fails,
+    /// for example because a module imports a tag.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if called with a store associated with a
+    /// [`asynchronous config`](crate::Config::async_support), or if any of
+    /// `modules` belongs to a different engine than `store`.
+    pub fn restore(
+        mut store: impl AsContextMut,
+        core_dump: &[u8],
+        modules: &[Module],
+    ) -> Result<Vec<Instance>> {
+        let store = store.as_context_mut();
+        Self::_restore(store, core_dump, modules)
+    }
+
+    fn _restore<T: 'static>(
+        mut store: StoreContextMut<'_, T>,
+        core_dump: &[u8],
+        modules: &[Module],
+    ) -> Result<Vec<Instance>> {
+        let dump = ParsedCoreDump::parse(core_dump)?;
+
+        // Find the module in `modules` for the core dump's `index`th module.
+        let resolve_module = |index: u32| -> Result<&Module> {
+            let name = usize::try_from(index)
+                .ok()
+                .and_then(|i| dump.modules.get(i))
+                .context("invalid module index in core dump")?;
+            let anonymous = name.starts_with("<anonymous-module-");
+            let mut candidates = modules.iter().filter(|m| match m.name() {
+                Some(n) => n == *name,
+                None => anonymous,
+            });
+            let module = candidates
+                .next()
+                .with_context(|| format!("no module was provided for `{name}` in the core dump"))?;
+            ensure!(
+                candidates.next().is_none(),
+                "multiple modules were provided for `{name}` in the core dump"
+            );
+            Ok(module)
+        };
+
+        let modules = dump
+            .instances
+            .iter()
+            .map(|recorded| resolve_module(recorded.module_index))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Find the instance that defines each of the core dump's memories and
+        // globals, if any, so that instances that import them can be restored
+        // after it regardless of the order in the core dump.
+        let mut memory_definers = vec![None; dump.memories.len()];
+        let mut global_definers = vec![None; dump.globals.len()];
+        let mut dependencies = Vec::with_capacity(dump.instances.len());
+        for (i, (recorded, module)) in dump.instances.iter().zip(&modules).enumerate() {
+            let env_module = module.env_module();
+            let imported_memories = env_module.num_imported_memories;
+            let imported_globals = env_module.num_imported_globals;
+            ensure!(
+                imported_memories <= recorded.memories.len()
+                    && imported_globals <= recorded.globals.len(),
+                "core dump does not match the imports of module `{}`",
+                module.name().unwrap_or("<module>"),
+            );
+            for index in &recorded.memories[imported_memories..] {
+                let definer = &mut memory_definers[usize::try_from(*index).unwrap()];
+                ensure!(
+                    definer.is_none(),
+                    "memory {index} is defined twice in core dump"
+                );
+                *definer = Some(i);
+            }
+            for index in &recorded.globals[imported_globals..] {
+                let definer = &mut global_definers[usize::try_from(*index).unwrap()];
+                ensure!(
+                    definer.is_none(),
+                    "global {index} is defined twice in core dump"
+                );
+                *definer = Some(i);
+            }
+            dependencies.push((
+                &recorded.memories[..imported_memories],
+                &recorded.globals[..imported_globals],
+            ));
+        }
+        let dependencies = dependencies
+            .into_iter()
+            .map(|(memories, globals)| {
+                let memories = memories
+                    .iter()
+                    .filter_map(|index| memory_definers[usize::try_from(*index).unwrap()]);
+                let globals = globals
+                    .iter()
+                    .filter_map(|index| global_definers[usize::try_from(*index).unwrap()]);
+                memories.chain(globals).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut memories: Vec<Option<Memory>> = vec![None; dump.memories.len()];
+        let mut globals: Vec<Option<Global>> = vec![None; dump.globals.len()];
+        let mut instances: Vec<Option<Instance>> = vec![None; dump.instances.len()];
+
+        while instances.iter().any(|i| i.is_none()) {
+            let i = (0..instances.len())
+                .find(|i| {
+                    instances[*i].is_none()
+                        && dependencies[*i].iter().all(|j| instances[*j].is_some())
+                })
+                .context("core dump has a cycle of imports between instances")?;
+            let recorded = &dump.instances[i];
+            let module = modules[i];
+
+            let mut imports = Vec::new();
+            let mut num_memories = 0;
+            let mut num_globals = 0;
+            for import in module.imports() {
+                let import = match import.ty() {
+                    ExternType::Func(ty) => Func::new(&mut store, ty, |_, _, _| {
+                        bail!("cannot call imported functions of a restored core dump")
+                    })
+                    .into(),
+                    ExternType::Memory(ty) => {
+                        let index = usize::try_from(recorded.memories[num_memories]).unwrap();
+                        num_memories += 1;
+                        match memories[index] {
+                            Some(memory) => memory.into(),
+                            None => {
+                                let memory = Memory::new(&mut store, ty)?;
+                                memories[index] = Some(memory);
+                                memory.into()
+                            }
+                        }
+                    }
+                    ExternType::Global(ty) => {
+                        let index = usize::try_from(recorded.globals[num_globals]).unwrap();
+                        num_globals += 1;
+                        match globals[index] {
+                            Some(global) => global.into(),
+                            None => {
+                                let value = dump.globals[index]
+                                    .to_val(ty.content())
+                                    .context("type mismatch for global in core dump")?;
+                                let global = Global::new(&mut store, ty, value)?;
+                                globals[index] = Some(global);
+                                global.into()
+                            }
+                        }
+                    }
+                    ExternType::Table(ty) => {
+                        let init = Ref::null(ty.element().heap_type());
+                        Table::new(&mut store, ty, init)?.into()
+                    }
+                    ExternType::Tag(_) => {
+                        bail!("cannot restore a core dump of a module which imports tags")
+                    }
+                };
+                imports.push(import);
+            }
+
+            let instance = Instance::new_unstarted(&mut store, module, &imports)?;
+
+            let instance_memories = instance.all_memories(store.0).collect::<Vec<_>>();
+            ensure!(
+                instance_memories.len() == recorded.memories.len(),
+                "memories of module `{}` do not match the core dump",
+                module.name().unwrap_or("<module>"),
+            );
+            for ((_, memory), index) in instance_memories.into_iter().zip(&recorded.memories) {
+                memories[usize::try_from(*index).unwrap()] = Some(memory);
+            }
+
+            let instance_globals = instance.all_globals(store.0).collect::<Vec<_>>();
+            ensure!(
+                instance_globals.len() == recorded.globals.len(),
+                "globals of module `{}` do not match the core dump",
+                module.name().unwrap_or("<module>"),
+            );
+            for ((_, global), index) in instance_globals.into_iter().zip(&recorded.globals) {
+                globals[usize::try_from(*index).unwrap()] = Some(global);
+            }
+
+            instances[i] = Some(instance);
+        }
+        let instances = instances.into_iter().map(Option::unwrap).collect();
+
+        for (i, memory) in memories.iter().enumerate() {
+            let Some(memory) = memory else { continue };
+            let size = dump.memories[i];
+            let current = memory.size(&store);
+            if size > current {
+                memory.grow(&mut store, size - current)?;
+            }
+            let data = memory.data_mut(&mut store);
+            data.fill(0);
+            for (_, offset, bytes) in dump.data.iter().filter(|(m, _, _)| *m == i) {
+                let dst = usize::try_from(*offset)
+                    .ok()
+                    .and_then(|start| data.get_mut(start..start.checked_add(bytes.len())?))
+                    .context("invalid data segment in core dump")?;
+                dst.copy_from_slice(bytes);
+            }
+        }
+
+        for (i, global) in globals.iter().enumerate() {
+            let Some(global) = global else { continue };
+            let ty = global.ty(&store);
+            if ty.mutability() != Mutability::Var || ty.content().is_ref() {
+                continue;
+            }
+            let value = dump.globals[i]
+                .to_val(ty.content())
+                .context("type mismatch for global in core dump")?;
+            global.set(&mut store, value)?;
+        }
+
+        Ok(instances)
+    }
+}
+
+/// The parts of a serialized core dump that are needed to restore it.
+struct ParsedCoreDump<'a> {
+    /// The names of the modules in the core dump.
+    modules: Vec<&'a str>,
+    /// The instances in the core dump.
+    instances: Vec<wasmparser::CoreDumpInstance>,
+    /// The size of each memory, in pages.
+    memories: Vec<u64>,
+    /// The value of each global.
+    globals: Vec<CoreDumpValue>,
+    /// The contents of memories, as `(memory, offset, bytes)`.
+    data: Vec<(usize, u64, &'a [u8])>,
+}
+
+/// The value of a global recorded in a core dump.
+#[derive(Clone, Copy)]
+enum CoreDumpValue {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+    V128(u128),
+    Null,
+}
+
+impl CoreDumpValue {
+    fn parse(expr: &wasmparser::ConstExpr<'_>) -> Result<CoreDumpValue> {
+        use wasmparser::Operator;
+        let mut ops = expr.get_operators_reader();
+        Ok(match ops.read()? {
+            Operator::I32Const { value } => CoreDumpValue::I32(value),
+            Operator::I64Const { value } => CoreDumpValue::I64(value),
+            Operator::F32Const { value } => CoreDumpValue::F32(value.bits()),
+            Operator::F64Const { value } => CoreDumpValue::F64(value.bits()),
+            Operator::V128Const { value } => CoreDumpValue::V128(value.i128() as u128),
+            Operator::RefNull { .. } => CoreDumpValue::Null,
+            _ => bail!("unsupported global initializer in core dump"),
+        })
+    }
+
+    /// Convert this value to a `Val` of type `ty`, if it has that type.
+    fn to_val(self, ty: &ValType) -> Option<Val> {
+        match (self, ty) {
+            (CoreDumpValue::I32(x), ValType::I32) => Some(Val::I32(x)),
+            (CoreDumpValue::I64(x), ValType::I64) => Some(Val::I64(x)),
+            (CoreDumpValue::F32(x), ValType::F32) => Some(Val::F32(x)),
+            (CoreDumpValue::F64(x), ValType::F64) => Some(Val::F64(x)),
+            (CoreDumpValue::V128(x), ValType::V128) => Some(Val::V128(x.into())),
+            (CoreDumpValue::Null, ValType::Ref(_)) => Val::default_for_ty(ty),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> ParsedCoreDump<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<ParsedCoreDump<'a>> {
+        use wasmparser::{DataKind, KnownCustom, Operator, Parser, Payload};
+
+        let mut is_core_dump = false;
+        let mut dump = ParsedCoreDump {
+            modules: Vec::new(),
+            instances: Vec::new(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            data: Vec::new(),
+        };
+
+        for payload in Parser::new(0).parse_all(bytes) {
+            match payload? {
+                Payload::CustomSection(section) => match section.as_known() {
+                    KnownCustom::CoreDump(_) => is_core_dump = true,
+                    KnownCustom::CoreDumpModules(modules) => dump.modules = modules.modules,
+                    KnownCustom::CoreDumpInstances(instances) => {
+                        dump.instances = instances.instances
+                    }
+                    _ => {}
+                },
+                Payload::MemorySection(memories) => {
+                    for memory in memories {
+                        dump.memories.push(memory?.initial);
+                    }
+                }
+                Payload::GlobalSection(globals) => {
+                    for global in globals {
+                        dump.globals.push(CoreDumpValue::parse(&global?.init_expr)?);
+                    }
+                }
+                Payload::DataSection(data) => {
+                    for segment in data {
+                        let segment = segment?;
+                        let DataKind::Active {
+                            memory_index,
+                            offset_expr,
+                        } = segment.kind
+                        else {
+                            bail!("unexpected passive data segment in core dump");
+                        };
+                        let offset = match offset_expr.get_operators_reader().read()? {
+                            Operator::I32Const { value } => u64::from(value as u32),
+                            Operator::I64Const { value } => value as u64,
+                            _ => bail!("unsupported data segment offset in core dump"),
+                        };
+                        let memory = usize::try_from(memory_index).unwrap();
+                        dump.data.push((memory, offset, segment.data));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        ensure!(is_core_dump, "not a wasm core dump");
+        for (memory, _, _) in dump.data.iter() {
+            ensure!(
+                *memory < dump.memories.len(),
+                "invalid memory index in core dump"
+            );
+        }
+        for instance in dump.instances.iter() {
+            for index in instance.memories.iter() {
+                ensure!(
+                    usize::try_from(*index).unwrap() < dump.memories.len(),
+                    "invalid memory index in core dump"
+                );
+            }
+            for index in instance.globals.iter() {
+                ensure!(
+                    usize::try_from(*index).unwrap() < dump.globals.len(),
+                    "invalid global index in core dump"
+                );
+            }
+        }
+        Ok(dump)
+    }
 }
 
 impl fmt::Display for WasmCoreDump {
@@ -135,7 +135,7 @@ impl Table {
         self._ty(store.as_context().0)
     }
 
-    fn _ty(&self, store: &StoreOpaque) -> TableType {
+    pub(crate) fn _ty(&self, store: &StoreOpaque) -> TableType {
         TableType::from_wasmtime_table(store.engine(), self.wasmtime_ty(store))
     }
 
@@ -162,6 +162,11 @@ pub(crate) struct BlockingContext<'a, 'b> {
     /// Cancellation is a case where it isn't passed back and a re-poll is a
     /// case where it's passed back.
     future_cx: Option<&'a mut Context<'b>>,
+
+    /// The store's host call trace, if any, which records the suspensions of
+    /// async host functions.
+    #[cfg(feature = "record-replay")]
+    trace: Option<crate::HostCallTrace>,
 }
 
 impl<'a, 'b> BlockingContext<'a, 'b> {
@@ -197,6 +202,8 @@ impl<'a, 'b> BlockingContext<'a, 'b> {
     {
         let opaque = store.as_store_opaque();
 
+        #[cfg(feature = "record-replay")]
+        let trace = opaque.record_replay_trace();
         let state = opaque.fiber_async_state_mut();
 
         // SAFETY: this is taking pointers from `AsyncState` and then unsafely
@@ -218,7 +225,12 @@ impl<'a, 'b> BlockingContext<'a, 'b> {
 
         let mut reset = ResetBlockingContext {
             store,
-            cx: BlockingContext { future_cx, suspend },
+            cx: BlockingContext {
+                future_cx,
+                suspend,
+                #[cfg(feature = "record-replay")]
+                trace,
+            },
         };
         return f(&mut reset.store, &mut reset.cx);
 
@@ -306,6 +318,37 @@ impl<'a, 'b> BlockingContext<'a, 'b> {
         }
     }
 
+    /// Same as [`BlockingContext::block_on`], but for the future of an async
+    /// host function.
+    ///
+    /// If the store has a host call trace, the points at which the future
+    /// suspends are recorded, or when replaying, the future is suspended at
+    /// least as many times as it was when recording.
+    pub(crate) fn block_on_host_call<F>(&mut self, future: F) -> Result<F::Output>
+    where
+        F: Future + Send,
+    {
+        #[cfg(feature = "record-replay")]
+        if let Some(trace) = self.trace.clone() {
+            let mut future = core::pin::pin!(future);
+            loop {
+                match future.as_mut().poll(self.future_cx.as_mut().unwrap()) {
+                    Poll::Ready(v) => {
+                        for _ in 0..trace.remaining_suspensions() {
+                            self.suspend(StoreFiberYield::KeepStore)?;
+                        }
+                        return Ok(v);
+                    }
+                    Poll::Pending => {
+                        trace.suspended();
+                        self.suspend(StoreFiberYield::KeepStore)?;
+                    }
+                }
+            }
+        }
+        self.block_on(future)
+    }
+
     /// Suspend this fiber with `yield_` as the reason.
     ///
     /// This function will suspend the current fiber and only return after the
@@ -334,17 +377,18 @@ impl<'a, 'b> BlockingContext<'a, 'b> {
 }
 
 impl<T> StoreContextMut<'_, T> {
-    /// Blocks on the future computed by `f`.
+    /// Blocks on the future of an async host function computed by `f`, see
+    /// [`BlockingContext::block_on_host_call`].
     ///
     /// # Panics
     ///
     /// Panics if this is invoked outside the context of a fiber.
-    pub(crate) fn block_on<R>(
+    pub(crate) fn block_on_host_call<R>(
         self,
         f: impl FnOnce(StoreContextMut<'_, T>) -> Pin<Box<dyn Future<Output = R> + Send + '_>>,
     ) -> Result<R> {
         BlockingContext::with(self.0, |store, cx| {
-            cx.block_on(f(StoreContextMut(store)).as_mut())
+            cx.block_on_host_call(f(StoreContextMut(store)).as_mut())
         })
     }
 
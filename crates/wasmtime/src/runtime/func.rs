@@ -518,7 +518,7 @@ impl Func {
             ty,
             move |Caller { store, caller }, params, results| {
                 store.with_blocking(|store, cx| {
-                    cx.block_on(core::pin::Pin::from(func(
+                    cx.block_on_host_call(core::pin::Pin::from(func(
                         Caller { store, caller },
                         params,
                         results,
@@ -840,7 +840,7 @@ impl Func {
             concat!("cannot use `wrap_async` without enabling async support on the config")
         );
         Func::wrap_inner(store, move |Caller { store, caller }, args| {
-            match store.block_on(|store| func(Caller { store, caller }, args).into()) {
+            match store.block_on_host_call(|store| func(Caller { store, caller }, args).into()) {
                 Ok(ret) => ret.into_fallible(),
                 Err(e) => R::fallible_from_error(e),
             }
@@ -2331,6 +2331,9 @@ impl HostContext {
             let state = &*(state as *const _ as *const HostFuncState<F>);
             let func = &state.func;
 
+            #[cfg(feature = "record-replay")]
+            let memories = caller.store.0.record_replay_checkpoint(caller.caller.id());
+
             let ret = 'ret: {
                 if let Err(trap) = caller.store.0.call_hook(CallHook::CallingHost) {
                     break 'ret R::fallible_from_error(trap);
@@ -2361,6 +2364,25 @@ impl HostContext {
                     unsafe { AutoAssertNoGc::disabled(caller.store.0) }
                 };
                 let ret = ret.store(&mut store, args.as_mut())?;
+                drop(store);
+
+                #[cfg(feature = "record-replay")]
+                if let Some(memories) = memories {
+                    let results = state.ty.unwrap_func().returns();
+                    // Safety: `ret.store` above initialized the results, and
+                    // `args` is large enough for both params and results.
+                    let values = unsafe {
+                        core::slice::from_raw_parts_mut(
+                            args.as_mut().as_mut_ptr().cast::<ValRaw>(),
+                            results.len(),
+                        )
+                    };
+                    caller
+                        .store
+                        .0
+                        .record_replay_host_call(Some(memories), results, values)?;
+                }
+
                 Ok(ret)
             }
         };
@@ -2434,10 +2456,20 @@ impl HostFunc {
         T: 'static,
     {
         assert!(ty.comes_from_same_engine(engine));
+        #[cfg(feature = "record-replay")]
+        let results = ty.clone().into_registered_type();
         let func = move |caller_vmctx, values: &mut [ValRaw]| {
             Caller::<T>::with(caller_vmctx, |mut caller| {
                 caller.store.0.call_hook(CallHook::CallingHost)?;
+                #[cfg(feature = "record-replay")]
+                let memories = caller.store.0.record_replay_checkpoint(caller.caller.id());
                 let result = func(caller.sub_caller(), values)?;
+                #[cfg(feature = "record-replay")]
+                caller.store.0.record_replay_host_call(
+                    memories,
+                    results.unwrap_func().returns(),
+                    values,
+                )?;
                 caller.store.0.call_hook(CallHook::ReturningFromHost)?;
                 Ok(result)
             })
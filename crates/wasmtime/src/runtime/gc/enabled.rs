@@ -5,6 +5,7 @@ mod anyref;
 mod arrayref;
 mod eqref;
 mod externref;
+mod heap_graph;
 mod i31;
 mod rooting;
 mod structref;
@@ -13,6 +14,7 @@ pub use anyref::*;
 pub use arrayref::*;
 pub use eqref::*;
 pub use externref::*;
+pub use heap_graph::*;
 pub use i31::*;
 pub use rooting::*;
 pub use structref::*;
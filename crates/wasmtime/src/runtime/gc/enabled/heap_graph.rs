@@ -0,0 +1,389 @@
+//! Inspecting the graph of live objects in a GC heap.
+
+use crate::hash_map::HashMap;
+use crate::prelude::*;
+use crate::runtime::vm::{GcStore, VMGcRef};
+use crate::{ArrayType, Engine, StructType};
+use core::fmt::{self, Write};
+use wasmtime_environ::{GcLayout, VMGcKind, VMSharedTypeIndex};
+
+/// A graph of every live object in a store's GC heap, and the references
+/// between them.
+///
+/// This is a point-in-time view of the GC heap that is useful for debugging
+/// memory leaks in WebAssembly guests that use GC types: it can be inspected
+/// directly, via [`GcHeapGraph::objects`], or exported to the Chrome
+/// `.heapsnapshot` format with [`GcHeapGraph::to_chrome_heap_snapshot`] and
+/// then loaded into Chrome DevTools' memory panel (or any other tool that
+/// understands that format).
+///
+/// An object is live if it is reachable from one of the store's GC roots:
+/// globals, tables, WebAssembly stack frames, and host-held
+/// [`Rooted`](crate::Rooted) and [`ManuallyRooted`](crate::ManuallyRooted)
+/// references. Garbage that has not been collected yet is not included.
+///
+/// Created with [`Store::gc_heap_graph`](crate::Store::gc_heap_graph).
+#[derive(Debug, Default)]
+pub struct GcHeapGraph {
+    objects: Vec<GcHeapObject>,
+    roots: Vec<usize>,
+}
+
+/// A live object in a [`GcHeapGraph`].
+#[derive(Debug)]
+pub struct GcHeapObject {
+    id: u32,
+    kind: GcHeapObjectKind,
+    size: usize,
+    references: Vec<usize>,
+    /// The index of the field or element holding each of `references`.
+    reference_slots: Vec<u32>,
+}
+
+/// What kind of object a [`GcHeapObject`] is.
+#[derive(Clone, Debug)]
+pub enum GcHeapObjectKind {
+    /// An instance of the given struct type.
+    Struct(StructType),
+    /// An instance of the given array type.
+    Array(ArrayType),
+    /// An `externref` wrapping host data.
+    Extern,
+}
+
+impl GcHeapGraph {
+    /// Walk the GC heap from the given roots, recording every object reachable
+    /// from them.
+    pub(crate) fn new(engine: &Engine, gc_store: &mut GcStore, roots: Vec<VMGcRef>) -> Self {
+        let mut builder = GraphBuilder {
+            engine,
+            graph: GcHeapGraph::default(),
+            indices: HashMap::default(),
+            kinds: HashMap::default(),
+            worklist: Vec::new(),
+        };
+
+        for root in roots {
+            debug_assert!(!root.is_i31());
+            let index = builder.object_index(gc_store, root);
+            if !builder.graph.roots.contains(&index) {
+                builder.graph.roots.push(index);
+            }
+        }
+
+        while let Some((index, gc_ref)) = builder.worklist.pop() {
+            let (slots, references) = builder.trace(gc_store, &gc_ref);
+            let object = &mut builder.graph.objects[index];
+            object.references = references;
+            object.reference_slots = slots;
+        }
+
+        builder.graph
+    }
+
+    /// Get every live object in the GC heap.
+    ///
+    /// The indices returned by [`GcHeapGraph::roots`] and
+    /// [`GcHeapObject::references`] index into this slice.
+    pub fn objects(&self) -> &[GcHeapObject] {
+        &self.objects
+    }
+
+    /// Get the indices of the objects that are directly referenced by a GC
+    /// root.
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    /// Get the total size, in bytes, of all the live objects in the GC heap.
+    pub fn total_size(&self) -> usize {
+        self.objects.iter().map(|o| o.size).sum()
+    }
+
+    /// Export this graph in the Chrome `.heapsnapshot` JSON format.
+    ///
+    /// The result can be saved to a file with the `.heapsnapshot` extension
+    /// and loaded in the "Memory" panel of Chrome DevTools.
+    ///
+    /// Objects are named after their kind and type, e.g. `struct #12`, where
+    /// the number identifies the object's type within the engine. Edges to
+    /// struct fields are named after the field's index and edges to array
+    /// elements are element edges. A synthetic `(GC roots)` node references
+    /// every root.
+    pub fn to_chrome_heap_snapshot(&self) -> String {
+        let mut out = String::new();
+        self.write_chrome_heap_snapshot(&mut out)
+            .expect("writing to a string is infallible");
+        out
+    }
+
+    fn write_chrome_heap_snapshot(&self, out: &mut String) -> fmt::Result {
+        // The number of fields per node and the indices of node and edge
+        // types, which must match the `meta` section below.
+        const NODE_FIELD_COUNT: usize = 6;
+        const NODE_TYPE_OBJECT: u32 = 3;
+        const NODE_TYPE_NATIVE: u32 = 8;
+        const NODE_TYPE_SYNTHETIC: u32 = 9;
+        const EDGE_TYPE_ELEMENT: u32 = 1;
+        const EDGE_TYPE_PROPERTY: u32 = 2;
+
+        let mut strings = StringTable::default();
+        let edge_count = self.roots.len()
+            + self
+                .objects
+                .iter()
+                .map(|o| o.references.len())
+                .sum::<usize>();
+
+        out.push_str(
+            "{\"snapshot\":{\"meta\":{\
+             \"node_fields\":[\"type\",\"name\",\"id\",\"self_size\",\"edge_count\",\"trace_node_id\"],\
+             \"node_types\":[[\"hidden\",\"array\",\"string\",\"object\",\"code\",\"closure\",\
+             \"regexp\",\"number\",\"native\",\"synthetic\",\"concatenated string\",\
+             \"sliced string\",\"symbol\",\"bigint\"],\
+             \"string\",\"number\",\"number\",\"number\",\"number\"],\
+             \"edge_fields\":[\"type\",\"name_or_index\",\"to_node\"],\
+             \"edge_types\":[[\"context\",\"element\",\"property\",\"internal\",\"hidden\",\
+             \"shortcut\",\"weak\"],\"string_or_number\",\"node\"],\
+             \"trace_function_info_fields\":[],\"trace_node_fields\":[],\
+             \"sample_fields\":[],\"location_fields\":[]},",
+        );
+        write!(
+            out,
+            "\"node_count\":{},\"edge_count\":{edge_count},\"trace_function_count\":0}},",
+            self.objects.len() + 1
+        )?;
+
+        // The synthetic root node always comes first, and then every object,
+        // offset by one. Node IDs must be unique, so use odd numbers, which
+        // can't collide with (aligned) object IDs, for the root.
+        out.push_str("\"nodes\":[");
+        let name = strings.intern("(GC roots)");
+        write!(
+            out,
+            "{NODE_TYPE_SYNTHETIC},{name},1,0,{},0",
+            self.roots.len()
+        )?;
+        for object in &self.objects {
+            let (ty, name) = match &object.kind {
+                GcHeapObjectKind::Struct(ty) => (
+                    NODE_TYPE_OBJECT,
+                    format!("struct #{}", ty.type_index().bits()),
+                ),
+                GcHeapObjectKind::Array(ty) => (
+                    NODE_TYPE_OBJECT,
+                    format!("array #{}", ty.type_index().bits()),
+                ),
+                GcHeapObjectKind::Extern => (NODE_TYPE_NATIVE, "externref".to_string()),
+            };
+            let name = strings.intern(&name);
+            write!(
+                out,
+                ",{ty},{name},{},{},{},0",
+                object.id,
+                object.size,
+                object.references.len()
+            )?;
+        }
+
+        out.push_str("],\"edges\":[");
+        let mut first = true;
+        let mut edge = |out: &mut String, ty: u32, name_or_index: usize, to: usize| {
+            let sep = if first { "" } else { "," };
+            first = false;
+            let to_node = (to + 1) * NODE_FIELD_COUNT;
+            write!(out, "{sep}{ty},{name_or_index},{to_node}")
+        };
+        for (i, root) in self.roots.iter().enumerate() {
+            edge(out, EDGE_TYPE_ELEMENT, i, *root)?;
+        }
+        for object in &self.objects {
+            for (slot, to) in object.reference_slots.iter().zip(&object.references) {
+                match object.kind {
+                    GcHeapObjectKind::Struct(_) => {
+                        let name = strings.intern(&slot.to_string());
+                        edge(out, EDGE_TYPE_PROPERTY, name, *to)?;
+                    }
+                    GcHeapObjectKind::Array(_) | GcHeapObjectKind::Extern => {
+                        edge(out, EDGE_TYPE_ELEMENT, *slot as usize, *to)?;
+                    }
+                }
+            }
+        }
+
+        out.push_str(
+            "],\"trace_function_infos\":[],\"trace_tree\":[],\"samples\":[],\
+             \"locations\":[],\"strings\":[",
+        );
+        for (i, s) in strings.strings.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write_json_string(out, s)?;
+        }
+        out.push_str("]}");
+        Ok(())
+    }
+}
+
+impl GcHeapObject {
+    /// An identifier for this object, unique within its [`GcHeapGraph`].
+    ///
+    /// This is derived from the object's location in the GC heap, so it is
+    /// only stable across graphs taken from the same store when no collection
+    /// moved the object in between.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// What kind of object this is, including its type.
+    pub fn kind(&self) -> &GcHeapObjectKind {
+        &self.kind
+    }
+
+    /// The size of this object in the GC heap, in bytes, including its header.
+    ///
+    /// This does not include any host data that an `externref` wraps.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The indices, within [`GcHeapGraph::objects`], of the objects that this
+    /// object references.
+    ///
+    /// For structs, there is one entry per non-null reference-typed field, in
+    /// field order. For arrays, there is one entry per non-null element, in
+    /// element order. Identical references are repeated, and `i31ref`s are
+    /// not included since they are not heap objects.
+    pub fn references(&self) -> &[usize] {
+        &self.references
+    }
+}
+
+struct GraphBuilder<'a> {
+    engine: &'a Engine,
+    graph: GcHeapGraph,
+    indices: HashMap<u32, usize>,
+    kinds: HashMap<VMSharedTypeIndex, GcHeapObjectKind>,
+    worklist: Vec<(usize, VMGcRef)>,
+}
+
+impl GraphBuilder<'_> {
+    /// Get the index of the given object in the graph, adding it to the graph
+    /// (and the worklist of objects whose references need tracing) if this is
+    /// the first time we've seen it.
+    fn object_index(&mut self, gc_store: &mut GcStore, gc_ref: VMGcRef) -> usize {
+        let id = gc_ref.as_raw_u32();
+        if let Some(index) = self.indices.get(&id) {
+            return *index;
+        }
+
+        let header = gc_store.header(&gc_ref);
+        let kind = match header.ty() {
+            Some(ty) => {
+                let engine = self.engine;
+                self.kinds
+                    .entry(ty)
+                    .or_insert_with(|| {
+                        if header.kind().matches(VMGcKind::StructRef) {
+                            GcHeapObjectKind::Struct(StructType::from_shared_type_index(engine, ty))
+                        } else {
+                            debug_assert!(header.kind().matches(VMGcKind::ArrayRef));
+                            GcHeapObjectKind::Array(ArrayType::from_shared_type_index(engine, ty))
+                        }
+                    })
+                    .clone()
+            }
+            None => {
+                debug_assert!(header.kind().matches(VMGcKind::ExternRef));
+                GcHeapObjectKind::Extern
+            }
+        };
+        let size = gc_store.gc_heap.object_size(&gc_ref);
+
+        let index = self.graph.objects.len();
+        self.graph.objects.push(GcHeapObject {
+            id,
+            kind,
+            size,
+            references: Vec::new(),
+            reference_slots: Vec::new(),
+        });
+        self.indices.insert(id, index);
+        self.worklist.push((index, gc_ref));
+        index
+    }
+
+    /// Get the indices of every object that the given object references,
+    /// along with the index of the field or element that holds each
+    /// reference.
+    fn trace(&mut self, gc_store: &mut GcStore, gc_ref: &VMGcRef) -> (Vec<u32>, Vec<usize>) {
+        let Some(ty) = gc_store.header(gc_ref).ty() else {
+            return (Vec::new(), Vec::new());
+        };
+        let slots = match self.engine.signatures().layout(ty) {
+            Some(GcLayout::Struct(l)) => l
+                .fields
+                .iter()
+                .zip(0..)
+                .filter(|(f, _)| f.is_gc_ref)
+                .map(|(f, i)| (i, f.offset))
+                .collect::<Vec<_>>(),
+            Some(GcLayout::Array(l)) if l.elems_are_gc_refs => {
+                let len = gc_store.array_len(gc_ref.as_arrayref_unchecked());
+                (0..len).map(|i| (i, l.elem_offset(i))).collect()
+            }
+            Some(GcLayout::Array(_)) => Vec::new(),
+            None => unreachable!("should have a GC layout for {ty:?}"),
+        };
+
+        let mut indices = Vec::new();
+        let mut references = Vec::new();
+        for (slot, offset) in slots {
+            let raw = gc_store.gc_object_data(gc_ref).read_u32(offset);
+            let Some(referent) = VMGcRef::from_raw_u32(raw) else {
+                continue;
+            };
+            if referent.is_i31() {
+                continue;
+            }
+            indices.push(slot);
+            references.push(self.object_index(gc_store, referent));
+        }
+        (indices, references)
+    }
+}
+
+/// The deduplicated strings of a Chrome heap snapshot.
+#[derive(Default)]
+struct StringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl StringTable {
+    fn intern(&mut self, s: &str) -> usize {
+        if let Some(i) = self.indices.get(s) {
+            return *i;
+        }
+        let i = self.strings.len();
+        self.strings.push(s.to_string());
+        self.indices.insert(s.to_string(), i);
+        i
+    }
+}
+
+/// Write `s` as a quoted and escaped JSON string.
+fn write_json_string(out: &mut String, s: &str) -> fmt::Result {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if u32::from(c) < 0x20 => write!(out, "\\u{:04x}", u32::from(c))?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
@@ -18,6 +18,8 @@ use wasmtime_environ::{
     TypeTrace,
 };
 
+mod hot_swap;
+
 /// An instantiated WebAssembly module.
 ///
 /// This type represents the instantiation of a [`Module`]. Once instantiated
@@ -185,6 +187,27 @@ impl Instance {
         Ok(owned_imports)
     }
 
+    /// Create an instance of `module` without running its `start` function.
+    ///
+    /// This is used to rebuild the instances recorded in a core dump, whose
+    /// start functions already ran before the core dump was taken.
+    #[cfg(feature = "coredump")]
+    pub(crate) fn new_unstarted(
+        mut store: impl AsContextMut,
+        module: &Module,
+        imports: &[Extern],
+    ) -> Result<Instance> {
+        let store = store.as_context_mut();
+        assert!(
+            !store.0.async_support(),
+            "cannot instantiate without a fiber when async support is enabled",
+        );
+        let imports = Instance::typecheck_externs(store.0, module, imports)?;
+        // See `new` for notes on this unsafety
+        let (instance, _start) = unsafe { Instance::new_raw(store.0, module, imports.as_ref())? };
+        Ok(instance)
+    }
+
     /// Internal function to create an instance and run the start function.
     ///
     /// This function's unsafety is the same as `Instance::new_raw`.
@@ -536,7 +559,7 @@ impl Instance {
         self.get_export(store, name)?.into_tag()
     }
 
-    #[cfg(feature = "component-model")]
+    #[cfg(any(feature = "component-model", feature = "record-replay"))]
     pub(crate) fn id(&self) -> InstanceId {
         self.id.instance()
     }
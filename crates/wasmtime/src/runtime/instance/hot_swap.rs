@@ -0,0 +1,446 @@
+//! Replacing the code of a live instance with a newer version of its module.
+
+use super::*;
+#[cfg(feature = "gc")]
+use crate::RootScope;
+use crate::hash_map::HashMap;
+use crate::{HeapType, Ref, Val};
+use wasmtime_environ::{DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex, EntityRef};
+
+impl<T: 'static> InstancePre<T> {
+    /// Instantiates this module as the replacement for `old`, an instance of
+    /// an earlier version of the module within `store`, taking over `old`'s
+    /// state.
+    ///
+    /// This is used to ship a new version of a module's code without losing
+    /// the state of an instance that's already running. A new instance is
+    /// created, without running the module's start function, and then:
+    ///
+    /// * Every memory and table that `old` defines is moved into the new
+    ///   instance in place of the one the new module defines, without being
+    ///   copied. None of the new module's active data or element segments
+    ///   are visible in them. If the new module's minimum size for a memory or
+    ///   table is larger than `old`'s, then `old`'s is grown to that size
+    ///   first.
+    /// * The values of mutable globals are copied over. Immutable globals keep
+    ///   the value that the new module initializes them to.
+    ///
+    /// References to `old`'s functions within tables and globals are replaced
+    /// with references to the new instance's function with the same index.
+    ///
+    /// Memories, tables, and globals that `old` imported aren't moved; the
+    /// new instance uses whatever this [`InstancePre`] was created with for
+    /// its imports, which will usually be the same items that `old` imported.
+    ///
+    /// The host should use the returned instance, and its exports, in place
+    /// of `old` from then on. The memories and tables that were created for
+    /// the new instance are deallocated, and `old` is left in `store` with its
+    /// own globals and with empty memories and tables of its module's minimum
+    /// sizes. These are allocated on demand rather than by the engine's
+    /// instance allocator, so repeatedly swapping doesn't use up the slots of
+    /// a pooling allocator, but like every instance `old` isn't deallocated
+    /// until `store` is. Other instances which imported `old`'s memories or
+    /// tables keep referring to those empty ones, and so don't see the state
+    /// that was moved; modules whose memories or tables are imported elsewhere
+    /// shouldn't be hot swapped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the new module isn't compatible with `old`'s:
+    ///
+    /// * Every export of `old`'s module must also be exported by this module,
+    ///   with a type that matches the original export's type as an import
+    ///   would.
+    /// * This module must define the same number of memories, tables, and
+    ///   globals as `old`'s module, with the same page sizes, index types,
+    ///   reference types, and maximum sizes. Mutable globals must have been
+    ///   mutable, with the same type, in `old`'s module too.
+    /// * Every function of `old` that is referenced by its tables or globals
+    ///   must have a counterpart of the same type, at the same index, in this
+    ///   module which may be referenced, for example because it appears in an
+    ///   element segment.
+    /// * Neither module may define shared memories.
+    ///
+    /// An error is also returned if `store` has async support enabled, if
+    /// instantiation fails, if allocating the empty memories and tables left
+    /// to `old` fails, or if growing `old`'s memories or tables fails, which is
+    /// only attempted once the new instance has been created. In every case
+    /// nothing is moved out of `old`, and it keeps its state; the only
+    /// exception is that if one of several memories or tables fails to grow,
+    /// the ones grown before it keep their new size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `old` or any import closed over by this [`InstancePre`]
+    /// isn't owned by `store`.
+    pub fn hot_swap(
+        &self,
+        mut store: impl AsContextMut<Data = T>,
+        old: Instance,
+    ) -> Result<Instance> {
+        let mut store = store.as_context_mut();
+        old.id.assert_belongs_to(store.0.id());
+        ensure!(
+            !store.0.async_support(),
+            "cannot hot swap an instance when async support is enabled",
+        );
+        let old_module = old._module(store.0).clone();
+        check_compatible(&old_module, &self.module)?;
+
+        #[cfg(feature = "gc")]
+        let mut store = RootScope::new(&mut store);
+        let mut swap = HotSwap::new(store.as_context_mut().0, old, &self.module);
+        swap.prepare(&mut store.as_context_mut())?;
+
+        let mut store = store.as_context_mut();
+        let imports = pre_instantiate_raw(
+            &mut store.0,
+            &self.module,
+            &self.items,
+            self.host_funcs,
+            &self.func_refs,
+        )?;
+        // This unsafety should be handled by the type-checking performed by
+        // the constructor of `InstancePre`, as in `InstancePre::instantiate`.
+        let (new, _start) = unsafe { Instance::new_raw(store.0, &self.module, imports.as_ref())? };
+
+        swap.grow(&mut store)?;
+        swap.finish(&mut store, new);
+        Ok(new)
+    }
+}
+
+/// Check that `new` can replace `old`, as described in
+/// `InstancePre::hot_swap`.
+fn check_compatible(old: &Module, new: &Module) -> Result<()> {
+    let old_env = old.env_module();
+    let new_env = new.env_module();
+
+    for (name, old_index) in old_env.exports.iter() {
+        let new_index = new_env
+            .exports
+            .get(name)
+            .with_context(|| format!("replacement module is missing export `{name}`"))?;
+        matching::entity_ty(
+            old.engine(),
+            &old_env.type_of(*old_index),
+            &new_env.type_of(*new_index),
+        )
+        .with_context(|| format!("incompatible type for export `{name}`"))?;
+    }
+
+    ensure!(
+        old_env.num_defined_memories() == new_env.num_defined_memories(),
+        "replacement module defines {} memories, but the instance defines {}",
+        new_env.num_defined_memories(),
+        old_env.num_defined_memories(),
+    );
+    for i in 0..old_env.num_defined_memories() {
+        let i = DefinedMemoryIndex::new(i);
+        let old_ty = &old_env.memories[old_env.memory_index(i)];
+        let new_ty = &new_env.memories[new_env.memory_index(i)];
+        ensure!(
+            !old_ty.shared && !new_ty.shared,
+            "cannot hot swap an instance which defines shared memories"
+        );
+        ensure!(
+            old_ty.page_size_log2 == new_ty.page_size_log2
+                && old_ty.idx_type == new_ty.idx_type
+                && old_ty.limits.max == new_ty.limits.max,
+            "incompatible type for defined memory {}",
+            i.as_u32(),
+        );
+    }
+
+    ensure!(
+        old_env.num_defined_tables() == new_env.num_defined_tables(),
+        "replacement module defines {} tables, but the instance defines {}",
+        new_env.num_defined_tables(),
+        old_env.num_defined_tables(),
+    );
+    for i in 0..old_env.num_defined_tables() {
+        let i = DefinedTableIndex::new(i);
+        let old_ty = &old_env.tables[old_env.table_index(i)];
+        let new_ty = &new_env.tables[new_env.table_index(i)];
+        ensure!(
+            old_ty.ref_type == new_ty.ref_type
+                && old_ty.idx_type == new_ty.idx_type
+                && old_ty.limits.max == new_ty.limits.max,
+            "incompatible type for defined table {}",
+            i.as_u32(),
+        );
+    }
+
+    ensure!(
+        old_env.num_defined_globals() == new_env.num_defined_globals(),
+        "replacement module defines {} globals, but the instance defines {}",
+        new_env.num_defined_globals(),
+        old_env.num_defined_globals(),
+    );
+    for i in 0..old_env.num_defined_globals() {
+        let i = DefinedGlobalIndex::new(i);
+        let old_ty = &old_env.globals[old_env.global_index(i)];
+        let new_ty = &new_env.globals[new_env.global_index(i)];
+        ensure!(
+            !new_ty.mutability || (old_ty.mutability && old_ty.wasm_ty == new_ty.wasm_ty),
+            "incompatible type for defined global {}",
+            i.as_u32(),
+        );
+    }
+
+    Ok(())
+}
+
+/// The state of an in-progress hot swap of `old`.
+struct HotSwap {
+    old: Instance,
+    old_env: Arc<wasmtime_environ::Module>,
+    new_env: Arc<wasmtime_environ::Module>,
+
+    /// The functions of `old` that may be referenced from its tables and
+    /// globals, keyed by the address of their `VMFuncRef`.
+    old_funcs: HashMap<usize, FuncIndex>,
+
+    /// The values of `old`'s mutable globals, read by `prepare`.
+    globals: Vec<(DefinedGlobalIndex, Val)>,
+
+    /// The empty memories and tables which `finish` leaves to `old` in place
+    /// of the new instance's, allocated by `prepare`.
+    memories: Vec<vm::Memory>,
+    tables: Vec<vm::Table>,
+}
+
+impl HotSwap {
+    fn new(store: &mut StoreOpaque, old: Instance, new_module: &Module) -> HotSwap {
+        let old_env = old._module(store).env_module().clone();
+        let new_env = new_module.env_module().clone();
+        let mut old_funcs = HashMap::new();
+        for (index, func) in old_env.functions.iter() {
+            if !func.is_escaping() {
+                continue;
+            }
+            if let Some(func_ref) = old.id.get_mut(store).get_func_ref(index) {
+                old_funcs.insert(func_ref.as_ptr().addr(), index);
+            }
+        }
+        HotSwap {
+            old,
+            old_env,
+            new_env,
+            old_funcs,
+            globals: Vec::new(),
+            memories: Vec::new(),
+            tables: Vec::new(),
+        }
+    }
+
+    /// Checks that `old`'s state can be moved into an instance of the new
+    /// module, and allocates the empty memories and tables to leave to `old`.
+    ///
+    /// Along with `grow`, this is everything that can fail, so that `finish`
+    /// can't leave the swap half done. Nothing here modifies `old`'s state, so
+    /// that it's unaffected if creating the new instance fails afterwards.
+    fn prepare<T>(&mut self, store: &mut StoreContextMut<'_, T>) -> Result<()> {
+        for i in 0..self.old_env.num_defined_tables() {
+            let i = DefinedTableIndex::new(i);
+            let table = self.old_export(store.0, EntityIndex::Table(self.old_env.table_index(i)));
+            let table = table.into_table().unwrap();
+            if !is_func_table(store.0, &table) {
+                continue;
+            }
+            // Reading every element also initializes the ones that are still
+            // lazily initialized, which the new module wouldn't know how to do
+            // once the table has been moved.
+            for j in 0..table.size(&*store) {
+                if let Some(Ref::Func(Some(f))) = table.get(&mut *store, j) {
+                    self.check_remap(store.0, f)?;
+                }
+            }
+        }
+
+        for i in 0..self.old_env.num_defined_globals() {
+            let i = DefinedGlobalIndex::new(i);
+            if !self.new_env.globals[self.new_env.global_index(i)].mutability {
+                continue;
+            }
+            let global =
+                self.old_export(store.0, EntityIndex::Global(self.old_env.global_index(i)));
+            let value = global.into_global().unwrap().get(&mut *store);
+            if let Val::FuncRef(Some(f)) = value {
+                self.check_remap(store.0, f)?;
+            }
+            self.globals.push((i, value));
+        }
+
+        let engine = store.engine().clone();
+        let creator = engine
+            .config()
+            .mem_creator
+            .as_deref()
+            .unwrap_or(&vm::DefaultMemoryCreator);
+        for i in 0..self.old_env.num_defined_memories() {
+            let ty = &self.old_env.memories[self.old_env.memory_index(DefinedMemoryIndex::new(i))];
+            let memory = vm::Memory::new_dynamic(
+                ty,
+                engine.tunables(),
+                creator,
+                store.0.traitobj_mut(),
+                None,
+            )?;
+            self.memories.push(memory);
+        }
+        for i in 0..self.old_env.num_defined_tables() {
+            let ty = &self.old_env.tables[self.old_env.table_index(DefinedTableIndex::new(i))];
+            let table = vm::Table::new_dynamic(ty, engine.tunables(), store.0.traitobj_mut())?;
+            self.tables.push(table);
+        }
+
+        Ok(())
+    }
+
+    /// Grows `old`'s memories and tables to the new module's minimum sizes,
+    /// once the new instance has been created.
+    fn grow<T>(&self, store: &mut StoreContextMut<'_, T>) -> Result<()> {
+        for i in 0..self.old_env.num_defined_memories() {
+            let i = DefinedMemoryIndex::new(i);
+            let memory =
+                self.old_export(store.0, EntityIndex::Memory(self.old_env.memory_index(i)));
+            let memory = memory.into_memory().unwrap();
+            let min = self.new_env.memories[self.new_env.memory_index(i)]
+                .limits
+                .min;
+            let size = memory.size(&*store);
+            if size < min {
+                memory
+                    .grow(&mut *store, min - size)
+                    .with_context(|| format!("failed to grow memory {}", i.as_u32()))?;
+            }
+        }
+
+        for i in 0..self.old_env.num_defined_tables() {
+            let i = DefinedTableIndex::new(i);
+            let table = self.old_export(store.0, EntityIndex::Table(self.old_env.table_index(i)));
+            let table = table.into_table().unwrap();
+            let min = self.new_env.tables[self.new_env.table_index(i)].limits.min;
+            let size = table.size(&*store);
+            if size < min {
+                let init = Ref::null(table.ty(&*store).element().heap_type());
+                table
+                    .grow(&mut *store, min - size, init)
+                    .with_context(|| format!("failed to grow table {}", i.as_u32()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves `old`'s state into `new`, after `prepare` and `grow` have
+    /// succeeded, and deallocates the memories and tables that `new` was
+    /// created with.
+    fn finish<T>(&mut self, store: &mut StoreContextMut<'_, T>, new: Instance) {
+        let (old_id, new_id) = (self.old.id.instance(), new.id.instance());
+        let engine = store.engine().clone();
+
+        for (i, memory) in core::mem::take(&mut self.memories).into_iter().enumerate() {
+            let i = DefinedMemoryIndex::new(i);
+            let (mut old, new) = store.0.instance_pair_mut(old_id, new_id);
+            old.as_mut().swap_defined_memory(new, i);
+            let (allocation_index, replaced) = old.replace_defined_memory(i, memory);
+            // SAFETY: `replaced` was allocated by the engine's allocator for
+            // `new`, and nothing refers to it anymore now that `new` uses
+            // `old`'s memory and `old` uses the empty one.
+            unsafe {
+                engine
+                    .allocator()
+                    .deallocate_memory(Some(i), allocation_index, replaced);
+            }
+        }
+
+        for (i, table) in core::mem::take(&mut self.tables).into_iter().enumerate() {
+            let i = DefinedTableIndex::new(i);
+            let (mut old, new_instance) = store.0.instance_pair_mut(old_id, new_id);
+            old.as_mut().swap_defined_table(new_instance, i);
+            let (allocation_index, replaced) = old.replace_defined_table(i, table);
+            // SAFETY: as with memories above.
+            unsafe {
+                engine
+                    .allocator()
+                    .deallocate_table(i, allocation_index, replaced);
+            }
+
+            let table = new._get_export(store.0, EntityIndex::Table(self.new_env.table_index(i)));
+            let table = table.into_table().unwrap();
+            if !is_func_table(store.0, &table) {
+                continue;
+            }
+            for j in 0..table.size(&*store) {
+                if let Some(Ref::Func(Some(f))) = table.get(&mut *store, j) {
+                    let f = self.remap_func(store.0, new, f);
+                    table.set(&mut *store, j, Ref::Func(Some(f))).unwrap();
+                }
+            }
+        }
+
+        for (i, value) in core::mem::take(&mut self.globals) {
+            let global =
+                new._get_export(store.0, EntityIndex::Global(self.new_env.global_index(i)));
+            let value = match value {
+                Val::FuncRef(Some(f)) => Val::FuncRef(Some(self.remap_func(store.0, new, f))),
+                value => value,
+            };
+            global
+                .into_global()
+                .unwrap()
+                .set(&mut *store, value)
+                .unwrap();
+        }
+    }
+
+    fn old_export(&self, store: &mut StoreOpaque, index: EntityIndex) -> Extern {
+        self.old._get_export(store, index)
+    }
+
+    /// Returns the index of `func` within `old` if it's one of `old`'s
+    /// functions.
+    fn old_func_index(&self, store: &mut StoreOpaque, func: Func) -> Option<FuncIndex> {
+        self.old_funcs
+            .get(&func.vm_func_ref(store).as_ptr().addr())
+            .copied()
+    }
+
+    /// Checks that if `func` is one of `old`'s functions then the new module
+    /// has a counterpart for it that `remap_func` can return.
+    fn check_remap(&self, store: &mut StoreOpaque, func: Func) -> Result<()> {
+        let Some(index) = self.old_func_index(store, func) else {
+            return Ok(());
+        };
+        let old_ty = self.old_env.functions[index].signature;
+        ensure!(
+            self.new_env
+                .functions
+                .get(index)
+                .is_some_and(|f| f.is_escaping() && f.signature == old_ty),
+            "function {} is referenced by the instance's state, but the replacement \
+             module has no function of the same type at that index which may be referenced",
+            index.as_u32(),
+        );
+        Ok(())
+    }
+
+    /// If `func` is one of `old`'s functions then return `new`'s function
+    /// with the same index, otherwise return `func` unchanged.
+    fn remap_func(&self, store: &mut StoreOpaque, new: Instance, func: Func) -> Func {
+        match self.old_func_index(store, func) {
+            Some(index) => new
+                ._get_export(store, EntityIndex::Function(index))
+                .into_func()
+                .unwrap(),
+            None => func,
+        }
+    }
+}
+
+/// Returns whether `table` holds references to functions.
+fn is_func_table(store: &StoreOpaque, table: &Table) -> bool {
+    matches!(table._ty(store).element().heap_type().top(), HeapType::Func)
+}
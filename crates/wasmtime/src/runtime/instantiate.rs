@@ -18,6 +18,7 @@ pub struct CompiledModule {
     module: Arc<Module>,
     funcs: PrimaryMap<DefinedFuncIndex, CompiledFunctionInfo>,
     wasm_to_array_trampolines: Vec<(ModuleInternedTypeIndex, FunctionLoc)>,
+    lazy_trampolines: Vec<(ModuleInternedTypeIndex, FunctionLoc)>,
     meta: Metadata,
     code_memory: Arc<CodeMemory>,
     /// A unique ID used to register this module with the engine.
@@ -51,6 +52,7 @@ impl CompiledModule {
             module: Arc::new(info.module),
             funcs: info.funcs,
             wasm_to_array_trampolines: info.wasm_to_array_trampolines,
+            lazy_trampolines: info.lazy_trampolines,
             code_memory,
             meta: info.meta,
             unique_id: CompiledModuleId::new(),
@@ -173,6 +175,23 @@ impl CompiledModule {
         &self.text()[loc.start as usize..][..loc.length as usize]
     }
 
+    /// Get the Wasm-to-lazy trampoline for the given signature.
+    ///
+    /// These trampolines are used for filling in `VMFuncRef::wasm_call` for
+    /// functions that haven't been compiled yet with tiered compilation.
+    pub fn lazy_trampoline(&self, signature: ModuleInternedTypeIndex) -> &[u8] {
+        let idx = match self
+            .lazy_trampolines
+            .binary_search_by_key(&signature, |entry| entry.0)
+        {
+            Ok(idx) => idx,
+            Err(_) => panic!("missing lazy trampoline for {signature:?}"),
+        };
+
+        let (_, loc) = self.lazy_trampolines[idx];
+        &self.text()[loc.start as usize..][..loc.length as usize]
+    }
+
     /// Lookups a defined function by a program counter value.
     ///
     /// Returns the defined function index and the relative address of
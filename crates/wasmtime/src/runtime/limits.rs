@@ -125,6 +125,79 @@ pub trait ResourceLimiter {
         Ok(())
     }
 
+    /// Notifies the resource limiter that the store's GC heap has been
+    /// requested to grow.
+    ///
+    /// * `current` is the current size of the GC heap in bytes.
+    /// * `desired` is the desired size of the GC heap in bytes.
+    /// * `maximum` is the largest size, in bytes, that the GC heap can ever
+    ///   reach, if any.
+    ///
+    /// Each store has a single GC heap that holds all of its GC objects (for
+    /// example `externref`s and Wasm GC structs and arrays), which Wasmtime
+    /// grows on demand when allocations don't fit. Growth is typically
+    /// attempted before, or in combination with, collecting garbage, so
+    /// rejecting growth does not necessarily fail the allocation that
+    /// triggered it.
+    ///
+    /// Wasmtime first asks to grow the GC heap by more than an allocation
+    /// needs, to amortize the cost of growth. If that is rejected, or fails
+    /// with an error, it asks again for just what the allocation needs, and
+    /// only an error from that second request is raised.
+    ///
+    /// By default this delegates to [`ResourceLimiter::memory_growing`], for
+    /// backwards compatibility with limiters that predate this method.
+    ///
+    /// See the details on the return values for `memory_growing` for what the
+    /// return value of this function indicates, where an `Err(e)` is raised
+    /// as a trap when the allocation that triggered growth came from
+    /// WebAssembly.
+    fn gc_heap_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool> {
+        self.memory_growing(current, desired, maximum)
+    }
+
+    /// Notifies the resource limiter that growing the GC heap, permitted by
+    /// the `gc_heap_growing` method, has failed.
+    ///
+    /// Note that this method is not called if `gc_heap_growing` returns an
+    /// error.
+    ///
+    /// By default this delegates to [`ResourceLimiter::memory_grow_failed`].
+    fn gc_heap_grow_failed(&mut self, error: anyhow::Error) -> Result<()> {
+        self.memory_grow_failed(error)
+    }
+
+    /// Notifies the resource limiter that a GC allocation of `bytes_needed`
+    /// bytes could not be satisfied, even after collecting garbage and
+    /// attempting to grow the GC heap.
+    ///
+    /// This is called right before the allocation fails with a
+    /// [`GcHeapOutOfMemory`](crate::GcHeapOutOfMemory) error, which traps when
+    /// the allocation came from WebAssembly.
+    ///
+    /// ## Return Value
+    ///
+    /// If `Ok(true)` is returned then Wasmtime will collect garbage and
+    /// attempt to grow the GC heap one more time, and then retry the
+    /// allocation. This lets the limiter grant more space, for example by
+    /// raising the budget that it enforces in `gc_heap_growing`. If the
+    /// allocation fails again, it is not retried again.
+    ///
+    /// If `Ok(false)` is returned, which is the default, then the allocation
+    /// fails as usual.
+    ///
+    /// If `Err(e)` is returned then the allocation fails with that error
+    /// instead.
+    fn gc_heap_out_of_memory(&mut self, bytes_needed: u64) -> Result<bool> {
+        let _ = bytes_needed;
+        Ok(false)
+    }
+
     /// The maximum number of instances that can be created for a `Store`.
     ///
     /// Module instantiation will fail if this limit is exceeded.
@@ -201,6 +274,35 @@ pub trait ResourceLimiterAsync {
         Ok(())
     }
 
+    /// Asynchronous version of [`ResourceLimiter::gc_heap_growing`]
+    //
+    // Note: this is the expansion of `async fn gc_heap_growing` written out by
+    // hand, so that the default implementation can forward to
+    // `memory_growing` without requiring `Self: Send`.
+    fn gc_heap_growing<'life0, 'async_trait>(
+        &'life0 mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> core::pin::Pin<Box<dyn core::future::Future<Output = Result<bool>> + Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        self.memory_growing(current, desired, maximum)
+    }
+
+    /// Identical to [`ResourceLimiter::gc_heap_grow_failed`]
+    fn gc_heap_grow_failed(&mut self, error: anyhow::Error) -> Result<()> {
+        self.memory_grow_failed(error)
+    }
+
+    /// Identical to [`ResourceLimiter::gc_heap_out_of_memory`]
+    fn gc_heap_out_of_memory(&mut self, bytes_needed: u64) -> Result<bool> {
+        let _ = bytes_needed;
+        Ok(false)
+    }
+
     /// Identical to [`ResourceLimiter::instances`]`
     fn instances(&self) -> usize {
         DEFAULT_INSTANCE_LIMIT
@@ -242,6 +344,23 @@ impl StoreLimitsBuilder {
         self
     }
 
+    /// The maximum number of bytes the store's GC heap can grow to.
+    ///
+    /// Growing the GC heap beyond this limit will fail, and GC allocations
+    /// that don't fit after collecting garbage will fail with a
+    /// [`GcHeapOutOfMemory`](crate::GcHeapOutOfMemory) error, trapping if
+    /// they came from WebAssembly. Since each store has a single GC heap, this
+    /// caps the total size of the store's GC objects.
+    ///
+    /// By default, the GC heap is limited by the [`memory_size`] limit, if
+    /// any.
+    ///
+    /// [`memory_size`]: StoreLimitsBuilder::memory_size
+    pub fn gc_heap_size(mut self, limit: usize) -> Self {
+        self.0.gc_heap_size = Some(limit);
+        self
+    }
+
     /// The maximum number of elements in a table.
     ///
     /// Growing a table beyond this limit will fail. This limit is applied to
@@ -315,6 +434,7 @@ impl StoreLimitsBuilder {
 #[derive(Clone, Debug)]
 pub struct StoreLimits {
     memory_size: Option<usize>,
+    gc_heap_size: Option<usize>,
     table_elements: Option<usize>,
     instances: usize,
     tables: usize,
@@ -326,6 +446,7 @@ impl Default for StoreLimits {
     fn default() -> Self {
         Self {
             memory_size: None,
+            gc_heap_size: None,
             table_elements: None,
             instances: DEFAULT_INSTANCE_LIMIT,
             tables: DEFAULT_TABLE_LIMIT,
@@ -365,6 +486,23 @@ impl ResourceLimiter for StoreLimits {
         }
     }
 
+    fn gc_heap_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool> {
+        let Some(limit) = self.gc_heap_size else {
+            return self.memory_growing(current, desired, maximum);
+        };
+        let allow = desired <= limit && maximum.is_none_or(|max| desired <= max);
+        if !allow && self.trap_on_grow_failure {
+            bail!("forcing trap when growing the GC heap to {desired} bytes")
+        } else {
+            Ok(allow)
+        }
+    }
+
     fn table_growing(
         &mut self,
         _current: usize,
@@ -474,7 +474,7 @@ impl<T> Linker<T> {
             let instance = caller.caller();
             caller.store.with_blocking(|store, cx| {
                 let caller = Caller::new(store, instance);
-                cx.block_on(core::pin::Pin::from(func(caller, params, results)))
+                cx.block_on_host_call(core::pin::Pin::from(func(caller, params, results)))
             })?
         })
     }
@@ -572,7 +572,7 @@ impl<T> Linker<T> {
         let func =
             HostFunc::wrap_inner(&self.engine, move |caller: Caller<'_, T>, args: Params| {
                 let instance = caller.caller();
-                let result = caller.store.block_on(|store| {
+                let result = caller.store.block_on_host_call(|store| {
                     let caller = Caller::new(store, instance);
                     func(caller, args).into()
                 });
@@ -359,8 +359,10 @@ impl Memory {
         offset: usize,
         buffer: &[u8],
     ) -> Result<(), MemoryAccessError> {
-        let mut context = store.as_context_mut();
-        self.data_mut(&mut context)
+        let store = store.as_context_mut().0;
+        #[cfg(feature = "record-replay")]
+        self.record_replay_write(store, offset..offset.saturating_add(buffer.len()));
+        self.data_mut_unrecorded(store)
             .get_mut(offset..)
             .and_then(|s| s.get_mut(..buffer.len()))
             .ok_or(MemoryAccessError { _private: () })?
@@ -397,14 +399,29 @@ impl Memory {
         &self,
         store: impl Into<StoreContextMut<'a, T>>,
     ) -> &'a mut [u8] {
+        let store: &'a mut StoreOpaque = store.into().0;
+        #[cfg(feature = "record-replay")]
+        self.record_replay_write(store, 0..usize::MAX);
+        self.data_mut_unrecorded(store)
+    }
+
+    /// Same as [`Memory::data_mut`], but without noting the access for host
+    /// call traces, for callers which note the part they write themselves.
+    fn data_mut_unrecorded<'a>(&self, store: &'a mut StoreOpaque) -> &'a mut [u8] {
         unsafe {
-            let store = store.into();
             let definition = store[self.instance].memory(self.index);
-            debug_assert!(!self.ty(store).is_shared());
+            debug_assert!(!self.wasmtime_ty(store).shared);
             slice::from_raw_parts_mut(definition.base.as_ptr(), definition.current_length())
         }
     }
 
+    /// Note that `range` of this memory is about to be written, so that a host
+    /// call trace can record the write.
+    #[cfg(feature = "record-replay")]
+    fn record_replay_write(&self, store: &StoreOpaque, range: core::ops::Range<usize>) {
+        store.record_replay_write(store[self.instance].memory_ptr(self.index), range);
+    }
+
     /// Same as [`Memory::data_mut`], but also returns the `T` from the
     /// [`StoreContextMut`].
     ///
@@ -19,12 +19,16 @@ use core::ptr::NonNull;
 use std::{fs::File, path::Path};
 use wasmparser::{Parser, ValidPayload, Validator};
 use wasmtime_environ::{
-    CompiledModuleInfo, EntityIndex, HostPtr, ModuleTypes, ObjectKind, TypeTrace, VMOffsets,
-    VMSharedTypeIndex,
+    CompiledModuleInfo, EntityIndex, HostPtr, ModuleInternedTypeIndex, ModuleTypes, ObjectKind,
+    TypeTrace, VMOffsets, VMSharedTypeIndex,
 };
 mod registry;
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+mod tiered;
 
 pub use registry::*;
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+pub(crate) use tiered::{LazyFunction, TieredModule};
 
 /// A compiled WebAssembly module, ready to be instantiated.
 ///
@@ -157,6 +161,11 @@ struct ModuleInner {
 
     /// Runtime offset information for `VMContext`.
     offsets: VMOffsets<HostPtr>,
+
+    /// The code compiled at runtime for this module's functions, if it was
+    /// compiled with tiered compilation.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    tiered: Option<TieredModule>,
 }
 
 impl fmt::Debug for Module {
@@ -531,6 +540,12 @@ impl Module {
 
         let _ = serializable;
 
+        #[cfg(any(feature = "cranelift", feature = "winch"))]
+        let tiered = match module.module().num_lazy_funcs {
+            0 => None,
+            n => Some(TieredModule::new(n)),
+        };
+
         Ok(Self {
             inner: Arc::new(ModuleInner {
                 engine: engine.clone(),
@@ -540,6 +555,8 @@ impl Module {
                 #[cfg(any(feature = "cranelift", feature = "winch"))]
                 serializable,
                 offsets,
+                #[cfg(any(feature = "cranelift", feature = "winch"))]
+                tiered,
             }),
         })
     }
@@ -623,6 +640,32 @@ impl Module {
         Ok(self.compiled_module().mmap().to_vec())
     }
 
+    /// Serializes this module into a new sealed, anonymous in-memory file.
+    ///
+    /// This is the same as [`Module::serialize`] except that the result is
+    /// placed in a `memfd_create` file whose contents can never change. The
+    /// file can be sent to other processes, for example over a Unix socket
+    /// with `SCM_RIGHTS`, and loaded there with
+    /// [`Module::deserialize_open_file`]. Modules loaded that way map their
+    /// code and initial memory images directly from the shared file, so the
+    /// memory for them is shared between all processes rather than
+    /// duplicated in each one.
+    ///
+    /// This is only available on Linux.
+    #[cfg(all(
+        any(feature = "cranelift", feature = "winch"),
+        feature = "std",
+        target_os = "linux",
+        not(miri)
+    ))]
+    pub fn serialize_to_memfd(&self) -> Result<File> {
+        let bytes = self.serialize()?;
+        match crate::runtime::vm::create_sealed_memfd("wasmtime-module", &bytes)? {
+            Some(memfd) => Ok(memfd.into_file()),
+            None => bail!("memfd_create is not supported on this system"),
+        }
+    }
+
     pub(crate) fn compiled_module(&self) -> &CompiledModule {
         &self.inner.module
     }
@@ -639,7 +682,7 @@ impl Module {
         self.inner.code.module_types()
     }
 
-    #[cfg(any(feature = "component-model", feature = "gc-drc"))]
+    #[cfg(any(feature = "component-model", feature = "gc"))]
     pub(crate) fn signatures(&self) -> &crate::type_registry::TypeCollection {
         self.inner.code.signatures()
     }
@@ -1073,6 +1116,37 @@ impl Module {
         signature: VMSharedTypeIndex,
     ) -> Option<NonNull<VMWasmCallFunction>> {
         log::trace!("Looking up trampoline for {signature:?}");
+        let trampoline_module_ty = self.trampoline_type(signature)?;
+        let ptr = self
+            .compiled_module()
+            .wasm_to_array_trampoline(trampoline_module_ty)
+            .as_ptr()
+            .cast::<VMWasmCallFunction>()
+            .cast_mut();
+        Some(NonNull::new(ptr).unwrap())
+    }
+
+    /// Return the address, in memory, of the trampoline that compiles a
+    /// function of the given signature on its first call with tiered
+    /// compilation.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub(crate) fn lazy_trampoline(
+        &self,
+        signature: VMSharedTypeIndex,
+    ) -> Option<NonNull<VMWasmCallFunction>> {
+        let trampoline_module_ty = self.trampoline_type(signature)?;
+        let ptr = self
+            .compiled_module()
+            .lazy_trampoline(trampoline_module_ty)
+            .as_ptr()
+            .cast::<VMWasmCallFunction>()
+            .cast_mut();
+        Some(NonNull::new(ptr).unwrap())
+    }
+
+    /// Returns this module's type index for the trampoline type of
+    /// `signature`, if this module has trampolines for it.
+    fn trampoline_type(&self, signature: VMSharedTypeIndex) -> Option<ModuleInternedTypeIndex> {
         let trampoline_shared_ty = self.inner.engine.signatures().trampoline_type(signature);
         let trampoline_module_ty = self
             .inner
@@ -1094,14 +1168,7 @@ impl Module {
                 .unwrap_func()
                 .is_trampoline_type()
         );
-
-        let ptr = self
-            .compiled_module()
-            .wasm_to_array_trampoline(trampoline_module_ty)
-            .as_ptr()
-            .cast::<VMWasmCallFunction>()
-            .cast_mut();
-        Some(NonNull::new(ptr).unwrap())
+        Some(trampoline_module_ty)
     }
 
     pub(crate) fn memory_images(&self) -> Result<Option<&ModuleMemoryImages>> {
@@ -1116,6 +1183,12 @@ impl Module {
     /// Lookup the stack map at a program counter value.
     #[cfg(feature = "gc")]
     pub(crate) fn lookup_stack_map(&self, pc: usize) -> Option<wasmtime_environ::StackMap<'_>> {
+        #[cfg(any(feature = "cranelift", feature = "winch"))]
+        if let Some((_, func)) = self.tiered().and_then(|t| t.lookup(pc)) {
+            let text_offset = u32::try_from(func.text_offset(pc)).unwrap();
+            let info = func.code_memory().stack_map_data();
+            return wasmtime_environ::StackMap::lookup(text_offset, info);
+        }
         let text_offset = u32::try_from(pc - self.inner.module.text().as_ptr() as usize).unwrap();
         let info = self.inner.code.code_memory().stack_map_data();
         wasmtime_environ::StackMap::lookup(text_offset, info)
@@ -1172,7 +1245,12 @@ fn memory_images(engine: &Engine, module: &CompiledModule) -> Result<Option<Modu
     } else {
         Some(module.mmap())
     };
-    ModuleMemoryImages::new(module.module(), module.code_memory().wasm_data(), mmap)
+    ModuleMemoryImages::new(
+        engine,
+        module.module(),
+        module.code_memory().wasm_data(),
+        mmap,
+    )
 }
 
 #[cfg(test)]
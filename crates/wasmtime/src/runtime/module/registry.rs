@@ -3,6 +3,8 @@
 use crate::code::CodeObject;
 #[cfg(feature = "component-model")]
 use crate::component::Component;
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+use crate::module::LazyFunction;
 use crate::prelude::*;
 use crate::runtime::vm::VMWasmCallFunction;
 use crate::sync::{OnceLock, RwLock};
@@ -10,6 +12,8 @@ use crate::{FrameInfo, Module, code_memory::CodeMemory};
 use alloc::collections::btree_map::{BTreeMap, Entry};
 use alloc::sync::Arc;
 use core::ptr::NonNull;
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+use wasmtime_environ::DefinedFuncIndex;
 use wasmtime_environ::VMSharedTypeIndex;
 
 /// Used for registering modules with a store.
@@ -72,8 +76,14 @@ impl ModuleRegistry {
     /// Fetches a registered module given a program counter value.
     #[cfg(feature = "gc")]
     pub fn lookup_module_by_pc(&self, pc: usize) -> Option<&Module> {
-        let (module, _) = self.module_and_offset(pc)?;
-        Some(module)
+        if let Some((module, _)) = self.module_and_offset(pc) {
+            return Some(module);
+        }
+        #[cfg(any(feature = "cranelift", feature = "winch"))]
+        if let Some((module, _, _)) = self.lookup_lazy_function(pc) {
+            return Some(module);
+        }
+        None
     }
 
     fn code(&self, pc: usize) -> Option<(&LoadedCode, usize)> {
@@ -179,9 +189,37 @@ impl ModuleRegistry {
     /// boolean indicates whether the engine used to compile this module is
     /// using environment variables to control debuginfo parsing.
     pub(crate) fn lookup_frame_info(&self, pc: usize) -> Option<(FrameInfo, &Module)> {
-        let (module, offset) = self.module_and_offset(pc)?;
-        let info = FrameInfo::new(module.clone(), offset)?;
-        Some((info, module))
+        if let Some((module, offset)) = self.module_and_offset(pc) {
+            let info = FrameInfo::new(module.clone(), offset)?;
+            return Some((info, module));
+        }
+        #[cfg(any(feature = "cranelift", feature = "winch"))]
+        if let Some((module, index, func)) = self.lookup_lazy_function(pc) {
+            let info = FrameInfo::new_lazy(module.clone(), index, func, pc)?;
+            return Some((info, module));
+        }
+        None
+    }
+
+    /// Looks up the function, compiled at runtime with tiered compilation,
+    /// whose code contains `pc`.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    fn lookup_lazy_function(
+        &self,
+        pc: usize,
+    ) -> Option<(&Module, DefinedFuncIndex, &LazyFunction)> {
+        // Like `wasm_to_array_trampoline` below this is a linear search over
+        // each module, which is fine as there are typically very few modules
+        // per store. Note that modules compiled with tiered compilation have no
+        // functions in their own code, so they're always found within
+        // `modules_with_only_trampolines`.
+        self.loaded_code
+            .values()
+            .flat_map(|(_, code)| &code.modules_with_only_trampolines)
+            .find_map(|module| {
+                let (index, func) = module.tiered()?.lookup(pc)?;
+                Some((module, index, func))
+            })
     }
 
     pub fn wasm_to_array_trampoline(
@@ -0,0 +1,481 @@
+//! Lazy and tiered compilation of a module's functions.
+//!
+//! With [`Strategy::Tiered`](crate::Strategy::Tiered) none of a module's
+//! functions are compiled when the module is created. Each function's
+//! `VMFuncRef` instead starts out pointing at a "lazy trampoline" which
+//! compiles the function with the baseline compiler on its first call. Once
+//! a function has been called enough times it's recompiled with the optimizing
+//! compiler, and its `VMFuncRef` is updated once more.
+//!
+//! The code compiled for each function is owned by the `Module`, so it's
+//! shared by all instances of the module. The code of each function compiled
+//! with the baseline compiler lives in its own `CodeMemory`, since functions
+//! are compiled one at a time as they're first called. When a function is
+//! recompiled with the optimizing compiler, all other functions of the
+//! instance which are at least halfway to being recompiled are recompiled
+//! along with it, into a single `CodeMemory`.
+//!
+//! Some limitations of this implementation are:
+//!
+//! * The module is translated from its original wasm once for each tier,
+//!   the first time that a function is compiled in that tier, and the
+//!   translation is kept until every function has been compiled in that tier.
+//!   The translation can't be shared between the tiers since compiling a
+//!   function consumes its validator, which can't be cloned.
+//!
+//! * Functions are only recompiled on entry, so a function which is called
+//!   once but runs a long loop keeps running its baseline code until it
+//!   returns. There is no on-stack replacement.
+//!
+//! * Compilation happens on a thread of its own, while the thread calling the
+//!   function waits, rather than on the stack of the wasm calling it, which
+//!   may be too small for the compiler. This costs spawning a thread per
+//!   compilation.
+//!
+//! * Copies of a function's `VMFuncRef` which were made before it was
+//!   compiled, such as other instances' imports of the function, keep calling
+//!   through the lazy trampoline, which calls the function's best code
+//!   compiled so far. This costs an extra load and indirect call per call.
+
+use crate::Module;
+use crate::code_memory::CodeMemory;
+use crate::prelude::*;
+use crate::runtime::vm::{VMArrayCallFunction, VMWasmCallFunction};
+use crate::sync::{OnceLock, RwLock};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::mem;
+use core::ops::Range;
+use core::ptr::NonNull;
+use wasmparser::{BinaryReader, FuncToValidate, FunctionBody, ValidatorResources};
+use wasmtime_environ::{
+    CompiledFunctionInfo, Compiler, DefinedFuncIndex, FilePos, FunctionBodyData, ModuleTranslation,
+    ModuleTypesBuilder, PrimaryMap,
+};
+
+/// The functions of a module compiled with tiered compilation.
+pub(crate) struct TieredModule {
+    /// The module's translation for each tier, which is recreated from the
+    /// original wasm the first time that a function is compiled in that tier.
+    translations: [RwLock<TierTranslation>; 2],
+
+    /// The code compiled so far for each of the module's defined functions.
+    funcs: PrimaryMap<DefinedFuncIndex, TieredFunc>,
+
+    /// The start address, functions, and tier of all code compiled so far,
+    /// keyed by the inclusive end address of its text section.
+    ranges: RwLock<BTreeMap<usize, (usize, Vec<DefinedFuncIndex>, Tier)>>,
+}
+
+#[derive(Default)]
+struct TieredFunc {
+    /// The function's baseline code, or the error message from compiling it.
+    ///
+    /// Compiling a function consumes its body from the translation, so each
+    /// function is only compiled once per tier and failures are remembered.
+    baseline: OnceLock<Result<LazyFunction, String>>,
+
+    /// This is `None` if the function failed to compile with the optimizing
+    /// compiler, in which case it keeps using its baseline code.
+    optimized: OnceLock<Option<LazyFunction>>,
+}
+
+#[derive(Clone, Copy)]
+enum Tier {
+    Baseline = 0,
+    Optimized = 1,
+}
+
+/// The state of a module's translation for one tier.
+#[derive(Default)]
+enum TierTranslation {
+    /// No function has been compiled in this tier yet.
+    #[default]
+    Untranslated,
+
+    /// Some functions have been compiled in this tier.
+    Translated {
+        translation: Arc<LazyTranslation>,
+        /// The bodies of the functions which haven't been compiled in this
+        /// tier yet, and aren't being compiled.
+        bodies: PrimaryMap<DefinedFuncIndex, Option<LazyBody>>,
+        /// The number of `Some` entries in `bodies`.
+        remaining: usize,
+    },
+
+    /// Every function has been compiled in this tier, or is being compiled,
+    /// so the translation has been dropped.
+    Done,
+}
+
+/// The parsed module that functions are compiled from.
+///
+/// This only has the parts of the translation which don't borrow from the
+/// original wasm, and so doesn't include the names of functions, which are
+/// left out of the symbols of lazily compiled code.
+struct LazyTranslation {
+    translation: ModuleTranslation<'static>,
+    types: ModuleTypesBuilder,
+}
+
+/// The body of a function which hasn't been compiled in a tier yet.
+struct LazyBody {
+    /// The location of the body within the module's original wasm.
+    range: Range<usize>,
+    validator: FuncToValidate<ValidatorResources>,
+}
+
+/// A function compiled at runtime with tiered compilation.
+pub(crate) struct LazyFunction {
+    code: Arc<LazyCode>,
+    info: CompiledFunctionInfo,
+}
+
+/// Code compiled at runtime with tiered compilation, which may be shared by
+/// several functions.
+struct LazyCode(Arc<CodeMemory>);
+
+impl TieredModule {
+    pub(crate) fn new(num_funcs: usize) -> TieredModule {
+        TieredModule {
+            translations: Default::default(),
+            funcs: (0..num_funcs).map(|_| TieredFunc::default()).collect(),
+            ranges: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the best code compiled so far for the function `index`, if
+    /// it's been compiled yet.
+    pub(crate) fn function(&self, index: DefinedFuncIndex) -> Option<&LazyFunction> {
+        let func = &self.funcs[index];
+        match func.optimized.get() {
+            Some(Some(optimized)) => Some(optimized),
+            _ => func.baseline.get()?.as_ref().ok(),
+        }
+    }
+
+    /// Returns whether the function `index` has been recompiled with the
+    /// optimizing compiler, or `None` if that hasn't finished yet.
+    ///
+    /// A function which failed to recompile keeps using its baseline code.
+    pub(crate) fn optimized(&self, index: DefinedFuncIndex) -> Option<bool> {
+        Some(self.funcs[index].optimized.get()?.is_some())
+    }
+
+    /// Looks up the function whose compiled code contains `pc`, if any.
+    pub(crate) fn lookup(&self, pc: usize) -> Option<(DefinedFuncIndex, &LazyFunction)> {
+        let ranges = self.ranges.read();
+        let (end, (start, indices, tier)) = ranges.range(pc..).next()?;
+        if pc < *start || *end < pc {
+            return None;
+        }
+        let funcs = indices.iter().filter_map(|&index| {
+            let func = &self.funcs[index];
+            let code = match tier {
+                Tier::Baseline => func.baseline.get()?.as_ref().ok()?,
+                Tier::Optimized => func.optimized.get()?.as_ref()?,
+            };
+            Some((index, code))
+        });
+
+        // Prefer the function whose own code contains `pc`, rather than one of
+        // the trampolines compiled alongside the functions.
+        let mut first = None;
+        for (index, code) in funcs {
+            if code.contains(code.text_offset(pc)) {
+                return Some((index, code));
+            }
+            first.get_or_insert((index, code));
+        }
+        first
+    }
+
+    /// Takes the bodies of those of the functions `indices` which haven't
+    /// been compiled in `tier` yet, and aren't being compiled by another
+    /// thread, along with the translation to compile them with.
+    ///
+    /// Returns `None` if there are no such functions.
+    fn take_bodies(
+        &self,
+        module: &Module,
+        indices: &[DefinedFuncIndex],
+        tier: Tier,
+    ) -> Result<Option<(Arc<LazyTranslation>, Vec<(DefinedFuncIndex, LazyBody)>)>> {
+        let mut state = self.translations[tier as usize].write();
+        if let TierTranslation::Untranslated = *state {
+            *state = LazyTranslation::new(module)?;
+        }
+        let TierTranslation::Translated {
+            translation,
+            bodies,
+            remaining,
+        } = &mut *state
+        else {
+            return Ok(None);
+        };
+        let funcs = indices
+            .iter()
+            .filter_map(|&index| Some((index, bodies[index].take()?)))
+            .collect::<Vec<_>>();
+        if funcs.is_empty() {
+            return Ok(None);
+        }
+        let translation = translation.clone();
+        *remaining -= funcs.len();
+        if *remaining == 0 {
+            // Once the functions being compiled are done with it, nothing
+            // needs this tier's translation anymore.
+            *state = TierTranslation::Done;
+        }
+        Ok(Some((translation, funcs)))
+    }
+
+    /// Compiles the functions `funcs`, whose bodies were returned by
+    /// `take_bodies` along with `lazy`, together into a single `CodeMemory`.
+    fn compile(
+        &self,
+        module: &Module,
+        compiler: &dyn Compiler,
+        lazy: &LazyTranslation,
+        funcs: Vec<(DefinedFuncIndex, LazyBody)>,
+        tier: Tier,
+    ) -> Result<Vec<(DefinedFuncIndex, LazyFunction)>> {
+        let engine = module.engine();
+        let wasm = module.code_object().code_memory().original_wasm();
+        let indices = funcs.iter().map(|(index, _)| *index).collect::<Vec<_>>();
+        let funcs = funcs
+            .into_iter()
+            .map(|(index, body)| (index, body.data(engine, wasm)))
+            .collect();
+        let (code, infos) = crate::compile::compile_lazy_functions(
+            engine,
+            compiler,
+            &lazy.types,
+            &lazy.translation,
+            funcs,
+        )?;
+        engine
+            .profiler()
+            .register_module(&code.mmap()[..], &|_| None);
+
+        crate::module::register_code(&code);
+        let text = code.text();
+        if !text.is_empty() {
+            let start = text.as_ptr().addr();
+            let end = start + text.len() - 1;
+            self.ranges
+                .write()
+                .insert(end, (start, indices.clone(), tier));
+        }
+        let code = Arc::new(LazyCode(code));
+        Ok(indices
+            .into_iter()
+            .zip(infos)
+            .map(|(index, info)| {
+                let code = code.clone();
+                (index, LazyFunction { code, info })
+            })
+            .collect())
+    }
+}
+
+impl LazyTranslation {
+    /// Translates `module`'s original wasm, returning the state of a tier
+    /// which none of the functions have been compiled in yet.
+    fn new(module: &Module) -> Result<TierTranslation> {
+        let wasm = module.code_object().code_memory().original_wasm();
+        let (types, mut translation) = crate::compile::translate_module(module.engine(), wasm)?;
+        let bodies = mem::take(&mut translation.function_body_inputs)
+            .into_iter()
+            .map(|(_, body)| {
+                Some(LazyBody {
+                    range: body.body.range(),
+                    validator: body.validator,
+                })
+            })
+            .collect::<PrimaryMap<_, _>>();
+        let remaining = bodies.len();
+        Ok(TierTranslation::Translated {
+            translation: Arc::new(LazyTranslation {
+                translation: translation.into_owned(),
+                types,
+            }),
+            bodies,
+            remaining,
+        })
+    }
+}
+
+impl LazyBody {
+    /// Returns the function body to compile, from the module's original
+    /// `wasm`.
+    fn data<'a>(self, engine: &crate::Engine, wasm: &'a [u8]) -> FunctionBodyData<'a> {
+        let reader = BinaryReader::new_features(
+            &wasm[self.range.clone()],
+            self.range.start,
+            engine.features(),
+        );
+        FunctionBodyData {
+            body: FunctionBody::new(reader),
+            validator: self.validator,
+        }
+    }
+}
+
+/// Runs `f`, which translates and compiles functions, on a thread of its own
+/// and waits for it.
+///
+/// Functions are compiled when they're first called, or once they've been
+/// called enough times, from the stack of the wasm calling them. That stack
+/// may be an async fiber's, and wasm may have used most of it, so it can't be
+/// relied on to have room for the translator or the compiler.
+fn on_compile_thread<R: Send>(f: impl FnOnce() -> Result<R> + Send) -> Result<R> {
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new()
+            .name("wasmtime-tiered-compile".to_string())
+            .spawn_scoped(scope, f)
+            .context("failed to spawn a thread to compile functions on")?;
+        match thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    })
+}
+
+impl LazyFunction {
+    /// Returns the function's code, with the Wasm calling convention.
+    pub(crate) fn wasm_call(&self) -> NonNull<VMWasmCallFunction> {
+        let loc = self.info.wasm_func_loc;
+        let ptr = self.code.0.text()[loc.start as usize..].as_ptr();
+        NonNull::new(ptr.cast::<VMWasmCallFunction>().cast_mut()).unwrap()
+    }
+
+    /// Returns the function's array-to-Wasm trampoline.
+    pub(crate) fn array_call(&self) -> NonNull<VMArrayCallFunction> {
+        let loc = self
+            .info
+            .array_to_wasm_trampoline
+            .expect("lazily compiled functions always escape");
+        let ptr = self.code.0.text()[loc.start as usize..].as_ptr();
+        NonNull::new(ptr.cast::<VMArrayCallFunction>().cast_mut()).unwrap()
+    }
+
+    /// Returns the code memory that this function was compiled into.
+    pub(crate) fn code_memory(&self) -> &CodeMemory {
+        &self.code.0
+    }
+
+    /// Returns whether `text_offset` is within the function itself, rather
+    /// than one of the trampolines compiled alongside it.
+    pub(crate) fn contains(&self, text_offset: usize) -> bool {
+        let loc = self.info.wasm_func_loc;
+        let start = loc.start as usize;
+        start <= text_offset && text_offset < start + loc.length as usize
+    }
+
+    /// Returns the offset of `pc` within this function's text section.
+    pub(crate) fn text_offset(&self, pc: usize) -> usize {
+        pc - self.code.0.text().as_ptr().addr()
+    }
+
+    /// Returns the original binary offset of the start of this function.
+    pub(crate) fn start_srcloc(&self) -> FilePos {
+        self.info.start_srcloc
+    }
+}
+
+impl Drop for LazyCode {
+    fn drop(&mut self) {
+        crate::module::unregister_code(&self.0);
+    }
+}
+
+impl Module {
+    /// Returns the state of this module's functions if it was compiled with
+    /// tiered compilation.
+    pub(crate) fn tiered(&self) -> Option<&TieredModule> {
+        self.inner.tiered.as_ref()
+    }
+
+    /// Compiles the function `index` of this module, which must have been
+    /// compiled with tiered compilation, with the baseline compiler if that
+    /// hasn't happened yet.
+    ///
+    /// Returns the best code compiled so far for the function. The function
+    /// is compiled on a thread of its own, see `on_compile_thread`, and
+    /// failing to compile it is an error.
+    pub(crate) fn lazy_compile(&self, index: DefinedFuncIndex) -> Result<&LazyFunction> {
+        let tiered = self.tiered().unwrap();
+        if let Some(func) = tiered.function(index) {
+            return Ok(func);
+        }
+        tiered.funcs[index]
+            .baseline
+            .get_or_init(|| {
+                log::debug!("compiling function {index:?} on its first call");
+                on_compile_thread(|| {
+                    let (lazy, funcs) = tiered
+                        .take_bodies(self, &[index], Tier::Baseline)?
+                        .expect("functions are only compiled once per tier");
+                    let funcs = tiered.compile(
+                        self,
+                        self.engine().compiler(),
+                        &lazy,
+                        funcs,
+                        Tier::Baseline,
+                    )?;
+                    let (_, func) = funcs
+                        .into_iter()
+                        .next()
+                        .expect("functions are only compiled once per tier");
+                    Ok(func)
+                })
+                .map_err(|e| format!("{e:?}"))
+            })
+            .as_ref()
+            .map_err(|e| anyhow!("failed to compile function {}: {e}", index.as_u32()))?;
+        Ok(tiered.function(index).unwrap())
+    }
+
+    /// Recompiles those of the functions `indices` of this module, which must
+    /// have been compiled with tiered compilation, which haven't been
+    /// recompiled with the optimizing compiler yet, together.
+    ///
+    /// Functions which are being recompiled by another thread are skipped,
+    /// and functions which fail to recompile keep using their baseline code.
+    pub(crate) fn tier_up(&self, indices: &[DefinedFuncIndex]) {
+        let tiered = self.tiered().unwrap();
+        let failed = |indices: &[DefinedFuncIndex]| {
+            for &index in indices {
+                tiered.funcs[index].optimized.get_or_init(|| None);
+            }
+        };
+        let Some(compiler) = self.engine().tier_up_compiler() else {
+            return failed(indices);
+        };
+        let result = on_compile_thread(|| {
+            let (lazy, funcs) = match tiered.take_bodies(self, indices, Tier::Optimized)? {
+                Some(taken) => taken,
+                None => return Ok(()),
+            };
+            let indices = funcs.iter().map(|(index, _)| *index).collect::<Vec<_>>();
+            log::debug!("recompiling functions {indices:?} with the optimizing compiler");
+            match tiered.compile(self, compiler, &lazy, funcs, Tier::Optimized) {
+                Ok(funcs) => {
+                    for (index, func) in funcs {
+                        tiered.funcs[index].optimized.get_or_init(|| Some(func));
+                    }
+                }
+                Err(e) => {
+                    log::warn!("failed to recompile functions {indices:?}: {e:?}");
+                    failed(&indices);
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            log::warn!("failed to recompile functions {indices:?}: {e:?}");
+            failed(indices);
+        }
+    }
+}
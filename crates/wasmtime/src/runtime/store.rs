@@ -122,6 +122,18 @@ mod async_;
 pub use self::async_::CallHookHandler;
 #[cfg(feature = "gc")]
 mod gc;
+mod snapshot;
+pub use self::snapshot::StoreSnapshot;
+mod fuel_profile;
+pub use self::fuel_profile::FuncFuel;
+mod memory_access;
+pub use self::memory_access::{MemoryAccess, MemoryAccessKind};
+#[cfg(feature = "record-replay")]
+mod replay;
+#[cfg(all(feature = "record-replay", feature = "component-model"))]
+pub(crate) use self::replay::HostCallFrame;
+#[cfg(feature = "record-replay")]
+pub use self::replay::HostCallTrace;
 
 /// A [`Store`] is a collection of WebAssembly instances and host-defined state.
 ///
@@ -235,6 +247,8 @@ pub struct StoreInner<T: 'static> {
     #[cfg(target_has_atomic = "64")]
     epoch_deadline_behavior:
         Option<Box<dyn FnMut(StoreContextMut<T>) -> Result<UpdateDeadline> + Send + Sync>>,
+    memory_access_hook:
+        Option<Box<dyn FnMut(StoreContextMut<'_, T>, MemoryAccess) -> Result<()> + Send + Sync>>,
     // for comments about `ManuallyDrop`, see `Store::into_data`
     data: ManuallyDrop<T>,
 }
@@ -386,6 +400,11 @@ pub struct StoreOpaque {
     /// guest code.
     pkey: Option<ProtectionKey>,
 
+    /// The trace that host function results are recorded into or replayed
+    /// from, if any. See `Store::host_call_trace`.
+    #[cfg(feature = "record-replay")]
+    host_call_trace: Option<HostCallTrace>,
+
     /// Runtime state for components used in the handling of resources, borrow,
     /// and calls. These also interact with the `ResourceAny` type and its
     /// internal representation.
@@ -584,6 +603,8 @@ impl<T> Store<T> {
             hostcall_val_storage: Vec::new(),
             wasm_val_raw_storage: Vec::new(),
             pkey,
+            #[cfg(feature = "record-replay")]
+            host_call_trace: None,
             #[cfg(feature = "component-model")]
             component_host_table: Default::default(),
             #[cfg(feature = "component-model")]
@@ -598,6 +619,7 @@ impl<T> Store<T> {
             call_hook: None,
             #[cfg(target_has_atomic = "64")]
             epoch_deadline_behavior: None,
+            memory_access_hook: None,
             data: ManuallyDrop::new(data),
         });
 
@@ -789,6 +811,37 @@ impl<T> Store<T> {
         self.inner.call_hook = Some(CallHookInner::Sync(Box::new(hook)));
     }
 
+    /// Configure a function that's called before every access to linear
+    /// memory by WebAssembly in this store.
+    ///
+    /// This requires
+    /// [`Config::memory_access_tracing`](crate::Config::memory_access_tracing)
+    /// to be enabled; otherwise compiled code doesn't report its memory
+    /// accesses and the hook is never called. The hook is passed a
+    /// [`MemoryAccess`] describing the access, and may record it, for example
+    /// by appending it to a buffer within `T`, or inspect the store's state.
+    ///
+    /// If the hook returns an error then the access doesn't happen and the
+    /// error is raised as a trap instead.
+    ///
+    /// This replaces any hook previously configured for this store.
+    pub fn memory_access_hook(
+        &mut self,
+        hook: impl FnMut(StoreContextMut<'_, T>, MemoryAccess) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.inner.memory_access_hook = Some(Box::new(hook));
+    }
+
+    /// Configure a trace to record the results of host functions into, or to
+    /// replay them from.
+    ///
+    /// See [`HostCallTrace`] for more information. This replaces any trace
+    /// previously configured for this store.
+    #[cfg(feature = "record-replay")]
+    pub fn host_call_trace(&mut self, trace: HostCallTrace) {
+        self.inner.host_call_trace = Some(trace);
+    }
+
     /// Returns the [`Engine`] that this store is associated with.
     pub fn engine(&self) -> &Engine {
         self.inner.engine()
@@ -813,6 +866,67 @@ impl<T> Store<T> {
         self.inner.gc(why);
     }
 
+    /// Build a graph of every live object in this store's GC heap.
+    ///
+    /// The resulting [`GcHeapGraph`][crate::GcHeapGraph] describes each
+    /// object that is reachable from this store's GC roots, along with its
+    /// type, size, and outgoing references, and can be exported to the Chrome
+    /// `.heapsnapshot` format. This is useful for tracking down memory leaks
+    /// in WebAssembly guests that use GC types.
+    ///
+    /// This method does not collect garbage and does not modify the GC heap.
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_graph(&mut self) -> crate::GcHeapGraph {
+        self.inner.gc_heap_graph()
+    }
+
+    /// Capture a snapshot of the state of every instance in this store.
+    ///
+    /// The snapshot includes the contents of all memories, tables, and globals
+    /// defined within this store, as well as its GC heap. It can later be
+    /// applied to a store containing the same instances with
+    /// [`Store::restore`], for example to warm-start a new process after
+    /// serializing it with [`StoreSnapshot::serialize`].
+    ///
+    /// This should not be called while WebAssembly is executing within this
+    /// store, for example from within a host function.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this store contains state that cannot be captured.
+    /// See [`StoreSnapshot`] for details.
+    pub fn snapshot(&mut self) -> Result<StoreSnapshot> {
+        self.inner.snapshot()
+    }
+
+    /// Restore a snapshot previously captured with [`Store::snapshot`].
+    ///
+    /// This store must contain the same instances, instantiated in the same
+    /// order, as the store that the snapshot was taken from, and it must use
+    /// the same [`Engine`] if the snapshot contains a GC heap. The contents of
+    /// all memories, tables, and globals are overwritten with the snapshot's
+    /// contents, growing them as necessary.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot is incompatible with this store, for
+    /// example if the instances differ or if a memory or table is already
+    /// larger than it was in the snapshot, if the snapshot's contents are
+    /// invalid, or if growing a memory or table fails. Compatibility and the
+    /// snapshot's contents are checked before any state is modified, and a
+    /// failure to grow leaves the contents of this store's memories, tables,
+    /// and globals unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if growth is required and this store is configured with an
+    /// async resource limiter, in the same manner as [`Memory::grow`].
+    pub fn restore(&mut self, snapshot: &StoreSnapshot) -> Result<()> {
+        self.inner.restore(snapshot)
+    }
+
     /// Returns the amount fuel in this [`Store`]. When fuel is enabled, it must
     /// be configured via [`Store::set_fuel`].
     ///
@@ -847,6 +961,22 @@ impl<T> Store<T> {
         self.inner.set_fuel(fuel)
     }
 
+    /// Returns the fuel consumed by each WebAssembly function that has
+    /// executed in this [`Store`], sorted from the most to the least fuel
+    /// consumed.
+    ///
+    /// Each function is reported once per module, with the fuel consumed by
+    /// all instances of that module in this store added together. Functions
+    /// which haven't consumed any fuel are omitted.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if fuel profiling is not enabled
+    /// via [`Config::fuel_profiling`](crate::Config::fuel_profiling).
+    pub fn fuel_profile(&self) -> Result<Vec<FuncFuel>> {
+        self.inner.fuel_profile()
+    }
+
     /// Configures a [`Store`] to yield execution of async WebAssembly code
     /// periodically.
     ///
@@ -1000,6 +1130,13 @@ impl<'a, T> StoreContext<'a, T> {
     pub fn get_fuel(&self) -> Result<u64> {
         self.0.get_fuel()
     }
+
+    /// Returns the fuel consumed by each function in this store.
+    ///
+    /// For more information see [`Store::fuel_profile`].
+    pub fn fuel_profile(&self) -> Result<Vec<FuncFuel>> {
+        self.0.fuel_profile()
+    }
 }
 
 impl<'a, T> StoreContextMut<'a, T> {
@@ -1032,6 +1169,30 @@ impl<'a, T> StoreContextMut<'a, T> {
         self.0.gc(why);
     }
 
+    /// Build a graph of every live object in this store's GC heap.
+    ///
+    /// Same as [`Store::gc_heap_graph`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_graph(&mut self) -> crate::GcHeapGraph {
+        self.0.gc_heap_graph()
+    }
+
+    /// Capture a snapshot of the state of every instance in this store.
+    ///
+    /// Same as [`Store::snapshot`].
+    pub fn snapshot(&mut self) -> Result<StoreSnapshot> {
+        self.0.snapshot()
+    }
+
+    /// Restore a snapshot previously captured with [`Store::snapshot`].
+    ///
+    /// Same as [`Store::restore`].
+    pub fn restore(&mut self, snapshot: &StoreSnapshot) -> Result<()> {
+        self.0.restore(snapshot)
+    }
+
     /// Returns remaining fuel in this store.
     ///
     /// For more information see [`Store::get_fuel`]
@@ -1046,6 +1207,13 @@ impl<'a, T> StoreContextMut<'a, T> {
         self.0.set_fuel(fuel)
     }
 
+    /// Returns the fuel consumed by each function in this store.
+    ///
+    /// For more information see [`Store::fuel_profile`]
+    pub fn fuel_profile(&self) -> Result<Vec<FuncFuel>> {
+        self.0.fuel_profile()
+    }
+
     /// Configures this `Store` to periodically yield while executing futures.
     ///
     /// For more information see [`Store::fuel_async_yield_interval`]
@@ -1294,6 +1462,20 @@ impl StoreOpaque {
         self.instances[id].handle.get_mut()
     }
 
+    /// Same as [`Self::instance_mut`], but for two distinct instances at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` and `b` are the same instance.
+    pub fn instance_pair_mut(
+        &mut self,
+        a: InstanceId,
+        b: InstanceId,
+    ) -> (Pin<&mut vm::Instance>, Pin<&mut vm::Instance>) {
+        let [a, b] = self.instances.get_many_mut([a, b]).unwrap();
+        (a.handle.get_mut(), b.handle.get_mut())
+    }
+
     /// Get all instances (ignoring dummy instances) within this store.
     pub fn all_instances<'a>(&'a mut self) -> impl ExactSizeIterator<Item = Instance> + 'a {
         let instances = self
@@ -2197,6 +2379,60 @@ unsafe impl<T> vm::VMStore for StoreInner<T> {
         }
     }
 
+    #[cfg(feature = "gc")]
+    fn gc_heap_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool, anyhow::Error> {
+        match self.limiter {
+            Some(ResourceLimiterInner::Sync(ref mut limiter)) => {
+                limiter(&mut self.data).gc_heap_growing(current, desired, maximum)
+            }
+            #[cfg(feature = "async")]
+            Some(ResourceLimiterInner::Async(_)) => self.block_on(|store| {
+                let limiter = match &mut store.0.limiter {
+                    Some(ResourceLimiterInner::Async(limiter)) => limiter,
+                    _ => unreachable!(),
+                };
+                limiter(&mut store.0.data).gc_heap_growing(current, desired, maximum)
+            })?,
+            None => Ok(true),
+        }
+    }
+
+    #[cfg(feature = "gc")]
+    fn gc_heap_grow_failed(&mut self, error: anyhow::Error) -> Result<()> {
+        match self.limiter {
+            Some(ResourceLimiterInner::Sync(ref mut limiter)) => {
+                limiter(&mut self.data).gc_heap_grow_failed(error)
+            }
+            #[cfg(feature = "async")]
+            Some(ResourceLimiterInner::Async(ref mut limiter)) => {
+                limiter(&mut self.data).gc_heap_grow_failed(error)
+            }
+            None => {
+                log::debug!("ignoring GC heap growth failure: {error:?}");
+                Ok(())
+            }
+        }
+    }
+
+    #[cfg(feature = "gc")]
+    fn gc_heap_out_of_memory(&mut self, bytes_needed: u64) -> Result<bool, anyhow::Error> {
+        match self.limiter {
+            Some(ResourceLimiterInner::Sync(ref mut limiter)) => {
+                limiter(&mut self.data).gc_heap_out_of_memory(bytes_needed)
+            }
+            #[cfg(feature = "async")]
+            Some(ResourceLimiterInner::Async(ref mut limiter)) => {
+                limiter(&mut self.data).gc_heap_out_of_memory(bytes_needed)
+            }
+            None => Ok(false),
+        }
+    }
+
     fn out_of_gas(&mut self) -> Result<()> {
         if !self.refuel() {
             return Err(Trap::OutOfFuel.into());
@@ -2260,6 +2496,10 @@ unsafe impl<T> vm::VMStore for StoreInner<T> {
         delta_result
     }
 
+    fn memory_access(&mut self, access: MemoryAccess) -> Result<()> {
+        StoreInner::memory_access(self, access)
+    }
+
     #[cfg(feature = "gc")]
     unsafe fn maybe_async_grow_or_collect_gc_heap(
         &mut self,
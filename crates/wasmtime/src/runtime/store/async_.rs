@@ -239,6 +239,9 @@ impl StoreOpaque {
     /// This only works on async futures and stores, and assumes that we're
     /// executing on a fiber. This will yield execution back to the caller once.
     pub fn async_yield_impl(&mut self) -> Result<()> {
+        #[cfg(feature = "record-replay")]
+        self.record_replay_yield()?;
+
         // When control returns, we have a `Result<()>` passed
         // in from the host fiber. If this finished successfully then
         // we were resumed normally via a `poll`, so keep going.  If
@@ -0,0 +1,82 @@
+//! Reading the per-function fuel counters maintained by fuel profiling.
+
+use super::*;
+use crate::runtime::vm::CompiledModuleId;
+use alloc::collections::BTreeMap;
+use wasmtime_environ::{DefinedFuncIndex, FuncIndex};
+
+/// The fuel consumed by a single WebAssembly function, as reported by
+/// [`Store::fuel_profile`].
+#[derive(Clone, Debug)]
+pub struct FuncFuel {
+    module: Module,
+    func_index: u32,
+    fuel: u64,
+}
+
+impl FuncFuel {
+    /// Returns the module that defines this function.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Returns the index of this function within its module's function index
+    /// space, which includes imported functions.
+    pub fn func_index(&self) -> u32 {
+        self.func_index
+    }
+
+    /// Returns the name of this function from the module's `name` section,
+    /// if it has one.
+    pub fn func_name(&self) -> Option<&str> {
+        self.module
+            .compiled_module()
+            .func_name(FuncIndex::from_u32(self.func_index))
+    }
+
+    /// Returns the fuel consumed by this function, not including the fuel
+    /// consumed by the functions that it called.
+    pub fn fuel(&self) -> u64 {
+        self.fuel
+    }
+}
+
+impl StoreOpaque {
+    pub(crate) fn fuel_profile(&self) -> Result<Vec<FuncFuel>> {
+        ensure!(
+            self.engine().tunables().fuel_profiling,
+            "fuel profiling is not configured in this store"
+        );
+
+        // Instances of the same module share a profile entry per function.
+        let mut profile = BTreeMap::<(CompiledModuleId, u32), FuncFuel>::new();
+        for (id, instance) in self.instances.iter() {
+            let StoreInstanceKind::Real { module_id } = instance.kind else {
+                continue;
+            };
+            let module = self
+                .modules()
+                .lookup_module_by_id(module_id)
+                .expect("should always have a registered module for real instances");
+            let env_module = module.env_module();
+            for (i, fuel) in self.instance(id).fuel_counters().iter().enumerate() {
+                if *fuel == 0 {
+                    continue;
+                }
+                let func_index = env_module.func_index(DefinedFuncIndex::new(i)).as_u32();
+                profile
+                    .entry((module.id(), func_index))
+                    .or_insert_with(|| FuncFuel {
+                        module: module.clone(),
+                        func_index,
+                        fuel: 0,
+                    })
+                    .fuel += fuel;
+            }
+        }
+
+        let mut profile = profile.into_values().collect::<Vec<_>>();
+        profile.sort_by(|a, b| b.fuel.cmp(&a.fuel));
+        Ok(profile)
+    }
+}
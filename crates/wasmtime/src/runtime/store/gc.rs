@@ -7,9 +7,12 @@ impl StoreOpaque {
     /// Collect garbage, potentially growing the GC heap.
     pub(crate) fn gc(&mut self, why: Option<&GcHeapOutOfMemory<()>>) {
         assert!(!self.async_support());
-        unsafe {
-            self.maybe_async_gc(None, why.map(|oom| oom.bytes_needed()))
-                .expect("infallible when not async");
+        let result = unsafe { self.maybe_async_gc(None, why.map(|oom| oom.bytes_needed())) };
+        if let Err(e) = result {
+            // When not async, the only errors come from the resource limiter
+            // rejecting GC heap growth, and garbage was still collected, so
+            // treat this like any other growth failure.
+            log::debug!("ignoring error while growing the GC heap: {e:?}");
         }
     }
 
@@ -39,9 +42,10 @@ impl StoreOpaque {
 
         if scope.async_support() {
             #[cfg(feature = "async")]
-            scope.block_on(|scope| Box::pin(scope.grow_or_collect_gc_heap_async(bytes_needed)))?;
+            scope
+                .block_on(|scope| Box::pin(scope.grow_or_collect_gc_heap_async(bytes_needed)))??;
         } else {
-            scope.grow_or_collect_gc_heap(bytes_needed);
+            scope.grow_or_collect_gc_heap(bytes_needed)?;
         }
 
         let root = match root {
@@ -58,25 +62,106 @@ impl StoreOpaque {
         Ok(root)
     }
 
-    fn grow_or_collect_gc_heap(&mut self, bytes_needed: Option<u64>) {
+    /// Grow the GC heap and/or collect garbage, according to the GC heap's
+    /// preference.
+    ///
+    /// Returns an error if the resource limiter rejected growing the GC heap
+    /// with an error, but only after collecting garbage, if we would have done
+    /// so anyways.
+    fn grow_or_collect_gc_heap(&mut self, bytes_needed: Option<u64>) -> Result<()> {
         assert!(!self.async_support());
         if let Some(n) = bytes_needed {
-            if unsafe { self.maybe_async_grow_gc_heap(n).is_ok() } {
-                return;
+            if self.gc_heap_collects_before_growing() {
+                self.do_gc();
+                if self.maybe_grow_gc_heap_after_gc(n)? {
+                    self.do_gc();
+                }
+                return Ok(());
             }
         }
+        let grow_result = match bytes_needed {
+            Some(n) => unsafe { self.maybe_async_grow_gc_heap(n) },
+            None => Ok(false),
+        };
+        if let Ok(true) = grow_result {
+            return Ok(());
+        }
         self.do_gc();
+        if bytes_needed.is_some() {
+            self.finish_deferred_gc_work();
+        }
+        grow_result.map(|_| ())
+    }
+
+    /// Walk the GC heap from the GC roots and build a graph of every live
+    /// object.
+    pub(crate) fn gc_heap_graph(&mut self) -> crate::GcHeapGraph {
+        if self.optional_gc_store().is_none() {
+            return crate::GcHeapGraph::default();
+        }
+
+        // Take the GC roots out of `self` so we can borrow it mutably but still
+        // call mutable methods on `self`, just like when collecting garbage.
+        let mut roots = core::mem::take(&mut self.gc_roots_list);
+        self.trace_roots(&mut roots);
+        let root_refs = unsafe { roots.iter() }
+            .map(|root| root.get())
+            .filter(|gc_ref| !gc_ref.is_i31())
+            .collect();
+        roots.clear();
+        self.gc_roots_list = roots;
+
+        let engine = self.engine().clone();
+        crate::GcHeapGraph::new(&engine, self.unwrap_gc_store_mut(), root_refs)
+    }
+
+    /// Does the GC heap prefer collecting garbage over growing when an
+    /// allocation fails?
+    fn gc_heap_collects_before_growing(&self) -> bool {
+        self.optional_gc_store()
+            .is_some_and(|gc_store| gc_store.gc_heap.collect_before_growing())
+    }
+
+    /// After a collection triggered by a failed allocation of `bytes_needed`
+    /// bytes, grow the GC heap if it asks for more space.
+    ///
+    /// Returns `true` if the heap grew but still needs another collection
+    /// before the allocation can succeed.
+    fn maybe_grow_gc_heap_after_gc(&mut self, bytes_needed: u64) -> Result<bool> {
+        let growth_needed = |store: &Self| {
+            store
+                .optional_gc_store()
+                .and_then(|gc_store| gc_store.gc_heap.growth_needed(bytes_needed))
+        };
+        match growth_needed(self) {
+            Some(delta) => {
+                let grew = unsafe { self.maybe_async_grow_gc_heap(delta)? };
+                Ok(grew && growth_needed(self).is_some())
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Finish any collection work deferred by incremental GC.
+    ///
+    /// Used when an allocation can't be satisfied by growing the GC heap, so
+    /// that no garbage is left unreclaimed because of the work budget.
+    fn finish_deferred_gc_work(&mut self) {
+        if let Some(gc_store) = self.optional_gc_store_mut() {
+            gc_store.finish_deferred_work();
+        }
     }
 
     /// Attempt to grow the GC heap by `bytes_needed` bytes.
     ///
-    /// Returns an error if growing the GC heap fails.
+    /// Returns whether the GC heap was grown. Returns an error if the resource
+    /// limiter raised an error in response to the growth.
     ///
     /// # Safety
     ///
     /// When async is enabled, it is the caller's responsibility to ensure that
     /// this is called on a fiber stack.
-    unsafe fn maybe_async_grow_gc_heap(&mut self, bytes_needed: u64) -> Result<()> {
+    pub(super) unsafe fn maybe_async_grow_gc_heap(&mut self, bytes_needed: u64) -> Result<bool> {
         log::trace!("Attempting to grow the GC heap by {bytes_needed} bytes");
         assert!(bytes_needed > 0);
 
@@ -84,7 +169,7 @@ impl StoreOpaque {
         // grow it, then replace it.
         let mut memory = unsafe { self.unwrap_gc_store_mut().gc_heap.take_memory() };
         let mut delta_bytes_grown = 0;
-        let grow_result: Result<()> = (|| {
+        let grow_result: Result<bool> = (|| {
             let page_size = self.engine().tunables().gc_heap_memory_type().page_size();
 
             let current_size_in_bytes = u64::try_from(memory.byte_size()).unwrap();
@@ -116,13 +201,50 @@ impl StoreOpaque {
             let delta_pages_for_alloc = delta_pages_for_alloc.max(pages_needed);
             assert!(delta_pages_for_alloc > 0);
 
+            // Give the store's resource limiter the first chance to reject
+            // this growth. Note that we consult the GC-heap-specific limiter
+            // hooks here, rather than letting `Memory::grow` consult the
+            // linear memory hooks, so that embedders can limit GC heaps and
+            // linear memories independently.
+            //
+            // If the limiter rejects doubling the heap, then ask again for
+            // just the pages needed by this allocation, so that a limit that
+            // isn't a power of two times the initial heap size can be reached.
+            // Limiters that trap on growth failure, such as `StoreLimits`
+            // with `trap_on_grow_failure`, report rejections as errors, so an
+            // error from the first request is treated as a rejection too, and
+            // only an error from the second one is raised.
+            let current = usize::try_from(current_size_in_bytes).unwrap();
+            let desired = |delta_pages: u64| {
+                delta_pages
+                    .checked_mul(page_size)
+                    .and_then(|delta| delta.checked_add(current_size_in_bytes))
+                    .and_then(|n| usize::try_from(n).ok())
+                    .unwrap_or(usize::MAX)
+            };
+            let maximum = usize::try_from(max_size_in_bytes).ok();
+            let store = unsafe { self.traitobj().as_mut() };
+            let mut delta_pages_for_alloc = delta_pages_for_alloc;
+            if delta_pages_for_alloc == pages_needed {
+                if !store.gc_heap_growing(current, desired(pages_needed), maximum)? {
+                    return Ok(false);
+                }
+            } else if !store
+                .gc_heap_growing(current, desired(delta_pages_for_alloc), maximum)
+                .unwrap_or(false)
+            {
+                if !store.gc_heap_growing(current, desired(pages_needed), maximum)? {
+                    return Ok(false);
+                }
+                delta_pages_for_alloc = pages_needed;
+            }
+
             // Safety: we pair growing the GC heap with updating its associated
             // `VMMemoryDefinition` in the `VMStoreContext` immediately
             // afterwards.
-            unsafe {
-                memory
-                    .grow(delta_pages_for_alloc, Some(self.traitobj().as_mut()))?
-                    .ok_or_else(|| anyhow!("failed to grow GC heap"))?;
+            if unsafe { memory.grow(delta_pages_for_alloc, None)? }.is_none() {
+                store.gc_heap_grow_failed(anyhow!("failed to grow GC heap"))?;
+                return Ok(false);
             }
             self.vm_store_context.gc_heap = memory.vmmemory();
 
@@ -134,7 +256,7 @@ impl StoreOpaque {
                 delta_bytes_grown >= delta_bytes_for_alloc,
                 "{delta_bytes_grown} should be greater than or equal to {delta_bytes_for_alloc}"
             );
-            Ok(())
+            Ok(true)
         })();
 
         // Regardless whether growing succeeded or failed, place the memory back
@@ -150,6 +272,11 @@ impl StoreOpaque {
 
     /// Attempt an allocation, if it fails due to GC OOM, then do a GC and
     /// retry.
+    ///
+    /// If the allocation still fails after the GC, then the store's resource
+    /// limiter is given a chance to make more capacity available via
+    /// `gc_heap_out_of_memory`, after which the allocation is attempted one
+    /// last time.
     pub(crate) fn retry_after_gc<T, U>(
         &mut self,
         value: T,
@@ -162,17 +289,7 @@ impl StoreOpaque {
             !self.async_support(),
             "use the `*_async` versions of methods when async is configured"
         );
-        match alloc_func(self, value) {
-            Ok(x) => Ok(x),
-            Err(e) => match e.downcast::<crate::GcHeapOutOfMemory<T>>() {
-                Ok(oom) => {
-                    let (value, oom) = oom.take_inner();
-                    self.gc(Some(&oom));
-                    alloc_func(self, value)
-                }
-                Err(e) => Err(e),
-            },
-        }
+        unsafe { self.retry_after_gc_maybe_async(value, alloc_func) }
     }
 
     /// Like `retry_after_gc` but async yielding (if necessary) is transparent.
@@ -189,17 +306,48 @@ impl StoreOpaque {
     where
         T: Send + Sync + 'static,
     {
-        match alloc_func(self, value) {
-            Ok(x) => Ok(x),
-            Err(e) => match e.downcast::<crate::GcHeapOutOfMemory<T>>() {
-                Ok(oom) => {
-                    let (value, oom) = oom.take_inner();
-                    self.maybe_async_gc(None, Some(oom.bytes_needed()))?;
-                    alloc_func(self, value)
-                }
-                Err(e) => Err(e),
-            },
+        let (value, oom) = match split_gc_heap_oom(alloc_func(self, value))? {
+            Ok(x) => return Ok(x),
+            Err(pair) => pair,
+        };
+        unsafe {
+            self.maybe_async_gc(None, Some(oom.bytes_needed()))?;
+        }
+
+        let (value, oom) = match split_gc_heap_oom(alloc_func(self, value))? {
+            Ok(x) => return Ok(x),
+            Err(pair) => pair,
+        };
+        if !self.gc_heap_out_of_memory(oom.bytes_needed())? {
+            return Err(GcHeapOutOfMemory::new(value, oom.bytes_needed()).into());
+        }
+        unsafe {
+            self.maybe_async_gc(None, Some(oom.bytes_needed()))?;
         }
+        alloc_func(self, value)
+    }
+
+    /// Notify the store's resource limiter that the GC heap is out of memory,
+    /// even after collecting garbage, and return whether the failed allocation
+    /// should be retried.
+    fn gc_heap_out_of_memory(&mut self, bytes_needed: u64) -> Result<bool> {
+        log::trace!("GC heap out of memory; {bytes_needed} bytes needed");
+        unsafe { self.traitobj().as_mut() }.gc_heap_out_of_memory(bytes_needed)
+    }
+}
+
+/// Split a GC heap out-of-memory error out of an allocation's result, so that
+/// the allocation's value can be recovered and the allocation retried.
+fn split_gc_heap_oom<T, U>(result: Result<U>) -> Result<Result<U, (T, GcHeapOutOfMemory<()>)>>
+where
+    T: Send + Sync + 'static,
+{
+    match result {
+        Ok(x) => Ok(Ok(x)),
+        Err(e) => match e.downcast::<GcHeapOutOfMemory<T>>() {
+            Ok(oom) => Ok(Err(oom.take_inner())),
+            Err(e) => Err(e),
+        },
     }
 }
 
@@ -215,15 +363,30 @@ impl StoreOpaque {
         Ok(())
     }
 
-    async fn grow_or_collect_gc_heap_async(&mut self, bytes_needed: Option<u64>) {
+    async fn grow_or_collect_gc_heap_async(&mut self, bytes_needed: Option<u64>) -> Result<()> {
         assert!(self.async_support());
         if let Some(bytes_needed) = bytes_needed {
-            if unsafe { self.maybe_async_grow_gc_heap(bytes_needed).is_ok() } {
-                return;
+            if self.gc_heap_collects_before_growing() {
+                self.do_gc_async().await;
+                if self.maybe_grow_gc_heap_after_gc(bytes_needed)? {
+                    self.do_gc_async().await;
+                }
+                return Ok(());
             }
         }
+        let grow_result = match bytes_needed {
+            Some(n) => unsafe { self.maybe_async_grow_gc_heap(n) },
+            None => Ok(false),
+        };
+        if let Ok(true) = grow_result {
+            return Ok(());
+        }
 
         self.do_gc_async().await;
+        if bytes_needed.is_some() {
+            self.finish_deferred_gc_work();
+        }
+        grow_result.map(|_| ())
     }
 
     /// Attempt an allocation, if it fails due to GC OOM, then do a GC and
@@ -240,16 +403,20 @@ impl StoreOpaque {
             self.async_support(),
             "you must configure async to use the `*_async` versions of methods"
         );
-        match alloc_func(self, value) {
-            Ok(x) => Ok(x),
-            Err(e) => match e.downcast::<crate::GcHeapOutOfMemory<T>>() {
-                Ok(oom) => {
-                    let (value, oom) = oom.take_inner();
-                    self.gc_async(Some(&oom)).await?;
-                    alloc_func(self, value)
-                }
-                Err(e) => Err(e),
-            },
+        let (value, oom) = match split_gc_heap_oom(alloc_func(self, value))? {
+            Ok(x) => return Ok(x),
+            Err(pair) => pair,
+        };
+        self.gc_async(Some(&oom)).await?;
+
+        let (value, oom) = match split_gc_heap_oom(alloc_func(self, value))? {
+            Ok(x) => return Ok(x),
+            Err(pair) => pair,
+        };
+        if !self.gc_heap_out_of_memory(oom.bytes_needed())? {
+            return Err(GcHeapOutOfMemory::new(value, oom.bytes_needed()).into());
         }
+        self.gc_async(Some(&oom)).await?;
+        alloc_func(self, value)
     }
 }
@@ -0,0 +1,75 @@
+//! Reporting accesses to linear memory when memory access tracing is enabled.
+
+use super::*;
+
+pub use wasmtime_environ::MemoryAccessKind;
+
+/// An access to linear memory by WebAssembly, as reported to the hook
+/// configured with [`Store::memory_access_hook`].
+///
+/// See [`Config::memory_access_tracing`](crate::Config::memory_access_tracing)
+/// for more information.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryAccess {
+    pub(crate) kind: MemoryAccessKind,
+    pub(crate) instance: Instance,
+    pub(crate) memory_index: u32,
+    pub(crate) address: u64,
+    pub(crate) size: u8,
+    pub(crate) func_index: u32,
+    pub(crate) module_offset: u32,
+}
+
+impl MemoryAccess {
+    /// Returns whether this access reads, writes, or atomically modifies
+    /// memory.
+    pub fn kind(&self) -> MemoryAccessKind {
+        self.kind
+    }
+
+    /// Returns the instance whose function performed this access.
+    pub fn instance(&self) -> Instance {
+        self.instance
+    }
+
+    /// Returns the index of the accessed memory within the instance's memory
+    /// index space, which includes imported memories.
+    pub fn memory_index(&self) -> u32 {
+        self.memory_index
+    }
+
+    /// Returns the address of the first accessed byte within the memory.
+    pub fn address(&self) -> u64 {
+        self.address
+    }
+
+    /// Returns the number of bytes accessed.
+    pub fn size(&self) -> u8 {
+        self.size
+    }
+
+    /// Returns the index of the function which performed this access within
+    /// its module's function index space, which includes imported functions.
+    pub fn func_index(&self) -> u32 {
+        self.func_index
+    }
+
+    /// Returns the offset within the original WebAssembly module of the
+    /// instruction which performed this access.
+    pub fn module_offset(&self) -> usize {
+        self.module_offset as usize
+    }
+}
+
+impl<T> StoreInner<T> {
+    pub(crate) fn memory_access(&mut self, access: MemoryAccess) -> Result<()> {
+        // Temporarily take the hook to avoid mutably borrowing the store
+        // multiple times.
+        let Some(mut hook) = self.memory_access_hook.take() else {
+            return Ok(());
+        };
+        let result = hook((&mut *self).as_context_mut(), access);
+        self.memory_access_hook = Some(hook);
+        result
+    }
+}
@@ -0,0 +1,934 @@
+//! Recording and replaying the values a store receives from the host.
+
+use super::*;
+use crate::runtime::vm::{SendSyncPtr, VMMemoryDefinition};
+use alloc::collections::BTreeMap;
+use core::ops::Range;
+use core::slice;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Mutex;
+use wasmtime_environ::{DefinedMemoryIndex, WasmValType};
+
+/// The Wasmtime version that produced a trace. Traces may only be replayed by
+/// the same version of Wasmtime.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The granularity at which the parts of linear memory that a host call may
+/// write are saved, and compared afterwards to find the bytes it wrote.
+const CHUNK_SIZE: usize = 4096;
+
+/// A log of the nondeterministic inputs that WebAssembly received from the
+/// host, used to deterministically re-execute it.
+///
+/// A trace is attached to a store with [`Store::host_call_trace`] and is
+/// either recording or replaying:
+///
+/// * A recording trace, created with [`HostCallTrace::record`], logs the
+///   results of every call to a host function, and the bytes it wrote into
+///   the caller's linear memories, along with every point at which execution
+///   yielded to the async executor and every point at which an async host
+///   function suspended. Once execution is done the trace can be saved with
+///   [`HostCallTrace::serialize`].
+///
+/// * A replaying trace, created with [`HostCallTrace::replay`], feeds the
+///   logged results and memory writes back in the same order. Host functions
+///   are still called, so that their side effects take place, but the results
+///   they return to WebAssembly and the contents of the caller's memories
+///   afterwards are replaced with the recorded ones. If execution doesn't
+///   follow the recorded trace, for example because a different host function
+///   signature is called or a yield happens at a different point, the host
+///   call returns an error describing the divergence.
+///
+/// Host state that is read without going through a host function's results,
+/// such as clocks and random numbers that WASI writes into linear memory, can
+/// be recorded as well with [`HostCallTrace::value`] and
+/// [`HostCallTrace::bytes`]. `wasmtime-wasi` uses these to record its clocks
+/// and random number generators when configured with a trace.
+///
+/// A trace is a cheaply-cloned handle, so that the same trace can be shared
+/// between a store and the host state which records into it.
+///
+/// Both core WebAssembly host functions, like those defined with
+/// [`Func::wrap`] and [`Linker::func_wrap`], and component-model host
+/// functions are recorded. For core host functions the memories visible to
+/// the calling instance are recorded, and for component-model host functions
+/// the memory that results are lowered into.
+///
+/// Only the parts of these memories that the host accesses mutably during the
+/// call are saved and compared afterwards to find the bytes it wrote. Writes
+/// through [`Memory::write`] and component-model lowering save just the 4 KiB
+/// chunks they touch, while taking the whole memory with [`Memory::data_mut`]
+/// or [`Memory::data_and_store_mut`] saves all of it, once per host call. A
+/// host call that doesn't write to memory costs nothing extra. Lowering
+/// results that calls the guest's `realloc` also saves the whole memory, so
+/// that the guest's allocator is left as it was when recording even if the
+/// host returns differently-sized results while replaying.
+///
+/// Reference-typed results of core host functions, the contents of shared
+/// memories, writes through [`Memory::data_ptr`], writes made by WebAssembly
+/// that a host function calls back into other than `realloc`, and any state
+/// outside of linear memory that `realloc` updates, such as globals, are not
+/// recorded. Yields triggered by epoch interruption happen at nondeterministic
+/// points and so will cause replay to diverge; use fuel-based yields
+/// ([`Store::fuel_async_yield_interval`]) instead.
+///
+/// When replaying, an async host function is suspended at least as many times
+/// as it was when recording, so that other futures on the executor get the
+/// same chances to run; it may suspend more often if its future takes longer
+/// to complete.
+///
+/// [`Linker::func_wrap`]: crate::Linker::func_wrap
+#[derive(Clone)]
+pub struct HostCallTrace {
+    state: Arc<Mutex<TraceState>>,
+}
+
+struct TraceState {
+    replaying: bool,
+    events: Vec<TraceEvent>,
+
+    /// When replaying, the index of the next event in `events`.
+    position: usize,
+
+    /// When replaying, a description of where execution first diverged from
+    /// the trace, if it has.
+    divergence: Option<String>,
+
+    /// The memories of each host call in progress, innermost last, which
+    /// save their contents as the host accesses them.
+    frames: Vec<Vec<MemoryCheckpoint>>,
+}
+
+#[derive(Serialize)]
+struct SerializedTraceRef<'a> {
+    version: &'a str,
+    events: &'a [TraceEvent],
+}
+
+#[derive(Deserialize)]
+struct SerializedTrace {
+    #[expect(dead_code, reason = "checked before deserializing the rest")]
+    version: String,
+    events: Vec<TraceEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum TraceEvent {
+    /// A core host function returned these results, having written to the
+    /// caller's memories.
+    HostCall {
+        results: Vec<RecordedVal>,
+        memories: Vec<MemoryWrites>,
+    },
+    /// A component-model host function returned these flat results, having
+    /// written to the memory its results were lowered into.
+    ComponentHostCall {
+        results: Vec<u64>,
+        memories: Vec<MemoryWrites>,
+    },
+    /// Execution yielded to the async executor.
+    Yield,
+    /// An async host function's future suspended.
+    Suspend,
+    /// A value recorded with `HostCallTrace::value`.
+    Value(u64),
+    /// Bytes recorded with `HostCallTrace::bytes`.
+    Bytes(Vec<u8>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+enum RecordedVal {
+    I32(u32),
+    I64(u64),
+    F32(u32),
+    F64(u64),
+    V128(u128),
+    /// References aren't recorded, only their position in the results.
+    Ref,
+}
+
+/// The bytes that a host call wrote into one linear memory.
+#[derive(Serialize, Deserialize, Clone)]
+struct MemoryWrites {
+    /// The size of the memory after the host call, in bytes.
+    byte_size: u64,
+    writes: Vec<MemoryWrite>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct MemoryWrite {
+    offset: u64,
+    bytes: Vec<u8>,
+}
+
+impl fmt::Debug for MemoryWrites {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryWrites")
+            .field("byte_size", &self.byte_size)
+            .field("writes", &self.writes.len())
+            .finish()
+    }
+}
+
+/// The contents of a linear memory before a host call, for the parts of it
+/// that the host has accessed mutably so far.
+///
+/// These are used to find the bytes written by the call when recording, and
+/// to undo the host's own writes before applying the recorded ones when
+/// replaying.
+struct MemoryCheckpoint {
+    definition: SendSyncPtr<VMMemoryDefinition>,
+
+    /// The size of the memory before the call, in bytes.
+    byte_size: usize,
+
+    /// The whole memory's contents before the call, once the host has
+    /// accessed all of it.
+    contents: Option<Vec<u8>>,
+
+    /// Otherwise, the contents before the call of each `CHUNK_SIZE` chunk the
+    /// host has accessed, by chunk index. Chunks are truncated to the memory's
+    /// size before the call, since the rest of them started out as zero.
+    chunks: BTreeMap<usize, Vec<u8>>,
+}
+
+impl MemoryCheckpoint {
+    /// Create an empty checkpoint of the memory defined by `definition`.
+    ///
+    /// # Safety
+    ///
+    /// `definition` must point to the definition of a live, unshared memory in
+    /// the store, and remain valid until this checkpoint is dropped.
+    unsafe fn new(definition: NonNull<VMMemoryDefinition>) -> MemoryCheckpoint {
+        MemoryCheckpoint {
+            definition: SendSyncPtr::new(definition),
+            byte_size: unsafe { definition.as_ref().current_length() },
+            contents: None,
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    /// The memory's current contents.
+    ///
+    /// # Safety
+    ///
+    /// The returned slice aliases the guest's linear memory. The caller must
+    /// not let it outlive the memory, must not use it across anything that
+    /// could grow the memory, and must not create any other reference to the
+    /// memory's contents while it is in use.
+    unsafe fn data<'a>(&self) -> &'a mut [u8] {
+        unsafe {
+            let definition = self.definition.as_ref();
+            slice::from_raw_parts_mut(definition.base.as_ptr(), definition.current_length())
+        }
+    }
+
+    /// Save the contents of `range` of the memory, which the host is about to
+    /// access mutably, unless they were already saved.
+    fn save(&mut self, range: Range<usize>) {
+        if self.contents.is_some() {
+            return;
+        }
+        // Safety: this is called before the host gets its mutable view of the
+        // memory, and `data` doesn't outlive this function.
+        let data = unsafe { self.data() };
+        let before = &data[..self.byte_size];
+        if range.start == 0 && range.end >= data.len() {
+            let mut contents = before.to_vec();
+            for (chunk, bytes) in mem::take(&mut self.chunks) {
+                contents[chunk * CHUNK_SIZE..][..bytes.len()].copy_from_slice(&bytes);
+            }
+            self.contents = Some(contents);
+            return;
+        }
+        let end = range.end.min(data.len());
+        if range.start >= end {
+            return;
+        }
+        for chunk in range.start / CHUNK_SIZE..end.div_ceil(CHUNK_SIZE) {
+            self.chunks.entry(chunk).or_insert_with(|| {
+                let start = (chunk * CHUNK_SIZE).min(before.len());
+                let end = (start + CHUNK_SIZE).min(before.len());
+                before[start..end].to_vec()
+            });
+        }
+    }
+
+    /// Compare the memory's current contents with the checkpoint to find the
+    /// bytes that have been written since.
+    fn writes(&self) -> MemoryWrites {
+        // Safety: only read here, while the host isn't accessing the memory.
+        let data: &[u8] = unsafe { self.data() };
+        let mut writes = Vec::new();
+        let mut diff = |start: usize, before: &[u8]| {
+            let chunk = &data[start..(start + CHUNK_SIZE).min(data.len())];
+            if before.len() >= chunk.len() && before[..chunk.len()] == *chunk {
+                return;
+            }
+            // Bytes beyond the memory's size before the call started out as
+            // zero.
+            let changed = |j: &usize| chunk[*j] != before.get(*j).copied().unwrap_or(0);
+            let Some(first) = (0..chunk.len()).find(changed) else {
+                return;
+            };
+            let last = (0..chunk.len()).rfind(changed).unwrap() + 1;
+            writes.push(MemoryWrite {
+                offset: u64::try_from(start + first).unwrap(),
+                bytes: chunk[first..last].to_vec(),
+            });
+        };
+        match &self.contents {
+            Some(contents) => {
+                for start in (0..data.len()).step_by(CHUNK_SIZE) {
+                    diff(start, contents.get(start..).unwrap_or(&[]));
+                }
+            }
+            None => {
+                for (chunk, before) in &self.chunks {
+                    diff(chunk * CHUNK_SIZE, before);
+                }
+            }
+        }
+        MemoryWrites {
+            byte_size: u64::try_from(data.len()).unwrap(),
+            writes,
+        }
+    }
+
+    /// Undo the host's writes to the saved parts of the memory, and then apply
+    /// `recorded`'s writes.
+    ///
+    /// The memory must already have been grown to `recorded.byte_size`.
+    fn restore(&self, recorded: &MemoryWrites) -> Result<(), String> {
+        // Safety: this runs after the host call has returned, so there are no
+        // other references to the memory's contents.
+        let data = unsafe { self.data() };
+        debug_assert_eq!(u64::try_from(data.len()).ok(), Some(recorded.byte_size));
+        for write in &recorded.writes {
+            let end = usize::try_from(write.offset)
+                .ok()
+                .and_then(|offset| offset.checked_add(write.bytes.len()));
+            if end.is_none_or(|end| end > data.len()) {
+                return Err(format!(
+                    "a memory write at {:#x} beyond the end of the memory",
+                    write.offset
+                ));
+            }
+        }
+        match &self.contents {
+            Some(contents) => {
+                data[..contents.len()].copy_from_slice(contents);
+                data[contents.len()..].fill(0);
+            }
+            None => {
+                for (chunk, before) in &self.chunks {
+                    let start = chunk * CHUNK_SIZE;
+                    let end = (start + CHUNK_SIZE).min(data.len());
+                    let chunk = &mut data[start..end];
+                    chunk[..before.len()].copy_from_slice(before);
+                    chunk[before.len()..].fill(0);
+                }
+            }
+        }
+        for write in &recorded.writes {
+            let offset = usize::try_from(write.offset).unwrap();
+            data[offset..][..write.bytes.len()].copy_from_slice(&write.bytes);
+        }
+        Ok(())
+    }
+}
+
+/// A host call in progress whose memory writes are being recorded or
+/// replayed, see [`StoreOpaque::record_replay_checkpoint`].
+///
+/// Dropping this without passing it back to the store, for example because
+/// the host function failed, stops tracking its memories.
+pub(crate) struct HostCallFrame {
+    trace: HostCallTrace,
+    depth: usize,
+}
+
+impl HostCallFrame {
+    /// Stop tracking this host call's memories and return them.
+    fn finish(self) -> Vec<MemoryCheckpoint> {
+        let mut state = self.trace.state.lock().unwrap();
+        if state.frames.len() <= self.depth {
+            return Vec::new();
+        }
+        state.frames.truncate(self.depth + 1);
+        state.frames.pop().unwrap()
+    }
+}
+
+impl Drop for HostCallFrame {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.trace.state.lock() {
+            state.frames.truncate(self.depth);
+        }
+    }
+}
+
+impl HostCallTrace {
+    /// Create a new, empty trace which records the inputs of the store it's
+    /// attached to.
+    pub fn record() -> HostCallTrace {
+        HostCallTrace::new(false, Vec::new())
+    }
+
+    /// Create a trace which replays a trace previously serialized with
+    /// [`HostCallTrace::serialize`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not a valid trace or if it was recorded
+    /// by a different version of Wasmtime.
+    pub fn replay(bytes: &[u8]) -> Result<HostCallTrace> {
+        let (version, _) = postcard::take_from_bytes::<&str>(bytes)
+            .context("failed to deserialize host call trace")?;
+        ensure!(
+            version == VERSION,
+            "host call trace was recorded by Wasmtime {version}, but this is Wasmtime {VERSION}"
+        );
+        let trace: SerializedTrace =
+            postcard::from_bytes(bytes).context("failed to deserialize host call trace")?;
+        Ok(HostCallTrace::new(true, trace.events))
+    }
+
+    fn new(replaying: bool, events: Vec<TraceEvent>) -> HostCallTrace {
+        HostCallTrace {
+            state: Arc::new(Mutex::new(TraceState {
+                replaying,
+                events,
+                position: 0,
+                divergence: None,
+                frames: Vec::new(),
+            })),
+        }
+    }
+
+    /// Returns whether this trace is replaying, rather than recording.
+    pub fn is_replaying(&self) -> bool {
+        self.state.lock().unwrap().replaying
+    }
+
+    /// Serialize the events in this trace so they can later be replayed with
+    /// [`HostCallTrace::replay`].
+    pub fn serialize(&self) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        postcard::to_allocvec(&SerializedTraceRef {
+            version: VERSION,
+            events: &state.events,
+        })
+        .unwrap()
+    }
+
+    /// Check that replaying this trace has finished successfully.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if execution diverged from the trace, or if it
+    /// finished without consuming every recorded event. Always succeeds for a
+    /// recording trace.
+    pub fn finish(&self) -> Result<()> {
+        let state = self.state.lock().unwrap();
+        if !state.replaying {
+            return Ok(());
+        }
+        if let Some(divergence) = &state.divergence {
+            bail!("{divergence}");
+        }
+        let remaining = state.events.len() - state.position;
+        ensure!(
+            remaining == 0,
+            "execution finished with {remaining} events of the host call trace left to replay"
+        );
+        Ok(())
+    }
+
+    /// Record a value read from the host, or replay a previously recorded one.
+    ///
+    /// When recording, this calls `read` and records its result. When
+    /// replaying, this returns the recorded value without calling `read`,
+    /// unless execution has diverged from the trace, in which case `read` is
+    /// called and the divergence is reported by the next host call.
+    pub fn value(&self, read: impl FnOnce() -> u64) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        if state.replaying {
+            match state.next("a host value") {
+                Some(TraceEvent::Value(value)) => return *value,
+                Some(_) => state.diverge(format_args!("a host value")),
+                None => {}
+            }
+            return read();
+        }
+        let value = read();
+        state.events.push(TraceEvent::Value(value));
+        value
+    }
+
+    /// Same as [`HostCallTrace::value`], but for filling `buf` with bytes read
+    /// from the host.
+    pub fn bytes(&self, buf: &mut [u8], read: impl FnOnce(&mut [u8])) {
+        let mut state = self.state.lock().unwrap();
+        if state.replaying {
+            let len = buf.len();
+            match state.next("host bytes") {
+                Some(TraceEvent::Bytes(bytes)) if bytes.len() == len => {
+                    buf.copy_from_slice(bytes);
+                    return;
+                }
+                Some(_) => state.diverge(format_args!("{len} host bytes")),
+                None => {}
+            }
+            return read(buf);
+        }
+        read(buf);
+        state.events.push(TraceEvent::Bytes(buf.to_vec()));
+    }
+
+    /// Start tracking the host's writes to `memories` during a host call.
+    fn begin(&self, memories: Vec<MemoryCheckpoint>) -> HostCallFrame {
+        let mut state = self.state.lock().unwrap();
+        state.frames.push(memories);
+        HostCallFrame {
+            trace: self.clone(),
+            depth: state.frames.len() - 1,
+        }
+    }
+
+    /// Save `range` of the memory defined by `definition` before the host
+    /// writes to it, if the innermost host call in progress tracks it.
+    fn will_write(&self, definition: NonNull<VMMemoryDefinition>, range: Range<usize>) {
+        let mut state = self.state.lock().unwrap();
+        let memory = state.frames.last_mut().and_then(|memories| {
+            memories
+                .iter_mut()
+                .find(|m| m.definition.as_non_null() == definition)
+        });
+        if let Some(memory) = memory {
+            memory.save(range);
+        }
+    }
+
+    /// Record the results of a core host function and the bytes it wrote
+    /// into `memories`, or replace the results with the recorded ones.
+    ///
+    /// When replaying, returns the recorded writes to apply to `memories`.
+    fn host_call(
+        &self,
+        tys: &[WasmValType],
+        results: &mut [ValRaw],
+        memories: &[MemoryCheckpoint],
+    ) -> Result<Option<Vec<MemoryWrites>>> {
+        let mut state = self.state.lock().unwrap();
+        if !state.replaying {
+            let results = tys
+                .iter()
+                .zip(results.iter())
+                .map(|(ty, val)| match ty {
+                    WasmValType::I32 => RecordedVal::I32(val.get_u32()),
+                    WasmValType::I64 => RecordedVal::I64(val.get_u64()),
+                    WasmValType::F32 => RecordedVal::F32(val.get_f32()),
+                    WasmValType::F64 => RecordedVal::F64(val.get_f64()),
+                    WasmValType::V128 => RecordedVal::V128(val.get_v128()),
+                    WasmValType::Ref(_) => RecordedVal::Ref,
+                })
+                .collect();
+            let memories = memories.iter().map(|m| m.writes()).collect();
+            state
+                .events
+                .push(TraceEvent::HostCall { results, memories });
+            return Ok(None);
+        }
+
+        state.check()?;
+        let (recorded, recorded_memories) = match state.next("a host call") {
+            Some(TraceEvent::HostCall { results, memories }) => (results, memories),
+            Some(_) => {
+                state.diverge(format_args!("a host call"));
+                return state.check().map(|()| None);
+            }
+            None => return state.check().map(|()| None),
+        };
+        let mut matches = recorded.len() == tys.len() && recorded_memories.len() == memories.len();
+        let mut replayed = Vec::with_capacity(tys.len());
+        for (ty, val) in tys.iter().zip(recorded) {
+            replayed.push(match (ty, *val) {
+                (WasmValType::I32, RecordedVal::I32(x)) => Some(ValRaw::u32(x)),
+                (WasmValType::I64, RecordedVal::I64(x)) => Some(ValRaw::u64(x)),
+                (WasmValType::F32, RecordedVal::F32(x)) => Some(ValRaw::f32(x)),
+                (WasmValType::F64, RecordedVal::F64(x)) => Some(ValRaw::f64(x)),
+                (WasmValType::V128, RecordedVal::V128(x)) => Some(ValRaw::v128(x)),
+                (WasmValType::Ref(_), RecordedVal::Ref) => None,
+                _ => {
+                    matches = false;
+                    None
+                }
+            });
+        }
+        if !matches {
+            let tys = tys.iter().map(|ty| ty.to_string()).collect::<Vec<_>>();
+            state.diverge(format_args!(
+                "a host call returning [{}] with {} memories",
+                tys.join(", "),
+                memories.len()
+            ));
+            return state.check().map(|()| None);
+        }
+        let recorded_memories = recorded_memories.clone();
+        for (result, replayed) in results.iter_mut().zip(replayed) {
+            if let Some(replayed) = replayed {
+                *result = replayed;
+            }
+        }
+        Ok(Some(recorded_memories))
+    }
+
+    /// Same as [`HostCallTrace::host_call`], but for a component-model host
+    /// function's flat results.
+    #[cfg(feature = "component-model")]
+    fn component_host_call(
+        &self,
+        results: &mut [ValRaw],
+        memories: &[MemoryCheckpoint],
+    ) -> Result<Option<Vec<MemoryWrites>>> {
+        let mut state = self.state.lock().unwrap();
+        if !state.replaying {
+            // Flat component results are always integers or floats, which are
+            // stored zero-extended to 64 bits.
+            let results = results.iter().map(|val| val.get_u64()).collect();
+            let memories = memories.iter().map(|m| m.writes()).collect();
+            state
+                .events
+                .push(TraceEvent::ComponentHostCall { results, memories });
+            return Ok(None);
+        }
+
+        state.check()?;
+        match state.next("a component host call") {
+            Some(TraceEvent::ComponentHostCall {
+                results: recorded,
+                memories: recorded_memories,
+            }) if recorded.len() == results.len() && recorded_memories.len() == memories.len() => {
+                for (result, recorded) in results.iter_mut().zip(recorded) {
+                    *result = ValRaw::u64(*recorded);
+                }
+                Ok(Some(recorded_memories.clone()))
+            }
+            Some(_) => {
+                state.diverge(format_args!(
+                    "a component host call with {} flat results",
+                    results.len()
+                ));
+                state.check().map(|()| None)
+            }
+            None => state.check().map(|()| None),
+        }
+    }
+
+    /// Report that applying the memory writes of the last replayed host call
+    /// failed.
+    fn memory_divergence(&self, actual: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.diverge(format_args!("{actual}"));
+        state.check()
+    }
+
+    /// Record that an async host function's future suspended, or when
+    /// replaying, consume a suspension recorded at this point if there is one.
+    ///
+    /// Unlike yields, suspensions that weren't recorded aren't a divergence,
+    /// since they depend on how long the host took to complete its future.
+    #[cfg(feature = "async")]
+    pub(crate) fn suspended(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.replaying {
+            state.events.push(TraceEvent::Suspend);
+            return;
+        }
+        state.take_suspend();
+    }
+
+    /// When replaying, consume the remaining suspensions recorded for an async
+    /// host function whose future just completed, and return how many there
+    /// were, so that the caller can suspend that many more times.
+    #[cfg(feature = "async")]
+    pub(crate) fn remaining_suspensions(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let mut n = 0;
+        while state.replaying && state.take_suspend() {
+            n += 1;
+        }
+        n
+    }
+
+    /// Record a yield to the async executor, or check that one was recorded
+    /// at this point.
+    #[cfg(feature = "async")]
+    fn yielded(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state.replaying {
+            state.events.push(TraceEvent::Yield);
+            return Ok(());
+        }
+        state.check()?;
+        match state.next("a yield") {
+            Some(TraceEvent::Yield) => return Ok(()),
+            Some(_) => state.diverge(format_args!("a yield")),
+            None => {}
+        }
+        state.check()
+    }
+}
+
+impl TraceState {
+    /// Return the next event to replay, or record a divergence if the trace
+    /// has been exhausted.
+    ///
+    /// After a divergence, this always returns `None`.
+    fn next(&mut self, expected: &str) -> Option<&TraceEvent> {
+        if self.divergence.is_some() {
+            return None;
+        }
+        if self.position == self.events.len() {
+            self.divergence = Some(format!(
+                "execution diverged from the host call trace: expected {expected} \
+                 after the end of the trace"
+            ));
+            return None;
+        }
+        self.position += 1;
+        Some(&self.events[self.position - 1])
+    }
+
+    /// Consume the next event if it's a suspension of an async host function.
+    #[cfg(feature = "async")]
+    fn take_suspend(&mut self) -> bool {
+        if self.divergence.is_none()
+            && matches!(self.events.get(self.position), Some(TraceEvent::Suspend))
+        {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Record that execution diverged from the trace at the event just
+    /// returned from `next`.
+    fn diverge(&mut self, actual: fmt::Arguments<'_>) {
+        if self.divergence.is_none() {
+            let position = self.position - 1;
+            self.divergence = Some(format!(
+                "execution diverged from the host call trace at event {position}: \
+                 recorded {:?}, but got {actual}",
+                self.events[position],
+            ));
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        match &self.divergence {
+            Some(divergence) => bail!("{divergence}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for HostCallTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("HostCallTrace")
+            .field("replaying", &state.replaying)
+            .field("events", &state.events.len())
+            .field("position", &state.position)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StoreOpaque {
+    /// Start tracking the host's writes to the memories visible to the
+    /// `caller` instance before calling a core host function, if this store
+    /// has a host call trace.
+    ///
+    /// Nothing is copied until the host accesses a memory mutably, see
+    /// [`StoreOpaque::record_replay_write`].
+    #[inline]
+    pub(crate) fn record_replay_checkpoint(&mut self, caller: InstanceId) -> Option<HostCallFrame> {
+        let trace = self.host_call_trace.as_ref()?;
+        let memories = self
+            .instance(caller)
+            .all_memories()
+            .filter(|(_, memory)| !memory.memory.shared)
+            // Safety: the memory belongs to this store and isn't shared.
+            .map(|(_, memory)| unsafe { MemoryCheckpoint::new(memory.definition) })
+            .collect();
+        Some(trace.begin(memories))
+    }
+
+    /// Same as [`StoreOpaque::record_replay_checkpoint`], but for a
+    /// component-model host function lowering its results into `memory`.
+    #[cfg(feature = "component-model")]
+    #[inline]
+    pub(crate) fn record_replay_component_checkpoint(
+        &mut self,
+        memory: *mut VMMemoryDefinition,
+    ) -> Option<HostCallFrame> {
+        let trace = self.host_call_trace.as_ref()?;
+        // Safety: a component's memory is defined by one of its core instances
+        // in this store, and can't be shared.
+        let memories = NonNull::new(memory)
+            .map(|memory| unsafe { MemoryCheckpoint::new(memory) })
+            .into_iter()
+            .collect();
+        Some(trace.begin(memories))
+    }
+
+    /// Note that the host is about to access `range` of the memory defined by
+    /// `definition` mutably, so that its previous contents are saved if a host
+    /// call in progress is being recorded or replayed.
+    ///
+    /// `range` may extend beyond the end of the memory, and `0..usize::MAX`
+    /// means the whole memory.
+    #[inline]
+    pub(crate) fn record_replay_write(
+        &self,
+        definition: NonNull<VMMemoryDefinition>,
+        range: Range<usize>,
+    ) {
+        if let Some(trace) = &self.host_call_trace {
+            trace.will_write(definition, range);
+        }
+    }
+
+    /// Returns this store's host call trace, if any.
+    #[cfg(feature = "async")]
+    pub(crate) fn record_replay_trace(&self) -> Option<HostCallTrace> {
+        self.host_call_trace.clone()
+    }
+
+    /// Record the results of a core host function which just returned to
+    /// WebAssembly, or replace them with recorded ones, if this store has a
+    /// host call trace.
+    ///
+    /// `frame` is the one returned by
+    /// [`StoreOpaque::record_replay_checkpoint`] before the call.
+    #[inline]
+    pub(crate) fn record_replay_host_call(
+        &mut self,
+        frame: Option<HostCallFrame>,
+        tys: &[WasmValType],
+        results: &mut [ValRaw],
+    ) -> Result<()> {
+        let (Some(trace), Some(frame)) = (&self.host_call_trace, frame) else {
+            return Ok(());
+        };
+        let trace = trace.clone();
+        let memories = frame.finish();
+        match trace.host_call(tys, results, &memories)? {
+            Some(recorded) => self.replay_memory_writes(&trace, &memories, &recorded),
+            None => Ok(()),
+        }
+    }
+
+    /// Same as [`StoreOpaque::record_replay_host_call`], but for the flat
+    /// results of a component-model host function.
+    #[cfg(feature = "component-model")]
+    #[inline]
+    pub(crate) fn record_replay_component_host_call(
+        &mut self,
+        frame: Option<HostCallFrame>,
+        results: &mut [ValRaw],
+    ) -> Result<()> {
+        let (Some(trace), Some(frame)) = (&self.host_call_trace, frame) else {
+            return Ok(());
+        };
+        let trace = trace.clone();
+        let memories = frame.finish();
+        match trace.component_host_call(results, &memories)? {
+            Some(recorded) => self.replay_memory_writes(&trace, &memories, &recorded),
+            None => Ok(()),
+        }
+    }
+
+    /// Make the contents of each memory in `memories` what they were after the
+    /// recorded host call, growing them if necessary.
+    fn replay_memory_writes(
+        &mut self,
+        trace: &HostCallTrace,
+        memories: &[MemoryCheckpoint],
+        recorded: &[MemoryWrites],
+    ) -> Result<()> {
+        for (memory, recorded) in memories.iter().zip(recorded) {
+            // Safety: only the length is used.
+            let current = unsafe { memory.data().len() };
+            let current = u64::try_from(current).unwrap();
+            if current > recorded.byte_size {
+                return trace.memory_divergence(&format!(
+                    "a memory grown to {current:#x} bytes rather than {:#x}",
+                    recorded.byte_size
+                ));
+            }
+            if current < recorded.byte_size {
+                if let Err(e) =
+                    self.grow_memory_to(memory.definition.as_non_null(), recorded.byte_size)
+                {
+                    return trace.memory_divergence(&format!(
+                        "a memory that couldn't be grown to {:#x} bytes: {e}",
+                        recorded.byte_size
+                    ));
+                }
+            }
+            if let Err(e) = memory.restore(recorded) {
+                return trace.memory_divergence(&e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Grow the memory defined by `definition` to `byte_size` bytes.
+    fn grow_memory_to(
+        &mut self,
+        definition: NonNull<VMMemoryDefinition>,
+        byte_size: u64,
+    ) -> Result<()> {
+        let (id, index) = self
+            .instances
+            .keys()
+            .find_map(|id| {
+                let instance = self.instance(id);
+                (0..instance.env_module().num_defined_memories())
+                    .map(DefinedMemoryIndex::new)
+                    .find(|index| instance.memory_ptr(*index) == definition)
+                    .map(|index| (id, index))
+            })
+            .ok_or_else(|| anyhow!("memory not found in store"))?;
+        unsafe {
+            let memory = self.instance_mut(id).get_defined_memory(index);
+            let current = u64::try_from(definition.as_ref().current_length()).unwrap();
+            let page_size = (*memory).page_size();
+            ensure!(
+                byte_size % page_size == 0,
+                "size is not a multiple of the page size"
+            );
+            (*memory)
+                .grow(
+                    (byte_size - current) / page_size,
+                    Some(self.traitobj().as_mut()),
+                )?
+                .ok_or_else(|| anyhow!("failed to grow memory"))?;
+            let vmmemory = (*memory).vmmemory();
+            self.instance(id).memory_ptr(index).write(vmmemory);
+        }
+        Ok(())
+    }
+
+    /// Record or check a yield to the async executor, if this store has a host
+    /// call trace.
+    #[cfg(feature = "async")]
+    pub(crate) fn record_replay_yield(&mut self) -> Result<()> {
+        match &self.host_call_trace {
+            Some(trace) => trace.yielded(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,1166 @@
+//! Snapshotting and restoring the state of all instances within a store.
+
+use super::*;
+use crate::hash_map::HashMap;
+use crate::runtime::vm::{TableElement, TableElementType, VMGlobalDefinition};
+use core::ops::Range;
+use core::{ptr, slice};
+use serde_derive::{Deserialize, Serialize};
+use wasmtime_environ::{
+    Collector, DefinedMemoryIndex, FuncIndex, VMSharedTypeIndex, WasmHeapTopType, WasmValType,
+};
+
+/// The Wasmtime version that produced a snapshot. Snapshots may only be
+/// restored by the same version of Wasmtime.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The size of the chunks that linear memories and GC heaps are broken up into
+/// when snapshotting them. See `Segment` for details.
+const CHUNK_SIZE: usize = 4096;
+
+/// A snapshot of the state of every instance within a [`Store`].
+///
+/// Snapshots are created with [`Store::snapshot`] and applied with
+/// [`Store::restore`]. They can be serialized with
+/// [`StoreSnapshot::serialize`] and later deserialized, for example to
+/// warm-start a new process or to migrate a long-running guest to another
+/// host.
+///
+/// A snapshot captures the contents of every linear memory, table, and global
+/// defined within a store, as well as the store's GC heap, if any. It does not
+/// capture the instances themselves: to restore a snapshot, first recreate the
+/// same instances, in the same order, within a fresh store of the same
+/// [`Engine`] and then call [`Store::restore`].
+///
+/// The following cannot be captured, and attempting to snapshot a store that
+/// contains them will return an error:
+///
+/// * Shared memories.
+///
+/// * References to functions that are neither defined by nor imported into an
+///   instance within the store, for example a host function that was placed
+///   directly into a table with [`Table::set`].
+///
+/// * Live `externref`s, since their host data is an arbitrary Rust value.
+///
+/// * Continuation references.
+///
+/// Additionally, GC references held by the host (for example via
+/// [`Rooted`](crate::Rooted)) and component-model state such as resource
+/// tables are not captured.
+#[derive(Serialize, Deserialize)]
+pub struct StoreSnapshot {
+    version: String,
+    instances: Vec<InstanceSnapshot>,
+    host_globals: Vec<GlobalSnapshot>,
+    gc_heap: Option<GcHeapSnapshot>,
+}
+
+/// The state of a single instance within a store.
+#[derive(Serialize, Deserialize)]
+struct InstanceSnapshot {
+    /// Whether this was a dummy instance, backing a host-created memory or
+    /// table, rather than an instantiation of a module.
+    dummy: bool,
+
+    /// The name of this instance's module, if any.
+    module: Option<String>,
+
+    /// The engine-level indices of this instance's module's types.
+    ///
+    /// These are only checked when restoring a GC heap, which refers to types
+    /// by these indices.
+    types: Vec<VMSharedTypeIndex>,
+
+    memories: Vec<MemorySnapshot>,
+    tables: Vec<TableSnapshot>,
+    globals: Vec<GlobalSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MemorySnapshot {
+    page_size_log2: u8,
+    byte_size: u64,
+    segments: Vec<Segment>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TableSnapshot {
+    ty: ValueKind,
+    elements: Vec<ValueSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GlobalSnapshot {
+    ty: ValueKind,
+    value: ValueSnapshot,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GcHeapSnapshot {
+    byte_size: u64,
+    segments: Vec<Segment>,
+
+    /// The collector that produced this heap, which must match the
+    /// restoring engine's.
+    collector_kind: Option<Collector>,
+
+    /// The collector's bookkeeping, see `GcHeap::snapshot`.
+    collector: Vec<u8>,
+
+    /// The contents of the GC heap's `funcref` side table, in ID order.
+    func_refs: Vec<Option<FuncId>>,
+}
+
+/// A non-zero run of bytes at `offset` within a linear memory or GC heap.
+///
+/// Like core dumps, we don't want to save whole memories since they likely
+/// contain large runs of zeroes, so memories are broken up into chunks and the
+/// runs of zeroes at the start and end of each chunk are trimmed.
+#[derive(Serialize, Deserialize)]
+struct Segment {
+    offset: u64,
+    bytes: Vec<u8>,
+}
+
+/// The representation of a value within a table or global.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+enum ValueKind {
+    I32,
+    I64,
+    F32,
+    F64,
+    V128,
+    FuncRef,
+    GcRef,
+    ContRef,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+enum ValueSnapshot {
+    Bits(u128),
+    Func(Option<FuncId>),
+    UninitFunc,
+    GcRef(u32),
+}
+
+/// A function, identified by the index of the store instance it belongs to
+/// and its index within that instance's module.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct FuncId {
+    instance: u32,
+    func: u32,
+}
+
+impl ValueKind {
+    fn new(ty: &WasmValType) -> ValueKind {
+        match ty {
+            WasmValType::I32 => ValueKind::I32,
+            WasmValType::I64 => ValueKind::I64,
+            WasmValType::F32 => ValueKind::F32,
+            WasmValType::F64 => ValueKind::F64,
+            WasmValType::V128 => ValueKind::V128,
+            WasmValType::Ref(r) => match r.heap_type.top() {
+                WasmHeapTopType::Func => ValueKind::FuncRef,
+                WasmHeapTopType::Extern | WasmHeapTopType::Any => ValueKind::GcRef,
+                WasmHeapTopType::Cont => ValueKind::ContRef,
+            },
+        }
+    }
+}
+
+impl StoreSnapshot {
+    /// Serialize this snapshot into a binary blob.
+    ///
+    /// The blob can be turned back into a snapshot with
+    /// [`StoreSnapshot::deserialize`] by the same version of Wasmtime.
+    pub fn serialize(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).unwrap()
+    }
+
+    /// Deserialize a snapshot previously produced by
+    /// [`StoreSnapshot::serialize`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not a valid snapshot or if it was
+    /// produced by a different version of Wasmtime.
+    ///
+    /// # Trust
+    ///
+    /// Snapshots must come from a trusted source, such as a store of the same
+    /// embedder. [`Store::restore`] checks that a snapshot matches the store's
+    /// instances and that everything in it is in bounds, but it can't check
+    /// that the GC heap's contents are consistent: a crafted snapshot can, for
+    /// example, point a table or global at the middle of a GC object. Such a
+    /// snapshot can't make Wasmtime access memory outside of the GC heap, but
+    /// the guest may then misbehave or trap in arbitrary ways.
+    pub fn deserialize(bytes: &[u8]) -> Result<StoreSnapshot> {
+        let (version, _) = postcard::take_from_bytes::<&str>(bytes)
+            .context("failed to deserialize store snapshot")?;
+        ensure!(
+            version == VERSION,
+            "store snapshot was created by Wasmtime {version}, but this is Wasmtime {VERSION}"
+        );
+        postcard::from_bytes(bytes).context("failed to deserialize store snapshot")
+    }
+}
+
+impl fmt::Debug for StoreSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreSnapshot")
+            .field("instances", &self.instances.len())
+            .field("host_globals", &self.host_globals.len())
+            .field("gc_heap", &self.gc_heap.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl StoreOpaque {
+    /// Capture a snapshot of every instance in this store.
+    pub(crate) fn snapshot(&mut self) -> Result<StoreSnapshot> {
+        let funcs = self.snapshot_func_ids();
+
+        let ids = self.instances.keys().collect::<Vec<_>>();
+        let instances = ids
+            .into_iter()
+            .map(|id| self.snapshot_instance(id, &funcs))
+            .collect::<Result<Vec<_>>>()?;
+
+        let host_globals = self
+            .host_globals
+            .values()
+            .map(|global| unsafe {
+                let global = global.get().as_ref();
+                snapshot_global(&global.ty, &global.global, &funcs)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let gc_heap = self.snapshot_gc_heap(&funcs)?;
+
+        Ok(StoreSnapshot {
+            version: VERSION.to_string(),
+            instances,
+            host_globals,
+            gc_heap,
+        })
+    }
+
+    /// Build a map from the address of every `VMFuncRef` that may be stored in
+    /// a table, global, or GC object to the function it represents.
+    fn snapshot_func_ids(&mut self) -> HashMap<usize, FuncId> {
+        let mut funcs = HashMap::new();
+        let ids = self.instances.keys().collect::<Vec<_>>();
+        for id in ids {
+            let module = self.instance(id).env_module().clone();
+            for (index, func) in module.functions.iter() {
+                if !func.is_escaping() {
+                    continue;
+                }
+                if let Some(func_ref) = self.instance_mut(id).get_func_ref(index) {
+                    let func = FuncId {
+                        instance: id.as_u32(),
+                        func: index.as_u32(),
+                    };
+                    funcs.insert(func_ref.as_ptr().addr(), func);
+                }
+            }
+        }
+        funcs
+    }
+
+    /// Get whether the given instance is a dummy instance, the name of its
+    /// module, and the engine-level indices of its module's types.
+    fn snapshot_instance_identity(
+        &self,
+        id: InstanceId,
+    ) -> (bool, Option<String>, Vec<VMSharedTypeIndex>) {
+        let store_id = StoreInstanceId::new(self.id(), id);
+        let Some(module) = self.module_for_instance(store_id) else {
+            return (true, None, Vec::new());
+        };
+        #[cfg(feature = "gc")]
+        let types = module
+            .signatures()
+            .as_module_map()
+            .values()
+            .copied()
+            .collect();
+        #[cfg(not(feature = "gc"))]
+        let types = Vec::new();
+        (false, module.name().map(|s| s.to_string()), types)
+    }
+
+    fn snapshot_instance(
+        &mut self,
+        id: InstanceId,
+        funcs: &HashMap<usize, FuncId>,
+    ) -> Result<InstanceSnapshot> {
+        let (dummy, module, types) = self.snapshot_instance_identity(id);
+        let env_module = self.instance(id).env_module().clone();
+
+        let mut memories = Vec::with_capacity(env_module.num_defined_memories());
+        for index in 0..env_module.num_defined_memories() {
+            let index = DefinedMemoryIndex::new(index);
+            let ty = &env_module.memories[env_module.memory_index(index)];
+            ensure!(!ty.shared, "cannot snapshot a store with shared memories");
+            let definition = self.instance(id).memory(index);
+            let data = unsafe {
+                slice::from_raw_parts(definition.base.as_ptr(), definition.current_length())
+            };
+            memories.push(MemorySnapshot {
+                page_size_log2: ty.page_size_log2,
+                byte_size: u64::try_from(data.len()).unwrap(),
+                segments: segments(data),
+            });
+        }
+
+        let mut tables = Vec::with_capacity(env_module.num_defined_tables());
+        for index in 0..env_module.num_defined_tables() {
+            let index = DefinedTableIndex::new(index);
+            let ty = ValueKind::new(&WasmValType::Ref(
+                env_module.tables[env_module.table_index(index)].ref_type,
+            ));
+            let table = self.instance_mut(id).get_defined_table(index);
+            let elements = unsafe {
+                match (*table).element_type() {
+                    TableElementType::Func => (0..(*table).size())
+                        .map(|i| match (*table).get(None, u64::try_from(i).unwrap()) {
+                            Some(TableElement::FuncRef(f)) => Ok(ValueSnapshot::Func(func_id(
+                                funcs,
+                                f.map_or(ptr::null_mut(), |f| f.as_ptr()),
+                            )?)),
+                            Some(TableElement::UninitFunc) => Ok(ValueSnapshot::UninitFunc),
+                            _ => unreachable!(),
+                        })
+                        .collect::<Result<Vec<_>>>()?,
+                    TableElementType::GcRef => (*table)
+                        .gc_refs_mut()
+                        .iter()
+                        .map(|r| ValueSnapshot::GcRef(r.as_ref().map_or(0, |r| r.as_raw_u32())))
+                        .collect(),
+                    TableElementType::Cont => {
+                        bail!("cannot snapshot a store with tables of continuations")
+                    }
+                }
+            };
+            tables.push(TableSnapshot { ty, elements });
+        }
+
+        let mut globals = Vec::with_capacity(env_module.num_defined_globals());
+        for index in 0..env_module.num_defined_globals() {
+            let index = DefinedGlobalIndex::new(index);
+            let ty = &env_module.globals[env_module.global_index(index)];
+            let definition = self.instance(id).global_ptr(index);
+            globals.push(unsafe { snapshot_global(ty, definition.as_ref(), funcs)? });
+        }
+
+        Ok(InstanceSnapshot {
+            dummy,
+            module,
+            types,
+            memories,
+            tables,
+            globals,
+        })
+    }
+
+    #[cfg(feature = "gc")]
+    fn snapshot_gc_heap(
+        &mut self,
+        funcs: &HashMap<usize, FuncId>,
+    ) -> Result<Option<GcHeapSnapshot>> {
+        let Some(gc_store) = self.optional_gc_store() else {
+            return Ok(None);
+        };
+        ensure!(
+            gc_store.host_data_table.is_empty(),
+            "cannot snapshot a store with live `externref`s; if they are garbage, \
+             collect them with `Store::gc` first"
+        );
+
+        let collector = gc_store.gc_heap.snapshot()?;
+        let heap = gc_store.gc_heap.heap_slice();
+        let func_refs = gc_store
+            .func_ref_table
+            .iter()
+            .enumerate()
+            .map(|(i, (id, f))| {
+                debug_assert_eq!(usize::try_from(id.into_raw()).unwrap(), i);
+                func_id(funcs, f.map_or(ptr::null_mut(), |f| f.as_ptr()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Some(GcHeapSnapshot {
+            byte_size: u64::try_from(heap.len()).unwrap(),
+            segments: segments(heap),
+            collector_kind: self.engine().tunables().collector,
+            collector,
+            func_refs,
+        }))
+    }
+
+    #[cfg(not(feature = "gc"))]
+    fn snapshot_gc_heap(
+        &mut self,
+        _funcs: &HashMap<usize, FuncId>,
+    ) -> Result<Option<GcHeapSnapshot>> {
+        Ok(None)
+    }
+
+    /// Restore a snapshot previously captured with `snapshot` into this store.
+    pub(crate) fn restore(&mut self, snapshot: &StoreSnapshot) -> Result<()> {
+        // Check and resolve everything in the snapshot before modifying
+        // anything, so that a bad snapshot leaves the store as it was.
+        let func_ref_table = self.check_snapshot_compatible(snapshot)?;
+        let resolved = self.resolve_snapshot(snapshot)?;
+
+        // Growing may still fail, for example if a resource limiter denies it,
+        // but that leaves the contents of memories and tables unchanged.
+        if let Some(gc_heap) = &snapshot.gc_heap {
+            self.grow_gc_heap_for_snapshot(gc_heap)?;
+        }
+        let ids = self.instances.keys().collect::<Vec<_>>();
+        for (id, instance) in ids.iter().zip(&snapshot.instances) {
+            for (i, memory) in instance.memories.iter().enumerate() {
+                self.grow_memory_for_snapshot(*id, DefinedMemoryIndex::new(i), memory)?;
+            }
+            for (i, table) in instance.tables.iter().enumerate() {
+                self.grow_table_for_snapshot(*id, DefinedTableIndex::new(i), table)?;
+            }
+        }
+
+        // Restore the GC heap first, so that the GC references we write into
+        // tables and globals below point into the restored heap.
+        if let (Some(gc_heap), Some(func_ref_table)) = (&snapshot.gc_heap, func_ref_table) {
+            self.restore_gc_heap(gc_heap, func_ref_table)?;
+        }
+
+        for ((id, instance), resolved) in ids
+            .into_iter()
+            .zip(&snapshot.instances)
+            .zip(resolved.instances)
+        {
+            for (i, memory) in instance.memories.iter().enumerate() {
+                self.restore_memory(id, DefinedMemoryIndex::new(i), memory);
+            }
+            for (i, elements) in resolved.tables.into_iter().enumerate() {
+                self.restore_table(id, DefinedTableIndex::new(i), elements)?;
+            }
+            for ((i, global), value) in instance.globals.iter().enumerate().zip(resolved.globals) {
+                let definition = self.instance(id).global_ptr(DefinedGlobalIndex::new(i));
+                unsafe { restore_global(global.ty, definition, value) };
+            }
+        }
+
+        for ((i, global), value) in snapshot
+            .host_globals
+            .iter()
+            .enumerate()
+            .zip(resolved.host_globals)
+        {
+            let definition = unsafe {
+                let index = DefinedGlobalIndex::new(i);
+                NonNull::from(&mut self.host_globals[index].get().as_mut().global)
+            };
+            unsafe { restore_global(global.ty, definition, value) };
+        }
+
+        Ok(())
+    }
+
+    /// Resolve the function references within `snapshot` to this store's,
+    /// checking that every table element and global value is valid.
+    fn resolve_snapshot(&mut self, snapshot: &StoreSnapshot) -> Result<ResolvedSnapshot> {
+        let mut instances = Vec::with_capacity(snapshot.instances.len());
+        for instance in &snapshot.instances {
+            let tables = instance
+                .tables
+                .iter()
+                .map(|table| {
+                    table
+                        .elements
+                        .iter()
+                        .map(|e| {
+                            Ok(match (table.ty, self.resolve_value(*e)?) {
+                                (ValueKind::FuncRef, ResolvedValue::Func(f)) => {
+                                    TableElement::FuncRef(f)
+                                }
+                                (ValueKind::FuncRef, ResolvedValue::UninitFunc) => {
+                                    TableElement::UninitFunc
+                                }
+                                (ValueKind::GcRef, ResolvedValue::GcRef(r)) => {
+                                    TableElement::GcRef(r)
+                                }
+                                _ => bail!("invalid table element in snapshot"),
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let globals = instance
+                .globals
+                .iter()
+                .map(|g| self.resolve_global(g))
+                .collect::<Result<Vec<_>>>()?;
+            instances.push(ResolvedInstance { tables, globals });
+        }
+
+        let host_globals = snapshot
+            .host_globals
+            .iter()
+            .map(|g| self.resolve_global(g))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(ResolvedSnapshot {
+            instances,
+            host_globals,
+        })
+    }
+
+    fn resolve_global(&mut self, global: &GlobalSnapshot) -> Result<ResolvedValue> {
+        let value = self.resolve_value(global.value)?;
+        let valid = match (global.ty, &value) {
+            (ValueKind::I32 | ValueKind::F32, ResolvedValue::Bits(bits)) => {
+                u32::try_from(*bits).is_ok()
+            }
+            (ValueKind::I64 | ValueKind::F64, ResolvedValue::Bits(bits)) => {
+                u64::try_from(*bits).is_ok()
+            }
+            (ValueKind::V128, ResolvedValue::Bits(_))
+            | (ValueKind::FuncRef, ResolvedValue::Func(_))
+            | (ValueKind::GcRef, ResolvedValue::GcRef(_)) => true,
+            _ => false,
+        };
+        ensure!(valid, "invalid global value in snapshot");
+        Ok(value)
+    }
+
+    /// Check that `snapshot` was taken from a store with the same shape as
+    /// this one, before we start mutating anything.
+    ///
+    /// Returns the `funcref` side table to install along with the snapshot's
+    /// GC heap, if it has one.
+    fn check_snapshot_compatible(
+        &mut self,
+        snapshot: &StoreSnapshot,
+    ) -> Result<Option<vm::FuncRefTable>> {
+        let func_ref_table = match &snapshot.gc_heap {
+            Some(gc_heap) => Some(self.check_gc_heap_snapshot_compatible(gc_heap)?),
+            None => None,
+        };
+
+        ensure!(
+            snapshot.instances.len() == self.instances.len(),
+            "snapshot has a different number of instances than the store"
+        );
+        ensure!(
+            snapshot.host_globals.len() == self.host_globals.len(),
+            "snapshot has {} host globals but the store has {}",
+            snapshot.host_globals.len(),
+            self.host_globals.len(),
+        );
+        for (i, global) in snapshot.host_globals.iter().enumerate() {
+            let ty = unsafe {
+                &self.host_globals[DefinedGlobalIndex::new(i)]
+                    .get()
+                    .as_ref()
+                    .ty
+            };
+            ensure!(
+                global.ty == ValueKind::new(&ty.wasm_ty),
+                "type mismatch for host global {i}"
+            );
+            check_gc_ref(global.value, snapshot.gc_heap.as_ref())?;
+        }
+
+        let ids = self.instances.keys().collect::<Vec<_>>();
+        for (id, snapshot_instance) in ids.into_iter().zip(&snapshot.instances) {
+            let actual = self.snapshot_instance_shape(id);
+            let expected = snapshot_instance;
+            let name = expected.module.as_deref().unwrap_or("<anonymous>");
+            let i = id.as_u32();
+            ensure!(
+                actual.dummy == expected.dummy && actual.module == expected.module,
+                "instance {i} is not an instance of module `{name}` as in the snapshot"
+            );
+            ensure!(
+                actual.memories.len() == expected.memories.len()
+                    && actual.tables.len() == expected.tables.len()
+                    && actual.globals.len() == expected.globals.len(),
+                "instance {i} of module `{name}` does not define the same memories, \
+                 tables, and globals as in the snapshot"
+            );
+            if snapshot.gc_heap.is_some() {
+                ensure!(
+                    actual.types == expected.types,
+                    "instance {i} of module `{name}` has different engine types than in \
+                     the snapshot; GC heap snapshots may only be restored within the same engine"
+                );
+            }
+
+            for (j, (actual, expected)) in
+                actual.memories.iter().zip(&expected.memories).enumerate()
+            {
+                ensure!(
+                    actual.page_size_log2 == expected.page_size_log2,
+                    "page size mismatch for memory {j} of instance {i}"
+                );
+                ensure!(
+                    actual.byte_size <= expected.byte_size,
+                    "memory {j} of instance {i} is larger than in the snapshot"
+                );
+                ensure!(
+                    expected.byte_size % (1 << expected.page_size_log2) == 0,
+                    "invalid size for memory {j} of instance {i} in the snapshot"
+                );
+                check_segments(&expected.segments, expected.byte_size)?;
+            }
+            for (j, (actual, expected)) in actual.tables.iter().zip(&expected.tables).enumerate() {
+                ensure!(
+                    actual.ty == expected.ty,
+                    "type mismatch for table {j} of instance {i}"
+                );
+                ensure!(
+                    actual.elements.len() <= expected.elements.len(),
+                    "table {j} of instance {i} is larger than in the snapshot"
+                );
+                for element in &expected.elements {
+                    check_gc_ref(*element, snapshot.gc_heap.as_ref())?;
+                }
+            }
+            for (j, (actual, expected)) in actual.globals.iter().zip(&expected.globals).enumerate()
+            {
+                ensure!(
+                    actual.ty == expected.ty,
+                    "type mismatch for global {j} of instance {i}"
+                );
+                check_gc_ref(expected.value, snapshot.gc_heap.as_ref())?;
+            }
+        }
+        Ok(func_ref_table)
+    }
+
+    /// Get the shape of the given instance, with sizes but without contents,
+    /// for compatibility checks.
+    fn snapshot_instance_shape(&self, id: InstanceId) -> InstanceSnapshot {
+        let (dummy, module, types) = self.snapshot_instance_identity(id);
+        let instance = self.instance(id);
+        let env_module = instance.env_module();
+        let memories = (0..env_module.num_defined_memories())
+            .map(|index| {
+                let index = DefinedMemoryIndex::new(index);
+                let ty = &env_module.memories[env_module.memory_index(index)];
+                MemorySnapshot {
+                    page_size_log2: ty.page_size_log2,
+                    byte_size: u64::try_from(instance.memory(index).current_length()).unwrap(),
+                    segments: Vec::new(),
+                }
+            })
+            .collect();
+        let tables = (0..env_module.num_defined_tables())
+            .map(|index| {
+                let index = DefinedTableIndex::new(index);
+                let ty = &env_module.tables[env_module.table_index(index)];
+                TableSnapshot {
+                    ty: ValueKind::new(&WasmValType::Ref(ty.ref_type)),
+                    elements: vec![
+                        ValueSnapshot::UninitFunc;
+                        instance.table(index).current_elements
+                    ],
+                }
+            })
+            .collect();
+        let globals = (0..env_module.num_defined_globals())
+            .map(|index| {
+                let index = DefinedGlobalIndex::new(index);
+                let ty = &env_module.globals[env_module.global_index(index)];
+                GlobalSnapshot {
+                    ty: ValueKind::new(&ty.wasm_ty),
+                    value: ValueSnapshot::Bits(0),
+                }
+            })
+            .collect();
+        InstanceSnapshot {
+            dummy,
+            module,
+            types,
+            memories,
+            tables,
+            globals,
+        }
+    }
+
+    fn grow_memory_for_snapshot(
+        &mut self,
+        id: InstanceId,
+        index: DefinedMemoryIndex,
+        snapshot: &MemorySnapshot,
+    ) -> Result<()> {
+        let byte_size = usize::try_from(snapshot.byte_size)?;
+        let current = self.instance(id).memory(index).current_length();
+        debug_assert!(current <= byte_size);
+        if current < byte_size {
+            let delta_pages = u64::try_from((byte_size - current) >> snapshot.page_size_log2)?;
+            unsafe {
+                let memory = self.instance_mut(id).get_defined_memory(index);
+                (*memory)
+                    .grow(delta_pages, Some(self.traitobj().as_mut()))?
+                    .ok_or_else(|| anyhow!("failed to grow memory to its snapshotted size"))?;
+                let vmmemory = (*memory).vmmemory();
+                self.instance(id).memory_ptr(index).write(vmmemory);
+            }
+        }
+        Ok(())
+    }
+
+    fn restore_memory(
+        &mut self,
+        id: InstanceId,
+        index: DefinedMemoryIndex,
+        snapshot: &MemorySnapshot,
+    ) {
+        let definition = self.instance(id).memory(index);
+        let data = unsafe {
+            slice::from_raw_parts_mut(definition.base.as_ptr(), definition.current_length())
+        };
+        restore_segments(data, &snapshot.segments);
+    }
+
+    fn grow_table_for_snapshot(
+        &mut self,
+        id: InstanceId,
+        index: DefinedTableIndex,
+        snapshot: &TableSnapshot,
+    ) -> Result<()> {
+        let table = self.instance_mut(id).get_defined_table(index);
+        unsafe {
+            let current = (*table).size();
+            debug_assert!(current <= snapshot.elements.len());
+            if current < snapshot.elements.len() {
+                let delta = u64::try_from(snapshot.elements.len() - current)?;
+                let init = match (*table).element_type() {
+                    TableElementType::Func => TableElement::FuncRef(None),
+                    TableElementType::GcRef => TableElement::GcRef(None),
+                    TableElementType::Cont => TableElement::ContRef(None),
+                };
+                (*table)
+                    .grow(delta, init, self.traitobj().as_mut())?
+                    .ok_or_else(|| anyhow!("failed to grow table to its snapshotted size"))?;
+                let vmtable = (*table).vmtable();
+                self.instance(id).table_ptr(index).write(vmtable);
+            }
+        }
+        Ok(())
+    }
+
+    fn restore_table(
+        &mut self,
+        id: InstanceId,
+        index: DefinedTableIndex,
+        elements: Vec<TableElement>,
+    ) -> Result<()> {
+        let table = self.instance_mut(id).get_defined_table(index);
+        unsafe {
+            // NB: these are raw writes, without GC barriers, because the
+            // reference counts and such for these GC references were captured
+            // along with the rest of the GC heap.
+            for (i, element) in elements.into_iter().enumerate() {
+                (*table)
+                    .set(u64::try_from(i).unwrap(), element)
+                    .map_err(|()| anyhow!("failed to restore table element"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the GC heap snapshot, including the collector's bookkeeping, and
+    /// rebuild its `funcref` side table.
+    #[cfg(feature = "gc")]
+    fn check_gc_heap_snapshot_compatible(
+        &mut self,
+        snapshot: &GcHeapSnapshot,
+    ) -> Result<vm::FuncRefTable> {
+        let collector = self.engine().tunables().collector;
+        ensure!(
+            snapshot.collector_kind == collector,
+            "GC heap snapshot was taken with the {} collector, but this engine uses the {} collector",
+            collector_name(snapshot.collector_kind),
+            collector_name(collector),
+        );
+        check_segments(&snapshot.segments, snapshot.byte_size)?;
+
+        let mut func_ref_table = vm::FuncRefTable::default();
+        for (i, func) in snapshot.func_refs.iter().enumerate() {
+            let func_ref = self.resolve_func(*func)?.map(SendSyncPtr::new);
+            // Safety: `func_ref` was resolved to a `VMFuncRef` within one of
+            // this store's instances, which lives as long as the store.
+            let id = unsafe { func_ref_table.intern(func_ref) };
+            ensure!(
+                usize::try_from(id.into_raw()).unwrap() == i,
+                "invalid `funcref` table in GC heap snapshot"
+            );
+        }
+
+        // The heap will be grown to at least the snapshot's size before the
+        // collector's bookkeeping is restored.
+        let gc_store = self.gc_store_mut()?;
+        ensure!(
+            gc_store.host_data_table.is_empty(),
+            "cannot restore a snapshot into a store with live `externref`s"
+        );
+        let heap_len = gc_store
+            .gc_heap
+            .heap_slice()
+            .len()
+            .max(usize::try_from(snapshot.byte_size)?);
+        gc_store
+            .gc_heap
+            .check_snapshot(&snapshot.collector, heap_len)?;
+
+        Ok(func_ref_table)
+    }
+
+    #[cfg(not(feature = "gc"))]
+    fn check_gc_heap_snapshot_compatible(
+        &mut self,
+        _snapshot: &GcHeapSnapshot,
+    ) -> Result<vm::FuncRefTable> {
+        bail!("cannot restore a GC heap snapshot: the `gc` feature was disabled at compile time")
+    }
+
+    #[cfg(feature = "gc")]
+    fn grow_gc_heap_for_snapshot(&mut self, snapshot: &GcHeapSnapshot) -> Result<()> {
+        let byte_size = usize::try_from(snapshot.byte_size)?;
+        let current = self.unwrap_gc_store().gc_heap.heap_slice().len();
+        if current < byte_size {
+            unsafe {
+                if !self.maybe_async_grow_gc_heap(u64::try_from(byte_size - current)?)? {
+                    bail!("failed to grow the GC heap to restore snapshot");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "gc"))]
+    fn grow_gc_heap_for_snapshot(&mut self, _snapshot: &GcHeapSnapshot) -> Result<()> {
+        unreachable!()
+    }
+
+    #[cfg(feature = "gc")]
+    fn restore_gc_heap(
+        &mut self,
+        snapshot: &GcHeapSnapshot,
+        func_ref_table: vm::FuncRefTable,
+    ) -> Result<()> {
+        // The bookkeeping was already checked by `check_snapshot_compatible`,
+        // so this only fails if the heap was somehow not grown to fit it.
+        let gc_store = self.unwrap_gc_store_mut();
+        let heap_len = gc_store.gc_heap.heap_slice().len();
+        gc_store
+            .gc_heap
+            .restore_snapshot(&snapshot.collector, heap_len)?;
+        restore_segments(gc_store.gc_heap.heap_slice_mut(), &snapshot.segments);
+        gc_store.func_ref_table = func_ref_table;
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "gc"))]
+    fn restore_gc_heap(
+        &mut self,
+        _snapshot: &GcHeapSnapshot,
+        _func_ref_table: vm::FuncRefTable,
+    ) -> Result<()> {
+        unreachable!()
+    }
+
+    fn resolve_value(&mut self, value: ValueSnapshot) -> Result<ResolvedValue> {
+        Ok(match value {
+            ValueSnapshot::Bits(bits) => ResolvedValue::Bits(bits),
+            ValueSnapshot::Func(f) => ResolvedValue::Func(self.resolve_func(f)?),
+            ValueSnapshot::UninitFunc => ResolvedValue::UninitFunc,
+            ValueSnapshot::GcRef(raw) => ResolvedValue::GcRef(VMGcRef::from_raw_u32(raw)),
+        })
+    }
+
+    fn resolve_func(&mut self, func: Option<FuncId>) -> Result<Option<NonNull<VMFuncRef>>> {
+        let Some(func) = func else {
+            return Ok(None);
+        };
+        let instance = InstanceId::from_u32(func.instance);
+        ensure!(
+            self.instances.get(instance).is_some(),
+            "invalid function reference in snapshot"
+        );
+        let index = FuncIndex::from_u32(func.func);
+        ensure!(
+            self.instance(instance)
+                .env_module()
+                .functions
+                .get(index)
+                .is_some(),
+            "invalid function reference in snapshot"
+        );
+        let func_ref = self.instance_mut(instance).get_func_ref(index);
+        ensure!(func_ref.is_some(), "invalid function reference in snapshot");
+        Ok(func_ref)
+    }
+}
+
+/// The contents of a `StoreSnapshot` that have been resolved to this store's
+/// function references, ready to be restored.
+struct ResolvedSnapshot {
+    instances: Vec<ResolvedInstance>,
+    host_globals: Vec<ResolvedValue>,
+}
+
+struct ResolvedInstance {
+    tables: Vec<Vec<TableElement>>,
+    globals: Vec<ResolvedValue>,
+}
+
+/// A `ValueSnapshot` that has been resolved to this store's function
+/// references.
+enum ResolvedValue {
+    Bits(u128),
+    Func(Option<NonNull<VMFuncRef>>),
+    UninitFunc,
+    GcRef(Option<VMGcRef>),
+}
+
+/// Check that a snapshotted GC reference is null, an `i31ref`, or points
+/// within the snapshotted GC heap.
+///
+/// This doesn't check that the reference points at the start of an object,
+/// which not every collector can tell, hence snapshots must be trusted. See
+/// `StoreSnapshot::deserialize`.
+fn check_gc_ref(value: ValueSnapshot, gc_heap: Option<&GcHeapSnapshot>) -> Result<()> {
+    let ValueSnapshot::GcRef(raw) = value else {
+        return Ok(());
+    };
+    let Some(gc_ref) = VMGcRef::from_raw_u32(raw) else {
+        return Ok(());
+    };
+    ensure!(
+        gc_ref.is_i31() || gc_heap.is_some_and(|heap| u64::from(raw) < heap.byte_size),
+        "invalid GC reference in snapshot"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "gc")]
+fn collector_name(collector: Option<Collector>) -> String {
+    match collector {
+        Some(collector) => collector.to_string(),
+        None => "disabled".to_string(),
+    }
+}
+
+fn func_id(funcs: &HashMap<usize, FuncId>, func_ref: *mut VMFuncRef) -> Result<Option<FuncId>> {
+    if func_ref.is_null() {
+        return Ok(None);
+    }
+    match funcs.get(&func_ref.addr()) {
+        Some(id) => Ok(Some(*id)),
+        None => bail!(
+            "cannot snapshot a reference to a function that is not defined by or \
+             imported into an instance within the store"
+        ),
+    }
+}
+
+unsafe fn snapshot_global(
+    ty: &wasmtime_environ::Global,
+    definition: &VMGlobalDefinition,
+    funcs: &HashMap<usize, FuncId>,
+) -> Result<GlobalSnapshot> {
+    let ty = ValueKind::new(&ty.wasm_ty);
+    let value = match ty {
+        ValueKind::I32 | ValueKind::F32 => ValueSnapshot::Bits((*definition.as_u32()).into()),
+        ValueKind::I64 | ValueKind::F64 => ValueSnapshot::Bits((*definition.as_u64()).into()),
+        ValueKind::V128 => ValueSnapshot::Bits(definition.get_u128()),
+        ValueKind::FuncRef => ValueSnapshot::Func(func_id(funcs, definition.as_func_ref())?),
+        ValueKind::GcRef => {
+            ValueSnapshot::GcRef(definition.as_gc_ref().map_or(0, |r| r.as_raw_u32()))
+        }
+        ValueKind::ContRef => bail!("cannot snapshot a store with continuation globals"),
+    };
+    Ok(GlobalSnapshot { ty, value })
+}
+
+/// Write `value` into the global at `definition`.
+///
+/// `value` must have been checked to be of type `ty` by `resolve_global`.
+unsafe fn restore_global(
+    ty: ValueKind,
+    mut definition: NonNull<VMGlobalDefinition>,
+    value: ResolvedValue,
+) {
+    let definition = definition.as_mut();
+    match (ty, value) {
+        (ValueKind::I32 | ValueKind::F32, ResolvedValue::Bits(bits)) => {
+            *definition.as_u32_mut() = u32::try_from(bits).unwrap();
+        }
+        (ValueKind::I64 | ValueKind::F64, ResolvedValue::Bits(bits)) => {
+            *definition.as_u64_mut() = u64::try_from(bits).unwrap();
+        }
+        (ValueKind::V128, ResolvedValue::Bits(bits)) => definition.set_u128(bits),
+        (ValueKind::FuncRef, ResolvedValue::Func(f)) => {
+            *definition.as_func_ref_mut() = f.map_or(ptr::null_mut(), |f| f.as_ptr());
+        }
+        // NB: like tables, this is a raw write without GC barriers. See
+        // `restore_table` for details.
+        (ValueKind::GcRef, ResolvedValue::GcRef(r)) => {
+            *definition.as_u32_mut() = r.map_or(0, |r| r.as_raw_u32());
+        }
+        _ => unreachable!("global values are checked by `resolve_global`"),
+    }
+}
+
+/// Break `data` up into its non-zero `Segment`s.
+fn segments(data: &[u8]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for (i, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+        if let Some(start) = chunk.iter().position(|byte| *byte != 0) {
+            let end = chunk.iter().rposition(|byte| *byte != 0).unwrap() + 1;
+            segments.push(Segment {
+                offset: u64::try_from(i * CHUNK_SIZE + start).unwrap(),
+                bytes: chunk[start..end].to_vec(),
+            });
+        }
+    }
+    segments
+}
+
+/// Check that the given `Segment`s are in order, don't overlap, and fit within
+/// `byte_size` bytes.
+fn check_segments(segments: &[Segment], byte_size: u64) -> Result<()> {
+    let mut prev_end = 0;
+    for segment in segments {
+        let end = u64::try_from(segment.bytes.len())
+            .ok()
+            .and_then(|len| segment.offset.checked_add(len));
+        ensure!(
+            segment.offset >= prev_end && end.is_some_and(|end| end <= byte_size),
+            "invalid memory segment in snapshot"
+        );
+        prev_end = end.unwrap();
+    }
+    Ok(())
+}
+
+/// Overwrite `data` with the given `Segment`s, zeroing everything else.
+///
+/// The segments must have been checked with `check_segments`.
+fn restore_segments(data: &mut [u8], segments: &[Segment]) {
+    let mut prev_end = 0;
+    for segment in segments {
+        let start = usize::try_from(segment.offset).unwrap();
+        zero(data, prev_end..start);
+        data[start..][..segment.bytes.len()].copy_from_slice(&segment.bytes);
+        prev_end = start + segment.bytes.len();
+    }
+    zero(data, prev_end..data.len());
+}
+
+/// Zero `data[range]`, without writing to the chunks of it that are zero
+/// already.
+///
+/// Large memories are mostly untouched pages, which reading doesn't commit but
+/// writing would.
+fn zero(data: &mut [u8], range: Range<usize>) {
+    let mut start = range.start;
+    while start < range.end {
+        let end = (start / CHUNK_SIZE + 1) * CHUNK_SIZE;
+        let chunk = &mut data[start..end.min(range.end)];
+        if chunk.iter().any(|byte| *byte != 0) {
+            chunk.fill(0);
+        }
+        start = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn rejected_snapshot_leaves_memory_unchanged() -> Result<()> {
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"
+                (module
+                    (memory (export "memory") 1)
+                    (global (export "g") (mut i32) (i32.const 0))
+                    (func (export "grow")
+                        (drop (memory.grow (i32.const 1)))
+                        (i32.store (i32.const 65540) (i32.const 0x1234))
+                    )
+                )
+            "#,
+        )?;
+
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let grow = instance.get_typed_func::<(), ()>(&mut store, "grow")?;
+        grow.call(&mut store, ())?;
+        let snapshot = store.snapshot()?;
+
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        memory.write(&mut store, 0, b"hello")?;
+
+        // A segment that extends past the end of the memory.
+        let mut bad = StoreSnapshot::deserialize(&snapshot.serialize())?;
+        let i = bad.instances.iter().position(|i| !i.dummy).unwrap();
+        bad.instances[i].memories[0].segments[0].offset = 2 * 65536 - 1;
+        assert!(store.restore(&bad).is_err());
+        assert_eq!(memory.size(&store), 1);
+        assert_eq!(&memory.data(&store)[..5], b"hello");
+
+        // An `i32` global with a value that doesn't fit.
+        let mut bad = StoreSnapshot::deserialize(&snapshot.serialize())?;
+        bad.instances[i].globals[0].value = super::ValueSnapshot::Bits(1 << 40);
+        assert!(store.restore(&bad).is_err());
+        assert_eq!(memory.size(&store), 1);
+        assert_eq!(&memory.data(&store)[..5], b"hello");
+
+        store.restore(&snapshot)?;
+        assert_eq!(memory.size(&store), 2);
+        assert_eq!(&memory.data(&store)[..5], [0; 5]);
+        Ok(())
+    }
+
+    #[test]
+    fn restore_segments_zeroes_the_rest() -> Result<()> {
+        use super::{CHUNK_SIZE, Segment, check_segments, restore_segments, segments};
+
+        let mut expected = vec![0; 3 * CHUNK_SIZE];
+        expected[10..20].fill(1);
+        expected[CHUNK_SIZE + 5] = 2;
+        let saved = segments(&expected);
+
+        let mut data = vec![0xff; 3 * CHUNK_SIZE];
+        check_segments(&saved, 3 * CHUNK_SIZE as u64)?;
+        restore_segments(&mut data, &saved);
+        assert_eq!(data, expected);
+
+        // Segments must be in order and not overlap.
+        let overlapping = [
+            Segment {
+                offset: 10,
+                bytes: vec![1; 10],
+            },
+            Segment {
+                offset: 15,
+                bytes: vec![1],
+            },
+        ];
+        assert!(check_segments(&overlapping, 3 * CHUNK_SIZE as u64).is_err());
+        Ok(())
+    }
+}
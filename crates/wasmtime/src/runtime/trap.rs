@@ -4,7 +4,9 @@ use crate::prelude::*;
 use crate::store::StoreOpaque;
 use crate::{AsContext, Module};
 use core::fmt;
-use wasmtime_environ::{FilePos, demangle_function_name, demangle_function_name_or_index};
+use wasmtime_environ::{
+    DefinedFuncIndex, FilePos, demangle_function_name, demangle_function_name_or_index,
+};
 
 /// Representation of a WebAssembly trap and what caused it to occur.
 ///
@@ -440,9 +442,6 @@ impl FrameInfo {
             compiled_module.code_memory().address_map_data(),
             text_offset,
         );
-        let index = compiled_module.module().func_index(index);
-        let func_index = index.as_u32();
-        let func_name = compiled_module.func_name(index).map(|s| s.to_string());
 
         // In debug mode for now assert that we found a mapping for `pc` within
         // the function, because otherwise something is buggy along the way and
@@ -456,6 +455,50 @@ impl FrameInfo {
             "failed to find instruction for {text_offset:#x}"
         );
 
+        Some(FrameInfo::from_parts(module, index, func_start, instr))
+    }
+
+    /// Fetches frame information about a program counter `pc` within `func`,
+    /// the defined function `index` of `module` which was compiled at runtime
+    /// with tiered compilation.
+    ///
+    /// Returns `None` if `pc` isn't within the function itself.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub(crate) fn new_lazy(
+        module: Module,
+        index: DefinedFuncIndex,
+        func: &crate::module::LazyFunction,
+        pc: usize,
+    ) -> Option<FrameInfo> {
+        let text_offset = func.text_offset(pc);
+        if !func.contains(text_offset) {
+            return None;
+        }
+        let address_map = func.code_memory().address_map_data();
+        let instr = wasmtime_environ::lookup_file_pos(address_map, text_offset);
+        debug_assert!(
+            instr.is_some() || address_map.is_empty(),
+            "failed to find instruction for {text_offset:#x}"
+        );
+        Some(FrameInfo::from_parts(
+            module,
+            index,
+            func.start_srcloc(),
+            instr,
+        ))
+    }
+
+    fn from_parts(
+        module: Module,
+        index: DefinedFuncIndex,
+        func_start: FilePos,
+        instr: Option<FilePos>,
+    ) -> FrameInfo {
+        let compiled_module = module.compiled_module();
+        let index = compiled_module.module().func_index(index);
+        let func_index = index.as_u32();
+        let func_name = compiled_module.func_name(index).map(|s| s.to_string());
+
         // Use our wasm-relative pc to symbolize this frame. If there's a
         // symbolication context (dwarf debug info) available then we can try to
         // look this up there.
@@ -492,14 +535,14 @@ impl FrameInfo {
             }
         }
 
-        Some(FrameInfo {
+        FrameInfo {
             module,
             func_index,
             func_name,
             instr,
             func_start,
             symbols,
-        })
+        }
     }
 
     /// Returns the WebAssembly function index for this frame.
@@ -100,12 +100,13 @@ pub use crate::runtime::vm::instance::{
 };
 #[cfg(feature = "pooling-allocator")]
 pub use crate::runtime::vm::instance::{
-    InstanceLimits, PoolConcurrencyLimitError, PoolingInstanceAllocator,
-    PoolingInstanceAllocatorConfig,
+    InstanceLimits, PoolAllocationFailures, PoolConcurrencyLimitError, PoolSlotStats,
+    PoolingAllocationStats, PoolingInstanceAllocator, PoolingInstanceAllocatorConfig,
 };
 pub use crate::runtime::vm::interpreter::*;
 pub use crate::runtime::vm::memory::{
-    Memory, MemoryBase, RuntimeLinearMemory, RuntimeMemoryCreator, SharedMemory,
+    DefaultMemoryCreator, Memory, MemoryBase, RuntimeLinearMemory, RuntimeMemoryCreator,
+    SharedMemory,
 };
 pub use crate::runtime::vm::mmap_vec::MmapVec;
 pub use crate::runtime::vm::provenance::*;
@@ -115,7 +116,14 @@ pub use crate::runtime::vm::store_box::*;
 pub use crate::runtime::vm::sys::mmap::open_file_for_mmap;
 #[cfg(has_host_compiler_backend)]
 pub use crate::runtime::vm::sys::unwind::UnwindRegistration;
-pub use crate::runtime::vm::table::{Table, TableElement};
+#[cfg(all(
+    any(feature = "cranelift", feature = "winch"),
+    feature = "std",
+    target_os = "linux",
+    not(miri)
+))]
+pub use crate::runtime::vm::sys::vm::create_sealed_memfd;
+pub use crate::runtime::vm::table::{Table, TableElement, TableElementType};
 pub use crate::runtime::vm::traphandlers::*;
 #[cfg(feature = "component-model")]
 pub use crate::runtime::vm::vmcontext::VMTableDefinition;
@@ -148,9 +156,9 @@ mod async_yield;
 #[cfg(feature = "async")]
 pub use crate::runtime::vm::async_yield::*;
 
-#[cfg(feature = "gc-null")]
+#[cfg(any(feature = "gc-null", feature = "gc-copying"))]
 mod send_sync_unsafe_cell;
-#[cfg(feature = "gc-null")]
+#[cfg(any(feature = "gc-null", feature = "gc-copying"))]
 pub use send_sync_unsafe_cell::SendSyncUnsafeCell;
 
 cfg_if::cfg_if! {
@@ -220,6 +228,29 @@ pub unsafe trait VMStore: 'static {
     /// Note that this is not invoked if `table_growing` returns an error.
     fn table_grow_failed(&mut self, error: Error) -> Result<()>;
 
+    /// Callback invoked to allow the store's resource limiter to reject a GC
+    /// heap grow operation.
+    #[cfg(feature = "gc")]
+    fn gc_heap_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> Result<bool, Error>;
+
+    /// Callback invoked to notify the store's resource limiter that a GC heap
+    /// grow operation has failed.
+    ///
+    /// Note that this is not invoked if `gc_heap_growing` returns an error.
+    #[cfg(feature = "gc")]
+    fn gc_heap_grow_failed(&mut self, error: Error) -> Result<()>;
+
+    /// Callback invoked to notify the store's resource limiter that a GC
+    /// allocation failed even after collecting garbage and attempting to grow
+    /// the GC heap. Returns whether to collect, grow, and retry once more.
+    #[cfg(feature = "gc")]
+    fn gc_heap_out_of_memory(&mut self, bytes_needed: u64) -> Result<bool, Error>;
+
     /// Callback invoked whenever fuel runs out by a wasm instance. If an error
     /// is returned that's raised as a trap. Otherwise wasm execution will
     /// continue as normal.
@@ -231,6 +262,11 @@ pub unsafe trait VMStore: 'static {
     #[cfg(target_has_atomic = "64")]
     fn new_epoch(&mut self) -> Result<u64, Error>;
 
+    /// Callback invoked before each access to linear memory when memory
+    /// access tracing is enabled. If an error is returned that's raised as a
+    /// trap.
+    fn memory_access(&mut self, access: crate::MemoryAccess) -> Result<(), Error>;
+
     /// Callback invoked whenever an instance needs to grow-or-collect the GC
     /// heap.
     ///
@@ -7,6 +7,8 @@
 
 use super::sys::DecommitBehavior;
 use crate::prelude::*;
+#[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+use crate::runtime::vm::instance::UffdSlot;
 use crate::runtime::vm::sys::vm::{self, MemoryImageSource};
 use crate::runtime::vm::{HostAlignedByteCount, MmapOffset, MmapVec, host_page_size};
 use alloc::sync::Arc;
@@ -68,6 +70,7 @@ pub struct MemoryImage {
 
 impl MemoryImage {
     fn new(
+        engine: &crate::Engine,
         page_size: u32,
         linear_memory_offset: HostAlignedByteCount,
         data: &[u8],
@@ -116,6 +119,23 @@ impl MemoryImage {
             }
         }
 
+        // If a directory for images is configured then the image is placed in
+        // a file there, named after its contents, so that other processes
+        // configured with the same directory share its page cache pages.
+        #[cfg(all(feature = "memory-image-dir", unix))]
+        if let Some(dir) = &engine.config().memory_image_dir {
+            if let Some(source) = MemoryImageSource::from_data_in_dir(dir, data)? {
+                return Ok(Some(MemoryImage {
+                    source,
+                    source_offset: 0,
+                    linear_memory_offset,
+                    len,
+                }));
+            }
+        }
+        #[cfg(not(all(feature = "memory-image-dir", unix)))]
+        let _ = engine;
+
         // If `mmap` doesn't come from a file then platform-specific mechanisms
         // may be used to place the data in a form that's amenable to an mmap.
         if let Some(source) = MemoryImageSource::from_data(data)? {
@@ -139,6 +159,29 @@ impl MemoryImage {
         )
     }
 
+    /// Read the page of this image that starts `offset` bytes into linear
+    /// memory into `page`, whose length is the host page size.
+    ///
+    /// Returns `false`, without reading anything, if that page isn't part of
+    /// this image, in which case it is all zeros.
+    #[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+    pub(crate) fn read_page_at(
+        &self,
+        offset: HostAlignedByteCount,
+        page: &mut [u8],
+    ) -> Result<bool> {
+        let image_end = self.linear_memory_offset.checked_add(self.len)?;
+        if offset < self.linear_memory_offset || offset >= image_end {
+            return Ok(false);
+        }
+        let offset_in_image = offset.checked_sub(self.linear_memory_offset)?;
+        let source_offset = self.source_offset + u64::try_from(offset_in_image.byte_count())?;
+        self.source
+            .read_exact_at(page, source_offset)
+            .context("failed to read memory image")?;
+        Ok(true)
+    }
+
     unsafe fn remap_as_zeros_at(&self, base: *mut u8) -> Result<()> {
         self.source.remap_as_zeros_at(
             base.add(self.linear_memory_offset.byte_count()),
@@ -153,6 +196,7 @@ impl ModuleMemoryImages {
     /// passed in as part of a `InstanceAllocationRequest` to speed up
     /// instantiation and execution by using copy-on-write-backed memories.
     pub fn new(
+        engine: &crate::Engine,
         module: &Module,
         wasm_data: &[u8],
         mmap: Option<&MmapVec>,
@@ -211,7 +255,7 @@ impl ModuleMemoryImages {
             };
             let offset = HostAlignedByteCount::new(offset_usize)
                 .expect("memory init offset is a multiple of the host page size");
-            let image = match MemoryImage::new(page_size, offset, data, mmap)? {
+            let image = match MemoryImage::new(engine, page_size, offset, data, mmap)? {
                 Some(image) => image,
                 None => return Ok(None),
             };
@@ -323,6 +367,12 @@ pub struct MemoryImageSlot {
     /// specific to this slot) in place when it is dropped. Default
     /// on, unless the caller knows what they are doing.
     clear_on_drop: bool,
+
+    /// If set, `image` is never mapped into this slot. Instead the slot is
+    /// registered with a userfaultfd whose handler fills in missing pages from
+    /// `image` on demand, and this is used to tell it which image that is.
+    #[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+    uffd: Option<UffdSlot>,
 }
 
 impl MemoryImageSlot {
@@ -346,9 +396,25 @@ impl MemoryImageSlot {
             image: None,
             dirty: false,
             clear_on_drop: true,
+            #[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+            uffd: None,
         }
     }
 
+    /// Like `create`, but for a slot whose missing pages are filled in by the
+    /// handler of the userfaultfd that `uffd` belongs to.
+    #[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+    pub(crate) fn create_uffd(
+        base: MmapOffset,
+        accessible: HostAlignedByteCount,
+        static_size: usize,
+        uffd: UffdSlot,
+    ) -> Self {
+        let mut slot = MemoryImageSlot::create(base, accessible, static_size);
+        slot.uffd = Some(uffd);
+        slot
+    }
+
     /// Inform the MemoryImageSlot that it should *not* clear the underlying
     /// address space when dropped. This should be used only when the
     /// caller will clear or reuse the address space in some other
@@ -466,13 +532,24 @@ impl MemoryImageSlot {
                 );
                 if !image.len.is_zero() {
                     unsafe {
-                        image.map_at(&self.base)?;
+                        self.place_image(image)?;
                     }
                 }
             }
             self.image = maybe_image.cloned();
         }
 
+        // Replacing mappings above, such as when removing a previous image,
+        // loses any huge page advice for them so it's given again for the
+        // whole slot.
+        if tunables.memory_huge_pages {
+            if let Ok(static_size) = HostAlignedByteCount::new(self.static_size) {
+                self.base
+                    .mmap()
+                    .advise_huge_pages(self.base.offset(), static_size)?;
+            }
+        }
+
         // Flag ourselves as `dirty` which means that the next operation on this
         // slot is required to be `clear_and_remain_ready`.
         self.dirty = true;
@@ -480,8 +557,42 @@ impl MemoryImageSlot {
         Ok(())
     }
 
+    /// Place `image` into this slot's linear memory.
+    unsafe fn place_image(&self, image: &Arc<MemoryImage>) -> Result<()> {
+        #[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+        if let Some(uffd) = &self.uffd {
+            // The image's pages are filled in lazily, but only once they're
+            // missing, so discard anything that's left over in its range.
+            uffd.set_image(Some(image));
+            vm::decommit_pages(
+                self.base
+                    .as_mut_ptr()
+                    .add(image.linear_memory_offset.byte_count()),
+                image.len.byte_count(),
+            )?;
+            return Ok(());
+        }
+
+        image.map_at(&self.base)
+    }
+
     pub(crate) fn remove_image(&mut self) -> Result<()> {
         if let Some(image) = &self.image {
+            #[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+            if let Some(uffd) = &self.uffd {
+                uffd.set_image(None);
+                unsafe {
+                    vm::decommit_pages(
+                        self.base
+                            .as_mut_ptr()
+                            .add(image.linear_memory_offset.byte_count()),
+                        image.len.byte_count(),
+                    )?;
+                }
+                self.image = None;
+                return Ok(());
+            }
+
             unsafe {
                 image.remap_as_zeros_at(self.base.as_mut_ptr())?;
             }
@@ -736,6 +847,20 @@ impl MemoryImageSlot {
             return Ok(());
         }
 
+        #[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+        if let Some(uffd) = &self.uffd {
+            // Replacing the mapping would unregister it from the userfaultfd,
+            // so discard its contents and make it inaccessible instead.
+            uffd.set_image(None);
+            unsafe {
+                vm::decommit_pages(self.base.as_mut_ptr(), self.static_size)?;
+                vm::hide_existing_mapping(self.base.as_mut_ptr(), self.static_size)?;
+            }
+            self.image = None;
+            self.accessible = HostAlignedByteCount::ZERO;
+            return Ok(());
+        }
+
         unsafe {
             vm::erase_existing_mapping(self.base.as_mut_ptr(), self.static_size)?;
         }
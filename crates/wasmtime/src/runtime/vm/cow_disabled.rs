@@ -24,6 +24,7 @@ pub enum MemoryImage {}
 
 impl ModuleMemoryImages {
     pub fn new(
+        _engine: &crate::Engine,
         _module: &Module,
         _wasm_data: &[u8],
         _mmap: Option<&MmapVec>,
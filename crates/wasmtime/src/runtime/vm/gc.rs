@@ -80,6 +80,11 @@ impl GcStore {
         collect_async(collection).await;
     }
 
+    /// Finish any work that incremental collection deferred to later steps.
+    pub fn finish_deferred_work(&mut self) {
+        self.gc_heap.finish_deferred_work(&mut self.host_data_table);
+    }
+
     /// Get the kind of the given GC reference.
     pub fn kind(&self, gc_ref: &VMGcRef) -> VMGcKind {
         debug_assert!(!gc_ref.is_i31());
@@ -12,6 +12,11 @@ pub use data::*;
 pub use externref::*;
 pub use structref::*;
 
+#[cfg(feature = "gc-copying")]
+mod copying;
+#[cfg(feature = "gc-copying")]
+pub use copying::*;
+
 #[cfg(feature = "gc-drc")]
 mod drc;
 #[cfg(feature = "gc-drc")]
@@ -0,0 +1,1011 @@
+//! The semi-space copying collector.
+//!
+//! The GC heap is split into two equally-sized semi-spaces. New objects are
+//! bump allocated out of the active semi-space, both by the host and inline by
+//! compiled Wasm code. When the active semi-space fills up, we collect garbage
+//! by evacuating every object that is reachable from the GC roots into the
+//! other semi-space, in address order, updating references to point at the
+//! objects' new locations, and then that other semi-space becomes the active
+//! one. Everything left behind is garbage, so reclaiming it takes no work at
+//! all; the cost of a collection is proportional to the amount of live data,
+//! not the amount of garbage. This makes allocation-heavy workloads that create
+//! many short-lived objects cheap.
+//!
+//! This collector does not require any GC barriers. Instead, because it moves
+//! objects, it requires that every GC reference on the Wasm stack is included
+//! in stack maps and reloaded from its stack slot after each safepoint.
+//!
+//! Evacuated objects are overwritten with a forwarding pointer: the first word
+//! of the object's header (which is never zero for a valid header, since it
+//! contains the object's `VMGcKind`) is zeroed and the second word is replaced
+//! with the object's new heap index.
+//!
+//! When the GC heap grows, the semi-spaces are resized to split the new heap in
+//! half. Collections always evacuate into whichever region of the heap does
+//! not overlap the active semi-space's allocated objects, so this is safe even
+//! when growth moves the boundary between the two semi-spaces.
+
+use super::*;
+use crate::hash_map::HashMap;
+use crate::hash_set::HashSet;
+use crate::{
+    Engine, EngineWeak,
+    prelude::*,
+    vm::{
+        ExternRefHostDataId, ExternRefHostDataTable, GarbageCollection, GcHeap, GcHeapObject,
+        GcProgress, GcRootsIter, GcRuntime, SendSyncUnsafeCell, TypedGcRef, VMGcHeader, VMGcRef,
+        VMMemoryDefinition,
+    },
+};
+use core::ptr::NonNull;
+use core::{
+    alloc::Layout,
+    any::Any,
+    mem,
+    num::{NonZeroU32, NonZeroUsize},
+};
+use serde_derive::{Deserialize, Serialize};
+use wasmtime_environ::copying::{ARRAY_LENGTH_OFFSET, CopyingTypeLayouts};
+use wasmtime_environ::{
+    GcArrayLayout, GcLayout, GcStructLayout, GcTypeLayouts, VMGcKind, VMSharedTypeIndex,
+};
+
+#[allow(clippy::cast_possible_truncation)]
+const GC_REF_ARRAY_ELEMS_OFFSET: u32 = ARRAY_LENGTH_OFFSET + (mem::size_of::<u32>() as u32);
+
+/// The lowest heap index that we allocate objects at.
+///
+/// Heap index zero is reserved for null references, and starting at the
+/// header's alignment keeps the start of the lower semi-space aligned.
+#[allow(clippy::cast_possible_truncation)]
+const MIN_HEAP_INDEX: u32 = mem::align_of::<VMGcHeader>() as u32;
+
+/// The maximum alignment of any GC object, which is the alignment of `v128`
+/// fields.
+const MAX_OBJECT_ALIGN: u32 = 16;
+
+/// The byte pattern that evacuated semi-spaces are filled with in debug builds,
+/// to help catch uses of stale GC references.
+const POISON: u8 = 0xef;
+
+/// The semi-space copying collector.
+#[derive(Default)]
+pub struct CopyingCollector {
+    layouts: CopyingTypeLayouts,
+}
+
+unsafe impl GcRuntime for CopyingCollector {
+    fn layouts(&self) -> &dyn GcTypeLayouts {
+        &self.layouts
+    }
+
+    fn new_gc_heap(&self, engine: &Engine) -> Result<Box<dyn GcHeap>> {
+        let heap = CopyingHeap::new(engine)?;
+        Ok(Box::new(heap) as _)
+    }
+}
+
+/// How to trace a GC object.
+enum TraceInfo {
+    /// How to trace an array.
+    Array {
+        /// The array type's alignment.
+        align: u32,
+
+        /// Whether this array type's elements are GC references, and need
+        /// tracing.
+        gc_ref_elems: bool,
+    },
+
+    /// How to trace a struct.
+    Struct {
+        /// The struct type's alignment.
+        align: u32,
+
+        /// The offsets of each GC reference field that needs tracing in
+        /// instances of this struct type.
+        gc_ref_offsets: Box<[u32]>,
+    },
+}
+
+impl TraceInfo {
+    fn align(&self) -> u32 {
+        match self {
+            TraceInfo::Array { align, .. } | TraceInfo::Struct { align, .. } => *align,
+        }
+    }
+}
+
+/// The region of the active semi-space that objects are bump allocated from.
+///
+/// This is mutated directly by compiled Wasm code, and its layout must match
+/// the offsets in `wasmtime_environ::copying`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct AllocArea {
+    /// Bump-allocation finger indexing into the GC heap.
+    next: u32,
+
+    /// The end of the active semi-space.
+    end: u32,
+}
+
+/// The bookkeeping captured by a copying heap snapshot, in addition to the raw
+/// bytes of the heap itself.
+#[derive(Serialize, Deserialize)]
+struct CopyingHeapSnapshot {
+    /// The start of the active semi-space.
+    space_start: u32,
+
+    /// The active semi-space's allocation area.
+    alloc_area: AllocArea,
+
+    /// The raw GC refs of every `externref` in the heap.
+    externrefs: Vec<u32>,
+}
+
+/// A GC heap for the copying collector.
+struct CopyingHeap {
+    engine: EngineWeak,
+
+    /// The active semi-space's allocation area.
+    ///
+    /// NB: this is an `UnsafeCell` because it is written to by compiled Wasm
+    /// code, and it is boxed so that the pointer we give to Wasm remains
+    /// stable.
+    alloc_area: Box<SendSyncUnsafeCell<AllocArea>>,
+
+    /// The start of the active semi-space.
+    space_start: u32,
+
+    /// For every type that we have encountered in this heap, how do we trace
+    /// it?
+    ///
+    /// Compiled Wasm code allocates objects inline, without telling us about
+    /// their types, so this is filled in lazily during collection.
+    trace_infos: HashMap<VMSharedTypeIndex, TraceInfo>,
+
+    /// Every `externref` that has been allocated in this heap and not yet
+    /// found to be unreachable, so that we can deallocate their host data
+    /// when they become garbage.
+    externrefs: Vec<VMGcRef>,
+
+    /// The number of active no-gc scopes at the current moment.
+    no_gc_count: usize,
+
+    /// The actual storage for the GC heap.
+    memory: Option<crate::vm::Memory>,
+}
+
+/// The common header for all arrays in the copying collector.
+#[repr(C)]
+struct VMCopyingArrayHeader {
+    header: VMGcHeader,
+    length: u32,
+}
+
+unsafe impl GcHeapObject for VMCopyingArrayHeader {
+    #[inline]
+    fn is(header: &VMGcHeader) -> bool {
+        header.kind() == VMGcKind::ArrayRef
+    }
+}
+
+impl VMCopyingArrayHeader {
+    fn typed_ref<'a>(
+        gc_heap: &CopyingHeap,
+        array: &'a VMArrayRef,
+    ) -> &'a TypedGcRef<VMCopyingArrayHeader> {
+        let gc_ref = array.as_gc_ref();
+        debug_assert!(gc_ref.is_typed::<VMCopyingArrayHeader>(gc_heap));
+        gc_ref.as_typed_unchecked()
+    }
+}
+
+/// The representation of an `externref` in the copying collector.
+#[repr(C)]
+struct VMCopyingExternRef {
+    header: VMGcHeader,
+    host_data: ExternRefHostDataId,
+}
+
+unsafe impl GcHeapObject for VMCopyingExternRef {
+    #[inline]
+    fn is(header: &VMGcHeader) -> bool {
+        header.kind() == VMGcKind::ExternRef
+    }
+}
+
+impl VMCopyingExternRef {
+    /// Convert a generic `externref` to a typed reference to our concrete
+    /// `externref` type.
+    fn typed_ref<'a>(
+        gc_heap: &CopyingHeap,
+        externref: &'a VMExternRef,
+    ) -> &'a TypedGcRef<VMCopyingExternRef> {
+        let gc_ref = externref.as_gc_ref();
+        debug_assert!(gc_ref.is_typed::<VMCopyingExternRef>(gc_heap));
+        gc_ref.as_typed_unchecked()
+    }
+}
+
+impl CopyingHeap {
+    /// Construct a new, default heap for the copying collector.
+    fn new(engine: &Engine) -> Result<Self> {
+        log::trace!("allocating new copying heap");
+        Ok(Self {
+            engine: engine.weak(),
+            alloc_area: Box::new(SendSyncUnsafeCell::new(AllocArea {
+                next: MIN_HEAP_INDEX,
+                end: MIN_HEAP_INDEX,
+            })),
+            space_start: MIN_HEAP_INDEX,
+            trace_infos: HashMap::default(),
+            externrefs: Vec::new(),
+            no_gc_count: 0,
+            memory: None,
+        })
+    }
+
+    fn engine(&self) -> Engine {
+        self.engine.upgrade().unwrap()
+    }
+
+    fn alloc_area(&self) -> AllocArea {
+        unsafe { *self.alloc_area.get() }
+    }
+
+    fn alloc_area_mut(&mut self) -> &mut AllocArea {
+        self.alloc_area.get_mut()
+    }
+
+    /// The capacity, in bytes, of each semi-space given the heap's current
+    /// size.
+    fn semi_space_capacity(&self) -> u32 {
+        let len = u64::try_from(self.memory.as_ref().unwrap().byte_size()).unwrap();
+        let usable = len.saturating_sub(u64::from(MIN_HEAP_INDEX));
+        let capacity = (usable / 2) & !u64::from(MAX_OBJECT_ALIGN - 1);
+        u32::try_from(capacity).unwrap()
+    }
+
+    /// Resize the semi-spaces to split the heap, which may have grown, in
+    /// half.
+    fn resize_semi_spaces(&mut self) {
+        let capacity = self.semi_space_capacity();
+        let lower_end = MIN_HEAP_INDEX + capacity;
+        let space_start = self.space_start;
+        let area = self.alloc_area_mut();
+
+        if space_start == MIN_HEAP_INDEX {
+            // The lower semi-space is active, and just gets bigger.
+            area.end = lower_end;
+        } else if area.next <= lower_end {
+            // The upper semi-space is active, but all of its objects fit below
+            // the new lower semi-space's end, so we can switch to allocating
+            // out of the lower semi-space. The next collection will evacuate
+            // into the (new) upper semi-space, which is above all of these
+            // objects.
+            area.end = lower_end;
+            self.space_start = MIN_HEAP_INDEX;
+        } else {
+            // Otherwise, keep allocating out of the old upper semi-space until
+            // the next collection evacuates its objects into the lower
+            // semi-space.
+        }
+
+        log::trace!(
+            "resized semi-spaces: capacity = {capacity:#x}, active = {:#x}..{:#x}",
+            self.space_start,
+            self.alloc_area().end,
+        );
+    }
+
+    /// Attempt to bump-allocate an object with the given layout and
+    /// header.
+    ///
+    /// Returns `Ok(Ok(r))` on success, `Ok(Err(bytes_needed))` when we don't
+    /// have enough heap space but collecting garbage or growing the GC heap
+    /// could make it allocatable, and `Err(_)` when we don't have enough space
+    /// and neither of those things will help.
+    fn alloc(&mut self, mut header: VMGcHeader, layout: Layout) -> Result<Result<VMGcRef, u64>> {
+        debug_assert!(layout.size() >= core::mem::size_of::<VMGcHeader>());
+        debug_assert!(layout.align() >= core::mem::align_of::<VMGcHeader>());
+        debug_assert_eq!(header.reserved_u27(), 0);
+
+        // Make sure that the requested allocation's size fits in the GC
+        // header's unused bits.
+        let size = match u32::try_from(layout.size()).ok().and_then(|size| {
+            if VMGcKind::value_fits_in_unused_bits(size) {
+                Some(size)
+            } else {
+                None
+            }
+        }) {
+            Some(size) => size,
+            None => return Err(crate::Trap::AllocationTooLarge.into()),
+        };
+
+        let area = self.alloc_area();
+
+        // Increment the bump pointer to the layout's requested alignment.
+        let aligned = match u32::try_from(layout.align())
+            .ok()
+            .and_then(|align| area.next.checked_next_multiple_of(align))
+        {
+            Some(aligned) => aligned,
+            None => return Err(crate::Trap::AllocationTooLarge.into()),
+        };
+
+        // Check whether the allocation fits in the active semi-space.
+        let end_of_object = match aligned.checked_add(size) {
+            Some(end) => end,
+            None => return Err(crate::Trap::AllocationTooLarge.into()),
+        };
+        if end_of_object > area.end {
+            return Ok(Err(u64::try_from(layout.size()).unwrap()));
+        }
+
+        // Update the bump pointer, write the header, and return the GC ref.
+        self.alloc_area_mut().next = end_of_object;
+
+        let aligned = NonZeroU32::new(aligned).unwrap();
+        let gc_ref = VMGcRef::from_heap_index(aligned).unwrap();
+
+        header.set_reserved_u27(size);
+        *self.header_mut(&gc_ref) = header;
+
+        Ok(Ok(gc_ref))
+    }
+
+    /// Read the native-endian `u32` at the given heap index.
+    fn read_u32(&self, index: u32) -> u32 {
+        let index = usize::try_from(index).unwrap();
+        let bytes = &self.heap_slice()[index..][..mem::size_of::<u32>()];
+        u32::from_ne_bytes(bytes.try_into().unwrap())
+    }
+
+    /// Write a native-endian `u32` at the given heap index.
+    fn write_u32(&mut self, index: u32, value: u32) {
+        let index = usize::try_from(index).unwrap();
+        let bytes = &mut self.heap_slice_mut()[index..][..mem::size_of::<u32>()];
+        bytes.copy_from_slice(&value.to_ne_bytes());
+    }
+
+    /// If the given object has already been evacuated, get its new location.
+    fn forwarded(&self, gc_ref: &VMGcRef) -> Option<VMGcRef> {
+        let index = gc_ref.as_heap_index().unwrap().get();
+        if self.read_u32(index) == 0 {
+            let new_index = self.read_u32(index + 4);
+            Some(VMGcRef::from_raw_u32(new_index).expect("forwarding pointer should be non-null"))
+        } else {
+            None
+        }
+    }
+
+    /// Copy the `size`-byte object at `old_index` to `new_index`, and leave a
+    /// forwarding pointer to it behind.
+    fn evacuate(&mut self, old_index: u32, new_index: u32, size: u32) {
+        let old_range =
+            usize::try_from(old_index).unwrap()..usize::try_from(old_index + size).unwrap();
+        self.heap_slice_mut()
+            .copy_within(old_range, usize::try_from(new_index).unwrap());
+        self.write_u32(old_index, 0);
+        self.write_u32(old_index + 4, new_index);
+        log::trace!("evacuated {old_index:#x} to {new_index:#x}");
+    }
+
+    /// Get the tracing information for the given type, creating it if
+    /// necessary.
+    fn trace_info<'a>(
+        engine: &Engine,
+        trace_infos: &'a mut HashMap<VMSharedTypeIndex, TraceInfo>,
+        ty: VMSharedTypeIndex,
+    ) -> &'a mut TraceInfo {
+        trace_infos.entry(ty).or_insert_with(|| {
+            let gc_layout = engine
+                .signatures()
+                .layout(ty)
+                .unwrap_or_else(|| panic!("should have a GC layout for {ty:?}"));
+            match gc_layout {
+                GcLayout::Array(l) => {
+                    if l.elems_are_gc_refs {
+                        debug_assert_eq!(l.elem_offset(0), GC_REF_ARRAY_ELEMS_OFFSET);
+                    }
+                    TraceInfo::Array {
+                        align: l.align,
+                        gc_ref_elems: l.elems_are_gc_refs,
+                    }
+                }
+                GcLayout::Struct(l) => TraceInfo::Struct {
+                    align: l.align,
+                    gc_ref_offsets: l
+                        .fields
+                        .iter()
+                        .filter_map(|f| if f.is_gc_ref { Some(f.offset) } else { None })
+                        .collect(),
+                },
+            }
+        })
+    }
+
+    /// Collect garbage, evacuating every reachable object out of the active
+    /// semi-space and into the other one.
+    fn collect(
+        &mut self,
+        roots: &mut GcRootsIter<'_>,
+        host_data_table: &mut ExternRefHostDataTable,
+    ) {
+        if !self.is_attached() {
+            return;
+        }
+
+        let capacity = self.semi_space_capacity();
+        let from_start = self.space_start;
+        let from_end = self.alloc_area().next;
+
+        // Evacuate into the upper semi-space when the lower one is active, and
+        // otherwise into the lower semi-space. Note that after growing the GC
+        // heap, the active upper semi-space might overlap the new lower
+        // semi-space, but the space below it is always large enough to hold
+        // everything allocated in it.
+        let (to_start, to_limit) = if from_start == MIN_HEAP_INDEX {
+            let to_start = MIN_HEAP_INDEX + capacity;
+            debug_assert!(from_end <= to_start);
+            (to_start, to_start + capacity)
+        } else {
+            (MIN_HEAP_INDEX, from_start.min(MIN_HEAP_INDEX + capacity))
+        };
+        log::trace!("evacuating {from_start:#x}..{from_end:#x} into {to_start:#x}..{to_limit:#x}");
+
+        let mut evacuation = Evacuation {
+            engine: self.engine(),
+            trace_infos: mem::take(&mut self.trace_infos),
+            live: HashSet::default(),
+            worklist: Vec::new(),
+            offsets: Vec::new(),
+        };
+
+        // Find every object that is reachable from the roots, without
+        // modifying the heap yet.
+        let roots: Vec<_> = roots.collect();
+        for root in &roots {
+            let gc_ref = root.get();
+            if gc_ref.is_i31() {
+                continue;
+            }
+            log::trace!("Found GC root: {gc_ref:#p}");
+            evacuation.mark(&gc_ref);
+        }
+        while let Some(gc_ref) = evacuation.worklist.pop() {
+            evacuation.trace(self, &gc_ref);
+        }
+
+        // Pick new locations for the live objects in the to-space, keeping
+        // them in the same order as in the from-space. Both spaces start at
+        // the same offset modulo `MAX_OBJECT_ALIGN`, so this never needs more
+        // alignment padding than the from-space layout did, and the live
+        // objects always fit. Should they not, leave the heap as it is, so
+        // that the allocation which triggered this collection fails with an
+        // out-of-memory error instead.
+        let mut live: Vec<u32> = evacuation.live.drain().collect();
+        live.sort_unstable();
+        let mut moves = Vec::with_capacity(live.len());
+        let mut to_next = to_start;
+        for old_index in live {
+            let gc_ref = VMGcRef::from_heap_index(NonZeroU32::new(old_index).unwrap()).unwrap();
+            let size = self.header(&gc_ref).reserved_u27();
+            let align = evacuation.align(self, &gc_ref);
+            let Some(new_index) = to_next
+                .checked_next_multiple_of(align)
+                .filter(|i| i.checked_add(size).is_some_and(|end| end <= to_limit))
+            else {
+                log::trace!("live objects don't fit in {to_start:#x}..{to_limit:#x}");
+                self.trace_infos = evacuation.trace_infos;
+                return;
+            };
+            moves.push((old_index, new_index, size));
+            to_next = new_index + size;
+        }
+
+        // Evacuate the live objects, leaving forwarding pointers behind.
+        for &(old_index, new_index, size) in &moves {
+            self.evacuate(old_index, new_index, size);
+        }
+
+        // Update the roots and the evacuated objects' outgoing references to
+        // point to the objects' new locations.
+        for mut root in roots {
+            let gc_ref = root.get();
+            if gc_ref.is_i31() {
+                continue;
+            }
+            root.set(self.forwarded(&gc_ref).unwrap());
+        }
+        for &(_, new_index, _) in &moves {
+            let gc_ref = VMGcRef::from_heap_index(NonZeroU32::new(new_index).unwrap()).unwrap();
+            evacuation.update_fields(self, &gc_ref);
+        }
+
+        // Any `externref` that wasn't evacuated is garbage, so deallocate its
+        // host data.
+        let mut externrefs = mem::take(&mut self.externrefs);
+        externrefs.retain_mut(|externref| match self.forwarded(externref) {
+            Some(new_ref) => {
+                *externref = new_ref;
+                true
+            }
+            None => {
+                let typed_ref = externref.as_typed_unchecked::<VMCopyingExternRef>();
+                let host_data_id = self.index(typed_ref).host_data;
+                log::trace!("reclaiming externref {externref:#p} and its host data");
+                host_data_table.dealloc(host_data_id);
+                false
+            }
+        });
+        self.externrefs = externrefs;
+
+        self.trace_infos = evacuation.trace_infos;
+
+        // Poison the evacuated semi-space in debug builds, so that stale
+        // references are more likely to be caught.
+        if cfg!(debug_assertions) && from_start < from_end {
+            let from = usize::try_from(from_start).unwrap()..usize::try_from(from_end).unwrap();
+            self.heap_slice_mut()[from].fill(POISON);
+        }
+
+        // Finally, start allocating out of the space we just evacuated into.
+        self.space_start = to_start;
+        let end = if to_start == MIN_HEAP_INDEX {
+            MIN_HEAP_INDEX + capacity
+        } else {
+            to_limit
+        };
+        *self.alloc_area_mut() = AllocArea { next: to_next, end };
+        log::trace!(
+            "copying collection complete: {} bytes live, {:#x}..{end:#x} active",
+            to_next - to_start,
+            to_start,
+        );
+    }
+}
+
+/// The state for evacuating objects during a single collection.
+///
+/// We can't do a traditional Cheney-style scan of the to-space because
+/// alignment padding between objects makes it unparsable, and copying objects
+/// in the order they are discovered could need more alignment padding than the
+/// from-space has room for. Instead, we first find all the live objects, then
+/// copy them in address order, and finally update the references to them.
+struct Evacuation {
+    engine: Engine,
+
+    /// The heap's tracing info, which is taken out of the heap for the
+    /// duration of the collection.
+    trace_infos: HashMap<VMSharedTypeIndex, TraceInfo>,
+
+    /// The heap indices of the objects found to be reachable so far.
+    live: HashSet<u32>,
+
+    /// Reachable objects whose outgoing edges have not been traced yet.
+    worklist: Vec<VMGcRef>,
+
+    /// Scratch space for the offsets of an object's GC reference fields.
+    offsets: Vec<u32>,
+}
+
+impl Evacuation {
+    /// Mark the given object as reachable, if it isn't already.
+    fn mark(&mut self, gc_ref: &VMGcRef) {
+        debug_assert!(!gc_ref.is_i31());
+        if self.live.insert(gc_ref.as_heap_index().unwrap().get()) {
+            self.worklist.push(gc_ref.unchecked_copy());
+        }
+    }
+
+    /// Mark all the objects referenced by the given, reachable object.
+    fn trace(&mut self, heap: &CopyingHeap, gc_ref: &VMGcRef) {
+        let mut offsets = mem::take(&mut self.offsets);
+        self.gc_ref_offsets(heap, gc_ref, &mut offsets);
+        for offset in offsets.drain(..) {
+            let raw = heap.gc_object_data(gc_ref).read_u32(offset);
+            if let Some(field) = VMGcRef::from_raw_u32(raw) {
+                if !field.is_i31() {
+                    self.mark(&field);
+                }
+            }
+        }
+        self.offsets = offsets;
+    }
+
+    /// Update all the references in the given, evacuated object to point to
+    /// the referenced objects' new locations.
+    fn update_fields(&mut self, heap: &mut CopyingHeap, gc_ref: &VMGcRef) {
+        let mut offsets = mem::take(&mut self.offsets);
+        self.gc_ref_offsets(heap, gc_ref, &mut offsets);
+        for offset in offsets.drain(..) {
+            let raw = heap.gc_object_data(gc_ref).read_u32(offset);
+            let Some(field) = VMGcRef::from_raw_u32(raw) else {
+                continue;
+            };
+            if field.is_i31() {
+                continue;
+            }
+            let new_field = heap
+                .forwarded(&field)
+                .expect("reachable objects should have been evacuated");
+            heap.gc_object_data_mut(gc_ref)
+                .write_u32(offset, new_field.as_raw_u32());
+        }
+        self.offsets = offsets;
+    }
+
+    /// Get the alignment of the given object.
+    fn align(&mut self, heap: &CopyingHeap, gc_ref: &VMGcRef) -> u32 {
+        let header = heap.header(gc_ref);
+        match header.ty() {
+            Some(ty) => CopyingHeap::trace_info(&self.engine, &mut self.trace_infos, ty).align(),
+            None => {
+                debug_assert_eq!(header.kind(), VMGcKind::ExternRef);
+                u32::try_from(mem::align_of::<VMCopyingExternRef>()).unwrap()
+            }
+        }
+    }
+
+    /// Push the offsets of the GC references stored in the given object onto
+    /// `offsets`.
+    fn gc_ref_offsets(&mut self, heap: &CopyingHeap, gc_ref: &VMGcRef, offsets: &mut Vec<u32>) {
+        let header = heap.header(gc_ref);
+        let Some(ty) = header.ty() else {
+            debug_assert!(header.kind().matches(VMGcKind::ExternRef));
+            return;
+        };
+
+        match CopyingHeap::trace_info(&self.engine, &mut self.trace_infos, ty) {
+            TraceInfo::Struct { gc_ref_offsets, .. } => {
+                offsets.extend_from_slice(gc_ref_offsets);
+            }
+            TraceInfo::Array { gc_ref_elems, .. } => {
+                if !*gc_ref_elems {
+                    return;
+                }
+                let len = heap.array_len(gc_ref.as_arrayref_unchecked());
+                offsets.extend((0..len).map(|i| {
+                    GC_REF_ARRAY_ELEMS_OFFSET + i * u32::try_from(mem::size_of::<u32>()).unwrap()
+                }));
+            }
+        }
+    }
+}
+
+unsafe impl GcHeap for CopyingHeap {
+    fn is_attached(&self) -> bool {
+        self.memory.is_some()
+    }
+
+    fn attach(&mut self, memory: crate::vm::Memory) {
+        assert!(!self.is_attached());
+        assert!(!memory.is_shared_memory());
+        self.memory = Some(memory);
+        self.space_start = MIN_HEAP_INDEX;
+        *self.alloc_area_mut() = AllocArea {
+            next: MIN_HEAP_INDEX,
+            end: MIN_HEAP_INDEX,
+        };
+        self.resize_semi_spaces();
+    }
+
+    fn detach(&mut self) -> crate::vm::Memory {
+        assert!(self.is_attached());
+
+        let CopyingHeap {
+            engine: _,
+            alloc_area,
+            space_start,
+            externrefs,
+            no_gc_count,
+            memory,
+
+            // NB: we will only ever be reused with the same engine, so no need
+            // to clear out our tracing info just to fill it back in with the
+            // same exact stuff.
+            trace_infos: _,
+        } = self;
+
+        *alloc_area.get_mut() = AllocArea {
+            next: MIN_HEAP_INDEX,
+            end: MIN_HEAP_INDEX,
+        };
+        *space_start = MIN_HEAP_INDEX;
+        externrefs.clear();
+        *no_gc_count = 0;
+
+        memory.take().unwrap()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self as _
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self as _
+    }
+
+    fn enter_no_gc_scope(&mut self) {
+        self.no_gc_count += 1;
+    }
+
+    fn exit_no_gc_scope(&mut self) {
+        self.no_gc_count -= 1;
+    }
+
+    unsafe fn take_memory(&mut self) -> crate::vm::Memory {
+        debug_assert!(self.is_attached());
+        self.memory.take().unwrap()
+    }
+
+    unsafe fn replace_memory(&mut self, memory: crate::vm::Memory, _delta_bytes_grown: u64) {
+        debug_assert!(self.memory.is_none());
+        self.memory = Some(memory);
+        self.resize_semi_spaces();
+    }
+
+    fn vmmemory(&self) -> VMMemoryDefinition {
+        debug_assert!(self.is_attached());
+        self.memory.as_ref().unwrap().vmmemory()
+    }
+
+    fn clone_gc_ref(&mut self, gc_ref: &VMGcRef) -> VMGcRef {
+        gc_ref.unchecked_copy()
+    }
+
+    fn write_gc_ref(
+        &mut self,
+        _host_data_table: &mut ExternRefHostDataTable,
+        destination: &mut Option<VMGcRef>,
+        source: Option<&VMGcRef>,
+    ) {
+        *destination = source.map(|s| s.unchecked_copy());
+    }
+
+    fn expose_gc_ref_to_wasm(&mut self, _gc_ref: VMGcRef) {
+        // Don't need to do anything special here: stack maps tell us about all
+        // the GC references inside Wasm frames.
+    }
+
+    fn need_gc_before_entering_wasm(&self, _num_gc_refs: NonZeroUsize) -> bool {
+        // Never need to GC before entering Wasm.
+        false
+    }
+
+    fn collect_before_growing(&self) -> bool {
+        // A collection only costs as much as the live data, and everything
+        // that isn't live is reclaimed, so always try collecting first. The
+        // exception is when we are inside a no-GC scope (for example, while
+        // evaluating const expressions) where only growing is allowed.
+        self.no_gc_count == 0
+    }
+
+    fn growth_needed(&self, bytes_needed: u64) -> Option<u64> {
+        let area = self.alloc_area();
+        let room = u64::from(area.end.saturating_sub(area.next));
+        let bytes_needed = bytes_needed.saturating_add(u64::from(MAX_OBJECT_ALIGN));
+
+        // Grow the heap when the allocation doesn't fit, or when the live data
+        // left after the collection we just did takes up more than half of the
+        // semi-space, so that we don't spend all our time collecting.
+        let live = u64::from(area.next - self.space_start);
+        let capacity = u64::from(self.semi_space_capacity());
+        if room >= bytes_needed && live.saturating_mul(2) <= capacity {
+            None
+        } else {
+            // Each semi-space only gets half of any new heap space.
+            Some(bytes_needed.saturating_mul(2))
+        }
+    }
+
+    fn alloc_externref(
+        &mut self,
+        host_data: ExternRefHostDataId,
+    ) -> Result<Result<VMExternRef, u64>> {
+        let gc_ref =
+            match self.alloc(VMGcHeader::externref(), Layout::new::<VMCopyingExternRef>())? {
+                Ok(r) => r,
+                Err(bytes_needed) => return Ok(Err(bytes_needed)),
+            };
+        self.index_mut::<VMCopyingExternRef>(gc_ref.as_typed_unchecked())
+            .host_data = host_data;
+        self.externrefs.push(gc_ref.unchecked_copy());
+        Ok(Ok(gc_ref.into_externref_unchecked()))
+    }
+
+    fn externref_host_data(&self, externref: &VMExternRef) -> ExternRefHostDataId {
+        let typed_ref = VMCopyingExternRef::typed_ref(self, externref);
+        self.index(typed_ref).host_data
+    }
+
+    fn object_size(&self, gc_ref: &VMGcRef) -> usize {
+        let size = self.header(gc_ref).reserved_u27();
+        usize::try_from(size).unwrap()
+    }
+
+    fn header(&self, gc_ref: &VMGcRef) -> &VMGcHeader {
+        self.index(gc_ref.as_typed_unchecked())
+    }
+
+    fn header_mut(&mut self, gc_ref: &VMGcRef) -> &mut VMGcHeader {
+        self.index_mut(gc_ref.as_typed_unchecked())
+    }
+
+    fn alloc_raw(&mut self, header: VMGcHeader, layout: Layout) -> Result<Result<VMGcRef, u64>> {
+        self.alloc(header, layout)
+    }
+
+    fn alloc_uninit_struct(
+        &mut self,
+        ty: VMSharedTypeIndex,
+        layout: &GcStructLayout,
+    ) -> Result<Result<VMStructRef, u64>> {
+        self.alloc(
+            VMGcHeader::from_kind_and_index(VMGcKind::StructRef, ty),
+            layout.layout(),
+        )
+        .map(|r| r.map(|r| r.into_structref_unchecked()))
+    }
+
+    fn dealloc_uninit_struct(&mut self, _struct_ref: VMStructRef) {}
+
+    fn alloc_uninit_array(
+        &mut self,
+        ty: VMSharedTypeIndex,
+        length: u32,
+        layout: &GcArrayLayout,
+    ) -> Result<Result<VMArrayRef, u64>> {
+        self.alloc(
+            VMGcHeader::from_kind_and_index(VMGcKind::ArrayRef, ty),
+            layout.layout(length),
+        )
+        .map(|r| {
+            r.map(|r| {
+                self.index_mut::<VMCopyingArrayHeader>(r.as_typed_unchecked())
+                    .length = length;
+                r.into_arrayref_unchecked()
+            })
+        })
+    }
+
+    fn dealloc_uninit_array(&mut self, _array_ref: VMArrayRef) {}
+
+    fn array_len(&self, arrayref: &VMArrayRef) -> u32 {
+        let arrayref = VMCopyingArrayHeader::typed_ref(self, arrayref);
+        self.index(arrayref).length
+    }
+
+    fn gc<'a>(
+        &'a mut self,
+        roots: GcRootsIter<'a>,
+        host_data_table: &'a mut ExternRefHostDataTable,
+    ) -> Box<dyn GarbageCollection<'a> + 'a> {
+        assert_eq!(self.no_gc_count, 0, "Cannot GC inside a no-GC scope!");
+        Box::new(CopyingCollection {
+            roots,
+            host_data_table,
+            heap: self,
+            done: false,
+        })
+    }
+
+    unsafe fn vmctx_gc_heap_data(&self) -> NonNull<u8> {
+        let ptr_to_alloc_area: *mut AllocArea = self.alloc_area.get();
+        NonNull::new(ptr_to_alloc_area).unwrap().cast()
+    }
+
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        debug_assert!(self.is_attached());
+        Ok(postcard::to_allocvec(&CopyingHeapSnapshot {
+            space_start: self.space_start,
+            alloc_area: self.alloc_area(),
+            externrefs: self.externrefs.iter().map(|r| r.as_raw_u32()).collect(),
+        })?)
+    }
+
+    fn check_snapshot(&self, snapshot: &[u8], heap_len: usize) -> Result<()> {
+        parse_snapshot(snapshot, heap_len).map(drop)
+    }
+
+    fn restore_snapshot(&mut self, snapshot: &[u8], heap_len: usize) -> Result<()> {
+        debug_assert!(self.is_attached());
+        let (snapshot, externrefs) = parse_snapshot(snapshot, heap_len)?;
+
+        self.space_start = snapshot.space_start;
+        *self.alloc_area_mut() = snapshot.alloc_area;
+        self.externrefs = externrefs;
+
+        // The heap we are restoring into may be larger than the snapshotted
+        // heap was.
+        self.resize_semi_spaces();
+        Ok(())
+    }
+}
+
+/// Decode and validate a copying heap snapshot, returning it along with its
+/// externrefs.
+fn parse_snapshot(snapshot: &[u8], heap_len: usize) -> Result<(CopyingHeapSnapshot, Vec<VMGcRef>)> {
+    let mut snapshot: CopyingHeapSnapshot = postcard::from_bytes(snapshot)?;
+
+    let AllocArea { next, end } = snapshot.alloc_area;
+    let space_start = snapshot.space_start;
+    ensure!(
+        MIN_HEAP_INDEX <= space_start
+            && space_start <= next
+            && next <= end
+            && usize::try_from(end).unwrap() <= heap_len,
+        "invalid copying GC heap snapshot: semi-space is out of bounds"
+    );
+    ensure!(
+        space_start == MIN_HEAP_INDEX || end - space_start <= space_start - MIN_HEAP_INDEX,
+        "invalid copying GC heap snapshot: semi-space is too large"
+    );
+
+    let mut externrefs = Vec::with_capacity(snapshot.externrefs.len());
+    for raw in core::mem::take(&mut snapshot.externrefs) {
+        let gc_ref = VMGcRef::from_raw_u32(raw)
+            .filter(|r| !r.is_i31() && space_start <= raw && raw < next)
+            .context("invalid copying GC heap snapshot: invalid externref")?;
+        externrefs.push(gc_ref);
+    }
+    Ok((snapshot, externrefs))
+}
+
+struct CopyingCollection<'a> {
+    roots: GcRootsIter<'a>,
+    host_data_table: &'a mut ExternRefHostDataTable,
+    heap: &'a mut CopyingHeap,
+    done: bool,
+}
+
+impl<'a> GarbageCollection<'a> for CopyingCollection<'a> {
+    fn collect_increment(&mut self) -> GcProgress {
+        if !self.done {
+            self.heap.collect(&mut self.roots, self.host_data_table);
+            self.done = true;
+        }
+        GcProgress::Complete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vm_gc_copying_header_size_align() {
+        assert_eq!(
+            (wasmtime_environ::copying::HEADER_SIZE as usize),
+            core::mem::size_of::<VMGcHeader>()
+        );
+        assert_eq!(
+            (wasmtime_environ::copying::HEADER_ALIGN as usize),
+            core::mem::align_of::<VMGcHeader>()
+        );
+    }
+
+    #[test]
+    fn vm_copying_array_header_length_offset() {
+        assert_eq!(
+            wasmtime_environ::copying::ARRAY_LENGTH_OFFSET,
+            u32::try_from(core::mem::offset_of!(VMCopyingArrayHeader, length)).unwrap(),
+        );
+    }
+
+    #[test]
+    fn vm_copying_alloc_area_offsets() {
+        assert_eq!(
+            wasmtime_environ::copying::ALLOC_AREA_NEXT_OFFSET,
+            u32::try_from(core::mem::offset_of!(AllocArea, next)).unwrap(),
+        );
+        assert_eq!(
+            wasmtime_environ::copying::ALLOC_AREA_END_OFFSET,
+            u32::try_from(core::mem::offset_of!(AllocArea, end)).unwrap(),
+        );
+    }
+}
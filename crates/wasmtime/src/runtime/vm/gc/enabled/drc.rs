@@ -61,6 +61,7 @@ use core::{
     ops::{Deref, DerefMut},
     ptr::NonNull,
 };
+use serde_derive::{Deserialize, Serialize};
 use wasmtime_environ::drc::{ARRAY_LENGTH_OFFSET, DrcTypeLayouts};
 use wasmtime_environ::{
     GcArrayLayout, GcLayout, GcStructLayout, GcTypeLayouts, VMGcKind, VMSharedTypeIndex,
@@ -109,6 +110,43 @@ enum TraceInfo {
     },
 }
 
+/// The bookkeeping captured by a DRC heap snapshot, in addition to the raw
+/// bytes of the heap itself.
+#[derive(Serialize, Deserialize)]
+struct DrcHeapSnapshot {
+    /// The free list's capacity, in bytes.
+    free_list_capacity: u64,
+
+    /// The free list's blocks, as `(index, length)` pairs.
+    free_blocks: Vec<(u32, u32)>,
+
+    /// The raw GC refs in the activations table's bump chunk. Each entry holds
+    /// one reference count, so duplicates are meaningful.
+    bump_chunk: Vec<u32>,
+
+    /// The raw GC refs in the activations table's over-approximated stack
+    /// roots set.
+    over_approximated_stack_roots: Vec<u32>,
+
+    /// Every type that may have been allocated in this heap.
+    types: Vec<VMSharedTypeIndex>,
+
+    /// The raw GC refs whose reference count decrements were deferred by
+    /// incremental collection.
+    deferred_dec_refs: Vec<u32>,
+}
+
+/// A `DrcHeapSnapshot` that has been checked against the heap it will be
+/// restored into.
+struct ParsedDrcHeapSnapshot {
+    capacity: usize,
+    free_blocks: Vec<(u32, u32)>,
+    types: Vec<VMSharedTypeIndex>,
+    bump_chunk: Vec<VMGcRef>,
+    over_approximated_stack_roots: Vec<VMGcRef>,
+    deferred_dec_refs: Vec<VMGcRef>,
+}
+
 /// A deferred reference-counting (DRC) heap.
 struct DrcHeap {
     engine: EngineWeak,
@@ -147,16 +185,68 @@ struct DrcHeap {
     /// yet another object, etc...
     ///
     /// We store this stack here to reuse the storage and avoid repeated
-    /// allocations.
+    /// allocations. When collecting incrementally, this stack also holds the
+    /// dec-refs that exceeded a step's work budget until a later step gets to
+    /// them. Each deferred entry keeps its object's reference count elevated,
+    /// so the object stays allocated until then.
     ///
     /// Note that the `Option` is perhaps technically unnecessary (we could
     /// remove the `Option` and, when we take the stack out of `self`, leave
     /// behind an empty vec instead of `None`) but we keep it because it will
     /// help us catch unexpected re-entry, similar to how a `RefCell` would.
     dec_ref_stack: Option<Vec<VMGcRef>>,
+
+    /// The maximum number of dec-refs to process at a time when collecting
+    /// incrementally, or `None` when collections run to completion.
+    work_budget: Option<NonZeroUsize>,
 }
 
 impl DrcHeap {
+    /// Decode a snapshot previously produced by `GcHeap::snapshot` and check
+    /// that it can be restored into a heap of `heap_len` bytes with this
+    /// heap's engine.
+    fn parse_snapshot(&self, snapshot: &[u8], heap_len: usize) -> Result<ParsedDrcHeapSnapshot> {
+        let snapshot: DrcHeapSnapshot = postcard::from_bytes(snapshot)?;
+
+        // Make sure every type in the snapshot is registered with our engine,
+        // so that tracing objects of those types later cannot fail.
+        let engine = self.engine();
+        for ty in snapshot.types.iter() {
+            ensure!(
+                engine.signatures().layout(*ty).is_some(),
+                "invalid DRC heap snapshot: type {ty:?} is not registered with this engine"
+            );
+        }
+
+        let capacity = usize::try_from(snapshot.free_list_capacity)?;
+        ensure!(
+            capacity <= heap_len,
+            "invalid DRC heap snapshot: free list is larger than the heap"
+        );
+        FreeList::check_blocks(capacity, &snapshot.free_blocks)
+            .context("invalid DRC heap snapshot")?;
+
+        // Every GC ref in the activations table and the deferred decrements
+        // must point at an object inside the snapshotted heap.
+        let gc_refs = |raws: Vec<u32>| {
+            raws.into_iter()
+                .map(|raw| {
+                    VMGcRef::from_raw_u32(raw)
+                        .filter(|r| !r.is_i31() && usize::try_from(raw).unwrap() < capacity)
+                        .context("invalid DRC heap snapshot: GC ref is out of bounds")
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(ParsedDrcHeapSnapshot {
+            capacity,
+            free_blocks: snapshot.free_blocks,
+            types: snapshot.types,
+            bump_chunk: gc_refs(snapshot.bump_chunk)?,
+            over_approximated_stack_roots: gc_refs(snapshot.over_approximated_stack_roots)?,
+            deferred_dec_refs: gc_refs(snapshot.deferred_dec_refs)?,
+        })
+    }
+
     /// Construct a new, default DRC heap.
     fn new(engine: &Engine) -> Result<Self> {
         log::trace!("allocating new DRC heap");
@@ -169,6 +259,7 @@ impl DrcHeap {
             vmmemory: None,
             free_list: None,
             dec_ref_stack: Some(vec![]),
+            work_budget: engine.config().incremental_gc_budget,
         })
     }
 
@@ -232,16 +323,37 @@ impl DrcHeap {
     /// This uses an explicit stack, rather than recursion, for the scenario
     /// where dropping one object means that the ref count for another object
     /// that it referenced reaches zero.
+    ///
+    /// When collecting incrementally, this only does up to the work budget's
+    /// worth of dec-refs, and defers the rest.
     fn dec_ref_and_maybe_dealloc(
         &mut self,
         host_data_table: &mut ExternRefHostDataTable,
         gc_ref: &VMGcRef,
+    ) {
+        self.dec_ref_stack
+            .as_mut()
+            .unwrap()
+            .push(gc_ref.unchecked_copy());
+        self.process_dec_ref_stack(host_data_table, self.work_budget);
+    }
+
+    /// Pop and process entries from the dec-ref stack until it is empty or we
+    /// have processed `budget` entries.
+    fn process_dec_ref_stack(
+        &mut self,
+        host_data_table: &mut ExternRefHostDataTable,
+        budget: Option<NonZeroUsize>,
     ) {
         let mut stack = self.dec_ref_stack.take().unwrap();
-        debug_assert!(stack.is_empty());
-        stack.push(gc_ref.unchecked_copy());
+        let mut work = 0;
+
+        while budget.is_none_or(|b| work < b.get()) {
+            let Some(gc_ref) = stack.pop() else {
+                break;
+            };
+            work += 1;
 
-        while let Some(gc_ref) = stack.pop() {
             if self.dec_ref(&gc_ref) {
                 // The object's reference count reached zero.
                 //
@@ -260,7 +372,11 @@ impl DrcHeap {
             }
         }
 
-        debug_assert!(stack.is_empty());
+        if !stack.is_empty() {
+            log::trace!("deferring {} dec-refs to a later GC step", stack.len());
+        }
+
+        debug_assert!(budget.is_some() || stack.is_empty());
         debug_assert!(self.dec_ref_stack.is_none());
         self.dec_ref_stack = Some(stack);
     }
@@ -451,7 +567,7 @@ impl DrcHeap {
     /// Sweep the bump allocation table after we've discovered our precise stack
     /// roots.
     fn sweep(&mut self, host_data_table: &mut ExternRefHostDataTable) {
-        self.sweep_bump_chunk(host_data_table);
+        self.sweep_bump_chunk();
 
         if log::log_enabled!(log::Level::Trace) {
             Self::log_gc_ref_set(
@@ -472,20 +588,18 @@ impl DrcHeap {
 
         // And finally, the new `precise_stack_roots` should be cleared and
         // remain empty until the next GC cycle.
+        log::trace!("Begin sweeping hash set");
+        let dec_ref_stack = self.dec_ref_stack.as_mut().unwrap();
+        dec_ref_stack.extend(self.activations_table.precise_stack_roots.drain());
+        log::trace!("Done sweeping hash set");
+
+        // Now actually perform the dec-refs we enqueued above, as well as any
+        // left over from previous incremental steps, up to our work budget.
         //
         // Note that this may run arbitrary code as we run gc_ref
         // destructors. Because of our `&mut` borrow above on this table,
         // though, we're guaranteed that nothing will touch this table.
-        log::trace!("Begin sweeping hash set");
-        let mut precise_stack_roots = mem::take(&mut self.activations_table.precise_stack_roots);
-        for gc_ref in precise_stack_roots.drain() {
-            self.dec_ref_and_maybe_dealloc(host_data_table, &gc_ref);
-        }
-        log::trace!("Done sweeping hash set");
-
-        // Make sure to replace the `precise_stack_roots` so that we reuse any
-        // allocated capacity.
-        self.activations_table.precise_stack_roots = precise_stack_roots;
+        self.process_dec_ref_stack(host_data_table, self.work_budget);
 
         if log::log_enabled!(log::Level::Trace) {
             Self::log_gc_ref_set(
@@ -498,7 +612,7 @@ impl DrcHeap {
         }
     }
 
-    fn sweep_bump_chunk(&mut self, host_data_table: &mut ExternRefHostDataTable) {
+    fn sweep_bump_chunk(&mut self) {
         if log::log_enabled!(log::Level::Trace) {
             Self::log_gc_ref_set("bump chunk before sweeping", self.iter_bump_chunk());
         }
@@ -512,11 +626,13 @@ impl DrcHeap {
 
             bump_chunk_was_full = len == alloc.capacity();
 
+            // Enqueue each slot's dec-ref; `sweep` processes them once we are
+            // done with the activations table.
+            let dec_ref_stack = heap.dec_ref_stack.as_mut().unwrap();
             for slot in alloc.chunk.iter_mut().take(len) {
                 let raw = mem::take(slot);
                 let gc_ref = VMGcRef::from_raw_u32(raw).expect("non-null");
-                heap.dec_ref_and_maybe_dealloc(host_data_table, &gc_ref);
-                *slot = 0;
+                dec_ref_stack.push(gc_ref);
             }
         });
 
@@ -636,6 +752,7 @@ unsafe impl GcHeap for DrcHeap {
             activations_table,
             free_list,
             dec_ref_stack,
+            work_budget: _,
             memory,
             vmmemory,
 
@@ -649,7 +766,9 @@ unsafe impl GcHeap for DrcHeap {
         activations_table.reset();
         *free_list = None;
         *vmmemory = None;
-        debug_assert!(dec_ref_stack.as_ref().is_some_and(|s| s.is_empty()));
+        // Any dec-refs deferred by incremental collection are moot now that the
+        // objects they refer to are going away with the memory.
+        dec_ref_stack.as_mut().unwrap().clear();
 
         memory.take().unwrap()
     }
@@ -830,11 +949,116 @@ unsafe impl GcHeap for DrcHeap {
         })
     }
 
+    fn finish_deferred_work(&mut self, host_data_table: &mut ExternRefHostDataTable) {
+        self.process_dec_ref_stack(host_data_table, None);
+    }
+
     unsafe fn vmctx_gc_heap_data(&self) -> NonNull<u8> {
         let ptr: NonNull<VMGcRefActivationsTable> = NonNull::from(&*self.activations_table);
         ptr.cast()
     }
 
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        debug_assert!(self.is_attached());
+        let (capacity, free_blocks) = self.free_list.as_ref().unwrap().blocks();
+
+        let num_filled = self.activations_table.alloc.len();
+        let bump_chunk = self
+            .activations_table
+            .alloc
+            .chunk
+            .iter()
+            .take(num_filled)
+            .copied()
+            .filter(|raw| *raw != 0)
+            .collect();
+        let over_approximated_stack_roots = self
+            .activations_table
+            .over_approximated_stack_roots
+            .iter()
+            .map(|r| r.as_raw_u32())
+            .collect();
+
+        let mut types: Vec<_> = self.trace_infos.keys().copied().collect();
+        types.sort();
+
+        let deferred_dec_refs = self
+            .dec_ref_stack
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|r| r.as_raw_u32())
+            .collect();
+
+        Ok(postcard::to_allocvec(&DrcHeapSnapshot {
+            free_list_capacity: u64::try_from(capacity).unwrap(),
+            free_blocks,
+            bump_chunk,
+            over_approximated_stack_roots,
+            types,
+            deferred_dec_refs,
+        })?)
+    }
+
+    fn check_snapshot(&self, snapshot: &[u8], heap_len: usize) -> Result<()> {
+        self.parse_snapshot(snapshot, heap_len).map(drop)
+    }
+
+    fn restore_snapshot(&mut self, snapshot: &[u8], heap_len: usize) -> Result<()> {
+        debug_assert!(self.is_attached());
+        let ParsedDrcHeapSnapshot {
+            capacity,
+            free_blocks,
+            types,
+            bump_chunk,
+            over_approximated_stack_roots,
+            deferred_dec_refs,
+        } = self.parse_snapshot(snapshot, heap_len)?;
+
+        for ty in types {
+            self.ensure_trace_info(ty);
+        }
+
+        let mut free_list = FreeList::from_blocks(capacity, free_blocks);
+        if heap_len > capacity {
+            free_list.add_capacity(heap_len - capacity);
+        }
+        self.free_list = Some(free_list);
+
+        // Discard whatever this heap's activations table previously held, the
+        // snapshot's heap bytes have replaced the objects it referenced.
+        let table = &mut *self.activations_table;
+        let num_filled = table.alloc.len();
+        for slot in table.alloc.chunk.iter_mut().take(num_filled) {
+            *slot = 0;
+        }
+        table.reset();
+
+        while table.alloc.capacity() < bump_chunk.len()
+            && table.alloc.capacity() < VMGcRefTableAlloc::MAX_CAPACITY
+        {
+            let old_capacity = table.alloc.capacity();
+            table.alloc.grow_bump_chunk();
+            if table.alloc.capacity() == old_capacity {
+                break;
+            }
+        }
+        table.alloc.reset();
+
+        for gc_ref in bump_chunk {
+            table.insert_without_gc(gc_ref);
+        }
+        for gc_ref in over_approximated_stack_roots {
+            table.insert_slow_without_gc(gc_ref);
+        }
+
+        let dec_ref_stack = self.dec_ref_stack.as_mut().unwrap();
+        dec_ref_stack.clear();
+        dec_ref_stack.extend(deferred_dec_refs);
+
+        Ok(())
+    }
+
     unsafe fn take_memory(&mut self) -> crate::vm::Memory {
         debug_assert!(self.is_attached());
         self.vmmemory.take();
@@ -1235,6 +1459,8 @@ mod tests {
             num_defined_globals: 0,
             num_defined_tags: 0,
             num_escaped_funcs: 0,
+            num_fuel_counters: 0,
+            num_lazy_funcs: 0,
         });
 
         assert_eq!(
@@ -1265,6 +1491,8 @@ mod tests {
             num_defined_globals: 0,
             num_defined_tags: 0,
             num_escaped_funcs: 0,
+            num_fuel_counters: 0,
+            num_lazy_funcs: 0,
         });
         assert_eq!(
             offsets.vm_gc_ref_activation_table_next() as usize,
@@ -1294,6 +1522,8 @@ mod tests {
             num_defined_globals: 0,
             num_defined_tags: 0,
             num_escaped_funcs: 0,
+            num_fuel_counters: 0,
+            num_lazy_funcs: 0,
         });
         assert_eq!(
             offsets.vm_gc_ref_activation_table_end() as usize,
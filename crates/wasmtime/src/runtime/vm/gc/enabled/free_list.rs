@@ -73,6 +73,50 @@ impl FreeList {
         free_list
     }
 
+    /// Recreate a `FreeList` from the capacity and free blocks previously
+    /// returned by `FreeList::blocks`.
+    pub fn from_blocks(capacity: usize, blocks: Vec<(u32, u32)>) -> Self {
+        let free_list = FreeList {
+            capacity,
+            free_block_index_to_len: blocks.into_iter().collect(),
+        };
+        #[cfg(debug_assertions)]
+        free_list.check_integrity();
+        free_list
+    }
+
+    /// Check that the given free blocks, as returned by `FreeList::blocks`,
+    /// are sorted, non-adjacent, aligned, and within `capacity`.
+    pub fn check_blocks(capacity: usize, blocks: &[(u32, u32)]) -> Result<()> {
+        let mut prev_end = None;
+        for &(index, len) in blocks {
+            let end = index
+                .checked_add(len)
+                .filter(|end| usize::try_from(*end).unwrap() <= capacity);
+            ensure!(
+                index >= ALIGN_U32
+                    && len >= ALIGN_U32
+                    && index % ALIGN_U32 == 0
+                    && len % ALIGN_U32 == 0
+                    && prev_end.is_none_or(|prev_end| prev_end < index),
+                "invalid free block at index {index}"
+            );
+            prev_end = Some(end.context("free block is out of bounds")?);
+        }
+        Ok(())
+    }
+
+    /// Get this free list's capacity and its free blocks, as `(index, length)`
+    /// pairs.
+    pub fn blocks(&self) -> (usize, Vec<(u32, u32)>) {
+        let blocks = self
+            .free_block_index_to_len
+            .iter()
+            .map(|(index, len)| (*index, *len))
+            .collect();
+        (self.capacity, blocks)
+    }
+
     /// Add additional capacity to this free list.
     pub fn add_capacity(&mut self, additional: usize) {
         let old_cap = self.capacity;
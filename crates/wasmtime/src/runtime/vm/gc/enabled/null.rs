@@ -348,6 +348,35 @@ unsafe impl GcHeap for NullHeap {
         let ptr_to_next: *mut NonZeroU32 = self.next.get();
         NonNull::new(ptr_to_next).unwrap().cast()
     }
+
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        debug_assert!(self.is_attached());
+        // The bump pointer is the only state we have outside of the heap's
+        // raw bytes.
+        let next = unsafe { *self.next.get() };
+        Ok(postcard::to_allocvec(&next.get())?)
+    }
+
+    fn check_snapshot(&self, snapshot: &[u8], heap_len: usize) -> Result<()> {
+        parse_snapshot(snapshot, heap_len).map(drop)
+    }
+
+    fn restore_snapshot(&mut self, snapshot: &[u8], heap_len: usize) -> Result<()> {
+        debug_assert!(self.is_attached());
+        *self.next.get_mut() = parse_snapshot(snapshot, heap_len)?;
+        Ok(())
+    }
+}
+
+/// Decode and validate a null heap snapshot, returning its bump pointer.
+fn parse_snapshot(snapshot: &[u8], heap_len: usize) -> Result<NonZeroU32> {
+    let next: u32 = postcard::from_bytes(snapshot)?;
+    let next = NonZeroU32::new(next).context("invalid null GC heap snapshot")?;
+    ensure!(
+        usize::try_from(next.get()).unwrap() <= heap_len.max(1),
+        "invalid null GC heap snapshot: bump pointer is out of bounds"
+    );
+    Ok(next)
 }
 
 struct NullCollection {}
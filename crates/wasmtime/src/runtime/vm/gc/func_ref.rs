@@ -93,4 +93,13 @@ impl FuncRefTable {
     pub fn get_untyped(&self, id: FuncRefTableId) -> Option<SendSyncPtr<VMFuncRef>> {
         self.slab.get(id.0).copied().expect("bad FuncRefTableId")
     }
+
+    /// Iterate over every interned `VMFuncRef` and its ID, in ID order.
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (FuncRefTableId, Option<SendSyncPtr<VMFuncRef>>)> + '_ {
+        self.slab
+            .iter()
+            .map(|(id, func_ref)| (FuncRefTableId(id), *func_ref))
+    }
 }
@@ -195,6 +195,24 @@ pub unsafe trait GcHeap: 'static + Send + Sync {
     /// passed into Wasm.
     fn need_gc_before_entering_wasm(&self, num_gc_refs: NonZeroUsize) -> bool;
 
+    /// Should the store collect garbage before growing this heap, when an
+    /// allocation fails?
+    ///
+    /// By default, heaps are grown first and garbage is only collected when
+    /// growth fails.
+    fn collect_before_growing(&self) -> bool {
+        false
+    }
+
+    /// Called right after a collection that was triggered by a failed
+    /// allocation of `bytes_needed` bytes, to determine how many bytes, if
+    /// any, this heap should grow by before retrying the allocation.
+    ///
+    /// Only used when `collect_before_growing` returns `true`.
+    fn growth_needed(&self, bytes_needed: u64) -> Option<u64> {
+        Some(bytes_needed)
+    }
+
     ////////////////////////////////////////////////////////////////////////////
     // `externref` Methods
 
@@ -368,6 +386,17 @@ pub unsafe trait GcHeap: 'static + Send + Sync {
         host_data_table: &'a mut ExternRefHostDataTable,
     ) -> Box<dyn GarbageCollection<'a> + 'a>;
 
+    /// Finish any work that incremental collection deferred to later steps.
+    ///
+    /// This is called when the GC heap is out of memory and cannot be grown,
+    /// at which point reclaiming all outstanding garbage takes priority over
+    /// bounding pause times.
+    ///
+    /// Collectors that always run to completion need not implement this.
+    fn finish_deferred_work(&mut self, host_data_table: &mut ExternRefHostDataTable) {
+        let _ = host_data_table;
+    }
+
     ////////////////////////////////////////////////////////////////////////////
     // JIT-Code Interaction Methods
 
@@ -428,6 +457,39 @@ pub unsafe trait GcHeap: 'static + Send + Sync {
         unsafe { slice::from_raw_parts_mut(ptr, len) }
     }
 
+    ////////////////////////////////////////////////////////////////////////////
+    // Snapshot Methods
+
+    /// Capture this heap's collector-specific bookkeeping (free lists, bump
+    /// pointers, etc...) for a store snapshot.
+    ///
+    /// The raw bytes of the heap are captured separately by the caller via
+    /// `heap_slice`. Together, the two are enough to recreate this heap's
+    /// current state inside another, freshly-attached heap of the same
+    /// collector with `restore_snapshot`.
+    fn snapshot(&self) -> Result<Vec<u8>>;
+
+    /// Check that bookkeeping previously captured by `snapshot` is valid for
+    /// a heap of `heap_len` bytes, without modifying this heap.
+    ///
+    /// `restore_snapshot` performs the same checks, but this method lets
+    /// callers reject a bad snapshot before they start mutating any other
+    /// state.
+    fn check_snapshot(&self, snapshot: &[u8], heap_len: usize) -> Result<()>;
+
+    /// Restore collector-specific bookkeeping previously captured by
+    /// `snapshot`.
+    ///
+    /// Before calling this method, the caller must have copied the
+    /// snapshotted heap bytes into the start of this heap and zeroed
+    /// everything after them. `heap_len` is the heap's current length, which
+    /// must be at least the length of the snapshotted heap.
+    ///
+    /// Passing bookkeeping that does not match the heap's contents is memory
+    /// safe, but may result in general failures such as panics or incorrect
+    /// results.
+    fn restore_snapshot(&mut self, snapshot: &[u8], heap_len: usize) -> Result<()>;
+
     ////////////////////////////////////////////////////////////////////////////
     // Provided helper methods.
 
@@ -62,6 +62,11 @@ impl ExternRefHostDataTable {
         let data: &mut Box<dyn Any + Send + Sync> = self.slab.get_mut(id.0).unwrap();
         deref_box_mut(data)
     }
+
+    /// Is this table empty, i.e. are there no live `externref`s?
+    pub fn is_empty(&self) -> bool {
+        self.slab.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -12,6 +12,8 @@ use crate::runtime::vm::vmcontext::{
     VMGlobalImport, VMMemoryDefinition, VMMemoryImport, VMOpaqueContext, VMStoreContext,
     VMTableDefinition, VMTableImport, VMTagDefinition, VMTagImport,
 };
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+use crate::runtime::vm::vmcontext::{VMLazyFunc, ValRaw};
 use crate::runtime::vm::{
     ExportFunction, ExportGlobal, ExportGlobalKind, ExportMemory, ExportTable, ExportTag, GcStore,
     Imports, ModuleRuntimeInfo, SendSyncPtr, VMGcRef, VMStore, VMStoreRawPtr, VmPtr, VmSafe,
@@ -36,6 +38,8 @@ use wasmtime_environ::{
     Trap, VMCONTEXT_MAGIC, VMOffsets, VMSharedTypeIndex, WasmHeapTopType,
     packed_option::ReservedValue,
 };
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+use wasmtime_environ::{DefinedFuncIndex, VM_LAZY_FUNC_MAGIC};
 #[cfg(feature = "wmemcheck")]
 use wasmtime_wmemcheck::Wmemcheck;
 
@@ -221,7 +225,9 @@ pub struct Instance {
     ///
     /// The `MemoryAllocationIndex` was given from our `InstanceAllocator` and
     /// must be given back to the instance allocator when deallocating each
-    /// memory.
+    /// memory. Memories put in place by `replace_defined_memory` weren't
+    /// allocated by it and have the default index, which allocators free by
+    /// dropping the memory.
     memories: PrimaryMap<DefinedMemoryIndex, (MemoryAllocationIndex, Memory)>,
 
     /// WebAssembly table data.
@@ -231,7 +237,8 @@ pub struct Instance {
     ///
     /// The `TableAllocationIndex` was given from our `InstanceAllocator` and
     /// must be given back to the instance allocator when deallocating each
-    /// table.
+    /// table. As with memories, tables put in place by
+    /// `replace_defined_table` have the default index.
     tables: PrimaryMap<DefinedTableIndex, (TableAllocationIndex, Table)>,
 
     /// Stores the dropped passive element segments in this instantiation by index.
@@ -345,7 +352,7 @@ impl Instance {
         self.runtime_info.env_module()
     }
 
-    #[cfg(feature = "gc")]
+    #[cfg(any(feature = "gc", feature = "cranelift", feature = "winch"))]
     pub(crate) fn runtime_module(&self) -> Option<&crate::Module> {
         match &self.runtime_info {
             ModuleRuntimeInfo::Module(m) => Some(m),
@@ -827,6 +834,16 @@ impl Instance {
         into: *mut VMFuncRef,
     ) {
         let func_ref = if let Some(def_index) = self.env_module().defined_func_index(index) {
+            #[cfg(any(feature = "cranelift", feature = "winch"))]
+            if self.env_module().num_lazy_funcs > 0 {
+                let func_ref = self.lazy_func_ref(def_index, type_index);
+                // SAFETY: the unsafe contract here is forwarded to callers of
+                // this function.
+                unsafe {
+                    ptr::write(into, func_ref);
+                }
+                return;
+            }
             VMFuncRef {
                 array_call: self
                     .runtime_info
@@ -854,6 +871,146 @@ impl Instance {
         }
     }
 
+    /// Returns the `VMFuncRef` of the defined function `index` of a module
+    /// compiled with tiered compilation.
+    ///
+    /// This refers to the best code compiled so far for the function, or to a
+    /// trampoline which compiles the function on its first call.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    fn lazy_func_ref(&self, index: DefinedFuncIndex, type_index: VMSharedTypeIndex) -> VMFuncRef {
+        let module = self.runtime_module().unwrap();
+        match module.tiered().unwrap().function(index) {
+            Some(func) => {
+                // SAFETY: the `VMLazyFunc` is within our `VMContext`, and the
+                // lazy trampoline only reads it on this thread.
+                unsafe {
+                    self.lazy_func(index).as_mut().wasm_call = Some(func.wasm_call().into());
+                }
+                VMFuncRef {
+                    array_call: func.array_call().into(),
+                    wasm_call: Some(func.wasm_call().into()),
+                    vmctx: VMOpaqueContext::from_vmcontext(self.vmctx()).into(),
+                    type_index,
+                }
+            }
+            None => VMFuncRef {
+                array_call: NonNull::new(lazy_array_call as *mut u8)
+                    .unwrap()
+                    .cast()
+                    .into(),
+                wasm_call: Some(
+                    module
+                        .lazy_trampoline(type_index)
+                        .expect("should have a lazy trampoline for every function's type")
+                        .into(),
+                ),
+                vmctx: VMOpaqueContext::from_vm_lazy_func(self.lazy_func(index)).into(),
+                type_index,
+            },
+        }
+    }
+
+    /// Returns a pointer to the `VMLazyFunc` of the defined function `index`.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    fn lazy_func(&self, index: DefinedFuncIndex) -> NonNull<VMLazyFunc> {
+        // SAFETY: the offset calculated here should be correct with
+        // `self.offsets`
+        unsafe { self.vmctx_plus_offset_raw(self.offsets().vmctx_lazy_func(index)) }
+    }
+
+    /// Compiles the defined function `index` with tiered compilation, if it
+    /// hasn't been compiled yet, and updates its `VMFuncRef` to refer to its
+    /// code.
+    ///
+    /// Returns the function's updated `VMFuncRef`, or the error compiling
+    /// the function failed with.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub(crate) fn lazy_compile(
+        self: Pin<&mut Self>,
+        index: DefinedFuncIndex,
+    ) -> Result<NonNull<VMFuncRef>> {
+        self.runtime_module().unwrap().lazy_compile(index)?;
+        let index = self.env_module().func_index(index);
+        Ok(self.get_func_ref(index).unwrap())
+    }
+
+    /// Recompiles the defined function `index` with the optimizing compiler
+    /// with tiered compilation, if that hasn't happened yet, and updates its
+    /// `VMFuncRef` to refer to its new code.
+    ///
+    /// All other defined functions which are at least halfway to being
+    /// recompiled are recompiled along with it, so that they share a single
+    /// compilation and code allocation. These functions stop counting their
+    /// calls afterwards.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub(crate) fn tier_up(mut self: Pin<&mut Self>, index: DefinedFuncIndex) {
+        let threshold = self.tier_up_threshold();
+        let module = self.runtime_module().unwrap().clone();
+        let mut batch = vec![index];
+        // SAFETY: the `VMLazyFunc`s are within our `VMContext`, and aren't
+        // otherwise borrowed.
+        unsafe {
+            batch.extend(module.env_module().defined_func_indices().filter(|&other| {
+                other != index && self.lazy_func(other).as_ref().calls_left <= threshold / 2
+            }));
+            for &index in &batch {
+                self.lazy_func(index).as_mut().calls_left = u32::MAX;
+            }
+        }
+
+        module.tier_up(&batch);
+        let tiered = module.tiered().unwrap();
+        for index in batch {
+            match tiered.optimized(index) {
+                Some(true) => {
+                    let index = self.env_module().func_index(index);
+                    self.as_mut().get_func_ref(index);
+                }
+                Some(false) => {}
+                // Another thread is still recompiling the function, so try
+                // again later.
+                //
+                // SAFETY: as above.
+                None => unsafe {
+                    self.lazy_func(index).as_mut().calls_left = threshold;
+                },
+            }
+        }
+    }
+
+    /// Returns the number of calls after which a function compiled with
+    /// tiered compilation is recompiled with the optimizing compiler.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    fn tier_up_threshold(&self) -> u32 {
+        self.runtime_module()
+            .unwrap()
+            .engine()
+            .config()
+            .tier_up_threshold
+            .max(1)
+    }
+
+    /// Initializes the `VMLazyFunc` of every defined function of a module
+    /// compiled with tiered compilation, along with the functions'
+    /// `VMFuncRef`s which compiled code calls through.
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    fn initialize_lazy_funcs(mut self: Pin<&mut Self>, module: &Module) {
+        let calls_left = self.tier_up_threshold();
+        for index in module.defined_func_indices() {
+            // SAFETY: the `VMLazyFunc` is within our `VMContext`.
+            unsafe {
+                self.lazy_func(index).write(VMLazyFunc {
+                    magic: VM_LAZY_FUNC_MAGIC,
+                    index: index.as_u32(),
+                    vmctx: self.vmctx().into(),
+                    wasm_call: None,
+                    calls_left,
+                });
+            }
+            self.as_mut().get_func_ref(module.func_index(index));
+        }
+    }
+
     /// Get a `&VMFuncRef` for the given `FuncIndex`.
     ///
     /// Returns `None` if the index is the reserved index value.
@@ -1310,6 +1467,80 @@ impl Instance {
         unsafe { &raw mut (*self.tables_mut().get_raw_mut(index).unwrap()).1 }
     }
 
+    /// Exchanges this instance's defined memory `index` with `other`'s, and
+    /// updates both instances' `VMContext`s to match.
+    ///
+    /// Neither memory may be shared.
+    pub(crate) fn swap_defined_memory(
+        mut self: Pin<&mut Self>,
+        mut other: Pin<&mut Instance>,
+        index: DefinedMemoryIndex,
+    ) {
+        core::mem::swap(
+            &mut self.as_mut().memories_mut()[index],
+            &mut other.as_mut().memories_mut()[index],
+        );
+        for mut instance in [self, other] {
+            let memory = &mut instance.as_mut().memories_mut()[index].1;
+            assert!(memory.as_shared_memory().is_none());
+            let vmmemory = memory.vmmemory();
+            instance.set_memory(index, vmmemory);
+        }
+    }
+
+    /// Exchanges this instance's defined table `index` with `other`'s, and
+    /// updates both instances' `VMContext`s to match.
+    pub(crate) fn swap_defined_table(
+        mut self: Pin<&mut Self>,
+        mut other: Pin<&mut Instance>,
+        index: DefinedTableIndex,
+    ) {
+        core::mem::swap(
+            &mut self.as_mut().tables_mut()[index],
+            &mut other.as_mut().tables_mut()[index],
+        );
+        for mut instance in [self, other] {
+            let vmtable = instance.as_mut().tables_mut()[index].1.vmtable();
+            instance.set_table(index, vmtable);
+        }
+    }
+
+    /// Replaces this instance's defined memory `index` with `memory`, which
+    /// must not have been allocated by an `InstanceAllocator`, and updates the
+    /// `VMContext` to match.
+    ///
+    /// Returns the previous memory, which the caller is responsible for
+    /// deallocating.
+    pub(crate) fn replace_defined_memory(
+        mut self: Pin<&mut Self>,
+        index: DefinedMemoryIndex,
+        memory: Memory,
+    ) -> (MemoryAllocationIndex, Memory) {
+        assert!(!memory.is_shared_memory());
+        let vmmemory = memory.vmmemory();
+        let prev = mem::replace(
+            &mut self.as_mut().memories_mut()[index],
+            (MemoryAllocationIndex::default(), memory),
+        );
+        self.set_memory(index, vmmemory);
+        prev
+    }
+
+    /// Same as [`Instance::replace_defined_memory`], but for tables.
+    pub(crate) fn replace_defined_table(
+        mut self: Pin<&mut Self>,
+        index: DefinedTableIndex,
+        mut table: Table,
+    ) -> (TableAllocationIndex, Table) {
+        let vmtable = table.vmtable();
+        let prev = mem::replace(
+            &mut self.as_mut().tables_mut()[index],
+            (TableAllocationIndex::default(), table),
+        );
+        self.set_table(index, vmtable);
+        prev
+    }
+
     pub(crate) fn with_defined_table_index_and_instance<R>(
         self: Pin<&mut Self>,
         index: TableIndex,
@@ -1453,6 +1684,19 @@ impl Instance {
             ));
             ptr = ptr.add(1);
         }
+
+        // Zero the per-function fuel counters used for fuel profiling.
+        let mut ptr = self.vmctx_plus_offset_raw::<u64>(offsets.vmctx_fuel_counters_begin());
+        for _ in 0..module.num_fuel_counters {
+            ptr.write(0);
+            ptr = ptr.add(1);
+        }
+
+        // Initialize the state of functions compiled with tiered compilation.
+        #[cfg(any(feature = "cranelift", feature = "winch"))]
+        if module.num_lazy_funcs > 0 {
+            self.as_mut().initialize_lazy_funcs(module);
+        }
     }
 
     /// Attempts to convert from the host `addr` specified to a WebAssembly
@@ -1479,6 +1723,21 @@ impl Instance {
         fault
     }
 
+    /// Returns the fuel consumed by each defined function of this instance,
+    /// indexed by `DefinedFuncIndex`.
+    ///
+    /// This is empty unless fuel profiling is enabled.
+    pub fn fuel_counters(&self) -> &[u64] {
+        let len = self.env_module().num_fuel_counters;
+        // SAFETY: the `VMContext` contains `num_fuel_counters` initialized
+        // counters at this offset, which are only written by wasm running on
+        // this thread.
+        unsafe {
+            let ptr = self.vmctx_plus_offset_raw::<u64>(self.offsets().vmctx_fuel_counters_begin());
+            core::slice::from_raw_parts(ptr.as_ptr(), len)
+        }
+    }
+
     /// Returns the id, within this instance's store, that it's assigned.
     pub fn id(&self) -> InstanceId {
         self.id
@@ -1563,6 +1822,45 @@ impl Instance {
     }
 }
 
+/// The `array_call` of a function which hasn't been compiled yet with tiered
+/// compilation.
+///
+/// This compiles the function, and then calls it through its updated
+/// `VMFuncRef`. The compiler runs on a thread of its own rather than on the
+/// caller's stack, and failing to compile the function traps with the
+/// compiler's error.
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+unsafe extern "C" fn lazy_array_call(
+    callee: NonNull<VMOpaqueContext>,
+    caller: NonNull<VMContext>,
+    args_and_results: NonNull<ValRaw>,
+    len: usize,
+) -> bool {
+    // SAFETY: this is only used as the `array_call` of `VMFuncRef`s whose
+    // `vmctx` is a `VMLazyFunc`, which is within a live `VMContext`.
+    unsafe {
+        let lazy_func = VMLazyFunc::from_opaque(callee).as_ref();
+        let vmctx = lazy_func.vmctx.as_non_null();
+        let index = DefinedFuncIndex::from_u32(lazy_func.index);
+        let mut func_ref = None;
+        let ok = crate::runtime::vm::traphandlers::catch_unwind_and_record_trap(|| {
+            Instance::from_vmctx(vmctx, |instance| -> Result<()> {
+                func_ref = Some(instance.lazy_compile(index)?);
+                Ok(())
+            })
+        });
+        match func_ref {
+            Some(func_ref) if ok => VMFuncRef::array_call(
+                func_ref,
+                None,
+                caller,
+                NonNull::slice_from_raw_parts(args_and_results, len),
+            ),
+            _ => false,
+        }
+    }
+}
+
 // SAFETY: `layout` should describe this accurately and `OwnedVMContext` is the
 // last field of `ComponentInstance`.
 unsafe impl InstanceLayout for Instance {
@@ -30,10 +30,12 @@ pub use self::on_demand::OnDemandInstanceAllocator;
 
 #[cfg(feature = "pooling-allocator")]
 mod pooling;
+#[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+pub(crate) use self::pooling::UffdSlot;
 #[cfg(feature = "pooling-allocator")]
 pub use self::pooling::{
-    InstanceLimits, PoolConcurrencyLimitError, PoolingInstanceAllocator,
-    PoolingInstanceAllocatorConfig,
+    InstanceLimits, PoolAllocationFailures, PoolConcurrencyLimitError, PoolSlotStats,
+    PoolingAllocationStats, PoolingInstanceAllocator, PoolingInstanceAllocatorConfig,
 };
 
 /// Represents a request for a new runtime instance.
@@ -368,6 +370,12 @@ pub unsafe trait InstanceAllocatorImpl {
 
     /// Allow access to memory regions protected by any protection key.
     fn allow_all_pkeys(&self);
+
+    /// Get this allocator as a pooling allocator, if it is one.
+    #[cfg(feature = "pooling-allocator")]
+    fn as_pooling(&self) -> Option<&PoolingInstanceAllocator> {
+        None
+    }
 }
 
 /// A thing that can allocate instances.
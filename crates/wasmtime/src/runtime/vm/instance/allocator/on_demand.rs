@@ -15,6 +15,16 @@ use wasmtime_environ::{
 #[cfg(feature = "gc")]
 use crate::runtime::vm::{GcHeap, GcHeapAllocationIndex, GcRuntime};
 
+#[cfg(feature = "memory-protection-keys")]
+use super::pooling::MemoryPool;
+#[cfg(feature = "memory-protection-keys")]
+use super::{PoolConcurrencyLimitError, PoolingInstanceAllocatorConfig};
+#[cfg(feature = "memory-protection-keys")]
+use crate::runtime::vm::{
+    HostAlignedByteCount,
+    mpk::{self, ProtectionMask},
+};
+
 #[cfg(feature = "async")]
 use wasmtime_fiber::RuntimeFiberStackCreator;
 
@@ -34,8 +44,22 @@ pub struct OnDemandInstanceAllocator {
     stack_size: usize,
     #[cfg(feature = "async")]
     stack_zeroing: bool,
+    /// Striped slots for linear memories that are tagged with protection
+    /// keys, if enabled with `enable_memory_protection_keys`.
+    #[cfg(feature = "memory-protection-keys")]
+    protected_memories: Option<Arc<MemoryPool>>,
 }
 
+/// The maximum number of memories tagged with protection keys at any one
+/// time. Memories beyond this are allocated without a protection key.
+#[cfg(feature = "memory-protection-keys")]
+const MAX_PROTECTED_MEMORIES: u32 = 10_000;
+
+/// How many slots of protected memories to reserve address space for at a
+/// time.
+#[cfg(feature = "memory-protection-keys")]
+const PROTECTED_MEMORY_CHUNK_SLOTS: u32 = 64;
+
 impl OnDemandInstanceAllocator {
     /// Creates a new on-demand instance allocator.
     pub fn new(
@@ -52,6 +76,8 @@ impl OnDemandInstanceAllocator {
             stack_size,
             #[cfg(feature = "async")]
             stack_zeroing,
+            #[cfg(feature = "memory-protection-keys")]
+            protected_memories: None,
         }
     }
 
@@ -60,6 +86,35 @@ impl OnDemandInstanceAllocator {
     pub fn set_stack_creator(&mut self, stack_creator: Arc<dyn RuntimeFiberStackCreator>) {
         self.stack_creator = Some(stack_creator);
     }
+
+    /// Tag linear memories of up to `max_memory_size` bytes with memory
+    /// protection keys, so that the memories of other stores serve as their
+    /// guard regions.
+    ///
+    /// This does nothing if fewer than two protection keys are available, or
+    /// if `tunables` need no guard region to begin with.
+    #[cfg(feature = "memory-protection-keys")]
+    pub fn enable_memory_protection_keys(
+        &mut self,
+        enable: crate::MpkEnabled,
+        max_keys: usize,
+        max_memory_size: usize,
+        tunables: &Tunables,
+    ) -> Result<()> {
+        let mut config = PoolingInstanceAllocatorConfig::default();
+        config.limits.total_memories = MAX_PROTECTED_MEMORIES;
+        config.limits.max_memory_size =
+            max_memory_size.min(usize::try_from(tunables.memory_reservation).unwrap_or(usize::MAX));
+        config.elastic_chunk_slots = PROTECTED_MEMORY_CHUNK_SLOTS;
+        config.memory_protection_keys = enable;
+        config.max_memory_protection_keys = max_keys;
+        let pool = MemoryPool::new(&config, tunables)
+            .context("failed to reserve memories for memory protection keys")?;
+        if pool.num_stripes() > 1 {
+            self.protected_memories = Some(Arc::new(pool));
+        }
+        Ok(())
+    }
 }
 
 impl Default for OnDemandInstanceAllocator {
@@ -72,6 +127,8 @@ impl Default for OnDemandInstanceAllocator {
             stack_size: 0,
             #[cfg(feature = "async")]
             stack_zeroing: false,
+            #[cfg(feature = "memory-protection-keys")]
+            protected_memories: None,
         }
     }
 }
@@ -117,6 +174,31 @@ unsafe impl InstanceAllocatorImpl for OnDemandInstanceAllocator {
         tunables: &Tunables,
         memory_index: Option<DefinedMemoryIndex>,
     ) -> Result<(MemoryAllocationIndex, Memory)> {
+        // Memories defined by modules are tagged with the store's protection
+        // key when they fit in a protected slot. Protected memories can't move,
+        // so this requires the memory's declared maximum to fit in the slot;
+        // otherwise it could fail to grow to a size its type permits.
+        // Everything else, and any memories beyond the limit of protected
+        // slots, is allocated as usual below; such memories have their own
+        // guard regions.
+        #[cfg(feature = "memory-protection-keys")]
+        if let Some(pool) = &self.protected_memories {
+            let fits = ty
+                .maximum_byte_size()
+                .is_ok_and(|max| max <= u64::try_from(pool.max_memory_bytes()).unwrap());
+            if memory_index.is_some()
+                && request.pkey.is_some()
+                && fits
+                && pool.validate_memory(ty).is_ok()
+            {
+                match pool.allocate(request, ty, tunables, memory_index) {
+                    Ok(result) => return Ok(result),
+                    Err(e) if e.is::<PoolConcurrencyLimitError>() => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
         let creator = self
             .mem_creator
             .as_deref()
@@ -146,9 +228,28 @@ unsafe impl InstanceAllocatorImpl for OnDemandInstanceAllocator {
         &self,
         _memory_index: Option<DefinedMemoryIndex>,
         allocation_index: MemoryAllocationIndex,
-        _memory: Memory,
+        memory: Memory,
     ) {
+        #[cfg(feature = "memory-protection-keys")]
+        if allocation_index != MemoryAllocationIndex::default() {
+            let pool = self
+                .protected_memories
+                .as_ref()
+                .expect("only protected memories have an allocation index");
+            // As in the pooling allocator, but without batching decommits.
+            let mut image = memory.unwrap_static_image();
+            image
+                .clear_and_remain_ready(HostAlignedByteCount::ZERO, |ptr, len| unsafe {
+                    crate::runtime::vm::sys::vm::decommit_pages(ptr, len)
+                        .expect("failed to decommit memory")
+                })
+                .expect("failed to reset memory image");
+            unsafe { pool.deallocate(allocation_index, image) };
+            return;
+        }
+
         debug_assert_eq!(allocation_index, MemoryAllocationIndex::default());
+        let _ = memory;
         // Normal destructors do all the necessary clean up.
     }
 
@@ -203,26 +304,41 @@ unsafe impl InstanceAllocatorImpl for OnDemandInstanceAllocator {
         let _ = stack;
     }
 
-    fn purge_module(&self, _: CompiledModuleId) {}
+    fn purge_module(&self, module: CompiledModuleId) {
+        #[cfg(feature = "memory-protection-keys")]
+        if let Some(pool) = &self.protected_memories {
+            pool.purge_module(module);
+        }
+        let _ = module;
+    }
 
     fn next_available_pkey(&self) -> Option<ProtectionKey> {
-        // The on-demand allocator cannot use protection keys--it requires
-        // back-to-back allocation of memory slots that this allocator cannot
-        // guarantee.
+        // Protection keys require back-to-back allocation of memory slots,
+        // which this allocator only does for memories in its pool of
+        // protected memories.
+        #[cfg(feature = "memory-protection-keys")]
+        if let Some(pool) = &self.protected_memories {
+            return pool.next_available_pkey();
+        }
         None
     }
 
-    fn restrict_to_pkey(&self, _: ProtectionKey) {
-        // The on-demand allocator cannot use protection keys; an on-demand
-        // allocator will never hand out protection keys to the stores its
-        // engine creates.
+    fn restrict_to_pkey(&self, pkey: ProtectionKey) {
+        // Without protected memories an on-demand allocator never hands out
+        // protection keys to the stores its engine creates.
+        #[cfg(feature = "memory-protection-keys")]
+        if self.protected_memories.is_some() {
+            return mpk::allow(ProtectionMask::zero().or(pkey));
+        }
+        let _ = pkey;
         unreachable!()
     }
 
     fn allow_all_pkeys(&self) {
-        // The on-demand allocator cannot use protection keys; an on-demand
-        // allocator will never hand out protection keys to the stores its
-        // engine creates.
+        #[cfg(feature = "memory-protection-keys")]
+        if self.protected_memories.is_some() {
+            return mpk::allow(ProtectionMask::all());
+        }
         unreachable!()
     }
 
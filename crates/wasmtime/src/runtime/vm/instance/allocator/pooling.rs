@@ -18,14 +18,21 @@
 //! item is stored in its own separate pool: [`memory_pool`], [`table_pool`],
 //! [`stack_pool`]. See those modules for more details.
 
+mod chunks;
 mod decommit_queue;
 mod index_allocator;
 mod memory_pool;
+mod stats;
 mod table_pool;
 
 #[cfg(feature = "gc")]
 mod gc_heap_pool;
 
+#[cfg(target_os = "linux")]
+mod uffd;
+#[cfg(target_os = "linux")]
+pub(crate) use self::uffd::UffdSlot;
+
 #[cfg(all(feature = "async"))]
 mod generic_stack_pool;
 #[cfg(all(feature = "async", unix, not(miri)))]
@@ -41,7 +48,8 @@ cfg_if::cfg_if! {
 }
 
 use self::decommit_queue::DecommitQueue;
-use self::memory_pool::MemoryPool;
+pub(super) use self::memory_pool::MemoryPool;
+use self::stats::FailureCounters;
 use self::table_pool::TablePool;
 use super::{
     InstanceAllocationRequest, InstanceAllocatorImpl, MemoryAllocationIndex, TableAllocationIndex,
@@ -80,6 +88,8 @@ use wasmtime_environ::{
     component::{Component, VMComponentOffsets},
 };
 
+pub use self::stats::{PoolAllocationFailures, PoolSlotStats, PoolingAllocationStats};
+
 fn round_up_to_pow2(n: usize, to: usize) -> usize {
     debug_assert!(to > 0);
     debug_assert!(to.is_power_of_two());
@@ -144,6 +154,15 @@ pub struct InstanceLimits {
     /// `memory_reservation` in `Tunables`.
     pub max_memory_size: usize,
 
+    /// The total number of slots dedicated to 64-bit linear memories. When
+    /// zero, 64-bit memories use the same slots as 32-bit ones.
+    pub total_memory64s: u32,
+
+    /// Maximum byte size of a 64-bit linear memory in one of the
+    /// `total_memory64s` slots. Unlike `max_memory_size` this may exceed
+    /// `memory_reservation` in `Tunables`.
+    pub max_memory64_size: usize,
+
     /// The total number of GC heaps in the pool, across all instances.
     #[cfg(feature = "gc")]
     pub total_gc_heaps: u32,
@@ -179,6 +198,11 @@ impl Default for InstanceLimits {
             max_memory_size: 1 << 32, // 4G,
             #[cfg(target_pointer_width = "32")]
             max_memory_size: 10 << 20, // 10 MiB
+            total_memory64s: 0,
+            #[cfg(target_pointer_width = "64")]
+            max_memory64_size: 1 << 32, // 4G,
+            #[cfg(target_pointer_width = "32")]
+            max_memory64_size: 10 << 20, // 10 MiB
             #[cfg(feature = "gc")]
             total_gc_heaps: total,
         }
@@ -191,6 +215,8 @@ impl Default for InstanceLimits {
 pub struct PoolingInstanceAllocatorConfig {
     /// See `PoolingAllocatorConfig::max_unused_warm_slots` in `wasmtime`
     pub max_unused_warm_slots: u32,
+    /// See `PoolingAllocatorConfig::elastic_chunk_slots` in `wasmtime`
+    pub elastic_chunk_slots: u32,
     /// The target number of decommits to do per batch. This is not precise, as
     /// we can queue up decommits at times when we aren't prepared to
     /// immediately flush them, and so we may go over this target size
@@ -224,12 +250,15 @@ pub struct PoolingInstanceAllocatorConfig {
     pub memory_protection_keys: MpkEnabled,
     /// How many memory protection keys to allocate.
     pub max_memory_protection_keys: usize,
+    /// Whether to initialize linear memories lazily with a userfaultfd.
+    pub memory_init_uffd: bool,
 }
 
 impl Default for PoolingInstanceAllocatorConfig {
     fn default() -> PoolingInstanceAllocatorConfig {
         PoolingInstanceAllocatorConfig {
             max_unused_warm_slots: 100,
+            elastic_chunk_slots: 0,
             decommit_batch_size: 1,
             stack_size: 2 << 20,
             limits: InstanceLimits::default(),
@@ -240,6 +269,7 @@ impl Default for PoolingInstanceAllocatorConfig {
             table_keep_resident: 0,
             memory_protection_keys: MpkEnabled::Disable,
             max_memory_protection_keys: 16,
+            memory_init_uffd: false,
         }
     }
 }
@@ -303,6 +333,21 @@ pub struct PoolingInstanceAllocator {
 
     #[cfg(feature = "async")]
     stacks: StackPool,
+
+    failures: PoolFailures,
+}
+
+/// Counters of failed allocations for each of the pooling allocator's pools.
+#[derive(Debug, Default)]
+struct PoolFailures {
+    core_instances: FailureCounters,
+    component_instances: FailureCounters,
+    memories: FailureCounters,
+    tables: FailureCounters,
+    #[cfg(feature = "async")]
+    stacks: FailureCounters,
+    #[cfg(feature = "gc")]
+    gc_heaps: FailureCounters,
 }
 
 #[cfg(debug_assertions)]
@@ -347,9 +392,42 @@ impl PoolingInstanceAllocator {
             gc_heaps: GcHeapPool::new(config)?,
             #[cfg(feature = "async")]
             stacks: StackPool::new(config)?,
+            failures: PoolFailures::default(),
         })
     }
 
+    /// Take a snapshot of this allocator's runtime statistics.
+    pub fn stats(&self) -> PoolingAllocationStats {
+        PoolingAllocationStats {
+            core_instances: self.live_core_instances.load(Ordering::Acquire),
+            core_instance_failures: self.failures.core_instances.snapshot(),
+            component_instances: self.live_component_instances.load(Ordering::Acquire),
+            component_instance_failures: self.failures.component_instances.snapshot(),
+            memory_stripes: self
+                .memories
+                .stripe_stats()
+                .into_iter()
+                .map(PoolSlotStats::from_slots)
+                .collect(),
+            memory64s: self
+                .memories
+                .memory64_stats()
+                .map(PoolSlotStats::from_slots),
+            memory_failures: self.failures.memories.snapshot(),
+            tables: PoolSlotStats::from_slots(self.tables.stats()),
+            table_failures: self.failures.tables.snapshot(),
+            #[cfg(feature = "async")]
+            stacks: PoolSlotStats::from_slots(self.stacks.stats()),
+            #[cfg(feature = "async")]
+            stack_failures: self.failures.stacks.snapshot(),
+            #[cfg(feature = "gc")]
+            gc_heaps: PoolSlotStats::from_slots(self.gc_heaps.stats()),
+            #[cfg(feature = "gc")]
+            gc_heap_failures: self.failures.gc_heaps.snapshot(),
+            decommit_queue_len: self.decommit_queue.lock().unwrap().entity_len(),
+        }
+    }
+
     fn core_instance_size(&self) -> usize {
         round_up_to_pow2(self.limits.core_instance_size, mem::align_of::<Instance>())
     }
@@ -588,11 +666,14 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
         let old_count = self.live_component_instances.fetch_add(1, Ordering::AcqRel);
         if old_count >= u64::from(self.limits.total_component_instances) {
             self.decrement_component_instance_count();
-            return Err(PoolConcurrencyLimitError::new(
-                usize::try_from(self.limits.total_component_instances).unwrap(),
-                "component instances",
-            )
-            .into());
+            return self
+                .failures
+                .component_instances
+                .record(Err(PoolConcurrencyLimitError::new(
+                    usize::try_from(self.limits.total_component_instances).unwrap(),
+                    "component instances",
+                )
+                .into()));
         }
         Ok(())
     }
@@ -606,11 +687,14 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
         let old_count = self.live_core_instances.fetch_add(1, Ordering::AcqRel);
         if old_count >= u64::from(self.limits.total_core_instances) {
             self.decrement_core_instance_count();
-            return Err(PoolConcurrencyLimitError::new(
-                usize::try_from(self.limits.total_core_instances).unwrap(),
-                "core instances",
-            )
-            .into());
+            return self
+                .failures
+                .core_instances
+                .record(Err(PoolConcurrencyLimitError::new(
+                    usize::try_from(self.limits.total_core_instances).unwrap(),
+                    "core instances",
+                )
+                .into()));
         }
         Ok(())
     }
@@ -626,7 +710,11 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
         tunables: &Tunables,
         memory_index: Option<DefinedMemoryIndex>,
     ) -> Result<(MemoryAllocationIndex, Memory)> {
-        self.with_flush_and_retry(|| self.memories.allocate(request, ty, tunables, memory_index))
+        self.failures.memories.record(
+            self.with_flush_and_retry(|| {
+                self.memories.allocate(request, ty, tunables, memory_index)
+            }),
+        )
     }
 
     unsafe fn deallocate_memory(
@@ -635,6 +723,12 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
         allocation_index: MemoryAllocationIndex,
         memory: Memory,
     ) {
+        // Memories that weren't allocated from the pool, see
+        // `Instance::replace_defined_memory`, are freed by their destructor.
+        if allocation_index == MemoryAllocationIndex::default() {
+            return;
+        }
+
         // Reset the image slot. If there is any error clearing the
         // image, just drop it here, and let the drop handler for the
         // slot unmap in a way that retains the address space
@@ -657,7 +751,9 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
         tunables: &Tunables,
         _table_index: DefinedTableIndex,
     ) -> Result<(super::TableAllocationIndex, Table)> {
-        self.with_flush_and_retry(|| self.tables.allocate(request, ty, tunables))
+        self.failures
+            .tables
+            .record(self.with_flush_and_retry(|| self.tables.allocate(request, ty, tunables)))
     }
 
     unsafe fn deallocate_table(
@@ -666,6 +762,12 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
         allocation_index: TableAllocationIndex,
         mut table: Table,
     ) {
+        // As with memories, tables that weren't allocated from the pool are
+        // freed by their destructor.
+        if allocation_index == TableAllocationIndex::default() {
+            return;
+        }
+
         let mut queue = DecommitQueue::default();
         self.tables
             .reset_table_pages_to_zero(allocation_index, &mut table, |ptr, len| {
@@ -677,7 +779,9 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
 
     #[cfg(feature = "async")]
     fn allocate_fiber_stack(&self) -> Result<wasmtime_fiber::FiberStack> {
-        self.with_flush_and_retry(|| self.stacks.allocate())
+        self.failures
+            .stacks
+            .record(self.with_flush_and_retry(|| self.stacks.allocate()))
     }
 
     #[cfg(feature = "async")]
@@ -705,6 +809,10 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
         mpk::allow(ProtectionMask::all());
     }
 
+    fn as_pooling(&self) -> Option<&PoolingInstanceAllocator> {
+        Some(self)
+    }
+
     #[cfg(feature = "gc")]
     fn allocate_gc_heap(
         &self,
@@ -713,8 +821,12 @@ unsafe impl InstanceAllocatorImpl for PoolingInstanceAllocator {
         memory_alloc_index: MemoryAllocationIndex,
         memory: Memory,
     ) -> Result<(GcHeapAllocationIndex, Box<dyn GcHeap>)> {
-        self.gc_heaps
-            .allocate(engine, gc_runtime, memory_alloc_index, memory)
+        self.failures.gc_heaps.record(self.gc_heaps.allocate(
+            engine,
+            gc_runtime,
+            memory_alloc_index,
+            memory,
+        ))
     }
 
     #[cfg(feature = "gc")]
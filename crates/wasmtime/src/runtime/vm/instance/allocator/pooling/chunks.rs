@@ -0,0 +1,163 @@
+//! Address space reservations for pools, made in chunks of slots.
+//!
+//! By default every pool reserves address space for all of its slots when the
+//! pooling allocator is created. When the pooling allocator is configured to be
+//! elastic, pools instead start with a single chunk of slots and reserve more
+//! chunks on demand, once all of their reserved slots are in use, up to their
+//! configured total number of slots:
+//!
+//! ```text
+//! ┌───────────────────────────┐ ┌───────────────────────────┐
+//! │Slot 0│Slot 1│Slot 2│Slot 3│ │Slot 4│Slot 5│Slot 6│Slot 7│ <not reserved>
+//! └───────────────────────────┘ └───────────────────────────┘
+//!            chunk 0                       chunk 1
+//! ```
+//!
+//! Each chunk is its own mapping, laid out exactly like a non-elastic pool with
+//! `slots_per_chunk` slots would be, so guard regions between slots work the
+//! same either way.
+
+use crate::prelude::*;
+use crate::runtime::vm::{HostAlignedByteCount, Mmap, mmap::AlignedLength};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The address space backing one of the pooling allocator's pools.
+#[derive(Debug)]
+pub struct PoolChunks {
+    /// The mapping for each chunk, in order. Only the first `num_reserved`
+    /// chunks are initialized.
+    chunks: Box<[OnceLock<Arc<Mmap<AlignedLength>>>]>,
+    /// How many chunks have been reserved so far.
+    num_reserved: AtomicUsize,
+    /// Held while reserving a new chunk, so that concurrent allocations that
+    /// run out of slots at the same time reserve only one new chunk.
+    grow_lock: Mutex<()>,
+    slots_per_chunk: usize,
+}
+
+impl PoolChunks {
+    /// Create a new, empty set of chunks for a pool of `max_slots` slots, to be
+    /// reserved `slots_per_chunk` slots at a time.
+    ///
+    /// No address space is reserved until `grow` is called.
+    pub fn new(max_slots: usize, slots_per_chunk: usize) -> Self {
+        let slots_per_chunk = slots_per_chunk.max(1);
+        let max_chunks = max_slots.div_ceil(slots_per_chunk);
+        PoolChunks {
+            chunks: std::iter::repeat_with(OnceLock::new)
+                .take(max_chunks)
+                .collect(),
+            num_reserved: AtomicUsize::new(0),
+            grow_lock: Mutex::new(()),
+            slots_per_chunk,
+        }
+    }
+
+    /// Reserve the first chunk, if the pool has any slots at all.
+    ///
+    /// For a non-elastic pool this reserves all of its address space.
+    pub fn reserve_initial(
+        &self,
+        reserve: impl FnOnce(usize) -> Result<Mmap<AlignedLength>>,
+    ) -> Result<()> {
+        if !self.chunks.is_empty() {
+            let grew = self.grow(0, reserve)?;
+            debug_assert!(grew);
+        }
+        Ok(())
+    }
+
+    /// The number of slots in each chunk.
+    #[allow(unused)] // some cfgs don't use this
+    pub fn slots_per_chunk(&self) -> usize {
+        self.slots_per_chunk
+    }
+
+    /// The size of each chunk, given the size of each of its slots.
+    pub fn chunk_bytes(&self, slot_bytes: HostAlignedByteCount) -> Result<HostAlignedByteCount> {
+        slot_bytes
+            .checked_mul(self.slots_per_chunk)
+            .context("size of pool chunk exceeds addressable memory")
+    }
+
+    /// The number of slots whose address space has been reserved.
+    ///
+    /// Note that this may exceed the pool's maximum number of slots when the
+    /// maximum isn't a multiple of the chunk size, in which case the last
+    /// chunk's trailing slots are never used.
+    pub fn reserved_slots(&self) -> usize {
+        self.num_reserved.load(Ordering::Acquire) * self.slots_per_chunk
+    }
+
+    /// Reserve the next chunk, using `reserve` to create its mapping.
+    ///
+    /// `reserve` is given the index of the new chunk's first slot.
+    ///
+    /// `reserved_slots` should be the value of `self.reserved_slots()` that
+    /// the caller observed before deciding that the pool needs to grow. If
+    /// another thread has grown the pool since then, this does nothing and
+    /// returns `Ok(true)`. Returns `Ok(false)` if all chunks have already been
+    /// reserved.
+    pub fn grow(
+        &self,
+        reserved_slots: usize,
+        reserve: impl FnOnce(usize) -> Result<Mmap<AlignedLength>>,
+    ) -> Result<bool> {
+        let _guard = self.grow_lock.lock().unwrap();
+        let num_reserved = self.num_reserved.load(Ordering::Acquire);
+        if num_reserved * self.slots_per_chunk > reserved_slots {
+            return Ok(true);
+        }
+        if num_reserved == self.chunks.len() {
+            return Ok(false);
+        }
+
+        let first_slot = num_reserved * self.slots_per_chunk;
+        log::debug!("reserving pool chunk {num_reserved} starting at slot {first_slot}");
+        let mapping = reserve(first_slot)?;
+        let set = self.chunks[num_reserved].set(Arc::new(mapping));
+        debug_assert!(set.is_ok());
+        self.num_reserved.store(num_reserved + 1, Ordering::Release);
+        Ok(true)
+    }
+
+    /// Get the chunk containing `slot`, along with the slot's index within that
+    /// chunk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chunk containing `slot` hasn't been reserved.
+    pub fn chunk_for_slot(&self, slot: usize) -> (&Arc<Mmap<AlignedLength>>, usize) {
+        let chunk = self.chunks[slot / self.slots_per_chunk]
+            .get()
+            .expect("slot's chunk should be reserved");
+        (chunk, slot % self.slots_per_chunk)
+    }
+
+    /// Get the reserved chunk whose mapping contains `addr`, along with the
+    /// index of that chunk's first slot.
+    #[allow(unused)] // some cfgs don't use this
+    pub fn chunk_containing(&self, addr: usize) -> Option<(&Arc<Mmap<AlignedLength>>, usize)> {
+        self.chunks
+            .iter()
+            .map_while(|chunk| chunk.get())
+            .enumerate()
+            .find(|(_, chunk)| {
+                let base = chunk.as_ptr() as usize;
+                addr >= base && addr < base + chunk.len()
+            })
+            .map(|(i, chunk)| (chunk, i * self.slots_per_chunk))
+    }
+}
+
+/// Compute a pool's number of slots per chunk.
+///
+/// When `elastic_chunk_slots` is zero the pool isn't elastic and all of its
+/// slots go in a single chunk.
+pub fn slots_per_chunk(max_slots: usize, elastic_chunk_slots: u32) -> usize {
+    match usize::try_from(elastic_chunk_slots).unwrap() {
+        0 => max_slots,
+        n => n.min(max_slots),
+    }
+}
@@ -96,6 +96,14 @@ impl DecommitQueue {
         self.raw.len()
     }
 
+    /// How many memories, tables, and stacks are enqueued for decommit?
+    pub fn entity_len(&self) -> usize {
+        let len = self.memories.len() + self.tables.len();
+        #[cfg(feature = "async")]
+        let len = len + self.stacks.len();
+        len
+    }
+
     /// Enqueue a region of memory for decommit.
     ///
     /// It is the caller's responsibility to push the associated data via
@@ -1,5 +1,5 @@
 use super::GcHeapAllocationIndex;
-use super::index_allocator::{SimpleIndexAllocator, SlotId};
+use super::index_allocator::{SimpleIndexAllocator, SlotId, SlotStats};
 use crate::runtime::vm::{GcHeap, GcRuntime, PoolingInstanceAllocatorConfig, Result};
 use crate::vm::{Memory, MemoryAllocationIndex};
 use crate::{Engine, prelude::*};
@@ -78,6 +78,11 @@ impl GcHeapPool {
         self.index_allocator.is_empty()
     }
 
+    /// Get a snapshot of this pool's slot usage.
+    pub fn stats(&self) -> SlotStats {
+        self.index_allocator.stats()
+    }
+
     /// Allocate a single table for the given instance allocation request.
     pub fn allocate(
         &self,
@@ -90,12 +95,7 @@ impl GcHeapPool {
             .index_allocator
             .alloc()
             .map(|slot| GcHeapAllocationIndex(slot.0))
-            .ok_or_else(|| {
-                anyhow!(
-                    "maximum concurrent GC heap limit of {} reached",
-                    self.max_gc_heaps
-                )
-            })?;
+            .ok_or_else(|| super::PoolConcurrencyLimitError::new(self.max_gc_heaps, "GC heaps"))?;
         debug_assert_ne!(allocation_index, GcHeapAllocationIndex::default());
 
         let mut heap = match {
@@ -1,5 +1,6 @@
 #![cfg_attr(not(asan), allow(dead_code))]
 
+use super::index_allocator::SlotStats;
 use crate::PoolConcurrencyLimitError;
 use crate::prelude::*;
 use crate::runtime::vm::PoolingInstanceAllocatorConfig;
@@ -39,6 +40,20 @@ impl StackPool {
         self.live_stacks.load(Ordering::Acquire) == 0
     }
 
+    /// Get a snapshot of this pool's usage.
+    ///
+    /// Stacks aren't reused, so there are never any warm slots.
+    pub fn stats(&self) -> SlotStats {
+        let used = usize::try_from(self.live_stacks.load(Ordering::Acquire)).unwrap();
+        let limit = usize::try_from(self.stack_limit).unwrap();
+        let used = used.min(limit);
+        SlotStats {
+            used,
+            unused_warm: 0,
+            unused_cold: limit - used,
+        }
+    }
+
     pub fn allocate(&self) -> Result<wasmtime_fiber::FiberStack> {
         if self.stack_size == 0 {
             bail!("fiber stack allocation not supported")
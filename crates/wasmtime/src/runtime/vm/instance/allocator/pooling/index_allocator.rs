@@ -40,10 +40,22 @@ impl SimpleIndexAllocator {
         self.0.alloc(None)
     }
 
+    pub fn with_cold_limit(self, limit: usize) -> Self {
+        SimpleIndexAllocator(self.0.with_cold_limit(limit))
+    }
+
+    pub fn raise_cold_limit(&self, limit: usize) {
+        self.0.raise_cold_limit(limit);
+    }
+
     pub(crate) fn free(&self, index: SlotId) {
         self.0.free(index);
     }
 
+    pub fn stats(&self) -> SlotStats {
+        self.0.stats()
+    }
+
     #[cfg(test)]
     #[allow(unused)]
     pub(crate) fn testing_freelist(&self) -> Vec<SlotId> {
@@ -51,6 +63,17 @@ impl SimpleIndexAllocator {
     }
 }
 
+/// Counts of the slots in an index allocator, by state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SlotStats {
+    /// The number of slots currently allocated.
+    pub used: usize,
+    /// The number of slots that are unused but were previously allocated.
+    pub unused_warm: usize,
+    /// The number of slots that have never been allocated.
+    pub unused_cold: usize,
+}
+
 /// A particular defined memory within a particular module.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct MemoryInModule(pub CompiledModuleId, pub DefinedMemoryIndex);
@@ -86,6 +109,12 @@ struct Inner {
     /// matches `max_cold`, there are no more cold slots left.
     last_cold: u32,
 
+    /// Cold slots at or beyond this index may not be allocated yet.
+    ///
+    /// This is the capacity for most pools, but elastic pools raise this
+    /// limit as they reserve address space for more slots.
+    cold_limit: u32,
+
     /// The state of any given slot.
     ///
     /// Records indices in the above list (empty) or two lists (with affinity),
@@ -161,6 +190,7 @@ impl ModuleAffinityIndexAllocator {
     pub fn new(capacity: u32, max_unused_warm_slots: u32) -> Self {
         ModuleAffinityIndexAllocator(Mutex::new(Inner {
             last_cold: 0,
+            cold_limit: capacity,
             max_unused_warm_slots,
             unused_warm_slots: 0,
             module_affine: HashMap::new(),
@@ -308,6 +338,36 @@ impl ModuleAffinityIndexAllocator {
         });
     }
 
+    /// Only allow allocating slots that have never been allocated before if
+    /// their index is less than `limit`.
+    pub fn with_cold_limit(self, limit: usize) -> Self {
+        let mut inner = self.0.into_inner().unwrap();
+        inner.cold_limit = u32::try_from(limit).unwrap_or(u32::MAX);
+        ModuleAffinityIndexAllocator(Mutex::new(inner))
+    }
+
+    /// Raise the limit set by `with_cold_limit` to `limit`.
+    ///
+    /// The limit only ever increases; attempts to lower it are ignored.
+    pub fn raise_cold_limit(&self, limit: usize) {
+        let mut inner = self.0.lock().unwrap();
+        let limit = u32::try_from(limit).unwrap_or(u32::MAX);
+        inner.cold_limit = inner.cold_limit.max(limit);
+    }
+
+    /// Get a snapshot of how many slots are in each state.
+    pub fn stats(&self) -> SlotStats {
+        let inner = self.0.lock().unwrap();
+        let total_slots = inner.slot_state.len();
+        let unused_cold = total_slots - inner.last_cold as usize;
+        let unused_warm = inner.unused_warm_slots as usize;
+        SlotStats {
+            used: total_slots - unused_cold - unused_warm,
+            unused_warm,
+            unused_cold,
+        }
+    }
+
     /// Return the number of empty slots available in this allocator.
     #[cfg(test)]
     pub fn num_empty_slots(&self) -> usize {
@@ -386,7 +446,7 @@ impl Inner {
     }
 
     fn pick_cold(&mut self) -> Option<SlotId> {
-        if (self.last_cold as usize) == self.slot_state.len() {
+        if (self.last_cold as usize) == self.slot_state.len() || self.last_cold >= self.cold_limit {
             None
         } else {
             let ret = Some(SlotId(self.last_cold));
@@ -500,6 +560,31 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_stats() {
+        let state = ModuleAffinityIndexAllocator::new(4, 10);
+        let stats = |used, unused_warm, unused_cold| SlotStats {
+            used,
+            unused_warm,
+            unused_cold,
+        };
+        assert_eq!(state.stats(), stats(0, 0, 4));
+
+        let a = state.alloc(None).unwrap();
+        let b = state.alloc(None).unwrap();
+        assert_eq!(state.stats(), stats(2, 0, 2));
+
+        state.free(a);
+        assert_eq!(state.stats(), stats(1, 1, 2));
+
+        // Reusing a warm slot moves it back to used.
+        state.free(b);
+        for _ in 0..4 {
+            state.alloc(None).unwrap();
+        }
+        assert_eq!(state.stats(), stats(4, 0, 0));
+    }
+
     #[test]
     fn test_affinity_allocation_strategy() {
         let id1 = MemoryInModule(CompiledModuleId::new(), DefinedMemoryIndex::new(0));
@@ -50,23 +50,27 @@
 //!
 //! [ColorGuard]: https://plas2022.github.io/files/pdf/SegueColorGuard.pdf
 
+#[cfg(target_os = "linux")]
+use super::uffd::{UffdHandler, UffdLayout};
 use super::{
     MemoryAllocationIndex,
-    index_allocator::{MemoryInModule, ModuleAffinityIndexAllocator, SlotId},
+    chunks::{self, PoolChunks},
+    index_allocator::{MemoryInModule, ModuleAffinityIndexAllocator, SlotId, SlotStats},
 };
 use crate::prelude::*;
 use crate::runtime::vm::{
     CompiledModuleId, InstanceAllocationRequest, InstanceLimits, Memory, MemoryBase,
-    MemoryImageSlot, Mmap, MmapOffset, PoolingInstanceAllocatorConfig, mmap::AlignedLength,
+    MemoryImageSlot, Mmap, MmapOffset, PoolingInstanceAllocatorConfig,
+    mmap::{AlignedLength, HUGE_PAGE_SIZE},
 };
 use crate::{
     MpkEnabled,
     runtime::vm::mpk::{self, ProtectionKey, ProtectionMask},
     vm::HostAlignedByteCount,
 };
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
-use wasmtime_environ::{DefinedMemoryIndex, Module, Tunables};
+use wasmtime_environ::{DefinedMemoryIndex, IndexType, Module, Tunables};
 
 /// A set of allocator slots.
 ///
@@ -87,7 +91,7 @@ struct Stripe {
 ///
 /// A linear memory is divided into accessible pages and guard pages. A memory
 /// pool contains linear memories: each memory occupies a slot in an
-/// allocated slab (i.e., `mapping`):
+/// allocated slab (i.e., one of the pool's `chunks`):
 ///
 /// ```text
 ///          layout.max_memory_bytes                 layout.slot_bytes
@@ -102,9 +106,14 @@ struct Stripe {
 ///             |
 ///   layout.pre_slab_guard_size
 /// ```
+///
+/// Unless the pool is elastic, there is a single chunk holding every slot.
+///
+/// If slots are dedicated to 64-bit memories, they form a second pool, with
+/// its own layout, held in `memory64`.
 #[derive(Debug)]
 pub struct MemoryPool {
-    mapping: Arc<Mmap<AlignedLength>>,
+    chunks: PoolChunks,
     /// This memory pool is stripe-aware. If using  memory protection keys, this
     /// will contain one stripe per available key; otherwise, a single stripe
     /// with an empty key.
@@ -114,8 +123,8 @@ pub struct MemoryPool {
     /// dynamically transfer ownership of a slot to a Memory when in use.
     image_slots: Vec<Mutex<Option<MemoryImageSlot>>>,
 
-    /// A description of the various memory sizes used in allocating the
-    /// `mapping` slab.
+    /// A description of the various memory sizes used in allocating each
+    /// chunk's slab.
     layout: SlabLayout,
 
     /// The maximum number of memories that a single core module instance may
@@ -137,6 +146,19 @@ pub struct MemoryPool {
     /// Keep track of protection keys handed out to initialized stores; this
     /// allows us to round-robin the assignment of stores to stripes.
     next_available_pkey: AtomicUsize,
+
+    /// If linear memories are initialized lazily with a userfaultfd, its
+    /// handler. Every chunk of the pool is registered with it.
+    #[cfg(target_os = "linux")]
+    uffd: Option<UffdHandler>,
+
+    /// Whether slots are aligned to, and backed by, huge pages.
+    huge_pages: bool,
+
+    /// The pool of slots dedicated to 64-bit memories, if any. Its slots are
+    /// numbered after this pool's: an allocation index of `image_slots.len()`
+    /// refers to its first slot.
+    memory64: Option<Box<MemoryPool>>,
 }
 
 impl MemoryPool {
@@ -150,6 +172,29 @@ impl MemoryPool {
                 tunables.memory_reservation
             );
         }
+        let mut pool = Self::new_slots(config, tunables)?;
+
+        // 64-bit memories always have explicit bounds checks, so their slots
+        // don't need to be sized to elide them and their maximum size may
+        // exceed the memory reservation. Slots are still at least as large as
+        // the reservation plus guard since compiled code may rely on that much
+        // being either accessible or inaccessible.
+        if config.limits.total_memory64s > 0 {
+            let mut config64 = *config;
+            config64.limits.total_memories = config.limits.total_memory64s;
+            config64.limits.max_memory_size = config.limits.max_memory64_size;
+            config64.limits.total_memory64s = 0;
+            config64.memory_protection_keys = MpkEnabled::Disable;
+            let pool64 = Self::new_slots(&config64, tunables)
+                .context("failed to create the pool for 64-bit memories")?;
+            pool.memory64 = Some(Box::new(pool64));
+        }
+
+        Ok(pool)
+    }
+
+    /// Create a pool of `config.limits.total_memories` slots.
+    fn new_slots(config: &PoolingInstanceAllocatorConfig, tunables: &Tunables) -> Result<Self> {
         let pkeys = match config.memory_protection_keys {
             MpkEnabled::Auto => {
                 if mpk::is_supported() {
@@ -182,54 +227,63 @@ impl MemoryPool {
             mpk::allow(ProtectionMask::all());
         }
 
-        // Create a slab layout and allocate it as a completely inaccessible
-        // region to start--`PROT_NONE`.
-        let constraints = SlabConstraints::new(&config.limits, tunables, pkeys.len())?;
-        let layout = calculate(&constraints)?;
+        // Create a slab layout for each chunk of the pool. Non-elastic pools
+        // have a single chunk holding every slot.
+        let mut constraints = SlabConstraints::new(&config.limits, tunables, pkeys.len())?;
+        let total_slots = constraints.num_slots;
+        constraints.num_slots = chunks::slots_per_chunk(total_slots, config.elastic_chunk_slots);
+        let mut layout = calculate(&constraints)?;
+        if layout.num_slots < total_slots {
+            // Each chunk must hold the same number of slots from every stripe
+            // so that a slot's stripe is the same whether it is counted from
+            // the start of the pool or from the start of its chunk.
+            layout.num_slots = layout.num_slots.next_multiple_of(layout.num_stripes);
+        }
+        if tunables.memory_huge_pages {
+            // Keep the start of every slot aligned to a huge page, given that
+            // the first one is. Larger slots only add to their guard region.
+            layout.slot_bytes = HostAlignedByteCount::new(
+                layout
+                    .slot_bytes
+                    .byte_count()
+                    .next_multiple_of(HUGE_PAGE_SIZE),
+            )
+            .expect("huge page size is a multiple of the host page size");
+        }
         log::debug!(
-            "creating memory pool: {constraints:?} -> {layout:?} (total: {})",
+            "creating memory pool: {constraints:?} -> {layout:?} (per chunk: {}, total slots: {total_slots})",
             layout.total_slab_bytes()?
         );
-        let mut mapping =
-            Mmap::accessible_reserved(HostAlignedByteCount::ZERO, layout.total_slab_bytes()?)
-                .context("failed to create memory pool mapping")?;
-
-        // Then, stripe the memory with the available protection keys. This is
-        // unnecessary if there is only one stripe color.
-        if layout.num_stripes >= 2 {
-            let mut cursor = layout.pre_slab_guard_bytes;
-            let pkeys = &pkeys[..layout.num_stripes];
-            for i in 0..constraints.num_slots {
-                let pkey = &pkeys[i % pkeys.len()];
-                let region = unsafe {
-                    mapping.slice_mut(
-                        cursor.byte_count()..cursor.byte_count() + layout.slot_bytes.byte_count(),
-                    )
-                };
-                pkey.protect(region)?;
-                cursor = cursor
-                    .checked_add(layout.slot_bytes)
-                    .context("cursor + slot_bytes overflows")?;
-            }
-            debug_assert_eq!(
-                cursor
-                    .checked_add(layout.post_slab_guard_bytes)
-                    .context("cursor + post_slab_guard_bytes overflows")?,
-                layout.total_slab_bytes()?
-            );
-        }
 
         let image_slots: Vec<_> = std::iter::repeat_with(|| Mutex::new(None))
-            .take(constraints.num_slots)
+            .take(total_slots)
             .collect();
 
+        #[cfg(target_os = "linux")]
+        let uffd = if config.memory_init_uffd {
+            Some(UffdHandler::new(
+                total_slots,
+                UffdLayout {
+                    pre_slab_guard_bytes: layout.pre_slab_guard_bytes,
+                    slot_bytes: layout.slot_bytes,
+                },
+            )?)
+        } else {
+            None
+        };
+        #[cfg(not(target_os = "linux"))]
+        if config.memory_init_uffd {
+            bail!("userfaultfd-based memory initialization is only supported on Linux");
+        }
+
         let create_stripe = |i| {
-            let num_slots = constraints.num_slots / layout.num_stripes
-                + usize::from(constraints.num_slots % layout.num_stripes > i);
+            let num_slots = total_slots / layout.num_stripes
+                + usize::from(total_slots % layout.num_stripes > i);
             let allocator = ModuleAffinityIndexAllocator::new(
                 num_slots.try_into().unwrap(),
                 config.max_unused_warm_slots,
-            );
+            )
+            .with_cold_limit(0);
             Stripe {
                 allocator,
                 pkey: pkeys.get(i).cloned(),
@@ -241,7 +295,7 @@ impl MemoryPool {
 
         let pool = Self {
             stripes,
-            mapping: Arc::new(mapping),
+            chunks: PoolChunks::new(total_slots, layout.num_slots),
             image_slots,
             layout,
             memories_per_instance: usize::try_from(config.limits.max_memories_per_module).unwrap(),
@@ -249,11 +303,98 @@ impl MemoryPool {
                 config.linear_memory_keep_resident,
             )?,
             next_available_pkey: AtomicUsize::new(0),
+            #[cfg(target_os = "linux")]
+            uffd,
+            huge_pages: tunables.memory_huge_pages,
+            memory64: None,
         };
+        pool.chunks
+            .reserve_initial(|first_slot| pool.reserve_chunk(first_slot))?;
+        pool.raise_cold_limits();
 
         Ok(pool)
     }
 
+    /// Reserve the address space for a new chunk of memories, starting at
+    /// `first_slot`, as a completely inaccessible region--`PROT_NONE`.
+    fn reserve_chunk(&self, first_slot: usize) -> Result<Mmap<AlignedLength>> {
+        let total_slab_bytes = self.layout.total_slab_bytes()?;
+        let mut mapping = if self.huge_pages {
+            let slots_bytes = self
+                .layout
+                .slot_bytes
+                .checked_mul(self.layout.num_slots)
+                .context("total size of slots overflows")?;
+            Mmap::reserve_for_huge_pages(
+                total_slab_bytes,
+                self.layout.pre_slab_guard_bytes,
+                slots_bytes,
+            )
+        } else {
+            Mmap::accessible_reserved(HostAlignedByteCount::ZERO, total_slab_bytes)
+        }
+        .context("failed to create memory pool mapping")?;
+
+        // Then, stripe the memory with the available protection keys. This is
+        // unnecessary if there is only one stripe color.
+        if self.layout.num_stripes >= 2 {
+            let mut cursor = self.layout.pre_slab_guard_bytes;
+            for i in 0..self.layout.num_slots {
+                let pkey = self.stripes[i % self.stripes.len()]
+                    .pkey
+                    .as_ref()
+                    .expect("striped pools have a protection key per stripe");
+                let region = unsafe {
+                    mapping.slice_mut(
+                        cursor.byte_count()
+                            ..cursor.byte_count() + self.layout.slot_bytes.byte_count(),
+                    )
+                };
+                pkey.protect(region)?;
+                cursor = cursor
+                    .checked_add(self.layout.slot_bytes)
+                    .context("cursor + slot_bytes overflows")?;
+            }
+            debug_assert_eq!(
+                cursor
+                    .checked_add(self.layout.post_slab_guard_bytes)
+                    .context("cursor + post_slab_guard_bytes overflows")?,
+                self.layout.total_slab_bytes()?
+            );
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(uffd) = &self.uffd {
+            uffd.register(mapping.as_ptr().cast_mut(), mapping.len(), first_slot)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = first_slot;
+
+        Ok(mapping)
+    }
+
+    /// Let each stripe allocate from all of the slots reserved so far.
+    fn raise_cold_limits(&self) {
+        let reserved_per_stripe = self.chunks.reserved_slots() / self.stripes.len();
+        for stripe in &self.stripes {
+            stripe.allocator.raise_cold_limit(reserved_per_stripe);
+        }
+    }
+
+    /// How many stripes, each with its own protection key, slots are divided
+    /// into. This does not count the pool for 64-bit memories.
+    #[allow(unused)] // some cfgs don't use this
+    pub fn num_stripes(&self) -> usize {
+        self.stripes.len()
+    }
+
+    /// The largest size, in bytes, that a memory in one of this pool's slots
+    /// can grow to.
+    #[allow(unused)] // some cfgs don't use this
+    pub fn max_memory_bytes(&self) -> usize {
+        self.layout.max_memory_bytes.byte_count()
+    }
+
     /// Return a protection key that stores can use for requesting new
     pub fn next_available_pkey(&self) -> Option<ProtectionKey> {
         let index = self.next_available_pkey.fetch_add(1, Ordering::SeqCst) % self.stripes.len();
@@ -288,6 +429,9 @@ impl MemoryPool {
 
     /// Validate one memory for this pool.
     pub fn validate_memory(&self, memory: &wasmtime_environ::Memory) -> Result<()> {
+        if let Some(pool) = self.memory64_pool(memory) {
+            return pool.validate_memory(memory);
+        }
         let min = memory.minimum_byte_size().with_context(|| {
             format!("memory has a minimum byte size that cannot be represented in a u64",)
         })?;
@@ -313,6 +457,46 @@ impl MemoryPool {
     #[allow(unused)] // some cfgs don't use this
     pub fn is_empty(&self) -> bool {
         self.stripes.iter().all(|s| s.allocator.is_empty())
+            && self.memory64.as_ref().map_or(true, |p| p.is_empty())
+    }
+
+    /// Get a snapshot of the slot usage of each of this pool's stripes.
+    ///
+    /// This doesn't include the slots dedicated to 64-bit memories, see
+    /// `memory64_stats`.
+    pub fn stripe_stats(&self) -> Vec<SlotStats> {
+        self.stripes.iter().map(|s| s.allocator.stats()).collect()
+    }
+
+    /// Get a snapshot of the slot usage of the slots dedicated to 64-bit
+    /// memories, if any.
+    pub fn memory64_stats(&self) -> Option<SlotStats> {
+        let pool = self.memory64.as_ref()?;
+        // Slots for 64-bit memories don't use protection keys, so they're
+        // always a single stripe.
+        debug_assert_eq!(pool.stripes.len(), 1);
+        Some(pool.stripes[0].allocator.stats())
+    }
+
+    /// Returns the pool of slots dedicated to 64-bit memories if `memory`
+    /// should be allocated from it.
+    fn memory64_pool(&self, memory: &wasmtime_environ::Memory) -> Option<&MemoryPool> {
+        match memory.idx_type {
+            IndexType::I64 => self.memory64.as_deref(),
+            IndexType::I32 => None,
+        }
+    }
+
+    /// If `index` refers to a slot of the pool for 64-bit memories, returns
+    /// that pool and the index relative to it.
+    fn split_memory64_index(
+        &self,
+        index: MemoryAllocationIndex,
+    ) -> Option<(&MemoryPool, MemoryAllocationIndex)> {
+        let num_slots = u32::try_from(self.image_slots.len()).unwrap();
+        let index64 = index.0.checked_sub(num_slots)?;
+        let pool = self.memory64.as_deref().expect("index is within the pool");
+        Some((pool, MemoryAllocationIndex(index64)))
     }
 
     /// Allocate a single memory for the given instance allocation request.
@@ -323,28 +507,45 @@ impl MemoryPool {
         tunables: &Tunables,
         memory_index: Option<DefinedMemoryIndex>,
     ) -> Result<(MemoryAllocationIndex, Memory)> {
-        let stripe_index = if let Some(pkey) = &request.pkey {
-            pkey.as_stripe()
-        } else {
-            debug_assert!(self.stripes.len() < 2);
-            0
+        if let Some(pool) = self.memory64_pool(ty) {
+            let (index, memory) = pool.allocate(request, ty, tunables, memory_index)?;
+            let num_slots = u32::try_from(self.image_slots.len()).unwrap();
+            return Ok((MemoryAllocationIndex(num_slots + index.0), memory));
+        }
+
+        // Note that a store may have a protection key even when allocating
+        // from an unstriped pool, such as the one for 64-bit memories.
+        let stripe_index = match &request.pkey {
+            Some(pkey) if self.stripes.len() > 1 => pkey.as_stripe(),
+            _ => {
+                debug_assert!(self.stripes.len() < 2);
+                0
+            }
         };
 
-        let striped_allocation_index = self.stripes[stripe_index]
-            .allocator
-            .alloc(memory_index.and_then(|mem_idx| {
-                request
-                    .runtime_info
-                    .unique_id()
-                    .map(|id| MemoryInModule(id, mem_idx))
-            }))
-            .map(|slot| StripedAllocationIndex(u32::try_from(slot.index()).unwrap()))
-            .ok_or_else(|| {
-                super::PoolConcurrencyLimitError::new(
+        let affinity = memory_index.and_then(|mem_idx| {
+            request
+                .runtime_info
+                .unique_id()
+                .map(|id| MemoryInModule(id, mem_idx))
+        });
+        let striped_allocation_index = loop {
+            let reserved_slots = self.chunks.reserved_slots();
+            if let Some(slot) = self.stripes[stripe_index].allocator.alloc(affinity) {
+                break StripedAllocationIndex(u32::try_from(slot.index()).unwrap());
+            }
+            if !self
+                .chunks
+                .grow(reserved_slots, |first_slot| self.reserve_chunk(first_slot))?
+            {
+                return Err(super::PoolConcurrencyLimitError::new(
                     self.stripes[stripe_index].allocator.len(),
                     format!("memory stripe {stripe_index}"),
                 )
-            })?;
+                .into());
+            }
+            self.raise_cold_limits();
+        };
         let allocation_index =
             striped_allocation_index.as_unstriped_slot_index(stripe_index, self.stripes.len());
 
@@ -420,6 +621,10 @@ impl MemoryPool {
         allocation_index: MemoryAllocationIndex,
         image: MemoryImageSlot,
     ) {
+        if let Some((pool, index)) = self.split_memory64_index(allocation_index) {
+            return unsafe { pool.deallocate(index, image) };
+        }
+
         self.return_memory_image_slot(allocation_index, image);
 
         let (stripe_index, striped_allocation_index) =
@@ -431,6 +636,10 @@ impl MemoryPool {
 
     /// Purging everything related to `module`.
     pub fn purge_module(&self, module: CompiledModuleId) {
+        if let Some(pool) = &self.memory64 {
+            pool.purge_module(module);
+        }
+
         // This primarily means clearing out all of its memory images present in
         // the virtual address space. Go through the index allocator for slots
         // affine to `module` and reset them, freeing up the index when we're
@@ -476,14 +685,15 @@ impl MemoryPool {
     }
 
     fn get_base(&self, allocation_index: MemoryAllocationIndex) -> MmapOffset {
-        assert!(allocation_index.index() < self.layout.num_slots);
+        assert!(allocation_index.index() < self.image_slots.len());
+        let (chunk, index_in_chunk) = self.chunks.chunk_for_slot(allocation_index.index());
         let offset = self
             .layout
             .slot_bytes
-            .checked_mul(allocation_index.index())
+            .checked_mul(index_in_chunk)
             .and_then(|c| c.checked_add(self.layout.pre_slab_guard_bytes))
             .expect("slot_bytes * index + pre_slab_guard_bytes overflows");
-        self.mapping.offset(offset).expect("offset is in bounds")
+        chunk.offset(offset).expect("offset is in bounds")
     }
 
     /// Take ownership of the given image slot. Must be returned via
@@ -495,11 +705,18 @@ impl MemoryPool {
             .take();
 
         maybe_slot.unwrap_or_else(|| {
-            MemoryImageSlot::create(
-                self.get_base(allocation_index),
-                HostAlignedByteCount::ZERO,
-                self.layout.max_memory_bytes.byte_count(),
-            )
+            let base = self.get_base(allocation_index);
+            let static_size = self.layout.max_memory_bytes.byte_count();
+            #[cfg(target_os = "linux")]
+            if let Some(uffd) = &self.uffd {
+                return MemoryImageSlot::create_uffd(
+                    base,
+                    HostAlignedByteCount::ZERO,
+                    static_size,
+                    uffd.slot(allocation_index.index()),
+                );
+            }
+            MemoryImageSlot::create(base, HostAlignedByteCount::ZERO, static_size)
         })
     }
 
@@ -518,8 +735,8 @@ impl Drop for MemoryPool {
     fn drop(&mut self) {
         // Clear the `clear_no_drop` flag (i.e., ask to *not* clear on
         // drop) for all slots, and then drop them here. This is
-        // valid because the one `Mmap` that covers the whole region
-        // can just do its one munmap.
+        // valid because the `Mmap` of each chunk that covers the whole
+        // region can just do its one munmap.
         for mut slot in std::mem::take(&mut self.image_slots) {
             if let Some(slot) = slot.get_mut().unwrap() {
                 slot.no_clear_on_drop();
@@ -817,7 +1034,7 @@ mod tests {
         assert_eq!(pool.layout.num_slots, 5);
         assert_eq!(pool.layout.max_memory_bytes, WASM_PAGE_SIZE as usize);
 
-        let base = pool.mapping.as_ptr() as usize;
+        let base = pool.chunks.chunk_for_slot(0).0.as_ptr() as usize;
 
         for i in 0..5 {
             let index = MemoryAllocationIndex(i);
@@ -831,6 +1048,68 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_elastic_memory_pool() -> Result<()> {
+        let pool = MemoryPool::new(
+            &PoolingInstanceAllocatorConfig {
+                limits: InstanceLimits {
+                    total_memories: 5,
+                    max_tables_per_module: 0,
+                    max_memories_per_module: 3,
+                    table_elements: 0,
+                    max_memory_size: WASM_PAGE_SIZE as usize,
+                    ..Default::default()
+                },
+                elastic_chunk_slots: 2,
+                memory_protection_keys: MpkEnabled::Disable,
+                ..Default::default()
+            },
+            &Tunables {
+                memory_reservation: WASM_PAGE_SIZE as u64,
+                memory_guard_size: 0,
+                ..Tunables::default_host()
+            },
+        )?;
+
+        assert_eq!(pool.layout.num_slots, 2);
+        assert_eq!(pool.chunks.reserved_slots(), 2);
+        assert_eq!(pool.stripes[0].allocator.len(), 5);
+
+        // Only the reserved slots are handed out until the pool grows.
+        let a = pool.stripes[0].allocator.alloc(None).unwrap();
+        let b = pool.stripes[0].allocator.alloc(None).unwrap();
+        assert!(pool.stripes[0].allocator.alloc(None).is_none());
+
+        while pool
+            .chunks
+            .grow(pool.chunks.reserved_slots(), |first_slot| {
+                pool.reserve_chunk(first_slot)
+            })?
+        {}
+        pool.raise_cold_limits();
+        assert_eq!(pool.chunks.reserved_slots(), 6);
+
+        // Slots in different chunks live in different mappings, but are laid
+        // out the same way within them.
+        for i in 0..5u32 {
+            let (chunk, index_in_chunk) = pool.chunks.chunk_for_slot(usize::try_from(i).unwrap());
+            let ptr = pool.get_base(MemoryAllocationIndex(i)).as_mut_ptr();
+            assert_eq!(
+                ptr as usize - chunk.as_ptr() as usize,
+                index_in_chunk * pool.layout.slot_bytes.byte_count()
+            );
+        }
+
+        let rest: Vec<_> = std::iter::from_fn(|| pool.stripes[0].allocator.alloc(None)).collect();
+        assert_eq!(rest.len(), 3);
+        for id in [a, b].into_iter().chain(rest) {
+            pool.stripes[0].allocator.free(id);
+        }
+
+        Ok(())
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_pooling_allocator_striping() {
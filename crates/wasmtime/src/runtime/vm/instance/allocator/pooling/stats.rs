@@ -0,0 +1,250 @@
+//! Runtime statistics for the pooling allocator.
+//!
+//! The pooling allocator records how full each of its pools is and how often,
+//! and why, allocations from them fail. Embedders can take a snapshot of these
+//! statistics with `Engine::pooling_allocation_stats` to size their
+//! `PoolingAllocationConfig::total_*` settings based on observed usage.
+
+use super::PoolConcurrencyLimitError;
+use super::index_allocator::SlotStats;
+use crate::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of the pooling allocator's runtime statistics.
+///
+/// This is returned by
+/// [`Engine::pooling_allocation_stats`](crate::Engine::pooling_allocation_stats).
+/// Each pool's statistics are read independently of the others, so a snapshot
+/// taken while other threads are allocating may be slightly inconsistent
+/// across pools.
+#[derive(Clone, Debug)]
+pub struct PoolingAllocationStats {
+    pub(super) core_instances: u64,
+    pub(super) core_instance_failures: PoolAllocationFailures,
+    pub(super) component_instances: u64,
+    pub(super) component_instance_failures: PoolAllocationFailures,
+    pub(super) memory_stripes: Vec<PoolSlotStats>,
+    pub(super) memory64s: Option<PoolSlotStats>,
+    pub(super) memory_failures: PoolAllocationFailures,
+    pub(super) tables: PoolSlotStats,
+    pub(super) table_failures: PoolAllocationFailures,
+    #[cfg(feature = "async")]
+    pub(super) stacks: PoolSlotStats,
+    #[cfg(feature = "async")]
+    pub(super) stack_failures: PoolAllocationFailures,
+    #[cfg(feature = "gc")]
+    pub(super) gc_heaps: PoolSlotStats,
+    #[cfg(feature = "gc")]
+    pub(super) gc_heap_failures: PoolAllocationFailures,
+    pub(super) decommit_queue_len: usize,
+}
+
+impl PoolingAllocationStats {
+    /// The number of core module instances that are currently live.
+    pub fn core_instances(&self) -> u64 {
+        self.core_instances
+    }
+
+    /// Failed attempts to create a core module instance.
+    pub fn core_instance_failures(&self) -> PoolAllocationFailures {
+        self.core_instance_failures
+    }
+
+    /// The number of component instances that are currently live.
+    pub fn component_instances(&self) -> u64 {
+        self.component_instances
+    }
+
+    /// Failed attempts to create a component instance.
+    pub fn component_instance_failures(&self) -> PoolAllocationFailures {
+        self.component_instance_failures
+    }
+
+    /// Slot usage of the linear memory pool, summed across all of its
+    /// stripes and the slots dedicated to 64-bit memories, if any.
+    pub fn memories(&self) -> PoolSlotStats {
+        self.memory_stripes
+            .iter()
+            .chain(&self.memory64s)
+            .fold(PoolSlotStats::default(), |a, b| PoolSlotStats {
+                capacity: a.capacity + b.capacity,
+                in_use: a.in_use + b.in_use,
+                unused_warm: a.unused_warm + b.unused_warm,
+                unused_cold: a.unused_cold + b.unused_cold,
+            })
+    }
+
+    /// Slot usage of each stripe of the linear memory pool.
+    ///
+    /// When memory protection keys are in use, the linear memory pool is
+    /// divided into one stripe per key, and each store allocates its linear
+    /// memories from a single stripe. Otherwise there is exactly one stripe.
+    ///
+    /// This doesn't include the slots dedicated to 64-bit memories, which
+    /// aren't striped; see [`memory64s`](Self::memory64s).
+    pub fn memory_stripes(&self) -> &[PoolSlotStats] {
+        &self.memory_stripes
+    }
+
+    /// Slot usage of the slots dedicated to 64-bit linear memories, or `None`
+    /// if there are none.
+    ///
+    /// See
+    /// [`PoolingAllocationConfig::total_memory64s`](crate::PoolingAllocationConfig::total_memory64s).
+    pub fn memory64s(&self) -> Option<PoolSlotStats> {
+        self.memory64s
+    }
+
+    /// Failed attempts to allocate a linear memory.
+    pub fn memory_failures(&self) -> PoolAllocationFailures {
+        self.memory_failures
+    }
+
+    /// Slot usage of the table pool.
+    pub fn tables(&self) -> PoolSlotStats {
+        self.tables
+    }
+
+    /// Failed attempts to allocate a table.
+    pub fn table_failures(&self) -> PoolAllocationFailures {
+        self.table_failures
+    }
+
+    /// Slot usage of the async stack pool.
+    #[cfg(feature = "async")]
+    pub fn stacks(&self) -> PoolSlotStats {
+        self.stacks
+    }
+
+    /// Failed attempts to allocate an async stack.
+    #[cfg(feature = "async")]
+    pub fn stack_failures(&self) -> PoolAllocationFailures {
+        self.stack_failures
+    }
+
+    /// Slot usage of the GC heap pool.
+    #[cfg(feature = "gc")]
+    pub fn gc_heaps(&self) -> PoolSlotStats {
+        self.gc_heaps
+    }
+
+    /// Failed attempts to allocate a GC heap.
+    #[cfg(feature = "gc")]
+    pub fn gc_heap_failures(&self) -> PoolAllocationFailures {
+        self.gc_heap_failures
+    }
+
+    /// The number of deallocated linear memories, tables, and stacks that are
+    /// waiting in the shared decommit queue.
+    ///
+    /// Slots in the decommit queue are still counted as in use until the queue
+    /// is flushed. See
+    /// [`PoolingAllocationConfig::decommit_batch_size`](crate::PoolingAllocationConfig::decommit_batch_size).
+    pub fn decommit_queue_len(&self) -> usize {
+        self.decommit_queue_len
+    }
+}
+
+/// Slot usage of a single pool within the pooling allocator.
+///
+/// Every slot in a pool is in exactly one of three states: in use, unused but
+/// previously used ("warm"), or never used ("cold"). Warm slots may still have
+/// memory resident from their previous use; see
+/// [`PoolingAllocationConfig::max_unused_warm_slots`](crate::PoolingAllocationConfig::max_unused_warm_slots).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolSlotStats {
+    capacity: usize,
+    in_use: usize,
+    unused_warm: usize,
+    unused_cold: usize,
+}
+
+impl PoolSlotStats {
+    pub(super) fn from_slots(stats: SlotStats) -> Self {
+        PoolSlotStats {
+            capacity: stats.used + stats.unused_warm + stats.unused_cold,
+            in_use: stats.used,
+            unused_warm: stats.unused_warm,
+            unused_cold: stats.unused_cold,
+        }
+    }
+
+    /// The total number of slots in this pool.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of slots that are currently allocated, including those that
+    /// are waiting to be decommitted.
+    pub fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    /// The number of unused slots that have previously been allocated.
+    pub fn unused_warm(&self) -> usize {
+        self.unused_warm
+    }
+
+    /// The number of unused slots that have never been allocated.
+    pub fn unused_cold(&self) -> usize {
+        self.unused_cold
+    }
+}
+
+/// Counts of failed allocations from a single pool, by reason.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolAllocationFailures {
+    limit_reached: u64,
+    other: u64,
+}
+
+impl PoolAllocationFailures {
+    /// The number of allocations that failed because every slot was in use,
+    /// even after flushing the decommit queue.
+    ///
+    /// These are the allocations that fail with a
+    /// [`PoolConcurrencyLimitError`].
+    pub fn limit_reached(&self) -> u64 {
+        self.limit_reached
+    }
+
+    /// The number of allocations that failed for any other reason, for example
+    /// because initializing a linear memory's image or committing its pages
+    /// failed.
+    pub fn other(&self) -> u64 {
+        self.other
+    }
+
+    /// The total number of failed allocations.
+    pub fn total(&self) -> u64 {
+        self.limit_reached + self.other
+    }
+}
+
+/// Live counters of a pool's allocation failures.
+#[derive(Debug, Default)]
+pub(super) struct FailureCounters {
+    limit_reached: AtomicU64,
+    other: AtomicU64,
+}
+
+impl FailureCounters {
+    /// Count `result` as a failure, if it is one, and pass it along.
+    pub fn record<T>(&self, result: Result<T>) -> Result<T> {
+        if let Err(e) = &result {
+            if e.is::<PoolConcurrencyLimitError>() {
+                self.limit_reached.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.other.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    pub fn snapshot(&self) -> PoolAllocationFailures {
+        PoolAllocationFailures {
+            limit_reached: self.limit_reached.load(Ordering::Relaxed),
+            other: self.other.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use super::{
     TableAllocationIndex,
-    index_allocator::{SimpleIndexAllocator, SlotId},
+    chunks::{self, PoolChunks},
+    index_allocator::{SimpleIndexAllocator, SlotId, SlotStats},
 };
 use crate::runtime::vm::sys::vm::commit_pages;
 use crate::runtime::vm::{
@@ -18,7 +19,7 @@ use wasmtime_environ::{Module, Tunables};
 #[derive(Debug)]
 pub struct TablePool {
     index_allocator: SimpleIndexAllocator,
-    mapping: Mmap<AlignedLength>,
+    chunks: PoolChunks,
     table_size: HostAlignedByteCount,
     max_total_tables: usize,
     tables_per_instance: usize,
@@ -38,24 +39,54 @@ impl TablePool {
         let max_total_tables = usize::try_from(config.limits.total_tables).unwrap();
         let tables_per_instance = usize::try_from(config.limits.max_tables_per_module).unwrap();
 
-        let allocation_size = table_size
+        table_size
             .checked_mul(max_total_tables)
             .context("total size of tables exceeds addressable memory")?;
 
-        let mapping = Mmap::accessible_reserved(allocation_size, allocation_size)
-            .context("failed to create table pool mapping")?;
-
         let keep_resident = HostAlignedByteCount::new_rounded_up(config.table_keep_resident)?;
 
-        Ok(Self {
-            index_allocator: SimpleIndexAllocator::new(config.limits.total_tables),
-            mapping,
+        let pool = Self {
+            index_allocator: SimpleIndexAllocator::new(config.limits.total_tables)
+                .with_cold_limit(0),
+            chunks: PoolChunks::new(
+                max_total_tables,
+                chunks::slots_per_chunk(max_total_tables, config.elastic_chunk_slots),
+            ),
             table_size,
             max_total_tables,
             tables_per_instance,
             keep_resident,
             nominal_table_elements: config.limits.table_elements,
-        })
+        };
+        pool.chunks.reserve_initial(|_| pool.reserve_chunk())?;
+        pool.index_allocator
+            .raise_cold_limit(pool.chunks.reserved_slots());
+        Ok(pool)
+    }
+
+    /// Reserve the address space for a new chunk of tables.
+    fn reserve_chunk(&self) -> Result<Mmap<AlignedLength>> {
+        let chunk_size = self.chunks.chunk_bytes(self.table_size)?;
+        Mmap::accessible_reserved(chunk_size, chunk_size)
+            .context("failed to create table pool mapping")
+    }
+
+    /// Allocate a table slot, reserving more address space for the pool if
+    /// it is elastic and all of its reserved slots are in use.
+    fn alloc_slot(&self) -> Result<TableAllocationIndex> {
+        loop {
+            let reserved_slots = self.chunks.reserved_slots();
+            if let Some(slot) = self.index_allocator.alloc() {
+                return Ok(TableAllocationIndex(slot.0));
+            }
+            if !self.chunks.grow(reserved_slots, |_| self.reserve_chunk())? {
+                return Err(
+                    super::PoolConcurrencyLimitError::new(self.max_total_tables, "tables").into(),
+                );
+            }
+            self.index_allocator
+                .raise_cold_limit(self.chunks.reserved_slots());
+        }
     }
 
     /// Validate whether this module's tables are allocatable by this pool.
@@ -97,16 +128,22 @@ impl TablePool {
         self.index_allocator.is_empty()
     }
 
+    /// Get a snapshot of this pool's slot usage.
+    pub fn stats(&self) -> SlotStats {
+        self.index_allocator.stats()
+    }
+
     /// Get the base pointer of the given table allocation.
     fn get(&self, table_index: TableAllocationIndex) -> *mut u8 {
         assert!(table_index.index() < self.max_total_tables);
 
+        let (chunk, index_in_chunk) = self.chunks.chunk_for_slot(table_index.index());
         unsafe {
-            self.mapping
+            chunk
                 .as_ptr()
                 .add(
                     self.table_size
-                        .checked_mul(table_index.index())
+                        .checked_mul(index_in_chunk)
                         .expect(
                             "checked in constructor that table_size * table_index doesn't overflow",
                         )
@@ -137,13 +174,7 @@ impl TablePool {
         ty: &wasmtime_environ::Table,
         tunables: &Tunables,
     ) -> Result<(TableAllocationIndex, Table)> {
-        let allocation_index = self
-            .index_allocator
-            .alloc()
-            .map(|slot| TableAllocationIndex(slot.0))
-            .ok_or_else(|| {
-                super::PoolConcurrencyLimitError::new(self.max_total_tables, "tables")
-            })?;
+        let allocation_index = self.alloc_slot()?;
 
         match (|| {
             let base = self.get(allocation_index);
@@ -246,7 +277,7 @@ mod tests {
         assert_eq!(pool.max_total_tables, 7);
         assert_eq!(pool.nominal_table_elements, 100);
 
-        let base = pool.mapping.as_ptr() as usize;
+        let base = pool.chunks.chunk_for_slot(0).0.as_ptr() as usize;
 
         for i in 0..7 {
             let index = TableAllocationIndex(i);
@@ -260,6 +291,38 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_elastic_table_pool() -> Result<()> {
+        let pool = TablePool::new(&PoolingInstanceAllocatorConfig {
+            limits: InstanceLimits {
+                total_tables: 7,
+                table_elements: 100,
+                ..Default::default()
+            },
+            elastic_chunk_slots: 3,
+            ..Default::default()
+        })?;
+        assert_eq!(pool.chunks.reserved_slots(), 3);
+
+        let mut slots = Vec::new();
+        for i in 0..7 {
+            let slot = pool.alloc_slot()?;
+            assert_eq!(slot.index(), i);
+            slots.push(slot);
+        }
+        assert_eq!(pool.chunks.reserved_slots(), 9);
+        assert!(pool.alloc_slot().is_err());
+
+        // Slots in different chunks have distinct addresses, and freed slots
+        // are reused without reserving more chunks.
+        assert_ne!(pool.get(slots[2]), pool.get(slots[3]));
+        pool.index_allocator.free(SlotId(slots[4].0));
+        assert_eq!(pool.alloc_slot()?, slots[4]);
+        assert_eq!(pool.chunks.reserved_slots(), 9);
+
+        Ok(())
+    }
+
     #[test]
     fn test_table_pool_continuations_capacity() -> Result<()> {
         let mkpool = |table_elements: usize| -> Result<TablePool> {
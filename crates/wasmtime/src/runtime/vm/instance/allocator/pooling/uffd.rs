@@ -0,0 +1,447 @@
+//! Lazy initialization of linear memories with `userfaultfd` on Linux.
+//!
+//! When enabled, the memory pool doesn't `mmap` a module's memory image into a
+//! slot on instantiation. Instead every chunk of the pool is registered with a
+//! userfaultfd, and the pages of a slot's accessible memory are left missing.
+//! The first access to a missing page blocks the faulting thread and sends an
+//! event to a handler thread, which fills in the page from the slot's current
+//! memory image (or with zeros outside of the image) and wakes the faulting
+//! thread back up.
+//!
+//! Resetting a slot is then just an `madvise(MADV_DONTNEED)` of its dirty
+//! pages, which makes them missing again, and only the pages that an instance
+//! actually touches are ever copied out of the image. For instances with large
+//! but sparsely accessed images this is cheaper than both copying the whole
+//! image in and `mmap`-ing the image over the slot, which has to be undone with
+//! another `mmap` whenever the slot is reused for a different module.
+//!
+//! Note that memory accesses from the kernel, for example a host `read` into
+//! linear memory on behalf of WASI, are also served by the handler thread.
+//!
+//! A faulting thread stays blocked until its fault is resolved, so the handler
+//! thread never gives up on a fault or exits early. If a page can't be read
+//! from its image it's filled with zeros instead, and errors are logged.
+
+use crate::prelude::*;
+use crate::runtime::vm::{HostAlignedByteCount, MemoryImage, host_page_size};
+use core::ptr::NonNull;
+use rustix::event::{EventfdFlags, PollFd, PollFlags, eventfd, poll};
+use rustix::fd::OwnedFd;
+use rustix::io::Errno;
+use rustix::ioctl::{Setter, Updater, ioctl, opcode};
+use rustix::mm::{MapFlags, ProtFlags, UserfaultfdFlags, mmap_anonymous, munmap, userfaultfd};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Definitions from `<linux/userfaultfd.h>`.
+const UFFD_API: u64 = 0xaa;
+const UFFDIO: u8 = 0xaa;
+const UFFDIO_REGISTER_MODE_MISSING: u64 = 1 << 0;
+const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct UffdioApi {
+    api: u64,
+    features: u64,
+    ioctls: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct UffdioRange {
+    start: u64,
+    len: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct UffdioRegister {
+    range: UffdioRange,
+    mode: u64,
+    ioctls: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct UffdioCopy {
+    dst: u64,
+    src: u64,
+    len: u64,
+    mode: u64,
+    copy: i64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct UffdioZeropage {
+    range: UffdioRange,
+    mode: u64,
+    zeropage: i64,
+}
+
+type ApiIoctl<'a> = Updater<'a, { opcode::read_write::<UffdioApi>(UFFDIO, 0x3f) }, UffdioApi>;
+type RegisterIoctl<'a> =
+    Updater<'a, { opcode::read_write::<UffdioRegister>(UFFDIO, 0x00) }, UffdioRegister>;
+type WakeIoctl = Setter<{ opcode::read::<UffdioRange>(UFFDIO, 0x02) }, UffdioRange>;
+type CopyIoctl<'a> = Updater<'a, { opcode::read_write::<UffdioCopy>(UFFDIO, 0x03) }, UffdioCopy>;
+type ZeropageIoctl<'a> =
+    Updater<'a, { opcode::read_write::<UffdioZeropage>(UFFDIO, 0x04) }, UffdioZeropage>;
+
+/// The layout of each chunk of the memory pool.
+#[derive(Clone, Copy, Debug)]
+pub struct UffdLayout {
+    /// The number of guard bytes at the start of each chunk, before its first
+    /// slot.
+    pub pre_slab_guard_bytes: HostAlignedByteCount,
+    /// The size of each slot.
+    pub slot_bytes: HostAlignedByteCount,
+}
+
+/// A reserved chunk of the memory pool that has been registered with the
+/// userfaultfd.
+#[derive(Debug)]
+struct Region {
+    start: usize,
+    len: usize,
+    first_slot: usize,
+}
+
+/// State shared between the memory pool and the fault handler thread.
+#[derive(Debug)]
+struct Shared {
+    uffd: OwnedFd,
+    layout: UffdLayout,
+    regions: RwLock<Vec<Region>>,
+    /// The memory image currently in use by each slot of the pool.
+    images: Box<[Mutex<Option<Arc<MemoryImage>>>]>,
+}
+
+/// A userfaultfd and the thread handling its page faults, for one memory pool.
+#[derive(Debug)]
+pub struct UffdHandler {
+    shared: Arc<Shared>,
+    /// Written to when the handler is dropped, to stop the handler thread.
+    stop: Arc<OwnedFd>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl UffdHandler {
+    /// Create a new userfaultfd for a memory pool with `num_slots` slots laid
+    /// out as described by `layout`, and spawn its fault handler thread.
+    pub fn new(num_slots: usize, layout: UffdLayout) -> Result<Self> {
+        let uffd = unsafe { userfaultfd(UserfaultfdFlags::CLOEXEC | UserfaultfdFlags::NONBLOCK) }
+            .map_err(|e| match e {
+            Errno::PERM | Errno::ACCESS => anyhow!(
+                "failed to create a userfaultfd: {e}; the process may lack the \
+                     privilege to handle page faults from the kernel (see the \
+                     `vm.unprivileged_userfaultfd` sysctl)"
+            ),
+            e => anyhow!("failed to create a userfaultfd: {e}"),
+        })?;
+
+        let mut api = UffdioApi {
+            api: UFFD_API,
+            ..Default::default()
+        };
+        unsafe { ioctl(&uffd, ApiIoctl::new(&mut api)) }
+            .context("failed to initialize userfaultfd API")?;
+
+        let stop = Arc::new(
+            eventfd(0, EventfdFlags::CLOEXEC).context("failed to create userfaultfd stop event")?,
+        );
+        let page = PageBuffer::new().context("failed to allocate userfaultfd page buffer")?;
+        let shared = Arc::new(Shared {
+            uffd,
+            layout,
+            regions: RwLock::new(Vec::new()),
+            images: std::iter::repeat_with(|| Mutex::new(None))
+                .take(num_slots)
+                .collect(),
+        });
+
+        let thread = std::thread::Builder::new()
+            .name("wasmtime-uffd".to_string())
+            .spawn({
+                let shared = shared.clone();
+                let stop = stop.clone();
+                move || handler_thread(&shared, &stop, page)
+            })
+            .context("failed to spawn userfaultfd handler thread")?;
+
+        Ok(UffdHandler {
+            shared,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Register a newly reserved chunk of the pool, starting at slot
+    /// `first_slot`, with the userfaultfd.
+    pub fn register(&self, start: *mut u8, len: usize, first_slot: usize) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let mut register = UffdioRegister {
+            range: UffdioRange {
+                start: start as u64,
+                len: u64::try_from(len).unwrap(),
+            },
+            mode: UFFDIO_REGISTER_MODE_MISSING,
+            ioctls: 0,
+        };
+        unsafe { ioctl(&self.shared.uffd, RegisterIoctl::new(&mut register)) }
+            .context("failed to register memory pool with userfaultfd")?;
+        self.shared.regions.write().unwrap().push(Region {
+            start: start as usize,
+            len,
+            first_slot,
+        });
+        Ok(())
+    }
+
+    /// Get a handle for changing the image that `slot`'s missing pages are
+    /// filled in from.
+    pub fn slot(&self, slot: usize) -> UffdSlot {
+        assert!(slot < self.shared.images.len());
+        UffdSlot {
+            shared: self.shared.clone(),
+            slot,
+        }
+    }
+}
+
+impl Drop for UffdHandler {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = rustix::io::write(&*self.stop, &1u64.to_ne_bytes());
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A handle to the image that one slot of a userfaultfd-registered memory pool
+/// is lazily initialized from.
+#[derive(Debug)]
+pub struct UffdSlot {
+    shared: Arc<Shared>,
+    slot: usize,
+}
+
+impl UffdSlot {
+    /// Fill in this slot's missing pages from `image` from now on, or with
+    /// zeros if `image` is `None`.
+    ///
+    /// This doesn't affect pages that are already present; callers must
+    /// decommit any pages that should be filled in again.
+    pub fn set_image(&self, image: Option<&Arc<MemoryImage>>) {
+        *self.shared.images[self.slot].lock().unwrap() = image.cloned();
+    }
+}
+
+/// A page-aligned buffer that the handler thread reads image pages into
+/// before copying them into place.
+struct PageBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: the buffer is only ever accessed by the thread that owns it.
+unsafe impl Send for PageBuffer {}
+
+impl PageBuffer {
+    fn new() -> rustix::io::Result<PageBuffer> {
+        let len = host_page_size();
+        let ptr = unsafe {
+            mmap_anonymous(
+                core::ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::PRIVATE,
+            )?
+        };
+        Ok(PageBuffer {
+            ptr: NonNull::new(ptr.cast()).unwrap(),
+            len,
+        })
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the buffer is a live, exclusively owned mapping of `len`
+        // bytes.
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for PageBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = munmap(self.ptr.as_ptr().cast(), self.len);
+        }
+    }
+}
+
+/// How long the handler thread waits before trying again after an unexpected
+/// error from the userfaultfd, to avoid spinning.
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+fn handler_thread(shared: &Shared, stop: &OwnedFd, mut page: PageBuffer) {
+    let page = page.as_mut_slice();
+    loop {
+        let mut fds = [
+            PollFd::new(&shared.uffd, PollFlags::IN),
+            PollFd::new(stop, PollFlags::IN),
+        ];
+        match poll(&mut fds, None) {
+            Ok(_) => {}
+            Err(Errno::INTR) => continue,
+            Err(e) => {
+                log::error!("failed to poll userfaultfd: {e}");
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        }
+        if !fds[1].revents().is_empty() {
+            break;
+        }
+        if fds[0].revents().is_empty() {
+            continue;
+        }
+
+        // Read a `struct uffd_msg`.
+        let mut msg = [0u8; 32];
+        match rustix::io::read(&shared.uffd, &mut msg) {
+            Ok(n) if n == msg.len() => {}
+            Ok(n) => {
+                log::error!("unexpected {n}-byte message from userfaultfd");
+                continue;
+            }
+            Err(Errno::AGAIN | Errno::INTR) => continue,
+            Err(e) => {
+                log::error!("failed to read from userfaultfd: {e}");
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        }
+        if msg[0] != UFFD_EVENT_PAGEFAULT {
+            log::warn!("ignoring unexpected userfaultfd event {:#x}", msg[0]);
+            continue;
+        }
+        let addr = u64::from_ne_bytes(msg[16..24].try_into().unwrap());
+        let addr = usize::try_from(addr).unwrap() & !(page.len() - 1);
+        shared.handle_fault(addr, page);
+    }
+}
+
+impl Shared {
+    /// Resolve the fault of the page at `addr`, using `page` as a buffer.
+    ///
+    /// This always resolves the fault, one way or another, since the faulting
+    /// thread is blocked until it is.
+    fn handle_fault(&self, addr: usize, page: &mut [u8]) {
+        log::trace!("handling userfaultfd page fault at {addr:#x}");
+
+        if let Some((image, offset)) = self.image_at(addr) {
+            match self.copy_from_image(addr, &image, offset, page) {
+                Ok(true) => return,
+                Ok(false) => {}
+                Err(e) => log::error!(
+                    "failed to fill in page at {addr:#x} in memory pool from its image, \
+                     filling it with zeros instead: {e:?}"
+                ),
+            }
+        }
+
+        // Everything outside of a slot's image, and outside of any slot, is
+        // zero.
+        if let Err(e) = self.zeropage(addr, page.len()) {
+            // As a last resort, wake the faulting thread up without resolving
+            // its fault. It retries its access, which faults again and gives
+            // this another go.
+            log::error!("failed to handle page fault at {addr:#x} in memory pool: {e:?}");
+            if let Err(e) = self.wake(addr, page.len()) {
+                log::error!("{e:?}");
+            }
+        }
+    }
+
+    /// Fill in the page at `addr` from `image`, at `offset` within the slot's
+    /// linear memory, if the image covers it.
+    fn copy_from_image(
+        &self,
+        addr: usize,
+        image: &MemoryImage,
+        offset: HostAlignedByteCount,
+        page: &mut [u8],
+    ) -> Result<bool> {
+        if !image.read_page_at(offset, page)? {
+            return Ok(false);
+        }
+        let mut copy = UffdioCopy {
+            dst: addr as u64,
+            src: page.as_ptr() as u64,
+            len: page.len() as u64,
+            mode: 0,
+            copy: 0,
+        };
+        self.resolved(addr, page.len(), unsafe {
+            ioctl(&self.uffd, CopyIoctl::new(&mut copy))
+        })?;
+        Ok(true)
+    }
+
+    /// Fill in the page at `addr` with zeros.
+    fn zeropage(&self, addr: usize, len: usize) -> Result<()> {
+        let mut zeropage = UffdioZeropage {
+            range: UffdioRange {
+                start: addr as u64,
+                len: len as u64,
+            },
+            mode: 0,
+            zeropage: 0,
+        };
+        self.resolved(addr, len, unsafe {
+            ioctl(&self.uffd, ZeropageIoctl::new(&mut zeropage))
+        })
+    }
+
+    /// Find the image of the slot containing `addr`, if any, along with
+    /// `addr`'s offset into that slot's linear memory.
+    fn image_at(&self, addr: usize) -> Option<(Arc<MemoryImage>, HostAlignedByteCount)> {
+        let regions = self.regions.read().unwrap();
+        let region = regions
+            .iter()
+            .find(|r| addr >= r.start && addr < r.start + r.len)?;
+        let offset =
+            (addr - region.start).checked_sub(self.layout.pre_slab_guard_bytes.byte_count())?;
+        let slot_bytes = self.layout.slot_bytes.byte_count();
+        let slot = region.first_slot + offset / slot_bytes;
+        let image = self.images.get(slot)?.lock().unwrap().clone()?;
+        let offset = HostAlignedByteCount::new(offset % slot_bytes)
+            .expect("faulting address and slots are page-aligned");
+        Some((image, offset))
+    }
+
+    /// Finish handling a fault with the result of the ioctl that resolved it.
+    fn resolved(&self, addr: usize, len: usize, result: rustix::io::Result<()>) -> Result<()> {
+        match result {
+            Ok(()) => Ok(()),
+            // The page was already filled in, or the mapping changed while it
+            // was being filled in. Either way the faulting thread just needs to
+            // be woken up to retry its access.
+            Err(Errno::EXIST | Errno::AGAIN) => self.wake(addr, len),
+            Err(e) => Err(e).context("failed to resolve page fault"),
+        }
+    }
+
+    /// Wake up the threads waiting on a fault within `len` bytes at `addr`.
+    fn wake(&self, addr: usize, len: usize) -> Result<()> {
+        let range = UffdioRange {
+            start: addr as u64,
+            len: len as u64,
+        };
+        unsafe { ioctl(&self.uffd, WakeIoctl::new(range)) }
+            .context("failed to wake faulting thread")
+    }
+}
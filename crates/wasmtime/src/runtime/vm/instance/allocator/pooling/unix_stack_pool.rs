@@ -1,6 +1,7 @@
 #![cfg_attr(asan, allow(dead_code))]
 
-use super::index_allocator::{SimpleIndexAllocator, SlotId};
+use super::chunks::{self, PoolChunks};
+use super::index_allocator::{SimpleIndexAllocator, SlotId, SlotStats};
 use crate::prelude::*;
 use crate::runtime::vm::sys::vm::commit_pages;
 use crate::runtime::vm::{
@@ -19,7 +20,7 @@ use crate::runtime::vm::{
 /// from the pool.
 #[derive(Debug)]
 pub struct StackPool {
-    mapping: Mmap<AlignedLength>,
+    chunks: PoolChunks,
     stack_size: HostAlignedByteCount,
     max_stacks: usize,
     page_size: HostAlignedByteCount,
@@ -30,8 +31,6 @@ pub struct StackPool {
 
 impl StackPool {
     pub fn new(config: &PoolingInstanceAllocatorConfig) -> Result<Self> {
-        use rustix::mm::{MprotectFlags, mprotect};
-
         let page_size = HostAlignedByteCount::host_page_size();
 
         // Add a page to the stack size for the guard page when using fiber stacks
@@ -45,25 +44,52 @@ impl StackPool {
 
         let max_stacks = usize::try_from(config.limits.total_stacks).unwrap();
 
-        let allocation_size = stack_size
+        stack_size
             .checked_mul(max_stacks)
             .context("total size of execution stacks exceeds addressable memory")?;
 
-        let mapping = Mmap::accessible_reserved(allocation_size, allocation_size)
+        let pool = Self {
+            chunks: PoolChunks::new(
+                max_stacks,
+                chunks::slots_per_chunk(max_stacks, config.elastic_chunk_slots),
+            ),
+            stack_size,
+            max_stacks,
+            page_size,
+            async_stack_zeroing: config.async_stack_zeroing,
+            async_stack_keep_resident: HostAlignedByteCount::new_rounded_up(
+                config.async_stack_keep_resident,
+            )?,
+            index_allocator: SimpleIndexAllocator::new(config.limits.total_stacks)
+                .with_cold_limit(0),
+        };
+        pool.chunks.reserve_initial(|_| pool.reserve_chunk())?;
+        pool.index_allocator
+            .raise_cold_limit(pool.chunks.reserved_slots());
+        Ok(pool)
+    }
+
+    /// Reserve the address space for a new chunk of stacks, and set up their
+    /// guard pages.
+    fn reserve_chunk(&self) -> Result<Mmap<AlignedLength>> {
+        use rustix::mm::{MprotectFlags, mprotect};
+
+        let chunk_size = self.chunks.chunk_bytes(self.stack_size)?;
+        let mapping = Mmap::accessible_reserved(chunk_size, chunk_size)
             .context("failed to create stack pool mapping")?;
 
         // Set up the stack guard pages.
-        if !allocation_size.is_zero() {
+        if !chunk_size.is_zero() {
             unsafe {
-                for i in 0..max_stacks {
-                    // Safety: i < max_stacks and we've already checked that
-                    // stack_size * max_stacks is valid.
-                    let offset = stack_size.unchecked_mul(i);
+                for i in 0..self.chunks.slots_per_chunk() {
+                    // Safety: i < slots_per_chunk and we've already checked
+                    // that stack_size * slots_per_chunk is valid.
+                    let offset = self.stack_size.unchecked_mul(i);
                     // Make the stack guard page inaccessible.
                     let bottom_of_stack = mapping.as_ptr().add(offset.byte_count()).cast_mut();
                     mprotect(
                         bottom_of_stack.cast(),
-                        page_size.byte_count(),
+                        self.page_size.byte_count(),
                         MprotectFlags::empty(),
                     )
                     .context("failed to protect stack guard page")?;
@@ -71,17 +97,7 @@ impl StackPool {
             }
         }
 
-        Ok(Self {
-            mapping,
-            stack_size,
-            max_stacks,
-            page_size,
-            async_stack_zeroing: config.async_stack_zeroing,
-            async_stack_keep_resident: HostAlignedByteCount::new_rounded_up(
-                config.async_stack_keep_resident,
-            )?,
-            index_allocator: SimpleIndexAllocator::new(config.limits.total_stacks),
-        })
+        Ok(mapping)
     }
 
     /// Are there zero slots in use right now?
@@ -90,19 +106,33 @@ impl StackPool {
         self.index_allocator.is_empty()
     }
 
+    /// Get a snapshot of this pool's slot usage.
+    pub fn stats(&self) -> SlotStats {
+        self.index_allocator.stats()
+    }
+
     /// Allocate a new fiber.
     pub fn allocate(&self) -> Result<wasmtime_fiber::FiberStack> {
         if self.stack_size.is_zero() {
             bail!("pooling allocator not configured to enable fiber stack allocation");
         }
 
-        let index = self
-            .index_allocator
-            .alloc()
-            .ok_or_else(|| super::PoolConcurrencyLimitError::new(self.max_stacks, "fibers"))?
-            .index();
+        let index = loop {
+            let reserved_slots = self.chunks.reserved_slots();
+            if let Some(slot) = self.index_allocator.alloc() {
+                break slot.index();
+            }
+            if !self.chunks.grow(reserved_slots, |_| self.reserve_chunk())? {
+                return Err(
+                    super::PoolConcurrencyLimitError::new(self.max_stacks, "fibers").into(),
+                );
+            }
+            self.index_allocator
+                .raise_cold_limit(self.chunks.reserved_slots());
+        };
 
         assert!(index < self.max_stacks);
+        let (chunk, index_in_chunk) = self.chunks.chunk_for_slot(index);
 
         unsafe {
             // Remove the guard page from the size
@@ -111,10 +141,9 @@ impl StackPool {
                  so it must be >= self.page_size",
             );
 
-            let bottom_of_stack = self
-                .mapping
+            let bottom_of_stack = chunk
                 .as_ptr()
-                .add(self.stack_size.unchecked_mul(index).byte_count())
+                .add(self.stack_size.unchecked_mul(index_in_chunk).byte_count())
                 .cast_mut();
 
             commit_pages(bottom_of_stack, size_without_guard.byte_count())?;
@@ -163,12 +192,12 @@ impl StackPool {
             .top()
             .expect("fiber stack not allocated from the pool") as usize;
 
-        let base = self.mapping.as_ptr() as usize;
-        let len = self.mapping.len();
-        assert!(
-            top > base && top <= (base + len),
-            "fiber stack top pointer not in range"
-        );
+        let (chunk, _) = self
+            .chunks
+            .chunk_containing(top - 1)
+            .expect("fiber stack top pointer not in range");
+        let base = chunk.as_ptr() as usize;
+        let len = chunk.len();
 
         // Remove the guard page from the size.
         let stack_size = self.stack_size.checked_sub(self.page_size).expect(
@@ -217,12 +246,12 @@ impl StackPool {
             .top()
             .expect("fiber stack not allocated from the pool") as usize;
 
-        let base = self.mapping.as_ptr() as usize;
-        let len = self.mapping.len();
-        assert!(
-            top > base && top <= (base + len),
-            "fiber stack top pointer not in range"
-        );
+        let (chunk, first_slot) = self
+            .chunks
+            .chunk_containing(top - 1)
+            .expect("fiber stack top pointer not in range");
+        let base = chunk.as_ptr() as usize;
+        let len = chunk.len();
 
         // Remove the guard page from the size
         let stack_size = self.stack_size.byte_count() - self.page_size.byte_count();
@@ -231,7 +260,7 @@ impl StackPool {
         assert!(start_of_stack >= base && start_of_stack < (base + len));
         assert!((start_of_stack - base) % self.stack_size.byte_count() == 0);
 
-        let index = (start_of_stack - base) / self.stack_size.byte_count();
+        let index = first_slot + (start_of_stack - base) / self.stack_size.byte_count();
         assert!(index < self.max_stacks);
         let index = u32::try_from(index).unwrap();
 
@@ -264,7 +293,7 @@ mod tests {
 
         assert_eq!(pool.index_allocator.testing_freelist(), []);
 
-        let base = pool.mapping.as_ptr() as usize;
+        let base = pool.chunks.chunk_for_slot(0).0.as_ptr() as usize;
 
         let mut stacks = Vec::new();
         for i in 0..10 {
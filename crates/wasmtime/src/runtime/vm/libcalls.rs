@@ -69,7 +69,10 @@ use core::pin::Pin;
 use core::ptr::NonNull;
 #[cfg(feature = "threads")]
 use core::time::Duration;
-use wasmtime_environ::{DataIndex, ElemIndex, FuncIndex, MemoryIndex, TableIndex, Trap};
+use wasmtime_environ::{
+    DataIndex, DefinedFuncIndex, ElemIndex, FuncIndex, MemoryAccessKind, MemoryIndex, TableIndex,
+    Trap,
+};
 #[cfg(feature = "wmemcheck")]
 use wasmtime_wmemcheck::AccessError::{
     DoubleMalloc, InvalidFree, InvalidRead, InvalidWrite, OutOfBounds,
@@ -528,15 +531,28 @@ unsafe fn grow_gc_heap(
 
     // JIT code relies on the memory having grown by `bytes_needed` bytes if
     // this libcall returns successfully, so trap if we didn't grow that much.
-    let new_len = u64::try_from(store.gc_store()?.gc_heap.vmmemory().current_length()).unwrap();
-    if orig_len
-        .checked_add(bytes_needed)
-        .is_none_or(|expected_len| new_len < expected_len)
-    {
-        return Err(crate::Trap::AllocationTooLarge.into());
+    let grew_enough = |store: &mut dyn VMStore| -> Result<bool> {
+        let new_len = u64::try_from(store.gc_store()?.gc_heap.vmmemory().current_length()).unwrap();
+        Ok(orig_len
+            .checked_add(bytes_needed)
+            .is_some_and(|expected_len| new_len >= expected_len))
+    };
+    if grew_enough(store)? {
+        return Ok(());
     }
 
-    Ok(())
+    // Give the resource limiter a chance to make more room before trapping.
+    if store.gc_heap_out_of_memory(bytes_needed)? {
+        store
+            .maybe_async_gc(None, Some(bytes_needed))
+            .context("failed to grow the GC heap")
+            .context(crate::Trap::AllocationTooLarge)?;
+        if grew_enough(store)? {
+            return Ok(());
+        }
+    }
+
+    Err(crate::Trap::AllocationTooLarge.into())
 }
 
 /// Do a GC, keeping `gc_ref` rooted and returning the updated `gc_ref`
@@ -579,7 +595,7 @@ unsafe fn gc(store: &mut dyn VMStore, _instance: Pin<&mut Instance>, gc_ref: u32
 /// Allocate a raw, unininitialized GC object for Wasm code.
 ///
 /// The Wasm code is responsible for initializing the object.
-#[cfg(feature = "gc-drc")]
+#[cfg(any(feature = "gc-drc", feature = "gc-copying"))]
 unsafe fn gc_alloc_raw(
     store: &mut dyn VMStore,
     instance: Pin<&mut Instance>,
@@ -1161,6 +1177,74 @@ unsafe impl HostResultHasUnwindSentinel for NextEpoch {
     }
 }
 
+// Compiles a function on its first call with tiered compilation, returning
+// its code.
+fn lazy_compile(
+    _store: &mut dyn VMStore,
+    instance: Pin<&mut Instance>,
+    func: u32,
+) -> Result<LazyCode> {
+    let func = DefinedFuncIndex::from_u32(func);
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    {
+        let func_ref = instance.lazy_compile(func)?;
+        // SAFETY: the func ref was just initialized by `lazy_compile`.
+        let wasm_call = unsafe { func_ref.as_ref().wasm_call.unwrap() };
+        Ok(LazyCode(wasm_call.as_non_null().cast()))
+    }
+    #[cfg(not(any(feature = "cranelift", feature = "winch")))]
+    {
+        let _ = (instance, func);
+        unreachable!("tiered compilation requires a compiler")
+    }
+}
+
+struct LazyCode(NonNull<u8>);
+
+unsafe impl HostResultHasUnwindSentinel for LazyCode {
+    type Abi = *mut u8;
+    const SENTINEL: *mut u8 = core::ptr::null_mut();
+    fn into_abi(self) -> *mut u8 {
+        self.0.as_ptr()
+    }
+}
+
+// Recompiles a function with the optimizing compiler once it's been called
+// enough times with tiered compilation.
+fn tier_up(_store: &mut dyn VMStore, instance: Pin<&mut Instance>, func: u32) {
+    let func = DefinedFuncIndex::from_u32(func);
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    instance.tier_up(func);
+    #[cfg(not(any(feature = "cranelift", feature = "winch")))]
+    {
+        let _ = (instance, func);
+        unreachable!("tiered compilation requires a compiler")
+    }
+}
+
+// Hook for memory access tracing, invoked before each access to linear memory.
+fn trace_memory_access(
+    store: &mut dyn VMStore,
+    instance: Pin<&mut Instance>,
+    memory: u32,
+    kind: u8,
+    size: u8,
+    addr: u64,
+    func: u32,
+    offset: u32,
+) -> Result<()> {
+    let access = crate::MemoryAccess {
+        kind: MemoryAccessKind::from_u8(kind).unwrap(),
+        instance: crate::Instance::from_wasmtime(instance.id(), store),
+        memory_index: memory,
+        address: addr,
+        size,
+        func_index: func,
+        module_offset: offset,
+    };
+    store.memory_access(access)
+}
+
 // Hook for validating malloc using wmemcheck_state.
 #[cfg(feature = "wmemcheck")]
 unsafe fn check_malloc(
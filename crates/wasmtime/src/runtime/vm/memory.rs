@@ -702,6 +702,14 @@ impl LocalMemory {
                 // dropped
                 // (https://github.com/bytecodealliance/wasmtime/issues/4240).
                 if let Some(store) = store {
+                    // In deterministic mode only exceeding the memory's
+                    // maximum, which the wasm itself declares, may fail
+                    // growth. Anything else depends on the host, so trap.
+                    if store.engine().is_deterministic()
+                        && !maximum.is_some_and(|max| new_byte_size > max)
+                    {
+                        return Err(e.context("memory growth cannot fail in deterministic mode"));
+                    }
                     store.memory_grow_failed(e)?;
                 }
                 Ok(None)
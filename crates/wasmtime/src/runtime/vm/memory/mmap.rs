@@ -43,6 +43,9 @@ pub struct MmapMemory {
     // optimize loads and stores with constant offsets.
     pre_guard_size: HostAlignedByteCount,
     offset_guard_size: HostAlignedByteCount,
+
+    // Whether allocations for this memory are set up to use huge pages.
+    huge_pages: bool,
 }
 
 impl MmapMemory {
@@ -104,7 +107,11 @@ impl MmapMemory {
             .and_then(|i| i.checked_add(offset_guard_bytes))
             .with_context(|| format!("cannot allocate {minimum} with guard regions"))?;
 
-        let mmap = Mmap::accessible_reserved(HostAlignedByteCount::ZERO, request_bytes)?;
+        let mmap = if tunables.memory_huge_pages {
+            Mmap::reserve_for_huge_pages(request_bytes, pre_guard_bytes, alloc_bytes)?
+        } else {
+            Mmap::accessible_reserved(HostAlignedByteCount::ZERO, request_bytes)?
+        };
 
         if minimum > 0 {
             let accessible = HostAlignedByteCount::new_rounded_up(minimum)?;
@@ -121,6 +128,7 @@ impl MmapMemory {
             pre_guard_size: pre_guard_bytes,
             offset_guard_size: offset_guard_bytes,
             extra_to_reserve_on_growth,
+            huge_pages: tunables.memory_huge_pages,
         })
     }
 
@@ -168,8 +176,14 @@ impl RuntimeLinearMemory for MmapMemory {
                 .and_then(|s| s.checked_add(self.offset_guard_size))
                 .context("overflow calculating size of memory allocation")?;
 
-            let mut new_mmap =
-                Mmap::accessible_reserved(HostAlignedByteCount::ZERO, request_bytes)?;
+            let mut new_mmap = if self.huge_pages {
+                let memory_bytes = new_accessible
+                    .checked_add(self.extra_to_reserve_on_growth)
+                    .context("overflow calculating size of memory allocation")?;
+                Mmap::reserve_for_huge_pages(request_bytes, self.pre_guard_size, memory_bytes)?
+            } else {
+                Mmap::accessible_reserved(HostAlignedByteCount::ZERO, request_bytes)?
+            };
             // SAFETY: new_mmap is not in use right now so it's safe to make it
             // accessible.
             unsafe {
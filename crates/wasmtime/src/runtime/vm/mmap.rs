@@ -10,6 +10,10 @@ use core::ptr::NonNull;
 #[cfg(feature = "std")]
 use std::fs::File;
 
+/// The size of the huge pages that linear memories are aligned to when
+/// `Tunables::memory_huge_pages` is enabled.
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
 /// A marker type for an [`Mmap`] where both the start address and length are a
 /// multiple of the host page size.
 ///
@@ -114,6 +118,70 @@ impl Mmap<AlignedLength> {
         }
     }
 
+    /// Create a new `Mmap` reserving `mapping_size` bytes of inaccessible
+    /// memory, set up for the `memory_len` bytes starting `memory_offset`
+    /// bytes into it to be backed by huge pages once made accessible.
+    ///
+    /// The start of that region is aligned to [`HUGE_PAGE_SIZE`] and the
+    /// kernel is asked to use transparent huge pages for it. On platforms
+    /// other than Linux this is the same as a plain reservation.
+    pub fn reserve_for_huge_pages(
+        mapping_size: HostAlignedByteCount,
+        memory_offset: HostAlignedByteCount,
+        memory_len: HostAlignedByteCount,
+    ) -> Result<Self> {
+        assert!(
+            memory_offset
+                .checked_add(memory_len)
+                .is_ok_and(|end| end <= mapping_size)
+        );
+
+        #[cfg(target_os = "linux")]
+        if !mapping_size.is_zero() {
+            let align = HostAlignedByteCount::new(HUGE_PAGE_SIZE)
+                .expect("huge page size is a multiple of the host page size");
+            let result = Mmap {
+                sys: mmap::Mmap::reserve_aligned(mapping_size, memory_offset, align)
+                    .context(format!("mmap failed to reserve {mapping_size:#x} bytes"))?,
+                data: AlignedLength {},
+            };
+            result.advise_huge_pages(memory_offset, memory_len)?;
+            return Ok(result);
+        }
+
+        let _ = (memory_offset, memory_len);
+        Self::accessible_reserved(HostAlignedByteCount::ZERO, mapping_size)
+    }
+
+    /// Ask the kernel to back the `len` bytes starting at `start` with
+    /// transparent huge pages. This is a no-op on platforms other than Linux.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start + len > self.len()`.
+    pub fn advise_huge_pages(
+        &self,
+        start: HostAlignedByteCount,
+        len: HostAlignedByteCount,
+    ) -> Result<()> {
+        assert!(
+            start
+                .checked_add(len)
+                .is_ok_and(|end| end <= self.len_aligned())
+        );
+        #[cfg(target_os = "linux")]
+        if !len.is_zero() {
+            // SAFETY: the range is within this mapping, and this advice
+            // doesn't change the contents of memory.
+            unsafe {
+                self.sys.advise_huge_pages(start, len)?;
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (start, len);
+        Ok(())
+    }
+
     /// Converts this `Mmap` into a `Mmap<UnalignedLength>`.
     ///
     /// `UnalignedLength` really means "_possibly_ unaligned length", so it can
@@ -82,6 +82,68 @@ impl Mmap {
         Ok(Mmap { memory })
     }
 
+    /// Like `reserve`, but places the mapping such that the address `offset`
+    /// bytes into it is a multiple of `align`.
+    #[cfg(target_os = "linux")]
+    pub fn reserve_aligned(
+        size: HostAlignedByteCount,
+        offset: HostAlignedByteCount,
+        align: HostAlignedByteCount,
+    ) -> Result<Self> {
+        assert!(align.byte_count().is_power_of_two());
+
+        // Over-reserve by `align` bytes and then unmap the excess on either
+        // side of the aligned region.
+        let padded = size
+            .checked_add(align)
+            .context("size of aligned reservation overflows")?;
+        let padded = Self::reserve(padded)?;
+        let start = padded.memory.as_ptr().cast::<u8>();
+        let padded_len = padded.len();
+        std::mem::forget(padded);
+
+        let addr = start.addr() + offset.byte_count();
+        let head = addr.next_multiple_of(align.byte_count()) - addr;
+        let tail = padded_len - head - size.byte_count();
+        unsafe {
+            if head > 0 {
+                rustix::mm::munmap(start.cast(), head)?;
+            }
+            if tail > 0 {
+                rustix::mm::munmap(start.add(head + size.byte_count()).cast(), tail)?;
+            }
+        }
+
+        let memory =
+            std::ptr::slice_from_raw_parts_mut(start.wrapping_add(head), size.byte_count());
+        let memory = SendSyncPtr::new(NonNull::new(memory).unwrap());
+        Ok(Mmap { memory })
+    }
+
+    /// Ask the kernel to back the memory starting at `start` and extending
+    /// for `len` bytes with transparent huge pages.
+    #[cfg(target_os = "linux")]
+    pub unsafe fn advise_huge_pages(
+        &self,
+        start: HostAlignedByteCount,
+        len: HostAlignedByteCount,
+    ) -> Result<()> {
+        let result = unsafe {
+            rustix::mm::madvise(
+                self.memory.as_ptr().byte_add(start.byte_count()).cast(),
+                len.byte_count(),
+                rustix::mm::Advice::LinuxHugepage,
+            )
+        };
+        match result {
+            Ok(()) => Ok(()),
+            // Kernels built without transparent huge page support reject this
+            // advice, in which case regular pages are used.
+            Err(rustix::io::Errno::INVAL) => Ok(()),
+            Err(e) => Err(e).context("failed to enable transparent huge pages"),
+        }
+    }
+
     #[cfg(feature = "std")]
     pub fn from_file(file: &File) -> Result<Self> {
         let len = file
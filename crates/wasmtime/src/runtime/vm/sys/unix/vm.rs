@@ -104,49 +104,92 @@ impl MemoryImageSource {
         // On Linux `memfd_create` is used to create an anonymous
         // in-memory file to represent the heap image. This anonymous
         // file is then used as the basis for further mmaps.
+        Ok(create_sealed_memfd("wasm-memory-image", data)?.map(MemoryImageSource::Memfd))
+    }
 
+    /// Create a source for an image in a file in `dir`, named after the
+    /// SHA-256 hash of `data`, or reuse an existing file there with the same
+    /// contents.
+    ///
+    /// Returns `None` if a file by that name exists but has different
+    /// contents, or if the file can't be created, for example because `dir`
+    /// isn't writable.
+    #[cfg(feature = "memory-image-dir")]
+    pub fn from_data_in_dir(
+        dir: &std::path::Path,
+        data: &[u8],
+    ) -> anyhow::Result<Option<MemoryImageSource>> {
+        use anyhow::Context;
+        use core::fmt::Write as _;
+        use core::sync::atomic::{AtomicU64, Ordering};
+        use sha2::{Digest, Sha256};
+        use std::fs::OpenOptions;
         use std::io::{ErrorKind, Write};
+        use std::os::unix::fs::OpenOptionsExt;
+
+        // The name must be the same in every process, even ones built with a
+        // different version of Rust, for processes to find each other's
+        // images. Contents are still checked below before use.
+        let mut name = std::string::String::from("wasmtime-image-");
+        for byte in Sha256::digest(data) {
+            write!(name, "{byte:02x}").unwrap();
+        }
+        let path = dir.join(&name);
 
-        // Create the memfd. It needs a name, but the documentation for
-        // `memfd_create()` says that names can be duplicated with no issues.
-        let memfd = match memfd::MemfdOptions::new()
-            .allow_sealing(true)
-            .create("wasm-memory-image")
-        {
-            Ok(memfd) => memfd,
-            // If this kernel is old enough to not support memfd then attempt to
-            // gracefully handle that and fall back to skipping the memfd
-            // optimization.
-            Err(memfd::Error::Create(err)) if err.kind() == ErrorKind::Unsupported => {
+        let open_existing = || -> anyhow::Result<Option<MemoryImageSource>> {
+            let file = match OpenOptions::new().read(true).open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to open `{}`", path.display()));
+                }
+            };
+            if !file_contents_eq(&file, data)? {
+                log::warn!(
+                    "memory image `{}` has unexpected contents, not using it",
+                    path.display()
+                );
                 return Ok(None);
             }
-            Err(e) => return Err(e.into()),
+            Ok(Some(MemoryImageSource::Mmap(Arc::new(file))))
         };
-        memfd.as_file().write_all(data)?;
-
-        // Seal the memfd's data and length.
-        //
-        // This is a defense-in-depth security mitigation. The
-        // memfd will serve as the starting point for the heap of
-        // every instance of this module. If anything were to
-        // write to this, it could affect every execution. The
-        // memfd object itself is owned by the machinery here and
-        // not exposed elsewhere, but it is still an ambient open
-        // file descriptor at the syscall level, so some other
-        // vulnerability that allowed writes to arbitrary fds
-        // could modify it. Or we could have some issue with the
-        // way that we map it into each instance. To be
-        // extra-super-sure that it never changes, and because
-        // this costs very little, we use the kernel's "seal" API
-        // to make the memfd image permanently read-only.
-        memfd.add_seals(&[
-            memfd::FileSeal::SealGrow,
-            memfd::FileSeal::SealShrink,
-            memfd::FileSeal::SealWrite,
-            memfd::FileSeal::SealSeal,
-        ])?;
-
-        Ok(Some(MemoryImageSource::Memfd(memfd)))
+
+        if let Some(source) = open_existing()? {
+            return Ok(Some(source));
+        }
+
+        // Write the image to a temporary file and then atomically move it into
+        // place, unless another process got there first. Files are created
+        // read-only since their contents must never change.
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let tmp = dir.join(format!(
+            "{name}.{}.{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = (|| -> anyhow::Result<()> {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o444)
+                .open(&tmp)?;
+            file.write_all(data)?;
+            match std::fs::hard_link(&tmp, &path) {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(()),
+                Err(e) => Err(e.into()),
+            }
+        })();
+        let _ = std::fs::remove_file(&tmp);
+        if let Err(e) = result {
+            log::warn!(
+                "failed to create memory image `{}`, not using it: {e}",
+                path.display()
+            );
+            return Ok(None);
+        }
+
+        open_existing()
     }
 
     pub(super) fn as_file(&self) -> &File {
@@ -158,6 +201,14 @@ impl MemoryImageSource {
         }
     }
 
+    /// Read `buf.len()` bytes of this image's source, starting `offset` bytes
+    /// into it.
+    #[cfg(all(feature = "pooling-allocator", target_os = "linux"))]
+    pub fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        self.as_file().read_exact_at(buf, offset)
+    }
+
     pub unsafe fn remap_as_zeros_at(&self, base: *mut u8, len: usize) -> io::Result<()> {
         let ptr = mmap_anonymous(
             base.cast(),
@@ -170,6 +221,75 @@ impl MemoryImageSource {
     }
 }
 
+/// Create a memfd named `name` holding `data` and seal it so that its
+/// contents can never change again.
+///
+/// Returns `None` if the kernel does not support memfd.
+#[cfg(target_os = "linux")]
+pub fn create_sealed_memfd(name: &str, data: &[u8]) -> anyhow::Result<Option<memfd::Memfd>> {
+    use std::io::{ErrorKind, Write};
+
+    // Create the memfd. It needs a name, but the documentation for
+    // `memfd_create()` says that names can be duplicated with no issues.
+    let memfd = match memfd::MemfdOptions::new().allow_sealing(true).create(name) {
+        Ok(memfd) => memfd,
+        // If this kernel is old enough to not support memfd then attempt to
+        // gracefully handle that and fall back to skipping the memfd
+        // optimization.
+        Err(memfd::Error::Create(err)) if err.kind() == ErrorKind::Unsupported => {
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    memfd.as_file().write_all(data)?;
+
+    // Seal the memfd's data and length.
+    //
+    // This is a defense-in-depth security mitigation. The
+    // memfd will serve as the starting point for the heap of
+    // every instance of this module. If anything were to
+    // write to this, it could affect every execution. The
+    // memfd object itself is owned by the machinery here and
+    // not exposed elsewhere, but it is still an ambient open
+    // file descriptor at the syscall level, so some other
+    // vulnerability that allowed writes to arbitrary fds
+    // could modify it. Or we could have some issue with the
+    // way that we map it into each instance. To be
+    // extra-super-sure that it never changes, and because
+    // this costs very little, we use the kernel's "seal" API
+    // to make the memfd image permanently read-only.
+    memfd.add_seals(&[
+        memfd::FileSeal::SealGrow,
+        memfd::FileSeal::SealShrink,
+        memfd::FileSeal::SealWrite,
+        memfd::FileSeal::SealSeal,
+    ])?;
+
+    Ok(Some(memfd))
+}
+
+/// Returns whether the contents of `file` are exactly `data`.
+#[cfg(feature = "memory-image-dir")]
+fn file_contents_eq(file: &File, data: &[u8]) -> io::Result<bool> {
+    use std::os::unix::fs::FileExt;
+
+    if file.metadata()?.len() != u64::try_from(data.len()).unwrap() {
+        return Ok(false);
+    }
+    let mut buf = vec![0; 64 << 10];
+    let mut offset = 0;
+    while offset < data.len() {
+        let chunk = &data[offset..][..buf.len().min(data.len() - offset)];
+        let buf = &mut buf[..chunk.len()];
+        file.read_exact_at(buf, u64::try_from(offset).unwrap())?;
+        if buf != chunk {
+            return Ok(false);
+        }
+        offset += chunk.len();
+    }
+    Ok(true)
+}
+
 impl PartialEq for MemoryImageSource {
     fn eq(&self, other: &MemoryImageSource) -> bool {
         self.as_file().as_raw_fd() == other.as_file().as_raw_fd()
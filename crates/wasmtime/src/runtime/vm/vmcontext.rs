@@ -960,6 +960,94 @@ mod test_vm_func_ref {
     }
 }
 
+/// The context of a function which is compiled lazily with tiered
+/// compilation.
+///
+/// Until such a function is compiled its `VMFuncRef` uses this as its `vmctx`,
+/// along with a trampoline which compiles the function on its first call. One
+/// of these lives in the `VMContext` of an instance for each of its defined
+/// functions, and the count of calls left until the function is recompiled
+/// with the optimizing compiler is stored here too.
+#[derive(Debug)]
+#[repr(C)]
+pub struct VMLazyFunc {
+    /// Always `VM_LAZY_FUNC_MAGIC`, to distinguish this from a `VMContext`.
+    pub magic: u32,
+
+    /// The index of the defined function that this is the context of.
+    pub index: u32,
+
+    /// The `VMContext` of the instance which defines this function.
+    pub vmctx: VmPtr<VMContext>,
+
+    /// The best code compiled so far for this function, once it's been
+    /// compiled.
+    ///
+    /// Copies of the function's original `VMFuncRef`, such as other
+    /// instances' imports of it, keep calling the lazy trampoline, which
+    /// calls this directly when it's set.
+    pub wasm_call: Option<VmPtr<VMWasmCallFunction>>,
+
+    /// The number of calls left until this function is recompiled.
+    pub calls_left: u32,
+    // If more elements are added here, remember to add offset_of tests below!
+}
+
+// SAFETY: the above structure is repr(C) and only contains `VmSafe` fields.
+unsafe impl VmSafe for VMLazyFunc {}
+
+impl VMLazyFunc {
+    /// Converts the `vmctx` of a function's `VMFuncRef` back to a
+    /// `VMLazyFunc`.
+    ///
+    /// # Safety
+    ///
+    /// The `opaque` pointer must actually point to a `VMLazyFunc`.
+    #[inline]
+    pub unsafe fn from_opaque(opaque: NonNull<VMOpaqueContext>) -> NonNull<VMLazyFunc> {
+        // See comments in `VMContext::from_opaque` for this debug assert.
+        //
+        // SAFETY: it's a contract of this function that `opaque` is a valid
+        // pointer.
+        unsafe {
+            debug_assert_eq!(opaque.as_ref().magic, wasmtime_environ::VM_LAZY_FUNC_MAGIC);
+        }
+        opaque.cast()
+    }
+}
+
+#[cfg(test)]
+mod test_vm_lazy_func {
+    use super::VMLazyFunc;
+    use core::mem::offset_of;
+    use std::mem::size_of;
+    use wasmtime_environ::{HostPtr, PtrSize};
+
+    #[test]
+    fn check_vm_lazy_func_offsets() {
+        assert_eq!(
+            size_of::<VMLazyFunc>(),
+            usize::from(HostPtr.size_of_vm_lazy_func())
+        );
+        assert_eq!(
+            offset_of!(VMLazyFunc, index),
+            usize::from(HostPtr.vm_lazy_func_index())
+        );
+        assert_eq!(
+            offset_of!(VMLazyFunc, vmctx),
+            usize::from(HostPtr.vm_lazy_func_vmctx())
+        );
+        assert_eq!(
+            offset_of!(VMLazyFunc, wasm_call),
+            usize::from(HostPtr.vm_lazy_func_wasm_call())
+        );
+        assert_eq!(
+            offset_of!(VMLazyFunc, calls_left),
+            usize::from(HostPtr.vm_lazy_func_calls_left())
+        );
+    }
+}
+
 macro_rules! define_builtin_array {
     (
         $(
@@ -1573,4 +1661,10 @@ impl VMOpaqueContext {
     ) -> NonNull<VMOpaqueContext> {
         ptr.cast()
     }
+
+    /// Helper function to clearly indicate that casts are desired.
+    #[inline]
+    pub fn from_vm_lazy_func(ptr: NonNull<VMLazyFunc>) -> NonNull<VMOpaqueContext> {
+        ptr.cast()
+    }
 }
@@ -19,6 +19,12 @@ impl<T> OnceLock<T> {
         OnceLock(OnceCell::new())
     }
 
+    #[inline]
+    #[cfg(any(feature = "cranelift", feature = "winch"))]
+    pub fn get(&self) -> Option<&T> {
+        self.0.get()
+    }
+
     #[inline]
     pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
         self.0.get_or_init(f)
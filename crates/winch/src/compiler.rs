@@ -97,6 +97,7 @@ impl wasmtime_environ::Compiler for Compiler {
         types: &ModuleTypesBuilder,
         _symbol: &str,
     ) -> Result<CompiledFunctionBody, CompileError> {
+        let def_index = index;
         let index = translation.module.func_index(index);
         let sig = translation.module.functions[index]
             .signature
@@ -110,6 +111,7 @@ impl wasmtime_environ::Compiler for Compiler {
         let func = self
             .isa
             .compile_function(
+                def_index,
                 ty,
                 &body,
                 translation,
@@ -160,6 +162,15 @@ impl wasmtime_environ::Compiler for Compiler {
             .compile_wasm_to_array_trampoline(wasm_func_ty, symbol)
     }
 
+    fn compile_wasm_to_lazy_trampoline(
+        &self,
+        wasm_func_ty: &wasmtime_environ::WasmFuncType,
+        symbol: &str,
+    ) -> Result<CompiledFunctionBody, CompileError> {
+        self.trampolines
+            .compile_wasm_to_lazy_trampoline(wasm_func_ty, symbol)
+    }
+
     fn append_code(
         &self,
         obj: &mut Object<'static>,
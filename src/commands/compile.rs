@@ -7,6 +7,9 @@ use std::path::PathBuf;
 use wasmtime::{CodeBuilder, CodeHint, Engine};
 use wasmtime_cli_flags::CommonOptions;
 
+#[cfg(feature = "pre-init")]
+mod pre_init;
+
 const AFTER_HELP: &str =
     "By default, no CPU features or presets will be enabled for the compilation.\n\
         \n\
@@ -22,7 +25,11 @@ const AFTER_HELP: &str =
         \n\
         Compiling for a specific platform (Linux) and CPU preset (Skylake):\n\
         \n  \
-        wasmtime compile --target x86_64-unknown-linux -Ccranelift-skylake foo.wasm\n";
+        wasmtime compile --target x86_64-unknown-linux -Ccranelift-skylake foo.wasm\n\
+        \n\
+        Pre-initializing a module by running its `init` export before compiling it:\n\
+        \n  \
+        wasmtime compile --pre-init init foo.wasm\n";
 
 /// Compiles a WebAssembly module.
 #[derive(Parser)]
@@ -43,6 +50,20 @@ pub struct CompileCommand {
     #[arg(long = "emit-clif", value_name = "PATH")]
     pub emit_clif: Option<PathBuf>,
 
+    /// Pre-initialize the module by calling the named export, and compile a
+    /// module whose memories and globals start in the resulting state.
+    ///
+    /// The export must take no parameters and return no results. The module
+    /// is instantiated without any imports: calling an imported function
+    /// traps, and importing anything other than functions is an error. The
+    /// export itself and the module's start function are removed from the
+    /// compiled module, since their effects have already been applied.
+    ///
+    /// Only core WebAssembly modules may be pre-initialized.
+    #[cfg(feature = "pre-init")]
+    #[arg(long, value_name = "EXPORT")]
+    pub pre_init: Option<String>,
+
     /// The path of the WebAssembly to compile
     #[arg(index = 1, value_name = "MODULE")]
     pub module: PathBuf,
@@ -70,6 +91,19 @@ impl CompileCommand {
             config.emit_clif(&path);
         }
 
+        // Pre-initialization runs the module, so it needs an engine for the
+        // host even when compiling for a different target.
+        #[cfg(feature = "pre-init")]
+        let host_engine = match &self.pre_init {
+            Some(_) => {
+                let target = self.common.target.take();
+                let host_config = self.common.config(None);
+                self.common.target = target;
+                Some(Engine::new(&host_config?)?)
+            }
+            None => None,
+        };
+
         let engine = Engine::new(&config)?;
 
         if self.module.file_name().is_none() {
@@ -80,6 +114,19 @@ impl CompileCommand {
         }
 
         let mut code = CodeBuilder::new(&engine);
+        #[cfg(feature = "pre-init")]
+        if let (Some(init_func), Some(host_engine)) = (&self.pre_init, &host_engine) {
+            #[cfg(feature = "wat")]
+            let wasm = wat::parse_file(&self.module)?;
+            #[cfg(not(feature = "wat"))]
+            let wasm = fs::read(&self.module)
+                .with_context(|| format!("failed to read input file: {}", self.module.display()))?;
+            let wasm = pre_init::pre_initialize(host_engine, &wasm, init_func)?;
+            code.wasm_binary(wasm, Some(&self.module))?;
+        } else {
+            code.wasm_binary_or_text_file(&self.module)?;
+        }
+        #[cfg(not(feature = "pre-init"))]
         code.wasm_binary_or_text_file(&self.module)?;
 
         let output = self.output.take().unwrap_or_else(|| {
@@ -142,6 +189,110 @@ mod test {
         Ok(())
     }
 
+    #[cfg(feature = "pre-init")]
+    #[test]
+    fn test_pre_init_compile() -> Result<()> {
+        let (mut input, input_path) = NamedTempFile::new()?.into_parts();
+        input.write_all(
+            r#"
+                (module
+                    (memory (export "memory") 1)
+                    (global $g (mut i32) (i32.const 0))
+                    (global $h (mut i64) (i64.const 0))
+                    (func $start (global.set $h (i64.const 7)))
+                    (start $start)
+                    (func (export "init")
+                        (drop (memory.grow (i32.const 1)))
+                        (i32.store (i32.const 70000) (i32.const 0x1234))
+                        (global.set $g (i32.const 42))
+                    )
+                    (func (export "get") (result i32 i64)
+                        (i32.add (global.get $g) (i32.load (i32.const 70000)))
+                        (global.get $h)
+                    )
+                    (data (i32.const 8) "hello")
+                )
+            "#
+            .as_bytes(),
+        )?;
+        drop(input);
+
+        let output_path = NamedTempFile::new()?.into_temp_path();
+
+        let command = CompileCommand::try_parse_from(vec![
+            "compile",
+            "-Dlogging=n",
+            "--pre-init",
+            "init",
+            "-o",
+            output_path.to_str().unwrap(),
+            input_path.to_str().unwrap(),
+        ])?;
+
+        command.execute()?;
+
+        let engine = Engine::default();
+        let contents = std::fs::read(output_path)?;
+        let module = unsafe { Module::deserialize(&engine, contents)? };
+        assert!(module.get_export("init").is_none());
+
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        assert_eq!(memory.size(&store), 2);
+        assert_eq!(&memory.data(&store)[8..13], b"hello");
+        let get = instance.get_typed_func::<(), (i32, i64)>(&mut store, "get")?;
+        assert_eq!(get.call(&mut store, ())?, (42 + 0x1234, 7));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "pre-init")]
+    #[test]
+    fn test_pre_init_many_segments() -> Result<()> {
+        // Write a byte to every other 4 KiB chunk, which needs more data
+        // segments than allowed here unless they are merged.
+        let wasm = wat::parse_str(
+            r#"
+                (module
+                    (memory (export "memory") 16)
+                    (data (i32.const 0) "\01")
+                    (func (export "init")
+                        (local $i i32)
+                        (loop $l
+                            (i32.store8 (local.get $i) (i32.const 1))
+                            (local.set $i (i32.add (local.get $i) (i32.const 8192)))
+                            (br_if $l (i32.lt_u (local.get $i) (i32.const 1048576)))
+                        )
+                    )
+                )
+            "#,
+        )?;
+
+        let engine = Engine::default();
+        let wasm =
+            super::pre_init::pre_initialize_with_max_data_segments(&engine, &wasm, "init", 10)?;
+
+        let mut segments = 0;
+        for payload in wasmparser::Parser::new(0).parse_all(&wasm) {
+            if let wasmparser::Payload::DataSection(data) = payload? {
+                segments += data.count();
+            }
+        }
+        assert!(segments <= 10, "{segments} data segments");
+
+        let module = Module::new(&engine, &wasm)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        let data = memory.data(&store);
+        for (i, byte) in data.iter().enumerate().step_by(4096) {
+            assert_eq!(*byte, u8::from(i % 8192 == 0), "byte at {i}");
+        }
+
+        Ok(())
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_x64_flags_compile() -> Result<()> {
@@ -0,0 +1,561 @@
+//! Wizer-style pre-initialization for `wasmtime compile --pre-init`.
+//!
+//! Pre-initialization works in three steps:
+//!
+//! 1. The input module is instrumented so that all of its defined memories and
+//!    mutable globals are exported, making their state observable from the
+//!    host.
+//!
+//! 2. The instrumented module is instantiated and its initialization function
+//!    is called.
+//!
+//! 3. The original module is rewritten such that its memories and globals
+//!    start in the state that the initialization function left them in. The
+//!    initialization function's export and the start function are removed,
+//!    since their effects have already been applied.
+//!
+//! Sections of the module that aren't affected are copied over verbatim.
+
+use anyhow::{Context, Result, bail};
+use wasm_encoder::reencode::{Reencode, RoundtripReencoder};
+use wasm_encoder::{
+    ConstExpr, DataCountSection, DataSection, ExportKind, ExportSection, GlobalSection, Ieee32,
+    Ieee64, MemorySection, RawSection, SectionId,
+};
+use wasmparser::{
+    DataKind, DataSectionReader, Encoding, ExternalKind, GlobalSectionReader, MemorySectionReader,
+    Parser, Payload, TypeRef,
+};
+use wasmtime::{Engine, Linker, Module, Store, Val};
+
+/// The prefix for the names of the exports added to expose memories.
+const MEMORY_EXPORT_PREFIX: &str = "__wasmtime_pre_init_memory";
+
+/// The prefix for the names of the exports added to expose globals.
+const GLOBAL_EXPORT_PREFIX: &str = "__wasmtime_pre_init_global";
+
+/// Linear memories are captured in chunks of this many bytes, and chunks that
+/// are entirely zero are omitted from the resulting data segments.
+///
+/// Using page-sized chunks keeps the resulting data segments dense and
+/// page-aligned, which allows them to be turned into copy-on-write memory
+/// images when the module is loaded.
+const CHUNK_SIZE: usize = 4096;
+
+/// The maximum number of data segments that a module may have, as enforced by
+/// `wasmparser` when validating modules.
+const MAX_DATA_SEGMENTS: usize = 100_000;
+
+/// Pre-initialize the core wasm module `wasm` by running its exported
+/// `init_func` and return a new module that starts in the resulting state.
+///
+/// The module is instantiated with no imports: any imported functions trap
+/// when called, and imports of any other kind are an error.
+///
+/// Note that only the state of the module's defined memories and mutable
+/// globals is captured. Changes to tables and to passive data and element
+/// segments are not.
+pub fn pre_initialize(engine: &Engine, wasm: &[u8], init_func: &str) -> Result<Vec<u8>> {
+    pre_initialize_with_max_data_segments(engine, wasm, init_func, MAX_DATA_SEGMENTS)
+}
+
+/// Like [`pre_initialize`], but producing a module with at most
+/// `max_data_segments` data segments.
+pub fn pre_initialize_with_max_data_segments(
+    engine: &Engine,
+    wasm: &[u8],
+    init_func: &str,
+    max_data_segments: usize,
+) -> Result<Vec<u8>> {
+    Module::validate(engine, wasm).context("failed to validate module for pre-initialization")?;
+    let info = ModuleInfo::parse(wasm)?;
+    let instrumented = info.instrument()?;
+    // The passive data segments, and placeholders for the active ones, are
+    // kept, and the memories share the remaining data segments.
+    let max_segments = max_data_segments
+        .checked_sub(info.kept_data_segments()?)
+        .context("module has too many data segments to pre-initialize")?;
+    let snapshot = Snapshot::capture(engine, &info, &instrumented, init_func, max_segments)?;
+    info.rewrite(init_func, &snapshot)
+}
+
+/// A section of a module, with a reader for the sections we may need to
+/// rewrite.
+enum Section<'a> {
+    Memory(MemorySectionReader<'a>),
+    Global(GlobalSectionReader<'a>),
+    Export(wasmparser::ExportSectionReader<'a>),
+    Start,
+    DataCount,
+    Data(DataSectionReader<'a>),
+    Other,
+}
+
+struct ModuleInfo<'a> {
+    /// Every section of the module, in order, along with its ID and raw
+    /// contents.
+    sections: Vec<(u8, &'a [u8], Section<'a>)>,
+    num_imported_memories: u32,
+    num_imported_globals: u32,
+    memories: Vec<wasmparser::MemoryType>,
+    globals: Vec<wasmparser::GlobalType>,
+}
+
+/// The state of a module's defined memories and mutable globals after
+/// initialization.
+struct Snapshot {
+    memories: Vec<MemorySnapshot>,
+    /// The value of each defined global, or `None` if it is immutable.
+    globals: Vec<Option<Val>>,
+}
+
+struct MemorySnapshot {
+    size: u64,
+    /// The `(offset, bytes)` data segments that recreate the memory's
+    /// contents.
+    segments: Vec<(usize, Vec<u8>)>,
+}
+
+impl<'a> ModuleInfo<'a> {
+    fn parse(wasm: &'a [u8]) -> Result<ModuleInfo<'a>> {
+        let mut info = ModuleInfo {
+            sections: Vec::new(),
+            num_imported_memories: 0,
+            num_imported_globals: 0,
+            memories: Vec::new(),
+            globals: Vec::new(),
+        };
+
+        for payload in Parser::new(0).parse_all(wasm) {
+            let payload = payload?;
+            let Some((id, range)) = payload.as_section() else {
+                if let Payload::Version { encoding, .. } = payload {
+                    if encoding != Encoding::Module {
+                        bail!("pre-initialization is only supported for core wasm modules");
+                    }
+                }
+                continue;
+            };
+            let section = match payload {
+                Payload::ImportSection(imports) => {
+                    for import in imports {
+                        match import?.ty {
+                            TypeRef::Memory(_) => info.num_imported_memories += 1,
+                            TypeRef::Global(_) => info.num_imported_globals += 1,
+                            _ => {}
+                        }
+                    }
+                    Section::Other
+                }
+                Payload::MemorySection(memories) => {
+                    for memory in memories.clone() {
+                        let memory = memory?;
+                        if memory.shared {
+                            bail!("pre-initialization of shared memories is not supported");
+                        }
+                        info.memories.push(memory);
+                    }
+                    Section::Memory(memories)
+                }
+                Payload::GlobalSection(globals) => {
+                    for global in globals.clone() {
+                        info.globals.push(global?.ty);
+                    }
+                    Section::Global(globals)
+                }
+                Payload::ExportSection(exports) => Section::Export(exports),
+                Payload::StartSection { .. } => Section::Start,
+                Payload::DataCountSection { .. } => Section::DataCount,
+                Payload::DataSection(data) => Section::Data(data),
+                _ => Section::Other,
+            };
+            info.sections.push((id, &wasm[range], section));
+        }
+
+        Ok(info)
+    }
+
+    /// Whether this module has a data count section, in which case bulk memory
+    /// instructions may refer to data segments by index.
+    fn has_data_count(&self) -> bool {
+        self.sections
+            .iter()
+            .any(|(_, _, s)| matches!(s, Section::DataCount))
+    }
+
+    /// The number of this module's data segments that `rewrite` keeps.
+    fn kept_data_segments(&self) -> Result<usize> {
+        let has_data_count = self.has_data_count();
+        let mut kept = 0;
+        for (_, _, section) in &self.sections {
+            if let Section::Data(reader) = section {
+                for segment in reader.clone() {
+                    if has_data_count || matches!(segment?.kind, DataKind::Passive) {
+                        kept += 1;
+                    }
+                }
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Get the index in `sections` before which a missing section with the
+    /// given `id` should be inserted to preserve the binary format's section
+    /// ordering.
+    fn insertion_point(&self, id: SectionId) -> usize {
+        let following: &[u8] = match id {
+            SectionId::Export => &[
+                SectionId::Start as u8,
+                SectionId::Element as u8,
+                SectionId::DataCount as u8,
+                SectionId::Code as u8,
+                SectionId::Data as u8,
+            ],
+            SectionId::Data => &[],
+            _ => unreachable!(),
+        };
+        let is_custom = |id: u8| id == SectionId::Custom as u8;
+        match self
+            .sections
+            .iter()
+            .position(|(id, _, _)| following.contains(id))
+        {
+            Some(i) => i,
+            // Place the section after the last non-custom section, so that
+            // trailing custom sections like `name` stay at the end.
+            None => self
+                .sections
+                .iter()
+                .rposition(|(id, _, _)| !is_custom(*id))
+                .map_or(0, |i| i + 1),
+        }
+    }
+
+    /// Create a copy of this module which exports all of its defined memories
+    /// and mutable globals.
+    fn instrument(&self) -> Result<Vec<u8>> {
+        let mut extra = Vec::new();
+        for i in 0..self.memories.len() {
+            let index = self.num_imported_memories + u32::try_from(i).unwrap();
+            extra.push((
+                format!("{MEMORY_EXPORT_PREFIX}{i}"),
+                ExportKind::Memory,
+                index,
+            ));
+        }
+        for (i, global) in self.globals.iter().enumerate() {
+            if global.mutable {
+                let index = self.num_imported_globals + u32::try_from(i).unwrap();
+                extra.push((
+                    format!("{GLOBAL_EXPORT_PREFIX}{i}"),
+                    ExportKind::Global,
+                    index,
+                ));
+            }
+        }
+        let add_extra = |exports: &mut ExportSection| {
+            for (name, kind, index) in &extra {
+                exports.export(name, *kind, *index);
+            }
+        };
+
+        let has_exports = self
+            .sections
+            .iter()
+            .any(|(_, _, s)| matches!(s, Section::Export(_)));
+        let export_point = self.insertion_point(SectionId::Export);
+
+        let mut module = wasm_encoder::Module::new();
+        for (i, (id, data, section)) in self.sections.iter().enumerate() {
+            if !has_exports && i == export_point {
+                let mut exports = ExportSection::new();
+                add_extra(&mut exports);
+                module.section(&exports);
+            }
+            match section {
+                Section::Export(reader) => {
+                    let mut exports = ExportSection::new();
+                    RoundtripReencoder.parse_export_section(&mut exports, reader.clone())?;
+                    add_extra(&mut exports);
+                    module.section(&exports);
+                }
+                _ => {
+                    module.section(&RawSection { id: *id, data });
+                }
+            }
+        }
+        if !has_exports && export_point == self.sections.len() {
+            let mut exports = ExportSection::new();
+            add_extra(&mut exports);
+            module.section(&exports);
+        }
+        Ok(module.finish())
+    }
+
+    /// Rewrite this module so that it starts in the state captured in
+    /// `snapshot`.
+    fn rewrite(&self, init_func: &str, snapshot: &Snapshot) -> Result<Vec<u8>> {
+        let has_data_count = self.has_data_count();
+
+        // Build the new data section first, since the data count section
+        // precedes it.
+        let mut data = DataSection::new();
+        if let Some((_, _, Section::Data(reader))) = self
+            .sections
+            .iter()
+            .find(|(_, _, s)| matches!(s, Section::Data(_)))
+        {
+            for segment in reader.clone() {
+                let segment = segment?;
+                match segment.kind {
+                    DataKind::Passive => RoundtripReencoder.parse_data(&mut data, segment)?,
+                    // Active segments have already been applied and are
+                    // replaced by the snapshot below. If bulk memory
+                    // instructions may refer to segments by index, keep an
+                    // empty placeholder so that the indices of the remaining
+                    // segments don't change. An active segment is dropped
+                    // after instantiation anyway, so an empty passive segment
+                    // behaves the same.
+                    DataKind::Active { .. } => {
+                        if has_data_count {
+                            data.passive([]);
+                        }
+                    }
+                }
+            }
+        }
+        for (i, (memory, ty)) in snapshot.memories.iter().zip(&self.memories).enumerate() {
+            let index = self.num_imported_memories + u32::try_from(i).unwrap();
+            for (offset, bytes) in &memory.segments {
+                let offset = *offset;
+                let offset = if ty.memory64 {
+                    ConstExpr::i64_const(i64::try_from(offset).unwrap())
+                } else {
+                    ConstExpr::i32_const(u32::try_from(offset).unwrap() as i32)
+                };
+                data.active(index, &offset, bytes.iter().copied());
+            }
+        }
+
+        let has_data = self
+            .sections
+            .iter()
+            .any(|(_, _, s)| matches!(s, Section::Data(_)));
+        let data_point = self.insertion_point(SectionId::Data);
+
+        let mut module = wasm_encoder::Module::new();
+        for (i, (id, raw, section)) in self.sections.iter().enumerate() {
+            if !has_data && i == data_point && !data.is_empty() {
+                module.section(&data);
+            }
+            match section {
+                Section::Memory(reader) => {
+                    let mut memories = MemorySection::new();
+                    for (memory, snapshot) in reader.clone().into_iter().zip(&snapshot.memories) {
+                        let mut ty = RoundtripReencoder.memory_type(memory?)?;
+                        ty.minimum = snapshot.size;
+                        memories.memory(ty);
+                    }
+                    module.section(&memories);
+                }
+                Section::Global(reader) => {
+                    let mut globals = GlobalSection::new();
+                    for (global, value) in reader.clone().into_iter().zip(&snapshot.globals) {
+                        let global = global?;
+                        match value {
+                            Some(value) => {
+                                let init = const_expr(&global.ty, value)?;
+                                let ty = RoundtripReencoder.global_type(global.ty)?;
+                                globals.global(ty, &init);
+                            }
+                            None => RoundtripReencoder.parse_global(&mut globals, global)?,
+                        }
+                    }
+                    module.section(&globals);
+                }
+                Section::Export(reader) => {
+                    let mut exports = ExportSection::new();
+                    for export in reader.clone() {
+                        let export = export?;
+                        if export.name == init_func && export.kind == ExternalKind::Func {
+                            continue;
+                        }
+                        RoundtripReencoder.parse_export(&mut exports, export)?;
+                    }
+                    module.section(&exports);
+                }
+                // The start function has already run, and its effects are part
+                // of the snapshot.
+                Section::Start => {}
+                Section::DataCount => {
+                    module.section(&DataCountSection { count: data.len() });
+                }
+                Section::Data(_) => {
+                    module.section(&data);
+                }
+                Section::Other => {
+                    module.section(&RawSection { id: *id, data: raw });
+                }
+            }
+        }
+        if !has_data && data_point == self.sections.len() && !data.is_empty() {
+            module.section(&data);
+        }
+        Ok(module.finish())
+    }
+}
+
+impl Snapshot {
+    /// Instantiate `instrumented`, call its `init_func` export, and capture the
+    /// resulting state, using at most `max_segments` data segments for the
+    /// memories.
+    fn capture(
+        engine: &Engine,
+        info: &ModuleInfo<'_>,
+        instrumented: &[u8],
+        init_func: &str,
+        max_segments: usize,
+    ) -> Result<Snapshot> {
+        let module = Module::new(engine, instrumented)?;
+        let mut linker = Linker::new(engine);
+        linker.define_unknown_imports_as_traps(&module)?;
+        let mut store = Store::new(engine, ());
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .context("failed to instantiate module for pre-initialization")?;
+
+        let init = instance
+            .get_typed_func::<(), ()>(&mut store, init_func)
+            .with_context(|| format!("failed to find pre-initialization function `{init_func}`"))?;
+        init.call(&mut store, ())
+            .with_context(|| format!("pre-initialization function `{init_func}` failed"))?;
+
+        // Share the data segments between the memories, letting memories that
+        // need fewer segments leave more for the ones after. Memories are
+        // scanned in place, and only the bytes of their segments copied.
+        let mut remaining = max_segments;
+        let mut memories = Vec::with_capacity(info.memories.len());
+        for i in 0..info.memories.len() {
+            let memory = instance
+                .get_memory(&mut store, &format!("{MEMORY_EXPORT_PREFIX}{i}"))
+                .unwrap();
+            let segments = segments(memory.data(&store), remaining / (info.memories.len() - i))?;
+            remaining -= segments.len();
+            memories.push(MemorySnapshot {
+                size: memory.size(&store),
+                segments: segments
+                    .into_iter()
+                    .map(|(offset, bytes)| (offset, bytes.to_vec()))
+                    .collect(),
+            });
+        }
+
+        let globals = info
+            .globals
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                if !ty.mutable {
+                    return None;
+                }
+                let global = instance
+                    .get_global(&mut store, &format!("{GLOBAL_EXPORT_PREFIX}{i}"))
+                    .unwrap();
+                Some(global.get(&mut store))
+            })
+            .collect();
+
+        Ok(Snapshot { memories, globals })
+    }
+}
+
+/// Create a constant expression which evaluates to `value`, for a global of
+/// type `ty`.
+fn const_expr(ty: &wasmparser::GlobalType, value: &Val) -> Result<ConstExpr> {
+    Ok(match value {
+        Val::I32(x) => ConstExpr::i32_const(*x),
+        Val::I64(x) => ConstExpr::i64_const(*x),
+        Val::F32(x) => ConstExpr::f32_const(Ieee32::from(f32::from_bits(*x))),
+        Val::F64(x) => ConstExpr::f64_const(Ieee64::from(f64::from_bits(*x))),
+        Val::V128(x) => ConstExpr::v128_const(x.as_u128() as i128),
+        Val::FuncRef(None) | Val::ExternRef(None) | Val::AnyRef(None) => {
+            let wasmparser::ValType::Ref(ty) = ty.content_type else {
+                unreachable!()
+            };
+            ConstExpr::ref_null(RoundtripReencoder.heap_type(ty.heap_type())?)
+        }
+        _ => bail!("pre-initialization cannot capture non-null reference values in globals"),
+    })
+}
+
+/// Break `data` up into at most `max_segments` `(offset, bytes)` data
+/// segments, omitting chunks that are entirely zero.
+///
+/// If there would be too many segments, the ones separated by the shortest
+/// runs of zeroes are merged together, including those zeroes.
+fn segments(data: &[u8], max_segments: usize) -> Result<Vec<(usize, &[u8])>> {
+    let segments = nonzero_runs(data);
+    if segments.len() <= max_segments {
+        return Ok(segments);
+    }
+    if max_segments == 0 {
+        bail!("module has too many data segments to pre-initialize");
+    }
+
+    // Merging every gap that is no longer than the `n`th shortest one merges
+    // at least `n` gaps.
+    let gap = |i: usize| {
+        let (prev_offset, prev_bytes) = segments[i - 1];
+        segments[i].0 - (prev_offset + prev_bytes.len())
+    };
+    let mut gaps = (1..segments.len()).map(gap).collect::<Vec<_>>();
+    let n = segments.len() - max_segments;
+    let (_, max_gap, _) = gaps.select_nth_unstable(n - 1);
+    let max_gap = *max_gap;
+
+    let mut merged = Vec::with_capacity(max_segments);
+    let mut start = segments[0].0;
+    for i in 1..segments.len() {
+        if gap(i) > max_gap {
+            let (prev_offset, prev_bytes) = segments[i - 1];
+            let end = prev_offset + prev_bytes.len();
+            merged.push((start, &data[start..end]));
+            start = segments[i].0;
+        }
+    }
+    let (last_offset, last_bytes) = segments[segments.len() - 1];
+    merged.push((start, &data[start..last_offset + last_bytes.len()]));
+    debug_assert!(merged.len() <= max_segments);
+    Ok(merged)
+}
+
+/// Break `data` up into `(offset, bytes)` runs of non-zero chunks, trimming
+/// the zeroes at either end of each run.
+fn nonzero_runs(data: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut segments = Vec::new();
+    let mut start = None;
+    for (i, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+        let is_zero = chunk.iter().all(|b| *b == 0);
+        match (start, is_zero) {
+            (None, false) => start = Some(i * CHUNK_SIZE),
+            (Some(s), true) => {
+                segments.push(trim(data, s, i * CHUNK_SIZE));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        segments.push(trim(data, s, data.len()));
+    }
+    segments
+}
+
+/// Trim the leading and trailing zeroes from `data[start..end]`, which must
+/// contain at least one non-zero byte.
+fn trim(data: &[u8], start: usize, end: usize) -> (usize, &[u8]) {
+    let bytes = &data[start..end];
+    let first = bytes.iter().position(|b| *b != 0).unwrap();
+    let last = bytes.iter().rposition(|b| *b != 0).unwrap();
+    (start + first, &bytes[first..=last])
+}
@@ -30,7 +30,7 @@ use wasmtime_wasi_http::{
     DEFAULT_OUTGOING_BODY_BUFFER_CHUNKS, DEFAULT_OUTGOING_BODY_CHUNK_SIZE, WasiHttpCtx,
 };
 #[cfg(feature = "wasi-keyvalue")]
-use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx, WasiKeyValueCtxBuilder};
+use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx};
 
 #[cfg(feature = "wasi-tls")]
 use wasmtime_wasi_tls::{WasiTls, WasiTlsCtx};
@@ -106,6 +106,10 @@ impl RunCommand {
                 // Further configured down below as well.
                 config.epoch_interruption(true);
             }
+            Some(Profile::Fuel { .. }) => {
+                config.consume_fuel(true);
+                config.fuel_profiling(true);
+            }
             None => {}
         }
 
@@ -164,6 +168,9 @@ impl RunCommand {
         // fuel amount to this store.
         if let Some(fuel) = self.run.common.wasm.fuel {
             store.set_fuel(fuel)?;
+        } else if let Some(Profile::Fuel { .. }) = &self.run.profile {
+            // Fuel is only being consumed to profile it, so don't limit it.
+            store.set_fuel(u64::MAX)?;
         }
 
         // Always run the module asynchronously to ensure that the module can be
@@ -235,6 +242,17 @@ impl RunCommand {
             .await
         });
 
+        // Write out the fuel profile regardless of whether execution succeeded,
+        // since where fuel went is just as interesting when it ran out.
+        if let Some(Profile::Fuel { path }) = &self.run.profile {
+            if let Err(e) = write_fuel_profile(&store, path) {
+                eprintln!("failed writing profile at {path}: {e:#}");
+            } else {
+                eprintln!();
+                eprintln!("Profile written to: {path}");
+            }
+        }
+
         // Load the main wasm module.
         match result.unwrap_or_else(|elapsed| {
             Err(anyhow::Error::from(wasmtime::Trap::Interrupt))
@@ -781,6 +799,12 @@ impl RunCommand {
                         // are enabled, then use the historical preview1
                         // implementation.
                         (Some(false), _) | (None, Some(true)) => {
+                            if linker.engine().is_deterministic() {
+                                bail!(
+                                    "the preview1 implementation of WASI used when `-S preview2=n` \
+                                     or `-S threads` is passed does not support deterministic mode"
+                                );
+                            }
                             wasi_common::tokio::add_to_linker(linker, |host| {
                                 host.preview1_ctx.as_mut().unwrap()
                             })?;
@@ -899,16 +923,7 @@ impl RunCommand {
                         bail!("Cannot enable wasi-keyvalue for core wasm modules");
                     }
                     CliLinker::Component(linker) => {
-                        let ctx = WasiKeyValueCtxBuilder::new()
-                            .in_memory_data(
-                                self.run
-                                    .common
-                                    .wasi
-                                    .keyvalue_in_memory_data
-                                    .iter()
-                                    .map(|v| (v.key.clone(), v.value.clone())),
-                            )
-                            .build();
+                        let ctx = self.run.wasi_keyvalue_ctx()?;
 
                         wasmtime_wasi_keyvalue::add_to_linker(linker, |h| {
                             let preview2_ctx =
@@ -1047,10 +1062,22 @@ impl RunCommand {
         }
 
         for (host, guest) in self.run.dirs.iter() {
+            if Path::new(host).is_file() {
+                bail!(
+                    "the preview1 implementation of WASI used when `-S preview2=n` \
+                     or `-S threads` is passed can't mount archives such as '{host}'"
+                );
+            }
             let dir = Dir::open_ambient_dir(host, ambient_authority())
                 .with_context(|| format!("failed to open directory '{host}'"))?;
             builder.preopened_dir(dir, guest)?;
         }
+        if let Some((host, _)) = self.run.overlay_dirs.first() {
+            bail!(
+                "the preview1 implementation of WASI used when `-S preview2=n` \
+                 or `-S threads` is passed can't mount overlays such as '{host}'"
+            );
+        }
 
         store.data_mut().preview1_ctx = Some(builder.build());
         Ok(())
@@ -1186,6 +1213,29 @@ fn ctx_set_listenfd(mut num_fd: usize, builder: &mut WasiCtxBuilder) -> Result<u
     Ok(num_fd)
 }
 
+/// Writes the fuel consumed by each function to `path`, one function per line
+/// from the most to the least fuel consumed.
+fn write_fuel_profile(store: &Store<Host>, path: &str) -> Result<()> {
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+
+    let mut output = BufWriter::new(File::create(path)?);
+    for func in store.fuel_profile()? {
+        let module = func.module().name().unwrap_or("<unknown>");
+        match func.func_name() {
+            Some(name) => writeln!(output, "{:>20} {module}!{name}", func.fuel())?,
+            None => writeln!(
+                output,
+                "{:>20} {module}!<wasm function {}>",
+                func.fuel(),
+                func.func_index()
+            )?,
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
 #[cfg(feature = "coredump")]
 fn write_core_dump(
     store: &mut Store<Host>,
@@ -28,7 +28,7 @@ use wasmtime_wasi_http::{
 #[cfg(feature = "wasi-config")]
 use wasmtime_wasi_config::{WasiConfig, WasiConfigVariables};
 #[cfg(feature = "wasi-keyvalue")]
-use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx, WasiKeyValueCtxBuilder};
+use wasmtime_wasi_keyvalue::{WasiKeyValue, WasiKeyValueCtx};
 #[cfg(feature = "wasi-nn")]
 use wasmtime_wasi_nn::wit::WasiNnCtx;
 
@@ -111,6 +111,12 @@ pub struct ServeCommand {
     /// The WebAssembly component to run.
     #[arg(value_name = "WASM", required = true)]
     component: PathBuf,
+
+    /// The `wasi-keyvalue` context shared by all requests, so that data
+    /// written while handling one request is visible to later ones.
+    #[cfg(feature = "wasi-keyvalue")]
+    #[arg(skip)]
+    wasi_keyvalue: Option<WasiKeyValueCtx>,
 }
 
 impl ServeCommand {
@@ -219,21 +225,9 @@ impl ServeCommand {
             }
         }
 
-        if self.run.common.wasi.keyvalue == Some(true) {
-            #[cfg(feature = "wasi-keyvalue")]
-            {
-                let ctx = WasiKeyValueCtxBuilder::new()
-                    .in_memory_data(
-                        self.run
-                            .common
-                            .wasi
-                            .keyvalue_in_memory_data
-                            .iter()
-                            .map(|v| (v.key.clone(), v.value.clone())),
-                    )
-                    .build();
-                host.wasi_keyvalue.replace(ctx);
-            }
+        #[cfg(feature = "wasi-keyvalue")]
+        if let Some(ctx) = &self.wasi_keyvalue {
+            host.wasi_keyvalue.replace(ctx.clone());
         }
 
         let mut store = Store::new(engine, host);
@@ -354,6 +348,9 @@ impl ServeCommand {
             Some(Profile::Guest { .. }) => {
                 config.epoch_interruption(true);
             }
+            Some(Profile::Fuel { .. }) => {
+                bail!("fuel profiling is not supported by `wasmtime serve`");
+            }
             None => {}
         }
 
@@ -362,6 +359,11 @@ impl ServeCommand {
 
         self.add_to_linker(&mut linker)?;
 
+        #[cfg(feature = "wasi-keyvalue")]
+        if self.run.common.wasi.keyvalue == Some(true) {
+            self.wasi_keyvalue = Some(self.run.wasi_keyvalue_ctx()?);
+        }
+
         let component = match self.run.load_module(&engine, &self.component)? {
             RunTarget::Core(_) => bail!("The serve command currently requires a component"),
             RunTarget::Component(c) => c,
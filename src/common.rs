@@ -52,7 +52,8 @@ pub struct RunCommon {
     #[arg(long = "allow-precompiled")]
     pub allow_precompiled: bool,
 
-    /// Profiling strategy (valid options are: perfmap, jitdump, vtune, guest)
+    /// Profiling strategy (valid options are: perfmap, jitdump, vtune, guest,
+    /// fuel)
     ///
     /// The perfmap, jitdump, and vtune profiling strategies integrate Wasmtime
     /// with external profilers such as `perf`. The guest profiling strategy
@@ -67,6 +68,16 @@ pub struct RunCommon {
     /// where `path` is where to write the profile and `interval` is the
     /// duration between samples. When used with `--wasm-timeout` the timeout
     /// will be rounded up to the nearest multiple of this interval.
+    ///
+    /// The fuel profiling strategy enables fuel consumption and writes the
+    /// exact amount of fuel consumed by each function to
+    /// `wasmtime-fuel-profile.txt` by default. It can be additionally
+    /// configured as:
+    ///
+    ///     --profile=fuel[,path]
+    ///
+    /// where `path` is where to write the profile. Unless `-W fuel` is also
+    /// given, the module runs with an unlimited amount of fuel.
     #[arg(
         long,
         value_name = "STRATEGY",
@@ -80,9 +91,25 @@ pub struct RunCommon {
     /// host is made available within the guest. If specified as `HOST::GUEST`
     /// then the `HOST` directory is opened and made available as the name
     /// `GUEST` in the guest.
+    ///
+    /// If `HOST_DIR` is a tar or zip archive rather than a directory then its
+    /// contents are made available read-only, without extracting them.
     #[arg(long = "dir", value_name = "HOST_DIR[::GUEST_DIR]", value_parser = parse_dirs)]
     pub dirs: Vec<(String, String)>,
 
+    /// Grant access of a host directory to a guest without letting the guest
+    /// modify it.
+    ///
+    /// This is like `--dir`, except that the guest's changes to the directory
+    /// are made to a copy-on-write layer in memory on top of it, and discarded
+    /// when the guest exits. The host directory itself is never modified.
+    #[arg(
+        long = "dir-overlay",
+        value_name = "HOST_DIR[::GUEST_DIR]",
+        value_parser = parse_dirs
+    )]
+    pub overlay_dirs: Vec<(String, String)>,
+
     /// Pass an environment variable to the program.
     ///
     /// The `--env FOO=BAR` form will set the environment variable named `FOO`
@@ -269,6 +296,12 @@ impl RunCommon {
         // something like `sleep(FOREVER)`.
         builder.allow_blocking_current_thread(self.common.wasm.timeout.is_none());
 
+        // Give the guest deterministic clocks and random numbers in
+        // deterministic mode, which otherwise trap when used.
+        if self.common.wasm.deterministic == Some(true) {
+            builder.deterministic_sources(0);
+        }
+
         if self.common.wasi.inherit_env == Some(true) {
             for (k, v) in std::env::vars() {
                 builder.env(&k, &v);
@@ -291,7 +324,19 @@ impl RunCommon {
         }
 
         for (host, guest) in self.dirs.iter() {
-            builder.preopened_dir(
+            if Path::new(host).is_file() {
+                builder.preopened_archive(host, guest)?;
+            } else {
+                builder.preopened_dir(
+                    host,
+                    guest,
+                    wasmtime_wasi::DirPerms::all(),
+                    wasmtime_wasi::FilePerms::all(),
+                )?;
+            }
+        }
+        for (host, guest) in self.overlay_dirs.iter() {
+            builder.preopened_dir_overlay(
                 host,
                 guest,
                 wasmtime_wasi::DirPerms::all(),
@@ -336,6 +381,31 @@ impl RunCommon {
         Ok(listeners)
     }
 
+    /// Create the context for `wasi-keyvalue`, with data stored in memory or
+    /// in the directory given by `-S keyvalue-dir`.
+    #[cfg(feature = "wasi-keyvalue")]
+    pub fn wasi_keyvalue_ctx(&self) -> Result<wasmtime_wasi_keyvalue::WasiKeyValueCtx> {
+        use wasmtime_wasi_keyvalue::{FileBackend, WasiKeyValueCtxBuilder};
+
+        let wasi = &self.common.wasi;
+        let builder = match &wasi.keyvalue_dir {
+            Some(dir) => {
+                if !wasi.keyvalue_in_memory_data.is_empty() {
+                    bail!("`-S keyvalue-in-memory-data` cannot be used with `-S keyvalue-dir`");
+                }
+                let backend = FileBackend::new(dir)
+                    .with_context(|| format!("failed to open key-value directory `{dir}`"))?;
+                WasiKeyValueCtxBuilder::new().backend(std::sync::Arc::new(backend))
+            }
+            None => WasiKeyValueCtxBuilder::new().in_memory_data(
+                wasi.keyvalue_in_memory_data
+                    .iter()
+                    .map(|v| (v.key.clone(), v.value.clone())),
+            ),
+        };
+        Ok(builder.build())
+    }
+
     pub fn compute_wasi_features(&self) -> LinkOptions {
         let mut options = LinkOptions::default();
         options.cli_exit_with_code(self.common.wasi.cli_exit_with_code.unwrap_or(false));
@@ -348,6 +418,7 @@ impl RunCommon {
 pub enum Profile {
     Native(wasmtime::ProfilingStrategy),
     Guest { path: String, interval: Duration },
+    Fuel { path: String },
 }
 
 impl Profile {
@@ -371,6 +442,12 @@ impl Profile {
                 path: path.to_string(),
                 interval: WasmtimeOptionValue::parse(Some(dur))?,
             }),
+            ["fuel"] => Ok(Profile::Fuel {
+                path: "wasmtime-fuel-profile.txt".to_string(),
+            }),
+            ["fuel", path] => Ok(Profile::Fuel {
+                path: path.to_string(),
+            }),
             _ => bail!("unknown profiling strategy: {s}"),
         }
     }
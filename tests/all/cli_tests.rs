@@ -109,6 +109,30 @@ fn run_coredump_smoketest() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn run_fuel_profile() -> Result<()> {
+    let wasm = build_wasm("tests/all/cli_tests/simple.wat")?;
+    let profile_file = NamedTempFile::new()?;
+    let profile_arg = format!("--profile=fuel,{}", profile_file.path().display());
+    run_wasmtime(&[
+        "run",
+        "--invoke",
+        "simple",
+        "-Ccache=n",
+        &profile_arg,
+        wasm.path().to_str().unwrap(),
+        "4",
+    ])?;
+    let profile = std::fs::read_to_string(profile_file.path())?;
+    let lines = profile.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1, "{profile}");
+    assert!(
+        lines[0].ends_with(" <unknown>!<wasm function 0>"),
+        "{profile}"
+    );
+    Ok(())
+}
+
 // Running simple wat
 #[test]
 fn run_wasmtime_simple_wat() -> Result<()> {
@@ -1244,6 +1268,26 @@ mod test_programs {
         Ok(())
     }
 
+    #[test]
+    fn cli_file_read_zip() -> Result<()> {
+        use zip::write::{FileOptions, ZipWriter};
+
+        let dir = tempfile::tempdir()?;
+        let archive = dir.path().join("archive.zip");
+        let mut zip = ZipWriter::new(std::fs::File::create(&archive)?);
+        zip.start_file("bar.txt", FileOptions::default())?;
+        zip.write_all(b"And stood awhile in thought")?;
+        zip.finish()?;
+
+        run_wasmtime(&[
+            "run",
+            "-Wcomponent-model",
+            &format!("--dir={}::/", archive.to_str().unwrap()),
+            CLI_FILE_READ_COMPONENT,
+        ])?;
+        Ok(())
+    }
+
     #[test]
     fn cli_file_append() -> Result<()> {
         let dir = tempfile::tempdir()?;
@@ -1269,6 +1313,29 @@ mod test_programs {
         Ok(())
     }
 
+    #[test]
+    fn cli_file_append_overlay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        std::fs::File::create(dir.path().join("bar.txt"))?
+            .write_all(b"'Twas brillig, and the slithy toves.\n")?;
+
+        run_wasmtime(&[
+            "run",
+            "-Wcomponent-model",
+            &format!("--dir-overlay={}::/", dir.path().to_str().unwrap()),
+            CLI_FILE_APPEND_COMPONENT,
+        ])?;
+
+        // The guest's changes were made to the overlay only.
+        let contents = std::fs::read(dir.path().join("bar.txt"))?;
+        assert_eq!(
+            std::str::from_utf8(&contents).unwrap(),
+            "'Twas brillig, and the slithy toves.\n"
+        );
+        Ok(())
+    }
+
     #[test]
     fn cli_file_dir_sync() -> Result<()> {
         let dir = tempfile::tempdir()?;
@@ -1354,6 +1421,34 @@ mod test_programs {
         Ok(())
     }
 
+    #[test]
+    fn cli_directory_list_tar() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let archive = dir.path().join("archive.tar");
+        let mut tar = tar::Builder::new(std::fs::File::create(&archive)?);
+        for path in [
+            "foo.txt",
+            "bar.txt",
+            "baz.txt",
+            "sub/wow.txt",
+            "sub/yay.txt",
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, path, std::io::empty())?;
+        }
+        tar.finish()?;
+
+        run_wasmtime(&[
+            "run",
+            "-Wcomponent-model",
+            &format!("--dir={}::/", archive.to_str().unwrap()),
+            CLI_DIRECTORY_LIST_COMPONENT,
+        ])?;
+        Ok(())
+    }
+
     #[test]
     fn cli_default_clocks() -> Result<()> {
         run_wasmtime(&["run", "-Wcomponent-model", CLI_DEFAULT_CLOCKS_COMPONENT])?;
@@ -2064,6 +2159,36 @@ start a print 1234
         Ok(())
     }
 
+    #[tokio::test]
+    async fn cli_serve_keyvalue_dir() -> Result<()> {
+        use wasmtime_wasi_keyvalue::{FileBackend, KeyValueBackend};
+
+        let dir = tempfile::tempdir()?;
+        FileBackend::new(dir.path())?.set("", "hello", b"world".to_vec())?;
+
+        let dir_arg = format!("-Skeyvalue-dir={}", dir.path().display());
+        let server = WasmtimeServe::new(CLI_SERVE_KEYVALUE_COMPONENT, |cmd| {
+            cmd.arg("-Scli");
+            cmd.arg("-Skeyvalue");
+            cmd.arg(&dir_arg);
+        })?;
+
+        for _ in 0..2 {
+            let resp = server
+                .send_request(
+                    hyper::Request::builder()
+                        .uri("http://localhost/")
+                        .body(String::new())
+                        .context("failed to make request")?,
+                )
+                .await?;
+
+            assert!(resp.status().is_success());
+            assert_eq!(resp.body(), "world");
+        }
+        Ok(())
+    }
+
     #[test]
     fn cli_keyvalue() -> Result<()> {
         run_wasmtime(&[
@@ -2075,6 +2200,31 @@ start a print 1234
         Ok(())
     }
 
+    #[test]
+    fn cli_keyvalue_dir() -> Result<()> {
+        use wasmtime_wasi_keyvalue::{FileBackend, KeyValueBackend};
+
+        let dir = tempfile::tempdir()?;
+        FileBackend::new(dir.path())?.set("", "atomics_key", b"5".to_vec())?;
+        let dir_arg = format!("-Skeyvalue-dir={}", dir.path().display());
+        run_wasmtime(&["run", "-Skeyvalue", &dir_arg, KEYVALUE_MAIN_COMPONENT])?;
+
+        // The guest's writes persist after it exits.
+        let backend = FileBackend::new(dir.path())?;
+        assert_eq!(backend.get("", "atomics_key")?, Some(b"6".to_vec()));
+
+        let err = run_wasmtime(&[
+            "run",
+            "-Skeyvalue",
+            &dir_arg,
+            "-Skeyvalue-in-memory-data=atomics_key=5",
+            KEYVALUE_MAIN_COMPONENT,
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("cannot be used with"), "{err:?}");
+        Ok(())
+    }
+
     #[test]
     fn cli_multiple_preopens() -> Result<()> {
         run_wasmtime(&[
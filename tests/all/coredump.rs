@@ -258,3 +258,213 @@ fn multiple_globals_memories_and_instances() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn coredump_restore() -> Result<()> {
+    let mut config = Config::default();
+    config.coredump_on_trap(true);
+    let engine = Engine::new(&config).unwrap();
+    let mut store = Store::<()>::new(&engine, ());
+    let mut linker = Linker::new(&engine);
+
+    let module_a = Module::new(
+        &engine,
+        r#"
+            (module $a
+                (memory (export "memory") 1)
+                (global (export "global") (mut i32) (i32.const 0))
+            )
+        "#,
+    )?;
+    let instance_a = linker.instantiate(&mut store, &module_a)?;
+    linker.instance(&mut store, "a", instance_a)?;
+    linker.func_wrap("host", "f", || {})?;
+
+    let module_b = Module::new(
+        &engine,
+        r#"
+            (module $b
+                (import "host" "f" (func $f))
+                (import "a" "memory" (memory 1))
+                (import "a" "global" (global $a (mut i32)))
+                (global $b (export "b") (mut i64) (i64.const 0))
+                (global (export "c") f64 (f64.const 1.5))
+
+                (func (export "run")
+                    (drop (memory.grow (i32.const 1)))
+                    (i32.store (i32.const 65540) (i32.const 0x1234))
+                    (global.set $a (i32.const 42))
+                    (global.set $b (i64.const -1))
+                    unreachable
+                )
+
+                (func (export "read") (result i32)
+                    (i32.add (global.get $a) (i32.load (i32.const 65540)))
+                )
+
+                (func (export "call") (call $f))
+            )
+        "#,
+    )?;
+    let instance_b = linker.instantiate(&mut store, &module_b)?;
+
+    let run = instance_b.get_typed_func::<(), ()>(&mut store, "run")?;
+    let err = run.call(&mut store, ()).unwrap_err();
+    let core_dump = err.downcast_ref::<WasmCoreDump>().unwrap();
+    let bytes = core_dump.serialize(&mut store, "restore");
+
+    let mut store = Store::<()>::new(&engine, ());
+    let instances = WasmCoreDump::restore(&mut store, &bytes, &[module_b.clone(), module_a])?;
+    assert_eq!(instances.len(), 2);
+
+    let memory = instances[0].get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 2);
+    let mut buf = [0; 4];
+    memory.read(&store, 65540, &mut buf)?;
+    assert_eq!(u32::from_le_bytes(buf), 0x1234);
+
+    let global = instances[0].get_global(&mut store, "global").unwrap();
+    assert_eq!(global.get(&mut store).unwrap_i32(), 42);
+    let b = instances[1].get_global(&mut store, "b").unwrap();
+    assert_eq!(b.get(&mut store).unwrap_i64(), -1);
+    let c = instances[1].get_global(&mut store, "c").unwrap();
+    assert_eq!(c.get(&mut store).unwrap_f64(), 1.5);
+
+    let read = instances[1].get_typed_func::<(), i32>(&mut store, "read")?;
+    assert_eq!(read.call(&mut store, ())?, 42 + 0x1234);
+
+    // Imported functions trap since the host isn't available.
+    let call = instances[1].get_typed_func::<(), ()>(&mut store, "call")?;
+    assert!(call.call(&mut store, ()).is_err());
+
+    // Every module in the core dump must be provided.
+    let mut store = Store::<()>::new(&engine, ());
+    let err = WasmCoreDump::restore(&mut store, &bytes, &[module_b]).unwrap_err();
+    assert!(format!("{err:?}").contains("no module was provided for `a`"));
+
+    Ok(())
+}
+
+/// Build a core dump of instances of `modules`, where each instance is given
+/// as `(module index, memory indices, global indices)`, with one single-page
+/// memory holding `0x1234` at offset 4 and one `i32` global holding 42.
+fn core_dump_with_instances(modules: &[&str], instances: &[(u32, &[u32], &[u32])]) -> Vec<u8> {
+    use wasm_encoder::{
+        ConstExpr, CoreDumpInstancesSection, CoreDumpModulesSection, CoreDumpSection, DataSection,
+        GlobalSection, GlobalType, MemorySection, MemoryType, ValType,
+    };
+
+    let mut dump = wasm_encoder::Module::new();
+    dump.section(&CoreDumpSection::new("test"));
+    let mut section = CoreDumpModulesSection::new();
+    for module in modules {
+        section.module(module);
+    }
+    dump.section(&section.as_custom());
+    let mut section = CoreDumpInstancesSection::new();
+    for (module, memories, globals) in instances {
+        section.instance(*module, memories.iter().copied(), globals.iter().copied());
+    }
+    dump.section(&section.as_custom());
+
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+    dump.section(&memories);
+    let mut globals = GlobalSection::new();
+    globals.global(
+        GlobalType {
+            val_type: ValType::I32,
+            mutable: true,
+            shared: false,
+        },
+        &ConstExpr::i32_const(42),
+    );
+    dump.section(&globals);
+    let mut data = DataSection::new();
+    data.active(0, &ConstExpr::i32_const(4), 0x1234_u32.to_le_bytes());
+    dump.section(&data);
+    dump.finish()
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn coredump_restore_import_before_definition() -> Result<()> {
+    let engine = Engine::default();
+    let module_a = Module::new(
+        &engine,
+        r#"
+            (module $a
+                (memory (export "memory") 1)
+                (global (export "global") (mut i32) (i32.const 0))
+            )
+        "#,
+    )?;
+    let module_b = Module::new(
+        &engine,
+        r#"
+            (module $b
+                (import "a" "memory" (memory 1))
+                (import "a" "global" (global $a (mut i32)))
+                (func (export "read") (result i32)
+                    (i32.add (global.get $a) (i32.load (i32.const 4)))
+                )
+            )
+        "#,
+    )?;
+
+    // The instance of `b` that imports `a`'s memory and global comes first.
+    let bytes = core_dump_with_instances(&["b", "a"], &[(0, &[0], &[0]), (1, &[0], &[0])]);
+    let mut store = Store::<()>::new(&engine, ());
+    let instances = WasmCoreDump::restore(&mut store, &bytes, &[module_a, module_b])?;
+
+    let read = instances[0].get_typed_func::<(), i32>(&mut store, "read")?;
+    assert_eq!(read.call(&mut store, ())?, 42 + 0x1234);
+
+    // Both instances share the same memory and global.
+    let memory = instances[1].get_memory(&mut store, "memory").unwrap();
+    memory.write(&mut store, 4, &1_u32.to_le_bytes())?;
+    let global = instances[1].get_global(&mut store, "global").unwrap();
+    global.set(&mut store, Val::I32(2))?;
+    assert_eq!(read.call(&mut store, ())?, 3);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn coredump_restore_invalid_indices() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module $a
+                (memory 1)
+                (global (mut i32) (i32.const 0))
+            )
+        "#,
+    )?;
+
+    let bytes = core_dump_with_instances(&["a"], &[(0, &[1], &[0])]);
+    let mut store = Store::<()>::new(&engine, ());
+    let err = WasmCoreDump::restore(&mut store, &bytes, &[module.clone()]).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("invalid memory index"),
+        "{err:?}"
+    );
+
+    let bytes = core_dump_with_instances(&["a"], &[(0, &[0], &[1])]);
+    let err = WasmCoreDump::restore(&mut store, &bytes, &[module]).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("invalid global index"),
+        "{err:?}"
+    );
+
+    Ok(())
+}
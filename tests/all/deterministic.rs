@@ -0,0 +1,277 @@
+use std::time::Duration;
+use wasmtime::*;
+use wasmtime_wasi::HostWallClock;
+use wasmtime_wasi::p2::WasiCtxBuilder;
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+
+fn deterministic_config() -> Config {
+    let mut config = Config::new();
+    config.deterministic(true);
+    config
+}
+
+#[test]
+fn deterministic_rejects_conflicting_settings() -> Result<()> {
+    let mut config = deterministic_config();
+    config.wasm_threads(true);
+    let err = Engine::new(&config).unwrap_err();
+    assert!(format!("{err}").contains("threads"), "{err}");
+
+    let mut config = deterministic_config();
+    config.relaxed_simd_deterministic(false);
+    let err = Engine::new(&config).unwrap_err();
+    assert!(format!("{err}").contains("relaxed SIMD"), "{err}");
+
+    let mut config = deterministic_config();
+    config.cranelift_nan_canonicalization(false);
+    assert!(Engine::new(&config).is_err());
+
+    let mut config = deterministic_config();
+    config.strategy(Strategy::Winch);
+    assert!(Engine::new(&config).is_err());
+
+    let engine = Engine::new(&deterministic_config())?;
+    assert!(engine.is_deterministic());
+    assert!(!Engine::default().is_deterministic());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deterministic_disables_threads() -> Result<()> {
+    let engine = Engine::new(&deterministic_config())?;
+    let wat = r#"(module (memory 1 1 shared))"#;
+    assert!(Module::new(&engine, wat).is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deterministic_canonicalizes_nans() -> Result<()> {
+    let engine = Engine::new(&deterministic_config())?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (func (export "f32") (param f32 f32) (result i32)
+                    (i32.reinterpret_f32 (f32.div (local.get 0) (local.get 1))))
+                (func (export "f64") (param f64 f64) (result i64)
+                    (i64.reinterpret_f64 (f64.div (local.get 0) (local.get 1))))
+            )
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+
+    let f32 = instance.get_typed_func::<(f32, f32), u32>(&mut store, "f32")?;
+    assert_eq!(f32.call(&mut store, (0.0, 0.0))?, 0x7fc0_0000);
+    let f64 = instance.get_typed_func::<(f64, f64), u64>(&mut store, "f64")?;
+    assert_eq!(f64.call(&mut store, (0.0, 0.0))?, 0x7ff8_0000_0000_0000);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deterministic_memory_growth_failure_traps() -> Result<()> {
+    let wat = r#"
+        (module
+            (memory 1)
+            (func (export "grow") (result i32)
+                (memory.grow (i32.const 1)))
+        )
+    "#;
+    let run = |deterministic: bool| -> Result<i32> {
+        let mut config = Config::new();
+        config.deterministic(deterministic);
+        config.memory_reservation(1 << 16);
+        config.memory_may_move(false);
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, wat)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let grow = instance.get_typed_func::<(), i32>(&mut store, "grow")?;
+        grow.call(&mut store, ())
+    };
+
+    assert_eq!(run(false)?, -1);
+    let err = run(true).unwrap_err();
+    assert!(format!("{err:?}").contains("deterministic mode"), "{err:?}");
+    Ok(())
+}
+
+struct FixedClock;
+
+impl HostWallClock for FixedClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+    fn now(&self) -> Duration {
+        Duration::from_secs(1_000_000)
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deterministic_wasi_requires_configured_clocks_and_random() -> Result<()> {
+    let engine = Engine::new(&deterministic_config())?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "clock_time_get"
+                    (func $clock_time_get (param i32 i64 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "random_get"
+                    (func $random_get (param i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (func (export "now") (result i64)
+                    (drop (call $clock_time_get (i32.const 0) (i64.const 0) (i32.const 0)))
+                    (i64.load (i32.const 0)))
+                (func (export "random") (result i32)
+                    (drop (call $random_get (i32.const 0) (i32.const 4)))
+                    (i32.load (i32.const 0)))
+            )
+        "#,
+    )?;
+    let mut linker = Linker::<WasiP1Ctx>::new(&engine);
+    preview1::add_to_linker_sync(&mut linker, |t| t)?;
+
+    // The host's clocks and random number generator can't be used.
+    let mut store = Store::new(
+        &engine,
+        WasiCtxBuilder::new().deterministic(true).build_p1(),
+    );
+    let instance = linker.instantiate(&mut store, &module)?;
+    let random = instance.get_typed_func::<(), i32>(&mut store, "random")?;
+    let err = random.call(&mut store, ()).unwrap_err();
+    assert!(format!("{err:?}").contains("deterministic mode"), "{err:?}");
+
+    // They work when the sources are explicitly configured.
+    let mut store = Store::new(
+        &engine,
+        WasiCtxBuilder::new()
+            .wall_clock(FixedClock)
+            .secure_random(wasmtime_wasi::Deterministic::new(vec![7]))
+            .build_p1(),
+    );
+    let instance = linker.instantiate(&mut store, &module)?;
+    let now = instance.get_typed_func::<(), i64>(&mut store, "now")?;
+    assert_eq!(now.call(&mut store, ())?, 1_000_000_000_000_000);
+    let random = instance.get_typed_func::<(), i32>(&mut store, "random")?;
+    assert_eq!(random.call(&mut store, ())?, 0x0707_0707);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deterministic_wasi_poll_oneoff_rejects_unconfigured_clock() -> Result<()> {
+    let engine = Engine::new(&deterministic_config())?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "poll_oneoff"
+                    (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                ;; A subscription to stdin being readable.
+                (data (i32.const 256) "\00\00\00\00\00\00\00\00\01")
+                ;; A subscription to the monotonic clock, with a 1ns timeout.
+                (data (i32.const 320) "\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00"
+                    "\01\00\00\00\00\00\00\00\01")
+                (func (export "poll") (param i32) (result i32)
+                    (call $poll_oneoff (local.get 0) (i32.const 512) (i32.const 1)
+                        (i32.const 600)))
+            )
+        "#,
+    )?;
+    let mut linker = Linker::<WasiP1Ctx>::new(&engine);
+    preview1::add_to_linker_sync(&mut linker, |t| t)?;
+    let poll = |builder: &mut WasiCtxBuilder, sub: i32| -> Result<i32> {
+        let mut store = Store::new(&engine, builder.build_p1());
+        let instance = linker.instantiate(&mut store, &module)?;
+        let poll = instance.get_typed_func::<i32, i32>(&mut store, "poll")?;
+        poll.call(&mut store, sub)
+    };
+
+    // Polling file descriptors doesn't need a clock.
+    assert_eq!(poll(WasiCtxBuilder::new().deterministic(true), 256)?, 0);
+
+    // Clock subscriptions need a configured monotonic clock.
+    let err = poll(WasiCtxBuilder::new().deterministic(true), 320).unwrap_err();
+    assert!(format!("{err:?}").contains("deterministic mode"), "{err:?}");
+    assert_eq!(
+        poll(WasiCtxBuilder::new().deterministic_sources(0), 320)?,
+        0
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deterministic_wasi_p2_requires_configured_random() -> Result<()> {
+    use wasmtime::component::{Component, Linker, ResourceTable};
+    use wasmtime_wasi::p2::{IoView, WasiCtx, WasiView};
+
+    struct Ctx {
+        wasi: WasiCtx,
+        table: ResourceTable,
+    }
+    impl IoView for Ctx {
+        fn table(&mut self) -> &mut ResourceTable {
+            &mut self.table
+        }
+    }
+    impl WasiView for Ctx {
+        fn ctx(&mut self) -> &mut WasiCtx {
+            &mut self.wasi
+        }
+    }
+
+    let engine = Engine::new(&deterministic_config())?;
+    let component = Component::new(
+        &engine,
+        r#"
+            (component
+                (import "wasi:random/random@0.2.6" (instance $random
+                    (export "get-random-u64" (func (result u64)))
+                ))
+                (core func $get (canon lower (func $random "get-random-u64")))
+                (core module $m
+                    (import "" "get" (func $get (result i64)))
+                    (func (export "run") (result i64) (call $get))
+                )
+                (core instance $m (instantiate $m
+                    (with "" (instance (export "get" (func $get))))
+                ))
+                (func (export "run") (result u64) (canon lift (core func $m "run")))
+            )
+        "#,
+    )?;
+    let mut linker = Linker::<Ctx>::new(&engine);
+    wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+    let run = |builder: &mut WasiCtxBuilder| -> Result<u64> {
+        let mut store = Store::new(
+            &engine,
+            Ctx {
+                wasi: builder.build(),
+                table: ResourceTable::new(),
+            },
+        );
+        let instance = linker.instantiate(&mut store, &component)?;
+        let run = instance.get_typed_func::<(), (u64,)>(&mut store, "run")?;
+        Ok(run.call(&mut store, ())?.0)
+    };
+
+    // The host's random number generator can't be used in a deterministic
+    // context.
+    let err = run(WasiCtxBuilder::new().deterministic(true)).unwrap_err();
+    assert!(format!("{err:?}").contains("deterministic mode"), "{err:?}");
+
+    // Whether a context is deterministic only depends on how it was built,
+    // not on the engine.
+    run(&mut WasiCtxBuilder::new())?;
+
+    // A seeded one produces the same numbers every time.
+    let value = run(WasiCtxBuilder::new().deterministic_sources(1))?;
+    assert_eq!(run(WasiCtxBuilder::new().deterministic_sources(1))?, value);
+    Ok(())
+}
@@ -310,3 +310,71 @@ fn ensure_stack_alignment(config: &mut Config) -> Result<()> {
     );
     Ok(())
 }
+
+#[wasmtime_test]
+#[cfg_attr(miri, ignore)]
+fn fuel_profile(config: &mut Config) -> Result<()> {
+    config.consume_fuel(true);
+    config.fuel_profiling(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module $m
+                (import "" "" (func))
+                (func $run (export "run")
+                    call 0
+                    call $work
+                    call $work
+                    drop
+                    drop)
+                (func $work (result i32)
+                    i32.const 1
+                    i32.const 2
+                    i32.add
+                    i32.const 3
+                    i32.mul)
+                (func $unused))
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    store.set_fuel(10_000)?;
+    let host = Func::wrap(&mut store, || {});
+    let instance = Instance::new(&mut store, &module, &[host.into()])?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+    run.call(&mut store, ())?;
+    let consumed = 10_000 - store.get_fuel()?;
+
+    let profile = store.fuel_profile()?;
+    assert_eq!(profile.len(), 2);
+    assert_eq!(profile.iter().map(|f| f.fuel()).sum::<u64>(), consumed);
+    let names = profile.iter().map(|f| f.func_name()).collect::<Vec<_>>();
+    assert_eq!(names, [Some("work"), Some("run")]);
+    assert_eq!(profile[0].func_index(), 2);
+    assert_eq!(profile[0].module().name(), Some("m"));
+
+    // A second instance of the same module is added to the same entries.
+    let instance = Instance::new(&mut store, &module, &[host.into()])?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+    run.call(&mut store, ())?;
+    let again = store.fuel_profile()?;
+    assert_eq!(again.len(), 2);
+    for (before, after) in profile.iter().zip(&again) {
+        assert_eq!(after.func_index(), before.func_index());
+        assert_eq!(after.fuel(), 2 * before.fuel());
+    }
+    Ok(())
+}
+
+#[test]
+fn fuel_profile_requires_fuel() -> Result<()> {
+    let mut config = Config::new();
+    config.fuel_profiling(true);
+    assert!(Engine::new(&config).is_err());
+
+    config.fuel_profiling(false).consume_fuel(true);
+    let engine = Engine::new(&config)?;
+    let store = Store::new(&engine, ());
+    assert!(store.fuel_profile().is_err());
+    Ok(())
+}
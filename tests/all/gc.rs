@@ -1175,6 +1175,126 @@ fn drc_transitive_drop_nested_arrays_tree() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg_attr(miri, ignore)]
+fn drc_incremental_collection() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    const BUDGET: usize = 10;
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::DeferredReferenceCounting);
+    config.incremental_gc_budget(std::num::NonZeroUsize::new(BUDGET));
+
+    let engine = Engine::new(&config)?;
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $cons (struct (field externref) (field (ref null $cons))))
+                (global (export "g") (ref null $cons) (ref.null $cons))
+            )
+        "#,
+    )?;
+
+    let export = module.exports().nth(0).unwrap().ty();
+    let global = export.unwrap_global();
+    let ref_ty = global.content().unwrap_ref();
+    let struct_ty = ref_ty.heap_type().unwrap_concrete_struct();
+
+    let mut store = Store::new(&engine, ());
+
+    let pre = StructRefPre::new(&mut store, struct_ty.clone());
+    let num_refs_dropped = Arc::new(AtomicUsize::new(0));
+
+    let len = 100;
+    {
+        let mut store = RootScope::new(&mut store);
+
+        let mut cdr = None;
+        for _ in 0..len {
+            let externref = ExternRef::new(&mut store, CountDrops(num_refs_dropped.clone()))?;
+            let cons = StructRef::new(&mut store, &pre, &[externref.into(), cdr.into()])?;
+            cdr = Some(cons);
+        }
+    }
+
+    // Dropping the list is bounded by the budget, so it can't have been freed
+    // all at once.
+    let mut dropped = num_refs_dropped.load(SeqCst);
+    assert!(dropped < len);
+
+    // Each collection step frees a bounded number of objects, and repeated
+    // steps eventually free everything.
+    let mut steps = 0;
+    while dropped < len {
+        store.gc(None);
+        steps += 1;
+        assert!(steps <= 2 * len, "collection is not making progress");
+
+        let now = num_refs_dropped.load(SeqCst);
+        assert!(
+            now - dropped <= BUDGET,
+            "{} objects freed in one step",
+            now - dropped
+        );
+        dropped = now;
+    }
+    assert!(steps > 1);
+    assert_eq!(dropped, len);
+
+    // The null collector doesn't collect anything, incrementally or otherwise.
+    config.collector(Collector::Null);
+    assert!(Engine::new(&config).is_err());
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn drc_incremental_collection_completes_when_out_of_memory() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::DeferredReferenceCounting);
+    config.incremental_gc_budget(std::num::NonZeroUsize::new(1));
+    config.memory_reservation(1 << 16);
+    config.memory_reservation_for_growth(0);
+    config.memory_guard_size(0);
+    config.memory_may_move(false);
+
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+    let array_ty = ArrayType::new(
+        &engine,
+        FieldType::new(
+            Mutability::Var,
+            StorageType::ValType(ValType::Ref(RefType::ANYREF)),
+        ),
+    );
+    let pre = ArrayRefPre::new(&mut store, array_ty);
+
+    // Repeatedly fill the GC heap with short-lived arrays of arrays. Freeing
+    // each generation takes many steps of work, but allocation must never fail
+    // while there is garbage left to reclaim.
+    for _ in 0..100 {
+        let mut store = RootScope::new(&mut store);
+        let mut elems = vec![];
+        for _ in 0..32 {
+            let elem = ArrayRef::new(&mut store, &pre, &Val::AnyRef(None), 16)?;
+            elems.push(elem.to_anyref().into());
+        }
+        ArrayRef::new_fixed(&mut store, &pre, &elems)?;
+    }
+
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(miri, ignore)]
 fn drc_traces_the_correct_number_of_gc_refs_in_arrays() -> Result<()> {
@@ -1398,3 +1518,336 @@ fn drc_gc_inbetween_host_calls() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn copying_moves_rooted_objects_and_collects_cycles() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::Copying);
+
+    let engine = Engine::new(&config)?;
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $cons (struct (field externref) (field (mut (ref null $cons)))))
+                (global (export "g") (ref null $cons) (ref.null $cons))
+            )
+        "#,
+    )?;
+
+    let export = module.exports().nth(0).unwrap().ty();
+    let global = export.unwrap_global();
+    let ref_ty = global.content().unwrap_ref();
+    let struct_ty = ref_ty.heap_type().unwrap_concrete_struct();
+
+    let mut store = Store::new(&engine, ());
+    let pre = StructRefPre::new(&mut store, struct_ty.clone());
+    let num_refs_dropped = Arc::new(AtomicUsize::new(0));
+
+    let len = 100;
+    {
+        let mut store = RootScope::new(&mut store);
+
+        let mut cdr = None;
+        for i in 0..len {
+            let externref = ExternRef::new(&mut store, i)?;
+            let cons = StructRef::new(&mut store, &pre, &[externref.into(), cdr.into()])?;
+            cdr = Some(cons);
+        }
+        let list = cdr.unwrap();
+
+        // Collecting moves every object in the list, but the list must remain
+        // intact and our root must be updated to point at its new head.
+        for _ in 0..3 {
+            store.as_context_mut().gc(None);
+        }
+        let mut expected = len;
+        let mut cell = Some(list);
+        while let Some(cons) = cell {
+            expected -= 1;
+            let car = cons.field(&mut store, 0)?;
+            let car = car.unwrap_externref().unwrap();
+            let data = car.data(&store)?.unwrap();
+            assert_eq!(data.downcast_ref::<usize>(), Some(&expected));
+            cell = cons
+                .field(&mut store, 1)?
+                .unwrap_anyref()
+                .map(|a| a.unwrap_struct(&store).unwrap());
+        }
+        assert_eq!(expected, 0);
+
+        // Make a cycle that also holds some host data alive.
+        let externref = ExternRef::new(&mut store, CountDrops(num_refs_dropped.clone()))?;
+        let a = StructRef::new(&mut store, &pre, &[externref.into(), Val::AnyRef(None)])?;
+        let b = StructRef::new(&mut store, &pre, &[Val::ExternRef(None), a.into()])?;
+        a.set_field(&mut store, 1, b.into())?;
+
+        store.as_context_mut().gc(None);
+        assert_eq!(num_refs_dropped.load(SeqCst), 0);
+    }
+
+    // Nothing is rooted anymore, so the cycle is garbage and its host data is
+    // dropped.
+    store.as_context_mut().gc(None);
+    assert_eq!(num_refs_dropped.load(SeqCst), 1);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn copying_wasm_allocation_triggers_collections() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::Copying);
+    config.memory_reservation(1 << 16);
+    config.memory_reservation_for_growth(0);
+    config.memory_guard_size(0);
+    config.memory_may_move(false);
+
+    let engine = Engine::new(&config)?;
+
+    // Build a long list while allocating lots of garbage along the way, so
+    // that the GC heap fills up many times over and the list has to survive
+    // being moved by collections triggered in the middle of Wasm code.
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $cons (struct (field i32) (field (ref null $cons))))
+                (type $garbage (array i64))
+
+                (func (export "run") (param $n i32) (result i32)
+                    (local $list (ref null $cons))
+                    (local $i i32)
+                    (local $sum i32)
+
+                    (loop $build
+                        (drop (array.new_default $garbage (i32.const 32)))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (local.set $list (struct.new $cons (local.get $i) (local.get $list)))
+                        (drop (array.new_default $garbage (i32.const 32)))
+                        (br_if $build (i32.lt_u (local.get $i) (local.get $n)))
+                    )
+
+                    (loop $sum
+                        (local.set $sum
+                            (i32.add (local.get $sum) (struct.get $cons 0 (local.get $list))))
+                        (local.set $list (struct.get $cons 1 (local.get $list)))
+                        (br_if $sum (i32.eqz (ref.is_null (local.get $list))))
+                    )
+                    (local.get $sum)
+                )
+            )
+        "#,
+    )?;
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+
+    let n = 1000;
+    assert_eq!(run.call(&mut store, n)?, n * (n + 1) / 2);
+    assert_eq!(run.call(&mut store, n)?, n * (n + 1) / 2);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn copying_full_heap_of_v128_structs() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::Copying);
+    let engine = Engine::new(&config)?;
+
+    // Keep the GC heap at a single 64 KiB page, so each semi-space holds
+    // 32752 bytes, starting at heap index 8.
+    let mut store = Store::new(
+        &engine,
+        StoreLimitsBuilder::new().gc_heap_size(1 << 16).build(),
+    );
+    store.limiter(|s| s as &mut dyn ResourceLimiter);
+
+    // 32-byte structs with 16-byte alignment, and 24-byte structs with 8-byte
+    // alignment.
+    let v128_ty = StructType::new(
+        &engine,
+        [FieldType::new(
+            Mutability::Const,
+            StorageType::ValType(ValType::V128),
+        )],
+    )?;
+    let v128_pre = StructRefPre::new(&mut store, v128_ty);
+    let anyref = FieldType::new(Mutability::Const, StorageType::ValType(ValType::ANYREF));
+    let small_ty = StructType::new(&engine, [anyref.clone(), anyref.clone(), anyref])?;
+    let small_pre = StructRefPre::new(&mut store, small_ty);
+    let array_ty = ArrayType::new(
+        &engine,
+        FieldType::new(Mutability::Var, StorageType::ValType(ValType::ANYREF)),
+    );
+    let array_pre = ArrayRefPre::new(&mut store, array_ty);
+
+    // Fill the heap, without any padding, with an array holding everything
+    // followed by blocks of one v128 struct and two small ones. The array
+    // lists each v128 struct right after a single small struct though, so
+    // copying objects in the array's order would need 8 bytes of padding
+    // before every v128 struct, which doesn't fit in the to-space.
+    let blocks = 353;
+    let mut store = RootScope::new(&mut store);
+    let array = ArrayRef::new(&mut store, &array_pre, &Val::AnyRef(None), 3 * blocks)?;
+    for i in 0..blocks {
+        let mut store = RootScope::new(&mut store);
+        let v128 = StructRef::new(&mut store, &v128_pre, &[Val::V128(u128::from(i).into())])?;
+        let small = [Val::AnyRef(None), Val::AnyRef(None), Val::AnyRef(None)];
+        let a = StructRef::new(&mut store, &small_pre, &small)?;
+        let b = StructRef::new(&mut store, &small_pre, &small)?;
+        array.set(&mut store, 2 * i, a.into())?;
+        array.set(&mut store, 2 * i + 1, v128.into())?;
+        array.set(&mut store, 2 * blocks + i, b.into())?;
+    }
+
+    store.as_context_mut().gc(None);
+
+    for i in 0..blocks {
+        let v128 = array.get(&mut store, 2 * i + 1)?;
+        let v128 = v128.unwrap_anyref().unwrap().unwrap_struct(&store)?;
+        let value = v128.field(&mut store, 0)?.unwrap_v128();
+        assert_eq!(value.as_u128(), u128::from(i));
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_heap_graph() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    for collector in [
+        Collector::DeferredReferenceCounting,
+        Collector::Null,
+        Collector::Copying,
+    ] {
+        let mut config = Config::new();
+        config.wasm_function_references(true);
+        config.wasm_gc(true);
+        config.collector(collector);
+        let engine = Engine::new(&config)?;
+
+        let module = Module::new(
+            &engine,
+            r#"
+                (module
+                    (type $arr (array (mut anyref)))
+                    (type $s (struct (field i32) (field (ref null $arr)) (field externref)))
+                    (global $g (export "g") (mut anyref) (ref.null any))
+
+                    (func (export "init") (param externref)
+                        (local $a (ref $arr))
+                        ;; Garbage that should not appear in the graph.
+                        (drop (struct.new $s (i32.const 0) (ref.null $arr) (ref.null extern)))
+
+                        (local.set $a (array.new $arr (ref.i31 (i32.const 7)) (i32.const 3)))
+                        (global.set $g (struct.new $s (i32.const 1) (local.get $a) (local.get 0)))
+                        ;; Make a cycle back to the struct, and reference it
+                        ;; twice.
+                        (array.set $arr (local.get $a) (i32.const 0) (global.get $g))
+                        (array.set $arr (local.get $a) (i32.const 2) (global.get $g))
+                    )
+                )
+            "#,
+        )?;
+
+        let mut store = Store::new(&engine, ());
+        assert!(store.gc_heap_graph().objects().is_empty());
+
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let init = instance.get_typed_func::<Option<Rooted<ExternRef>>, ()>(&mut store, "init")?;
+        {
+            let mut scope = RootScope::new(&mut store);
+            let externref = ExternRef::new(&mut scope, "hello")?;
+            init.call(&mut scope, Some(externref))?;
+        }
+
+        let graph = store.gc_heap_graph();
+        assert_eq!(graph.objects().len(), 3, "{collector:?}: {graph:#?}");
+        assert_eq!(graph.roots().len(), 1);
+
+        let s = &graph.objects()[graph.roots()[0]];
+        assert!(matches!(s.kind(), GcHeapObjectKind::Struct(_)));
+        assert_eq!(s.references().len(), 2);
+
+        let a = &graph.objects()[s.references()[0]];
+        let GcHeapObjectKind::Array(array_ty) = a.kind() else {
+            panic!("expected an array, got {:?}", a.kind());
+        };
+        assert!(array_ty.mutability().is_var());
+        assert_eq!(a.references(), &[graph.roots()[0], graph.roots()[0]]);
+
+        let e = &graph.objects()[s.references()[1]];
+        assert!(matches!(e.kind(), GcHeapObjectKind::Extern));
+        assert!(e.references().is_empty());
+
+        assert!(graph.objects().iter().all(|o| o.size() > 0));
+        assert_eq!(
+            graph.total_size(),
+            graph.objects().iter().map(|o| o.size()).sum::<usize>()
+        );
+
+        // The Chrome heap snapshot has one node for every object plus a
+        // synthetic root, and one edge for every reference plus root.
+        let snapshot: serde_json::Value = serde_json::from_str(&graph.to_chrome_heap_snapshot())?;
+        let node_fields = snapshot["snapshot"]["meta"]["node_fields"]
+            .as_array()
+            .unwrap()
+            .len();
+        let edge_fields = snapshot["snapshot"]["meta"]["edge_fields"]
+            .as_array()
+            .unwrap()
+            .len();
+        let nodes = snapshot["nodes"].as_array().unwrap();
+        let edges = snapshot["edges"].as_array().unwrap();
+        assert_eq!(nodes.len(), 4 * node_fields);
+        assert_eq!(edges.len(), 5 * edge_fields);
+        assert_eq!(snapshot["snapshot"]["node_count"], 4);
+        assert_eq!(snapshot["snapshot"]["edge_count"], 5);
+        let strings = snapshot["strings"].as_array().unwrap();
+        assert!(strings.iter().any(|s| s == "(GC roots)"));
+        assert!(strings.iter().any(|s| s == "externref"));
+
+        // Struct field edges are named after the field's index, and array
+        // element edges use the element's index, skipping over non-reference
+        // fields and null or `i31ref` elements.
+        let edges = edges
+            .chunks(edge_fields)
+            .map(|e| (e[0].as_u64().unwrap(), e[1].as_u64().unwrap()))
+            .collect::<Vec<_>>();
+        let fields = edges
+            .iter()
+            .filter(|(ty, _)| *ty == 2)
+            .map(|(_, name)| strings[*name as usize].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, ["1", "2"]);
+        let elements = edges
+            .iter()
+            .skip(1)
+            .filter(|(ty, _)| *ty == 1)
+            .map(|(_, index)| *index)
+            .collect::<Vec<_>>();
+        assert_eq!(elements, [0, 2]);
+    }
+
+    Ok(())
+}
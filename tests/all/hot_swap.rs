@@ -0,0 +1,394 @@
+use wasmtime::*;
+
+fn counter(step: i32) -> String {
+    format!(
+        r#"
+            (module
+                (import "host" "log" (func $log (param i32)))
+                (memory (export "memory") 1)
+                (global $count (mut i32) (i32.const 0))
+                (table 1 funcref)
+                (elem (i32.const 0) $incr)
+                (type $t (func (result i32)))
+
+                (func $incr (export "incr") (result i32)
+                    (global.set $count (i32.add (global.get $count) (i32.const {step})))
+                    (i32.store (i32.const 0) (global.get $count))
+                    (call $log (global.get $count))
+                    (global.get $count))
+
+                (func (export "incr_indirect") (result i32)
+                    (call_indirect (type $t) (i32.const 0)))
+
+                (start $init)
+                (func $init
+                    (i32.store (i32.const 4) (i32.const {step})))
+            )
+        "#
+    )
+}
+
+fn linker(engine: &Engine) -> Result<Linker<Vec<i32>>> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap("host", "log", |mut caller: Caller<'_, Vec<i32>>, x: i32| {
+        caller.data_mut().push(x);
+    })?;
+    Ok(linker)
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn hot_swap_keeps_state() -> Result<()> {
+    let engine = Engine::default();
+    let linker = linker(&engine)?;
+    let mut store = Store::new(&engine, Vec::new());
+
+    let v1 = Module::new(&engine, counter(1))?;
+    let old = linker.instantiate(&mut store, &v1)?;
+    let incr = old.get_typed_func::<(), i32>(&mut store, "incr")?;
+    assert_eq!(incr.call(&mut store, ())?, 1);
+    assert_eq!(incr.call(&mut store, ())?, 2);
+
+    let v2 = Module::new(&engine, counter(10))?;
+    let new = linker.instantiate_pre(&v2)?.hot_swap(&mut store, old)?;
+
+    // The new code picks up where the old code left off.
+    let incr = new.get_typed_func::<(), i32>(&mut store, "incr")?;
+    assert_eq!(incr.call(&mut store, ())?, 12);
+
+    // Tables refer to the new code.
+    let incr_indirect = new.get_typed_func::<(), i32>(&mut store, "incr_indirect")?;
+    assert_eq!(incr_indirect.call(&mut store, ())?, 22);
+
+    // Memory was carried over, and the start function didn't run again.
+    let memory = new.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[..8], &[22, 0, 0, 0, 1, 0, 0, 0]);
+
+    // The old instance keeps its globals, but not its memory, and using it
+    // doesn't affect the new instance.
+    let incr = old.get_typed_func::<(), i32>(&mut store, "incr")?;
+    assert_eq!(incr.call(&mut store, ())?, 3);
+    let old_memory = old.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&old_memory.data(&store)[..8], &[3, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(&memory.data(&store)[..8], &[22, 0, 0, 0, 1, 0, 0, 0]);
+
+    assert_eq!(store.data(), &[1, 2, 12, 22, 3]);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn hot_swap_grows_memory() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let v1 = Module::new(&engine, r#"(module (memory (export "memory") 1))"#)?;
+    let old = Instance::new(&mut store, &v1, &[])?;
+    let memory = old.get_memory(&mut store, "memory").unwrap();
+    memory.grow(&mut store, 1)?;
+    memory.data_mut(&mut store)[0x1_0000] = 42;
+
+    // The new module's data segments don't overwrite the old state, even
+    // beyond the end of the old memory.
+    let linker = Linker::new(&engine);
+    let v2 = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "memory") 3)
+                (data (i32.const 0x1_0000) "\01")
+                (data (i32.const 0x2_0000) "\02")
+            )
+        "#,
+    )?;
+    let new = linker.instantiate_pre(&v2)?.hot_swap(&mut store, old)?;
+    let memory = new.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 3);
+    assert_eq!(memory.data(&store)[0x1_0000], 42);
+    assert_eq!(memory.data(&store)[0x2_0000], 0);
+
+    // Memories with a different maximum size are an error.
+    let v3 = Module::new(&engine, r#"(module (memory (export "memory") 1 3))"#)?;
+    let err = linker.instantiate_pre(&v3)?.hot_swap(&mut store, new);
+    assert!(err.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn hot_swap_failed_instantiation_keeps_sizes() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, StoreLimitsBuilder::new().instances(1).build());
+    store.limiter(|limits| limits);
+    let v1 = Module::new(
+        &engine,
+        r#"(module (memory (export "memory") 1) (table (export "table") 1 funcref))"#,
+    )?;
+    let old = Instance::new(&mut store, &v1, &[])?;
+
+    // Creating the new instance fails, so the old memory and table aren't
+    // grown to the new module's minimum sizes.
+    let linker = Linker::new(&engine);
+    let v2 = Module::new(
+        &engine,
+        r#"(module (memory (export "memory") 3) (table (export "table") 4 funcref))"#,
+    )?;
+    assert!(
+        linker
+            .instantiate_pre(&v2)?
+            .hot_swap(&mut store, old)
+            .is_err()
+    );
+    let memory = old.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 1);
+    let table = old.get_table(&mut store, "table").unwrap();
+    assert_eq!(table.size(&store), 1);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn hot_swap_repeatedly_moves_state() -> Result<()> {
+    #[derive(Default)]
+    struct Usage {
+        memory_bytes: usize,
+        table_elements: usize,
+    }
+
+    impl ResourceLimiter for Usage {
+        fn memory_growing(
+            &mut self,
+            current: usize,
+            desired: usize,
+            _: Option<usize>,
+        ) -> Result<bool> {
+            self.memory_bytes += desired - current;
+            Ok(true)
+        }
+        fn table_growing(
+            &mut self,
+            current: usize,
+            desired: usize,
+            _: Option<usize>,
+        ) -> Result<bool> {
+            self.table_elements += desired - current;
+            Ok(true)
+        }
+    }
+
+    let version = |n: i32| {
+        format!(
+            r#"
+                (module
+                    (memory (export "memory") 1)
+                    (table (export "table") 1 funcref)
+                    (elem (i32.const 0) $get)
+                    (func $get (result i32) (i32.const {n}))
+                    (func (export "get") (result i32)
+                        (call_indirect (result i32) (i32.const 0)))
+                )
+            "#
+        )
+    };
+
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, Usage::default());
+    store.limiter(|usage| usage);
+    let linker = Linker::new(&engine);
+    let versions = [
+        linker.instantiate_pre(&Module::new(&engine, version(1))?)?,
+        linker.instantiate_pre(&Module::new(&engine, version(2))?)?,
+    ];
+    let mut instance = versions[0].instantiate(&mut store)?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    memory.grow(&mut store, 63)?;
+    let table = instance.get_table(&mut store, "table").unwrap();
+    table.grow(&mut store, 999, Ref::Func(None))?;
+    let before = (store.data().memory_bytes, store.data().table_elements);
+    assert_eq!(before, (64 * 0x1_0000, 1000));
+
+    for i in 1..=21 {
+        instance = versions[i % 2].hot_swap(&mut store, instance)?;
+    }
+    let get = instance.get_typed_func::<(), i32>(&mut store, "get")?;
+    assert_eq!(get.call(&mut store, ())?, 2);
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 64);
+    let table = instance.get_table(&mut store, "table").unwrap();
+    assert_eq!(table.size(&store), 1000);
+
+    // Each swap only allocates the new module's initial memory and table, and
+    // empty ones for the old instance, rather than copies of the state that
+    // was moved.
+    let after = (store.data().memory_bytes, store.data().table_elements);
+    assert_eq!(after, (before.0 + 42 * 0x1_0000, before.1 + 42));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn hot_swap_frees_pooled_memories_and_tables() -> Result<()> {
+    let version = |n: i32| {
+        format!(
+            r#"
+                (module
+                    (memory (export "memory") 1)
+                    (table (export "table") 1 funcref)
+                    (elem (i32.const 0) $get)
+                    (func $get (result i32) (i32.const {n}))
+                    (func (export "get") (result i32)
+                        (call_indirect (result i32) (i32.const 0)))
+                    (func (export "store") (param i32)
+                        (i32.store (i32.const 0) (local.get 0)))
+                )
+            "#
+        )
+    };
+
+    // Only two memories and tables fit in the pool, so swapping more than
+    // once would fail if the replaced ones weren't returned to it.
+    let mut pool = crate::small_pool_config();
+    pool.total_memories(2)
+        .total_tables(2)
+        .total_core_instances(100);
+    let mut config = Config::new();
+    config.allocation_strategy(InstanceAllocationStrategy::Pooling(pool));
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+    let linker = Linker::new(&engine);
+    let versions = [
+        linker.instantiate_pre(&Module::new(&engine, version(1))?)?,
+        linker.instantiate_pre(&Module::new(&engine, version(2))?)?,
+    ];
+    let mut instance = versions[0].instantiate(&mut store)?;
+    let store_fn = instance.get_typed_func::<i32, ()>(&mut store, "store")?;
+    store_fn.call(&mut store, 42)?;
+
+    let mut old = Vec::new();
+    for i in 1..=20 {
+        old.push(instance);
+        instance = versions[i % 2].hot_swap(&mut store, instance)?;
+    }
+    let get = instance.get_typed_func::<(), i32>(&mut store, "get")?;
+    assert_eq!(get.call(&mut store, ())?, 1);
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[..4], &[42, 0, 0, 0]);
+
+    // The old instances are left with empty memories and tables which are
+    // still safe to use.
+    for old in old {
+        let memory = old.get_memory(&mut store, "memory").unwrap();
+        assert_eq!(memory.size(&store), 1);
+        assert_eq!(&memory.data(&store)[..4], &[0, 0, 0, 0]);
+        let store_fn = old.get_typed_func::<i32, ()>(&mut store, "store")?;
+        store_fn.call(&mut store, 7)?;
+        assert_eq!(&memory.data(&store)[..4], &[7, 0, 0, 0]);
+        let table = old.get_table(&mut store, "table").unwrap();
+        assert_eq!(table.size(&store), 1);
+    }
+    assert_eq!(
+        &instance
+            .get_memory(&mut store, "memory")
+            .unwrap()
+            .data(&store)[..4],
+        &[42, 0, 0, 0]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn hot_swap_rejects_async_stores() -> Result<()> {
+    let mut config = Config::new();
+    config.async_support(true);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+    let module = Module::new(&engine, r#"(module (memory (export "memory") 1))"#)?;
+    let linker = Linker::new(&engine);
+    let pre = linker.instantiate_pre(&module)?;
+    let old = pre.instantiate_async(&mut store).await?;
+    let err = pre.hot_swap(&mut store, old).unwrap_err();
+    assert!(format!("{err:?}").contains("async support"), "{err:?}");
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn hot_swap_rejects_incompatible_modules() -> Result<()> {
+    let engine = Engine::default();
+    let linker = linker(&engine)?;
+    let mut store = Store::new(&engine, Vec::new());
+    let old = linker.instantiate(&mut store, &Module::new(&engine, counter(1))?)?;
+
+    let check = |store: &mut Store<Vec<i32>>, wat: &str, expected: &str| -> Result<()> {
+        let module = Module::new(&engine, wat)?;
+        let err = linker
+            .instantiate_pre(&module)?
+            .hot_swap(&mut *store, old)
+            .unwrap_err();
+        let err = format!("{err:?}");
+        assert!(err.contains(expected), "{err}");
+        Ok(())
+    };
+
+    check(
+        &mut store,
+        r#"(module (memory (export "memory") 1))"#,
+        "missing export `incr`",
+    )?;
+    check(
+        &mut store,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "incr") (result i64) i64.const 0)
+                (func (export "incr_indirect") (result i32) i32.const 0)
+            )
+        "#,
+        "incompatible type for export `incr`",
+    )?;
+    check(
+        &mut store,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "incr") (result i32) i32.const 0)
+                (func (export "incr_indirect") (result i32) i32.const 0)
+            )
+        "#,
+        "defines 0 tables",
+    )?;
+    check(
+        &mut store,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (global (mut i64) (i64.const 0))
+                (table 1 funcref)
+                (func (export "incr") (result i32) i32.const 0)
+                (func (export "incr_indirect") (result i32) i32.const 0)
+            )
+        "#,
+        "incompatible type for defined global 0",
+    )?;
+    check(
+        &mut store,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (global (mut i32) (i32.const 0))
+                (table 1 funcref)
+                (func (export "incr") (result i32) i32.const 0)
+                (func (param i32))
+                (func (export "incr_indirect") (result i32) i32.const 0)
+            )
+        "#,
+        "function 1 is referenced",
+    )?;
+
+    // The instance is still usable after a failed swap.
+    let incr = old.get_typed_func::<(), i32>(&mut store, "incr")?;
+    assert_eq!(incr.call(&mut store, ())?, 1);
+    let memory = old.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[..8], &[1, 0, 0, 0, 1, 0, 0, 0]);
+    Ok(())
+}
@@ -1175,3 +1175,241 @@ fn growth_trap() -> Result<()> {
 
     Ok(())
 }
+
+const GC_HEAP_LIMITS_WAT: &str = r#"
+    (module
+        (memory (export "m") 0)
+        (type $bytes (array (mut i8)))
+        (type $node (struct (field (ref null $node)) (field (ref $bytes))))
+        (global $list (mut (ref null $node)) (ref.null $node))
+
+        ;; Allocate `n` 64 KiB byte arrays and keep all of them alive.
+        (func (export "alloc") (param $n i32)
+            (loop $loop
+                (if (i32.eqz (local.get $n)) (then (return)))
+                (global.set $list
+                    (struct.new $node
+                        (global.get $list)
+                        (array.new_default $bytes (i32.const 65536))))
+                (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                (br $loop)
+            )
+        )
+
+        (func (export "grow") (param i32) (result i32)
+            (memory.grow (local.get 0)))
+    )
+"#;
+
+fn gc_heap_limits_engine(collector: Collector) -> Result<Engine> {
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(collector);
+    Engine::new(&config)
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_heap_size_limit() -> Result<()> {
+    for collector in [
+        Collector::DeferredReferenceCounting,
+        Collector::Null,
+        Collector::Copying,
+    ] {
+        let engine = gc_heap_limits_engine(collector)?;
+        let module = Module::new(&engine, GC_HEAP_LIMITS_WAT)?;
+
+        let mut store = Store::new(
+            &engine,
+            StoreLimitsBuilder::new().gc_heap_size(1 << 20).build(),
+        );
+        store.limiter(|s| s as &mut dyn ResourceLimiter);
+        let instance = Instance::new(&mut store, &module, &[])?;
+
+        // Keeping 2 MiB of arrays alive exceeds the GC heap's limit.
+        let alloc = instance.get_typed_func::<i32, ()>(&mut store, "alloc")?;
+        alloc.call(&mut store, 4)?;
+        assert!(alloc.call(&mut store, 32).is_err());
+
+        // But the GC heap's limit does not apply to linear memories.
+        let grow = instance.get_typed_func::<i32, i32>(&mut store, "grow")?;
+        assert_eq!(grow.call(&mut store, 64)?, 0);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_heap_size_limit_not_power_of_two() -> Result<()> {
+    // The number of 64 KiB arrays that can be kept alive within a 3 MiB heap,
+    // but not within a 2 MiB one. The copying collector only uses half of its
+    // heap at a time.
+    for (collector, n) in [
+        (Collector::DeferredReferenceCounting, 32),
+        (Collector::Null, 32),
+        (Collector::Copying, 20),
+    ] {
+        let engine = gc_heap_limits_engine(collector)?;
+        let module = Module::new(&engine, GC_HEAP_LIMITS_WAT)?;
+
+        let mut store = Store::new(
+            &engine,
+            StoreLimitsBuilder::new().gc_heap_size(3 << 20).build(),
+        );
+        store.limiter(|s| s as &mut dyn ResourceLimiter);
+        let instance = Instance::new(&mut store, &module, &[])?;
+
+        // Doubling the heap to 4 MiB would exceed the limit, but growing by
+        // just what's needed doesn't.
+        let alloc = instance.get_typed_func::<i32, ()>(&mut store, "alloc")?;
+        alloc.call(&mut store, n)?;
+        assert!(alloc.call(&mut store, n).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_heap_size_limit_trap_on_grow_failure() -> Result<()> {
+    for (collector, n) in [
+        (Collector::DeferredReferenceCounting, 32),
+        (Collector::Null, 32),
+        (Collector::Copying, 20),
+    ] {
+        let engine = gc_heap_limits_engine(collector)?;
+        let module = Module::new(&engine, GC_HEAP_LIMITS_WAT)?;
+
+        let mut store = Store::new(
+            &engine,
+            StoreLimitsBuilder::new()
+                .gc_heap_size(3 << 20)
+                .trap_on_grow_failure(true)
+                .build(),
+        );
+        store.limiter(|s| s as &mut dyn ResourceLimiter);
+        let instance = Instance::new(&mut store, &module, &[])?;
+
+        // Doubling the heap to 4 MiB is rejected without trapping, and growing
+        // by just what's needed succeeds.
+        let alloc = instance.get_typed_func::<i32, ()>(&mut store, "alloc")?;
+        alloc.call(&mut store, n)?;
+
+        // Only when even that is rejected does growth trap.
+        let err = alloc.call(&mut store, n).unwrap_err();
+        assert!(
+            format!("{err:?}").contains("forcing trap when growing the GC heap"),
+            "{err:?}"
+        );
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct GcHeapLimiter {
+    gc_heap_limit: usize,
+    gc_heap_growing_calls: usize,
+    memory_growing_calls: usize,
+    out_of_memory_calls: usize,
+    raise_limit_on_oom: bool,
+}
+
+impl ResourceLimiter for GcHeapLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        self.memory_growing_calls += 1;
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn gc_heap_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        self.gc_heap_growing_calls += 1;
+        Ok(desired <= self.gc_heap_limit)
+    }
+
+    fn gc_heap_out_of_memory(&mut self, _bytes_needed: u64) -> Result<bool> {
+        self.out_of_memory_calls += 1;
+        if self.raise_limit_on_oom {
+            self.gc_heap_limit = usize::MAX;
+        }
+        Ok(self.raise_limit_on_oom)
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn custom_gc_heap_limiter() -> Result<()> {
+    for collector in [
+        Collector::DeferredReferenceCounting,
+        Collector::Null,
+        Collector::Copying,
+    ] {
+        let engine = gc_heap_limits_engine(collector)?;
+        let module = Module::new(&engine, GC_HEAP_LIMITS_WAT)?;
+
+        let mut store = Store::new(
+            &engine,
+            GcHeapLimiter {
+                gc_heap_limit: 1 << 20,
+                ..Default::default()
+            },
+        );
+        store.limiter(|s| s as &mut dyn ResourceLimiter);
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let alloc = instance.get_typed_func::<i32, ()>(&mut store, "alloc")?;
+        let memory_growing_calls = store.data().memory_growing_calls;
+
+        assert!(alloc.call(&mut store, 32).is_err());
+        let limiter = store.data();
+        assert!(limiter.gc_heap_growing_calls > 0);
+        assert_eq!(limiter.memory_growing_calls, memory_growing_calls);
+        assert!(limiter.out_of_memory_calls > 0);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn gc_heap_out_of_memory_callback_can_retry() -> Result<()> {
+    for collector in [
+        Collector::DeferredReferenceCounting,
+        Collector::Null,
+        Collector::Copying,
+    ] {
+        let engine = gc_heap_limits_engine(collector)?;
+        let module = Module::new(&engine, GC_HEAP_LIMITS_WAT)?;
+
+        let mut store = Store::new(
+            &engine,
+            GcHeapLimiter {
+                gc_heap_limit: 1 << 20,
+                raise_limit_on_oom: true,
+                ..Default::default()
+            },
+        );
+        store.limiter(|s| s as &mut dyn ResourceLimiter);
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let alloc = instance.get_typed_func::<i32, ()>(&mut store, "alloc")?;
+
+        alloc.call(&mut store, 32)?;
+        assert_eq!(store.data().out_of_memory_calls, 1);
+    }
+    Ok(())
+}
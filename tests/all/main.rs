@@ -12,6 +12,7 @@ mod coredump;
 mod custom_code_memory;
 mod debug;
 mod defaults;
+mod deterministic;
 mod epoch_interruption;
 mod externals;
 mod fuel;
@@ -20,6 +21,7 @@ mod funcref;
 mod gc;
 mod globals;
 mod host_funcs;
+mod hot_swap;
 mod i31ref;
 mod iloop;
 mod import_calling_export;
@@ -29,6 +31,7 @@ mod invoke_func_via_table;
 mod limits;
 mod linker;
 mod memory;
+mod memory_access_tracing;
 mod memory_creator;
 mod module;
 mod module_serialize;
@@ -37,7 +40,9 @@ mod noextern;
 mod piped_tests;
 mod pooling_allocator;
 mod pulley;
+mod record_replay;
 mod relocs;
+mod snapshot;
 mod stack_creator;
 mod stack_overflow;
 mod store;
@@ -46,6 +51,7 @@ mod table;
 #[cfg(all(feature = "stack-switching", unix, target_arch = "x86_64"))]
 mod tags;
 mod threads;
+mod tiered;
 mod traps;
 mod types;
 mod wait_notify;
@@ -312,6 +312,80 @@ fn guards_present_pooling_mpk(config: &mut Config) -> Result<()> {
     Ok(())
 }
 
+#[wasmtime_test]
+#[cfg_attr(miri, ignore)]
+#[cfg_attr(asan, ignore)]
+#[cfg(target_arch = "x86_64")] // only platform with mpk
+fn guards_present_on_demand_mpk(config: &mut Config) -> Result<()> {
+    if !wasmtime::PoolingAllocationConfig::are_memory_protection_keys_available() {
+        println!("skipping `guards_present_on_demand_mpk` test; mpk is not supported");
+        return Ok(());
+    }
+
+    const GUARD_SIZE: u64 = 65536;
+    config
+        .memory_protection_keys(MpkEnabled::Enable)
+        .max_memory_protection_keys(2)
+        .max_protected_memory_size(10 << 16);
+    config.memory_reservation(1 << 20);
+    config.memory_guard_size(GUARD_SIZE);
+    config.guard_before_linear_memory(true);
+    let engine = Engine::new(&config)?;
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "") 1 10)
+                (func (export "bump") (result i32)
+                    (i32.store (i32.const 0) (i32.add (i32.load (i32.const 0)) (i32.const 1)))
+                    (i32.load (i32.const 0))))
+        "#,
+    )?;
+    let mut stores = Vec::new();
+    for _ in 0..4 {
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let memory = instance.get_memory(&mut store, "").unwrap();
+        let bump = instance.get_typed_func::<(), i32>(&mut store, "bump")?;
+        stores.push((store, memory, bump));
+    }
+
+    for (store, mem, bump) in &mut stores {
+        assert_eq!(bump.call(&mut *store, ())?, 1);
+        unsafe {
+            assert_faults(mem.data_ptr(&store).offset(-(GUARD_SIZE as isize)));
+            assert_faults(mem.data_ptr(&store).add(mem.data_size(&store)));
+            assert_faults(mem.data_ptr(&store).add(1 << 20));
+        }
+    }
+
+    // Protected memories grow within their slot up to their maximum. Note
+    // that the host can access all stripes, so guard regions are only checked
+    // above, before neighboring memories grow into them.
+    for (store, mem, bump) in &mut stores {
+        mem.grow(&mut *store, 9)?;
+        assert!(mem.grow(&mut *store, 1).is_err());
+        assert_eq!(bump.call(&mut *store, ())?, 2);
+    }
+
+    // Memories which may grow beyond a protected slot, whether by their
+    // minimum, their maximum, or having no maximum at all, are allocated as
+    // usual and can grow up to their own maximum.
+    for memory in ["11", "1 11", "1"] {
+        let mut store = Store::new(&engine, ());
+        let wat = format!(r#"(module (memory (export "") {memory}))"#);
+        let module = Module::new(&engine, &wat)?;
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let memory = instance.get_memory(&mut store, "").unwrap();
+        let size = memory.size(&store);
+        memory.grow(&mut store, 11 - size)?;
+        assert_eq!(memory.size(&store), 11);
+    }
+
+    Ok(())
+}
+
 unsafe fn assert_faults(ptr: *mut u8) {
     use std::io::Error;
     #[cfg(unix)]
@@ -773,3 +847,48 @@ fn configure_zero(config: &mut Config) -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg_attr(asan, ignore)]
+#[cfg(target_os = "linux")]
+fn huge_pages() -> Result<()> {
+    const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+    let mut pool = crate::small_pool_config();
+    pool.total_memories(2).max_memory_size(4 << 20);
+    for pooling in [false, true] {
+        let mut config = Config::new();
+        config.memory_huge_pages(true);
+        config.memory_reservation(4 << 20);
+        config.memory_guard_size(1 << 16);
+        if pooling {
+            config.allocation_strategy(pool.clone());
+        }
+        let engine = Engine::new(&config)?;
+        let module = Module::new(
+            &engine,
+            r#"
+                (module
+                    (memory (export "m") 1 64)
+                    (data (i32.const 0x100) "hello"))
+            "#,
+        )?;
+
+        let mut store = Store::new(&engine, ());
+        for _ in 0..2 {
+            let instance = Instance::new(&mut store, &module, &[])?;
+            let memory = instance.get_memory(&mut store, "m").unwrap();
+            assert_eq!(memory.data_ptr(&store) as usize % HUGE_PAGE_SIZE, 0);
+            assert_eq!(&memory.data(&store)[0x100..0x105], b"hello");
+
+            memory.grow(&mut store, 63)?;
+            memory.data_mut(&mut store)[(4 << 20) - 1] = 1;
+            unsafe {
+                assert_faults(memory.data_ptr(&store).add(4 << 20));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use wasmtime::*;
+
+fn tracing_engine() -> Result<Engine> {
+    let mut config = Config::new();
+    config.memory_access_tracing(true);
+    config.wasm_threads(true);
+    Engine::new(&config)
+}
+
+const WAT: &str = r#"
+    (module
+        (import "" "memory" (memory 1 1 shared))
+        (memory $private 1)
+        (func $helper)
+        (func (export "run") (param i32)
+            (i32.store offset=4 (local.get 0) (i32.const 1))
+            (drop (i64.load8_u $private offset=1 (local.get 0)))
+            (drop (i32.atomic.rmw.add (i32.const 8) (i32.const 1)))
+            (i64.atomic.store (i32.const 16) (i64.const 2))
+            (drop (v128.load (i32.const 32))))
+    )
+"#;
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn memory_access_tracing_reports_accesses() -> Result<()> {
+    let engine = tracing_engine()?;
+    let wasm = wat::parse_str(WAT)?;
+    let module = Module::new(&engine, &wasm)?;
+    let mut store = Store::new(&engine, Vec::new());
+    store.memory_access_hook(|mut store, access| {
+        store.data_mut().push(access);
+        Ok(())
+    });
+    let memory = SharedMemory::new(&engine, MemoryType::shared(1, 1))?;
+    let instance = Instance::new(&mut store, &module, &[memory.into()])?;
+    let run = instance.get_typed_func::<i32, ()>(&mut store, "run")?;
+    run.call(&mut store, 100)?;
+
+    let accesses = store.data().clone();
+    let summary = accesses
+        .iter()
+        .map(|a| (a.kind(), a.memory_index(), a.address(), a.size()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (MemoryAccessKind::Store, 0, 104, 4),
+            (MemoryAccessKind::Load, 1, 101, 1),
+            (MemoryAccessKind::ReadModifyWrite, 0, 8, 4),
+            (MemoryAccessKind::Store, 0, 16, 8),
+            (MemoryAccessKind::Load, 0, 32, 16),
+        ]
+    );
+
+    // Each access is attributed to the instruction that performed it.
+    let opcodes = [0x36, 0x31, 0xfe, 0xfe, 0xfd];
+    for (access, opcode) in accesses.iter().zip(opcodes) {
+        assert_eq!(access.func_index(), 1);
+        assert!(access.instance().get_export(&mut store, "run").is_some());
+        assert_eq!(wasm[access.module_offset()], opcode);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn memory_access_hook_can_trap() -> Result<()> {
+    let engine = tracing_engine()?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "store") (param i32)
+                    (i32.store8 (local.get 0) (i32.const 1)))
+            )
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    store.memory_access_hook(|_, access| {
+        if access.address() == 0 {
+            anyhow::bail!("null pointer write");
+        }
+        Ok(())
+    });
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    let func = instance.get_typed_func::<i32, ()>(&mut store, "store")?;
+
+    func.call(&mut store, 1)?;
+    let err = func.call(&mut store, 0).unwrap_err();
+    assert!(format!("{err:?}").contains("null pointer write"), "{err:?}");
+    assert_eq!(&memory.data(&store)[..2], &[0, 1]);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn memory_access_hook_can_grow_memory() -> Result<()> {
+    let mut config = Config::new();
+    config.memory_access_tracing(true);
+    // Make sure that growing the memory moves it.
+    config.memory_reservation(0);
+    config.memory_guard_size(0);
+    config.memory_reservation_for_growth(0);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "store") (param i32 i32)
+                    (i32.store8 (local.get 0) (local.get 1)))
+            )
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    store.memory_access_hook(|mut store, access| {
+        let memory = access.instance().get_memory(&mut store, "memory").unwrap();
+        memory.grow(&mut store, 1)?;
+        Ok(())
+    });
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    let func = instance.get_typed_func::<(i32, i32), ()>(&mut store, "store")?;
+
+    // The access goes to the grown memory, both for an address that was
+    // already in bounds and for one that's only in bounds once the hook has
+    // grown the memory.
+    func.call(&mut store, (8, 1))?;
+    func.call(&mut store, (2 * 65536 + 8, 2))?;
+    assert_eq!(memory.size(&store), 3);
+    let data = memory.data(&store);
+    assert_eq!(data[8], 1);
+    assert_eq!(data[2 * 65536 + 8], 2);
+
+    // Accesses which are out of bounds even after growing still trap.
+    let err = func.call(&mut store, (10 * 65536, 3)).unwrap_err();
+    assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::MemoryOutOfBounds));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn memory_access_tracing_is_opt_in() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory 1)
+                (func (export "run") (drop (i32.load (i32.const 0))))
+            )
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    store.memory_access_hook(|_, _| panic!("tracing isn't enabled"));
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+    run.call(&mut store, ())?;
+
+    // Modules must be compiled with tracing to be loaded into an engine with
+    // it enabled.
+    let bytes = module.serialize()?;
+    assert!(unsafe { Module::deserialize(&tracing_engine()?, &bytes) }.is_err());
+
+    let mut config = Config::new();
+    config.memory_access_tracing(true);
+    config.strategy(Strategy::Winch);
+    assert!(Engine::new(&config).is_err());
+    Ok(())
+}
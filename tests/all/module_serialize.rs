@@ -154,3 +154,97 @@ fn detect_precompiled() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg(target_os = "linux")]
+fn serialize_to_memfd() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "hello")
+                (func (export "run") (result i32) i32.const 42))
+        "#,
+    )?;
+    let file = module.serialize_to_memfd()?;
+    assert_eq!(
+        file.metadata()?.len(),
+        u64::try_from(module.serialize()?.len())?
+    );
+
+    let module = unsafe { Module::deserialize_open_file(&engine, file)? };
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let func = instance.get_typed_func::<(), i32>(&mut store, "run")?;
+    assert_eq!(func.call(&mut store, ())?, 42);
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[..5], b"hello");
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg(unix)]
+fn memory_image_dir() -> Result<()> {
+    let td = tempfile::TempDir::new()?;
+    let wat = r#"
+        (module
+            (memory (export "memory") 1)
+            (data (i32.const 0) "hello")
+            (data (i32.const 0x8000) "world"))
+    "#;
+
+    // Two engines, standing in for two processes, share the same image file.
+    for _ in 0..2 {
+        let mut config = Config::new();
+        config.memory_image_dir(td.path());
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, wat)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        memory.data_mut(&mut store)[0] = b'j';
+        assert_eq!(&memory.data(&store)[..5], b"jello");
+        assert_eq!(&memory.data(&store)[0x8000..][..5], b"world");
+
+        let images = fs::read_dir(td.path())?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(images.len(), 1);
+        let image = fs::read(images[0].path())?;
+        assert_eq!(&image[..5], b"hello");
+    }
+
+    // An image file that doesn't match its name is not used.
+    let path = fs::read_dir(td.path())?.next().unwrap()?.path();
+    fs::remove_file(&path)?;
+    fs::write(&path, vec![0xff; 0x10000])?;
+    let mut config = Config::new();
+    config.memory_image_dir(td.path());
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, wat)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[..5], b"hello");
+    assert_eq!(memory.data(&store)[5], 0);
+
+    // Images are named after the SHA-256 hash of their contents.
+    let name = path.file_name().unwrap().to_str().unwrap();
+    let hash = name.strip_prefix("wasmtime-image-").unwrap();
+    assert_eq!(hash.len(), 64);
+    assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+
+    // A directory that images can't be written to isn't used.
+    let mut config = Config::new();
+    config.memory_image_dir(td.path().join("missing"));
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, wat)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[..5], b"hello");
+    assert!(!td.path().join("missing").exists());
+    Ok(())
+}
@@ -1333,3 +1333,345 @@ fn instantiate_non_page_aligned_sizes() -> Result<()> {
     Instance::new(&mut store, &module, &[])?;
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn pooling_allocation_stats() -> Result<()> {
+    let engine = Engine::default();
+    assert!(engine.pooling_allocation_stats().is_none());
+
+    let mut pool = crate::small_pool_config();
+    pool.total_core_instances(3);
+    pool.total_memories(2);
+    pool.total_tables(3);
+    let mut config = Config::new();
+    config.allocation_strategy(pool);
+    config.memory_guard_size(0);
+    config.memory_reservation(1 << 16);
+
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, r#"(module (memory 1) (table 1 funcref))"#)?;
+
+    let stats = engine.pooling_allocation_stats().unwrap();
+    assert_eq!(stats.core_instances(), 0);
+    assert_eq!(stats.memories().capacity(), 2);
+    assert_eq!(stats.memories().in_use(), 0);
+    assert_eq!(stats.memories().unused_cold(), 2);
+    assert_eq!(stats.tables().capacity(), 3);
+    assert_eq!(stats.memory_failures().total(), 0);
+
+    {
+        let mut store1 = Store::new(&engine, ());
+        Instance::new(&mut store1, &module, &[])?;
+        let mut store2 = Store::new(&engine, ());
+        Instance::new(&mut store2, &module, &[])?;
+
+        let stats = engine.pooling_allocation_stats().unwrap();
+        assert_eq!(stats.core_instances(), 2);
+        assert_eq!(stats.memories().in_use(), 2);
+        assert_eq!(stats.memories().unused_cold(), 0);
+        assert_eq!(stats.tables().in_use(), 2);
+        assert_eq!(stats.tables().unused_cold(), 1);
+
+        // The memory pool is exhausted, so the next instantiation fails.
+        let mut store3 = Store::new(&engine, ());
+        let err = Instance::new(&mut store3, &module, &[]).unwrap_err();
+        assert!(err.is::<PoolConcurrencyLimitError>());
+
+        let stats = engine.pooling_allocation_stats().unwrap();
+        assert_eq!(stats.memory_failures().limit_reached(), 1);
+        assert_eq!(stats.memory_failures().other(), 0);
+        assert_eq!(stats.core_instance_failures().total(), 0);
+        assert_eq!(stats.table_failures().total(), 0);
+    }
+
+    // Once the stores are dropped their slots become warm.
+    let stats = engine.pooling_allocation_stats().unwrap();
+    assert_eq!(stats.core_instances(), 0);
+    assert_eq!(stats.decommit_queue_len(), 0);
+    assert_eq!(stats.memories().in_use(), 0);
+    assert_eq!(stats.memories().unused_warm(), 2);
+    assert_eq!(stats.tables().in_use(), 0);
+    assert_eq!(stats.tables().unused_warm(), 2);
+    assert_eq!(stats.tables().unused_cold(), 1);
+    assert_eq!(stats.memory_failures().limit_reached(), 1);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn elastic_pools() -> Result<()> {
+    let mut pool = crate::small_pool_config();
+    pool.total_core_instances(10);
+    pool.total_memories(5);
+    pool.total_tables(5);
+    pool.elastic_chunk_slots(2);
+    pool.memory_protection_keys(MpkEnabled::Disable);
+    let mut config = Config::new();
+    config.allocation_strategy(pool);
+    config.memory_guard_size(0);
+    config.memory_reservation(1 << 16);
+
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 1)
+                (table 1 funcref)
+                (func (export "poke") (param i32)
+                    local.get 0
+                    i32.const 42
+                    i32.store8))
+        "#,
+    )?;
+
+    // Instantiate up to the limit, which requires reserving more chunks of
+    // each pool along the way. Every memory must be usable.
+    let mut stores = Vec::new();
+    for _ in 0..5 {
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let poke = instance.get_typed_func::<u32, ()>(&mut store, "poke")?;
+        poke.call(&mut store, 100)?;
+        let memory = instance.get_memory(&mut store, "m").unwrap();
+        assert_eq!(memory.data(&store)[100], 42);
+        stores.push(store);
+    }
+
+    let stats = engine.pooling_allocation_stats().unwrap();
+    assert_eq!(stats.memories().capacity(), 5);
+    assert_eq!(stats.memories().in_use(), 5);
+    assert_eq!(stats.tables().in_use(), 5);
+
+    // The configured totals are still hard limits.
+    let mut store = Store::new(&engine, ());
+    let err = Instance::new(&mut store, &module, &[]).unwrap_err();
+    assert!(err.is::<PoolConcurrencyLimitError>());
+
+    // Slots freed up in already-reserved chunks are reused, and their memories
+    // are reset.
+    stores.clear();
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let memory = instance.get_memory(&mut store, "m").unwrap();
+    assert_eq!(memory.data(&store)[100], 0);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg(target_os = "linux")]
+fn memory_init_uffd() -> Result<()> {
+    let mut pool = crate::small_pool_config();
+    pool.total_memories(1);
+    pool.max_memory_size(1 << 20);
+    pool.memory_init_uffd(true);
+    let mut config = Config::new();
+    config.allocation_strategy(pool);
+    config.memory_guard_size(0);
+    config.memory_reservation(1 << 20);
+
+    let engine = match Engine::new(&config) {
+        Ok(engine) => engine,
+        Err(e) if format!("{e:?}").contains("userfaultfd") => {
+            println!("skipping `memory_init_uffd` test; userfaultfd is unavailable: {e:?}");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    let module1 = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 16)
+                (data (i32.const 0) "hello")
+                (data (i32.const 0x80000) "world"))
+        "#,
+    )?;
+    let module2 = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 16)
+                (data (i32.const 0x40000) "other"))
+        "#,
+    )?;
+
+    // Only a single slot is available, so every instance below reuses it.
+    for _ in 0..2 {
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module1, &[])?;
+        let memory = instance.get_memory(&mut store, "m").unwrap();
+        let data = memory.data_mut(&mut store);
+        assert_eq!(&data[0..5], b"hello");
+        assert_eq!(&data[0x80000..0x80005], b"world");
+        assert!(data[0x40000..0x40005].iter().all(|b| *b == 0));
+        assert!(data[0x10000..0x10005].iter().all(|b| *b == 0));
+
+        // Dirty the image and the memory around it; all of this is reset
+        // before the slot is reused.
+        data[0..5].copy_from_slice(b"HELLO");
+        data[0x40000] = 1;
+        data[0xfffff] = 1;
+    }
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module2, &[])?;
+    let memory = instance.get_memory(&mut store, "m").unwrap();
+    let data = memory.data(&store);
+    assert_eq!(&data[0x40000..0x40005], b"other");
+    assert!(data[0..5].iter().all(|b| *b == 0));
+    assert!(data[0x80000..0x80005].iter().all(|b| *b == 0));
+    assert_eq!(data[0xfffff], 0);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg(target_os = "linux")]
+fn memory_init_uffd_unreadable_image() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let td = tempfile::TempDir::new()?;
+    let mut pool = crate::small_pool_config();
+    pool.total_memories(1);
+    pool.max_memory_size(1 << 20);
+    pool.memory_init_uffd(true);
+    let mut config = Config::new();
+    config.allocation_strategy(pool);
+    config.memory_guard_size(0);
+    config.memory_reservation(1 << 20);
+    config.memory_image_dir(td.path());
+
+    let engine = match Engine::new(&config) {
+        Ok(engine) => engine,
+        Err(e) if format!("{e:?}").contains("userfaultfd") => {
+            println!(
+                "skipping `memory_init_uffd_unreadable_image` test; \
+                 userfaultfd is unavailable: {e:?}"
+            );
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 16)
+                (data (i32.const 0) "hello"))
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let memory = instance.get_memory(&mut store, "m").unwrap();
+    assert_eq!(&memory.data(&store)[0..5], b"hello");
+    drop(store);
+
+    // Truncate the image behind the pool's back so that reading it fails. The
+    // page is filled with zeros instead of leaving the access blocked forever.
+    for entry in std::fs::read_dir(td.path())? {
+        let path = entry?.path();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))?;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(0)?;
+    }
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let memory = instance.get_memory(&mut store, "m").unwrap();
+    assert!(memory.data(&store)[0..5].iter().all(|b| *b == 0));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg(target_pointer_width = "64")]
+fn memory64_slots() -> Result<()> {
+    let mut pool = crate::small_pool_config();
+    pool.total_core_instances(2);
+    pool.max_memories_per_module(2);
+    pool.total_memories(2);
+    pool.total_memory64s(1);
+    pool.max_memory64_size(8 << 30);
+    let mut config = Config::new();
+    config.allocation_strategy(pool);
+    config.wasm_memory64(true);
+    config.wasm_multi_memory(true);
+    config.memory_guard_size(1 << 16);
+    config.memory_reservation(1 << 16);
+
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m32") 1)
+                (memory (export "m64") i64 1)
+                (func (export "grow") (param i64) (result i64)
+                    local.get 0
+                    memory.grow 1)
+                (func (export "store") (param i64 i32)
+                    local.get 0
+                    local.get 1
+                    i32.store 1)
+                (func (export "load") (param i64) (result i32)
+                    local.get 0
+                    i32.load 1))
+        "#,
+    )?;
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let grow = instance.get_typed_func::<u64, u64>(&mut store, "grow")?;
+    let store_fn = instance.get_typed_func::<(u64, u32), ()>(&mut store, "store")?;
+    let load = instance.get_typed_func::<u64, u32>(&mut store, "load")?;
+
+    // The 64-bit memory may grow well beyond both the memory reservation and
+    // the limit for 32-bit memories, up to its own limit.
+    let pages_5g = (5 << 30) / 0x10000;
+    assert_eq!(grow.call(&mut store, pages_5g - 1)?, 1);
+    let addr = (5 << 30) - 4;
+    store_fn.call(&mut store, (addr, 42))?;
+    assert_eq!(load.call(&mut store, addr)?, 42);
+    assert!(store_fn.call(&mut store, (addr + 1, 0)).is_err());
+    assert_eq!(grow.call(&mut store, 4 * pages_5g)?, u64::MAX);
+
+    let stats = engine.pooling_allocation_stats().unwrap();
+    assert_eq!(stats.memory_stripes().len(), 1);
+    assert_eq!(stats.memory_stripes()[0].capacity(), 2);
+    assert_eq!(stats.memory_stripes()[0].in_use(), 1);
+    let memory64s = stats.memory64s().unwrap();
+    assert_eq!(memory64s.capacity(), 1);
+    assert_eq!(memory64s.in_use(), 1);
+    assert_eq!(stats.memories().capacity(), 3);
+    assert_eq!(stats.memories().in_use(), 2);
+
+    // The only slot for 64-bit memories is in use.
+    let mut store2 = Store::new(&engine, ());
+    let err = Instance::new(&mut store2, &module, &[]).unwrap_err();
+    assert!(err.is::<PoolConcurrencyLimitError>());
+
+    // Once it's free again it is reset for the next instance.
+    drop(store);
+    let instance = Instance::new(&mut store2, &module, &[])?;
+    let memory = instance.get_memory(&mut store2, "m64").unwrap();
+    assert_eq!(memory.size(&store2), 1);
+    let load = instance.get_typed_func::<u64, u32>(&mut store2, "load")?;
+    assert_eq!(load.call(&mut store2, 0)?, 0);
+
+    // A 64-bit memory's minimum size is validated against its own limit.
+    let err = Module::new(&engine, "(module (memory i64 0x30000))").unwrap_err();
+    assert!(
+        format!("{err:?}").contains("exceeds the limit"),
+        "bad error: {err:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,377 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use wasmtime::*;
+
+const WAT: &str = r#"
+    (module
+        (import "host" "now" (func $now (result i64)))
+        (import "host" "sample" (func $sample (param i32) (result i32 f64)))
+        (import "host" "log" (func $log (param i32)))
+
+        (func (export "run") (result i64)
+            (local $x i32)
+            (call $sample (i32.const 1))
+            drop
+            local.set $x
+            (call $log (local.get $x))
+            (i64.add
+                (i64.sub (call $now) (call $now))
+                (i64.extend_i32_u (local.get $x)))
+        )
+    )
+"#;
+
+fn instantiate(store: &mut Store<()>, module: &Module, seed: i64) -> Result<Instance> {
+    let clock = Arc::new(AtomicI64::new(seed));
+
+    let mut linker = Linker::new(module.engine());
+    linker.func_wrap("host", "now", move || clock.fetch_add(10, Ordering::SeqCst))?;
+    linker.func_new(
+        "host",
+        "sample",
+        FuncType::new(
+            module.engine(),
+            [ValType::I32],
+            [ValType::I32, ValType::F64],
+        ),
+        move |_, params, results| {
+            results[0] = Val::I32(params[0].unwrap_i32() + seed as i32);
+            results[1] = Val::F64(1.5f64.to_bits());
+            Ok(())
+        },
+    )?;
+    linker.func_wrap("host", "log", |_: i32| {})?;
+    linker.instantiate(store, module)
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn record_and_replay_host_calls() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, WAT)?;
+
+    let mut store = Store::new(&engine, ());
+    let trace = HostCallTrace::record();
+    store.host_call_trace(trace.clone());
+    let instance = instantiate(&mut store, &module, 100)?;
+    let run = instance.get_typed_func::<(), i64>(&mut store, "run")?;
+    let recorded = run.call(&mut store, ())?;
+    assert_eq!(recorded, -10 + 101);
+
+    // Replaying against a host which returns different values reproduces the
+    // recorded execution.
+    let trace = HostCallTrace::replay(&trace.serialize())?;
+    assert!(trace.is_replaying());
+    let mut store = Store::new(&engine, ());
+    store.host_call_trace(trace.clone());
+    let instance = instantiate(&mut store, &module, 7)?;
+    let run = instance.get_typed_func::<(), i64>(&mut store, "run")?;
+    assert_eq!(run.call(&mut store, ())?, recorded);
+    trace.finish()?;
+
+    // Running again goes past the end of the trace.
+    assert!(run.call(&mut store, ()).is_err());
+    assert!(trace.finish().is_err());
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn replay_detects_divergence() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, WAT)?;
+
+    let mut store = Store::new(&engine, ());
+    let trace = HostCallTrace::record();
+    store.host_call_trace(trace.clone());
+    let now = Func::wrap(&mut store, || 1i64);
+    now.typed::<(), i64>(&store)?.call(&mut store, ())?;
+    assert_eq!(trace.value(|| 3), 3);
+
+    // Replaying the trace with a different execution is an error.
+    let trace = HostCallTrace::replay(&trace.serialize())?;
+    let mut store = Store::new(&engine, ());
+    store.host_call_trace(trace.clone());
+    let instance = instantiate(&mut store, &module, 0)?;
+    let run = instance.get_typed_func::<(), i64>(&mut store, "run")?;
+    let err = run.call(&mut store, ()).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("execution diverged from the host call trace at event 0"),
+        "{err:?}"
+    );
+    assert!(trace.finish().is_err());
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn record_and_replay_wasi_memory_writes() -> Result<()> {
+    use wasmtime_wasi::p2::WasiCtxBuilder;
+    use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+    use wasmtime_wasi::{DirPerms, FilePerms};
+
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "wasi_snapshot_preview1" "path_open"
+                    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_read"
+                    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "random_get"
+                    (func $random_get (param i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0) "data.txt")
+                ;; An iovec for the 32 bytes at 100.
+                (data (i32.const 32) "\64\00\00\00\20\00\00\00")
+
+                (func (export "run") (result i32)
+                    (if (call $path_open (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 8)
+                            (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 16))
+                        (then unreachable))
+                    (if (call $fd_read (i32.load (i32.const 16)) (i32.const 32) (i32.const 1)
+                            (i32.const 48))
+                        (then unreachable))
+                    (if (call $random_get (i32.const 200) (i32.const 32))
+                        (then unreachable))
+                    (i32.load (i32.const 48))
+                )
+            )
+        "#,
+    )?;
+    let mut linker = Linker::<WasiP1Ctx>::new(&engine);
+    preview1::add_to_linker_sync(&mut linker, |t| t)?;
+
+    let run = |trace: &HostCallTrace, contents: &str| -> Result<(i32, Vec<u8>)> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("data.txt"), contents)?;
+        let wasi = WasiCtxBuilder::new()
+            .preopened_dir(dir.path(), "/", DirPerms::all(), FilePerms::all())?
+            .build_p1();
+        let mut store = Store::new(&engine, wasi);
+        store.host_call_trace(trace.clone());
+        let instance = linker.instantiate(&mut store, &module)?;
+        let run = instance.get_typed_func::<(), i32>(&mut store, "run")?;
+        let nread = run.call(&mut store, ())?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        Ok((nread, memory.data(&store)[..256].to_vec()))
+    };
+
+    let trace = HostCallTrace::record();
+    let (nread, memory) = run(&trace, "hello")?;
+    assert_eq!(nread, 5);
+    assert_eq!(&memory[100..105], b"hello");
+    assert_ne!(&memory[200..232], [0; 32]);
+
+    // Replaying with different file contents and random numbers reproduces
+    // the bytes the host wrote into memory when recording.
+    let trace = HostCallTrace::replay(&trace.serialize())?;
+    let (replayed_nread, replayed_memory) = run(&trace, "a different file")?;
+    trace.finish()?;
+    assert_eq!(replayed_nread, nread);
+    assert_eq!(replayed_memory, memory);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn record_and_replay_component_host_calls() -> Result<()> {
+    use wasmtime::component::{Component, Linker};
+    use wasmtime_component_util::REALLOC_AND_FREE;
+
+    let engine = Engine::default();
+    let component = Component::new(
+        &engine,
+        format!(
+            r#"
+                (component
+                    (import "read" (func $read (param "n" u32) (result string)))
+
+                    (core module $libc
+                        (memory (export "memory") 1)
+                        {REALLOC_AND_FREE}
+                    )
+                    (core instance $libc (instantiate $libc))
+                    (core func $read_lower
+                        (canon lower (func $read)
+                            (memory $libc "memory")
+                            (realloc (func $libc "realloc"))))
+
+                    (core module $m
+                        (import "libc" "memory" (memory 1))
+                        (import "host" "read" (func $read (param i32 i32)))
+                        (func (export "run") (result i32)
+                            (call $read (i32.const 1) (i32.const 1000))
+                            (i32.load (i32.const 1004)))
+                        (func (export "get") (result i32)
+                            i32.const 1000)
+                    )
+                    (core instance $m (instantiate $m
+                        (with "libc" (instance $libc))
+                        (with "host" (instance (export "read" (func $read_lower))))
+                    ))
+
+                    (func (export "run") (result u32)
+                        (canon lift (core func $m "run")))
+                    (func (export "get") (result string)
+                        (canon lift (core func $m "get") (memory $libc "memory")))
+                )
+            "#
+        ),
+    )?;
+
+    let run = |trace: &HostCallTrace, contents: &'static str| -> Result<(u32, String)> {
+        let mut linker = Linker::new(&engine);
+        linker
+            .root()
+            .func_wrap("read", move |_, (_,): (u32,)| Ok((contents.to_string(),)))?;
+        let mut store = Store::new(&engine, ());
+        store.host_call_trace(trace.clone());
+        let instance = linker.instantiate(&mut store, &component)?;
+        let run = instance.get_typed_func::<(), (u32,)>(&mut store, "run")?;
+        let (len,) = run.call(&mut store, ())?;
+        run.post_return(&mut store)?;
+        let get = instance.get_typed_func::<(), (String,)>(&mut store, "get")?;
+        let (contents,) = get.call(&mut store, ())?;
+        get.post_return(&mut store)?;
+        Ok((len, contents))
+    };
+
+    let trace = HostCallTrace::record();
+    assert_eq!(run(&trace, "recorded")?, (8, "recorded".to_string()));
+
+    let trace = HostCallTrace::replay(&trace.serialize())?;
+    assert_eq!(
+        run(&trace, "replayed differently")?,
+        (8, "recorded".to_string())
+    );
+    trace.finish()?;
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn record_and_replay_memory_accessor_writes() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "host" "fill" (func $fill (param i32)))
+                (memory (export "memory") 16)
+                (func (export "run") (result i32)
+                    (call $fill (i32.const 65540))
+                    (i32.load (i32.const 65540)))
+            )
+        "#,
+    )?;
+
+    let run = |trace: &HostCallTrace, value: u32| -> Result<(u32, Vec<u8>)> {
+        let mut store = Store::new(&engine, ());
+        store.host_call_trace(trace.clone());
+        let mut linker = Linker::new(&engine);
+        linker.func_wrap(
+            "host",
+            "fill",
+            move |mut caller: Caller<'_, ()>, ptr: i32| {
+                let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+                memory.write(&mut caller, ptr as usize, &value.to_le_bytes())?;
+                Ok(())
+            },
+        )?;
+        let instance = linker.instantiate(&mut store, &module)?;
+        let run = instance.get_typed_func::<(), u32>(&mut store, "run")?;
+        let result = run.call(&mut store, ())?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        Ok((result, memory.data(&store).to_vec()))
+    };
+
+    let trace = HostCallTrace::record();
+    let (result, memory) = run(&trace, 0x1234_5678)?;
+    assert_eq!(result, 0x1234_5678);
+
+    // Only the bytes around the write are recorded, not the whole memory.
+    let serialized = trace.serialize();
+    assert!(serialized.len() < 1024, "{}", serialized.len());
+
+    let trace = HostCallTrace::replay(&serialized)?;
+    let (replayed, replayed_memory) = run(&trace, 7)?;
+    trace.finish()?;
+    assert_eq!(replayed, result);
+    assert!(replayed_memory == memory);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn record_and_replay_async_host_suspensions() -> Result<()> {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    let mut config = Config::new();
+    config.async_support(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "host" "now" (func $now (result i64)))
+                (func (export "run") (result i64)
+                    (i64.add (call $now) (call $now)))
+            )
+        "#,
+    )?;
+
+    // Run `run` with a host function that suspends `suspensions` times before
+    // returning, and return its result along with how many times the call
+    // itself suspended.
+    let run = |trace: &HostCallTrace, suspensions: usize| -> Result<(i64, usize)> {
+        let mut store = Store::new(&engine, ());
+        store.host_call_trace(trace.clone());
+        let mut linker = Linker::new(&engine);
+        linker.func_wrap_async("host", "now", move |_, (): ()| {
+            let mut remaining = suspensions;
+            Box::new(std::future::poll_fn(move |cx| {
+                if remaining == 0 {
+                    return Poll::Ready(Ok(suspensions as i64));
+                }
+                remaining -= 1;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }))
+        })?;
+
+        let mut future = Box::pin(async {
+            let instance = linker.instantiate_async(&mut store, &module).await?;
+            let run = instance.get_typed_func::<(), i64>(&mut store, "run")?;
+            run.call_async(&mut store, ()).await
+        });
+        let mut pending = 0;
+        loop {
+            match future
+                .as_mut()
+                .poll(&mut Context::from_waker(Waker::noop()))
+            {
+                Poll::Ready(result) => return Ok((result?, pending)),
+                Poll::Pending => pending += 1,
+            }
+        }
+    };
+
+    let trace = HostCallTrace::record();
+    assert_eq!(run(&trace, 2)?, (4, 4));
+
+    // The replayed call suspends at the same points even though the host
+    // function no longer does.
+    let trace = HostCallTrace::replay(&trace.serialize())?;
+    assert_eq!(run(&trace, 0)?, (4, 4));
+    trace.finish()?;
+
+    Ok(())
+}
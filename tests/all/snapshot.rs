@@ -0,0 +1,200 @@
+use wasmtime::*;
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_memories_tables_and_globals() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "memory") 1)
+                (table (export "table") 1 funcref)
+                (global $g (export "g") (mut i64) (i64.const 0))
+
+                (func $forty_two (result i32) i32.const 42)
+                (elem declare func $forty_two)
+
+                (func (export "mutate")
+                    (drop (memory.grow (i32.const 1)))
+                    (i32.store (i32.const 65540) (i32.const 0x1234))
+                    (drop (table.grow (ref.func $forty_two) (i32.const 1)))
+                    (global.set $g (i64.const -1))
+                )
+
+                (func (export "call") (param i32) (result i32)
+                    (call_indirect (result i32) (local.get 0))
+                )
+            )
+        "#,
+    )?;
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let mutate = instance.get_typed_func::<(), ()>(&mut store, "mutate")?;
+    mutate.call(&mut store, ())?;
+    let snapshot = store.snapshot()?;
+    let snapshot = StoreSnapshot::deserialize(&snapshot.serialize())?;
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    store.restore(&snapshot)?;
+
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 2);
+    let mut bytes = [0; 4];
+    memory.read(&store, 65540, &mut bytes)?;
+    assert_eq!(u32::from_le_bytes(bytes), 0x1234);
+
+    let table = instance.get_table(&mut store, "table").unwrap();
+    assert_eq!(table.size(&store), 2);
+    let call = instance.get_typed_func::<u32, i32>(&mut store, "call")?;
+    assert_eq!(call.call(&mut store, 1)?, 42);
+    assert!(call.call(&mut store, 0).is_err());
+
+    let global = instance.get_global(&mut store, "g").unwrap();
+    assert_eq!(global.get(&mut store).unwrap_i64(), -1);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_restore_requires_same_instances() -> Result<()> {
+    let engine = Engine::default();
+    let a = Module::new(&engine, r#"(module $a (memory 1))"#)?;
+    let b = Module::new(&engine, r#"(module $b (memory 1))"#)?;
+
+    let mut store = Store::new(&engine, ());
+    Instance::new(&mut store, &a, &[])?;
+    let snapshot = store.snapshot()?;
+
+    let mut store = Store::new(&engine, ());
+    let err = store.restore(&snapshot).unwrap_err();
+    assert!(format!("{err:?}").contains("different number of instances"));
+
+    Instance::new(&mut store, &b, &[])?;
+    let err = store.restore(&snapshot).unwrap_err();
+    assert!(format!("{err:?}").contains("not an instance of module `a`"));
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_rejects_host_funcs_in_tables() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, r#"(module (table (export "t") 1 funcref))"#)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let func = Func::wrap(&mut store, || {});
+    let table = instance.get_table(&mut store, "t").unwrap();
+    table.set(&mut store, 0, func.into())?;
+    assert!(store.snapshot().is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_gc_heap() -> Result<()> {
+    for collector in [
+        Collector::DeferredReferenceCounting,
+        Collector::Null,
+        Collector::Copying,
+    ] {
+        let mut config = Config::new();
+        config.wasm_function_references(true);
+        config.wasm_gc(true);
+        config.collector(collector);
+        let engine = Engine::new(&config)?;
+
+        let module = Module::new(
+            &engine,
+            r#"
+                (module
+                    (type $s (struct (field (mut i32)) (field (ref null $s))))
+                    (global $g (mut (ref null $s)) (ref.null $s))
+
+                    (func (export "init")
+                        (global.set $g
+                            (struct.new $s
+                                (i32.const 1)
+                                (struct.new $s (i32.const 2) (ref.null $s))))
+                    )
+
+                    (func (export "sum") (result i32)
+                        (i32.add
+                            (struct.get $s 0 (global.get $g))
+                            (struct.get $s 0 (struct.get $s 1 (global.get $g))))
+                    )
+                )
+            "#,
+        )?;
+
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let init = instance.get_typed_func::<(), ()>(&mut store, "init")?;
+        init.call(&mut store, ())?;
+        let snapshot = store.snapshot()?;
+
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        store.restore(&snapshot)?;
+        let sum = instance.get_typed_func::<(), i32>(&mut store, "sum")?;
+        assert_eq!(sum.call(&mut store, ())?, 3);
+
+        // Allocation and collection continue to work after restoring.
+        let init = instance.get_typed_func::<(), ()>(&mut store, "init")?;
+        init.call(&mut store, ())?;
+        store.gc(None);
+        assert_eq!(sum.call(&mut store, ())?, 3);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn snapshot_gc_heap_rejects_other_collector() -> Result<()> {
+    let wat = r#"
+        (module
+            (type $s (struct (field i32)))
+            (memory (export "memory") 1)
+            (global $g (mut (ref null $s)) (ref.null $s))
+
+            (func (export "init")
+                (drop (memory.grow (i32.const 1)))
+                (global.set $g (struct.new $s (i32.const 1)))
+            )
+        )
+    "#;
+    let engine = |collector| {
+        let mut config = Config::new();
+        config.wasm_function_references(true);
+        config.wasm_gc(true);
+        config.collector(collector);
+        Engine::new(&config)
+    };
+
+    let drc = engine(Collector::DeferredReferenceCounting)?;
+    let module = Module::new(&drc, wat)?;
+    let mut store = Store::new(&drc, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let init = instance.get_typed_func::<(), ()>(&mut store, "init")?;
+    init.call(&mut store, ())?;
+    let snapshot = store.snapshot()?;
+
+    // The mismatch is caught before anything in the store is modified.
+    let copying = engine(Collector::Copying)?;
+    let module = Module::new(&copying, wat)?;
+    let mut store = Store::new(&copying, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let err = store.restore(&snapshot).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("deferred reference-counting collector"),
+        "{err:?}"
+    );
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(memory.size(&store), 1);
+
+    Ok(())
+}
@@ -0,0 +1,296 @@
+// Winch, which tiered compilation starts out with, is only fully supported on
+// x86_64.
+#![cfg(target_arch = "x86_64")]
+
+use wasmtime::*;
+
+fn tiered_engine(threshold: u32) -> Result<Engine> {
+    let mut config = Config::new();
+    config.strategy(Strategy::Tiered);
+    config.tier_up_threshold(threshold);
+    Engine::new(&config)
+}
+
+const FIB: &str = r#"
+    (module
+        (func $fib (export "fib") (param i32) (result i32)
+            (if (result i32) (i32.lt_u (local.get 0) (i32.const 2))
+                (then (local.get 0))
+                (else
+                    (i32.add
+                        (call $fib (i32.sub (local.get 0) (i32.const 1)))
+                        (call $fib (i32.sub (local.get 0) (i32.const 2)))))))
+
+        (func $unreachable (export "unreachable") (param i32)
+            (if (i32.eqz (local.get 0)) (then unreachable)))
+        (func (export "trap") (param i32)
+            (call $unreachable (local.get 0)))
+
+        (table 2 funcref)
+        (elem (i32.const 0) $fib)
+        (type $fib (func (param i32) (result i32)))
+        (func (export "fib_indirect") (param i32) (result i32)
+            (call_indirect (type $fib) (local.get 0) (i32.const 0)))
+    )
+"#;
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_compiles_on_first_call() -> Result<()> {
+    let engine = tiered_engine(u32::MAX)?;
+    let module = Module::new(&engine, FIB)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let fib = instance.get_typed_func::<i32, i32>(&mut store, "fib")?;
+    assert_eq!(fib.call(&mut store, 10)?, 55);
+    assert_eq!(fib.call(&mut store, 20)?, 6765);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_tiers_up() -> Result<()> {
+    let engine = tiered_engine(2)?;
+    let module = Module::new(&engine, FIB)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let fib = instance.get_typed_func::<i32, i32>(&mut store, "fib")?;
+    for _ in 0..5 {
+        assert_eq!(fib.call(&mut store, 20)?, 6765);
+    }
+
+    // A second instance shares the module's compiled code.
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let fib = instance.get_typed_func::<i32, i32>(&mut store, "fib")?;
+    assert_eq!(fib.call(&mut store, 20)?, 6765);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_call_indirect() -> Result<()> {
+    let engine = tiered_engine(3)?;
+    let module = Module::new(&engine, FIB)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let fib = instance.get_typed_func::<i32, i32>(&mut store, "fib_indirect")?;
+    for _ in 0..5 {
+        assert_eq!(fib.call(&mut store, 15)?, 610);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_traps_have_backtraces() -> Result<()> {
+    let engine = tiered_engine(2)?;
+    let module = Module::new(&engine, FIB)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let trap = instance.get_typed_func::<i32, ()>(&mut store, "trap")?;
+
+    // Check backtraces both before and after the functions are recompiled.
+    for _ in 0..5 {
+        let err = trap.call(&mut store, 0).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Trap>(),
+            Some(&Trap::UnreachableCodeReached)
+        );
+        let trace = err.downcast_ref::<WasmBacktrace>().unwrap().frames();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].func_name(), Some("unreachable"));
+        assert_eq!(trace[1].func_index(), 2);
+        trap.call(&mut store, 1)?;
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_imports_between_instances() -> Result<()> {
+    let engine = tiered_engine(2)?;
+    let mut store = Store::new(&engine, ());
+    let fib = Instance::new(&mut store, &Module::new(&engine, FIB)?, &[])?;
+    let fib = fib.get_func(&mut store, "fib").unwrap();
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "fib" (func $fib (param i32) (result i32)))
+                (func (export "run") (param i32) (result i32)
+                    (i32.add (call $fib (local.get 0)) (i32.const 1)))
+            )
+        "#,
+    )?;
+    let instance = Instance::new(&mut store, &module, &[fib.into()])?;
+    let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+    for _ in 0..5 {
+        assert_eq!(run.call(&mut store, 10)?, 56);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_tiers_up_warm_functions_together() -> Result<()> {
+    let engine = tiered_engine(4)?;
+    let mut store = Store::new(&engine, ());
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (func (export "a") (param i32) (result i32)
+                    (i32.add (local.get 0) (i32.const 1)))
+                (func (export "b") (param i32) (result i32)
+                    (i32.mul (local.get 0) (i32.const 2)))
+                (func (export "c") (param i32) (result i32)
+                    (i32.sub (local.get 0) (i32.const 3)))
+            )
+        "#,
+    )?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let a = instance.get_typed_func::<i32, i32>(&mut store, "a")?;
+    let b = instance.get_typed_func::<i32, i32>(&mut store, "b")?;
+    let c = instance.get_typed_func::<i32, i32>(&mut store, "c")?;
+
+    // `b` is halfway to being recompiled when `a` is, so it's recompiled
+    // along with `a`, while `c` is only recompiled later on its own.
+    for i in 0..2 {
+        assert_eq!(b.call(&mut store, i)?, i * 2);
+    }
+    assert_eq!(c.call(&mut store, 5)?, 2);
+    for i in 0..4 {
+        assert_eq!(a.call(&mut store, i)?, i + 1);
+    }
+    for i in 0..10 {
+        assert_eq!(a.call(&mut store, i)?, i + 1);
+        assert_eq!(b.call(&mut store, i)?, i * 2);
+        assert_eq!(c.call(&mut store, i)?, i - 3);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_imports_made_before_compiling() -> Result<()> {
+    let engine = tiered_engine(2)?;
+    let mut store = Store::new(&engine, ());
+    let fib = Instance::new(&mut store, &Module::new(&engine, FIB)?, &[])?;
+    let fib_func = fib.get_func(&mut store, "fib").unwrap();
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "fib" (func $fib (param i32) (result i32)))
+                (func (export "run") (param i32) (result i32)
+                    (call $fib (local.get 0)))
+            )
+        "#,
+    )?;
+    let instance = Instance::new(&mut store, &module, &[fib_func.into()])?;
+
+    // Compile and recompile `fib` through its own exports, and then call it
+    // through the import which still refers to the lazy trampoline.
+    let fib = fib.get_typed_func::<i32, i32>(&mut store, "fib")?;
+    for _ in 0..5 {
+        assert_eq!(fib.call(&mut store, 15)?, 610);
+    }
+    let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+    for _ in 0..5 {
+        assert_eq!(run.call(&mut store, 15)?, 610);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_calls_host() -> Result<()> {
+    let engine = tiered_engine(2)?;
+    let mut store = Store::new(&engine, 0);
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "add" (func $add (param i32)))
+                (func (export "run") (param i32)
+                    (call $add (local.get 0)))
+            )
+        "#,
+    )?;
+    let add = Func::wrap(&mut store, |mut caller: Caller<'_, i32>, x: i32| {
+        *caller.data_mut() += x;
+    });
+    let instance = Instance::new(&mut store, &module, &[add.into()])?;
+    let run = instance.get_typed_func::<i32, ()>(&mut store, "run")?;
+    for i in 0..5 {
+        run.call(&mut store, i)?;
+    }
+    assert_eq!(*store.data(), 10);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_serialize() -> Result<()> {
+    let engine = tiered_engine(2)?;
+    let bytes = Module::new(&engine, FIB)?.serialize()?;
+    let module = unsafe { Module::deserialize(&engine, &bytes)? };
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let fib = instance.get_typed_func::<i32, i32>(&mut store, "fib")?;
+    for _ in 0..5 {
+        assert_eq!(fib.call(&mut store, 20)?, 6765);
+    }
+
+    // Modules compiled with tiered compilation can't be loaded into engines
+    // which compile eagerly, and vice versa.
+    assert!(unsafe { Module::deserialize(&Engine::default(), &bytes) }.is_err());
+    let bytes = Module::new(&Engine::default(), FIB)?.serialize()?;
+    assert!(unsafe { Module::deserialize(&engine, &bytes) }.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tiered_rejects_unsupported_configurations() -> Result<()> {
+    let mut config = Config::new();
+    config.strategy(Strategy::Tiered);
+    config.deterministic(true);
+    assert!(Engine::new(&config).is_err());
+
+    let engine = tiered_engine(2)?;
+    let err = Module::new(&engine, "(module (func (param i32) (result i32)))").unwrap_err();
+    assert!(format!("{err:?}").contains("type mismatch"), "{err:?}");
+
+    let err = match component::Component::new(&engine, "(component)") {
+        Ok(_) => panic!("components should be rejected"),
+        Err(e) => e,
+    };
+    assert!(
+        format!("{err:?}").contains("not supported with components"),
+        "{err:?}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn tiered_compiles_off_small_async_stacks() -> Result<()> {
+    let mut config = Config::new();
+    config.strategy(Strategy::Tiered);
+    config.tier_up_threshold(2);
+    config.async_support(true);
+    config.max_wasm_stack(16 << 10);
+    config.async_stack_size(32 << 10);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, FIB)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new_async(&mut store, &module, &[]).await?;
+    let fib = instance.get_typed_func::<i32, i32>(&mut store, "fib")?;
+    for _ in 0..5 {
+        assert_eq!(fib.call_async(&mut store, 15).await?, 610);
+    }
+    Ok(())
+}
@@ -82,17 +82,19 @@ fn main() {
             },
         );
 
-        // If applicable, also run with the null collector in addition to the
-        // default collector.
+        // If applicable, also run with the null and copying collectors in
+        // addition to the default collector.
         if test.test_uses_gc_types() {
-            add_trial(
-                &test,
-                WastConfig {
-                    compiler,
-                    pooling: false,
-                    collector: Collector::Null,
-                },
-            );
+            for collector in [Collector::Null, Collector::Copying] {
+                add_trial(
+                    &test,
+                    WastConfig {
+                        compiler,
+                        pooling: false,
+                        collector,
+                    },
+                );
+            }
         }
     }
 
@@ -145,7 +145,19 @@ impl FnCall {
             Callee::FuncRef(_) => {
                 Self::lower_funcref(env.callee_sig::<M::ABI>(callee)?, ptr, context, masm)
             }
-            Callee::Local(i) => Ok(Self::lower_local(env, *i)),
+            Callee::Local(i) => match env.local_func_ref(*i) {
+                Some(offset) => {
+                    let sig = env.callee_sig::<M::ABI>(callee)?;
+                    Self::lower_from_vmctx(
+                        offset + u32::from(ptr.vm_func_ref_wasm_call()),
+                        offset + u32::from(ptr.vm_func_ref_vmctx()),
+                        sig,
+                        context,
+                        masm,
+                    )
+                }
+                None => Ok(Self::lower_local(env, *i)),
+            },
             Callee::Import(i) => {
                 let sig = env.callee_sig::<M::ABI>(callee)?;
                 Self::lower_import(*i, sig, context, masm, vmoffsets)
@@ -186,16 +198,33 @@ impl FnCall {
         context: &mut CodeGenContext<Emission>,
         masm: &mut M,
         vmoffsets: &VMOffsets<P>,
+    ) -> Result<(CalleeKind, ContextArgs)> {
+        Self::lower_from_vmctx(
+            vmoffsets.vmctx_vmfunction_import_wasm_call(index),
+            vmoffsets.vmctx_vmfunction_import_vmctx(index),
+            sig,
+            context,
+            masm,
+        )
+    }
+
+    /// Lowers a function whose address and vmctx are stored in the
+    /// `VMContext`, at the given offsets, by loading them to the next
+    /// available registers.
+    fn lower_from_vmctx<M: MacroAssembler>(
+        callee_body_offset: u32,
+        callee_vmctx_offset: u32,
+        sig: &ABISig,
+        context: &mut CodeGenContext<Emission>,
+        masm: &mut M,
     ) -> Result<(CalleeKind, ContextArgs)> {
         let (callee, callee_vmctx) =
             context.without::<Result<(Reg, Reg)>, M, _>(&sig.regs, masm, |context, masm| {
                 Ok((context.any_gpr(masm)?, context.any_gpr(masm)?))
             })??;
-        let callee_vmctx_offset = vmoffsets.vmctx_vmfunction_import_vmctx(index);
         let callee_vmctx_addr = masm.address_at_vmctx(callee_vmctx_offset)?;
         masm.load_ptr(callee_vmctx_addr, writable!(callee_vmctx))?;
 
-        let callee_body_offset = vmoffsets.vmctx_vmfunction_import_wasm_call(index);
         let callee_addr = masm.address_at_vmctx(callee_body_offset)?;
         masm.load_ptr(callee_addr, writable!(callee))?;
 
@@ -12,7 +12,7 @@ use std::collections::{
 use std::mem;
 use wasmparser::BlockType;
 use wasmtime_environ::{
-    BuiltinFunctionIndex, FuncIndex, GlobalIndex, IndexType, Memory, MemoryIndex,
+    BuiltinFunctionIndex, DefinedFuncIndex, FuncIndex, GlobalIndex, IndexType, Memory, MemoryIndex,
     ModuleTranslation, ModuleTypesBuilder, PrimaryMap, PtrSize, Table, TableIndex, TypeConvert,
     TypeIndex, VMOffsets, WasmHeapType, WasmValType,
 };
@@ -99,6 +99,8 @@ pub struct FuncEnv<'a, 'translation: 'a, 'data: 'translation, P: PtrSize> {
     pub types: &'translation ModuleTypesBuilder,
     /// The built-in functions available to the JIT code.
     pub builtins: &'translation mut BuiltinFunctions,
+    /// The index of the function being compiled.
+    func_index: DefinedFuncIndex,
     /// Track resolved table information.
     resolved_tables: HashMap<TableIndex, TableData>,
     /// Track resolved heap information.
@@ -132,6 +134,7 @@ pub fn ptr_type_from_ptr_size(size: u8) -> WasmValType {
 impl<'a, 'translation, 'data, P: PtrSize> FuncEnv<'a, 'translation, 'data, P> {
     /// Create a new function environment.
     pub fn new(
+        func_index: DefinedFuncIndex,
         vmoffsets: &'a VMOffsets<P>,
         translation: &'translation ModuleTranslation<'data>,
         types: &'translation ModuleTypesBuilder,
@@ -140,6 +143,7 @@ impl<'a, 'translation, 'data, P: PtrSize> FuncEnv<'a, 'translation, 'data, P> {
         ptr_type: WasmValType,
     ) -> Self {
         Self {
+            func_index,
             vmoffsets,
             translation,
             types,
@@ -158,6 +162,36 @@ impl<'a, 'translation, 'data, P: PtrSize> FuncEnv<'a, 'translation, 'data, P> {
         }
     }
 
+    /// The offset within the `VMContext` of the fuel counter of the function
+    /// being compiled, if fuel profiling is enabled.
+    pub(crate) fn fuel_counter(&self) -> Option<u32> {
+        (self.translation.module.num_fuel_counters > 0)
+            .then(|| self.vmoffsets.vmctx_fuel_counter(self.func_index))
+    }
+
+    /// The offset within the `VMContext` of the `calls_left` counter of the
+    /// function being compiled, if tiered compilation is enabled.
+    pub(crate) fn tier_up_counter(&self) -> Option<u32> {
+        (self.translation.module.num_lazy_funcs > 0)
+            .then(|| self.vmoffsets.vmctx_lazy_func_calls_left(self.func_index))
+    }
+
+    /// The index of the function being compiled.
+    pub(crate) fn func_index(&self) -> DefinedFuncIndex {
+        self.func_index
+    }
+
+    /// The offset within the `VMContext` of the `VMFuncRef` of the locally
+    /// defined function `index`, if calls to it must go through its
+    /// `VMFuncRef` because tiered compilation is enabled.
+    pub(crate) fn local_func_ref(&self, index: FuncIndex) -> Option<u32> {
+        if self.translation.module.num_lazy_funcs == 0 {
+            return None;
+        }
+        let func_ref = self.translation.module.functions[index].func_ref;
+        Some(self.vmoffsets.vmctx_func_ref(func_ref))
+    }
+
     /// Derive the [`WasmType`] from the pointer size.
     pub(crate) fn ptr_type(&self) -> WasmValType {
         self.ptr_type
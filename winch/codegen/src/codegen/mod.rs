@@ -319,6 +319,8 @@ where
 
         self.maybe_emit_epoch_check()?;
 
+        self.maybe_emit_tier_up_check()?;
+
         // Once we have emitted the epilogue and reserved stack space for the locals, we push the
         // base control flow block.
         self.control_frames.push(ControlStackFrame::block(
@@ -1231,6 +1233,66 @@ where
         Ok(())
     }
 
+    /// Emits a check, when tiered compilation is enabled, which counts calls
+    /// to the function and invokes the `tier_up` builtin to recompile it with
+    /// the optimizing compiler once it's been called enough times.
+    pub fn maybe_emit_tier_up_check(&mut self) -> Result<()> {
+        let Some(counter_offset) = self.env.tier_up_counter() else {
+            return Ok(());
+        };
+
+        let cont = self.masm.get_label()?;
+        let tier_up = self.env.builtins.tier_up::<M::ABI, M::Ptr>()?;
+        let counter_reg = self.context.without::<Result<Reg>, M, _>(
+            &tier_up.sig().regs,
+            self.masm,
+            |cx, masm| cx.any_gpr(masm),
+        )??;
+
+        // Decrement the number of calls left before tiering up.
+        self.masm.load(
+            self.masm.address_at_vmctx(counter_offset)?,
+            writable!(counter_reg),
+            OperandSize::S32,
+        )?;
+        self.masm.sub(
+            writable!(counter_reg),
+            counter_reg,
+            RegImm::i32(1),
+            OperandSize::S32,
+        )?;
+        self.masm.store(
+            counter_reg.into(),
+            self.masm.address_at_vmctx(counter_offset)?,
+            OperandSize::S32,
+        )?;
+
+        // Spill locals and registers to avoid conflicts at the control flow
+        // merge below.
+        self.context.spill(self.masm)?;
+        self.masm.branch(
+            IntCmpKind::Ne,
+            counter_reg,
+            RegImm::i32(0),
+            cont,
+            OperandSize::S32,
+        )?;
+        // No calls left branch.
+        self.context
+            .stack
+            .extend([self.env.func_index().as_u32().try_into().unwrap()]);
+        FnCall::emit::<M>(
+            &mut self.env,
+            self.masm,
+            &mut self.context,
+            Callee::Builtin(tier_up.clone()),
+        )?;
+
+        self.masm.bind(cont)?;
+        self.context.free_reg(counter_reg);
+        Ok(())
+    }
+
     fn emit_load_epoch_deadline_and_counter(
         &mut self,
         epoch_deadline_reg: Reg,
@@ -1316,6 +1378,29 @@ where
 
         self.context.free_reg(limits_reg);
 
+        // When profiling, also attribute the fuel consumed to the current
+        // function's counter in the `VMContext`.
+        if let Some(counter_offset) = self.env.fuel_counter() {
+            self.masm.with_scratch::<IntScratch, _>(|masm, scratch| {
+                masm.load(
+                    masm.address_at_vmctx(counter_offset)?,
+                    scratch.writable(),
+                    OperandSize::S64,
+                )?;
+                masm.add(
+                    scratch.writable(),
+                    scratch.inner(),
+                    RegImm::i64(fuel_at_point),
+                    OperandSize::S64,
+                )?;
+                masm.store(
+                    scratch.inner().into(),
+                    masm.address_at_vmctx(counter_offset)?,
+                    OperandSize::S64,
+                )
+            })?;
+        }
+
         Ok(())
     }
 
@@ -17,7 +17,9 @@ use masm::MacroAssembler as Aarch64Masm;
 use target_lexicon::Triple;
 use wasmparser::{FuncValidator, FunctionBody, ValidatorResources};
 use wasmtime_cranelift::CompiledFunction;
-use wasmtime_environ::{ModuleTranslation, ModuleTypesBuilder, Tunables, VMOffsets, WasmFuncType};
+use wasmtime_environ::{
+    DefinedFuncIndex, ModuleTranslation, ModuleTypesBuilder, Tunables, VMOffsets, WasmFuncType,
+};
 
 mod abi;
 mod address;
@@ -82,6 +84,7 @@ impl TargetIsa for Aarch64 {
 
     fn compile_function(
         &self,
+        index: DefinedFuncIndex,
         sig: &WasmFuncType,
         body: &FunctionBody,
         translation: &ModuleTranslation,
@@ -98,6 +101,7 @@ impl TargetIsa for Aarch64 {
         let abi_sig = wasm_sig::<abi::Aarch64ABI>(sig)?;
 
         let env = FuncEnv::new(
+            index,
             &vmoffsets,
             translation,
             types,
@@ -16,7 +16,9 @@ use cranelift_codegen::{MachTextSectionBuilder, TextSectionBuilder};
 use target_lexicon::Triple;
 use wasmparser::{FuncValidator, FunctionBody, ValidatorResources};
 use wasmtime_cranelift::CompiledFunction;
-use wasmtime_environ::{ModuleTranslation, ModuleTypesBuilder, Tunables, VMOffsets, WasmFuncType};
+use wasmtime_environ::{
+    DefinedFuncIndex, ModuleTranslation, ModuleTypesBuilder, Tunables, VMOffsets, WasmFuncType,
+};
 
 use self::regs::{fpr_bit_set, gpr_bit_set};
 
@@ -85,6 +87,7 @@ impl TargetIsa for X64 {
 
     fn compile_function(
         &self,
+        index: DefinedFuncIndex,
         sig: &WasmFuncType,
         body: &FunctionBody,
         translation: &ModuleTranslation,
@@ -107,6 +110,7 @@ impl TargetIsa for X64 {
         let abi_sig = wasm_sig::<abi::X64ABI>(sig)?;
 
         let env = FuncEnv::new(
+            index,
             &vmoffsets,
             translation,
             types,